@@ -0,0 +1,1742 @@
+//! Abstract syntax tree produced by the parser.
+//!
+//! Every node implements `Display`, printing canonical SQL that parses back
+//! to the same tree. Parentheses are only emitted where operator precedence
+//! requires them.
+
+use std::fmt;
+
+use crate::planner::SubqueryPlan;
+use crate::storage::{CheckConstraint, ColumnDef, FkAction, References};
+use crate::types::{Collation, Value};
+
+/// A top-level SQL statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Select(Box<SelectStmt>),
+    CreateTable(CreateTableStmt),
+    DropTable(DropTableStmt),
+    CreateIndex(CreateIndexStmt),
+    DropIndex(DropIndexStmt),
+    CreateView(CreateViewStmt),
+    DropView(DropViewStmt),
+    Truncate(TruncateStmt),
+    AlterTable(AlterTableStmt),
+    Insert(InsertStmt),
+    Update(UpdateStmt),
+    Delete(DeleteStmt),
+    /// `BEGIN [TRANSACTION]`
+    Begin,
+    /// `COMMIT [TRANSACTION]` or `END [TRANSACTION]`
+    Commit,
+    /// `ROLLBACK [TRANSACTION]`
+    Rollback,
+    /// `SAVEPOINT <name>`
+    Savepoint(String),
+    /// `RELEASE [SAVEPOINT] <name>`
+    Release(String),
+    /// `ROLLBACK [TRANSACTION] TO [SAVEPOINT] <name>`
+    RollbackTo(String),
+    /// `EXPLAIN <select>`: the plan of the query rather than its rows.
+    Explain(Box<SelectStmt>),
+    /// `ANALYZE [<table>]`, of every table if none is named.
+    Analyze(Option<String>),
+    Pragma(PragmaStmt),
+}
+
+/// `CREATE TABLE [IF NOT EXISTS] <name> (<column>, ...)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTableStmt {
+    pub name: String,
+    pub if_not_exists: bool,
+    pub columns: Vec<ColumnDef>,
+    pub constraints: Vec<TableConstraint>,
+}
+
+/// A constraint declared in `CREATE TABLE` after the columns, over
+/// columns named by it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableConstraint {
+    /// `CHECK (<expr>)`
+    Check(CheckConstraint),
+    /// `PRIMARY KEY (<column>, ...)`
+    PrimaryKey(Vec<String>),
+    /// `UNIQUE (<column>, ...)`
+    Unique(Vec<String>),
+    /// `FOREIGN KEY (<column>, ...) REFERENCES ...`
+    ForeignKey {
+        columns: Vec<String>,
+        references: References,
+    },
+}
+
+/// `DROP TABLE [IF EXISTS] <name>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropTableStmt {
+    pub name: String,
+    pub if_exists: bool,
+}
+
+/// `CREATE [UNIQUE] INDEX [IF NOT EXISTS] <name> ON <table> (<column>, ...)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndexStmt {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+    pub if_not_exists: bool,
+}
+
+/// `DROP INDEX [IF EXISTS] <name>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropIndexStmt {
+    pub name: String,
+    pub if_exists: bool,
+}
+
+/// `CREATE [OR REPLACE] VIEW [IF NOT EXISTS] <name> AS <query>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateViewStmt {
+    pub name: String,
+    pub or_replace: bool,
+    pub if_not_exists: bool,
+    pub query: Box<SelectStmt>,
+}
+
+/// `DROP VIEW [IF EXISTS] <name>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropViewStmt {
+    pub name: String,
+    pub if_exists: bool,
+}
+
+/// `TRUNCATE [TABLE] <name>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TruncateStmt {
+    pub name: String,
+}
+
+/// `PRAGMA <name> [(<arg>)]` or `PRAGMA <name> = <arg>`: a question about
+/// the schema, such as `table_info(<table>)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PragmaStmt {
+    pub name: String,
+    /// The argument, a name given bare or quoted.
+    pub arg: Option<String>,
+}
+
+/// `ALTER TABLE <table> ...`
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlterTableStmt {
+    /// `ADD [COLUMN] <column>`
+    AddColumn {
+        table: String,
+        column_def: ColumnDef,
+    },
+    /// `DROP [COLUMN] [IF EXISTS] <name>`
+    DropColumn {
+        table: String,
+        column_name: String,
+        if_exists: bool,
+    },
+}
+
+/// `INSERT [OR <action>] INTO <table> [(<column>, ...)] <source>
+/// [RETURNING ...]`, where `REPLACE INTO` stands for `INSERT OR REPLACE
+/// INTO`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InsertStmt {
+    /// What becomes of a row whose key is taken; without one the
+    /// statement fails.
+    pub conflict: Option<InsertConflictAction>,
+    pub table: String,
+    pub columns: Option<Vec<String>>,
+    pub source: InsertSource,
+    /// The items of the RETURNING clause, computed for each inserted row;
+    /// empty without one.
+    pub returning: Vec<SelectItem>,
+}
+
+/// What an INSERT does with a row that would give a UNIQUE or primary
+/// key a value some row already has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertConflictAction {
+    /// Delete the rows holding the key first, as a DELETE would.
+    Replace,
+    /// Leave the row out.
+    Ignore,
+}
+
+/// `UPDATE <table> SET <column> = <expr>, ... [WHERE <expr>] [RETURNING ...]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateStmt {
+    pub table: String,
+    pub assignments: Vec<(String, Expr)>,
+    pub where_clause: Option<Expr>,
+    /// As for [`InsertStmt`], for each row as updated.
+    pub returning: Vec<SelectItem>,
+}
+
+/// `DELETE FROM <table> [WHERE <expr>] [RETURNING ...]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteStmt {
+    pub table: String,
+    pub where_clause: Option<Expr>,
+    /// As for [`InsertStmt`], for each row as it was before deletion.
+    pub returning: Vec<SelectItem>,
+}
+
+/// Where an INSERT takes its rows from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertSource {
+    /// `VALUES (<expr>, ...), ...`
+    Values(Vec<Vec<Expr>>),
+    /// `SELECT ...`: the rows of a query.
+    Query(Box<SelectStmt>),
+}
+
+/// A query: a SELECT, or several combined by set operators, then ordered
+/// and limited as a whole.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectStmt {
+    pub with: Option<WithClause>,
+    pub body: QueryBody,
+    pub order_by: Vec<OrderByItem>,
+    pub limit: Option<LimitClause>,
+}
+
+/// `WITH [RECURSIVE] <cte>, ...`: named queries the query after it can
+/// read as tables, each also visible to those defined after it and, with
+/// RECURSIVE, to itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithClause {
+    pub recursive: bool,
+    pub ctes: Vec<Cte>,
+}
+
+/// `<name> [(<column>, ...)] AS (<query>)`, a common table expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cte {
+    pub name: String,
+    /// Names for the query's columns, replacing those it gives them.
+    pub columns: Option<Vec<String>>,
+    pub query: SelectStmt,
+}
+
+/// The rows a query orders and limits.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryBody {
+    Select(Box<SelectCore>),
+    /// `<left> UNION [ALL] | INTERSECT | EXCEPT <right>`. INTERSECT binds
+    /// tighter than UNION and EXCEPT, which associate to the left.
+    SetOperation {
+        op: SetOperator,
+        left: Box<QueryBody>,
+        right: Box<QueryBody>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOperator {
+    Union,
+    UnionAll,
+    Intersect,
+    Except,
+}
+
+/// `SELECT [DISTINCT] <projection> [FROM <table>] [WHERE <expr>]
+/// [GROUP BY <expr>, ...] [HAVING <expr>]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectCore {
+    pub distinct: bool,
+    pub projection: Vec<SelectItem>,
+    pub from: Option<TableRef>,
+    pub where_clause: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+}
+
+/// `LIMIT <n> [OFFSET <m>]`, `LIMIT <m>, <n>` or a bare `OFFSET <m>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitClause {
+    pub limit: Option<Expr>,
+    pub offset: Option<Expr>,
+}
+
+/// `expr [ASC|DESC] [NULLS FIRST|NULLS LAST]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderByItem {
+    pub expr: Expr,
+    pub desc: bool,
+    /// `None` when NULLS placement was not given explicitly.
+    pub nulls_first: Option<bool>,
+}
+
+/// The window of a window function call: `OVER ([PARTITION BY <exprs>]
+/// [ORDER BY <items>] [<frame>])`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowSpec {
+    pub partition_by: Vec<Expr>,
+    pub order_by: Vec<OrderByItem>,
+    /// `None` leaves the frame to the function.
+    pub frame: Option<WindowFrame>,
+}
+
+/// `{ROWS | RANGE} BETWEEN <start> AND <end>`: the rows of the partition,
+/// relative to the current row, that a window function reads. `ROWS
+/// <start>` ends at the current row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowFrame {
+    pub mode: FrameMode,
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameMode {
+    /// Bounds count rows.
+    Rows,
+    /// Bounds are distances from the current row's value of the window's
+    /// single ORDER BY expression, and the current row stands for all its
+    /// peers.
+    Range,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(u64),
+    CurrentRow,
+    Following(u64),
+    UnboundedFollowing,
+}
+
+/// One entry of a SELECT list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    /// `*`
+    Wildcard,
+    /// `t.*`
+    QualifiedWildcard(String),
+    /// `expr [AS alias]`
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+/// A table reference in a FROM clause.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableRef {
+    Table {
+        name: String,
+        alias: Option<String>,
+    },
+    /// `(<select>) [[AS] <alias> [(<column>, ...)]]`, a derived table. The
+    /// list renames the query's columns in order; columns it does not
+    /// name keep the query's name for them. Without an alias the table is
+    /// called `(subquery)`, a name no query refers to it by.
+    Subquery {
+        query: Box<SelectStmt>,
+        alias: Option<String>,
+        columns: Vec<String>,
+    },
+    /// `(VALUES (<expr>, ...), ...) [[AS] <alias> [(<column>, ...)]]`: rows
+    /// given inline, each with as many values. Columns the list does not
+    /// name are called `column1`, `column2` and so on.
+    Values {
+        rows: Vec<Vec<Expr>>,
+        alias: Option<String>,
+        columns: Vec<String>,
+    },
+    /// `<left> [NATURAL] <kind> JOIN <right> [ON <expr> | USING (<cols>)]`;
+    /// joins chain to the left.
+    Join {
+        left: Box<TableRef>,
+        right: Box<TableRef>,
+        kind: JoinKind,
+        constraint: Option<JoinConstraint>,
+    },
+}
+
+/// How the rows of a join are matched up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinConstraint {
+    On(Expr),
+    /// Equality on each listed column, which then appears once in the output.
+    Using(Vec<String>),
+    /// `USING` every column name the two sides have in common.
+    Natural,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    /// Keeps unmatched left rows, padding the right side with NULLs.
+    Left,
+    /// Keeps unmatched right rows, padding the left side with NULLs.
+    Right,
+    /// Keeps unmatched rows of both sides.
+    Full,
+    /// The Cartesian product. Unlike `FROM a, b`, an inner join, its
+    /// tables are joined in the order written.
+    Cross,
+}
+
+impl JoinKind {
+    /// Whether unmatched rows of the left input appear in the output.
+    pub fn preserves_left(self) -> bool {
+        matches!(self, JoinKind::Left | JoinKind::Full)
+    }
+
+    /// Whether unmatched rows of the right input appear in the output.
+    pub fn preserves_right(self) -> bool {
+        matches!(self, JoinKind::Right | JoinKind::Full)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Concat,
+    Eq,
+    Neq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// `GLOB`, LIKE with Unix wildcards.
+    Glob,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Plus,
+    Not,
+}
+
+/// A scalar expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    /// `[table.]column`
+    Column(Option<String>, String),
+    /// A column of an enclosing query's row, as the planner binds a column
+    /// reference in a correlated subquery: `index` into the row of the
+    /// query `depth` levels further out than the innermost enclosing one.
+    OuterRef {
+        table: Option<String>,
+        name: String,
+        depth: usize,
+        index: usize,
+    },
+    BinOp {
+        op: BinOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    UnaryOp {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    FunctionCall {
+        name: String,
+        args: Vec<Expr>,
+    },
+    /// A call of an aggregate function, computed over a group of rows.
+    Aggregate {
+        func: AggFunc,
+        distinct: bool,
+        /// Empty for `COUNT(*)`.
+        args: Vec<Expr>,
+        /// `ORDER BY` after the arguments: the order the rows are read in,
+        /// which only `GROUP_CONCAT` depends on.
+        order_by: Vec<OrderByItem>,
+        /// `FILTER (WHERE <filter>)`: only the rows it holds for are
+        /// aggregated.
+        filter: Option<Box<Expr>>,
+    },
+    /// `<name>(<args>) OVER (<window>)`: a window function, computed for
+    /// each row from the rows of its partition.
+    Window {
+        name: String,
+        /// Empty for `COUNT(*)`.
+        args: Vec<Expr>,
+        /// As for [`Expr::Aggregate`]; only aggregates take one.
+        filter: Option<Box<Expr>>,
+        window: Box<WindowSpec>,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    InSubquery {
+        expr: Box<Expr>,
+        subquery: Box<SelectStmt>,
+        negated: bool,
+    },
+    /// `<expr> <op> ANY|SOME|ALL (<select>)`: whether comparing `expr` with
+    /// the values of the subquery's single column holds for some of them,
+    /// or for all.
+    QuantifiedComparison {
+        expr: Box<Expr>,
+        op: BinOp,
+        quantifier: Quantifier,
+        subquery: Box<SelectStmt>,
+    },
+    Exists(Box<SelectStmt>),
+    ScalarSubquery(Box<SelectStmt>),
+    /// `(<expr>, <expr>, ...)`, a row value. Only the left side of an IN
+    /// and the items of its list may be one.
+    Tuple(Vec<Expr>),
+    /// A subquery after planning; only the planner creates these, from the
+    /// subquery forms above.
+    Subquery {
+        kind: SubqueryKind,
+        plan: SubqueryPlan,
+    },
+    /// `CASE WHEN <condition> THEN <result> ... [ELSE <result>] END`. The
+    /// parser desugars the simple form, `CASE <operand> WHEN <value> ...`,
+    /// into comparisons of the operand with each value.
+    Case {
+        when_clauses: Vec<(Expr, Expr)>,
+        else_expr: Option<Box<Expr>>,
+    },
+    Cast {
+        expr: Box<Expr>,
+        /// The type name as written, e.g. `INTEGER` or `VARCHAR(10)`.
+        ty: String,
+    },
+    /// `<expr> LIKE <pattern> [ESCAPE <escape>]`
+    Like {
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        escape: Option<Box<Expr>>,
+    },
+    /// `<expr> COLLATE <collation>`: the expression's value, compared
+    /// under the given collation.
+    Collate {
+        expr: Box<Expr>,
+        collation: Collation,
+    },
+    CurrentTime(CurrentTime),
+}
+
+/// `CURRENT_DATE`, `CURRENT_TIME` or `CURRENT_TIMESTAMP`: the UTC date,
+/// time of day, or both, at which the statement started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurrentTime {
+    Date,
+    Time,
+    Timestamp,
+}
+
+impl CurrentTime {
+    pub fn from_name(name: &str) -> Option<CurrentTime> {
+        match name.to_ascii_uppercase().as_str() {
+            "CURRENT_DATE" => Some(CurrentTime::Date),
+            "CURRENT_TIME" => Some(CurrentTime::Time),
+            "CURRENT_TIMESTAMP" => Some(CurrentTime::Timestamp),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CurrentTime::Date => "CURRENT_DATE",
+            CurrentTime::Time => "CURRENT_TIME",
+            CurrentTime::Timestamp => "CURRENT_TIMESTAMP",
+        }
+    }
+}
+
+/// Which values of a subquery a [`Expr::QuantifiedComparison`] must hold
+/// for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantifier {
+    /// `ANY`, also called `SOME`: at least one. FALSE with no values.
+    Any,
+    /// `ALL`: every one. TRUE with no values.
+    All,
+}
+
+impl Quantifier {
+    pub fn name(self) -> &'static str {
+        match self {
+            Quantifier::Any => "ANY",
+            Quantifier::All => "ALL",
+        }
+    }
+}
+
+/// How a planned subquery's rows become a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubqueryKind {
+    /// The single column of the single row, or NULL without rows.
+    Scalar,
+    /// Whether there is any row.
+    Exists,
+    /// Whether `expr` equals a row, or the value of a single column; a
+    /// [`Expr::Tuple`] is compared with rows of as many columns.
+    In { expr: Box<Expr>, negated: bool },
+    /// Whether comparing `expr` with the values of the single column holds
+    /// for some or all of them.
+    Quantified {
+        expr: Box<Expr>,
+        op: BinOp,
+        quantifier: Quantifier,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    /// `GROUP_CONCAT(x[, delimiter])`, also called `STRING_AGG`.
+    GroupConcat,
+    /// The variance of a population, dividing by the count.
+    VarPop,
+    /// The variance of a sample, dividing by one less than the count; also
+    /// called `VARIANCE`.
+    VarSamp,
+    StddevPop,
+    /// Also called `STDDEV`.
+    StddevSamp,
+}
+
+impl AggFunc {
+    /// The aggregate function called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<AggFunc> {
+        let func = match name.to_ascii_uppercase().as_str() {
+            "COUNT" => AggFunc::Count,
+            "SUM" => AggFunc::Sum,
+            "AVG" => AggFunc::Avg,
+            "MIN" => AggFunc::Min,
+            "MAX" => AggFunc::Max,
+            "GROUP_CONCAT" | "STRING_AGG" => AggFunc::GroupConcat,
+            "VAR_POP" => AggFunc::VarPop,
+            "VAR_SAMP" | "VARIANCE" => AggFunc::VarSamp,
+            "STDDEV_POP" => AggFunc::StddevPop,
+            "STDDEV_SAMP" | "STDDEV" => AggFunc::StddevSamp,
+            _ => return None,
+        };
+        Some(func)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AggFunc::Count => "COUNT",
+            AggFunc::Sum => "SUM",
+            AggFunc::Avg => "AVG",
+            AggFunc::Min => "MIN",
+            AggFunc::Max => "MAX",
+            AggFunc::GroupConcat => "GROUP_CONCAT",
+            AggFunc::VarPop => "VAR_POP",
+            AggFunc::VarSamp => "VAR_SAMP",
+            AggFunc::StddevPop => "STDDEV_POP",
+            AggFunc::StddevSamp => "STDDEV_SAMP",
+        }
+    }
+}
+
+impl Expr {
+    /// The function of the first aggregate call in the expression, outside
+    /// any subquery.
+    pub fn first_aggregate(&self) -> Option<AggFunc> {
+        let mut found = None;
+        self.transform(&mut |e| match e {
+            Expr::Aggregate { func, .. } => {
+                found = found.or(Some(*func));
+                Some(e.clone())
+            }
+            _ => None,
+        });
+        found
+    }
+
+    /// The function name of the first window function call in the
+    /// expression, outside any subquery.
+    pub fn first_window(&self) -> Option<String> {
+        let mut found = None;
+        self.transform(&mut |e| match e {
+            Expr::Window { name, .. } => {
+                found = found.take().or_else(|| Some(name.clone()));
+                Some(e.clone())
+            }
+            _ => None,
+        });
+        found
+    }
+
+    /// Rebuild the expression bottom-up. `f` is offered every node first;
+    /// returning `Some` replaces that node (and its children are not
+    /// visited), returning `None` keeps it and recurses into its children.
+    /// Subqueries are opaque: their bodies belong to a different scope.
+    pub fn transform(&self, f: &mut impl FnMut(&Expr) -> Option<Expr>) -> Expr {
+        if let Some(replacement) = f(self) {
+            return replacement;
+        }
+        let mut sub = |e: &Expr| Box::new(e.transform(f));
+        match self {
+            Expr::Literal(_)
+            | Expr::CurrentTime(_)
+            | Expr::Column(..)
+            | Expr::OuterRef { .. }
+            | Expr::Exists(_)
+            | Expr::ScalarSubquery(_) => self.clone(),
+            Expr::Subquery { kind, plan } => Expr::Subquery {
+                kind: match kind {
+                    SubqueryKind::In { expr, negated } => SubqueryKind::In {
+                        expr: sub(expr),
+                        negated: *negated,
+                    },
+                    SubqueryKind::Quantified {
+                        expr,
+                        op,
+                        quantifier,
+                    } => SubqueryKind::Quantified {
+                        expr: sub(expr),
+                        op: *op,
+                        quantifier: *quantifier,
+                    },
+                    kind => kind.clone(),
+                },
+                plan: plan.clone(),
+            },
+            Expr::BinOp { op, left, right } => Expr::BinOp {
+                op: *op,
+                left: sub(left),
+                right: sub(right),
+            },
+            Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+                op: *op,
+                expr: sub(expr),
+            },
+            Expr::FunctionCall { name, args } => Expr::FunctionCall {
+                name: name.clone(),
+                args: args.iter().map(|a| *sub(a)).collect(),
+            },
+            Expr::Tuple(items) => Expr::Tuple(items.iter().map(|e| *sub(e)).collect()),
+            Expr::Aggregate {
+                func,
+                distinct,
+                args,
+                order_by,
+                filter,
+            } => Expr::Aggregate {
+                func: *func,
+                distinct: *distinct,
+                args: args.iter().map(|a| *sub(a)).collect(),
+                order_by: order_by
+                    .iter()
+                    .map(|item| OrderByItem {
+                        expr: *sub(&item.expr),
+                        ..item.clone()
+                    })
+                    .collect(),
+                filter: filter.as_deref().map(&mut sub),
+            },
+            Expr::Window {
+                name,
+                args,
+                filter,
+                window,
+            } => Expr::Window {
+                name: name.clone(),
+                args: args.iter().map(|a| *sub(a)).collect(),
+                filter: filter.as_deref().map(&mut sub),
+                window: Box::new(WindowSpec {
+                    partition_by: window.partition_by.iter().map(|e| *sub(e)).collect(),
+                    order_by: window
+                        .order_by
+                        .iter()
+                        .map(|item| OrderByItem {
+                            expr: *sub(&item.expr),
+                            ..item.clone()
+                        })
+                        .collect(),
+                    frame: window.frame,
+                }),
+            },
+            Expr::IsNull { expr, negated } => Expr::IsNull {
+                expr: sub(expr),
+                negated: *negated,
+            },
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => Expr::Between {
+                expr: sub(expr),
+                low: sub(low),
+                high: sub(high),
+                negated: *negated,
+            },
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => Expr::InList {
+                expr: sub(expr),
+                list: list.iter().map(|e| *sub(e)).collect(),
+                negated: *negated,
+            },
+            Expr::InSubquery {
+                expr,
+                subquery,
+                negated,
+            } => Expr::InSubquery {
+                expr: sub(expr),
+                subquery: subquery.clone(),
+                negated: *negated,
+            },
+            Expr::QuantifiedComparison {
+                expr,
+                op,
+                quantifier,
+                subquery,
+            } => Expr::QuantifiedComparison {
+                expr: sub(expr),
+                op: *op,
+                quantifier: *quantifier,
+                subquery: subquery.clone(),
+            },
+            Expr::Case {
+                when_clauses,
+                else_expr,
+            } => Expr::Case {
+                when_clauses: when_clauses
+                    .iter()
+                    .map(|(when, then)| (*sub(when), *sub(then)))
+                    .collect(),
+                else_expr: else_expr.as_deref().map(&mut sub),
+            },
+            Expr::Cast { expr, ty } => Expr::Cast {
+                expr: sub(expr),
+                ty: ty.clone(),
+            },
+            Expr::Like {
+                expr,
+                pattern,
+                escape,
+            } => Expr::Like {
+                expr: sub(expr),
+                pattern: sub(pattern),
+                escape: escape.as_deref().map(&mut sub),
+            },
+            Expr::Collate { expr, collation } => Expr::Collate {
+                expr: sub(expr),
+                collation: *collation,
+            },
+        }
+    }
+}
+
+// Binding strength used when printing; mirrors the parser's levels.
+const PREC_OR: u8 = 1;
+const PREC_AND: u8 = 2;
+const PREC_NOT: u8 = 3;
+const PREC_CMP: u8 = 4;
+const PREC_ADD: u8 = 5;
+const PREC_MUL: u8 = 6;
+const PREC_CONCAT: u8 = 7;
+const PREC_UNARY: u8 = 8;
+const PREC_PRIMARY: u8 = 9;
+
+impl BinOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Or => PREC_OR,
+            BinOp::And => PREC_AND,
+            BinOp::Eq
+            | BinOp::Neq
+            | BinOp::Lt
+            | BinOp::Le
+            | BinOp::Gt
+            | BinOp::Ge
+            | BinOp::Glob => PREC_CMP,
+            BinOp::Add | BinOp::Sub => PREC_ADD,
+            BinOp::Mul | BinOp::Div | BinOp::Mod => PREC_MUL,
+            BinOp::Concat => PREC_CONCAT,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Concat => "||",
+            BinOp::Eq => "=",
+            BinOp::Neq => "<>",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::Glob => "GLOB",
+            BinOp::And => "AND",
+            BinOp::Or => "OR",
+        }
+    }
+}
+
+impl Expr {
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::BinOp { op, .. } => op.precedence(),
+            Expr::UnaryOp {
+                op: UnaryOp::Not, ..
+            } => PREC_NOT,
+            Expr::UnaryOp { .. } | Expr::Collate { .. } => PREC_UNARY,
+            Expr::IsNull { .. }
+            | Expr::Like { .. }
+            | Expr::Between { .. }
+            | Expr::InList { .. }
+            | Expr::InSubquery { .. }
+            | Expr::QuantifiedComparison { .. }
+            | Expr::Subquery {
+                kind: SubqueryKind::In { .. } | SubqueryKind::Quantified { .. },
+                ..
+            } => PREC_CMP,
+            Expr::Literal(Value::Integer(n)) if *n < 0 => PREC_UNARY,
+            Expr::Literal(Value::Real(n)) if n.is_sign_negative() => PREC_UNARY,
+            _ => PREC_PRIMARY,
+        }
+    }
+}
+
+/// Wraps an expression so it prints with parentheses if its precedence is
+/// below `min`.
+struct Prec<'a>(&'a Expr, u8);
+
+impl fmt::Display for Prec<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.precedence() < self.1 {
+            write!(f, "({})", self.0)
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+/// Print an identifier, quoting it if it would not lex back as a bare word.
+pub struct Ident<'a>(pub &'a str);
+
+impl fmt::Display for Ident<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.0;
+        let bare = name
+            .chars()
+            .next()
+            .is_some_and(|c| c == '_' || c.is_alphabetic())
+            && name.chars().all(|c| c == '_' || c.is_alphanumeric());
+        let is_keyword = matches!(
+            crate::lexer::tokenize(name).as_deref(),
+            Ok([tok]) if !matches!(tok, crate::lexer::Token::Ident(_))
+        );
+        if bare && !is_keyword {
+            write!(f, "{name}")
+        } else {
+            write!(f, "\"{}\"", name.replace('"', "\"\""))
+        }
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{item}")?;
+    }
+    Ok(())
+}
+
+fn write_filter(f: &mut fmt::Formatter<'_>, filter: Option<&Expr>) -> fmt::Result {
+    match filter {
+        Some(filter) => write!(f, " FILTER (WHERE {filter})"),
+        None => Ok(()),
+    }
+}
+
+/// Print a value as a SQL literal.
+fn write_literal(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        Value::Null => write!(f, "NULL"),
+        Value::Integer(n) => write!(f, "{n}"),
+        // `{:?}` keeps a trailing `.0` so the literal lexes back as REAL.
+        Value::Real(n) => write!(f, "{n:?}"),
+        Value::Text(s) => write!(f, "'{}'", s.replace('\'', "''")),
+        Value::Blob(_) => write!(f, "X'{value}'"),
+        Value::Boolean(true) => write!(f, "TRUE"),
+        Value::Boolean(false) => write!(f, "FALSE"),
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(value) => write_literal(f, value),
+            Expr::Column(Some(table), name) => write!(f, "{}.{}", Ident(table), Ident(name)),
+            Expr::Column(None, name) => write!(f, "{}", Ident(name)),
+            Expr::OuterRef {
+                table: Some(table),
+                name,
+                ..
+            } => write!(f, "{}.{}", Ident(table), Ident(name)),
+            Expr::OuterRef { name, .. } => write!(f, "{}", Ident(name)),
+            Expr::BinOp { op, left, right } => {
+                let prec = op.precedence();
+                write!(
+                    f,
+                    "{} {} {}",
+                    Prec(left, prec),
+                    op.symbol(),
+                    Prec(right, prec + 1)
+                )
+            }
+            Expr::UnaryOp {
+                op: UnaryOp::Not,
+                expr,
+            } => write!(f, "NOT {}", Prec(expr, PREC_NOT)),
+            Expr::UnaryOp { op, expr } => {
+                let symbol = if *op == UnaryOp::Neg { "-" } else { "+" };
+                // `- -x` must not collapse into a `--` comment.
+                let inner = Prec(expr, PREC_UNARY).to_string();
+                if inner.starts_with(['-', '+']) {
+                    write!(f, "{symbol}({inner})")
+                } else {
+                    write!(f, "{symbol}{inner}")
+                }
+            }
+            Expr::CurrentTime(kind) => write!(f, "{}", kind.name()),
+            Expr::FunctionCall { name, args } => {
+                write!(f, "{name}(")?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+            Expr::Tuple(items) => {
+                write!(f, "(")?;
+                write_list(f, items)?;
+                write!(f, ")")
+            }
+            Expr::Aggregate {
+                func,
+                distinct,
+                args,
+                order_by,
+                filter,
+            } => {
+                write!(f, "{}(", func.name())?;
+                if *distinct {
+                    write!(f, "DISTINCT ")?;
+                }
+                if args.is_empty() {
+                    write!(f, "*")?;
+                }
+                write_list(f, args)?;
+                if !order_by.is_empty() {
+                    write!(f, " ORDER BY ")?;
+                    write_list(f, order_by)?;
+                }
+                write!(f, ")")?;
+                write_filter(f, filter.as_deref())
+            }
+            Expr::Window {
+                name,
+                args,
+                filter,
+                window,
+            } => {
+                write!(f, "{name}(")?;
+                if args.is_empty() && name.eq_ignore_ascii_case("COUNT") {
+                    write!(f, "*")?;
+                }
+                write_list(f, args)?;
+                write!(f, ")")?;
+                write_filter(f, filter.as_deref())?;
+                write!(f, " OVER ({window})")
+            }
+            Expr::IsNull { expr, negated } => {
+                let not = if *negated { " NOT" } else { "" };
+                write!(f, "{} IS{not} NULL", Prec(expr, PREC_CMP))
+            }
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => {
+                let not = if *negated { " NOT" } else { "" };
+                write!(
+                    f,
+                    "{}{not} BETWEEN {} AND {}",
+                    Prec(expr, PREC_CMP),
+                    Prec(low, PREC_ADD),
+                    Prec(high, PREC_ADD)
+                )
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let not = if *negated { " NOT" } else { "" };
+                write!(f, "{}{not} IN (", Prec(expr, PREC_CMP))?;
+                write_list(f, list)?;
+                write!(f, ")")
+            }
+            Expr::InSubquery {
+                expr,
+                subquery,
+                negated,
+            } => {
+                let not = if *negated { " NOT" } else { "" };
+                write!(f, "{}{not} IN ({subquery})", Prec(expr, PREC_CMP))
+            }
+            Expr::QuantifiedComparison {
+                expr,
+                op,
+                quantifier,
+                subquery,
+            } => write_quantified(f, expr, *op, *quantifier, subquery),
+            Expr::Exists(subquery) => write!(f, "EXISTS ({subquery})"),
+            Expr::ScalarSubquery(subquery) => write!(f, "({subquery})"),
+            Expr::Subquery { kind, plan } => match kind {
+                SubqueryKind::Scalar => write!(f, "({})", plan.query),
+                SubqueryKind::Exists => write!(f, "EXISTS ({})", plan.query),
+                SubqueryKind::In { expr, negated } => {
+                    let not = if *negated { " NOT" } else { "" };
+                    write!(f, "{}{not} IN ({})", Prec(expr, PREC_CMP), plan.query)
+                }
+                SubqueryKind::Quantified {
+                    expr,
+                    op,
+                    quantifier,
+                } => write_quantified(f, expr, *op, *quantifier, &plan.query),
+            },
+            Expr::Case {
+                when_clauses,
+                else_expr,
+            } => {
+                write!(f, "CASE")?;
+                for (when, then) in when_clauses {
+                    write!(f, " WHEN {when} THEN {then}")?;
+                }
+                if let Some(else_expr) = else_expr {
+                    write!(f, " ELSE {else_expr}")?;
+                }
+                write!(f, " END")
+            }
+            Expr::Cast { expr, ty } => write!(f, "CAST({expr} AS {ty})"),
+            Expr::Like {
+                expr,
+                pattern,
+                escape,
+            } => {
+                write!(
+                    f,
+                    "{} LIKE {}",
+                    Prec(expr, PREC_CMP),
+                    Prec(pattern, PREC_CMP + 1)
+                )?;
+                if let Some(escape) = escape {
+                    write!(f, " ESCAPE {}", Prec(escape, PREC_CMP + 1))?;
+                }
+                Ok(())
+            }
+            Expr::Collate { expr, collation } => {
+                write!(f, "{} COLLATE {collation}", Prec(expr, PREC_PRIMARY))
+            }
+        }
+    }
+}
+
+impl fmt::Display for SelectItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectItem::Wildcard => write!(f, "*"),
+            SelectItem::QualifiedWildcard(table) => write!(f, "{}.*", Ident(table)),
+            SelectItem::Expr { expr, alias: None } => write!(f, "{expr}"),
+            SelectItem::Expr {
+                expr,
+                alias: Some(alias),
+            } => write!(f, "{expr} AS {}", Ident(alias)),
+        }
+    }
+}
+
+impl fmt::Display for TableRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableRef::Table { name, alias } => {
+                write!(f, "{}", Ident(name))?;
+                if let Some(alias) = alias {
+                    write!(f, " AS {}", Ident(alias))?;
+                }
+                Ok(())
+            }
+            TableRef::Subquery {
+                query,
+                alias,
+                columns,
+            } => {
+                write!(f, "({query})")?;
+                if let Some(alias) = alias {
+                    write!(f, " AS {}", Ident(alias))?;
+                }
+                write_column_aliases(f, columns)
+            }
+            TableRef::Values {
+                rows,
+                alias,
+                columns,
+            } => {
+                write!(f, "(")?;
+                write_values(f, rows)?;
+                write!(f, ")")?;
+                if let Some(alias) = alias {
+                    write!(f, " AS {}", Ident(alias))?;
+                }
+                write_column_aliases(f, columns)
+            }
+            TableRef::Join {
+                left,
+                right,
+                kind,
+                constraint,
+            } => match constraint {
+                None => write!(f, "{left} {kind} {right}"),
+                Some(JoinConstraint::On(on)) => write!(f, "{left} {kind} {right} ON {on}"),
+                Some(JoinConstraint::Using(columns)) => {
+                    write!(f, "{left} {kind} {right} USING (")?;
+                    for (i, column) in columns.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", Ident(column))?;
+                    }
+                    write!(f, ")")
+                }
+                Some(JoinConstraint::Natural) => write!(f, "{left} NATURAL {kind} {right}"),
+            },
+        }
+    }
+}
+
+impl fmt::Display for JoinKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinKind::Inner => write!(f, "JOIN"),
+            JoinKind::Left => write!(f, "LEFT JOIN"),
+            JoinKind::Right => write!(f, "RIGHT JOIN"),
+            JoinKind::Full => write!(f, "FULL JOIN"),
+            JoinKind::Cross => write!(f, "CROSS JOIN"),
+        }
+    }
+}
+
+impl fmt::Display for SelectStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(with) = &self.with {
+            write!(f, "{with} ")?;
+        }
+        write!(f, "{}", self.body)?;
+        if !self.order_by.is_empty() {
+            write!(f, " ORDER BY ")?;
+            write_list(f, &self.order_by)?;
+        }
+        if let Some(limit) = &self.limit {
+            write!(f, "{limit}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for WithClause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WITH ")?;
+        if self.recursive {
+            write!(f, "RECURSIVE ")?;
+        }
+        write_list(f, &self.ctes)
+    }
+}
+
+impl fmt::Display for Cte {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Ident(&self.name))?;
+        if let Some(columns) = &self.columns {
+            let columns: Vec<Ident> = columns.iter().map(|c| Ident(c)).collect();
+            write!(f, " (")?;
+            write_list(f, &columns)?;
+            write!(f, ")")?;
+        }
+        write!(f, " AS ({})", self.query)
+    }
+}
+
+impl fmt::Display for QueryBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryBody::Select(select) => write!(f, "{select}"),
+            QueryBody::SetOperation { op, left, right } => write!(f, "{left} {op} {right}"),
+        }
+    }
+}
+
+impl fmt::Display for SetOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SetOperator::Union => "UNION",
+            SetOperator::UnionAll => "UNION ALL",
+            SetOperator::Intersect => "INTERSECT",
+            SetOperator::Except => "EXCEPT",
+        })
+    }
+}
+
+impl fmt::Display for SelectCore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SELECT ")?;
+        if self.distinct {
+            write!(f, "DISTINCT ")?;
+        }
+        write_list(f, &self.projection)?;
+        if let Some(from) = &self.from {
+            write!(f, " FROM {from}")?;
+        }
+        if let Some(where_clause) = &self.where_clause {
+            write!(f, " WHERE {where_clause}")?;
+        }
+        if !self.group_by.is_empty() {
+            write!(f, " GROUP BY ")?;
+            write_list(f, &self.group_by)?;
+        }
+        if let Some(having) = &self.having {
+            write!(f, " HAVING {having}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints with a leading space, as it always follows another clause.
+impl fmt::Display for LimitClause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(limit) = &self.limit {
+            write!(f, " LIMIT {limit}")?;
+        }
+        if let Some(offset) = &self.offset {
+            write!(f, " OFFSET {offset}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for OrderByItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expr)?;
+        if self.desc {
+            write!(f, " DESC")?;
+        }
+        match self.nulls_first {
+            Some(true) => write!(f, " NULLS FIRST"),
+            Some(false) => write!(f, " NULLS LAST"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for WindowSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.partition_by.is_empty() {
+            write!(f, "PARTITION BY ")?;
+            write_list(f, &self.partition_by)?;
+            if !self.order_by.is_empty() {
+                write!(f, " ")?;
+            }
+        }
+        if !self.order_by.is_empty() {
+            write!(f, "ORDER BY ")?;
+            write_list(f, &self.order_by)?;
+        }
+        if let Some(frame) = &self.frame {
+            if !(self.partition_by.is_empty() && self.order_by.is_empty()) {
+                write!(f, " ")?;
+            }
+            write!(f, "{frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for WindowFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.mode {
+            FrameMode::Rows => "ROWS",
+            FrameMode::Range => "RANGE",
+        };
+        write!(f, "{mode} BETWEEN {} AND {}", self.start, self.end)
+    }
+}
+
+impl fmt::Display for FrameBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameBound::UnboundedPreceding => write!(f, "UNBOUNDED PRECEDING"),
+            FrameBound::Preceding(n) => write!(f, "{n} PRECEDING"),
+            FrameBound::CurrentRow => write!(f, "CURRENT ROW"),
+            FrameBound::Following(n) => write!(f, "{n} FOLLOWING"),
+            FrameBound::UnboundedFollowing => write!(f, "UNBOUNDED FOLLOWING"),
+        }
+    }
+}
+
+impl fmt::Display for ColumnDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Ident(&self.name))?;
+        // AUTOINCREMENT may only follow PRIMARY KEY; elsewhere the column
+        // was declared SERIAL.
+        let serial = self.autoincrement && !self.primary_key;
+        if serial {
+            write!(f, " SERIAL")?;
+        } else if let Some(ty) = self.data_type {
+            write!(f, " {ty}")?;
+        }
+        if self.collation != Collation::Binary {
+            write!(f, " COLLATE {}", self.collation)?;
+        }
+        if !self.nullable {
+            write!(f, " NOT NULL")?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " DEFAULT {}", Prec(default, PREC_UNARY))?;
+        }
+        if self.primary_key {
+            write!(f, " PRIMARY KEY")?;
+            if self.autoincrement {
+                write!(f, " AUTOINCREMENT")?;
+            }
+        }
+        if self.unique {
+            write!(f, " UNIQUE")?;
+        }
+        if let Some(references) = &self.references {
+            write!(f, " {references}")?;
+        }
+        for check in &self.checks {
+            write!(f, " {check}")?;
+        }
+        if let Some(generated) = &self.generated {
+            let storage = if generated.stored {
+                "STORED"
+            } else {
+                "VIRTUAL"
+            };
+            write!(f, " GENERATED ALWAYS AS ({}) {storage}", generated.expr)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CheckConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = &self.name {
+            write!(f, "CONSTRAINT {} ", Ident(name))?;
+        }
+        write!(f, "CHECK ({})", self.expr)
+    }
+}
+
+impl fmt::Display for References {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "REFERENCES {}", Ident(&self.table))?;
+        if !self.columns.is_empty() {
+            let columns: Vec<Ident> = self.columns.iter().map(|c| Ident(c)).collect();
+            write!(f, " (")?;
+            write_list(f, &columns)?;
+            write!(f, ")")?;
+        }
+        if self.on_delete != FkAction::NoAction {
+            write!(f, " ON DELETE {}", self.on_delete)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CreateTableStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CREATE TABLE ")?;
+        if self.if_not_exists {
+            write!(f, "IF NOT EXISTS ")?;
+        }
+        write!(f, "{} (", Ident(&self.name))?;
+        write_list(f, &self.columns)?;
+        for constraint in &self.constraints {
+            write!(f, ", {constraint}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Display for TableConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (keyword, columns) = match self {
+            TableConstraint::Check(check) => return write!(f, "{check}"),
+            TableConstraint::PrimaryKey(columns) => ("PRIMARY KEY", columns),
+            TableConstraint::Unique(columns) => ("UNIQUE", columns),
+            TableConstraint::ForeignKey { columns, .. } => ("FOREIGN KEY", columns),
+        };
+        let columns: Vec<Ident> = columns.iter().map(|c| Ident(c)).collect();
+        write!(f, "{keyword} (")?;
+        write_list(f, &columns)?;
+        write!(f, ")")?;
+        if let TableConstraint::ForeignKey { references, .. } = self {
+            write!(f, " {references}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DropTableStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DROP TABLE ")?;
+        if self.if_exists {
+            write!(f, "IF EXISTS ")?;
+        }
+        write!(f, "{}", Ident(&self.name))
+    }
+}
+
+impl fmt::Display for CreateIndexStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CREATE ")?;
+        if self.unique {
+            write!(f, "UNIQUE ")?;
+        }
+        write!(f, "INDEX ")?;
+        if self.if_not_exists {
+            write!(f, "IF NOT EXISTS ")?;
+        }
+        write!(f, "{} ON {} (", Ident(&self.name), Ident(&self.table))?;
+        let columns: Vec<Ident> = self.columns.iter().map(|c| Ident(c)).collect();
+        write_list(f, &columns)?;
+        write!(f, ")")
+    }
+}
+
+impl fmt::Display for DropIndexStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DROP INDEX ")?;
+        if self.if_exists {
+            write!(f, "IF EXISTS ")?;
+        }
+        write!(f, "{}", Ident(&self.name))
+    }
+}
+
+impl fmt::Display for CreateViewStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CREATE ")?;
+        if self.or_replace {
+            write!(f, "OR REPLACE ")?;
+        }
+        write!(f, "VIEW ")?;
+        if self.if_not_exists {
+            write!(f, "IF NOT EXISTS ")?;
+        }
+        write!(f, "{} AS {}", Ident(&self.name), self.query)
+    }
+}
+
+impl fmt::Display for DropViewStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DROP VIEW ")?;
+        if self.if_exists {
+            write!(f, "IF EXISTS ")?;
+        }
+        write!(f, "{}", Ident(&self.name))
+    }
+}
+
+impl fmt::Display for AlterTableStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlterTableStmt::AddColumn { table, column_def } => {
+                write!(f, "ALTER TABLE {} ADD COLUMN {column_def}", Ident(table))
+            }
+            AlterTableStmt::DropColumn {
+                table,
+                column_name,
+                if_exists,
+            } => {
+                write!(f, "ALTER TABLE {} DROP COLUMN ", Ident(table))?;
+                if *if_exists {
+                    write!(f, "IF EXISTS ")?;
+                }
+                write!(f, "{}", Ident(column_name))
+            }
+        }
+    }
+}
+
+impl fmt::Display for InsertStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "INSERT ")?;
+        match self.conflict {
+            Some(InsertConflictAction::Replace) => write!(f, "OR REPLACE ")?,
+            Some(InsertConflictAction::Ignore) => write!(f, "OR IGNORE ")?,
+            None => {}
+        }
+        write!(f, "INTO {}", Ident(&self.table))?;
+        if let Some(columns) = &self.columns {
+            let columns: Vec<Ident> = columns.iter().map(|c| Ident(c)).collect();
+            write!(f, " (")?;
+            write_list(f, &columns)?;
+            write!(f, ")")?;
+        }
+        write!(f, " {}", self.source)?;
+        write_returning(f, &self.returning)
+    }
+}
+
+impl fmt::Display for UpdateStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UPDATE {} SET ", Ident(&self.table))?;
+        for (i, (column, expr)) in self.assignments.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} = {expr}", Ident(column))?;
+        }
+        if let Some(where_clause) = &self.where_clause {
+            write!(f, " WHERE {where_clause}")?;
+        }
+        write_returning(f, &self.returning)
+    }
+}
+
+impl fmt::Display for DeleteStmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DELETE FROM {}", Ident(&self.table))?;
+        if let Some(where_clause) = &self.where_clause {
+            write!(f, " WHERE {where_clause}")?;
+        }
+        write_returning(f, &self.returning)
+    }
+}
+
+fn write_returning(f: &mut fmt::Formatter<'_>, items: &[SelectItem]) -> fmt::Result {
+    if items.is_empty() {
+        return Ok(());
+    }
+    write!(f, " RETURNING ")?;
+    write_list(f, items)
+}
+
+impl fmt::Display for InsertSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertSource::Values(rows) => write_values(f, rows),
+            InsertSource::Query(query) => write!(f, "{query}"),
+        }
+    }
+}
+
+/// Print `<expr> <op> ANY|ALL (<select>)`.
+fn write_quantified(
+    f: &mut fmt::Formatter<'_>,
+    expr: &Expr,
+    op: BinOp,
+    quantifier: Quantifier,
+    subquery: &SelectStmt,
+) -> fmt::Result {
+    write!(
+        f,
+        "{} {} {} ({subquery})",
+        Prec(expr, PREC_CMP),
+        op.symbol(),
+        quantifier.name()
+    )
+}
+
+/// Print a derived table's ` (<column>, ...)`, if it names any columns.
+fn write_column_aliases(f: &mut fmt::Formatter<'_>, columns: &[String]) -> fmt::Result {
+    if columns.is_empty() {
+        return Ok(());
+    }
+    let columns: Vec<Ident> = columns.iter().map(|c| Ident(c)).collect();
+    write!(f, " (")?;
+    write_list(f, &columns)?;
+    write!(f, ")")
+}
+
+/// Print `VALUES (<expr>, ...), ...`.
+fn write_values(f: &mut fmt::Formatter<'_>, rows: &[Vec<Expr>]) -> fmt::Result {
+    write!(f, "VALUES ")?;
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "(")?;
+        write_list(f, row)?;
+        write!(f, ")")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Select(select) => write!(f, "{select}"),
+            Statement::CreateTable(create) => write!(f, "{create}"),
+            Statement::DropTable(drop) => write!(f, "{drop}"),
+            Statement::CreateIndex(create) => write!(f, "{create}"),
+            Statement::DropIndex(drop) => write!(f, "{drop}"),
+            Statement::CreateView(create) => write!(f, "{create}"),
+            Statement::DropView(drop) => write!(f, "{drop}"),
+            Statement::Truncate(truncate) => write!(f, "TRUNCATE TABLE {}", Ident(&truncate.name)),
+            Statement::AlterTable(alter) => write!(f, "{alter}"),
+            Statement::Insert(insert) => write!(f, "{insert}"),
+            Statement::Update(update) => write!(f, "{update}"),
+            Statement::Delete(delete) => write!(f, "{delete}"),
+            Statement::Begin => write!(f, "BEGIN TRANSACTION"),
+            Statement::Commit => write!(f, "COMMIT"),
+            Statement::Rollback => write!(f, "ROLLBACK"),
+            Statement::Savepoint(name) => write!(f, "SAVEPOINT {}", Ident(name)),
+            Statement::Release(name) => write!(f, "RELEASE SAVEPOINT {}", Ident(name)),
+            Statement::RollbackTo(name) => write!(f, "ROLLBACK TO SAVEPOINT {}", Ident(name)),
+            Statement::Explain(select) => write!(f, "EXPLAIN {select}"),
+            Statement::Analyze(None) => write!(f, "ANALYZE"),
+            Statement::Analyze(Some(table)) => write!(f, "ANALYZE {}", Ident(table)),
+            Statement::Pragma(pragma) => {
+                write!(f, "PRAGMA {}", Ident(&pragma.name))?;
+                match &pragma.arg {
+                    Some(arg) => write!(f, "({})", Ident(arg)),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,428 @@
+//! Connections to a [`SharedDatabase`], each with its own transactions.
+//!
+//! In the default `memory` journal mode a connection's statements run on
+//! the shared database itself, so other connections see a transaction's
+//! writes before it commits. After `PRAGMA journal_mode = WAL` a BEGIN
+//! takes a snapshot instead: the transaction reads and writes its own copy
+//! of the database, which logs the rows its statements insert and delete,
+//! and COMMIT merges those into the shared one. Writes made by others
+//! meanwhile stay out of its sight.
+
+use std::sync::{MutexGuard, PoisonError};
+
+use crate::ast::{PragmaStmt, Statement};
+use crate::executor::{EvalError, execute_each};
+use crate::storage::{Database, DbError, Row, SharedDatabase};
+use crate::types::Value;
+
+/// How a connection keeps a transaction's writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JournalMode {
+    /// In the shared database, restored from a copy on ROLLBACK.
+    #[default]
+    Memory,
+    /// Apart from it until COMMIT, against a snapshot.
+    Wal,
+}
+
+impl JournalMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            JournalMode::Memory => "memory",
+            JournalMode::Wal => "wal",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+enum TransactionState {
+    #[default]
+    Idle,
+    /// A `wal` mode transaction. `snapshot` is the database as it was at
+    /// BEGIN with the transaction's own writes made to it, which it logs.
+    Active { snapshot: Box<Database> },
+}
+
+pub struct Connection {
+    db: SharedDatabase,
+    journal_mode: JournalMode,
+    state: TransactionState,
+}
+
+impl Connection {
+    pub fn open(db: SharedDatabase) -> Self {
+        Connection {
+            db,
+            journal_mode: JournalMode::default(),
+            state: TransactionState::Idle,
+        }
+    }
+
+    pub fn journal_mode(&self) -> JournalMode {
+        self.journal_mode
+    }
+
+    /// Whether a `wal` mode transaction is open. One open in `memory` mode
+    /// belongs to the shared database.
+    pub fn in_snapshot(&self) -> bool {
+        matches!(self.state, TransactionState::Active { .. })
+    }
+
+    /// Execute one statement as [`crate::executor::execute`] does, within
+    /// this connection's transaction if it has one open.
+    pub fn execute(&mut self, stmt: &Statement) -> Result<Vec<Row>, EvalError> {
+        let mut rows = Vec::new();
+        self.execute_each(stmt, |row| rows.push(row))?;
+        Ok(rows)
+    }
+
+    /// Execute one statement as [`execute_each`] does, handing each result
+    /// row to `emit` as it is produced.
+    pub fn execute_each(
+        &mut self,
+        stmt: &Statement,
+        emit: impl FnMut(Row),
+    ) -> Result<(), EvalError> {
+        if let Statement::Pragma(pragma) = stmt
+            && pragma.name.eq_ignore_ascii_case("journal_mode")
+        {
+            self.set_journal_mode(pragma)?.into_iter().for_each(emit);
+            return Ok(());
+        }
+        match (&mut self.state, stmt) {
+            (TransactionState::Idle, Statement::Begin) if self.journal_mode == JournalMode::Wal => {
+                let mut snapshot = Box::new(self.lock().snapshot());
+                snapshot.record_writes();
+                self.state = TransactionState::Active { snapshot };
+                Ok(())
+            }
+            (TransactionState::Idle, stmt) => execute_each(&mut self.lock(), stmt, emit),
+            (TransactionState::Active { .. }, Statement::Begin) => {
+                Err(DbError::NestedTransaction.into())
+            }
+            (TransactionState::Active { snapshot }, Statement::Commit) => {
+                let writes = snapshot.pending_writes();
+                // On failure the transaction stays open, for a ROLLBACK.
+                self.lock().merge(&writes)?;
+                self.state = TransactionState::Idle;
+                Ok(())
+            }
+            (TransactionState::Active { .. }, Statement::Rollback) => {
+                self.state = TransactionState::Idle;
+                Ok(())
+            }
+            (TransactionState::Active { .. }, stmt) if changes_schema(stmt) => {
+                Err(DbError::SnapshotSchemaChange.into())
+            }
+            (TransactionState::Active { snapshot }, stmt) => execute_each(snapshot, stmt, emit),
+        }
+    }
+
+    /// Answer `PRAGMA journal_mode`, first switching to the mode given if
+    /// any. A mode other than `wal` means `memory`, which is all an
+    /// in-memory database has besides.
+    fn set_journal_mode(&mut self, pragma: &PragmaStmt) -> Result<Vec<Row>, EvalError> {
+        if let Some(arg) = &pragma.arg {
+            let mode = if arg.eq_ignore_ascii_case("wal") {
+                JournalMode::Wal
+            } else {
+                JournalMode::Memory
+            };
+            if mode != self.journal_mode && (self.in_snapshot() || self.lock().in_transaction()) {
+                return Err(DbError::JournalMode {
+                    into: mode == JournalMode::Wal,
+                    mode: JournalMode::Wal.name(),
+                }
+                .into());
+            }
+            self.journal_mode = mode;
+        }
+        Ok(vec![vec![Value::Text(
+            self.journal_mode.name().to_string(),
+        )]])
+    }
+
+    /// The shared database, locked for as long as the guard is held.
+    pub fn lock(&self) -> MutexGuard<'_, Database> {
+        // A statement that panicked may have made some of its writes and
+        // not others. The database is used as it was left all the same:
+        // there is no copy of it to restore.
+        self.db.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Whether the statement changes the schema rather than rows.
+fn changes_schema(stmt: &Statement) -> bool {
+    matches!(
+        stmt,
+        Statement::CreateTable(_)
+            | Statement::DropTable(_)
+            | Statement::AlterTable(_)
+            | Statement::CreateIndex(_)
+            | Statement::DropIndex(_)
+            | Statement::CreateView(_)
+            | Statement::DropView(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_statement;
+
+    fn shared() -> SharedDatabase {
+        let db = Database::new().into_shared();
+        let mut setup = Connection::open(db.clone());
+        for sql in [
+            "CREATE TABLE accounts (id INTEGER PRIMARY KEY, owner TEXT, balance INTEGER)",
+            "INSERT INTO accounts VALUES (1, 'ann', 100), (2, 'bob', 50)",
+        ] {
+            setup.execute(&parse_statement(sql).unwrap()).unwrap();
+        }
+        db
+    }
+
+    fn wal(db: &SharedDatabase) -> Connection {
+        let mut conn = Connection::open(db.clone());
+        run(&mut conn, "PRAGMA journal_mode = WAL").unwrap();
+        conn
+    }
+
+    fn run(conn: &mut Connection, sql: &str) -> Result<Vec<String>, EvalError> {
+        Ok(conn
+            .execute(&parse_statement(sql).unwrap())?
+            .iter()
+            .map(|row| {
+                let values: Vec<String> = row.iter().map(Value::to_string).collect();
+                values.join("|")
+            })
+            .collect())
+    }
+
+    const BALANCES: &str = "SELECT owner, balance FROM accounts ORDER BY id";
+
+    #[test]
+    fn test_journal_mode() {
+        let db = shared();
+        let mut conn = Connection::open(db.clone());
+        assert_eq!(run(&mut conn, "PRAGMA journal_mode").unwrap(), ["memory"]);
+        assert_eq!(
+            run(&mut conn, "PRAGMA journal_mode = wal").unwrap(),
+            ["wal"]
+        );
+        assert_eq!(conn.journal_mode(), JournalMode::Wal);
+        run(&mut conn, "BEGIN").unwrap();
+        assert_eq!(
+            run(&mut conn, "PRAGMA journal_mode = MEMORY")
+                .unwrap_err()
+                .to_string(),
+            "cannot change out of wal mode from within a transaction"
+        );
+        assert_eq!(run(&mut conn, "PRAGMA journal_mode").unwrap(), ["wal"]);
+        run(&mut conn, "COMMIT").unwrap();
+        assert_eq!(
+            run(&mut conn, "PRAGMA journal_mode = off").unwrap(),
+            ["memory"]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_reads() {
+        let db = shared();
+        let mut reader = wal(&db);
+        let mut writer = Connection::open(db.clone());
+        run(&mut reader, "BEGIN").unwrap();
+        run(&mut writer, "UPDATE accounts SET balance = 0 WHERE id = 1").unwrap();
+        run(&mut writer, "INSERT INTO accounts VALUES (3, 'cy', 10)").unwrap();
+        assert_eq!(run(&mut reader, BALANCES).unwrap(), ["ann|100", "bob|50"]);
+        assert_eq!(
+            run(&mut writer, BALANCES).unwrap(),
+            ["ann|0", "bob|50", "cy|10"]
+        );
+        run(&mut reader, "COMMIT").unwrap();
+        assert_eq!(
+            run(&mut reader, BALANCES).unwrap(),
+            ["ann|0", "bob|50", "cy|10"]
+        );
+    }
+
+    #[test]
+    fn test_commit_merges_writes() {
+        let db = shared();
+        let mut conn = wal(&db);
+        let mut other = Connection::open(db.clone());
+        run(&mut conn, "BEGIN").unwrap();
+        run(
+            &mut conn,
+            "UPDATE accounts SET balance = balance - 30 WHERE id = 1",
+        )
+        .unwrap();
+        run(&mut conn, "INSERT INTO accounts VALUES (4, 'di', 30)").unwrap();
+        run(&mut conn, "INSERT INTO accounts VALUES (5, 'ed', 1)").unwrap();
+        run(&mut conn, "DELETE FROM accounts WHERE id = 5").unwrap();
+        // Its own writes are visible to the transaction, and to no one else.
+        assert_eq!(
+            run(&mut conn, BALANCES).unwrap(),
+            ["ann|70", "bob|50", "di|30"]
+        );
+        run(&mut other, "INSERT INTO accounts VALUES (3, 'cy', 10)").unwrap();
+        assert_eq!(
+            run(&mut other, BALANCES).unwrap(),
+            ["ann|100", "bob|50", "cy|10"]
+        );
+        let TransactionState::Active { snapshot } = &conn.state else {
+            panic!("the transaction is open");
+        };
+        let writes = snapshot.pending_writes();
+        let accounts = writes.table("ACCOUNTS").unwrap();
+        assert_eq!(accounts.inserted.len(), 2);
+        assert_eq!(accounts.deleted.len(), 1);
+        run(&mut conn, "COMMIT").unwrap();
+        assert!(!conn.in_snapshot());
+        assert_eq!(
+            run(&mut other, BALANCES).unwrap(),
+            ["ann|70", "bob|50", "cy|10", "di|30"]
+        );
+        // The indexes were kept up: the keys merged in are enforced.
+        assert_eq!(
+            run(&mut other, "INSERT INTO accounts VALUES (4, 'x', 0)")
+                .unwrap_err()
+                .to_string(),
+            "UNIQUE constraint failed: accounts.id"
+        );
+    }
+
+    #[test]
+    fn test_rollback_discards_writes() {
+        let db = shared();
+        let mut conn = wal(&db);
+        run(&mut conn, "BEGIN").unwrap();
+        run(&mut conn, "DELETE FROM accounts").unwrap();
+        assert!(run(&mut conn, BALANCES).unwrap().is_empty());
+        run(&mut conn, "ROLLBACK").unwrap();
+        assert_eq!(run(&mut conn, BALANCES).unwrap(), ["ann|100", "bob|50"]);
+    }
+
+    #[test]
+    fn test_conflicting_commit_changes_nothing() {
+        let db = shared();
+        let mut conn = wal(&db);
+        let mut other = Connection::open(db.clone());
+        run(&mut conn, "BEGIN").unwrap();
+        run(&mut conn, "UPDATE accounts SET balance = 1").unwrap();
+        run(&mut conn, "INSERT INTO accounts VALUES (3, 'cy', 10)").unwrap();
+        run(&mut other, "INSERT INTO accounts VALUES (3, 'zed', 0)").unwrap();
+        assert_eq!(
+            run(&mut conn, "COMMIT").unwrap_err().to_string(),
+            "UNIQUE constraint failed: accounts.id"
+        );
+        assert!(conn.in_snapshot());
+        assert_eq!(
+            run(&mut other, BALANCES).unwrap(),
+            ["ann|100", "bob|50", "zed|0"]
+        );
+        run(&mut conn, "ROLLBACK").unwrap();
+        assert_eq!(
+            run(&mut conn, BALANCES).unwrap(),
+            ["ann|100", "bob|50", "zed|0"]
+        );
+    }
+
+    #[test]
+    fn test_commit_rechecks_foreign_keys() {
+        let db = shared();
+        let mut setup = Connection::open(db.clone());
+        for sql in [
+            "CREATE TABLE cards (n INTEGER PRIMARY KEY, account REFERENCES accounts)",
+            "INSERT INTO cards VALUES (10, 2)",
+        ] {
+            run(&mut setup, sql).unwrap();
+        }
+        // A card for an account another connection deletes meanwhile.
+        let mut conn = wal(&db);
+        let mut other = Connection::open(db.clone());
+        run(&mut conn, "BEGIN").unwrap();
+        run(&mut conn, "INSERT INTO cards VALUES (11, 1)").unwrap();
+        run(&mut other, "DELETE FROM accounts WHERE id = 1").unwrap();
+        assert_eq!(
+            run(&mut conn, "COMMIT").unwrap_err().to_string(),
+            "FOREIGN KEY constraint failed"
+        );
+        run(&mut conn, "ROLLBACK").unwrap();
+        assert_eq!(run(&mut other, "SELECT n FROM cards").unwrap(), ["10"]);
+        // An account deleted while another connection gives it a card.
+        run(&mut other, "DELETE FROM cards").unwrap();
+        run(&mut conn, "BEGIN").unwrap();
+        run(&mut conn, "DELETE FROM accounts WHERE id = 2").unwrap();
+        run(&mut other, "INSERT INTO cards VALUES (12, 2)").unwrap();
+        assert_eq!(
+            run(&mut conn, "COMMIT").unwrap_err().to_string(),
+            "FOREIGN KEY constraint failed"
+        );
+        assert_eq!(run(&mut other, BALANCES).unwrap(), ["bob|50"]);
+        // With the card gone again, the deletion goes through.
+        run(&mut other, "DELETE FROM cards").unwrap();
+        run(&mut conn, "COMMIT").unwrap();
+        assert!(run(&mut other, BALANCES).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_writes_are_logged_as_made() {
+        let db = shared();
+        let mut conn = wal(&db);
+        run(&mut conn, "BEGIN").unwrap();
+        run(&mut conn, "UPDATE accounts SET balance = balance + 1").unwrap();
+        run(&mut conn, "UPDATE accounts SET balance = balance - 1").unwrap();
+        run(&mut conn, "SAVEPOINT a").unwrap();
+        run(&mut conn, "DELETE FROM accounts").unwrap();
+        run(&mut conn, "ROLLBACK TO a").unwrap();
+        // A failed statement leaves nothing in the log either.
+        assert!(
+            run(
+                &mut conn,
+                "INSERT INTO accounts VALUES (3, 'cy', 1), (1, 'x', 0)"
+            )
+            .is_err()
+        );
+        let TransactionState::Active { snapshot } = &conn.state else {
+            panic!("the transaction is open");
+        };
+        assert!(snapshot.pending_writes().is_empty());
+        run(&mut conn, "INSERT INTO accounts VALUES (3, 'cy', 1)").unwrap();
+        let TransactionState::Active { snapshot } = &conn.state else {
+            panic!("the transaction is open");
+        };
+        let writes = snapshot.pending_writes();
+        let accounts = writes.table("accounts").unwrap();
+        assert_eq!(accounts.inserted.len(), 1);
+        assert!(accounts.deleted.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_transaction_rules() {
+        let db = shared();
+        let mut conn = wal(&db);
+        run(&mut conn, "BEGIN").unwrap();
+        assert_eq!(
+            run(&mut conn, "BEGIN").unwrap_err().to_string(),
+            "cannot start a transaction within a transaction"
+        );
+        assert_eq!(
+            run(&mut conn, "CREATE TABLE t (x)")
+                .unwrap_err()
+                .to_string(),
+            "cannot change the schema within a wal transaction"
+        );
+        // Savepoints work inside the snapshot.
+        run(&mut conn, "SAVEPOINT a").unwrap();
+        run(&mut conn, "DELETE FROM accounts WHERE id = 2").unwrap();
+        run(&mut conn, "ROLLBACK TO a").unwrap();
+        run(&mut conn, "RELEASE a").unwrap();
+        run(&mut conn, "COMMIT").unwrap();
+        assert_eq!(run(&mut conn, BALANCES).unwrap(), ["ann|100", "bob|50"]);
+        assert_eq!(
+            run(&mut conn, "COMMIT").unwrap_err().to_string(),
+            "cannot commit - no transaction is active"
+        );
+    }
+}
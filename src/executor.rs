@@ -0,0 +1,6917 @@
+//! Query execution.
+
+mod aggregate;
+mod datetime;
+mod eval;
+mod functions;
+mod glob;
+mod join;
+mod like;
+mod pragma;
+mod set_operation;
+mod window;
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::ast::{
+    AggFunc, AlterTableStmt, CreateIndexStmt, CreateTableStmt, CreateViewStmt, DeleteStmt,
+    DropTableStmt, Expr, InsertSource, InsertStmt, SetOperator, Statement, TableConstraint,
+    UpdateStmt,
+};
+use crate::planner::{
+    CommonTable, PlanNode, ReturningPlan, SortKey, VIEW_DEPTH_LIMIT, bind_check, explain,
+    plan_checks, plan_delete, plan_generated, plan_returning, plan_select, plan_update,
+};
+use crate::storage::{ColumnDef, Database, DbError, ForeignKey, Row, Table};
+use crate::types::{OrdValue, TypeError, Value, collated_compare};
+
+pub use functions::{Eval, FunctionImpl, FunctionRegistry, MAX_LENGTH};
+pub use glob::eval_glob;
+pub use window::{
+    NtileFunction, OffsetDirection, OffsetRowFunction, RankingFunction, ValueFunction,
+    ValuePosition, WindowFunction,
+};
+
+pub use eval::{
+    Evaluator, ValueSet, eval_binop, eval_unary, predicate_holds, three_valued_and,
+    three_valued_not, three_valued_or, truth_value,
+};
+
+/// A failure while evaluating an expression or executing a statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    Type(TypeError),
+    Db(DbError),
+    NoSuchColumn(String),
+    AmbiguousColumn(String),
+    /// `SELECT *` without a FROM clause.
+    NoTablesSpecified,
+    /// An ORDER BY term of a SELECT DISTINCT that is not an output column.
+    DistinctOrderByNotSelected,
+    /// An ORDER BY ordinal outside `1..=n`, where `n` is the column count.
+    OrderByTermOutOfRange(usize),
+    /// A GROUP BY ordinal outside `1..=n`, where `n` is the column count.
+    GroupByTermOutOfRange(usize),
+    /// A select list alias whose expression refers to it, directly or
+    /// through other aliases.
+    CircularAlias(String),
+    /// Operands of a set operation with different column counts.
+    SetOperationColumnCount(SetOperator),
+    /// A VALUES tuple whose length differs from the target column list.
+    ColumnCountMismatch,
+    /// A subquery used as a value, or compared with a row value, whose
+    /// result has `columns` columns instead of `expected`.
+    SubqueryColumnCount {
+        columns: usize,
+        expected: usize,
+    },
+    /// A row value where none is allowed, or compared with one of a
+    /// different size.
+    RowValueMisused,
+    /// A scalar subquery that produced more than one row.
+    SubqueryMultipleRows,
+    /// A NATURAL JOIN of two sides that share no column name.
+    NoCommonColumns,
+    /// A `USING` column missing from one side of the join.
+    UsingColumnNotInBothTables(String),
+    /// A column default that reads more than constants; it names the
+    /// column.
+    NonConstantDefault(String),
+    /// An aggregate call where none is allowed, such as in WHERE or inside
+    /// another aggregate's argument.
+    MisusedAggregate(AggFunc),
+    /// A column of an aggregated SELECT that is neither grouped nor inside
+    /// an aggregate call.
+    NotGrouped(String),
+    NoSuchFunction(String),
+    /// A call of the named function with an argument count it does not
+    /// take.
+    WrongArgumentCount(String),
+    /// A window function call where none is allowed, such as in WHERE or
+    /// inside another window function's arguments.
+    MisusedWindow(String),
+    /// OVER after a call of the named function, which is not a window
+    /// function.
+    NotWindowFunction(String),
+    /// A call of the named window function, which numbers rows in window
+    /// order, over a window without ORDER BY.
+    WindowOrderRequired(String),
+    /// A RANGE frame bounded by an offset from the current row's value, in
+    /// a window without exactly one ORDER BY expression to take it from.
+    RangeFrameOrderBy,
+    /// A constant integer argument of a window function, such as LAG's
+    /// offset, that is out of range: below 1 if it must be `positive`,
+    /// else below 0. `position` counts from 1.
+    InvalidWindowArgument {
+        name: String,
+        position: usize,
+        positive: bool,
+    },
+    /// A common table expression whose column list names more or fewer
+    /// columns than its query has.
+    CteColumnCount {
+        table: String,
+        values: usize,
+        columns: usize,
+    },
+    /// A recursive common table expression joined to its step with
+    /// UNION instead of UNION ALL.
+    RecursiveUnion(String),
+    /// A recursive step that reads its table more than once.
+    MultipleRecursiveReferences(String),
+    /// A recursive step that reads its table from a subquery.
+    RecursiveReferenceInSubquery(String),
+    /// A recursive common table expression still producing rows after
+    /// [`RECURSION_LIMIT`] runs of its step.
+    RecursionLimit(String),
+    /// A view read through more than [`VIEW_DEPTH_LIMIT`] views, as one
+    /// that reads itself is.
+    ViewDepthLimit(String),
+    /// A LIKE ESCAPE operand that is not a single character.
+    InvalidEscape,
+    /// A string or blob longer than [`MAX_LENGTH`] bytes.
+    TooBig,
+    /// A PRINTF format with a conversion it does not know, such as `%y`.
+    InvalidFormat(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Type(e) => write!(f, "{e}"),
+            EvalError::Db(e) => write!(f, "{e}"),
+            EvalError::NoSuchColumn(name) => write!(f, "no such column: {name}"),
+            EvalError::AmbiguousColumn(name) => write!(f, "ambiguous column name: {name}"),
+            EvalError::NoTablesSpecified => write!(f, "no tables specified"),
+            EvalError::DistinctOrderByNotSelected => write!(
+                f,
+                "for SELECT DISTINCT, ORDER BY expressions must appear in select list"
+            ),
+            EvalError::OrderByTermOutOfRange(n) => {
+                write!(
+                    f,
+                    "ORDER BY term out of range - should be between 1 and {n}"
+                )
+            }
+            EvalError::GroupByTermOutOfRange(n) => {
+                write!(
+                    f,
+                    "GROUP BY term out of range - should be between 1 and {n}"
+                )
+            }
+            EvalError::CircularAlias(name) => write!(f, "circular reference to alias: {name}"),
+            EvalError::SetOperationColumnCount(op) => write!(
+                f,
+                "SELECTs to the left and right of {op} do not have the same number of result columns"
+            ),
+            EvalError::ColumnCountMismatch => write!(f, "column count mismatch"),
+            EvalError::SubqueryColumnCount { columns, expected } => {
+                write!(
+                    f,
+                    "sub-select returns {columns} columns - expected {expected}"
+                )
+            }
+            EvalError::RowValueMisused => write!(f, "row value misused"),
+            EvalError::SubqueryMultipleRows => write!(f, "subquery returns more than one row"),
+            EvalError::NoCommonColumns => write!(f, "NATURAL JOIN has no common columns"),
+            EvalError::UsingColumnNotInBothTables(name) => write!(
+                f,
+                "cannot join using column {name} - column not present in both tables"
+            ),
+            EvalError::NonConstantDefault(name) => {
+                write!(f, "default value of column [{name}] is not constant")
+            }
+            EvalError::MisusedAggregate(func) => {
+                write!(f, "misuse of aggregate: {}()", func.name())
+            }
+            EvalError::NotGrouped(name) => write!(
+                f,
+                "column \"{name}\" must appear in the GROUP BY clause or be used in an aggregate function"
+            ),
+            EvalError::NoSuchFunction(name) => write!(f, "no such function: {name}"),
+            EvalError::WrongArgumentCount(name) => {
+                write!(f, "wrong number of arguments to function {name}()")
+            }
+            EvalError::MisusedWindow(name) => write!(f, "misuse of window function {name}()"),
+            EvalError::NotWindowFunction(name) => {
+                write!(f, "{name}() may not be used as a window function")
+            }
+            EvalError::WindowOrderRequired(name) => {
+                write!(f, "{name}() requires an ORDER BY in its window")
+            }
+            EvalError::RangeFrameOrderBy => write!(
+                f,
+                "RANGE with offset PRECEDING/FOLLOWING requires one ORDER BY expression"
+            ),
+            EvalError::InvalidWindowArgument {
+                name,
+                position,
+                positive,
+            } => {
+                let sign = if *positive {
+                    "positive"
+                } else {
+                    "non-negative"
+                };
+                write!(
+                    f,
+                    "argument {position} of {name}() must be a {sign} integer"
+                )
+            }
+            EvalError::CteColumnCount {
+                table,
+                values,
+                columns,
+            } => write!(f, "table {table} has {values} values for {columns} columns"),
+            EvalError::RecursiveUnion(name) => write!(
+                f,
+                "recursive common table expression {name} must use UNION ALL"
+            ),
+            EvalError::MultipleRecursiveReferences(name) => {
+                write!(f, "multiple recursive references: {name}")
+            }
+            EvalError::RecursiveReferenceInSubquery(name) => {
+                write!(f, "recursive reference in a subquery: {name}")
+            }
+            EvalError::RecursionLimit(name) => write!(
+                f,
+                "recursive common table expression {name} exceeded {RECURSION_LIMIT} iterations"
+            ),
+            EvalError::ViewDepthLimit(name) => write!(
+                f,
+                "view {name} is nested more than {VIEW_DEPTH_LIMIT} views deep"
+            ),
+            EvalError::InvalidEscape => {
+                write!(f, "ESCAPE expression must be a single character")
+            }
+            EvalError::TooBig => write!(f, "string or blob too big"),
+            EvalError::InvalidFormat(conversion) => {
+                write!(f, "unknown format conversion: {conversion}")
+            }
+            EvalError::Unsupported(what) => write!(f, "{what} is not supported"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl From<TypeError> for EvalError {
+    fn from(e: TypeError) -> Self {
+        EvalError::Type(e)
+    }
+}
+
+impl From<DbError> for EvalError {
+    fn from(e: DbError) -> Self {
+        EvalError::Db(e)
+    }
+}
+
+/// Execute one statement as [`execute`] does, but hand its result rows to
+/// `emit` one at a time. A query's rows are passed on as its plan produces
+/// them, so only those a node must hold, as a sort does, are ever held at
+/// once; the rows passed on before an error remain passed on.
+pub fn execute_each(
+    db: &mut Database,
+    stmt: &Statement,
+    mut emit: impl FnMut(Row),
+) -> Result<(), EvalError> {
+    if let Statement::Select(select) = stmt {
+        let plan = plan_select(db, select)?;
+        for row in execute_plan(Context::new(db), &plan) {
+            emit(row?);
+        }
+        return Ok(());
+    }
+    execute(db, stmt)?.into_iter().for_each(emit);
+    Ok(())
+}
+
+/// Execute one statement, returning its result rows. Statements that
+/// produce no result set return an empty list.
+pub fn execute(db: &mut Database, stmt: &Statement) -> Result<Vec<Row>, EvalError> {
+    match stmt {
+        Statement::Select(select) => {
+            let plan = plan_select(db, select)?;
+            execute_plan(Context::new(db), &plan).collect()
+        }
+        Statement::CreateTable(create) => {
+            create_table(db, create)?;
+            Ok(Vec::new())
+        }
+        Statement::DropTable(drop) => {
+            drop_table(db, drop)?;
+            Ok(Vec::new())
+        }
+        Statement::AlterTable(alter) => {
+            alter_table(db, alter)?;
+            Ok(Vec::new())
+        }
+        Statement::CreateIndex(create) => {
+            create_index(db, create)?;
+            Ok(Vec::new())
+        }
+        Statement::DropIndex(drop) => {
+            if !(drop.if_exists && db.get_index(&drop.name).is_none()) {
+                db.drop_index(&drop.name)?;
+            }
+            Ok(Vec::new())
+        }
+        Statement::CreateView(create) => {
+            create_view(db, create)?;
+            Ok(Vec::new())
+        }
+        Statement::DropView(drop) => {
+            if !(drop.if_exists && db.get_view(&drop.name).is_none()) {
+                db.drop_view(&drop.name)?;
+            }
+            Ok(Vec::new())
+        }
+        Statement::Begin => {
+            db.begin()?;
+            Ok(Vec::new())
+        }
+        Statement::Commit => {
+            db.commit()?;
+            Ok(Vec::new())
+        }
+        Statement::Rollback => {
+            db.rollback()?;
+            Ok(Vec::new())
+        }
+        Statement::Savepoint(name) => {
+            db.savepoint(name);
+            Ok(Vec::new())
+        }
+        Statement::Release(name) => {
+            db.release(name)?;
+            Ok(Vec::new())
+        }
+        Statement::RollbackTo(name) => {
+            db.rollback_to(name)?;
+            Ok(Vec::new())
+        }
+        Statement::Truncate(truncate) => {
+            // Unlike DELETE there is no predicate: every row goes at once,
+            // and the numbering starts over.
+            let count = db
+                .get_table(&truncate.name)
+                .ok_or_else(|| DbError::NoSuchTable(truncate.name.clone()))?
+                .rows
+                .len();
+            let positions: Vec<usize> = (0..count).collect();
+            db.delete_rows(&truncate.name, &positions, |e| {
+                Evaluator::constant().eval(e)
+            })?;
+            db.reset_sequence(&truncate.name);
+            db.record_changes(count);
+            Ok(Vec::new())
+        }
+        Statement::Insert(insert_stmt) => {
+            let (count, rows) = insert(db, insert_stmt)?;
+            db.record_changes(count);
+            Ok(rows)
+        }
+        Statement::Update(update_stmt) => {
+            let (count, rows) = update(db, update_stmt)?;
+            db.record_changes(count);
+            Ok(rows)
+        }
+        Statement::Delete(delete_stmt) => {
+            let (count, rows) = delete(db, delete_stmt)?;
+            db.record_changes(count);
+            Ok(rows)
+        }
+        Statement::Analyze(table) => {
+            db.analyze(table.as_deref())?;
+            Ok(Vec::new())
+        }
+        Statement::Pragma(stmt) => pragma::pragma(db, stmt),
+        Statement::Explain(select) => {
+            let plan = plan_select(db, select)?;
+            Ok(explain(&plan, db, 0)
+                .lines()
+                .map(|line| vec![Value::Text(line.to_string())])
+                .collect())
+        }
+    }
+}
+
+/// A stream of result rows.
+pub type Rows<'a> = Box<dyn Iterator<Item = Result<Row, EvalError>> + 'a>;
+
+/// Run a plan, producing its rows lazily.
+/// Where a plan runs: the database and, for a correlated subquery, the rows
+/// of the enclosing queries its outer references read.
+#[derive(Clone, Copy)]
+pub struct Context<'a> {
+    pub db: &'a Database,
+    outer: Option<&'a OuterRow<'a>>,
+    /// When the statement started, which is the current time throughout.
+    now: SystemTime,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Context {
+            db,
+            outer: None,
+            now: SystemTime::now(),
+        }
+    }
+}
+
+/// How many times the step of a recursive common table expression may run
+/// before it is taken to recurse forever.
+pub const RECURSION_LIMIT: usize = 1000;
+
+/// Compute the rows of a common table expression. It reads no enclosing
+/// query's row, so they are the same wherever it is scanned.
+fn materialize(ctx: Context<'_>, cte: &CommonTable) -> Result<Vec<Row>, EvalError> {
+    let ctx = Context { outer: None, ..ctx };
+    let mut rows: Vec<Row> = execute_plan(ctx, &cte.plan).collect::<Result<_, _>>()?;
+    let Some(step) = &cte.step else {
+        return Ok(rows);
+    };
+    // The rows the LIMIT and OFFSET take all come before this many.
+    let wanted = step.limit.map(|limit| step.offset.saturating_add(limit));
+    let mut previous = rows.clone();
+    let mut runs = 0;
+    while !previous.is_empty() && wanted.is_none_or(|wanted| rows.len() < wanted) {
+        if runs == RECURSION_LIMIT {
+            return Err(EvalError::RecursionLimit(cte.name.clone()));
+        }
+        *step.working.rows.lock().unwrap() = previous;
+        previous = execute_plan(ctx, &step.plan).collect::<Result<_, _>>()?;
+        rows.extend(previous.iter().cloned());
+        runs += 1;
+    }
+    if let Some(wanted) = wanted {
+        rows.truncate(wanted);
+    }
+    rows.drain(..step.offset.min(rows.len()));
+    Ok(rows)
+}
+
+/// The row an enclosing query evaluates a subquery for, and those of the
+/// queries around that one.
+struct OuterRow<'a> {
+    row: &'a [Value],
+    parent: Option<&'a OuterRow<'a>>,
+}
+
+pub fn execute_plan<'a>(ctx: Context<'a>, plan: &'a PlanNode) -> Rows<'a> {
+    let db = ctx.db;
+    match plan {
+        PlanNode::SingleRow => Box::new(std::iter::once(Ok(Vec::new()))),
+        PlanNode::Values { rows, .. } => Box::new(rows.iter().map(move |row| {
+            let evaluator = Evaluator::new(ctx, PlanNode::SingleRow.schema(), &[]);
+            row.iter().map(|e| evaluator.eval(e)).collect()
+        })),
+        PlanNode::TableScan { table, .. } => match db.get_table(table) {
+            Some(table) => Box::new(table.rows.iter().cloned().map(Ok)),
+            None => Box::new(std::iter::once(Err(
+                DbError::NoSuchTable(table.clone()).into()
+            ))),
+        },
+        PlanNode::IndexScan {
+            table, index, key, ..
+        } => match index_scan(ctx, table, index, key) {
+            Ok(rows) => Box::new(rows.map(Ok)),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        },
+        PlanNode::CteScan { cte, .. } => match cte.cached_rows(|cte| materialize(ctx, cte)) {
+            Ok(rows) => Box::new(rows.iter().cloned().map(Ok)),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        },
+        PlanNode::WorkingTableScan { table, .. } => {
+            let rows = table.rows.lock().unwrap().clone();
+            Box::new(rows.into_iter().map(Ok))
+        }
+        PlanNode::SubqueryScan { input, .. } => execute_plan(ctx, input),
+        PlanNode::Projection { input, exprs, .. } => {
+            let schema = input.schema();
+            Box::new(execute_plan(ctx, input).map(move |row| {
+                let row = row?;
+                let evaluator = Evaluator::new(ctx, schema, &row);
+                exprs.iter().map(|e| evaluator.eval(e)).collect()
+            }))
+        }
+        PlanNode::Sort { input, keys } => match sort_rows(ctx, input, keys) {
+            Ok(rows) => Box::new(rows.into_iter().map(Ok)),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        },
+        PlanNode::Limit {
+            input,
+            limit,
+            offset,
+        } => {
+            let rows = execute_plan(ctx, input).skip(*offset);
+            match limit {
+                Some(limit) => Box::new(rows.take(*limit)),
+                None => Box::new(rows),
+            }
+        }
+        PlanNode::Exists { input, .. } => {
+            let first = execute_plan(ctx, input).next().transpose();
+            Box::new(std::iter::once(
+                first.map(|row| vec![Value::Boolean(row.is_some())]),
+            ))
+        }
+        PlanNode::Distinct { input } => distinct(execute_plan(ctx, input)),
+        PlanNode::SetOperation { op, left, right } => {
+            set_operation::set_operation(ctx, *op, left, right)
+        }
+        PlanNode::NestedLoopJoin {
+            left,
+            right,
+            kind,
+            predicate,
+            schema,
+        } => join::nested_loop(ctx, left, right, *kind, predicate.as_ref(), schema),
+        PlanNode::HashJoin(join) => join::hash_join(ctx, join),
+        PlanNode::SortMergeJoin(join) => join::sort_merge_join(ctx, join),
+        PlanNode::IndexNestedLoopJoin(join) => join::index_nested_loop(ctx, join),
+        PlanNode::Aggregate {
+            input,
+            group_by,
+            calls,
+            ..
+        } => match aggregate::aggregate(ctx, input, group_by, calls) {
+            Ok(rows) => Box::new(rows.into_iter().map(Ok)),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        },
+        PlanNode::Window { input, calls, .. } => match window::window(ctx, input, calls) {
+            Ok(rows) => Box::new(rows.into_iter().map(Ok)),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        },
+        PlanNode::Filter { input, predicate } => {
+            let schema = input.schema();
+            Box::new(execute_plan(ctx, input).filter_map(move |row| {
+                let row = match row {
+                    Ok(row) => row,
+                    Err(e) => return Some(Err(e)),
+                };
+                match Evaluator::new(ctx, schema, &row).holds(predicate) {
+                    Ok(true) => Some(Ok(row)),
+                    Ok(false) => None,
+                    Err(e) => Some(Err(e)),
+                }
+            }))
+        }
+    }
+}
+
+/// The rows of a table an index finds for `key`.
+fn index_scan<'a>(
+    ctx: Context<'a>,
+    table: &str,
+    index: &str,
+    key: &[Expr],
+) -> Result<impl Iterator<Item = Row> + 'a, EvalError> {
+    let table = ctx
+        .db
+        .get_table(table)
+        .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?;
+    let index = table
+        .index(index)
+        .ok_or_else(|| DbError::NoSuchIndex(index.to_string()))?;
+    let evaluator = Evaluator::without_row(ctx);
+    let key = key
+        .iter()
+        .map(|e| evaluator.eval(e))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(index.lookup(&key).iter().map(|&i| table.rows[i].clone()))
+}
+
+/// The rows with duplicates removed, keeping first occurrences.
+fn distinct(rows: Rows<'_>) -> Rows<'_> {
+    let mut seen = HashSet::new();
+    Box::new(rows.filter(move |row| match row {
+        Ok(row) => seen.insert(row_key(row)),
+        Err(_) => true,
+    }))
+}
+
+/// A row's values in a form that hashes and compares, so that rows equal
+/// value by value (NULLs included) have equal keys.
+fn row_key(row: &[Value]) -> Vec<OrdValue> {
+    row.iter().cloned().map(OrdValue).collect()
+}
+
+fn sort_rows(ctx: Context<'_>, input: &PlanNode, keys: &[SortKey]) -> Result<Vec<Row>, EvalError> {
+    let schema = input.schema();
+    let mut keyed = execute_plan(ctx, input)
+        .map(|row| {
+            let row = row?;
+            let evaluator = Evaluator::new(ctx, schema, &row);
+            let key = keys
+                .iter()
+                .map(|k| evaluator.eval(&k.expr))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((key, row))
+        })
+        .collect::<Result<Vec<_>, EvalError>>()?;
+    // `sort_by` is stable, so rows with equal keys keep their input order.
+    keyed.sort_by(|(a, _), (b, _)| compare_keys(keys, a, b));
+    Ok(keyed.into_iter().map(|(_, row)| row).collect())
+}
+
+fn compare_keys(keys: &[SortKey], a: &[Value], b: &[Value]) -> Ordering {
+    for ((key, a), b) in keys.iter().zip(a).zip(b) {
+        let ordering = match (a.is_null(), b.is_null()) {
+            (true, true) => Ordering::Equal,
+            (true, false) if key.nulls_first => Ordering::Less,
+            (true, false) => Ordering::Greater,
+            (false, true) if key.nulls_first => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => {
+                let ordering = collated_compare(a, b, key.collation).unwrap_or(Ordering::Equal);
+                if key.desc {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Store a view, whose query must plan, so it only reads tables and views
+/// that exist; they may be dropped later, making the view fail when read.
+fn create_view(db: &mut Database, stmt: &CreateViewStmt) -> Result<(), EvalError> {
+    if stmt.if_not_exists && db.get_view(&stmt.name).is_some() {
+        return Ok(());
+    }
+    plan_select(db, &stmt.query)?;
+    db.create_view(&stmt.name, (*stmt.query).clone(), stmt.or_replace)?;
+    Ok(())
+}
+
+fn create_table(db: &mut Database, stmt: &CreateTableStmt) -> Result<(), EvalError> {
+    if stmt.if_not_exists && db.get_table(&stmt.name).is_some() {
+        return Ok(());
+    }
+    let column_index = |name: &String| {
+        stmt.columns
+            .iter()
+            .position(|c| c.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| EvalError::NoSuchColumn(name.clone()))
+    };
+    for column in &stmt.columns {
+        check_default(column)?;
+        // A column's own constraints see no other column.
+        for check in &column.checks {
+            bind_check(db, &stmt.name, std::slice::from_ref(column), &check.expr)?;
+        }
+        check_generated(db, &stmt.name, &stmt.columns, column)?;
+    }
+    let mut keys = Vec::new();
+    let mut foreign_keys = Vec::new();
+    let mut checks = Vec::new();
+    for constraint in &stmt.constraints {
+        let columns = match constraint {
+            TableConstraint::Check(check) => {
+                bind_check(db, &stmt.name, &stmt.columns, &check.expr)?;
+                checks.push(check.clone());
+                continue;
+            }
+            TableConstraint::PrimaryKey(columns)
+            | TableConstraint::Unique(columns)
+            | TableConstraint::ForeignKey { columns, .. } => columns,
+        };
+        let columns = columns.iter().map(column_index).collect::<Result<_, _>>()?;
+        match constraint {
+            TableConstraint::ForeignKey { references, .. } => foreign_keys.push(ForeignKey {
+                columns,
+                references: references.clone(),
+            }),
+            _ => keys.push((constraint, columns)),
+        }
+    }
+    // Tables may already refer to the new one, so a cycle is only found
+    // now, and must be before the table is created.
+    db.check_no_cycle(&stmt.name, &foreign_keys)?;
+    db.create_table(&stmt.name, stmt.columns.clone())?;
+    for (constraint, columns) in keys {
+        match constraint {
+            TableConstraint::PrimaryKey(_) => db.add_primary_key(&stmt.name, columns)?,
+            _ => db.add_unique(&stmt.name, columns)?,
+        }
+    }
+    for foreign_key in foreign_keys {
+        db.add_foreign_key(&stmt.name, foreign_key)?;
+    }
+    for check in checks {
+        db.add_check(&stmt.name, check)?;
+    }
+    Ok(())
+}
+
+/// Check that a column's default, if it has one, is made of constants, as
+/// it is evaluated without a row.
+fn check_default(column: &ColumnDef) -> Result<(), EvalError> {
+    let Some(default) = &column.default else {
+        return Ok(());
+    };
+    let mut constant = true;
+    default.transform(&mut |e| match e {
+        Expr::Column(..)
+        | Expr::Aggregate { .. }
+        | Expr::InSubquery { .. }
+        | Expr::QuantifiedComparison { .. }
+        | Expr::Exists(_)
+        | Expr::ScalarSubquery(_) => {
+            constant = false;
+            Some(e.clone())
+        }
+        _ => None,
+    });
+    if constant {
+        Ok(())
+    } else {
+        Err(EvalError::NonConstantDefault(column.name.clone()))
+    }
+}
+
+/// Check that a generated column, if `column` is one, is computed from
+/// the other columns of its row alone, `columns` being those of its table.
+fn check_generated(
+    db: &Database,
+    table: &str,
+    columns: &[ColumnDef],
+    column: &ColumnDef,
+) -> Result<(), EvalError> {
+    let Some(generated) = &column.generated else {
+        return Ok(());
+    };
+    if column.default.is_some() {
+        return Err(DbError::GeneratedDefault.into());
+    }
+    let mut error = None;
+    generated.expr.transform(&mut |e| match e {
+        Expr::InSubquery { .. }
+        | Expr::QuantifiedComparison { .. }
+        | Expr::Exists(_)
+        | Expr::ScalarSubquery(_) => {
+            error.get_or_insert(DbError::GeneratedSubquery);
+            Some(e.clone())
+        }
+        Expr::Column(_, name)
+            if columns
+                .iter()
+                .any(|c| c.generated.is_some() && c.name.eq_ignore_ascii_case(name)) =>
+        {
+            error.get_or_insert(DbError::GeneratedLoop(column.name.clone()));
+            Some(e.clone())
+        }
+        _ => None,
+    });
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+    bind_check(db, table, columns, &generated.expr)?;
+    Ok(())
+}
+
+fn drop_table(db: &mut Database, stmt: &DropTableStmt) -> Result<(), EvalError> {
+    if stmt.if_exists && db.get_table(&stmt.name).is_none() {
+        return Ok(());
+    }
+    db.drop_table(&stmt.name)?;
+    Ok(())
+}
+
+fn create_index(db: &mut Database, stmt: &CreateIndexStmt) -> Result<(), EvalError> {
+    if stmt.if_not_exists && db.get_index(&stmt.name).is_some() {
+        return Ok(());
+    }
+    let table = db
+        .get_table(&stmt.table)
+        .ok_or_else(|| DbError::NoSuchTable(stmt.table.clone()))?;
+    let columns = stmt
+        .columns
+        .iter()
+        .map(|name| {
+            table
+                .column_index(name)
+                .ok_or_else(|| EvalError::NoSuchColumn(name.clone()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    db.create_index(&stmt.table, &stmt.name, columns, stmt.unique)?;
+    Ok(())
+}
+
+fn alter_table(db: &mut Database, stmt: &AlterTableStmt) -> Result<(), EvalError> {
+    match stmt {
+        AlterTableStmt::AddColumn { table, column_def } => {
+            check_default(column_def)?;
+            for check in &column_def.checks {
+                bind_check(db, table, std::slice::from_ref(column_def), &check.expr)?;
+            }
+            let stored = db
+                .get_table(table)
+                .ok_or_else(|| DbError::NoSuchTable(table.clone()))?;
+            let mut columns = stored.schema.clone();
+            columns.push(column_def.clone());
+            check_generated(db, table, &columns, column_def)?;
+            let default = match &column_def.default {
+                Some(default) => Evaluator::without_row(Context::new(db)).eval(default)?,
+                None => Value::Null,
+            };
+            db.add_column(table, column_def.clone(), default)?;
+            // The existing rows must satisfy the new column's constraints
+            // with the value it gave them.
+            if !column_def.checks.is_empty() {
+                let stored = db.get_table(table).expect("the column was added");
+                let (index, rows) = (stored.schema.len() - 1, stored.rows.clone());
+                if let Err(e) = check_rows(db, table, rows) {
+                    db.drop_column(table, index)?;
+                    return Err(e);
+                }
+            }
+        }
+        AlterTableStmt::DropColumn {
+            table,
+            column_name,
+            if_exists,
+        } => {
+            let index = db
+                .get_table(table)
+                .ok_or_else(|| DbError::NoSuchTable(table.clone()))?
+                .column_index(column_name);
+            match index {
+                Some(index) => db.drop_column(table, index)?,
+                None if *if_exists => {}
+                None => return Err(EvalError::NoSuchColumn(column_name.clone())),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check rows about to be stored in `table` against its CHECK
+/// constraints, returning them coerced to the column types as storing them
+/// would coerce them, which is what the constraints see.
+/// Compute the generated columns of rows about to be stored in `table`:
+/// the value of each stored one, and NULL in place of each virtual one,
+/// whose value need only satisfy NOT NULL.
+fn generate_columns(db: &Database, table: &str, rows: Vec<Row>) -> Result<Vec<Row>, EvalError> {
+    let plan = plan_generated(db, table)?;
+    if plan.columns.is_empty() {
+        return Ok(rows);
+    }
+    let stored = db.get_table(table).expect("the columns were planned");
+    let ctx = Context::new(db);
+    rows.into_iter()
+        .map(|row| {
+            let mut row = stored.coerce_row(row)?;
+            let values = {
+                let evaluator = Evaluator::new(ctx, &plan.schema, &row);
+                plan.columns
+                    .iter()
+                    .map(|(i, expr, _)| Ok((*i, evaluator.eval(expr)?)))
+                    .collect::<Result<Vec<_>, EvalError>>()?
+            };
+            for ((i, value), (_, _, is_stored)) in values.into_iter().zip(&plan.columns) {
+                let column = &stored.schema[i];
+                if *is_stored {
+                    row[i] = value;
+                } else if value.is_null() && !column.nullable {
+                    return Err(DbError::NotNull {
+                        table: stored.name.clone(),
+                        column: column.name.clone(),
+                    }
+                    .into());
+                }
+            }
+            Ok(row)
+        })
+        .collect()
+}
+
+fn check_rows(db: &Database, table: &str, rows: Vec<Row>) -> Result<Vec<Row>, EvalError> {
+    let plan = plan_checks(db, table)?;
+    if plan.checks.is_empty() {
+        return Ok(rows);
+    }
+    let stored = db.get_table(table).expect("the checks were planned");
+    let ctx = Context::new(db);
+    rows.into_iter()
+        .map(|row| {
+            let row = stored.coerce_row(row)?;
+            let evaluator = Evaluator::new(ctx, &plan.schema, &row);
+            for (name, expr) in &plan.checks {
+                // Unlike a WHERE clause, NULL lets the row through.
+                if truth_value(&evaluator.eval(expr)?) == Some(false) {
+                    return Err(DbError::Check(name.clone()).into());
+                }
+            }
+            Ok(row)
+        })
+        .collect()
+}
+
+/// Apply an INSERT, returning how many rows it inserted and what its
+/// RETURNING clause gives for each of them as stored. Rows left out by
+/// `OR IGNORE` count for nothing. The key of the last row inserted is
+/// remembered for `LAST_INSERT_ROWID()`.
+fn insert(db: &mut Database, stmt: &InsertStmt) -> Result<(usize, Vec<Row>), EvalError> {
+    let returning = plan_returning(db, &stmt.table, &stmt.returning)?;
+    let evaluator = Evaluator::without_row(Context::new(db));
+    let table = db
+        .get_table(&stmt.table)
+        .ok_or_else(|| DbError::NoSuchTable(stmt.table.clone()))?;
+    // Position in the table of each value in a tuple. Generated columns
+    // are computed, so they take none.
+    let targets = match &stmt.columns {
+        Some(columns) => columns
+            .iter()
+            .map(|name| {
+                let i = table
+                    .column_index(name)
+                    .ok_or_else(|| EvalError::NoSuchColumn(name.clone()))?;
+                match &table.schema[i].generated {
+                    Some(_) => Err(DbError::InsertGenerated(table.schema[i].name.clone()).into()),
+                    None => Ok(i),
+                }
+            })
+            .collect::<Result<Vec<_>, EvalError>>()?,
+        None => (0..table.schema.len())
+            .filter(|&i| table.schema[i].generated.is_none())
+            .collect(),
+    };
+    // The values to insert, one list per row, in the order of `targets`.
+    let sources: Vec<Vec<Value>> = match &stmt.source {
+        InsertSource::Values(tuples) => tuples
+            .iter()
+            .map(|tuple| {
+                if tuple.len() != targets.len() {
+                    return Err(EvalError::ColumnCountMismatch);
+                }
+                tuple.iter().map(|expr| evaluator.eval(expr)).collect()
+            })
+            .collect::<Result<_, _>>()?,
+        // The query runs to completion first, so it reads none of the rows
+        // it inserts, even those of its own table.
+        InsertSource::Query(query) => {
+            let plan = plan_select(db, query)?;
+            if plan.schema().columns.len() != targets.len() {
+                return Err(EvalError::ColumnCountMismatch);
+            }
+            execute_plan(Context::new(db), &plan).collect::<Result<_, _>>()?
+        }
+    };
+    let mut rows = Vec::with_capacity(sources.len());
+    for values in sources {
+        let mut row: Row = vec![Value::Null; table.schema.len()];
+        for (i, column) in table.schema.iter().enumerate() {
+            if targets.contains(&i) {
+                continue;
+            }
+            match &column.default {
+                Some(default) => row[i] = evaluator.eval(default)?,
+                // Left out, the column could only be NULL, unless it is
+                // numbered or computed when stored.
+                None if !column.nullable && !column.autoincrement && column.generated.is_none() => {
+                    return Err(DbError::NotNull {
+                        table: table.name.clone(),
+                        column: column.name.clone(),
+                    }
+                    .into());
+                }
+                None => {}
+            }
+        }
+        for (&i, value) in targets.iter().zip(values) {
+            row[i] = value;
+        }
+        rows.push(row);
+    }
+    let rows = generate_columns(db, &stmt.table, rows)?;
+    let rows = check_rows(db, &stmt.table, rows)?;
+    let Some(conflict) = stmt.conflict else {
+        let start = table.rows.len();
+        db.insert_rows(&stmt.table, rows)?;
+        let table = db.get_table(&stmt.table).expect("rows were inserted");
+        let inserted = &table.rows[start..];
+        let (count, id) = (inserted.len(), last_insert_id(table, inserted));
+        let rows = returned(Context::new(db), &returning, inserted)?;
+        db.record_insert(id);
+        return Ok((count, rows));
+    };
+    let inserted = db.insert_rows_or(&stmt.table, rows, conflict, |e| {
+        Evaluator::constant().eval(e)
+    })?;
+    let table = db.get_table(&stmt.table).expect("rows were inserted");
+    let id = last_insert_id(table, &inserted);
+    let rows = returned(Context::new(db), &returning, &inserted)?;
+    db.record_insert(id);
+    Ok((inserted.len(), rows))
+}
+
+/// The INTEGER PRIMARY KEY of the last of the rows inserted into `table`,
+/// 0 if it has none, and `None` if no row was inserted.
+fn last_insert_id(table: &Table, inserted: &[Row]) -> Option<i64> {
+    let row = inserted.last()?;
+    let id = match table.integer_key().map(|i| &row[i]) {
+        Some(Value::Integer(id)) => *id,
+        _ => 0,
+    };
+    Some(id)
+}
+
+/// The values of a RETURNING clause for each of `rows`.
+fn returned<'a>(
+    ctx: Context<'_>,
+    returning: &ReturningPlan,
+    rows: impl IntoIterator<Item = &'a Row>,
+) -> Result<Vec<Row>, EvalError> {
+    if returning.exprs.is_empty() {
+        return Ok(Vec::new());
+    }
+    rows.into_iter()
+        .map(|row| {
+            let evaluator = Evaluator::new(ctx, &returning.schema, row);
+            returning.exprs.iter().map(|e| evaluator.eval(e)).collect()
+        })
+        .collect()
+}
+
+/// Apply an UPDATE, returning how many rows it changed and what its
+/// RETURNING clause gives for each of them as updated. Every assignment
+/// is evaluated against the row as it was before the statement, and the
+/// table is only written once all of them have been.
+fn update(db: &mut Database, stmt: &UpdateStmt) -> Result<(usize, Vec<Row>), EvalError> {
+    let plan = plan_update(db, stmt)?;
+    let returning = plan_returning(db, &stmt.table, &stmt.returning)?;
+    let ctx = Context::new(db);
+    let table = db
+        .get_table(&plan.table)
+        .ok_or_else(|| DbError::NoSuchTable(plan.table.clone()))?;
+    let mut updates = Vec::new();
+    for (i, row) in table.rows.iter().enumerate() {
+        let evaluator = Evaluator::new(ctx, &plan.schema, row);
+        if let Some(predicate) = &plan.predicate
+            && !evaluator.holds(predicate)?
+        {
+            continue;
+        }
+        let mut updated = row.clone();
+        for (column, expr) in &plan.assignments {
+            updated[*column] = evaluator.eval(expr)?;
+        }
+        updates.push((i, updated));
+    }
+    let (positions, rows): (Vec<usize>, Vec<Row>) = updates.into_iter().unzip();
+    let rows = generate_columns(db, &plan.table, rows)?;
+    let rows = check_rows(db, &plan.table, rows)?;
+    db.update_rows(&plan.table, positions.iter().copied().zip(rows).collect())?;
+    let table = db.get_table(&plan.table).expect("rows were updated");
+    let rows = positions.iter().map(|&i| &table.rows[i]);
+    let rows = returned(Context::new(db), &returning, rows)?;
+    Ok((positions.len(), rows))
+}
+
+/// Apply a DELETE, returning how many rows it removed and what its
+/// RETURNING clause gives for each of them. The rows are only removed
+/// once the predicate has been evaluated for every row, along with the
+/// rows of other tables that foreign keys delete with them.
+fn delete(db: &mut Database, stmt: &DeleteStmt) -> Result<(usize, Vec<Row>), EvalError> {
+    let plan = plan_delete(db, stmt)?;
+    let returning = plan_returning(db, &stmt.table, &stmt.returning)?;
+    let ctx = Context::new(db);
+    let table = db
+        .get_table(&plan.table)
+        .ok_or_else(|| DbError::NoSuchTable(plan.table.clone()))?;
+    let mut positions = Vec::new();
+    for (i, row) in table.rows.iter().enumerate() {
+        let deleted = match &plan.predicate {
+            Some(predicate) => Evaluator::new(ctx, &plan.schema, row).holds(predicate)?,
+            None => true,
+        };
+        if deleted {
+            positions.push(i);
+        }
+    }
+    let rows = returned(ctx, &returning, positions.iter().map(|&i| &table.rows[i]))?;
+    db.delete_rows(&plan.table, &positions, |e| Evaluator::constant().eval(e))?;
+    Ok((positions.len(), rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_statement;
+    use crate::types::DataType;
+
+    /// Parse and execute each statement in turn, stopping at the first error.
+    pub(crate) fn run(db: &mut Database, sql: &[&str]) -> Result<Vec<Row>, EvalError> {
+        let mut rows = Vec::new();
+        for sql in sql {
+            let stmt = parse_statement(sql).unwrap_or_else(|e| panic!("{sql}: {e}"));
+            rows = execute(db, &stmt)?;
+        }
+        Ok(rows)
+    }
+
+    #[test]
+    fn test_create_table_registers_schema() {
+        let mut db = Database::new();
+        let rows = run(
+            &mut db,
+            &["CREATE TABLE foo (id INTEGER NOT NULL, name TEXT DEFAULT 'anon', score REAL)"],
+        )
+        .unwrap();
+        assert!(rows.is_empty());
+        let table = db.get_table("foo").unwrap();
+        let columns: Vec<_> = table
+            .schema
+            .iter()
+            .map(|c| (c.name.as_str(), c.data_type, c.nullable))
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                ("id", Some(DataType::Int), false),
+                ("name", Some(DataType::Text), true),
+                ("score", Some(DataType::Real), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_create_duplicate_table_fails() {
+        let mut db = Database::new();
+        let err = run(
+            &mut db,
+            &["CREATE TABLE t (a INT)", "CREATE TABLE T (b INT)"],
+        )
+        .unwrap_err();
+        assert_eq!(err, EvalError::Db(DbError::TableExists("T".into())));
+        assert_eq!(err.to_string(), "table T already exists");
+    }
+
+    #[test]
+    fn test_create_table_if_not_exists_is_idempotent() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (a INT)",
+                "CREATE TABLE IF NOT EXISTS t (b TEXT, c TEXT)",
+                "CREATE TABLE IF NOT EXISTS u (x)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(db.get_table("t").unwrap().schema.len(), 1);
+        assert!(db.get_table("u").is_some());
+    }
+
+    #[test]
+    fn test_drop_table() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (a INTEGER)",
+                "INSERT INTO t VALUES (1)",
+                "DROP TABLE T",
+            ],
+        )
+        .unwrap();
+        assert!(db.get_table("t").is_none());
+        assert_eq!(
+            run(&mut db, &["SELECT * FROM t"]).unwrap_err().to_string(),
+            "no such table: t"
+        );
+        assert_eq!(
+            run(&mut db, &["DROP TABLE t"]).unwrap_err().to_string(),
+            "no such table: t"
+        );
+        run(&mut db, &["DROP TABLE IF EXISTS t"]).unwrap();
+        // The name is free again, and nothing of the old table is left.
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (b TEXT, c TEXT)",
+                "INSERT INTO t VALUES ('x', 'y')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(query(&mut db, &["SELECT * FROM t"]).unwrap(), ["x|y"]);
+    }
+
+    #[test]
+    fn test_views() {
+        let mut db = employees();
+        run(
+            &mut db,
+            &[
+                "CREATE VIEW paid AS SELECT name, dept, salary * 2 FROM employees WHERE salary > 70",
+                "CREATE VIEW IF NOT EXISTS eng AS SELECT name FROM Paid WHERE dept = 'eng'",
+                "CREATE VIEW IF NOT EXISTS eng AS SELECT 1",
+            ],
+        )
+        .unwrap();
+        // Columns computed by an expression are named after it.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT p.name, \"salary * 2\" FROM paid AS p WHERE p.dept <> 'eng'"]
+            )
+            .unwrap(),
+            ["Cy|160"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT eng.name, paid.dept FROM eng JOIN paid USING (name) ORDER BY 1"]
+            )
+            .unwrap(),
+            ["Ann|eng", "Bob|eng"]
+        );
+        // A view sees the table as it is when read, and none of the common
+        // table expressions of the query reading it.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "UPDATE employees SET dept = 'eng' WHERE name = 'Cy'",
+                    "WITH employees AS (SELECT 'x' AS name) SELECT * FROM eng"
+                ]
+            )
+            .unwrap(),
+            ["Ann", "Bob", "Cy"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "CREATE OR REPLACE VIEW eng AS SELECT COUNT(*) FROM paid",
+                    "SELECT * FROM eng"
+                ]
+            )
+            .unwrap(),
+            ["3"]
+        );
+        for (sql, error) in [
+            ("CREATE VIEW eng AS SELECT 1", "view eng already exists"),
+            (
+                "CREATE VIEW employees AS SELECT 1",
+                "table employees already exists",
+            ),
+            ("CREATE TABLE ENG (a)", "view ENG already exists"),
+            ("CREATE VIEW v AS SELECT * FROM nope", "no such table: nope"),
+            ("DROP VIEW employees", "no such view: employees"),
+        ] {
+            assert_eq!(run(&mut db, &[sql]).unwrap_err().to_string(), error);
+        }
+        run(&mut db, &["DROP VIEW eng", "DROP VIEW IF EXISTS eng"]).unwrap();
+        assert_eq!(
+            run(&mut db, &["SELECT * FROM eng"])
+                .unwrap_err()
+                .to_string(),
+            "no such table: eng"
+        );
+        // Dropping a table a view reads breaks the view only when read.
+        run(&mut db, &["DROP TABLE employees"]).unwrap();
+        assert_eq!(
+            run(&mut db, &["SELECT * FROM paid"])
+                .unwrap_err()
+                .to_string(),
+            "no such table: employees"
+        );
+    }
+
+    #[test]
+    fn test_view_depth_limit() {
+        let mut db = Database::new();
+        run(&mut db, &["CREATE VIEW v0 AS SELECT 1 AS x"]).unwrap();
+        for i in 1..=VIEW_DEPTH_LIMIT {
+            let sql = format!("CREATE VIEW v{i} AS SELECT x + 1 AS x FROM v{}", i - 1);
+            run(&mut db, &[&sql]).unwrap();
+        }
+        let sql = format!("SELECT x FROM v{}", VIEW_DEPTH_LIMIT - 1);
+        assert_eq!(query(&mut db, &[&sql]).unwrap(), ["50"]);
+        let sql = format!("SELECT x FROM v{VIEW_DEPTH_LIMIT}");
+        assert_eq!(
+            run(&mut db, &[&sql]).unwrap_err().to_string(),
+            "view v0 is nested more than 50 views deep"
+        );
+        // A view replaced by one reading itself can no longer be read.
+        assert_eq!(
+            run(
+                &mut db,
+                &[
+                    "CREATE OR REPLACE VIEW v0 AS SELECT * FROM v0",
+                    "SELECT * FROM v0"
+                ]
+            )
+            .unwrap_err()
+            .to_string(),
+            "view v0 is nested more than 50 views deep"
+        );
+    }
+
+    #[test]
+    fn test_alter_table_add_column() {
+        let mut db = employees();
+        run(
+            &mut db,
+            &[
+                "ALTER TABLE employees ADD COLUMN hired TEXT DEFAULT '2024-01-01'",
+                "ALTER TABLE employees ADD level INTEGER NOT NULL DEFAULT '1'",
+                "ALTER TABLE employees ADD note",
+                "INSERT INTO employees (name) VALUES ('Fay')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, hired, level, note FROM employees WHERE name IN ('Ann', 'Fay')"]
+            )
+            .unwrap(),
+            ["Ann|2024-01-01|1|NULL", "Fay|2024-01-01|1|NULL"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM employees WHERE name = 'Bob'"]).unwrap(),
+            ["Bob|eng|80|1.5|2024-01-01|1|NULL"]
+        );
+    }
+
+    #[test]
+    fn test_alter_table_add_column_errors() {
+        let mut db = employees();
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(
+                &mut db,
+                "ALTER TABLE employees ADD COLUMN level INTEGER NOT NULL"
+            ),
+            "Cannot add a NOT NULL column with default value NULL"
+        );
+        assert_eq!(
+            error(&mut db, "ALTER TABLE staff ADD COLUMN level INTEGER"),
+            "no such table: staff"
+        );
+        assert_eq!(
+            error(&mut db, "ALTER TABLE employees ADD COLUMN Salary REAL"),
+            "duplicate column name: Salary"
+        );
+        assert_eq!(
+            error(
+                &mut db,
+                "ALTER TABLE employees ADD COLUMN level DEFAULT salary"
+            ),
+            "default value of column [level] is not constant"
+        );
+        assert_eq!(db.get_table("employees").unwrap().schema.len(), 4);
+    }
+
+    #[test]
+    fn test_alter_table_drop_column() {
+        let mut db = employees();
+        run(
+            &mut db,
+            &[
+                "ALTER TABLE employees DROP COLUMN bonus",
+                "ALTER TABLE employees DROP dept",
+                "ALTER TABLE employees DROP COLUMN IF EXISTS bonus",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM employees WHERE salary >= 80"]).unwrap(),
+            ["Ann|100", "Bob|80", "Cy|80"]
+        );
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut db, "SELECT name FROM employees WHERE bonus > 1"),
+            "no such column: bonus"
+        );
+        assert_eq!(
+            error(&mut db, "ALTER TABLE employees DROP COLUMN bonus"),
+            "no such column: bonus"
+        );
+        assert_eq!(
+            error(&mut db, "ALTER TABLE staff DROP COLUMN IF EXISTS bonus"),
+            "no such table: staff"
+        );
+        run(&mut db, &["ALTER TABLE employees DROP COLUMN salary"]).unwrap();
+        assert_eq!(
+            error(&mut db, "ALTER TABLE employees DROP COLUMN name"),
+            r#"cannot drop column "name": no other columns exist"#
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT COUNT(name) FROM employees"]).unwrap(),
+            ["5"]
+        );
+    }
+
+    #[test]
+    fn test_alter_table_drop_key_column() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INTEGER PRIMARY KEY, v TEXT)",
+                "INSERT INTO t VALUES (1, 'a'), (2, 'a')",
+                "ALTER TABLE t DROP COLUMN id",
+                // With the key gone, equal rows are allowed.
+                "INSERT INTO t VALUES ('a')",
+            ],
+        )
+        .unwrap();
+        assert!(db.get_table("t").unwrap().primary_key.is_none());
+        assert_eq!(query(&mut db, &["SELECT COUNT(*) FROM t"]).unwrap(), ["3"]);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut db = employees();
+        run(&mut db, &["TRUNCATE TABLE employees"]).unwrap();
+        assert!(
+            query(&mut db, &["SELECT * FROM employees"])
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(db.get_table("employees").unwrap().schema.len(), 4);
+        run(
+            &mut db,
+            &["INSERT INTO employees VALUES ('Fay', 'eng', '90', NULL)"],
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{:?}", db.get_table("employees").unwrap().rows),
+            r#"[[Text("Fay"), Text("eng"), Integer(90), Null]]"#
+        );
+        assert_eq!(
+            run(&mut db, &["TRUNCATE staff"]).unwrap_err().to_string(),
+            "no such table: staff"
+        );
+    }
+
+    #[test]
+    fn test_autoincrement() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE events (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT)",
+                "INSERT INTO events (name) VALUES ('click'), ('scroll')",
+                "INSERT INTO events VALUES (NULL, 'hover')",
+            ],
+        )
+        .unwrap();
+        let events = |db: &mut Database| query(db, &["SELECT * FROM events ORDER BY id"]).unwrap();
+        assert_eq!(events(&mut db), ["1|click", "2|scroll", "3|hover"]);
+        // The largest id is not given out again once deleted.
+        run(
+            &mut db,
+            &[
+                "DELETE FROM events WHERE id >= 2",
+                "INSERT INTO events (name) VALUES ('tap')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(events(&mut db), ["1|click", "4|tap"]);
+        // An explicit id beyond the sequence advances it; one below does not.
+        run(
+            &mut db,
+            &[
+                "INSERT INTO events VALUES (10, 'drag')",
+                "INSERT INTO events VALUES (7, 'drop')",
+                "INSERT INTO events (name) VALUES ('zoom')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            events(&mut db),
+            ["1|click", "4|tap", "7|drop", "10|drag", "11|zoom"]
+        );
+        assert_eq!(db.sequence("EVENTS"), Some(11));
+        // So does an update, and a failed insert leaves it alone.
+        run(&mut db, &["UPDATE events SET id = 20 WHERE id = 1"]).unwrap();
+        assert!(
+            run(
+                &mut db,
+                &["INSERT INTO events VALUES (NULL, 'a'), (4, 'duplicate')"]
+            )
+            .is_err()
+        );
+        run(&mut db, &["INSERT INTO events (name) VALUES ('pinch')"]).unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT id FROM events WHERE name = 'pinch'"]).unwrap(),
+            ["21"]
+        );
+        run(
+            &mut db,
+            &[
+                "TRUNCATE events",
+                "INSERT INTO events (name) VALUES ('reset')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(events(&mut db), ["1|reset"]);
+        // Rolling back restores the sequence with the rows.
+        run(
+            &mut db,
+            &[
+                "BEGIN",
+                "INSERT INTO events VALUES (100, 'gone')",
+                "ROLLBACK",
+                "INSERT INTO events (name) VALUES ('next')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(events(&mut db), ["1|reset", "2|next"]);
+    }
+
+    #[test]
+    fn test_serial_columns() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE tickets (code TEXT PRIMARY KEY, number SERIAL)",
+                "INSERT INTO tickets (code) VALUES ('a'), ('b')",
+                "INSERT OR REPLACE INTO tickets (code) VALUES ('a') RETURNING number",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM tickets ORDER BY number"]).unwrap(),
+            ["b|2", "a|3"]
+        );
+        for (sql, error) in [
+            (
+                "CREATE TABLE t (id TEXT PRIMARY KEY AUTOINCREMENT)",
+                "AUTOINCREMENT is only allowed on an INTEGER PRIMARY KEY",
+            ),
+            (
+                "CREATE TABLE t (a SERIAL, b SERIAL)",
+                "table \"t\" has more than one AUTOINCREMENT column",
+            ),
+            (
+                "ALTER TABLE tickets ADD COLUMN n SERIAL",
+                "Cannot add a AUTOINCREMENT column",
+            ),
+        ] {
+            assert_eq!(run(&mut db, &[sql]).unwrap_err().to_string(), error);
+        }
+    }
+
+    #[test]
+    fn test_generated_columns() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE products (name TEXT, price REAL, tax REAL, \
+                 total REAL GENERATED ALWAYS AS (price + tax) STORED, \
+                 label TEXT AS (upper(name)) VIRTUAL)",
+                "INSERT INTO products (name, price, tax) VALUES ('pen', 1.5, 0.5)",
+                "INSERT INTO products VALUES ('ink', 10, 2)",
+            ],
+        )
+        .unwrap();
+        // Only the stored column has its value in the rows.
+        assert_eq!(
+            format!("{:?}", db.get_table("products").unwrap().rows[0]),
+            r#"[Text("pen"), Real(1.5), Real(0.5), Real(2.0), Null]"#
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM products"]).unwrap(),
+            ["pen|1.5|0.5|2.0|PEN", "ink|10.0|2.0|12.0|INK"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, total FROM products WHERE label = 'INK' AND total > 5"]
+            )
+            .unwrap(),
+            ["ink|12.0"]
+        );
+        run(
+            &mut db,
+            &["UPDATE products SET tax = 1 WHERE label = 'PEN'"],
+        )
+        .unwrap();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT p.label, q.total FROM products p JOIN products q \
+                     ON p.label = upper(q.name) ORDER BY p.total"]
+            )
+            .unwrap(),
+            ["PEN|2.5", "INK|12.0"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["DELETE FROM products WHERE label = 'INK' RETURNING label, total"]
+            )
+            .unwrap(),
+            ["INK|12.0"]
+        );
+        for (sql, error) in [
+            (
+                "INSERT INTO products (name, total) VALUES ('cap', 1)",
+                "cannot INSERT into generated column \"total\"",
+            ),
+            (
+                "INSERT INTO products VALUES ('cap', 1, 1, 2, 'CAP')",
+                "column count mismatch",
+            ),
+            (
+                "UPDATE products SET label = 'x'",
+                "cannot UPDATE generated column \"label\"",
+            ),
+            (
+                "CREATE INDEX by_label ON products (label)",
+                "cannot index virtual generated column \"label\"",
+            ),
+            (
+                "ALTER TABLE products DROP COLUMN price",
+                "cannot drop column \"price\": used in a generated column",
+            ),
+            (
+                "CREATE TABLE t (a INT, b INT AS (a) PRIMARY KEY)",
+                "generated columns cannot be part of the PRIMARY KEY",
+            ),
+            (
+                "CREATE TABLE t (a INT, b INT DEFAULT 1 AS (a))",
+                "cannot use DEFAULT on a generated column",
+            ),
+            (
+                "CREATE TABLE t (a INT AS (b), b INT AS (1))",
+                "generated column loop on \"a\"",
+            ),
+            (
+                "CREATE TABLE t (a INT, b INT AS ((SELECT 1)))",
+                "subqueries prohibited in generated columns",
+            ),
+        ] {
+            assert_eq!(
+                run(&mut db, &[sql]).unwrap_err().to_string(),
+                error,
+                "{sql}"
+            );
+        }
+        // The index on the stored column holds its values.
+        run(&mut db, &["CREATE INDEX by_total ON products (total)"]).unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM products WHERE total = 2.5"]).unwrap(),
+            ["pen"]
+        );
+    }
+
+    #[test]
+    fn test_virtual_column_constraints() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &["CREATE TABLE t (a INT, b INT AS (a * 2) NOT NULL, CHECK (b < 10))"],
+        )
+        .unwrap();
+        run(&mut db, &["INSERT INTO t (a) VALUES (4)"]).unwrap();
+        assert_eq!(
+            run(&mut db, &["INSERT INTO t (a) VALUES (5)"])
+                .unwrap_err()
+                .to_string(),
+            "CHECK constraint failed: b < 10"
+        );
+        assert_eq!(
+            run(&mut db, &["INSERT INTO t (a) VALUES (NULL)"])
+                .unwrap_err()
+                .to_string(),
+            "NOT NULL constraint failed: t.b"
+        );
+        run(&mut db, &["ALTER TABLE t ADD COLUMN c AS (a + 1)"]).unwrap();
+        assert_eq!(query(&mut db, &["SELECT * FROM t"]).unwrap(), ["4|8|5"]);
+        assert_eq!(
+            run(&mut db, &["ALTER TABLE t ADD COLUMN d AS (a + 1) STORED"])
+                .unwrap_err()
+                .to_string(),
+            "Cannot add a STORED column"
+        );
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut db = employees();
+        let changes = |db: &mut Database| {
+            query(db, &["SELECT changes(), row_count(), total_changes()"]).unwrap()
+        };
+        assert_eq!(changes(&mut db), ["5|5|5"]);
+        run(
+            &mut db,
+            &[
+                "INSERT INTO employees VALUES ('Fay', 'eng', 90, NULL), ('Gus', 'ops', 70, NULL), \
+               ('Hal', 'law', 50, NULL)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(changes(&mut db), ["3|3|8"]);
+        // A SELECT, CHANGES() included, changes nothing.
+        assert_eq!(changes(&mut db), ["3|3|8"]);
+        run(
+            &mut db,
+            &["UPDATE employees SET salary = 0 WHERE dept = 'hr'"],
+        )
+        .unwrap();
+        assert_eq!(changes(&mut db), ["0|0|8"]);
+        run(
+            &mut db,
+            &["UPDATE employees SET salary = salary + 1 WHERE dept = 'eng'"],
+        )
+        .unwrap();
+        assert_eq!(changes(&mut db), ["3|3|11"]);
+        run(&mut db, &["DELETE FROM employees WHERE dept = 'law'"]).unwrap();
+        assert_eq!(changes(&mut db), ["2|2|13"]);
+        // A failed statement leaves the counts as they were, and a rolled
+        // back one stays counted.
+        assert!(run(&mut db, &["INSERT INTO employees VALUES (1)"]).is_err());
+        run(
+            &mut db,
+            &[
+                "BEGIN",
+                "DELETE FROM employees WHERE dept = 'ops'",
+                "ROLLBACK",
+            ],
+        )
+        .unwrap();
+        assert_eq!(changes(&mut db), ["3|3|16"]);
+        run(&mut db, &["TRUNCATE employees"]).unwrap();
+        assert_eq!(changes(&mut db), ["6|6|22"]);
+        assert_eq!(
+            query(&mut db, &["SELECT changes(1)"])
+                .unwrap_err()
+                .to_string(),
+            "wrong number of arguments to function changes()"
+        );
+    }
+
+    #[test]
+    fn test_last_insert_rowid() {
+        let mut db = employees();
+        let last = |db: &mut Database| {
+            query(db, &["SELECT last_insert_rowid(), last_insert_id()"]).unwrap()
+        };
+        // The employees have no INTEGER PRIMARY KEY.
+        assert_eq!(last(&mut db), ["0|0"]);
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE events (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT)",
+                "INSERT INTO events (name) VALUES ('click')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(last(&mut db), ["1|1"]);
+        run(
+            &mut db,
+            &["INSERT INTO events (name) VALUES ('scroll'), ('hover'), ('tap')"],
+        )
+        .unwrap();
+        assert_eq!(last(&mut db), ["4|4"]);
+        // Reading, or inserting nothing, leaves it alone.
+        query(&mut db, &["SELECT * FROM events"]).unwrap();
+        run(
+            &mut db,
+            &["INSERT OR IGNORE INTO events VALUES (1, 'again')"],
+        )
+        .unwrap();
+        assert_eq!(last(&mut db), ["4|4"]);
+        run(
+            &mut db,
+            &["INSERT OR REPLACE INTO events VALUES (2, 'drag')"],
+        )
+        .unwrap();
+        assert_eq!(last(&mut db), ["2|2"]);
+        run(
+            &mut db,
+            &["INSERT INTO employees VALUES ('Fay', 'eng', 90, NULL)"],
+        )
+        .unwrap();
+        assert_eq!(last(&mut db), ["0|0"]);
+    }
+
+    #[test]
+    fn test_index_lookup_matches_scan() {
+        let predicates = [
+            "dept = 'eng'",
+            "'ops' = dept AND salary > 70",
+            "dept = 'eng' OR salary = 60",
+            "dept = NULL",
+            "dept = 'none'",
+            "dept = (SELECT MAX(dept) FROM employees)",
+        ];
+        let select = |db: &mut Database| {
+            predicates
+                .iter()
+                .map(|p| query(db, &[&format!("SELECT name FROM employees WHERE {p}")]).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let mut scanned = employees();
+        let mut indexed = employees();
+        run(&mut indexed, &["CREATE INDEX by_dept ON employees (dept)"]).unwrap();
+        assert_eq!(select(&mut indexed), select(&mut scanned));
+        // The index follows every change to the rows.
+        let changes = [
+            "INSERT INTO employees VALUES ('Fay', 'eng', 90, NULL)",
+            "UPDATE employees SET dept = 'ops' WHERE name = 'Ann'",
+            "DELETE FROM employees WHERE name = 'Bob'",
+        ];
+        run(&mut scanned, &changes).unwrap();
+        run(&mut indexed, &changes).unwrap();
+        assert_eq!(select(&mut indexed), select(&mut scanned));
+        assert_eq!(
+            query(
+                &mut indexed,
+                &["SELECT name FROM employees WHERE dept = 'ops'"]
+            )
+            .unwrap(),
+            ["Ann", "Cy", "Di"]
+        );
+        run(&mut indexed, &["TRUNCATE employees"]).unwrap();
+        assert!(
+            query(
+                &mut indexed,
+                &["SELECT name FROM employees WHERE dept = 'ops'"]
+            )
+            .unwrap()
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_unique_index() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INTEGER, v TEXT)",
+                "INSERT INTO t VALUES (1, 'a'), (2, NULL), (3, NULL)",
+                "CREATE UNIQUE INDEX t_v ON t (v)",
+            ],
+        )
+        .unwrap();
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut db, "INSERT INTO t VALUES (4, 'a')"),
+            "UNIQUE constraint failed: t.v"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE t SET v = 'a' WHERE id = 2"),
+            "UNIQUE constraint failed: t.v"
+        );
+        // NULLs never conflict.
+        run(&mut db, &["INSERT INTO t VALUES (4, NULL)"]).unwrap();
+        assert_eq!(
+            error(&mut db, "CREATE UNIQUE INDEX t_id ON t (id, nope)"),
+            "no such column: nope"
+        );
+        assert_eq!(
+            error(&mut db, "CREATE INDEX t_v ON t (id)"),
+            "index t_v already exists"
+        );
+        run(
+            &mut db,
+            &[
+                "CREATE INDEX IF NOT EXISTS t_v ON t (id)",
+                "DROP INDEX t_v",
+                "INSERT INTO t VALUES (5, 'a')",
+                "DROP INDEX IF EXISTS t_v",
+            ],
+        )
+        .unwrap();
+        assert_eq!(error(&mut db, "DROP INDEX t_v"), "no such index: t_v");
+        // An index cannot be made unique over rows that are not.
+        assert_eq!(
+            error(&mut db, "CREATE UNIQUE INDEX t_v ON t (v)"),
+            "UNIQUE constraint failed: t.v"
+        );
+        assert!(db.get_index("t_v").is_none());
+        assert_eq!(
+            query(&mut db, &["SELECT id FROM t WHERE v = 'a'"]).unwrap(),
+            ["1", "5"]
+        );
+    }
+
+    #[test]
+    fn test_unique_constraints() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE users (email TEXT UNIQUE, id INT)",
+                "CREATE TABLE t (a INT, b INT, UNIQUE (a, b))",
+                "INSERT INTO users VALUES ('a@x', 1), (NULL, 2), (NULL, 3)",
+                "INSERT INTO t VALUES (1, 1), (1, 2), (2, 1), (1, NULL), (1, NULL)",
+            ],
+        )
+        .unwrap();
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut db, "INSERT INTO users VALUES ('a@x', 4)"),
+            "UNIQUE constraint failed: users.email"
+        );
+        assert_eq!(
+            error(&mut db, "INSERT INTO t VALUES (2, 1)"),
+            "UNIQUE constraint failed: t.a, t.b"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE users SET email = 'a@x' WHERE id = 3"),
+            "UNIQUE constraint failed: users.email"
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT id, email FROM users WHERE id = 3"]).unwrap(),
+            ["3|NULL"]
+        );
+        // Rows may trade values, as only the result must be unique.
+        run(
+            &mut db,
+            &["UPDATE t SET b = 3 - b WHERE b IS NOT NULL AND a = 1"],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM t WHERE a = 1 AND b > 0"]).unwrap(),
+            ["1|2", "1|1"]
+        );
+        assert_eq!(
+            error(&mut db, "CREATE TABLE u (a INT, UNIQUE (a, nope))"),
+            "no such column: nope"
+        );
+        assert!(db.get_table("u").is_none());
+        assert_eq!(
+            error(&mut db, "DROP INDEX sqlite_autoindex_users_1"),
+            "index associated with UNIQUE or PRIMARY KEY constraint cannot be dropped"
+        );
+        assert_eq!(
+            error(&mut db, "CREATE INDEX sqlite_autoindex_x ON t (a)"),
+            "object name reserved for internal use: sqlite_autoindex_x"
+        );
+    }
+
+    #[test]
+    fn test_index_lookup_plan() {
+        let mut db = Database::new();
+        run(&mut db, &["CREATE TABLE big (k INTEGER, v TEXT)"]).unwrap();
+        for k in 0..10_000 {
+            db.insert_row("big", vec![Value::Integer(k), Value::Text(format!("v{k}"))])
+                .unwrap();
+        }
+        let lookups = |db: &mut Database| -> Vec<String> {
+            let mut found = Vec::new();
+            for k in (0..10_000).step_by(500) {
+                found.extend(query(db, &[&format!("SELECT v FROM big WHERE k = {k}")]).unwrap());
+            }
+            found
+        };
+        let plan = |db: &mut Database| {
+            query(db, &["EXPLAIN SELECT v FROM big WHERE k = 500"])
+                .unwrap()
+                .join("\n")
+        };
+        assert!(plan(&mut db).contains("TableScan(big)"));
+        let scanned = lookups(&mut db);
+        run(&mut db, &["CREATE INDEX big_k ON big (k)"]).unwrap();
+        // Each lookup reads only the rows with its key.
+        assert!(plan(&mut db).contains("IndexScan(big USING big_k, key 500)"));
+        let indexed = lookups(&mut db);
+        assert_eq!(indexed, scanned);
+        assert_eq!(scanned.len(), 20);
+    }
+
+    #[test]
+    fn test_primary_key_implies_not_null() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &["CREATE TABLE t (id INTEGER PRIMARY KEY, v TEXT UNIQUE)"],
+        )
+        .unwrap();
+        let schema = &db.get_table("t").unwrap().schema;
+        assert!(schema[0].primary_key && !schema[0].nullable);
+        assert!(schema[1].unique && schema[1].nullable);
+    }
+
+    fn stored(db: &Database, table: &str) -> Vec<String> {
+        db.get_table(table)
+            .unwrap()
+            .rows
+            .iter()
+            .map(|row| format!("{row:?}"))
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_fills_defaults() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INTEGER NOT NULL, name TEXT DEFAULT 'anon', score REAL)",
+                "INSERT INTO t (id) VALUES (1)",
+                "INSERT INTO t (score, id) VALUES (2.5, 2)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            stored(&db, "t"),
+            [
+                r#"[Integer(1), Text("anon"), Null]"#,
+                r#"[Integer(2), Text("anon"), Real(2.5)]"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_expressions() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INT, n INT DEFAULT 0, s TEXT DEFAULT 'unknown', \
+                 e INT DEFAULT (1 + 1), neg INT DEFAULT -1, at TEXT DEFAULT CURRENT_TIMESTAMP, x TEXT)",
+                "INSERT INTO t (id) VALUES (1), (2)",
+                "INSERT INTO t (id, e) VALUES (3, NULL)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT id, n, s, e, neg, x FROM t"]).unwrap(),
+            [
+                "1|0|unknown|2|-1|NULL",
+                "2|0|unknown|2|-1|NULL",
+                "3|0|unknown|NULL|-1|NULL"
+            ]
+        );
+        // Both rows of one statement get the time it started at.
+        let times = query(&mut db, &["SELECT DISTINCT at FROM t WHERE id < 3"]).unwrap();
+        let [time] = &times[..] else {
+            panic!("expected one timestamp, got {times:?}");
+        };
+        let shape = time
+            .chars()
+            .map(|c| if c.is_ascii_digit() { '0' } else { c })
+            .collect::<String>();
+        assert_eq!(shape, "0000-00-00 00:00:00");
+
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut db, "CREATE TABLE u (a INT, b INT DEFAULT (a + 1))"),
+            "default value of column [b] is not constant"
+        );
+        assert!(db.get_table("u").is_none());
+        assert_eq!(
+            error(&mut db, "ALTER TABLE t ADD c INT DEFAULT (SELECT 1)"),
+            "default value of column [c] is not constant"
+        );
+    }
+
+    #[test]
+    fn test_insert_not_null_violation() {
+        let mut db = Database::new();
+        let err = run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INTEGER NOT NULL, name TEXT)",
+                "INSERT INTO t (name) VALUES ('x')",
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "NOT NULL constraint failed: t.id");
+        assert!(stored(&db, "t").is_empty());
+    }
+
+    #[test]
+    fn test_not_null_constraint() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INTEGER NOT NULL, name TEXT NOT NULL DEFAULT 'anon')",
+                "INSERT INTO t (id) VALUES (1)",
+            ],
+        )
+        .unwrap();
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        for sql in [
+            "INSERT INTO t VALUES (NULL, 'x')",
+            "INSERT INTO t VALUES (1 + NULL, 'x')",
+            "INSERT INTO t (name) VALUES ('x')",
+            "UPDATE t SET id = NULL",
+            "UPDATE t SET name = 'x', id = id * NULL WHERE id = 1",
+        ] {
+            assert_eq!(
+                error(&mut db, sql),
+                "NOT NULL constraint failed: t.id",
+                "{sql}"
+            );
+        }
+        assert_eq!(
+            error(&mut db, "INSERT INTO t VALUES (3, NULL)"),
+            "NOT NULL constraint failed: t.name"
+        );
+        assert_eq!(query(&mut db, &["SELECT * FROM t"]).unwrap(), ["1|anon"]);
+    }
+
+    #[test]
+    fn test_insert_coerces_values() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (i INTEGER, r REAL, s TEXT)",
+                "INSERT INTO t VALUES ('42', 1, 3 * 4)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            stored(&db, "t"),
+            [r#"[Integer(42), Real(1.0), Text("12")]"#]
+        );
+    }
+
+    #[test]
+    fn test_multi_row_insert() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INTEGER, name TEXT)",
+                "INSERT INTO t VALUES (1, 'a'), (2, 'b'), (3, NULL)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(stored(&db, "t").len(), 3);
+    }
+
+    #[test]
+    fn test_insert_column_count_mismatch() {
+        let mut db = Database::new();
+        run(&mut db, &["CREATE TABLE t (a INT, b INT)"]).unwrap();
+        for sql in [
+            "INSERT INTO t VALUES (1)",
+            "INSERT INTO t (a) VALUES (1, 2)",
+        ] {
+            let err = run(&mut db, &[sql]).unwrap_err();
+            assert_eq!(err.to_string(), "column count mismatch");
+        }
+        let err = run(&mut db, &["INSERT INTO t (c) VALUES (1)"]).unwrap_err();
+        assert_eq!(err, EvalError::NoSuchColumn("c".into()));
+    }
+
+    #[test]
+    fn test_insert_select() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE orders (id INTEGER PRIMARY KEY, customer INT, total TEXT)",
+                "CREATE TABLE customers (id INT, name TEXT)",
+                "CREATE TABLE archive (id INTEGER PRIMARY KEY, name TEXT, total REAL)",
+                "INSERT INTO orders VALUES (1, 10, '5.5'), (2, 20, '7'), (3, 10, '1')",
+                "INSERT INTO customers VALUES (10, 'ann'), (20, 'bob')",
+                "INSERT INTO archive SELECT id, 'x', total FROM orders WHERE id > 5",
+            ],
+        )
+        .unwrap();
+        assert!(stored(&db, "archive").is_empty());
+        // Each row is coerced to the target's column types.
+        let rows = query(
+            &mut db,
+            &[
+                "INSERT INTO archive (id, name, total) \
+                 SELECT o.id, c.name, o.total FROM orders o JOIN customers c \
+                 ON o.customer = c.id WHERE o.id < 3",
+                "SELECT id, name, total, TYPEOF(total) FROM archive ORDER BY id",
+            ],
+        )
+        .unwrap();
+        assert_eq!(rows, ["1|ann|5.5|real", "2|bob|7.0|real"]);
+        assert_eq!(
+            run(&mut db, &["INSERT INTO archive SELECT id FROM orders"])
+                .unwrap_err()
+                .to_string(),
+            "column count mismatch"
+        );
+        // Order 3 goes in before order 1 is rejected as a duplicate, and is
+        // taken out again.
+        let err = run(
+            &mut db,
+            &["INSERT INTO archive SELECT id, 'y', total FROM orders ORDER BY id DESC"],
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "UNIQUE constraint failed: archive.id");
+        assert_eq!(stored(&db, "archive").len(), 2);
+        // A query of the target table sees none of the rows it inserts.
+        run(
+            &mut db,
+            &["INSERT INTO archive SELECT id + 10, name, total FROM archive"],
+        )
+        .unwrap();
+        assert_eq!(stored(&db, "archive").len(), 4);
+    }
+
+    #[test]
+    fn test_insert_duplicate_primary_key() {
+        let mut db = Database::new();
+        let err = run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INTEGER PRIMARY KEY, v TEXT)",
+                "INSERT INTO t VALUES (1, 'a')",
+                "INSERT INTO t VALUES (1, 'b')",
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "UNIQUE constraint failed: t.id");
+        assert_eq!(stored(&db, "t").len(), 1);
+        // A NULL key is reported as such, before any duplicate.
+        assert_eq!(
+            run(&mut db, &["INSERT INTO t VALUES (NULL, 'c')"])
+                .unwrap_err()
+                .to_string(),
+            "NOT NULL constraint failed: t.id"
+        );
+    }
+
+    #[test]
+    fn test_multi_row_insert_is_all_or_nothing() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &["CREATE TABLE t (id INTEGER PRIMARY KEY, label TEXT DEFAULT 'none', n INTEGER NOT NULL)"],
+        )
+        .unwrap();
+        let tuples: Vec<String> = (1..=100).map(|i| format!("({i}, 'r{i}', {i})")).collect();
+        run(
+            &mut db,
+            &[&format!("INSERT INTO t VALUES {}", tuples.join(", "))],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT COUNT(*), SUM(n), MAX(label) FROM t"]).unwrap(),
+            ["100|5050|r99"]
+        );
+        // A violation by any row inserts none of them, whichever check
+        // catches it.
+        for (sql, error) in [
+            (
+                "INSERT INTO t VALUES (101, 'a', 1), (102, 'b', 2), (5, 'c', 3)",
+                "UNIQUE constraint failed: t.id",
+            ),
+            (
+                "INSERT INTO t VALUES (101, 'a', 1), (101, 'b', 2)",
+                "UNIQUE constraint failed: t.id",
+            ),
+            (
+                "INSERT INTO t VALUES (101, 'a', 1), (102, 'b', NULL)",
+                "NOT NULL constraint failed: t.n",
+            ),
+            (
+                "INSERT INTO t VALUES (101, 'a', 1), (102, 'b', 'x')",
+                "cannot convert 'x' to INTEGER",
+            ),
+            (
+                "INSERT INTO t (id, label) VALUES (101, 'a'), (102, 'b')",
+                "NOT NULL constraint failed: t.n",
+            ),
+        ] {
+            assert_eq!(
+                run(&mut db, &[sql]).unwrap_err().to_string(),
+                error,
+                "{sql}"
+            );
+            assert_eq!(stored(&db, "t").len(), 100, "{sql}");
+        }
+        run(
+            &mut db,
+            &[
+                "DELETE FROM t WHERE id > 2",
+                "INSERT INTO t (n, id) VALUES (30, 3), (40, 4)",
+                "INSERT OR IGNORE INTO t (id, n) VALUES (2, 0), (5, 50), (1, 0), (6, 60)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM t"]).unwrap(),
+            [
+                "1|r1|1",
+                "2|r2|2",
+                "3|none|30",
+                "4|none|40",
+                "5|none|50",
+                "6|none|60"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_or_replace() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE, name TEXT, \
+                 age INTEGER DEFAULT 30)",
+                "INSERT INTO users VALUES (1, 'a@x', 'Ann', 41), (2, 'b@x', 'Bob', 52)",
+                "INSERT OR REPLACE INTO users (id, email, name) VALUES (1, 'ann@x', 'Annie')",
+            ],
+        )
+        .unwrap();
+        // The old row is gone entirely: its age is not kept.
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM users"]).unwrap(),
+            ["2|b@x|Bob|52", "1|ann@x|Annie|30"]
+        );
+        // A row may be in the way of more than one key.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "REPLACE INTO users VALUES (2, 'ann@x', 'Cy', 20), (3, 'c@x', 'Di', 25) \
+                   RETURNING id, name"
+                ]
+            )
+            .unwrap(),
+            ["2|Cy", "3|Di"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT id, email FROM users"]).unwrap(),
+            ["2|ann@x", "3|c@x"]
+        );
+        // A later row of the statement replaces an earlier one.
+        run(
+            &mut db,
+            &["INSERT OR REPLACE INTO users VALUES (4, 'd@x', 'Ed', 1), (4, 'e@x', 'Ed', 2)"],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT email, age FROM users WHERE id = 4"]).unwrap(),
+            ["e@x|2"]
+        );
+        // Other constraints still fail the statement, and nothing changes.
+        assert_eq!(
+            run(
+                &mut db,
+                &["INSERT OR REPLACE INTO users VALUES (2, 'f@x', 'Fay', 3), (NULL, 'g@x', 'Gil', 4)"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "NOT NULL constraint failed: users.id"
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM users"]).unwrap(),
+            ["Cy", "Di", "Ed"]
+        );
+    }
+
+    #[test]
+    fn test_insert_or_replace_applies_foreign_key_actions() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT)",
+                "CREATE TABLE books (title TEXT, author_id INTEGER \
+                 REFERENCES authors(id) ON DELETE CASCADE)",
+                "CREATE TABLE prizes (author_id INTEGER REFERENCES authors(id))",
+                "INSERT INTO authors VALUES (1, 'Ann'), (2, 'Bob')",
+                "INSERT INTO books VALUES ('A', 1), ('B', 1), ('C', 2)",
+                "INSERT OR REPLACE INTO authors VALUES (1, 'Annie')",
+            ],
+        )
+        .unwrap();
+        // Replacing a row deletes it, and with it the rows referring to it.
+        assert_eq!(query(&mut db, &["SELECT title FROM books"]).unwrap(), ["C"]);
+        run(&mut db, &["INSERT INTO prizes VALUES (2)"]).unwrap();
+        assert_eq!(
+            run(
+                &mut db,
+                &["INSERT OR REPLACE INTO authors VALUES (3, 'Cy'), (2, 'Bobby')"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "FOREIGN KEY constraint failed"
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM authors"]).unwrap(),
+            ["Bob", "Annie"]
+        );
+        assert_eq!(query(&mut db, &["SELECT title FROM books"]).unwrap(), ["C"]);
+    }
+
+    #[test]
+    fn test_insert_or_ignore() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INTEGER PRIMARY KEY, code TEXT UNIQUE)",
+                "INSERT INTO t VALUES (1, 'a')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "INSERT OR IGNORE INTO t VALUES (1, 'b'), (2, 'a'), (3, 'c'), (3, 'd') \
+                   RETURNING *"
+                ]
+            )
+            .unwrap(),
+            ["3|c"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM t"]).unwrap(),
+            ["1|a", "3|c"]
+        );
+        // Only key conflicts are ignored.
+        assert_eq!(
+            run(
+                &mut db,
+                &["INSERT OR IGNORE INTO t VALUES (4, 'e'), (NULL, 'f')"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "NOT NULL constraint failed: t.id"
+        );
+        assert_eq!(stored(&db, "t").len(), 2);
+    }
+
+    #[test]
+    fn test_check_constraints() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE products (name TEXT, price REAL CHECK (price > 0), \
+               quantity INT CONSTRAINT stocked CHECK (quantity >= 0), \
+               CHECK (quantity >= 0 AND quantity < 10000), \
+               CONSTRAINT worth CHECK (price * quantity < 1000), CHECK (1))",
+            ],
+        )
+        .unwrap();
+        run(
+            &mut db,
+            &["INSERT INTO products VALUES ('a', 2.5, 10), ('b', NULL, 1), ('c', '9', NULL)"],
+        )
+        .unwrap();
+        for (sql, error) in [
+            (
+                "INSERT INTO products VALUES ('d', 0, 1)",
+                "CHECK constraint failed: products.price",
+            ),
+            (
+                "INSERT INTO products VALUES ('d', 1, -1)",
+                "CHECK constraint failed: stocked",
+            ),
+            (
+                "INSERT INTO products VALUES ('d', 0.01, 10000)",
+                "CHECK constraint failed: quantity >= 0 AND quantity < 10000",
+            ),
+            (
+                "INSERT INTO products VALUES ('d', 1, 1), ('e', 10, 100)",
+                "CHECK constraint failed: worth",
+            ),
+            // The constraints see the value as stored, not as given.
+            (
+                "INSERT INTO products VALUES ('d', '-1', 1)",
+                "CHECK constraint failed: products.price",
+            ),
+            (
+                "UPDATE products SET price = price - 5",
+                "CHECK constraint failed: products.price",
+            ),
+            (
+                "UPDATE products SET quantity = quantity * 10000 WHERE name = 'b'",
+                "CHECK constraint failed: quantity >= 0 AND quantity < 10000",
+            ),
+        ] {
+            assert_eq!(
+                run(&mut db, &[sql]).unwrap_err().to_string(),
+                error,
+                "{sql}"
+            );
+        }
+        assert_eq!(
+            changed(&mut db, "UPDATE products SET quantity = 50 WHERE price < 5"),
+            Ok(1)
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM products"]).unwrap(),
+            ["a|2.5|50", "b|NULL|1", "c|9.0|NULL"]
+        );
+    }
+
+    #[test]
+    fn test_check_constraint_columns() {
+        let mut db = Database::new();
+        // A column's constraints see only that column.
+        for (sql, error) in [
+            (
+                "CREATE TABLE t (a INT CHECK (a < b), b INT)",
+                "no such column: b",
+            ),
+            ("CREATE TABLE t (a INT, CHECK (c > 0))", "no such column: c"),
+            (
+                "CREATE TABLE t (a INT CHECK (COUNT(*) > 0))",
+                "misuse of aggregate: COUNT()",
+            ),
+        ] {
+            assert_eq!(run(&mut db, &[sql]).unwrap_err().to_string(), error);
+            assert!(db.get_table("t").is_none());
+        }
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (a INT, b INT, CHECK (a < b))",
+                "INSERT INTO t VALUES (1, 2)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut db, &["ALTER TABLE t DROP COLUMN b"])
+                .unwrap_err()
+                .to_string(),
+            "cannot drop column \"b\": used in a CHECK constraint"
+        );
+        // A new column's constraints must hold for its value in the
+        // existing rows.
+        assert_eq!(
+            run(
+                &mut db,
+                &["ALTER TABLE t ADD c INT DEFAULT 0 CHECK (c > 0)"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "CHECK constraint failed: t.c"
+        );
+        assert_eq!(db.get_table("t").unwrap().schema.len(), 2);
+        run(
+            &mut db,
+            &[
+                "ALTER TABLE t ADD c INT DEFAULT 1 CHECK (c > 0)",
+                "ALTER TABLE t DROP COLUMN c",
+                "INSERT INTO t VALUES (2, 3)",
+            ],
+        )
+        .unwrap();
+        assert!(run(&mut db, &["INSERT INTO t VALUES (3, 2)"]).is_err());
+    }
+
+    #[test]
+    fn test_composite_primary_key() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE lines (order_id INT, line_num INT, item TEXT, \
+                 PRIMARY KEY (order_id, line_num))",
+                "INSERT INTO lines VALUES (1, 1, 'a'), (1, 2, 'b'), (2, 1, 'c')",
+            ],
+        )
+        .unwrap();
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut db, "INSERT INTO lines VALUES (1, 2, 'd')"),
+            "UNIQUE constraint failed: lines.order_id, lines.line_num"
+        );
+        assert_eq!(
+            error(&mut db, "INSERT INTO lines VALUES (3, NULL, 'd')"),
+            "NOT NULL constraint failed: lines.line_num"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE lines SET order_id = 1 WHERE item = 'c'"),
+            "UNIQUE constraint failed: lines.order_id, lines.line_num"
+        );
+        let table = db.get_table("lines").unwrap();
+        assert_eq!(table.primary_key.as_ref().unwrap().columns, [0, 1]);
+        assert!(table.schema[1].primary_key && !table.schema[1].nullable);
+        assert_eq!(
+            error(
+                &mut db,
+                "CREATE TABLE t (a INT, b INT, PRIMARY KEY (a, nope))"
+            ),
+            "no such column: nope"
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT item FROM lines WHERE line_num = 1"]).unwrap(),
+            ["a", "c"]
+        );
+    }
+
+    /// Customers and their orders, whose foreign key deletes with `action`.
+    fn customer_orders(action: &str) -> Database {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE customers (id INT PRIMARY KEY, name TEXT)",
+                &format!(
+                    "CREATE TABLE orders (id INT, customer_id INT DEFAULT 3 \
+                     REFERENCES customers(id) ON DELETE {action})"
+                ),
+                "INSERT INTO customers VALUES (1, 'ann'), (2, 'bob'), (3, 'cy')",
+                "INSERT INTO orders VALUES (10, 1), (11, 2), (12, 1), (13, NULL)",
+            ],
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_foreign_key_references() {
+        let mut db = customer_orders("NO ACTION");
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut db, "INSERT INTO orders VALUES (14, 4)"),
+            "FOREIGN KEY constraint failed"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE orders SET customer_id = 5 WHERE id = 10"),
+            "FOREIGN KEY constraint failed"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE customers SET id = 5 WHERE id = 2"),
+            "FOREIGN KEY constraint failed"
+        );
+        // Neither a NULL key nor a parent row that is no longer used needs
+        // checking.
+        run(
+            &mut db,
+            &[
+                "INSERT INTO orders VALUES (14, NULL)",
+                "UPDATE orders SET customer_id = 3 WHERE id = 11",
+                "UPDATE customers SET id = 4 WHERE id = 2",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            error(&mut db, "DELETE FROM customers WHERE id = 1"),
+            "FOREIGN KEY constraint failed"
+        );
+        assert_eq!(
+            error(&mut db, "DROP TABLE customers"),
+            "cannot drop table customers: referenced by orders"
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT count(*) FROM customers"]).unwrap(),
+            ["3"]
+        );
+        // The referenced key is only looked for once there is a row to
+        // check.
+        run(
+            &mut db,
+            &["CREATE TABLE bad (customer_name TEXT REFERENCES customers(name))"],
+        )
+        .unwrap();
+        assert_eq!(
+            error(&mut db, "INSERT INTO bad VALUES ('ann')"),
+            r#"foreign key mismatch - "bad" referencing "customers""#
+        );
+        run(
+            &mut db,
+            &[
+                "DROP TABLE orders",
+                "DROP TABLE bad",
+                "DROP TABLE customers",
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_foreign_key_delete_actions() {
+        let remaining = |action: &str, sql: &str| {
+            let mut db = customer_orders(action);
+            run(&mut db, &[sql]).map(|_| {
+                query(&mut db, &["SELECT id, customer_id FROM orders ORDER BY id"]).unwrap()
+            })
+        };
+        let delete = "DELETE FROM customers WHERE id = 1";
+        assert_eq!(remaining("CASCADE", delete).unwrap(), ["11|2", "13|NULL"]);
+        assert_eq!(
+            remaining("SET NULL", delete).unwrap(),
+            ["10|NULL", "11|2", "12|NULL", "13|NULL"]
+        );
+        assert_eq!(
+            remaining("SET DEFAULT", delete).unwrap(),
+            ["10|3", "11|2", "12|3", "13|NULL"]
+        );
+        for action in ["RESTRICT", "NO ACTION"] {
+            assert_eq!(
+                remaining(action, delete).unwrap_err().to_string(),
+                "FOREIGN KEY constraint failed"
+            );
+        }
+        // A default that is itself missing from the parent fails, and
+        // leaves every table as it was.
+        let mut db = customer_orders("SET DEFAULT");
+        assert_eq!(
+            run(&mut db, &["DELETE FROM customers WHERE id <> 2"])
+                .unwrap_err()
+                .to_string(),
+            "FOREIGN KEY constraint failed"
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT count(*) FROM customers"]).unwrap(),
+            ["3"]
+        );
+        assert_eq!(
+            remaining("CASCADE", "TRUNCATE customers").unwrap(),
+            ["13|NULL"]
+        );
+    }
+
+    #[test]
+    fn test_foreign_key_cascades_through_tables() {
+        let mut db = customer_orders("CASCADE");
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE items (order_id INT, FOREIGN KEY (order_id) \
+                 REFERENCES orders(id) ON DELETE CASCADE)",
+                "CREATE UNIQUE INDEX order_ids ON orders (id)",
+                "INSERT INTO items VALUES (10), (10), (11), (12)",
+                "DELETE FROM customers WHERE name = 'ann'",
+            ],
+        )
+        .unwrap();
+        assert_eq!(query(&mut db, &["SELECT * FROM items"]).unwrap(), ["11"]);
+        assert_eq!(
+            run(
+                &mut db,
+                &["CREATE TABLE a (id INT PRIMARY KEY, b_id INT REFERENCES b(id))"]
+            )
+            .and_then(|_| run(
+                &mut db,
+                &["CREATE TABLE b (id INT PRIMARY KEY, a_id INT, \
+                   FOREIGN KEY (a_id) REFERENCES a(id))"]
+            ))
+            .unwrap_err()
+            .to_string(),
+            "circular foreign key reference involving table b"
+        );
+        assert!(db.get_table("b").is_none());
+        assert_eq!(
+            run(
+                &mut db,
+                &["CREATE TABLE c (id INT PRIMARY KEY, up INT REFERENCES c)"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "circular foreign key reference involving table c"
+        );
+    }
+
+    /// Run the statements and render the final result as `|`-separated rows.
+    pub(crate) fn query(db: &mut Database, sql: &[&str]) -> Result<Vec<String>, EvalError> {
+        Ok(run(db, sql)?
+            .iter()
+            .map(|row| {
+                let fields: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+                fields.join("|")
+            })
+            .collect())
+    }
+
+    fn users() -> Database {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE users (id INTEGER, name TEXT)",
+                "INSERT INTO users VALUES (1, 'ann'), (2, 'bob')",
+            ],
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_select_without_from() {
+        let mut db = Database::new();
+        assert_eq!(
+            query(&mut db, &["SELECT 1, 'hello', 2 * 3"]).unwrap(),
+            ["1|hello|6"]
+        );
+    }
+
+    #[test]
+    fn test_select_columns() {
+        let mut db = users();
+        assert_eq!(
+            query(&mut db, &["SELECT name, id * 10 FROM users"]).unwrap(),
+            ["ann|10", "bob|20"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT users.id, u.name FROM users u"]).unwrap_err(),
+            EvalError::NoSuchColumn("users.id".into())
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT u.id, u.name FROM users u"]).unwrap(),
+            ["1|ann", "2|bob"]
+        );
+    }
+
+    #[test]
+    fn test_select_star_expansion() {
+        let mut db = users();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM users"]).unwrap(),
+            ["1|ann", "2|bob"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT t.*, t.id FROM users AS t"]).unwrap(),
+            ["1|ann|1", "2|bob|2"]
+        );
+    }
+
+    #[test]
+    fn test_select_star_across_sources() {
+        let mut db = users();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE orders (order_id INTEGER, user_id INTEGER, item TEXT)",
+                "INSERT INTO orders VALUES (10, 1, 'pen'), (11, 2, 'ink')",
+                "CREATE TABLE items (item TEXT, price INTEGER)",
+                "INSERT INTO items VALUES ('pen', 3), ('ink', 5)",
+            ],
+        )
+        .unwrap();
+        // Every table's columns, in the order the tables are written.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT * FROM users u JOIN orders o ON o.user_id = u.id \
+                   JOIN items i ON i.item = o.item ORDER BY u.id"]
+            )
+            .unwrap(),
+            ["1|ann|10|1|pen|pen|3", "2|bob|11|2|ink|ink|5"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT o.*, u.name FROM users u JOIN orders o ON o.user_id = u.id ORDER BY u.id"]
+            )
+            .unwrap(),
+            ["10|1|pen|ann", "11|2|ink|bob"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT u.*, u.* FROM users u WHERE id = 1"]).unwrap(),
+            ["1|ann|1|ann"]
+        );
+        assert_eq!(
+            run(&mut db, &["SELECT x.* FROM users u"])
+                .unwrap_err()
+                .to_string(),
+            "no such table: x"
+        );
+        // A derived table, view or CTE expands to its output columns.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT * FROM (SELECT *, id * 2 AS twice FROM users) AS d WHERE d.id = 2"]
+            )
+            .unwrap(),
+            ["2|bob|4"]
+        );
+        run(
+            &mut db,
+            &["CREATE VIEW names AS SELECT name, id FROM users"],
+        )
+        .unwrap();
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "WITH c AS (SELECT item FROM items) SELECT names.*, c.* FROM names, c \
+                   WHERE id = 1 ORDER BY item"
+                ]
+            )
+            .unwrap(),
+            ["ann|1|ink", "ann|1|pen"]
+        );
+        // A column added later is part of `*`.
+        run(
+            &mut db,
+            &["ALTER TABLE users ADD COLUMN age INTEGER DEFAULT 30"],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM users WHERE id = 1"]).unwrap(),
+            ["1|ann|30"]
+        );
+    }
+
+    #[test]
+    fn test_select_unknown_column() {
+        let mut db = users();
+        let err = query(&mut db, &["SELECT id, email FROM users"]).unwrap_err();
+        assert_eq!(err.to_string(), "no such column: email");
+    }
+
+    fn nullable() -> Database {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INTEGER, x INTEGER, s TEXT)",
+                "INSERT INTO t VALUES (1, 10, 'apple'), (2, NULL, 'Banana'), \
+                 (3, 30, NULL), (4, 40, 'cherry')",
+            ],
+        )
+        .unwrap();
+        db
+    }
+
+    fn ids(db: &mut Database, predicate: &str) -> Vec<String> {
+        query(db, &[&format!("SELECT id FROM t WHERE {predicate}")]).unwrap()
+    }
+
+    #[test]
+    fn test_where_comparisons() {
+        let mut db = nullable();
+        assert_eq!(ids(&mut db, "x = 10"), ["1"]);
+        assert_eq!(ids(&mut db, "x <> 10"), ["3", "4"]);
+        assert_eq!(ids(&mut db, "x < 30"), ["1"]);
+        assert_eq!(ids(&mut db, "x <= 30"), ["1", "3"]);
+        assert_eq!(ids(&mut db, "x > 10"), ["3", "4"]);
+        assert_eq!(ids(&mut db, "x >= 40"), ["4"]);
+        assert_eq!(ids(&mut db, "x BETWEEN 10 AND 30"), ["1", "3"]);
+        assert_eq!(ids(&mut db, "x IN (10, 40)"), ["1", "4"]);
+        assert_eq!(ids(&mut db, "x NOT IN (10, NULL)"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_where_null_handling() {
+        let mut db = nullable();
+        assert!(ids(&mut db, "x = NULL").is_empty());
+        assert!(ids(&mut db, "x <> NULL").is_empty());
+        assert_eq!(ids(&mut db, "x IS NULL"), ["2"]);
+        assert_eq!(ids(&mut db, "x IS NOT NULL"), ["1", "3", "4"]);
+        assert_eq!(ids(&mut db, "NOT x > 10"), ["1"]);
+    }
+
+    #[test]
+    fn test_where_boolean_connectives() {
+        let mut db = nullable();
+        assert_eq!(ids(&mut db, "x > 10 AND s IS NOT NULL"), ["4"]);
+        assert_eq!(ids(&mut db, "x = 10 OR s = 'cherry'"), ["1", "4"]);
+        // NULL OR TRUE is TRUE; NULL AND TRUE is NULL.
+        assert_eq!(ids(&mut db, "x > 0 OR id = 2"), ["1", "2", "3", "4"]);
+        assert_eq!(ids(&mut db, "x > 0 AND id = 2"), Vec::<String>::new());
+        assert_eq!(ids(&mut db, "id"), ["1", "2", "3", "4"]);
+        // Non-empty text is truthy.
+        assert_eq!(ids(&mut db, "s"), ["1", "2", "4"]);
+    }
+
+    #[test]
+    fn test_between() {
+        let mut db = nullable();
+        // Both bounds are inclusive.
+        assert_eq!(ids(&mut db, "x BETWEEN 10 AND 30"), ["1", "3"]);
+        assert_eq!(ids(&mut db, "x NOT BETWEEN 10 AND 30"), ["4"]);
+        assert_eq!(ids(&mut db, "x BETWEEN 30 AND 10"), Vec::<String>::new());
+        assert_eq!(ids(&mut db, "x BETWEEN 9.5 AND 10.5"), ["1"]);
+        assert_eq!(ids(&mut db, "s BETWEEN 'apple' AND 'banana'"), ["1"]);
+        // Text compares by bytes, so uppercase sorts first.
+        assert_eq!(ids(&mut db, "s BETWEEN 'A' AND 'Z'"), ["2"]);
+        for (expr, result) in [
+            ("NULL BETWEEN 1 AND 2", "NULL"),
+            ("1 BETWEEN NULL AND 2", "NULL"),
+            ("1 BETWEEN 0 AND NULL", "NULL"),
+            ("1 NOT BETWEEN NULL AND 2", "NULL"),
+            // A bound already missed decides the result despite a NULL.
+            ("1 BETWEEN NULL AND 0", "0"),
+            ("1 NOT BETWEEN 2 AND NULL", "1"),
+            ("2 BETWEEN 1 AND 3.5", "1"),
+        ] {
+            let mut db = Database::new();
+            assert_eq!(
+                query(&mut db, &[&format!("SELECT {expr}")]).unwrap(),
+                [result],
+                "{expr}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_where_like() {
+        let mut db = nullable();
+        assert_eq!(ids(&mut db, "s LIKE '%an%'"), ["2"]);
+        assert_eq!(ids(&mut db, "s LIKE 'b%'"), Vec::<String>::new());
+        assert_eq!(ids(&mut db, "s LIKE '_pple'"), ["1"]);
+        assert_eq!(ids(&mut db, "s NOT LIKE '%e%'"), ["2"]);
+    }
+
+    #[test]
+    fn test_like_escape_collation_and_null() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (id INTEGER, s TEXT, n TEXT COLLATE NOCASE)",
+                "INSERT INTO t VALUES (1, 'Apple', 'Apple'), (2, '50% off', 'BANANA')",
+                "INSERT INTO t VALUES (3, 'a_b', NULL)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(ids(&mut db, "s LIKE 'apple'"), Vec::<String>::new());
+        assert_eq!(ids(&mut db, "n LIKE 'apple'"), ["1"]);
+        assert_eq!(ids(&mut db, "'BANANA' LIKE n"), ["2"]);
+        assert_eq!(ids(&mut db, "s COLLATE NOCASE LIKE 'a%'"), ["1", "3"]);
+        assert_eq!(
+            ids(&mut db, "n COLLATE BINARY LIKE 'apple'"),
+            Vec::<String>::new()
+        );
+        assert_eq!(ids(&mut db, "s LIKE '%!%%' ESCAPE '!'"), ["2"]);
+        assert_eq!(ids(&mut db, "s LIKE 'a\\_b' ESCAPE '\\'"), ["3"]);
+        assert_eq!(ids(&mut db, "s NOT LIKE '%!%%' ESCAPE '!'"), ["1", "3"]);
+        // NULL on either side, or as the escape, makes the match NULL, and
+        // so does NOT LIKE.
+        assert_eq!(ids(&mut db, "n LIKE '%' OR n NOT LIKE '%'"), ["1", "2"]);
+        for expr in [
+            "NULL LIKE 'a'",
+            "'a' LIKE NULL",
+            "'a' LIKE 'a' ESCAPE NULL",
+            "NULL NOT LIKE 'a'",
+            "'a' NOT LIKE NULL",
+        ] {
+            let mut db = Database::new();
+            assert_eq!(
+                query(&mut db, &[&format!("SELECT {expr}")]).unwrap(),
+                ["NULL"]
+            );
+        }
+        assert_eq!(
+            query(&mut db, &["SELECT 'a' LIKE 'a' ESCAPE '!!'"])
+                .unwrap_err()
+                .to_string(),
+            "ESCAPE expression must be a single character"
+        );
+    }
+
+    #[test]
+    fn test_collations() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE users (name TEXT COLLATE NOCASE UNIQUE, code TEXT COLLATE RTRIM, \
+                 tag TEXT)",
+                "INSERT INTO users VALUES ('alice', 'a1', 'x'), ('Bob', 'b2  ', 'Y'), \
+                 ('carol', 'c3', 'z')",
+            ],
+        )
+        .unwrap();
+        // Keys are unique under the column's collation.
+        assert_eq!(
+            run(&mut db, &["INSERT INTO users VALUES ('ALICE', 'a9', NULL)"])
+                .unwrap_err()
+                .to_string(),
+            "UNIQUE constraint failed: users.name"
+        );
+        let names = |db: &mut Database, sql: &str| {
+            query(db, &[&format!("SELECT name FROM users {sql}")]).unwrap()
+        };
+        assert_eq!(names(&mut db, "WHERE name = 'BOB'"), ["Bob"]);
+        assert_eq!(names(&mut db, "WHERE 'BOB' = name"), ["Bob"]);
+        assert_eq!(names(&mut db, "WHERE name > 'B'"), ["Bob", "carol"]);
+        assert_eq!(names(&mut db, "WHERE name BETWEEN 'B' AND 'C'"), ["Bob"]);
+        assert_eq!(names(&mut db, "WHERE code = 'b2'"), ["Bob"]);
+        assert_eq!(
+            names(&mut db, "WHERE code = 'b2 ' AND code <> ' b2'"),
+            ["Bob"]
+        );
+        // A COLLATE in the expression overrides the columns'.
+        assert!(names(&mut db, "WHERE name = 'BOB' COLLATE BINARY").is_empty());
+        assert!(names(&mut db, "WHERE name COLLATE BINARY = 'BOB'").is_empty());
+        assert_eq!(names(&mut db, "WHERE tag = 'y' COLLATE NOCASE"), ["Bob"]);
+        assert!(names(&mut db, "WHERE tag = 'y'").is_empty());
+        assert_eq!(
+            names(&mut db, "ORDER BY name DESC"),
+            ["carol", "Bob", "alice"]
+        );
+        assert_eq!(
+            names(&mut db, "ORDER BY tag COLLATE NOCASE"),
+            ["alice", "Bob", "carol"]
+        );
+        assert_eq!(names(&mut db, "ORDER BY tag"), ["Bob", "alice", "carol"]);
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT 'a ' = 'a' COLLATE RTRIM, 'A' < 'a' COLLATE NOCASE"]
+            )
+            .unwrap(),
+            ["1|0"]
+        );
+    }
+
+    #[test]
+    fn test_collated_unique_and_foreign_keys() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE tags (name TEXT COLLATE NOCASE PRIMARY KEY)",
+                "CREATE TABLE posts (tag TEXT REFERENCES tags(name))",
+                "INSERT INTO tags VALUES ('Rust')",
+                "INSERT INTO posts VALUES ('RUST'), ('rust')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut db, &["INSERT INTO posts VALUES ('go')"])
+                .unwrap_err()
+                .to_string(),
+            "FOREIGN KEY constraint failed"
+        );
+        assert!(run(&mut db, &["DELETE FROM tags"]).is_err());
+        // An index on a NOCASE column cannot answer a BINARY equality.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*) FROM tags WHERE name = 'rust' COLLATE BINARY"]
+            )
+            .unwrap(),
+            ["0"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM tags WHERE name = 'RUST'"]).unwrap(),
+            ["Rust"]
+        );
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE plain (s TEXT UNIQUE)",
+                "CREATE INDEX plain_s ON plain (s)",
+                "INSERT INTO plain VALUES ('a'), ('A')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT s FROM plain WHERE s = 'a' COLLATE NOCASE"]
+            )
+            .unwrap(),
+            ["a", "A"]
+        );
+    }
+
+    #[test]
+    fn test_where_glob() {
+        let mut db = nullable();
+        assert_eq!(ids(&mut db, "s GLOB '*an*'"), ["2"]);
+        assert_eq!(ids(&mut db, "s GLOB '?pple'"), ["1"]);
+        assert_eq!(ids(&mut db, "s GLOB '[a-c]*'"), ["1", "4"]);
+        assert_eq!(ids(&mut db, "s NOT GLOB '[!a]*'"), ["1"]);
+        // Unlike LIKE, GLOB ignores the collation and never ignores case.
+        assert_eq!(ids(&mut db, "s COLLATE NOCASE LIKE 'APPLE'"), ["1"]);
+        assert_eq!(
+            ids(&mut db, "s COLLATE NOCASE GLOB 'APPLE'"),
+            Vec::<String>::new()
+        );
+        for expr in ["NULL GLOB '*'", "'a' GLOB NULL", "NULL NOT GLOB 'a'"] {
+            let mut db = Database::new();
+            assert_eq!(
+                query(&mut db, &[&format!("SELECT {expr}")]).unwrap(),
+                ["NULL"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_and_or_short_circuit() {
+        let mut db = nullable();
+        // The right operand would fail to evaluate if it were reached.
+        let fails = "(SELECT 1 UNION ALL SELECT 2)";
+        assert_eq!(
+            ids(&mut db, &format!("id > 5 AND {fails}")),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            ids(&mut db, &format!("0 AND {fails}")),
+            Vec::<String>::new()
+        );
+        assert_eq!(ids(&mut db, &format!("1 OR {fails}")), ["1", "2", "3", "4"]);
+        assert!(query(&mut db, &[&format!("SELECT id FROM t WHERE 1 AND {fails}")]).is_err());
+    }
+
+    #[test]
+    fn test_where_unknown_column() {
+        let mut db = nullable();
+        let err = query(&mut db, &["SELECT id FROM t WHERE y = 1"]).unwrap_err();
+        assert_eq!(err, EvalError::NoSuchColumn("y".into()));
+    }
+
+    fn scores() -> Database {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE s (name TEXT, team TEXT, score INTEGER)",
+                "INSERT INTO s VALUES ('a', 'red', 3), ('b', 'blue', NULL), ('c', 'red', 1), \
+                 ('d', 'blue', 3), ('e', 'red', NULL), ('f', 'blue', 1)",
+            ],
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_order_by_multiple_keys() {
+        let mut db = scores();
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM s ORDER BY team, score DESC"]).unwrap(),
+            ["b", "d", "f", "e", "a", "c"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM s ORDER BY team DESC, score, name DESC"]
+            )
+            .unwrap(),
+            ["c", "a", "e", "f", "d", "b"]
+        );
+    }
+
+    #[test]
+    fn test_order_by_null_placement() {
+        let mut db = scores();
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM s ORDER BY score"]).unwrap(),
+            ["c", "f", "a", "d", "b", "e"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM s ORDER BY score DESC"]).unwrap(),
+            ["b", "e", "a", "d", "c", "f"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM s ORDER BY score NULLS FIRST"]).unwrap(),
+            ["b", "e", "c", "f", "a", "d"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM s ORDER BY score DESC NULLS LAST"]
+            )
+            .unwrap(),
+            ["a", "d", "c", "f", "b", "e"]
+        );
+    }
+
+    #[test]
+    fn test_order_by_is_stable() {
+        let mut db = scores();
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM s ORDER BY team"]).unwrap(),
+            ["b", "d", "f", "a", "c", "e"]
+        );
+    }
+
+    #[test]
+    fn test_order_by_alias_and_ordinal() {
+        let mut db = scores();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, -score AS neg FROM s WHERE score IS NOT NULL ORDER BY neg, 1"]
+            )
+            .unwrap(),
+            ["a|-3", "d|-3", "c|-1", "f|-1"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM s WHERE score > 1 ORDER BY 1 DESC"]
+            )
+            .unwrap(),
+            ["d", "a"]
+        );
+    }
+
+    #[test]
+    fn test_select_list_aliases() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, salary * 2 AS adjusted FROM employees \
+                   WHERE salary IS NOT NULL ORDER BY adjusted DESC, name"]
+            )
+            .unwrap(),
+            ["Ann|200", "Bob|160", "Cy|160", "Ed|120"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept, AVG(salary) AS avg_sal FROM employees \
+                   GROUP BY dept HAVING avg_sal > 70 ORDER BY dept"]
+            )
+            .unwrap(),
+            ["eng|90.0", "ops|80.0"]
+        );
+        // An alias refers to the select list's other aliases, through as
+        // many levels as there are, and so do ORDER BY and HAVING.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT salary + 1 AS a, a * 2 AS b, b - a AS c FROM employees \
+                   WHERE salary IS NOT NULL ORDER BY c, name LIMIT 2"
+                ]
+            )
+            .unwrap(),
+            ["61|122|61", "81|162|81"]
+        );
+        // A real column wins over an alias of the same name.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT salary * 2 AS name, name AS label FROM employees WHERE dept = 'law'"]
+            )
+            .unwrap(),
+            ["120|Ed"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT a AS b, b AS a FROM employees"]),
+            Err(EvalError::CircularAlias("a".into()))
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT total + 1 AS total FROM employees"]),
+            Err(EvalError::CircularAlias("total".into()))
+        );
+    }
+
+    #[test]
+    fn test_limit_and_offset() {
+        let mut db = scores();
+        let names = |db: &mut Database, clause: &str| {
+            query(db, &[&format!("SELECT name FROM s ORDER BY name {clause}")]).unwrap()
+        };
+        assert_eq!(names(&mut db, "LIMIT 2"), ["a", "b"]);
+        assert!(names(&mut db, "LIMIT 0").is_empty());
+        assert_eq!(names(&mut db, "LIMIT 2 OFFSET 0"), ["a", "b"]);
+        assert_eq!(names(&mut db, "LIMIT 2 OFFSET 3"), ["d", "e"]);
+        assert_eq!(names(&mut db, "LIMIT 3, 2"), ["d", "e"]);
+        assert_eq!(names(&mut db, "LIMIT 100"), ["a", "b", "c", "d", "e", "f"]);
+        assert!(names(&mut db, "LIMIT 2 OFFSET 10").is_empty());
+        assert_eq!(names(&mut db, "OFFSET 4"), ["e", "f"]);
+        assert_eq!(names(&mut db, "LIMIT -1 OFFSET 5"), ["f"]);
+        assert_eq!(names(&mut db, "LIMIT 1 + 1"), ["a", "b"]);
+        assert!(query(&mut db, &["SELECT name FROM s LIMIT 'x'"]).is_err());
+    }
+
+    #[test]
+    fn test_select_distinct() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE e (name TEXT, city TEXT, dept TEXT)",
+                "INSERT INTO e VALUES ('a', 'rome', 'x'), ('b', 'oslo', 'y'), ('c', 'rome', 'x'), \
+                 ('d', NULL, 'y'), ('e', NULL, NULL), ('f', 'rome', 'y'), ('g', NULL, NULL)",
+            ],
+        )
+        .unwrap();
+        // First occurrences in input order; no sort is imposed.
+        assert_eq!(
+            query(&mut db, &["SELECT DISTINCT city FROM e"]).unwrap(),
+            ["rome", "oslo", "NULL"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT DISTINCT city, dept FROM e"]).unwrap(),
+            ["rome|x", "oslo|y", "NULL|y", "NULL|NULL", "rome|y"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT DISTINCT city FROM e WHERE city IS NULL"]).unwrap(),
+            ["NULL"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT DISTINCT * FROM e WHERE name < 'c'"]).unwrap(),
+            ["a|rome|x", "b|oslo|y"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT DISTINCT dept AS d FROM e ORDER BY d DESC"]
+            )
+            .unwrap(),
+            ["NULL", "y", "x"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT DISTINCT city FROM e ORDER BY 1 LIMIT 2"]).unwrap(),
+            ["oslo", "rome"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT DISTINCT city FROM e ORDER BY name"]).unwrap_err(),
+            EvalError::DistinctOrderByNotSelected
+        );
+    }
+
+    #[test]
+    fn test_distinct_treats_equal_numbers_as_duplicates() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE n (v)",
+                "INSERT INTO n VALUES (1), (1.0), (2), (NULL), ('1'), (NULL)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT DISTINCT v FROM n"]).unwrap(),
+            ["1", "2", "NULL", "1"]
+        );
+    }
+
+    fn shop() -> Database {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE a (id INTEGER, name TEXT)",
+                "CREATE TABLE b (id INTEGER, a_id INTEGER, item TEXT)",
+                "CREATE TABLE c (id INTEGER, label TEXT)",
+                "CREATE TABLE empty (id INTEGER)",
+                "INSERT INTO a VALUES (1, 'ann'), (2, 'bob'), (3, 'cy')",
+                "INSERT INTO b VALUES (10, 1, 'pen'), (11, 1, 'ink'), (12, 3, 'cup'), (13, 9, 'hat')",
+                "INSERT INTO c VALUES (10, 'x'), (12, 'y')",
+            ],
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_inner_join() {
+        let mut db = shop();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT a.name, b.item FROM a INNER JOIN b ON a.id = b.a_id"]
+            )
+            .unwrap(),
+            ["ann|pen", "ann|ink", "cy|cup"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT * FROM a JOIN b ON a.id = b.a_id WHERE b.id > 10"]
+            )
+            .unwrap(),
+            ["1|ann|11|1|ink", "3|cy|12|3|cup"]
+        );
+    }
+
+    #[test]
+    fn test_chained_joins() {
+        let mut db = shop();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, item, label FROM a JOIN b ON a.id = b.a_id JOIN c ON c.id = b.id"]
+            )
+            .unwrap(),
+            ["ann|pen|x", "cy|cup|y"]
+        );
+    }
+
+    #[test]
+    fn test_join_with_empty_table() {
+        let mut db = shop();
+        assert!(
+            query(&mut db, &["SELECT * FROM a JOIN empty ON 1"])
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            query(&mut db, &["SELECT * FROM empty JOIN a ON 1"])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_join_ambiguous_column() {
+        let mut db = shop();
+        let err = query(&mut db, &["SELECT id FROM a JOIN b ON a.id = b.a_id"]).unwrap_err();
+        assert_eq!(err.to_string(), "ambiguous column name: id");
+        let err = query(&mut db, &["SELECT a.id FROM a JOIN b ON id = a_id"]).unwrap_err();
+        assert_eq!(err, EvalError::AmbiguousColumn("id".into()));
+    }
+
+    #[test]
+    fn test_left_join() {
+        let mut db = shop();
+        // Several matches, one match and no match per left row.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT a.name, b.item FROM a LEFT JOIN b ON a.id = b.a_id"]
+            )
+            .unwrap(),
+            ["ann|pen", "ann|ink", "bob|NULL", "cy|cup"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM a LEFT OUTER JOIN empty ON 1"]).unwrap(),
+            ["1|ann|NULL", "2|bob|NULL", "3|cy|NULL"]
+        );
+    }
+
+    #[test]
+    fn test_left_join_filtered_on_right_is_inner_join() {
+        let mut db = shop();
+        let left = query(
+            &mut db,
+            &["SELECT a.name, b.item FROM a LEFT JOIN b ON a.id = b.a_id WHERE b.id IS NOT NULL"],
+        )
+        .unwrap();
+        let inner = query(
+            &mut db,
+            &["SELECT a.name, b.item FROM a JOIN b ON a.id = b.a_id"],
+        )
+        .unwrap();
+        assert_eq!(left, inner);
+    }
+
+    #[test]
+    fn test_right_join() {
+        let mut db = shop();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT a.name, b.item FROM a RIGHT JOIN b ON a.id = b.a_id"]
+            )
+            .unwrap(),
+            ["ann|pen", "ann|ink", "cy|cup", "NULL|hat"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM empty RIGHT JOIN c ON 1"]).unwrap(),
+            ["NULL|10|x", "NULL|12|y"]
+        );
+    }
+
+    #[test]
+    fn test_chained_left_and_inner_joins() {
+        let mut db = shop();
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT a.name, b.item, c.label FROM a LEFT JOIN b ON a.id = b.a_id \
+                   LEFT JOIN c ON c.id = b.id"
+                ]
+            )
+            .unwrap(),
+            ["ann|pen|x", "ann|ink|NULL", "bob|NULL|NULL", "cy|cup|y"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT a.name, c.label FROM a LEFT JOIN b ON a.id = b.a_id \
+                   JOIN c ON c.id = b.id"
+                ]
+            )
+            .unwrap(),
+            ["ann|x", "cy|y"]
+        );
+    }
+
+    #[test]
+    fn test_full_outer_join() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE l (id INTEGER, v TEXT)",
+                "CREATE TABLE r (id INTEGER, w TEXT)",
+                "CREATE TABLE none1 (id INTEGER)",
+                "CREATE TABLE none2 (id INTEGER)",
+                "INSERT INTO l VALUES (1, 'a'), (2, 'b'), (3, 'c')",
+                "INSERT INTO r VALUES (4, 'z'), (2, 'y'), (5, 'x'), (1, 'w')",
+            ],
+        )
+        .unwrap();
+        // Matched rows in left order, then unmatched left, then unmatched right.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT * FROM l FULL OUTER JOIN r ON l.id = r.id"]
+            )
+            .unwrap(),
+            [
+                "1|a|1|w",
+                "2|b|2|y",
+                "3|c|NULL|NULL",
+                "NULL|NULL|4|z",
+                "NULL|NULL|5|x"
+            ]
+        );
+        // Every row matches.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT l.v, r.w FROM l FULL JOIN r ON l.id = r.id WHERE l.id < 3 AND r.id < 3"]
+            )
+            .unwrap(),
+            ["a|w", "b|y"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT l.id, x.id FROM l FULL JOIN l AS x ON l.id = x.id"]
+            )
+            .unwrap(),
+            ["1|1", "2|2", "3|3"]
+        );
+        // No rows match.
+        assert_eq!(
+            query(&mut db, &["SELECT l.id, r.id FROM l FULL JOIN r ON 0"]).unwrap(),
+            [
+                "1|NULL", "2|NULL", "3|NULL", "NULL|4", "NULL|2", "NULL|5", "NULL|1"
+            ]
+        );
+        assert!(
+            query(&mut db, &["SELECT * FROM none1 FULL JOIN none2 ON 1"])
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM none1 FULL JOIN l ON 1"]).unwrap(),
+            ["NULL|1|a", "NULL|2|b", "NULL|3|c"]
+        );
+    }
+
+    fn sized(sizes: &[(&str, usize)]) -> Database {
+        let mut db = Database::new();
+        for (name, size) in sizes {
+            run(&mut db, &[&format!("CREATE TABLE {name} (v INTEGER)")]).unwrap();
+            for i in 1..=*size {
+                run(&mut db, &[&format!("INSERT INTO {name} VALUES ({i})")]).unwrap();
+            }
+        }
+        db
+    }
+
+    #[test]
+    fn test_hash_join_matches_nested_loop() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE l (k INTEGER, v INTEGER)",
+                "CREATE TABLE r (k INTEGER, w INTEGER)",
+            ],
+        )
+        .unwrap();
+        for i in 0..1000 {
+            // Every tenth key is NULL, which matches nothing.
+            let key = |k: i64| match k % 10 {
+                0 => Value::Null,
+                _ => Value::Integer(k % 300),
+            };
+            db.insert_row("l", vec![key(i), Value::Integer(i)]).unwrap();
+            db.insert_row("r", vec![key(i * 7), Value::Integer(i)])
+                .unwrap();
+        }
+        let plan = |db: &mut Database, sql: &str| {
+            query(db, &[&format!("EXPLAIN {sql}")]).unwrap().join("\n")
+        };
+        // The same condition, but with no column alone on each side.
+        let hashed = "SELECT l.v, r.w FROM l JOIN r ON l.k = r.k";
+        let nested = "SELECT l.v, r.w FROM l JOIN r ON l.k + 0 = r.k";
+        assert!(plan(&mut db, hashed).contains("HashJoin(JOIN ON l.k = r.k, build right)"));
+        assert!(plan(&mut db, nested).contains("NestedLoopJoin"));
+        let hash_rows = query(&mut db, &[hashed]).unwrap();
+        assert_eq!(hash_rows, query(&mut db, &[nested]).unwrap());
+        assert_eq!(hash_rows.len(), 3010);
+
+        // Outer joins and the rest of the condition, on fewer rows.
+        for kind in ["LEFT", "RIGHT", "FULL"] {
+            for residual in ["", " AND l.v % 2 = r.w % 2"] {
+                let hashed = format!(
+                    "SELECT l.v, r.w FROM (SELECT * FROM l WHERE v < 200) AS l \
+                     {kind} JOIN (SELECT * FROM r WHERE w < 100) AS r ON l.k = r.k{residual}"
+                );
+                let nested = hashed.replace("l.k = r.k", "l.k + 0 = r.k");
+                assert!(plan(&mut db, &hashed).contains("HashJoin"));
+                assert_eq!(
+                    query(&mut db, &[&hashed]).unwrap(),
+                    query(&mut db, &[&nested]).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash_join_smaller_side_and_collation() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE names (name TEXT COLLATE NOCASE, n INTEGER)",
+                "CREATE TABLE tags (name TEXT, tag TEXT)",
+                "INSERT INTO names VALUES ('Ann', 1), ('bob', 2)",
+                "INSERT INTO tags VALUES ('ann', 'x'), ('BOB', 'y'), ('cy', 'z'), \
+                 ('ANN', 'w'), (NULL, 'v')",
+            ],
+        )
+        .unwrap();
+        let sql = "SELECT tags.tag, names.n FROM tags JOIN names ON tags.name = names.name";
+        // The smaller table, written on the right, is put on the left and
+        // built.
+        let plan = query(&mut db, &[&format!("EXPLAIN {sql}")]).unwrap();
+        assert!(plan[2].contains("HashJoin(JOIN ON names.name = tags.name, build left)"));
+        // Texts match under the column's collation; the rows come in the
+        // order of the probe side.
+        assert_eq!(query(&mut db, &[sql]).unwrap(), ["x|1", "y|2", "w|1"]);
+    }
+
+    #[test]
+    fn test_sort_merge_join_matches_hash_join() {
+        let mut db = Database::new();
+        for table in ["l", "r"] {
+            run(
+                &mut db,
+                &[&format!("CREATE TABLE {table} (k INTEGER, v INTEGER)")],
+            )
+            .unwrap();
+        }
+        let row = |table: &str, i: i64| {
+            let per_key = if table == "l" { 2 } else { 3 };
+            match i % 100 {
+                0 => vec![Value::Null, Value::Integer(i)],
+                _ => vec![Value::Integer(i / per_key), Value::Integer(i)],
+            }
+        };
+        for i in 0..10_000 {
+            for table in ["l", "r"] {
+                db.insert_row(table, row(table, i * 7919 % 10_000)).unwrap();
+            }
+        }
+        let plan = |db: &mut Database, sql: &str| {
+            query(db, &[&format!("EXPLAIN {sql}")]).unwrap().join("\n")
+        };
+        let sorted = |db: &mut Database, sql: &str| {
+            let mut rows = query(db, &[sql]).unwrap();
+            rows.sort();
+            rows
+        };
+        // Inputs sorted by the query are merged, and not sorted again.
+        for residual in ["", " AND l.v % 2 = r.v % 2"] {
+            let merged = format!(
+                "SELECT l.v, r.v FROM (SELECT * FROM l ORDER BY k) AS l \
+                 JOIN (SELECT * FROM r ORDER BY k) AS r ON l.k = r.k{residual}"
+            );
+            let hashed = format!("SELECT l.v, r.v FROM l JOIN r ON l.k = r.k{residual}");
+            let explained = plan(&mut db, &merged);
+            assert!(explained.contains("SortMergeJoin(JOIN ON l.k = r.k"));
+            assert_eq!(explained.matches("Sort(").count(), 2);
+            assert!(plan(&mut db, &hashed).contains("HashJoin"));
+            let rows = sorted(&mut db, &merged);
+            assert_eq!(rows, sorted(&mut db, &hashed));
+            if residual.is_empty() {
+                assert_eq!(rows.len(), 19_633);
+            }
+        }
+        // Stored rows are in no known order, even inserted in key order:
+        // a merge would sort them, and hashing them is cheaper.
+        run(&mut db, &["CREATE TABLE ordered (k INTEGER, v INTEGER)"]).unwrap();
+        for i in 0..10_000 {
+            db.insert_row("ordered", row("l", i)).unwrap();
+        }
+        let explained = plan(
+            &mut db,
+            "SELECT ordered.v, r.v FROM ordered \
+             JOIN (SELECT * FROM r ORDER BY k) AS r ON ordered.k = r.k",
+        );
+        assert!(explained.contains("HashJoin"));
+
+        // Outer joins are merged too, their unmatched rows padded.
+        for kind in ["LEFT", "RIGHT", "FULL"] {
+            for residual in ["", " AND l.v % 2 = r.v % 2"] {
+                let merged = format!(
+                    "SELECT l.v, r.v FROM (SELECT * FROM l ORDER BY k) AS l \
+                     {kind} JOIN (SELECT * FROM r ORDER BY k) AS r ON l.k = r.k{residual}"
+                );
+                let hashed = format!("SELECT l.v, r.v FROM l {kind} JOIN r ON l.k = r.k{residual}");
+                assert!(plan(&mut db, &merged).contains("SortMergeJoin"), "{merged}");
+                assert!(plan(&mut db, &hashed).contains("HashJoin"), "{hashed}");
+                assert_eq!(
+                    sorted(&mut db, &merged),
+                    sorted(&mut db, &hashed),
+                    "{merged}"
+                );
+            }
+        }
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE a (k INTEGER, s TEXT)",
+                "CREATE TABLE b (k INTEGER, n INTEGER)",
+                "INSERT INTO a VALUES (1, 'x'), (2, 'y'), (NULL, 'z'), (4, 'w')",
+                "INSERT INTO b VALUES (1, 10), (1, 11), (3, 30), (4, 40), (5, 50), (NULL, 60)",
+            ],
+        )
+        .unwrap();
+        let merged = |kind: &str| {
+            format!(
+                "SELECT * FROM (SELECT * FROM a ORDER BY k) AS a \
+                 {kind} JOIN (SELECT * FROM b ORDER BY k) AS b ON a.k = b.k"
+            )
+        };
+        for kind in ["LEFT", "RIGHT", "FULL"] {
+            assert!(
+                plan(&mut db, &merged(kind)).contains("SortMergeJoin"),
+                "{kind}"
+            );
+        }
+        // Rows with a NULL key match nothing, and come first if preserved.
+        assert_eq!(
+            query(&mut db, &[&merged("LEFT")]).unwrap(),
+            [
+                "NULL|z|NULL|NULL",
+                "1|x|1|10",
+                "1|x|1|11",
+                "2|y|NULL|NULL",
+                "4|w|4|40"
+            ]
+        );
+        assert_eq!(
+            query(&mut db, &[&merged("RIGHT")]).unwrap(),
+            [
+                "NULL|NULL|NULL|60",
+                "1|x|1|10",
+                "1|x|1|11",
+                "NULL|NULL|3|30",
+                "4|w|4|40",
+                "NULL|NULL|5|50"
+            ]
+        );
+        assert_eq!(
+            query(&mut db, &[&merged("FULL")]).unwrap(),
+            [
+                "NULL|z|NULL|NULL",
+                "NULL|NULL|NULL|60",
+                "1|x|1|10",
+                "1|x|1|11",
+                "2|y|NULL|NULL",
+                "NULL|NULL|3|30",
+                "4|w|4|40",
+                "NULL|NULL|5|50"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_index_nested_loop_join() {
+        // The same customers twice, only one table with an index.
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE customers (id INTEGER PRIMARY KEY, name TEXT)",
+                "CREATE TABLE unindexed (id INTEGER, name TEXT)",
+                "CREATE TABLE orders (id INTEGER, customer_id INTEGER)",
+            ],
+        )
+        .unwrap();
+        for i in 0..10_000 {
+            let id = i * 7919 % 10_000;
+            let row = vec![Value::Integer(id), Value::Text(format!("c{id}"))];
+            db.insert_row("customers", row.clone()).unwrap();
+            db.insert_row("unindexed", row).unwrap();
+        }
+        for i in 0..1000 {
+            // Some orders have no customer, or an unknown one.
+            let customer = match i % 50 {
+                0 => Value::Null,
+                _ => Value::Integer(i * 37 % 12_000),
+            };
+            db.insert_row("orders", vec![Value::Integer(i), customer])
+                .unwrap();
+        }
+        let plan = |db: &mut Database, sql: &str| {
+            query(db, &[&format!("EXPLAIN {sql}")]).unwrap().join("\n")
+        };
+        // The rows of a query in order.
+        let sorted = |db: &mut Database, sql: &str| {
+            let mut rows = query(db, &[sql]).unwrap();
+            rows.sort();
+            rows
+        };
+        let indexed = "SELECT orders.id, customers.name FROM orders \
+                       JOIN customers ON orders.customer_id = customers.id";
+        let scanned = indexed.replace("customers", "unindexed");
+        assert!(plan(&mut db, indexed).contains(
+            "IndexNestedLoopJoin(JOIN ON orders.customer_id = customers.id, \
+             customers USING sqlite_autoindex_customers_1)"
+        ));
+        assert!(plan(&mut db, &scanned).contains("HashJoin"));
+        let index_rows = sorted(&mut db, indexed);
+        assert_eq!(index_rows, sorted(&mut db, &scanned));
+        assert_eq!(index_rows.len(), 821);
+
+        // Outer joins look up the table whose rows are not preserved, and
+        // a filter on the table is checked on the rows found.
+        for sql in [
+            "SELECT orders.id, customers.name FROM orders \
+             LEFT JOIN customers ON orders.customer_id = customers.id",
+            "SELECT orders.id, customers.name FROM customers \
+             RIGHT JOIN orders ON orders.customer_id = customers.id",
+            "SELECT orders.id, customers.name FROM orders \
+             JOIN customers ON orders.customer_id = customers.id \
+             WHERE customers.name LIKE 'c1%' AND orders.id % 3 = customers.id % 3",
+        ] {
+            assert!(plan(&mut db, sql).contains("IndexNestedLoopJoin"));
+            let scanned = sql.replace("customers", "unindexed");
+            assert_eq!(sorted(&mut db, sql), sorted(&mut db, &scanned));
+        }
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*), COUNT(customers.id) FROM orders \
+                   LEFT JOIN customers ON orders.customer_id = customers.id"]
+            )
+            .unwrap(),
+            ["1000|821"]
+        );
+    }
+
+    #[test]
+    fn test_cross_join() {
+        let mut db = sized(&[("three", 3), ("four", 4), ("two", 2), ("zero", 0)]);
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM three CROSS JOIN four"])
+                .unwrap()
+                .len(),
+            12
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM three, four"])
+                .unwrap()
+                .len(),
+            12
+        );
+        assert!(
+            query(&mut db, &["SELECT * FROM three, zero"])
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            query(&mut db, &["SELECT * FROM zero CROSS JOIN three"])
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM two, three WHERE two.v = three.v"]).unwrap(),
+            ["1|1", "2|2"]
+        );
+    }
+
+    #[test]
+    fn test_comma_join_matches_cross_join() {
+        let mut db = sized(&[("a", 2), ("b", 3), ("c", 2)]);
+        let mut comma = query(&mut db, &["SELECT * FROM a, b, c"]).unwrap();
+        let cross = query(&mut db, &["SELECT * FROM a CROSS JOIN b CROSS JOIN c"]).unwrap();
+        assert_eq!(comma.len(), 12);
+        // As in SQLite, a CROSS JOIN joins its tables in the order written,
+        // even when another order is cheaper; a comma join may reorder
+        // them, so only its rows are the same.
+        assert_eq!(&cross[..3], ["1|1|1", "1|1|2", "1|2|1"]);
+        let mut sorted = cross.clone();
+        sorted.sort();
+        comma.sort();
+        assert_eq!(comma, sorted);
+        let plan = query(
+            &mut db,
+            &["EXPLAIN SELECT * FROM b CROSS JOIN a WHERE a.v = b.v"],
+        )
+        .unwrap()
+        .join("\n");
+        assert!(plan.contains("Join(CROSS JOIN ON"), "{plan}");
+        assert!(
+            plan.find("TableScan(b)") < plan.find("TableScan(a)"),
+            "{plan}"
+        );
+    }
+
+    #[test]
+    fn test_cross_join_streams() {
+        let db = sized(&[("big", 1000)]);
+        let stmt = parse_statement("SELECT * FROM big, big AS b2, big AS b3").unwrap();
+        let Statement::Select(select) = stmt else {
+            unreachable!()
+        };
+        let plan = plan_select(&db, &select).unwrap();
+        // A billion rows: only feasible if nothing forces the whole product.
+        let first: Vec<_> = execute_plan(Context::new(&db), &plan)
+            .take(3)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(first.len(), 3);
+    }
+
+    fn departments() -> Database {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE emp (id INTEGER, name TEXT, dept_id INTEGER)",
+                "CREATE TABLE dept (dept_id INTEGER, dname TEXT)",
+                "INSERT INTO emp VALUES (1, 'Ann', 10), (2, 'Bob', 20), (3, 'Cy', 30)",
+                "INSERT INTO dept VALUES (10, 'Eng'), (20, 'Ops'), (40, 'Law')",
+            ],
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_join_using() {
+        let mut db = departments();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM emp JOIN dept USING (dept_id)"]).unwrap(),
+            ["1|Ann|10|Eng", "2|Bob|20|Ops"]
+        );
+        // The shared column resolves unqualified, and through either table.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept_id, emp.dept_id, dept.dept_id, dept.* FROM emp JOIN dept USING (dept_id) WHERE dept_id = 10"]
+            )
+            .unwrap(),
+            ["10|10|10|10|Eng"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT * FROM emp LEFT JOIN dept USING (dept_id)"]
+            )
+            .unwrap(),
+            ["1|Ann|10|Eng", "2|Bob|20|Ops", "3|Cy|30|NULL"]
+        );
+        // Only the right side is preserved, so its copy is the one kept.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept_id, name FROM emp RIGHT JOIN dept USING (dept_id)"]
+            )
+            .unwrap(),
+            ["10|Ann", "20|Bob", "40|NULL"]
+        );
+        assert_eq!(
+            run(&mut db, &["SELECT * FROM emp JOIN dept USING (name)"])
+                .unwrap_err()
+                .to_string(),
+            "cannot join using column name - column not present in both tables"
+        );
+    }
+
+    #[test]
+    fn test_natural_join() {
+        let mut db = departments();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM emp NATURAL JOIN dept"]).unwrap(),
+            ["1|Ann|10|Eng", "2|Bob|20|Ops"]
+        );
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE p (a INTEGER, b INTEGER, c INTEGER, x TEXT)",
+                "CREATE TABLE q (c INTEGER, y TEXT, b INTEGER, a INTEGER)",
+                "INSERT INTO p VALUES (1, 1, 1, 'p1'), (1, 2, 3, 'p2'), (2, 2, 2, 'p3')",
+                "INSERT INTO q VALUES (1, 'q1', 1, 1), (3, 'q2', 2, 1), (2, 'q3', 1, 2)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM p NATURAL JOIN q"]).unwrap(),
+            ["1|1|1|p1|q1", "1|2|3|p2|q2"]
+        );
+        assert_eq!(
+            run(&mut db, &["SELECT * FROM p NATURAL JOIN dept"])
+                .unwrap_err()
+                .to_string(),
+            "NATURAL JOIN has no common columns"
+        );
+    }
+
+    #[test]
+    fn test_case() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, CASE WHEN salary > 90 THEN 'high' WHEN salary > 70 THEN 'medium' \
+                   ELSE 'low' END FROM employees"
+                ]
+            )
+            .unwrap(),
+            ["Ann|high", "Bob|medium", "Cy|medium", "Di|low", "Ed|low"]
+        );
+        // Without ELSE, a row no clause matches gives NULL; so does a NULL
+        // operand in the simple form, even against a NULL value.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, CASE salary WHEN 80 THEN 'eighty' WHEN NULL THEN 'null' END, \
+                   CASE WHEN bonus > 2 THEN 'big' END FROM employees"
+                ]
+            )
+            .unwrap(),
+            [
+                "Ann|NULL|NULL",
+                "Bob|eighty|NULL",
+                "Cy|eighty|NULL",
+                "Di|NULL|big",
+                "Ed|NULL|NULL"
+            ]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, CASE dept WHEN 'eng' THEN CASE WHEN salary > 90 THEN 'lead' \
+                   ELSE 'dev' END ELSE dept END FROM employees WHERE salary IS NOT NULL"
+                ]
+            )
+            .unwrap(),
+            ["Ann|lead", "Bob|dev", "Cy|ops", "Ed|law"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees \
+                   ORDER BY CASE dept WHEN 'law' THEN 0 WHEN 'ops' THEN 1 ELSE 2 END, name"]
+            )
+            .unwrap(),
+            ["Ed", "Cy", "Di", "Ann", "Bob"]
+        );
+        // Later branches are not evaluated once one matches.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT CASE WHEN 1 THEN 'first' WHEN 1 / 0 THEN 'x' END"]
+            )
+            .unwrap(),
+            ["first"]
+        );
+    }
+
+    fn employees() -> Database {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE employees (name TEXT, dept TEXT, salary INTEGER, bonus REAL)",
+                "INSERT INTO employees VALUES \
+                 ('Ann', 'eng', 100, NULL), ('Bob', 'eng', 80, 1.5), \
+                 ('Cy', 'ops', 80, NULL), ('Di', 'ops', NULL, 2.5), ('Ed', 'law', 60, NULL)",
+                "CREATE TABLE empty (x INTEGER)",
+            ],
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*), COUNT(salary), SUM(salary), AVG(salary), MIN(salary), MAX(salary) FROM employees"]
+            )
+            .unwrap(),
+            ["5|4|320|80.0|60|100"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT MIN(name), MAX(name), SUM(bonus), AVG(bonus), count(bonus) FROM employees"]
+            )
+            .unwrap(),
+            ["Ann|Ed|4.0|2.0|2"]
+        );
+        // Aggregates combine with other expressions, and with WHERE.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT MAX(salary) - MIN(salary), COUNT(*) * 10 FROM employees WHERE dept = 'eng'"]
+            )
+            .unwrap(),
+            ["20|20"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT COUNT(*), SUM(1 + 2)"]).unwrap(),
+            ["1|3"]
+        );
+    }
+
+    #[test]
+    fn test_aggregates_over_empty_and_null_input() {
+        let mut db = employees();
+        let all = "COUNT(*), COUNT(x), SUM(x), AVG(x), MIN(x), MAX(x)";
+        assert_eq!(
+            query(&mut db, &[&format!("SELECT {all} FROM empty")]).unwrap(),
+            ["0|0|NULL|NULL|NULL|NULL"]
+        );
+        run(&mut db, &["INSERT INTO empty VALUES (NULL), (NULL)"]).unwrap();
+        assert_eq!(
+            query(&mut db, &[&format!("SELECT {all} FROM empty")]).unwrap(),
+            ["2|0|NULL|NULL|NULL|NULL"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT SUM(salary) FROM employees WHERE salary > 1000"]
+            )
+            .unwrap(),
+            ["NULL"]
+        );
+    }
+
+    #[test]
+    fn test_distinct_aggregates() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(DISTINCT salary), SUM(DISTINCT salary), AVG(DISTINCT salary), COUNT(DISTINCT dept) FROM employees"]
+            )
+            .unwrap(),
+            ["3|240|80.0|3"]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_errors() {
+        let mut db = employees();
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut db, "SELECT name, COUNT(*) FROM employees"),
+            "column \"name\" must appear in the GROUP BY clause or be used in an aggregate function"
+        );
+        assert_eq!(
+            error(&mut db, "SELECT COUNT(*) FROM employees ORDER BY salary"),
+            "column \"salary\" must appear in the GROUP BY clause or be used in an aggregate function"
+        );
+        assert_eq!(
+            error(&mut db, "SELECT name FROM employees WHERE COUNT(*) > 1"),
+            "misuse of aggregate: COUNT()"
+        );
+        assert_eq!(
+            error(&mut db, "SELECT SUM(MAX(salary)) FROM employees"),
+            "misuse of aggregate: MAX()"
+        );
+        assert_eq!(
+            error(&mut db, "SELECT SUM(nope) FROM employees"),
+            "no such column: nope"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_filter() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*) FILTER (WHERE dept = 'eng'), COUNT(*), \
+                   SUM(salary) FILTER (WHERE bonus IS NULL), \
+                   COUNT(*) FILTER (WHERE salary >= 80), COUNT(*) FILTER (WHERE salary < 80) FROM employees"]
+            )
+            .unwrap(),
+            ["2|5|240|3|1"]
+        );
+        // A filter no row passes leaves the aggregate's empty result.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT COUNT(*) FILTER (WHERE FALSE), SUM(salary) FILTER (WHERE dept = 'hr'), \
+                   AVG(salary) FILTER (WHERE NULL) FROM employees"
+                ]
+            )
+            .unwrap(),
+            ["0|NULL|NULL"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept, COUNT(*) FILTER (WHERE bonus IS NOT NULL), \
+                   MAX(name) FILTER (WHERE salary < 100) FROM employees GROUP BY dept"]
+            )
+            .unwrap(),
+            ["eng|1|Bob", "law|0|Ed", "ops|1|Cy"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, SUM(salary) FILTER (WHERE name <> 'Ann') OVER (ORDER BY name), \
+                   COUNT(*) FILTER (WHERE salary IS NULL) OVER () FROM employees"
+                ]
+            )
+            .unwrap(),
+            ["Ann|NULL|1", "Bob|80|1", "Cy|160|1", "Di|160|1", "Ed|220|1"]
+        );
+        assert_eq!(
+            run(
+                &mut db,
+                &["SELECT COUNT(*) FILTER (WHERE MAX(salary) > 1) FROM employees"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "misuse of aggregate: MAX()"
+        );
+    }
+
+    #[test]
+    fn test_group_concat() {
+        let mut db = employees();
+        // Values come in input order without an ORDER BY, and their NULLs
+        // are skipped.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept, GROUP_CONCAT(name), STRING_AGG(salary, ', ') FROM employees GROUP BY dept"]
+            )
+            .unwrap(),
+            ["eng|Ann,Bob|100, 80", "law|Ed|60", "ops|Cy,Di|80"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT GROUP_CONCAT(name, '' ORDER BY salary DESC, name DESC), \
+                   GROUP_CONCAT(bonus, ' ' ORDER BY bonus DESC) FROM employees"
+                ]
+            )
+            .unwrap(),
+            ["DiAnnCyBobEd|2.5 1.5"]
+        );
+        // The separator may also follow the sort keys.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT dept, GROUP_CONCAT(name ORDER BY name DESC, ', ') FROM employees \
+                   GROUP BY dept"
+                ]
+            )
+            .unwrap(),
+            ["eng|Bob, Ann", "law|Ed", "ops|Di, Cy"]
+        );
+        // An empty group, or one whose values are all NULL, gives NULL.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT GROUP_CONCAT(name), GROUP_CONCAT(bonus) FILTER (WHERE dept = 'law') \
+                   FROM employees WHERE salary > 90"
+                ]
+            )
+            .unwrap(),
+            ["Ann|NULL"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT GROUP_CONCAT(x) FROM empty"]).unwrap(),
+            ["NULL"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT GROUP_CONCAT(DISTINCT dept, ',' ORDER BY dept), \
+                   GROUP_CONCAT(DISTINCT salary, '+') FROM employees"]
+            )
+            .unwrap(),
+            ["eng,law,ops|100+80+60"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, GROUP_CONCAT(name, '/') OVER (PARTITION BY dept ORDER BY name) \
+                   FROM employees ORDER BY name"
+                ]
+            )
+            .unwrap(),
+            ["Ann|Ann", "Bob|Ann/Bob", "Cy|Cy", "Di|Cy/Di", "Ed|Ed"]
+        );
+        assert_eq!(
+            run(&mut db, &["SELECT GROUP_CONCAT(name, dept) FROM employees"])
+                .unwrap_err()
+                .to_string(),
+            "a GROUP_CONCAT() delimiter that is not constant is not supported"
+        );
+    }
+
+    #[test]
+    fn test_variance_and_stddev() {
+        let mut db = employees();
+        // The salaries 100, 80, 80 and 60 have mean 80 and squared
+        // deviations summing to 800.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT ROUND(VAR_POP(salary), 6), ROUND(VAR_SAMP(salary), 6), \
+                   ROUND(VARIANCE(salary), 6), ROUND(STDDEV_POP(salary), 6), \
+                   ROUND(STDDEV_SAMP(salary), 6), ROUND(STDDEV(salary), 6) FROM employees"
+                ]
+            )
+            .unwrap(),
+            ["200.0|266.666667|266.666667|14.142136|16.329932|16.329932"]
+        );
+        // One value has no sample variance, and none has no variance at all.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT dept, VAR_SAMP(salary), VAR_POP(salary), STDDEV_POP(salary), \
+                   VAR_POP(bonus) FROM employees GROUP BY dept"
+                ]
+            )
+            .unwrap(),
+            [
+                "eng|200.0|100.0|10.0|0.0",
+                "law|NULL|0.0|0.0|NULL",
+                "ops|NULL|0.0|0.0|0.0"
+            ]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT STDDEV(x), VAR_POP(x) FROM empty"]).unwrap(),
+            ["NULL|NULL"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, VAR_POP(salary) OVER (ORDER BY name ROWS 1 PRECEDING) \
+                   FROM employees ORDER BY name"
+                ]
+            )
+            .unwrap(),
+            ["Ann|0.0", "Bob|100.0", "Cy|0.0", "Di|0.0", "Ed|0.0"]
+        );
+    }
+
+    #[test]
+    fn test_variance_is_numerically_stable() {
+        // Values with a large common offset, whose squares differ in digits
+        // a double does not hold, against a two-pass computation.
+        let values = [4.0, 7.0, 13.0, 16.0].map(|x: f64| 1e9 + x);
+        let mean = values.iter().sum::<f64>() / 4.0;
+        let expected = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 3.0;
+        assert_eq!(expected, 30.0);
+        let mut db = Database::new();
+        run(&mut db, &["CREATE TABLE t (x REAL)"]).unwrap();
+        for x in values {
+            run(&mut db, &[&format!("INSERT INTO t VALUES ({x})")]).unwrap();
+        }
+        let rows = query(&mut db, &["SELECT VAR_SAMP(x), STDDEV_SAMP(x) FROM t"]).unwrap();
+        let [variance, stddev]: [f64; 2] = rows[0]
+            .split('|')
+            .map(|v| v.parse().unwrap())
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap();
+        assert!((variance - expected).abs() < 1e-6, "{variance}");
+        assert!((stddev - expected.sqrt()).abs() < 1e-6, "{stddev}");
+    }
+
+    #[test]
+    fn test_group_by() {
+        let mut db = employees();
+        // Groups come out in key order.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept, COUNT(*), AVG(salary) FROM employees GROUP BY dept"]
+            )
+            .unwrap(),
+            ["eng|2|90.0", "law|1|60.0", "ops|2|80.0"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*) AS n, employees.dept FROM employees GROUP BY 2 ORDER BY n DESC, dept"]
+            )
+            .unwrap(),
+            ["2|eng", "2|ops", "1|law"]
+        );
+        run(
+            &mut db,
+            &["INSERT INTO employees VALUES ('Fay', 'eng', 80, NULL)"],
+        )
+        .unwrap();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept, salary, COUNT(*) FROM employees GROUP BY dept, salary"]
+            )
+            .unwrap(),
+            [
+                "eng|80|2",
+                "eng|100|1",
+                "law|60|1",
+                "ops|NULL|1",
+                "ops|80|1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_by_expression() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT salary / 50 AS band, COUNT(*) FROM employees WHERE salary IS NOT NULL GROUP BY band"]
+            )
+            .unwrap(),
+            ["1|3", "2|1"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT salary > 70, MIN(name) FROM employees GROUP BY salary > 70 ORDER BY 1"]
+            )
+            .unwrap(),
+            ["0|Ed", "1|Ann", "NULL|Di"]
+        );
+    }
+
+    #[test]
+    fn test_group_by_empty_table() {
+        let mut db = employees();
+        assert!(
+            query(&mut db, &["SELECT x, COUNT(*) FROM empty GROUP BY x"])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_group_by_errors() {
+        let mut db = employees();
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut db, "SELECT dept, name FROM employees GROUP BY dept"),
+            "column \"name\" must appear in the GROUP BY clause or be used in an aggregate function"
+        );
+        assert_eq!(
+            error(&mut db, "SELECT dept FROM employees GROUP BY 2"),
+            "GROUP BY term out of range - should be between 1 and 1"
+        );
+        assert_eq!(
+            error(&mut db, "SELECT COUNT(*) FROM employees GROUP BY 1"),
+            "misuse of aggregate: COUNT()"
+        );
+    }
+
+    #[test]
+    fn test_having() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept, COUNT(*) FROM employees GROUP BY dept HAVING COUNT(*) > 1"]
+            )
+            .unwrap(),
+            ["eng|2", "ops|2"]
+        );
+        // The aggregate need not be selected.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept FROM employees GROUP BY dept HAVING AVG(salary) >= 80 AND dept <> 'ops'"]
+            )
+            .unwrap(),
+            ["eng"]
+        );
+        // Aliases of the select list are visible.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept, SUM(salary) AS total FROM employees GROUP BY dept HAVING total < 100"]
+            )
+            .unwrap(),
+            ["law|60", "ops|80"]
+        );
+    }
+
+    #[test]
+    fn test_having_without_group_by() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*) FROM employees HAVING MAX(salary) > 90"]
+            )
+            .unwrap(),
+            ["5"]
+        );
+        assert!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*) FROM employees HAVING MAX(salary) > 100"]
+            )
+            .unwrap()
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_having_runs_between_group_by_and_limit() {
+        let mut db = employees();
+        // LIMIT counts the groups HAVING kept, not those GROUP BY made.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept FROM employees GROUP BY dept HAVING COUNT(*) = 2 ORDER BY dept DESC LIMIT 1"]
+            )
+            .unwrap(),
+            ["ops"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept FROM employees GROUP BY dept HAVING COUNT(*) = 2 LIMIT 1 OFFSET 1"]
+            )
+            .unwrap(),
+            ["ops"]
+        );
+        assert_eq!(
+            run(
+                &mut db,
+                &["SELECT dept FROM employees GROUP BY dept HAVING salary > 1"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "column \"salary\" must appear in the GROUP BY clause or be used in an aggregate function"
+        );
+    }
+
+    #[test]
+    fn test_window_aggregates() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, AVG(salary) OVER (PARTITION BY dept) FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            ["Ann|90.0", "Bob|90.0", "Cy|80.0", "Di|80.0", "Ed|60.0"]
+        );
+        // Without PARTITION BY the whole input is one partition.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, COUNT(*) OVER (), SUM(salary) OVER (), MIN(name) OVER () FROM employees WHERE dept <> 'law'"]
+            )
+            .unwrap(),
+            ["Ann|4|260|Ann", "Bob|4|260|Ann", "Cy|4|260|Ann", "Di|4|260|Ann"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, COUNT(*) OVER (PARTITION BY salary > 70, bonus IS NULL) FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            ["Ann|2", "Bob|1", "Cy|2", "Di|1", "Ed|1"]
+        );
+        // Calls with different windows are computed independently.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, MAX(salary) OVER (PARTITION BY dept), COUNT(bonus) OVER (), \
+                   MAX(salary) OVER (PARTITION BY bonus IS NULL) FROM employees ORDER BY name"
+                ]
+            )
+            .unwrap(),
+            [
+                "Ann|100|2|100",
+                "Bob|100|2|80",
+                "Cy|80|2|100",
+                "Di|80|2|80",
+                "Ed|60|2|100"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_window_order_by() {
+        let mut db = employees();
+        // With ORDER BY the frame ends at the row's last peer, so rows that
+        // sort equal share a running total.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, SUM(salary) OVER (ORDER BY salary), COUNT(*) OVER (ORDER BY salary) FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            ["Ann|320|4", "Bob|220|3", "Cy|220|3", "Di|320|5", "Ed|60|1"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, SUM(salary) OVER (PARTITION BY dept ORDER BY name DESC) FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            ["Ann|180", "Bob|80", "Cy|80", "Di|NULL", "Ed|60"]
+        );
+        // The outer ORDER BY and LIMIT see every row's window value.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, SUM(salary) OVER (ORDER BY salary DESC) AS running FROM employees \
+                   ORDER BY running, name LIMIT 3"]
+            )
+            .unwrap(),
+            ["Ann|100", "Bob|260", "Cy|260"]
+        );
+    }
+
+    #[test]
+    fn test_window_over_groups() {
+        let mut db = employees();
+        // GROUP BY runs first: the window's rows are the groups.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept, SUM(salary), SUM(SUM(salary)) OVER (), COUNT(*) OVER () FROM employees \
+                   GROUP BY dept ORDER BY dept"]
+            )
+            .unwrap(),
+            ["eng|180|320|3", "law|60|320|3", "ops|80|320|3"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept, MAX(salary) OVER (ORDER BY dept) FROM employees GROUP BY dept"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "column \"salary\" must appear in the GROUP BY clause or be used in an aggregate function"
+        );
+    }
+
+    #[test]
+    fn test_ranking_functions() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, ROW_NUMBER() OVER (ORDER BY salary DESC NULLS LAST), \
+                   RANK() OVER (ORDER BY salary DESC NULLS LAST), \
+                   DENSE_RANK() OVER (ORDER BY salary DESC NULLS LAST), \
+                   PERCENT_RANK() OVER (ORDER BY salary DESC NULLS LAST) FROM employees ORDER BY 2"
+                ]
+            )
+            .unwrap(),
+            [
+                "Ann|1|1|1|0.0",
+                "Bob|2|2|2|0.25",
+                "Cy|3|2|2|0.25",
+                "Ed|4|4|3|0.75",
+                "Di|5|5|4|1.0",
+            ]
+        );
+        // Numbering restarts in each partition.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, ROW_NUMBER() OVER (PARTITION BY dept ORDER BY salary), \
+                   PERCENT_RANK() OVER (PARTITION BY dept ORDER BY salary) FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            ["Ann|2|1.0", "Bob|1|0.0", "Cy|1|0.0", "Di|2|1.0", "Ed|1|0.0"]
+        );
+    }
+
+    #[test]
+    fn test_ranking_functions_over_ties() {
+        let mut db = employees();
+        // Every row of a partition ties with every other.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT ROW_NUMBER() OVER (ORDER BY dept), RANK() OVER (ORDER BY dept), \
+                   DENSE_RANK() OVER (ORDER BY dept), PERCENT_RANK() OVER (ORDER BY dept) \
+                   FROM employees WHERE dept = 'eng'"
+                ]
+            )
+            .unwrap(),
+            ["1|1|1|0.0", "2|1|1|0.0"]
+        );
+        // ROW_NUMBER still numbers the tied rows uniquely.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dept, COUNT(*), COUNT(DISTINCT n), MAX(n) FROM \
+                   (SELECT dept, ROW_NUMBER() OVER (PARTITION BY dept ORDER BY 'x') AS n FROM employees) AS r \
+                   GROUP BY dept"]
+            )
+            .unwrap(),
+            ["eng|2|2|2", "law|1|1|1", "ops|2|2|2"]
+        );
+        for sql in [
+            "SELECT RANK() OVER () FROM employees",
+            "SELECT ROW_NUMBER() OVER (PARTITION BY dept) FROM employees",
+        ] {
+            assert!(
+                run(&mut db, &[sql])
+                    .unwrap_err()
+                    .to_string()
+                    .ends_with("() requires an ORDER BY in its window"),
+                "{sql}"
+            );
+        }
+        assert_eq!(
+            run(
+                &mut db,
+                &["SELECT rank(salary) OVER (ORDER BY salary) FROM employees"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "wrong number of arguments to function rank()"
+        );
+    }
+
+    #[test]
+    fn test_lag_and_lead() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, LAG(salary, 1, 0) OVER (ORDER BY name), LAG(salary, 2) OVER (ORDER BY name), \
+                   LEAD(salary) OVER (ORDER BY name), LEAD(name, 1, 'none') OVER (ORDER BY name), \
+                   LAG(salary, 0) OVER (ORDER BY name) FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            [
+                "Ann|0|NULL|80|Bob|100",
+                "Bob|100|NULL|80|Cy|80",
+                "Cy|80|100|NULL|Di|80",
+                "Di|80|80|60|Ed|NULL",
+                "Ed|NULL|80|NULL|none|60",
+            ]
+        );
+        // Offsets stay within the partition, and the default is evaluated
+        // for the current row.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, LAG(name) OVER (PARTITION BY dept ORDER BY name), \
+                   LAG(salary, 1, salary * 10) OVER (PARTITION BY dept ORDER BY name) FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            ["Ann|NULL|1000", "Bob|Ann|100", "Cy|NULL|800", "Di|Cy|80", "Ed|NULL|600"]
+        );
+        for sql in [
+            "SELECT LAG(salary, -1) OVER (ORDER BY name) FROM employees",
+            "SELECT LAG(salary, NULL) OVER (ORDER BY name) FROM employees",
+        ] {
+            assert_eq!(
+                run(&mut db, &[sql]).unwrap_err().to_string(),
+                "argument 2 of LAG() must be a non-negative integer",
+                "{sql}"
+            );
+        }
+        assert_eq!(
+            run(
+                &mut db,
+                &["SELECT LEAD() OVER (ORDER BY name) FROM employees"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "wrong number of arguments to function LEAD()"
+        );
+    }
+
+    #[test]
+    fn test_value_functions() {
+        let mut db = employees();
+        // LAST_VALUE sees the whole partition, the others only the rows up
+        // to the current one.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, FIRST_VALUE(name) OVER (PARTITION BY dept ORDER BY salary), LAST_VALUE(name) OVER (PARTITION BY dept ORDER BY salary), \
+                   NTH_VALUE(name, 2) OVER (PARTITION BY dept ORDER BY salary), NTH_VALUE(name, 3) OVER (PARTITION BY dept ORDER BY salary), \
+                   LAST_VALUE(salary) OVER (PARTITION BY dept ORDER BY salary) FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            [
+                "Ann|Bob|Ann|Ann|NULL|100",
+                "Bob|Bob|Ann|NULL|NULL|100",
+                "Cy|Cy|Di|NULL|NULL|NULL",
+                "Di|Cy|Di|Di|NULL|NULL",
+                "Ed|Ed|Ed|NULL|NULL|60",
+            ]
+        );
+        // A NULL is returned like any other value.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT DISTINCT FIRST_VALUE(salary) OVER (ORDER BY salary DESC) FROM employees"]
+            )
+            .unwrap(),
+            ["NULL"]
+        );
+        assert_eq!(
+            run(
+                &mut db,
+                &["SELECT NTH_VALUE(name, 0) OVER (ORDER BY name) FROM employees"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "argument 2 of NTH_VALUE() must be a positive integer"
+        );
+        for sql in [
+            "SELECT NTH_VALUE(name) OVER () FROM employees",
+            "SELECT FIRST_VALUE(name, 1) OVER () FROM employees",
+            "SELECT LAST_VALUE() OVER () FROM employees",
+        ] {
+            assert!(
+                run(&mut db, &[sql])
+                    .unwrap_err()
+                    .to_string()
+                    .starts_with("wrong number of arguments"),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rows_frames() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE s (id INTEGER, v INTEGER)",
+                "INSERT INTO s VALUES (1, 10), (2, 20), (3, 30), (4, 40), (5, 50)",
+            ],
+        )
+        .unwrap();
+        // A sliding average two rows either side of the current one.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT id, AVG(v) OVER (ORDER BY id ROWS BETWEEN 2 PRECEDING AND 2 FOLLOWING), \
+                   SUM(v) OVER (ORDER BY id ROWS BETWEEN 2 PRECEDING AND 1 FOLLOWING) FROM s"]
+            )
+            .unwrap(),
+            ["1|20.0|30", "2|25.0|60", "3|30.0|100", "4|35.0|140", "5|40.0|120"]
+        );
+        // A frame past the end of the partition is empty.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT SUM(v) OVER (ORDER BY id ROWS BETWEEN 1 FOLLOWING AND 2 FOLLOWING), \
+                   COUNT(*) OVER (ORDER BY id ROWS BETWEEN 1 FOLLOWING AND 2 FOLLOWING), \
+                   FIRST_VALUE(v) OVER (ORDER BY id ROWS BETWEEN 1 FOLLOWING AND 2 FOLLOWING) FROM s"]
+            )
+            .unwrap(),
+            ["50|2|20", "70|2|30", "90|2|40", "50|1|50", "NULL|0|NULL"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT SUM(v) OVER (ORDER BY id ROWS UNBOUNDED PRECEDING), \
+                   SUM(v) OVER (ORDER BY id ROWS BETWEEN CURRENT ROW AND UNBOUNDED FOLLOWING), \
+                   SUM(v) OVER (ORDER BY id DESC ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) FROM s"
+                ]
+            )
+            .unwrap(),
+            [
+                "10|150|30",
+                "30|140|50",
+                "60|120|70",
+                "100|90|90",
+                "150|50|50"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range_frames() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE r (x INTEGER)",
+                "INSERT INTO r VALUES (1), (2), (4), (7), (8), (NULL)",
+            ],
+        )
+        .unwrap();
+        // The frame holds the rows whose value is within 2 of the current
+        // row's; the NULL row's frame is the NULL rows.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT x, COUNT(*) OVER (ORDER BY x RANGE BETWEEN 2 PRECEDING AND 2 FOLLOWING), \
+                   SUM(x) OVER (ORDER BY x RANGE BETWEEN 2 PRECEDING AND 2 FOLLOWING) FROM r"]
+            )
+            .unwrap(),
+            ["1|2|3", "2|3|7", "4|2|6", "7|2|15", "8|2|15", "NULL|1|NULL"]
+        );
+        // Descending, the preceding rows hold the larger values.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT x, SUM(x) OVER (ORDER BY x DESC RANGE BETWEEN 1 PRECEDING AND CURRENT ROW) \
+                   FROM r WHERE x IS NOT NULL"]
+            )
+            .unwrap(),
+            ["1|3", "2|2", "4|4", "7|15", "8|8"]
+        );
+        // In ROWS mode peers are separate rows; in RANGE mode, the default,
+        // the current row stands for all of them.
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, SUM(salary) OVER (ORDER BY salary ROWS UNBOUNDED PRECEDING), \
+                   SUM(salary) OVER (ORDER BY salary RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW), \
+                   LAST_VALUE(name) OVER (PARTITION BY dept ORDER BY salary ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW), \
+                   LAST_VALUE(name) OVER (PARTITION BY dept ORDER BY salary ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING) \
+                   FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            [
+                "Ann|320|320|Ann|Ann",
+                "Bob|140|220|Bob|Ann",
+                "Cy|220|220|Cy|Di",
+                "Di|320|320|Di|Di",
+                "Ed|60|60|Ed|Ed",
+            ]
+        );
+        for sql in [
+            "SELECT SUM(salary) OVER (RANGE 1 PRECEDING) FROM employees",
+            "SELECT SUM(salary) OVER (ORDER BY salary, name RANGE BETWEEN CURRENT ROW AND 1 FOLLOWING) FROM employees",
+        ] {
+            assert_eq!(
+                run(&mut db, &[sql]).unwrap_err().to_string(),
+                "RANGE with offset PRECEDING/FOLLOWING requires one ORDER BY expression",
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ntile() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE n (x INTEGER)",
+                "INSERT INTO n WITH RECURSIVE c (x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 10) \
+                 SELECT x FROM c",
+            ],
+        )
+        .unwrap();
+        let buckets = |db: &mut Database, n: i64| {
+            query(
+                db,
+                &[&format!(
+                    "SELECT NTILE({n}) OVER (ORDER BY x) FROM n ORDER BY x"
+                )],
+            )
+            .unwrap()
+            .join(",")
+        };
+        // Ten rows in four buckets: the first two get the extra rows.
+        assert_eq!(buckets(&mut db, 4), "1,1,1,2,2,2,3,3,4,4");
+        assert_eq!(buckets(&mut db, 3), "1,1,1,1,2,2,2,3,3,3");
+        assert_eq!(buckets(&mut db, 1), "1,1,1,1,1,1,1,1,1,1");
+        assert_eq!(buckets(&mut db, 10), "1,2,3,4,5,6,7,8,9,10");
+        assert_eq!(buckets(&mut db, 25), "1,2,3,4,5,6,7,8,9,10");
+        // Each partition is split on its own.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT x, NTILE(2) OVER (PARTITION BY x % 2 ORDER BY x) FROM n WHERE x <= 5 ORDER BY x"]
+            )
+            .unwrap(),
+            ["1|1", "2|1", "3|1", "4|2", "5|2"]
+        );
+        run(&mut db, &["INSERT INTO n VALUES (NULL)"]).unwrap();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT x, NTILE(3) OVER (ORDER BY x) FROM n WHERE x IS NULL OR x > 8"]
+            )
+            .unwrap(),
+            ["9|1", "10|2", "NULL|3"]
+        );
+        for sql in [
+            "SELECT NTILE(0) OVER (ORDER BY x) FROM n",
+            "SELECT NTILE(-2) OVER (ORDER BY x) FROM n",
+            "SELECT NTILE(NULL) OVER (ORDER BY x) FROM n",
+        ] {
+            assert_eq!(
+                run(&mut db, &[sql]).unwrap_err().to_string(),
+                "argument 1 of NTILE() must be a positive integer",
+                "{sql}"
+            );
+        }
+        assert_eq!(
+            run(&mut db, &["SELECT NTILE() OVER (ORDER BY x) FROM n"])
+                .unwrap_err()
+                .to_string(),
+            "wrong number of arguments to function NTILE()"
+        );
+    }
+
+    #[test]
+    fn test_window_errors() {
+        let mut db = employees();
+        for (sql, message) in [
+            (
+                "SELECT name FROM employees WHERE COUNT(*) OVER () > 1",
+                "misuse of window function COUNT()",
+            ),
+            (
+                "SELECT dept FROM employees GROUP BY COUNT(*) OVER ()",
+                "misuse of window function COUNT()",
+            ),
+            (
+                "SELECT dept FROM employees GROUP BY dept HAVING COUNT(*) OVER () > 1",
+                "misuse of window function COUNT()",
+            ),
+            (
+                "SELECT SUM(COUNT(*) OVER ()) OVER () FROM employees",
+                "misuse of window function COUNT()",
+            ),
+            (
+                "SELECT abs(salary) OVER () FROM employees",
+                "abs() may not be used as a window function",
+            ),
+            (
+                "SELECT nope(salary) OVER () FROM employees",
+                "no such function: nope",
+            ),
+        ] {
+            assert_eq!(
+                run(&mut db, &[sql]).unwrap_err().to_string(),
+                message,
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_derived_table() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT d.name FROM (SELECT dept AS name, COUNT(*) AS cnt FROM employees GROUP BY dept) AS d WHERE d.cnt > 1"]
+            )
+            .unwrap(),
+            ["eng", "ops"]
+        );
+        // Unaliased expressions are named by position; columns keep their name.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT col2, name, t.col3 FROM (SELECT name, salary * 2, salary + 1 FROM employees) t WHERE name = 'Ann'"]
+            )
+            .unwrap(),
+            ["200|Ann|101"]
+        );
+        assert_eq!(
+            run(&mut db, &["SELECT x FROM (SELECT 1) AS t"])
+                .unwrap_err()
+                .to_string(),
+            "no such column: x"
+        );
+        // Without an alias, its columns are found by name alone.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT dept, n FROM (SELECT dept, COUNT(*) AS n FROM employees GROUP BY dept) \
+                   JOIN (SELECT 2 AS m) ON n = m"
+                ]
+            )
+            .unwrap(),
+            ["eng|2", "ops|2"]
+        );
+    }
+
+    #[test]
+    fn test_table_and_subquery_aliases() {
+        let mut db = employees();
+        // Two scans of one table are told apart only by their aliases.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT a.name, b.name FROM employees AS a JOIN employees AS b \
+                   ON a.dept = b.dept AND a.name < b.name ORDER BY a.name"
+                ]
+            )
+            .unwrap(),
+            ["Ann|Bob", "Cy|Di"]
+        );
+        assert_eq!(
+            run(&mut db, &["SELECT employees.name FROM employees AS e"])
+                .unwrap_err()
+                .to_string(),
+            "no such column: employees.name"
+        );
+        // The column list renames the query's columns, hiding their names.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT t.who, pay FROM (SELECT name, salary FROM employees) AS t (who, pay) \
+                   WHERE pay > 80"
+                ]
+            )
+            .unwrap(),
+            ["Ann|100"]
+        );
+        assert_eq!(
+            run(
+                &mut db,
+                &["SELECT name FROM (SELECT name FROM employees) AS t (who)"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "no such column: name"
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT * FROM (SELECT 1, 2, 3) AS t (a, b) WHERE a = 1 AND col3 = 3"]
+            )
+            .unwrap(),
+            ["1|2|3"]
+        );
+        assert!(run(&mut db, &["SELECT * FROM (SELECT 1) AS t (a, b)"]).is_err());
+        // Aliases inside a derived table are its own; the outer query sees
+        // only the derived table's.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT o.n FROM (SELECT i.name FROM (SELECT name FROM employees AS e \
+                   WHERE e.dept = 'ops') AS i) AS o (n) ORDER BY o.n"
+                ]
+            )
+            .unwrap(),
+            ["Cy", "Di"]
+        );
+        assert!(
+            run(
+                &mut db,
+                &["SELECT i.name FROM (SELECT i.name FROM (SELECT name FROM employees) AS i) AS o"]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_derived_table_keeps_its_own_order_and_limit() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT top.name FROM (SELECT name, salary FROM employees ORDER BY salary DESC NULLS LAST LIMIT 2) AS top ORDER BY top.name DESC"]
+            )
+            .unwrap(),
+            ["Bob", "Ann"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*) FROM (SELECT DISTINCT dept FROM employees LIMIT 5 OFFSET 1) AS d"]
+            )
+            .unwrap(),
+            ["2"]
+        );
+    }
+
+    #[test]
+    fn test_derived_table_in_join() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT e.name, d.top FROM employees e \
+                   JOIN (SELECT dept, MAX(salary) AS top FROM employees GROUP BY dept) d \
+                   ON e.dept = d.dept AND e.salary = d.top ORDER BY e.name"]
+            )
+            .unwrap(),
+            ["Ann|100", "Cy|80", "Ed|60"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT * FROM (SELECT 1 AS dept_id) AS a NATURAL JOIN (SELECT 1 AS dept_id, 'x') AS b"]
+            )
+            .unwrap(),
+            ["1|x"]
+        );
+    }
+
+    #[test]
+    fn test_values_in_from() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT t.name, id * 10 FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, name)"]
+            )
+            .unwrap(),
+            ["a|10", "b|20"]
+        );
+        // Columns left unnamed are numbered.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT *, v.column1 FROM (VALUES (1), (2), (3)) AS v WHERE column1 > 1"]
+            )
+            .unwrap(),
+            ["2|2", "3|3"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT x, column2, column3 FROM (VALUES (1 + 1, 'y', (SELECT COUNT(*) FROM employees))) v(x)"]
+            )
+            .unwrap(),
+            ["2|y|5"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT column2, column3 FROM (VALUES (NULL, 'a', 5))"]
+            )
+            .unwrap(),
+            ["a|5"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT e.name, d.title FROM employees e \
+                   JOIN (VALUES ('eng', 'Engineering'), ('ops', 'Operations')) AS d(dept, title) \
+                   USING (dept) ORDER BY e.name"]
+            )
+            .unwrap(),
+            [
+                "Ann|Engineering",
+                "Bob|Engineering",
+                "Cy|Operations",
+                "Di|Operations"
+            ]
+        );
+        assert_eq!(
+            run(&mut db, &["SELECT * FROM (VALUES (1, 2)) AS t(a, b, c)"])
+                .unwrap_err()
+                .to_string(),
+            "table t has 2 values for 3 columns"
+        );
+        for sql in [
+            "SELECT * FROM (VALUES (1, 2), (3)) AS t",
+            "INSERT INTO employees VALUES ('x', 'y', 1, 2.0), ('z')",
+        ] {
+            assert_eq!(
+                parse_statement(sql).unwrap_err().to_string(),
+                "all VALUES must have the same number of terms"
+            );
+        }
+    }
+
+    #[test]
+    fn test_common_table_expressions() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "WITH paid AS (SELECT * FROM employees WHERE salary IS NOT NULL), \
+                   totals AS (SELECT dept, SUM(salary) AS total, COUNT(*) FROM paid GROUP BY dept) \
+                   SELECT dept, total, \"COUNT(*)\" FROM totals WHERE total > 60 ORDER BY dept"
+                ]
+            )
+            .unwrap(),
+            ["eng|180|2", "ops|80|1"]
+        );
+        // One CTE read twice, under two aliases.
+        assert_eq!(
+            query(
+                &mut db,
+                &["WITH d AS (SELECT DISTINCT dept FROM employees) \
+                   SELECT a.dept, b.dept FROM d a JOIN d b ON a.dept < b.dept \
+                   ORDER BY a.dept, b.dept"]
+            )
+            .unwrap(),
+            ["eng|law", "eng|ops", "law|ops"]
+        );
+        // A CTE hides a table of its name, for this statement only, and is
+        // visible to subqueries.
+        assert_eq!(
+            query(
+                &mut db,
+                &["WITH employees AS (SELECT 'Zed' AS name) \
+                   SELECT name, (SELECT COUNT(*) FROM employees) FROM employees"]
+            )
+            .unwrap(),
+            ["Zed|1"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT COUNT(*) FROM employees"]).unwrap(),
+            ["5"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "INSERT INTO empty WITH n AS (SELECT 7) SELECT * FROM n",
+                    "SELECT x FROM empty"
+                ]
+            )
+            .unwrap(),
+            ["7"]
+        );
+        // A CTE cannot read those defined after it.
+        assert_eq!(
+            query(
+                &mut db,
+                &["WITH a AS (SELECT * FROM b), b AS (SELECT 1) SELECT * FROM a"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "no such table: b"
+        );
+    }
+
+    #[test]
+    fn test_recursive_common_table_expressions() {
+        let mut db = Database::new();
+        assert_eq!(
+            query(
+                &mut db,
+                &["WITH RECURSIVE fib(n, a, b) AS \
+                   (SELECT 1, 0, 1 UNION ALL SELECT n + 1, b, a + b FROM fib WHERE n < 10) \
+                   SELECT a FROM fib"]
+            )
+            .unwrap(),
+            ["0", "1", "1", "2", "3", "5", "8", "13", "21", "34"]
+        );
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE tree (id INT, parent INT, name TEXT)",
+                "INSERT INTO tree VALUES (1, NULL, 'root'), (2, 1, 'a'), (3, 1, 'b'), \
+                 (4, 2, 'a1'), (5, 4, 'a11'), (6, NULL, 'other')",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "WITH RECURSIVE sub(id, depth) AS (SELECT id, 0 FROM tree WHERE id = 2 \
+                   UNION ALL SELECT t.id, s.depth + 1 FROM tree t JOIN sub s ON t.parent = s.id) \
+                   SELECT name, depth FROM sub JOIN tree USING (id) ORDER BY depth"
+                ]
+            )
+            .unwrap(),
+            ["a|0", "a1|1", "a11|2"]
+        );
+        // Without a reference to itself, the query is an ordinary one.
+        assert_eq!(
+            query(
+                &mut db,
+                &["WITH RECURSIVE c(x) AS (SELECT 1 UNION SELECT 1) SELECT x FROM c"]
+            )
+            .unwrap(),
+            ["1"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT 2 ORDER BY 1 DESC LIMIT 1) \
+                   SELECT x FROM c"
+                ]
+            )
+            .unwrap(),
+            ["2"]
+        );
+        // A LIMIT stops the recursion once it has its rows, and an OFFSET
+        // skips the first rows after the step has read them.
+        let count = |limit: &str| {
+            let mut db = Database::new();
+            query(
+                &mut db,
+                &[&format!(
+                    "WITH RECURSIVE c(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM c {limit}) \
+                     SELECT n FROM c"
+                )],
+            )
+            .unwrap()
+        };
+        assert_eq!(count("LIMIT 3"), ["1", "2", "3"]);
+        assert_eq!(count("LIMIT 2 OFFSET 4"), ["5", "6"]);
+        assert_eq!(count("LIMIT 500").len(), 500);
+        assert_eq!(count("WHERE n < 3 LIMIT 10"), ["1", "2", "3"]);
+        assert!(count("LIMIT 0").is_empty());
+        let err = |sql: &str| {
+            let mut db = Database::new();
+            query(&mut db, &[sql]).unwrap_err().to_string()
+        };
+        assert_eq!(
+            err(
+                "WITH RECURSIVE c(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM c ORDER BY n LIMIT 3) \
+                 SELECT n FROM c"
+            ),
+            "ORDER BY in a recursive common table expression is not supported"
+        );
+        assert_eq!(
+            err("WITH RECURSIVE r(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r) SELECT n FROM r"),
+            "recursive common table expression r exceeded 1000 iterations"
+        );
+        assert_eq!(
+            err("WITH RECURSIVE r(n) AS (SELECT 1 UNION SELECT n FROM r) SELECT n FROM r"),
+            "recursive common table expression r must use UNION ALL"
+        );
+        assert_eq!(
+            err("WITH RECURSIVE r(n) AS (SELECT 1 UNION ALL \
+                 SELECT a.n FROM r a JOIN r b ON a.n < 1) SELECT n FROM r"),
+            "multiple recursive references: r"
+        );
+        assert_eq!(
+            err("WITH RECURSIVE r(n) AS (SELECT 1 UNION ALL \
+                 SELECT 2 WHERE EXISTS (SELECT * FROM r)) SELECT n FROM r"),
+            "recursive reference in a subquery: r"
+        );
+        assert_eq!(
+            err("WITH RECURSIVE r(n, m) AS (SELECT 1 UNION ALL SELECT n FROM r) SELECT n FROM r"),
+            "table r has 1 values for 2 columns"
+        );
+        // Without RECURSIVE, a CTE cannot read itself.
+        assert_eq!(
+            err("WITH r(n) AS (SELECT 1 UNION ALL SELECT n FROM r) SELECT n FROM r"),
+            "no such table: r"
+        );
+    }
+
+    #[test]
+    fn test_nested_derived_tables() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT outer_t.n + 1 FROM (SELECT inner_t.s AS n FROM \
+                   (SELECT SUM(salary) AS s FROM employees) AS inner_t) AS outer_t"]
+            )
+            .unwrap(),
+            ["321"]
+        );
+    }
+
+    #[test]
+    fn test_scalar_subquery() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, salary - (SELECT MIN(salary) FROM employees) FROM employees WHERE dept = 'eng'"]
+            )
+            .unwrap(),
+            ["Ann|40", "Bob|20"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees WHERE salary > (SELECT AVG(salary) FROM employees)"]
+            )
+            .unwrap(),
+            ["Ann"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT (SELECT 1) + (SELECT COUNT(*) FROM employees)"]
+            )
+            .unwrap(),
+            ["6"]
+        );
+    }
+
+    #[test]
+    fn test_scalar_subquery_without_rows_is_null() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT (SELECT x FROM empty), (SELECT x FROM empty) IS NULL"]
+            )
+            .unwrap(),
+            ["NULL|1"]
+        );
+        assert!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees WHERE salary = (SELECT x FROM empty)"]
+            )
+            .unwrap()
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_scalar_subquery_errors() {
+        let mut db = employees();
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut db, "SELECT (SELECT name FROM employees)"),
+            "subquery returns more than one row"
+        );
+        assert_eq!(
+            error(&mut db, "SELECT (SELECT name, dept FROM employees LIMIT 1)"),
+            "sub-select returns 2 columns - expected 1"
+        );
+        assert_eq!(
+            error(
+                &mut db,
+                "SELECT name FROM employees WHERE salary = (SELECT nope FROM employees)"
+            ),
+            "no such column: nope"
+        );
+    }
+
+    #[test]
+    fn test_scalar_subquery_in_select_and_where() {
+        let mut db = employees();
+        let top = "(SELECT MAX(salary) FROM employees WHERE dept <> 'eng')";
+        assert_eq!(
+            query(
+                &mut db,
+                &[&format!(
+                    "SELECT name, {top} FROM employees WHERE salary >= {top} ORDER BY name"
+                )]
+            )
+            .unwrap(),
+            ["Ann|80", "Bob|80", "Cy|80"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[&format!(
+                    "SELECT dept, COUNT(*) FROM employees GROUP BY dept HAVING MAX(salary) < {top}"
+                )]
+            )
+            .unwrap(),
+            ["law|1"]
+        );
+    }
+
+    #[test]
+    fn test_correlated_scalar_subquery() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees e \
+                   WHERE salary > (SELECT AVG(salary) FROM employees WHERE dept = e.dept)"]
+            )
+            .unwrap(),
+            ["Ann"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, (SELECT COUNT(*) FROM employees WHERE dept = e.dept) \
+                   FROM employees e ORDER BY name"
+                ]
+            )
+            .unwrap(),
+            ["Ann|2", "Bob|2", "Cy|2", "Di|2", "Ed|1"]
+        );
+    }
+
+    #[test]
+    fn test_correlated_exists_and_in() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees e WHERE EXISTS \
+                   (SELECT 1 FROM employees WHERE dept = e.dept AND name <> e.name) ORDER BY name"]
+            )
+            .unwrap(),
+            ["Ann", "Bob", "Cy", "Di"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees e WHERE salary IN \
+                   (SELECT salary FROM employees WHERE dept <> e.dept) ORDER BY name"]
+            )
+            .unwrap(),
+            ["Bob", "Cy"]
+        );
+    }
+
+    #[test]
+    fn test_subquery_nested_two_levels_deep() {
+        let mut db = employees();
+        // The innermost subquery reads both enclosing rows.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees a WHERE EXISTS \
+                   (SELECT 1 FROM employees b WHERE b.dept = a.dept AND EXISTS \
+                    (SELECT 1 FROM employees c WHERE c.salary < a.salary AND c.name = b.name)) \
+                   ORDER BY name"]
+            )
+            .unwrap(),
+            ["Ann"]
+        );
+    }
+
+    #[test]
+    fn test_subquery_column_shadows_outer_column() {
+        let mut db = employees();
+        // Both `dept`s inside the subquery are its own table's column, so
+        // the subquery finds Ed's row whichever outer row it runs for.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*) FROM employees e WHERE EXISTS \
+                   (SELECT 1 FROM employees WHERE dept = dept AND name = 'Ed')"]
+            )
+            .unwrap(),
+            ["5"]
+        );
+    }
+
+    #[test]
+    fn test_in_subquery() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees \
+                   WHERE salary IN (SELECT salary FROM employees WHERE dept = 'ops') ORDER BY name"]
+            )
+            .unwrap(),
+            ["Bob", "Cy"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees \
+                   WHERE dept NOT IN (SELECT dept FROM employees WHERE salary < 90) ORDER BY name"]
+            )
+            .unwrap(),
+            Vec::<String>::new()
+        );
+        // Nothing is in an empty result, not even NULL.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT NULL IN (SELECT x FROM empty), NULL NOT IN (SELECT x FROM empty)"]
+            )
+            .unwrap(),
+            ["0|1"]
+        );
+    }
+
+    #[test]
+    fn test_in_subquery_with_nulls() {
+        let mut db = employees();
+        // Di's salary is NULL, so whether it is in the list is unknown.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, salary IN (SELECT salary FROM employees WHERE dept = 'eng') \
+                   FROM employees ORDER BY name"
+                ]
+            )
+            .unwrap(),
+            ["Ann|1", "Bob|1", "Cy|1", "Di|NULL", "Ed|0"]
+        );
+        // The ops salaries include a NULL, so a salary that matches none of
+        // them might still equal the missing one.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, salary NOT IN (SELECT salary FROM employees WHERE dept = 'ops') \
+                   FROM employees ORDER BY name"
+                ]
+            )
+            .unwrap(),
+            ["Ann|NULL", "Bob|0", "Cy|0", "Di|NULL", "Ed|NULL"]
+        );
+        assert!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees WHERE salary NOT IN (SELECT salary FROM employees)"]
+            )
+            .unwrap()
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_in_subquery_must_have_one_column() {
+        let mut db = employees();
+        assert_eq!(
+            run(
+                &mut db,
+                &["SELECT name FROM employees WHERE salary IN (SELECT salary, bonus FROM employees)"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "sub-select returns 2 columns - expected 1"
+        );
+    }
+
+    #[test]
+    fn test_in_row_values() {
+        let mut db = employees();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name FROM employees WHERE (dept, salary) IN (('eng', 80), ('ops', 80), ('law', 80)) \
+                   ORDER BY name"]
+            )
+            .unwrap(),
+            ["Bob", "Cy"]
+        );
+        // A NULL decides nothing where another value already differs.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, (dept, salary) IN (('ops', 80), ('eng', NULL)) FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            ["Ann|NULL", "Bob|NULL", "Cy|1", "Di|NULL", "Ed|0"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT name, (dept, salary) NOT IN (('ops', 80), ('law', 60)) FROM employees ORDER BY name"]
+            )
+            .unwrap(),
+            ["Ann|1", "Bob|1", "Cy|0", "Di|NULL", "Ed|0"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT e.name FROM employees e WHERE (e.dept, e.salary) IN \
+                   (SELECT dept, MAX(salary) FROM employees GROUP BY dept) ORDER BY e.name"
+                ]
+            )
+            .unwrap(),
+            ["Ann", "Cy", "Ed"]
+        );
+        for sql in [
+            "SELECT name FROM employees WHERE (dept, salary) IN (('eng', 80), 'ops')",
+            "SELECT name FROM employees WHERE salary IN ((80, 100))",
+            "SELECT (1, 2)",
+            "SELECT name FROM employees WHERE (dept, salary) = ('eng', 80)",
+        ] {
+            assert_eq!(
+                run(&mut db, &[sql]).unwrap_err(),
+                EvalError::RowValueMisused,
+                "{sql}"
+            );
+        }
+        assert_eq!(
+            run(
+                &mut db,
+                &["SELECT name FROM employees WHERE (dept, salary) IN (SELECT dept FROM employees)"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "sub-select returns 1 columns - expected 2"
+        );
+    }
+
+    #[test]
+    fn test_quantified_comparisons() {
+        let mut db = employees();
+        let names = |db: &mut Database, predicate: &str| {
+            query(
+                db,
+                &[&format!(
+                    "SELECT name FROM employees WHERE {predicate} ORDER BY name"
+                )],
+            )
+            .unwrap()
+        };
+        // The ops salaries are 80 and NULL, the law one 60.
+        let law = "(SELECT salary FROM employees WHERE dept = 'law')";
+        assert_eq!(names(&mut db, &format!("salary = ANY {law}")), ["Ed"]);
+        assert_eq!(
+            names(&mut db, &format!("salary <> SOME {law}")),
+            ["Ann", "Bob", "Cy"]
+        );
+        assert_eq!(
+            names(&mut db, &format!("salary < ANY {law}")),
+            [] as [&str; 0]
+        );
+        assert_eq!(names(&mut db, &format!("salary <= ANY {law}")), ["Ed"]);
+        assert_eq!(
+            names(&mut db, &format!("salary > ANY {law}")),
+            ["Ann", "Bob", "Cy"]
+        );
+        assert_eq!(
+            names(&mut db, &format!("salary >= ALL {law}")),
+            ["Ann", "Bob", "Cy", "Ed"]
+        );
+        assert_eq!(
+            names(
+                &mut db,
+                "salary >= ALL (SELECT salary FROM employees WHERE salary IS NOT NULL)"
+            ),
+            ["Ann"]
+        );
+        // Without values, ANY holds for none and ALL for every row.
+        let none = "(SELECT salary FROM employees WHERE dept = 'none')";
+        assert!(names(&mut db, &format!("salary = ANY {none}")).is_empty());
+        assert_eq!(names(&mut db, &format!("salary = ALL {none}")).len(), 5);
+        // A NULL among the values leaves unknown what no other value
+        // decides.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "SELECT name, salary > ANY (SELECT salary FROM employees WHERE dept = 'ops'), \
+                   salary < ALL (SELECT salary FROM employees WHERE dept = 'ops') \
+                   FROM employees ORDER BY name"
+                ]
+            )
+            .unwrap(),
+            [
+                "Ann|1|0",
+                "Bob|NULL|0",
+                "Cy|NULL|0",
+                "Di|NULL|NULL",
+                "Ed|NULL|NULL"
+            ]
+        );
+        assert_eq!(
+            run(
+                &mut db,
+                &["SELECT name FROM employees WHERE salary = ANY (SELECT salary, bonus FROM employees)"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "sub-select returns 2 columns - expected 1"
+        );
+    }
+
+    #[test]
+    fn test_exists() {
+        let mut db = departments();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dname FROM dept d \
+                   WHERE EXISTS (SELECT 1 FROM emp e WHERE e.dept_id = d.dept_id) ORDER BY dname"]
+            )
+            .unwrap(),
+            ["Eng", "Ops"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT dname FROM dept d \
+                   WHERE NOT EXISTS (SELECT 1 FROM emp e WHERE e.dept_id = d.dept_id)"]
+            )
+            .unwrap(),
+            ["Law"]
+        );
+    }
+
+    #[test]
+    fn test_exists_is_never_null() {
+        let mut db = employees();
+        // The subquery's row exists even though its value is NULL.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT EXISTS (SELECT NULL FROM employees), EXISTS (SELECT NULL FROM empty)"]
+            )
+            .unwrap(),
+            ["1|0"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*) FROM employees WHERE NOT EXISTS (SELECT * FROM empty)"]
+            )
+            .unwrap(),
+            ["5"]
+        );
+    }
+
+    #[test]
+    fn test_exists_stops_at_first_row() {
+        let mut db = sized(&[("big", 1000)]);
+        // A billion rows: only feasible if the subquery stops at the first.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT EXISTS (SELECT * FROM big, big AS b2, big AS b3)"]
+            )
+            .unwrap(),
+            ["1"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*) FROM big WHERE v <= 3 AND EXISTS \
+                   (SELECT * FROM big AS b2, big AS b3 WHERE b2.v = big.v)"]
+            )
+            .unwrap(),
+            ["3"]
+        );
+    }
+
+    fn sets() -> Database {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE a (x INTEGER, y TEXT)",
+                "CREATE TABLE b (x INTEGER, y TEXT)",
+                "CREATE TABLE none (x INTEGER, y TEXT)",
+                "INSERT INTO a VALUES (1, 'p'), (2, 'q'), (2, 'q'), (NULL, NULL), (3, 'r')",
+                "INSERT INTO b VALUES (2, 'q'), (4, 's'), (NULL, NULL), (4, 's')",
+            ],
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_union() {
+        let mut db = sets();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM a UNION SELECT * FROM b"]).unwrap(),
+            ["1|p", "2|q", "NULL|NULL", "3|r", "4|s"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM a UNION ALL SELECT * FROM b"]).unwrap(),
+            [
+                "1|p",
+                "2|q",
+                "2|q",
+                "NULL|NULL",
+                "3|r",
+                "2|q",
+                "4|s",
+                "NULL|NULL",
+                "4|s"
+            ]
+        );
+        // Column names come from the left side.
+        let stmt = parse_statement("SELECT x AS n FROM a UNION SELECT y FROM b").unwrap();
+        let Statement::Select(select) = stmt else {
+            unreachable!()
+        };
+        let plan = plan_select(&db, &select).unwrap();
+        assert_eq!(plan.schema().columns[0].name, "n");
+    }
+
+    #[test]
+    fn test_union_all_streams() {
+        let mut db = Database::new();
+        let size = 1_000_000;
+        for name in ["l", "r"] {
+            run(&mut db, &[&format!("CREATE TABLE {name} (v INTEGER)")]).unwrap();
+            db.insert_rows(name, (0..size).map(|i| vec![Value::Integer(i)]))
+                .unwrap();
+        }
+        let Statement::Select(select) =
+            parse_statement("SELECT v FROM l UNION ALL SELECT -v FROM r").unwrap()
+        else {
+            unreachable!()
+        };
+        // Rows are counted as they arrive, none kept.
+        let (mut count, mut first, mut last) = (0, None, None);
+        execute_each(&mut db, &Statement::Select(select), |row| {
+            count += 1;
+            first.get_or_insert_with(|| row[0].clone());
+            last = Some(row[0].clone());
+        })
+        .unwrap();
+        assert_eq!(count, 2 * size);
+        assert_eq!(first, Some(Value::Integer(0)));
+        assert_eq!(last, Some(Value::Integer(1 - size)));
+    }
+
+    /// Counts the bytes each thread holds allocated, and the most it has
+    /// held at once, for tests of how many rows a query holds.
+    struct CountingAllocator;
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    thread_local! {
+        static HELD: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+        static PEAK: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = HELD.try_with(|held| {
+                held.set(held.get() + layout.size());
+                PEAK.with(|peak| peak.set(peak.get().max(held.get())));
+            });
+            unsafe { std::alloc::System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            // Memory freed by another thread than allocated it is not
+            // counted against either.
+            let _ = HELD.try_with(|held| held.set(held.get().saturating_sub(layout.size())));
+            unsafe { std::alloc::System.dealloc(ptr, layout) }
+        }
+    }
+
+    /// The most bytes running `sql` held at once on top of those held
+    /// before, with the result's rows handed over one at a time and
+    /// dropped.
+    fn peak_memory(db: &mut Database, sql: &str) -> usize {
+        let stmt = parse_statement(sql).unwrap();
+        let before = HELD.with(|held| held.get());
+        PEAK.with(|peak| peak.set(before));
+        execute_each(db, &stmt, drop).unwrap();
+        PEAK.with(|peak| peak.get()) - before
+    }
+
+    #[test]
+    fn test_scans_stream_their_rows() {
+        let mut db = Database::new();
+        run(&mut db, &["CREATE TABLE t (id INTEGER, body TEXT)"]).unwrap();
+        let rows = 100_000;
+        let body = "x".repeat(100);
+        db.insert_rows(
+            "t",
+            (0..rows).map(|i| vec![Value::Integer(i), Value::Text(body.clone())]),
+        )
+        .unwrap();
+        let row_size = body.len() + 2 * std::mem::size_of::<Value>();
+        let table_size = rows as usize * row_size;
+        // A sort holds every row, as the meter shows; a limited scan only
+        // the rows it passes on.
+        let sorted = peak_memory(&mut db, "SELECT * FROM t ORDER BY id DESC LIMIT 10");
+        assert!(sorted > table_size, "sorted {sorted} of {table_size}");
+        for sql in [
+            "SELECT * FROM t LIMIT 10",
+            "SELECT id, body || '!' FROM t WHERE id % 2 = 1 LIMIT 10 OFFSET 5",
+            "SELECT * FROM t UNION ALL SELECT * FROM t",
+        ] {
+            let streamed = peak_memory(&mut db, sql);
+            assert!(
+                streamed < table_size / 100,
+                "{sql}: {streamed} of {table_size}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_intersect_and_except() {
+        let mut db = sets();
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM a INTERSECT SELECT * FROM b"]).unwrap(),
+            ["2|q", "NULL|NULL"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM a EXCEPT SELECT * FROM b"]).unwrap(),
+            ["1|p", "3|r"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT * FROM b EXCEPT SELECT * FROM a"]).unwrap(),
+            ["4|s"]
+        );
+    }
+
+    #[test]
+    fn test_set_operations_with_empty_inputs() {
+        let mut db = sets();
+        let rows = |db: &mut Database, sql: &str| query(db, &[sql]).unwrap().len();
+        assert_eq!(rows(&mut db, "SELECT * FROM none UNION SELECT * FROM a"), 4);
+        assert_eq!(
+            rows(&mut db, "SELECT * FROM a UNION ALL SELECT * FROM none"),
+            5
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT * FROM none UNION SELECT * FROM none"),
+            0
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT * FROM a INTERSECT SELECT * FROM none"),
+            0
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT * FROM none INTERSECT SELECT * FROM a"),
+            0
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT * FROM a EXCEPT SELECT * FROM none"),
+            4
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT * FROM none EXCEPT SELECT * FROM a"),
+            0
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT * FROM none EXCEPT SELECT * FROM none"),
+            0
+        );
+    }
+
+    #[test]
+    fn test_set_operation_precedence_and_chaining() {
+        let mut db = sets();
+        // INTERSECT first: a UNION (b INTERSECT {4}) rather than
+        // (a UNION b) INTERSECT {4}.
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT x FROM a UNION SELECT x FROM b INTERSECT SELECT 4"]
+            )
+            .unwrap(),
+            ["1", "2", "NULL", "3", "4"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT 4 INTERSECT SELECT x FROM b UNION SELECT x FROM a"]
+            )
+            .unwrap(),
+            ["4", "1", "2", "NULL", "3"]
+        );
+        // Left to right: (a EXCEPT b) UNION b, not a EXCEPT (b UNION b).
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT x FROM a EXCEPT SELECT x FROM b UNION SELECT x FROM b"]
+            )
+            .unwrap(),
+            ["1", "3", "2", "4", "NULL"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT 1 UNION ALL SELECT 1 UNION SELECT 1"]).unwrap(),
+            ["1"]
+        );
+    }
+
+    #[test]
+    fn test_set_operation_order_and_limit() {
+        let mut db = sets();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT x FROM a UNION SELECT x FROM b ORDER BY x DESC LIMIT 3"]
+            )
+            .unwrap(),
+            ["NULL", "4", "3"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT y, x FROM a UNION SELECT y, x FROM b ORDER BY 2, 1 LIMIT 2"]
+            )
+            .unwrap(),
+            ["p|1", "q|2"]
+        );
+        assert_eq!(
+            run(
+                &mut db,
+                &["SELECT x FROM a UNION SELECT x FROM b ORDER BY y"]
+            )
+            .unwrap_err()
+            .to_string(),
+            "no such column: y"
+        );
+    }
+
+    #[test]
+    fn test_set_operation_column_count_must_match() {
+        let mut db = sets();
+        assert_eq!(
+            run(&mut db, &["SELECT x FROM a UNION SELECT x, y FROM b"])
+                .unwrap_err()
+                .to_string(),
+            "SELECTs to the left and right of UNION do not have the same number of result columns"
+        );
+        assert_eq!(
+            run(&mut db, &["SELECT * FROM a EXCEPT SELECT 1"])
+                .unwrap_err()
+                .to_string(),
+            "SELECTs to the left and right of EXCEPT do not have the same number of result columns"
+        );
+    }
+
+    #[test]
+    fn test_set_operation_in_subqueries() {
+        let mut db = sets();
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT COUNT(*) FROM (SELECT x FROM a UNION SELECT x FROM b) AS u"]
+            )
+            .unwrap(),
+            ["5"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["SELECT y FROM a WHERE x IN (SELECT x FROM b INTERSECT SELECT 2)"]
+            )
+            .unwrap(),
+            ["q", "q"]
+        );
+    }
+
+    /// Run an UPDATE or DELETE, returning how many rows it changed.
+    fn changed(db: &mut Database, sql: &str) -> Result<usize, EvalError> {
+        match parse_statement(sql).unwrap_or_else(|e| panic!("{sql}: {e}")) {
+            Statement::Update(stmt) => update(db, &stmt).map(|(count, _)| count),
+            Statement::Delete(stmt) => delete(db, &stmt).map(|(count, _)| count),
+            other => panic!("not an UPDATE or DELETE: {other}"),
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let mut db = employees();
+        assert_eq!(
+            changed(
+                &mut db,
+                "UPDATE employees SET salary = salary + 5, bonus = 0.5 WHERE dept = 'eng'"
+            ),
+            Ok(2)
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT name, salary, bonus FROM employees"]).unwrap(),
+            [
+                "Ann|105|0.5",
+                "Bob|85|0.5",
+                "Cy|80|NULL",
+                "Di|NULL|2.5",
+                "Ed|60|NULL"
+            ]
+        );
+        assert_eq!(changed(&mut db, "UPDATE employees SET dept = 'all'"), Ok(5));
+        assert_eq!(
+            changed(
+                &mut db,
+                "UPDATE employees SET bonus = 1 WHERE salary > 1000"
+            ),
+            Ok(0)
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT DISTINCT dept FROM employees"]).unwrap(),
+            ["all"]
+        );
+    }
+
+    #[test]
+    fn test_update_to_null_and_coerced_values() {
+        let mut db = employees();
+        run(
+            &mut db,
+            &[
+                "UPDATE employees SET bonus = NULL WHERE name = 'Bob'",
+                "UPDATE employees SET salary = '70' WHERE name = 'Ed'",
+            ],
+        )
+        .unwrap();
+        let rows = run(
+            &mut db,
+            &["SELECT bonus, salary FROM employees WHERE name IN ('Bob', 'Ed')"],
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{rows:?}"),
+            "[[Null, Integer(80)], [Null, Integer(70)]]"
+        );
+        assert!(
+            run(
+                &mut db,
+                &["UPDATE employees SET salary = 'lots' WHERE name = 'Ed'"]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_update_reads_original_values() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (a INTEGER, b INTEGER)",
+                "INSERT INTO t VALUES (1, 2), (3, 4)",
+                "UPDATE t SET a = b, b = a",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT a, b FROM t"]).unwrap(),
+            ["2|1", "4|3"]
+        );
+        // The subquery sees the table as it was before the update.
+        run(&mut db, &["UPDATE t SET a = (SELECT MAX(a) FROM t) + b"]).unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT a, b FROM t"]).unwrap(),
+            ["5|1", "7|3"]
+        );
+    }
+
+    #[test]
+    fn test_update_errors() {
+        let mut db = employees();
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut db, "UPDATE employees SET title = 'x'"),
+            "no such column: title"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE employees SET salary = 1 WHERE nope = 1"),
+            "no such column: nope"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE staff SET salary = 1"),
+            "no such table: staff"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE employees SET salary = MAX(salary)"),
+            "misuse of aggregate: MAX()"
+        );
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut db = employees();
+        assert_eq!(
+            changed(
+                &mut db,
+                "DELETE FROM employees WHERE (dept = 'eng' AND salary < 90) OR bonus > 2"
+            ),
+            Ok(2)
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM employees"]).unwrap(),
+            ["Ann", "Cy", "Ed"]
+        );
+        // FALSE OR NULL is NULL, so the rows without a bonus stay.
+        assert_eq!(
+            changed(
+                &mut db,
+                "DELETE FROM employees WHERE bonus IS NOT NULL OR bonus > 0"
+            ),
+            Ok(0)
+        );
+        assert_eq!(
+            changed(&mut db, "DELETE FROM employees WHERE salary > 1000"),
+            Ok(0)
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT COUNT(*) FROM employees"]).unwrap(),
+            ["3"]
+        );
+        assert_eq!(changed(&mut db, "DELETE FROM employees"), Ok(3));
+        assert!(
+            query(&mut db, &["SELECT * FROM employees"])
+                .unwrap()
+                .is_empty()
+        );
+        run(
+            &mut db,
+            &["INSERT INTO employees VALUES ('Fay', 'eng', 90, NULL)"],
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM employees"]).unwrap(),
+            ["Fay"]
+        );
+    }
+
+    #[test]
+    fn test_returning() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &["CREATE TABLE orders (id INTEGER PRIMARY KEY, amount REAL, status TEXT DEFAULT 'new')"],
+        )
+        .unwrap();
+        // Inserted rows are returned as stored, defaults and coercions
+        // applied.
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "INSERT INTO orders (id, amount) VALUES (1, 99), (2, '5.5') \
+                   RETURNING id, amount, status, TYPEOF(amount)"
+                ]
+            )
+            .unwrap(),
+            ["1|99.0|new|real", "2|5.5|new|real"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["INSERT INTO orders SELECT id + 10, amount, 'old' FROM orders RETURNING *"]
+            )
+            .unwrap(),
+            ["11|99.0|old", "12|5.5|old"]
+        );
+        assert!(
+            query(&mut db, &["INSERT INTO orders VALUES (3, 1, 'x')"])
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "UPDATE orders SET amount = amount * 2 WHERE status <> 'old' \
+                   RETURNING orders.id, amount, (SELECT COUNT(*) FROM orders WHERE amount > 50)"
+                ]
+            )
+            .unwrap(),
+            ["1|198.0|2", "2|11.0|2", "3|2.0|2"]
+        );
+        assert_eq!(
+            query(
+                &mut db,
+                &["DELETE FROM orders WHERE status = 'old' RETURNING id, amount"]
+            )
+            .unwrap(),
+            ["11|99.0", "12|5.5"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT id FROM orders"]).unwrap(),
+            ["1", "2", "3"]
+        );
+        // Nothing is changed by a statement whose RETURNING clause fails.
+        for (sql, error) in [
+            (
+                "DELETE FROM orders RETURNING COUNT(*)",
+                "misuse of aggregate: COUNT()",
+            ),
+            (
+                "UPDATE orders SET status = 'x' RETURNING nope",
+                "no such column: nope",
+            ),
+            (
+                "INSERT INTO orders VALUES (4, 1, 'x') RETURNING SUM(id)",
+                "misuse of aggregate: SUM()",
+            ),
+        ] {
+            assert_eq!(run(&mut db, &[sql]).unwrap_err().to_string(), error);
+        }
+        assert_eq!(
+            query(&mut db, &["SELECT id, status FROM orders"]).unwrap(),
+            ["1|new", "2|new", "3|x"]
+        );
+    }
+
+    #[test]
+    fn test_transactions() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (x INTEGER PRIMARY KEY)",
+                "INSERT INTO t VALUES (1)",
+            ],
+        )
+        .unwrap();
+        run(
+            &mut db,
+            &[
+                "BEGIN",
+                "INSERT INTO t VALUES (2)",
+                "UPDATE t SET x = x * 10",
+                "CREATE TABLE u (y)",
+                "CREATE INDEX t_x ON t (x)",
+            ],
+        )
+        .unwrap();
+        assert!(db.in_transaction());
+        // The transaction's own statements see its changes.
+        assert_eq!(query(&mut db, &["SELECT x FROM t"]).unwrap(), ["10", "20"]);
+        run(&mut db, &["ROLLBACK"]).unwrap();
+        assert!(!db.in_transaction());
+        assert_eq!(query(&mut db, &["SELECT x FROM t"]).unwrap(), ["1"]);
+        assert!(db.get_table("u").is_none());
+        assert!(db.get_index("t_x").is_none());
+
+        run(
+            &mut db,
+            &["BEGIN TRANSACTION", "INSERT INTO t VALUES (2)", "COMMIT"],
+        )
+        .unwrap();
+        assert_eq!(query(&mut db, &["SELECT x FROM t"]).unwrap(), ["1", "2"]);
+        // A failed statement leaves the transaction open.
+        run(&mut db, &["BEGIN", "DELETE FROM t WHERE x = 1"]).unwrap();
+        assert!(run(&mut db, &["INSERT INTO t VALUES (2)"]).is_err());
+        assert_eq!(
+            run(&mut db, &["BEGIN"]).unwrap_err().to_string(),
+            "cannot start a transaction within a transaction"
+        );
+        run(&mut db, &["END"]).unwrap();
+        assert_eq!(query(&mut db, &["SELECT x FROM t"]).unwrap(), ["2"]);
+
+        assert_eq!(
+            run(&mut db, &["COMMIT"]).unwrap_err().to_string(),
+            "cannot commit - no transaction is active"
+        );
+        assert_eq!(
+            run(&mut db, &["ROLLBACK"]).unwrap_err().to_string(),
+            "cannot rollback - no transaction is active"
+        );
+    }
+
+    #[test]
+    fn test_savepoints() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE t (x INTEGER)",
+                "BEGIN",
+                "INSERT INTO t VALUES (1)",
+                "SAVEPOINT a",
+                "INSERT INTO t VALUES (2)",
+                "SAVEPOINT b",
+                "INSERT INTO t VALUES (3)",
+                "SAVEPOINT c",
+                "INSERT INTO t VALUES (4)",
+            ],
+        )
+        .unwrap();
+        // Rolling back to b undoes its changes and releases c, but b and
+        // the transaction stay open.
+        run(&mut db, &["ROLLBACK TO SAVEPOINT b"]).unwrap();
+        assert_eq!(query(&mut db, &["SELECT x FROM t"]).unwrap(), ["1", "2"]);
+        assert_eq!(
+            run(&mut db, &["ROLLBACK TO c"]).unwrap_err().to_string(),
+            "no such savepoint: c"
+        );
+        run(&mut db, &["INSERT INTO t VALUES (5)", "ROLLBACK TO b"]).unwrap();
+        assert_eq!(query(&mut db, &["SELECT x FROM t"]).unwrap(), ["1", "2"]);
+        // Released savepoints keep their changes but cannot be rolled
+        // back to.
+        run(&mut db, &["INSERT INTO t VALUES (6)", "RELEASE b"]).unwrap();
+        assert!(run(&mut db, &["ROLLBACK TO b"]).is_err());
+        assert!(db.in_transaction());
+        run(&mut db, &["COMMIT"]).unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT x FROM t"]).unwrap(),
+            ["1", "2", "6"]
+        );
+        assert!(run(&mut db, &["RELEASE a"]).is_err());
+
+        // Names are case-sensitive, and a repeated name means the
+        // innermost savepoint.
+        run(
+            &mut db,
+            &[
+                "BEGIN",
+                "SAVEPOINT s",
+                "DELETE FROM t WHERE x = 1",
+                "SAVEPOINT s",
+                "DELETE FROM t WHERE x = 2",
+            ],
+        )
+        .unwrap();
+        assert!(run(&mut db, &["ROLLBACK TO S"]).is_err());
+        run(&mut db, &["ROLLBACK TO s"]).unwrap();
+        assert_eq!(query(&mut db, &["SELECT x FROM t"]).unwrap(), ["2", "6"]);
+        run(&mut db, &["ROLLBACK"]).unwrap();
+        assert_eq!(
+            query(&mut db, &["SELECT x FROM t"]).unwrap(),
+            ["1", "2", "6"]
+        );
+
+        // A savepoint outside a transaction starts one, which releasing
+        // it commits and rolling back undoes.
+        run(&mut db, &["SAVEPOINT a", "DELETE FROM t"]).unwrap();
+        assert_eq!(
+            run(&mut db, &["BEGIN"]).unwrap_err().to_string(),
+            "cannot start a transaction within a transaction"
+        );
+        run(&mut db, &["ROLLBACK"]).unwrap();
+        assert_eq!(query(&mut db, &["SELECT COUNT(*) FROM t"]).unwrap(), ["3"]);
+        run(&mut db, &["SAVEPOINT a", "DELETE FROM t", "RELEASE a"]).unwrap();
+        assert!(!db.in_transaction());
+        assert!(query(&mut db, &["SELECT x FROM t"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_delete_with_subquery() {
+        let mut db = employees();
+        // The subquery sees every row, including those being deleted.
+        assert_eq!(
+            changed(
+                &mut db,
+                "DELETE FROM employees WHERE salary < (SELECT MAX(salary) FROM employees)"
+            ),
+            Ok(3)
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT name FROM employees"]).unwrap(),
+            ["Ann", "Di"]
+        );
+    }
+
+    #[test]
+    fn test_delete_errors() {
+        let mut db = employees();
+        let error = |db: &mut Database, sql: &str| run(db, &[sql]).unwrap_err().to_string();
+        assert_eq!(error(&mut db, "DELETE FROM staff"), "no such table: staff");
+        assert_eq!(
+            error(&mut db, "DELETE FROM employees WHERE nope = 1"),
+            "no such column: nope"
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT COUNT(*) FROM employees"]).unwrap(),
+            ["5"]
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut db = employees();
+        run(
+            &mut db,
+            &[
+                "CREATE TABLE depts (dept TEXT, floor INTEGER)",
+                "CREATE INDEX by_dept ON employees (dept)",
+            ],
+        )
+        .unwrap();
+        // Each node's estimates follow it.
+        assert_eq!(
+            query(
+                &mut db,
+                &["EXPLAIN SELECT name FROM employees WHERE salary > 70"]
+            )
+            .unwrap(),
+            [
+                "Projection(name) rows=1.25 cost=11.25",
+                "  Filter(salary > 70) rows=1.25 cost=10",
+                "    TableScan(employees) rows=5 cost=5",
+            ]
+        );
+        let mut explain = |sql: &str| {
+            let lines = query(&mut db, &[&format!("EXPLAIN {sql}")]).unwrap();
+            lines
+                .iter()
+                .map(|line| line.split(" rows=").next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            explain("SELECT name FROM employees WHERE dept = 'eng'"),
+            [
+                "Projection(name)",
+                "  Filter(dept = 'eng')",
+                "    IndexScan(employees USING by_dept, key 'eng')",
+            ]
+        );
+        assert_eq!(
+            explain("SELECT e.name, d.floor FROM employees e LEFT JOIN depts d ON e.dept = d.dept"),
+            [
+                "Projection(e.name, d.floor)",
+                "  NestedLoopJoin(LEFT JOIN ON e.dept = d.dept)",
+                "    TableScan(employees)",
+                "    TableScan(depts)",
+            ]
+        );
+        assert_eq!(
+            explain("SELECT dept, COUNT(*) FROM employees GROUP BY dept"),
+            [
+                "Projection(dept, \"COUNT(*)\")",
+                "  Aggregate(GROUP BY dept; COUNT(*))",
+                "    TableScan(employees)",
+            ]
+        );
+        assert_eq!(
+            explain("SELECT name FROM employees ORDER BY salary DESC, name LIMIT 2 OFFSET 1"),
+            [
+                "Limit(2 OFFSET 1)",
+                "  Projection(name)",
+                "    Sort(salary DESC, name)",
+                "      TableScan(employees)",
+            ]
+        );
+        assert_eq!(
+            explain("SELECT DISTINCT dept FROM employees UNION ALL SELECT dept FROM depts"),
+            [
+                "SetOperation(UNION ALL)",
+                "  Distinct",
+                "    Projection(dept)",
+                "      TableScan(employees)",
+                "  Projection(dept)",
+                "    TableScan(depts)",
+            ]
+        );
+        // Planning errors are reported as they would be for the query.
+        assert_eq!(
+            run(&mut db, &["EXPLAIN SELECT nope FROM employees"])
+                .unwrap_err()
+                .to_string(),
+            "no such column: nope"
+        );
+    }
+
+    #[test]
+    fn test_analyze() {
+        let mut db = employees();
+        let filter = |db: &mut Database, predicate: &str| {
+            let sql = format!("EXPLAIN SELECT name FROM employees WHERE {predicate}");
+            let lines = query(db, &[&sql]).unwrap();
+            lines[1].trim().to_string()
+        };
+        assert_eq!(
+            filter(&mut db, "dept = 'eng'"),
+            "Filter(dept = 'eng') rows=1.25 cost=10"
+        );
+        assert!(query(&mut db, &["ANALYZE employees"]).unwrap().is_empty());
+        // One of the three departments.
+        assert_eq!(
+            filter(&mut db, "dept = 'eng'"),
+            "Filter(dept = 'eng') rows=1.67 cost=10"
+        );
+        // Three of the four buckets of salaries hold more than 70.
+        assert_eq!(
+            filter(&mut db, "salary > 70"),
+            "Filter(salary > 70) rows=3.75 cost=10"
+        );
+        assert_eq!(
+            filter(&mut db, "bonus IS NULL"),
+            "Filter(bonus IS NULL) rows=3 cost=10"
+        );
+        run(
+            &mut db,
+            &["INSERT INTO employees VALUES ('Fy', 'hr', 50, NULL), ('Gu', 'pr', 40, NULL)"],
+        )
+        .unwrap();
+        // The statistics stand until the table is analyzed again.
+        assert_eq!(
+            filter(&mut db, "dept = 'eng'"),
+            "Filter(dept = 'eng') rows=2.33 cost=14"
+        );
+        run(&mut db, &["ANALYZE"]).unwrap();
+        assert_eq!(
+            filter(&mut db, "dept = 'eng'"),
+            "Filter(dept = 'eng') rows=1.4 cost=14"
+        );
+        assert_eq!(
+            run(&mut db, &["ANALYZE staff"]).unwrap_err().to_string(),
+            "no such table: staff"
+        );
+    }
+}
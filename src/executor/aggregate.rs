@@ -0,0 +1,184 @@
+//! Aggregation.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+
+use super::functions::StringAggAccumulator;
+use super::{Context, EvalError, Evaluator, eval_binop, execute_plan};
+use crate::ast::{AggFunc, BinOp, Expr};
+use crate::planner::{AggregateCall, PlanNode, SortKey};
+use crate::storage::Row;
+use crate::types::{DataType, OrdValue, Value, coerce};
+
+/// Group the input rows by the values of `group_by` and compute every call
+/// over each group. An output row holds the group's key values followed by
+/// one value per call, and groups come out in key order. Without grouping
+/// expressions the whole input forms one group, so an empty input still
+/// produces a row.
+pub(super) fn aggregate(
+    ctx: Context<'_>,
+    input: &PlanNode,
+    group_by: &[Expr],
+    calls: &[AggregateCall],
+) -> Result<Vec<Row>, EvalError> {
+    let schema = input.schema();
+    let new_group = || calls.iter().map(Accumulator::new).collect::<Vec<_>>();
+    let mut groups = BTreeMap::new();
+    if group_by.is_empty() {
+        groups.insert(Vec::new(), new_group());
+    }
+    for row in execute_plan(ctx, input) {
+        let row = row?;
+        let evaluator = Evaluator::new(ctx, schema, &row);
+        let key = group_by
+            .iter()
+            .map(|e| evaluator.eval(e).map(OrdValue))
+            .collect::<Result<Vec<_>, _>>()?;
+        let accumulators = groups.entry(key).or_insert_with(new_group);
+        for (accumulator, call) in accumulators.iter_mut().zip(calls) {
+            if let Some(filter) = &call.filter
+                && !evaluator.holds(filter)?
+            {
+                continue;
+            }
+            let value = match &call.arg {
+                Some(arg) => evaluator.eval(arg)?,
+                // `COUNT(*)` counts rows, so any non-NULL value will do.
+                None => Value::Integer(1),
+            };
+            let sort_key = call
+                .order_by
+                .iter()
+                .map(|key| evaluator.eval(&key.expr))
+                .collect::<Result<_, _>>()?;
+            accumulator.add(value, sort_key)?;
+        }
+    }
+    Ok(groups
+        .into_iter()
+        .map(|(key, accumulators)| {
+            let values = accumulators
+                .into_iter()
+                .zip(calls)
+                .map(|(accumulator, call)| accumulator.finish(&call.order_by));
+            key.into_iter().map(|k| k.0).chain(values).collect()
+        })
+        .collect())
+}
+
+/// The running state of one aggregate call over one group.
+#[derive(Clone)]
+pub(super) struct Accumulator {
+    func: AggFunc,
+    /// The values counted so far, for a DISTINCT call.
+    seen: Option<HashSet<OrdValue>>,
+    /// How many non-NULL values were counted.
+    count: i64,
+    /// The running total for SUM and AVG, or the extreme value so far for
+    /// MIN and MAX; NULL until the first non-NULL value.
+    value: Value,
+    /// The values so far of a GROUP_CONCAT call.
+    strings: Option<StringAggAccumulator>,
+    /// The mean of the values so far and the sum of their squared
+    /// differences from it, for the variance functions. Welford's method
+    /// updates both per value, which stays accurate where subtracting a sum
+    /// of squares would cancel out.
+    mean: f64,
+    squares: f64,
+}
+
+impl Accumulator {
+    pub(super) fn new(call: &AggregateCall) -> Self {
+        Accumulator {
+            func: call.func,
+            seen: call.distinct.then(HashSet::new),
+            count: 0,
+            value: Value::Null,
+            strings: call.delimiter.as_deref().map(StringAggAccumulator::new),
+            mean: 0.0,
+            squares: 0.0,
+        }
+    }
+
+    /// Fold one value in, from a row with the given values of the call's
+    /// ORDER BY keys. NULLs are ignored by every aggregate.
+    pub(super) fn add(&mut self, value: Value, sort_key: Vec<Value>) -> Result<(), EvalError> {
+        if value.is_null() {
+            return Ok(());
+        }
+        if let Some(seen) = &mut self.seen
+            && !seen.insert(OrdValue(value.clone()))
+        {
+            return Ok(());
+        }
+        self.count += 1;
+        match self.func {
+            AggFunc::Count => {}
+            AggFunc::Sum | AggFunc::Avg => {
+                let total = std::mem::replace(&mut self.value, Value::Null);
+                let total = if total.is_null() {
+                    Value::Integer(0)
+                } else {
+                    total
+                };
+                self.value = eval_binop(BinOp::Add, total, value)?;
+            }
+            AggFunc::Min if self.extreme_is(&value, Ordering::Less) => self.value = value,
+            AggFunc::Max if self.extreme_is(&value, Ordering::Greater) => self.value = value,
+            AggFunc::Min | AggFunc::Max => {}
+            AggFunc::GroupConcat => {
+                if let Some(strings) = &mut self.strings {
+                    strings.add(sort_key, &value);
+                }
+            }
+            AggFunc::VarPop | AggFunc::VarSamp | AggFunc::StddevPop | AggFunc::StddevSamp => {
+                let Value::Real(x) = coerce(value, DataType::Real)? else {
+                    unreachable!("a non-NULL value coerced to REAL");
+                };
+                let delta = x - self.mean;
+                self.mean += delta / self.count as f64;
+                self.squares += delta * (x - self.mean);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `value` compares to the current extreme as `wanted`, or there
+    /// is none yet.
+    fn extreme_is(&self, value: &Value, wanted: Ordering) -> bool {
+        self.value.is_null() || OrdValue(value.clone()).cmp(&OrdValue(self.value.clone())) == wanted
+    }
+
+    /// The aggregate's value, concatenating in the order of `order_by` for
+    /// GROUP_CONCAT.
+    pub(super) fn finish(self, order_by: &[SortKey]) -> Value {
+        match self.func {
+            AggFunc::Count => Value::Integer(self.count),
+            AggFunc::Sum | AggFunc::Min | AggFunc::Max => self.value,
+            AggFunc::Avg => match coerce(self.value, DataType::Real) {
+                Ok(Value::Real(total)) => Value::Real(total / self.count as f64),
+                _ => Value::Null,
+            },
+            AggFunc::GroupConcat => self
+                .strings
+                .map_or(Value::Null, |strings| strings.finish(order_by)),
+            AggFunc::VarPop | AggFunc::StddevPop if self.count > 0 => {
+                self.deviation(self.squares / self.count as f64)
+            }
+            AggFunc::VarSamp | AggFunc::StddevSamp if self.count > 1 => {
+                self.deviation(self.squares / (self.count - 1) as f64)
+            }
+            AggFunc::VarPop | AggFunc::VarSamp | AggFunc::StddevPop | AggFunc::StddevSamp => {
+                Value::Null
+            }
+        }
+    }
+
+    /// `variance`, or its square root for the standard deviations.
+    fn deviation(&self, variance: f64) -> Value {
+        match self.func {
+            AggFunc::StddevPop | AggFunc::StddevSamp => Value::Real(variance.sqrt()),
+            _ => Value::Real(variance),
+        }
+    }
+}
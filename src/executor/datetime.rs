@@ -0,0 +1,424 @@
+//! Dates and times, in UTC and the proleptic Gregorian calendar, as SQLite
+//! keeps them.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ast::CurrentTime;
+
+/// A calendar date and a time of day, to the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    /// The moment `secs` seconds after 1970-01-01 00:00:00.
+    pub fn from_unix(secs: i64) -> Self {
+        let days = secs.div_euclid(86_400);
+        let time = secs.rem_euclid(86_400) as u32;
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: time / 3600,
+            minute: time / 60 % 60,
+            second: time % 60,
+        }
+    }
+
+    pub fn at(time: SystemTime) -> Self {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => after.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs_f64().ceil() as i64),
+        };
+        Self::from_unix(secs)
+    }
+
+    /// Seconds since 1970-01-01 00:00:00.
+    pub fn to_unix(self) -> i64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        days * 86_400 + i64::from(self.hour * 3600 + self.minute * 60 + self.second)
+    }
+
+    /// The Julian day number of this moment: days since noon on
+    /// 4714-11-24 BC, with the time of day as a fraction.
+    pub fn to_julian_day(self) -> f64 {
+        self.to_unix() as f64 / 86_400.0 + 2_440_587.5
+    }
+
+    /// The moment a Julian day number names, to the nearest second.
+    pub fn from_julian_day(day: f64) -> Option<Self> {
+        Self::from_unix_checked((day - 2_440_587.5) * 86_400.0)
+    }
+
+    /// The moment `secs` seconds after 1970, to the nearest second, if it
+    /// is in the years 0000 to 9999.
+    pub fn from_unix_checked(secs: f64) -> Option<Self> {
+        let secs = secs.round();
+        if !secs.is_finite() || secs.abs() > MAX_SECONDS {
+            return None;
+        }
+        Self::from_unix(secs as i64).in_range()
+    }
+
+    /// This moment moved by one of the modifiers SQLite's date functions
+    /// take:
+    ///
+    /// - `±N days`, `hours`, `minutes` or `seconds`, `N` perhaps having a
+    ///   fraction;
+    /// - `±N months` or `years`, `N` whole, keeping the day of the month
+    ///   and rolling past the end of a short month into the next one, so
+    ///   that a month after 2024-01-31 is 2024-03-02;
+    /// - `start of day`, `start of month` or `start of year`.
+    ///
+    /// `None` for any other modifier, or a moment outside the years 0000
+    /// to 9999.
+    pub fn modify(&self, modifier: &str) -> Option<Self> {
+        let modifier = modifier.trim().to_ascii_lowercase();
+        let midnight = DateTime {
+            hour: 0,
+            minute: 0,
+            second: 0,
+            ..*self
+        };
+        match modifier.as_str() {
+            "start of day" => return Some(midnight),
+            "start of month" => return Some(DateTime { day: 1, ..midnight }),
+            "start of year" => {
+                return Some(DateTime {
+                    month: 1,
+                    day: 1,
+                    ..midnight
+                });
+            }
+            _ => {}
+        }
+        let (amount, unit) = modifier.split_once(char::is_whitespace)?;
+        let unit = unit.trim_start();
+        let unit = unit.strip_suffix('s').unwrap_or(unit);
+        // Rust reads `inf` and `NaN` as floats too; SQL does not.
+        if !amount
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.'))
+        {
+            return None;
+        }
+        let amount: f64 = amount.parse().ok()?;
+        let seconds = match unit {
+            "day" => 86_400.0,
+            "hour" => 3600.0,
+            "minute" => 60.0,
+            "second" => 1.0,
+            "month" | "year" if amount.fract() == 0.0 && amount.abs() < 120_000.0 => {
+                let months = amount as i64 * if unit == "year" { 12 } else { 1 };
+                return self.add_months(months);
+            }
+            _ => return None,
+        };
+        Self::from_unix_checked(self.to_unix() as f64 + amount * seconds)
+    }
+
+    fn add_months(&self, months: i64) -> Option<Self> {
+        let total = self.year * 12 + i64::from(self.month - 1) + months;
+        let moved = DateTime {
+            year: total.div_euclid(12),
+            month: total.rem_euclid(12) as u32 + 1,
+            ..*self
+        };
+        moved.in_range()?;
+        Self::from_unix(moved.to_unix()).in_range()
+    }
+
+    fn in_range(self) -> Option<Self> {
+        (0..=9999).contains(&self.year).then_some(self)
+    }
+
+    /// This moment written out by a `STRFTIME` format: `%Y`, `%m`, `%d`,
+    /// `%H`, `%M` and `%S` for its fields, `%F` for `%Y-%m-%d`, `%T` for
+    /// `%H:%M:%S`, `%j` for the day of the year, `%w` for the day of the
+    /// week from Sunday as 0, `%s` for seconds since 1970 and `%%` for
+    /// `%`. `None` if the format has any other `%` code.
+    pub fn strftime(&self, format: &str) -> Option<String> {
+        let mut out = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            let days = days_from_civil(self.year, self.month, self.day);
+            let field = match chars.next()? {
+                'Y' => format!("{:04}", self.year),
+                'm' => format!("{:02}", self.month),
+                'd' => format!("{:02}", self.day),
+                'H' => format!("{:02}", self.hour),
+                'M' => format!("{:02}", self.minute),
+                'S' => format!("{:02}", self.second),
+                'F' => self.date(),
+                'T' => self.time(),
+                'j' => format!("{:03}", days - days_from_civil(self.year, 1, 1) + 1),
+                // 1970-01-01 was a Thursday.
+                'w' => (days + 4).rem_euclid(7).to_string(),
+                's' => self.to_unix().to_string(),
+                '%' => "%".to_string(),
+                _ => return None,
+            };
+            out.push_str(&field);
+        }
+        Some(out)
+    }
+
+    /// `YYYY-MM-DD`
+    pub fn date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// `HH:MM:SS`
+    pub fn time(&self) -> String {
+        format!("{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+
+    /// The text `CURRENT_DATE`, `CURRENT_TIME` or `CURRENT_TIMESTAMP`
+    /// gives for this moment.
+    pub fn format(&self, kind: CurrentTime) -> String {
+        match kind {
+            CurrentTime::Date => self.date(),
+            CurrentTime::Time => self.time(),
+            CurrentTime::Timestamp => format!("{} {}", self.date(), self.time()),
+        }
+    }
+}
+
+/// Seconds either side of 1970 beyond which no moment is in the years
+/// 0000 to 9999.
+const MAX_SECONDS: f64 = 1e12;
+
+/// The moment text in one of the forms SQLite reads names: a date
+/// `YYYY-MM-DD`, which may be followed by a space or `T` and a time, or a
+/// time alone, which falls on 2000-01-01. A time is `HH:MM`, `HH:MM:SS` or
+/// `HH:MM:SS.SSS`, the fraction of a second being dropped. A day past the
+/// end of its month rolls into the next, as SQLite's does, so 2023-02-30
+/// is 2023-03-02.
+pub fn parse_datetime(s: &str) -> Option<DateTime> {
+    let s = s.trim();
+    if let Some((hour, minute, second)) = parse_time(s) {
+        return Some(DateTime {
+            year: 2000,
+            month: 1,
+            day: 1,
+            hour,
+            minute,
+            second,
+        });
+    }
+    let (date, time) = match (s.get(..10), s.get(10..)) {
+        (Some(date), Some("")) => (date, (0, 0, 0)),
+        (Some(date), Some(rest)) if rest.starts_with([' ', 'T']) => {
+            (date, parse_time(rest[1..].trim_start())?)
+        }
+        _ => return None,
+    };
+    let mut parts = date.split('-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let (year, month, day) = (digits(year)?, digits(month)?, digits(day)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let moment = DateTime {
+        year: i64::from(year),
+        month,
+        day,
+        hour: time.0,
+        minute: time.1,
+        second: time.2,
+    };
+    Some(DateTime::from_unix(moment.to_unix()))
+}
+
+/// `HH:MM`, `HH:MM:SS` or `HH:MM:SS.SSS`, as hours, minutes and seconds.
+fn parse_time(s: &str) -> Option<(u32, u32, u32)> {
+    let (s, fraction) = match s.split_once('.') {
+        Some((s, fraction)) => (s, Some(fraction)),
+        None => (s, None),
+    };
+    let mut parts = s.split(':');
+    let hour = parts.next()?;
+    let minute = parts.next()?;
+    let second = parts.next();
+    if parts.next().is_some()
+        || fraction.is_some_and(|f| digits(f).is_none() || second.is_none())
+        || [Some(hour), Some(minute), second]
+            .into_iter()
+            .flatten()
+            .any(|part| part.len() != 2)
+    {
+        return None;
+    }
+    let (hour, minute) = (digits(hour)?, digits(minute)?);
+    let second = second.map_or(Some(0), digits)?;
+    (hour < 24 && minute < 60 && second < 60).then_some((hour, minute, second))
+}
+
+/// A run of ASCII digits as a number.
+fn digits(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// The count of days since 1970-01-01 of a date, after Howard Hinnant's
+/// `days_from_civil`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let doy = (153 * if month > 2 { month - 3 } else { month + 9 } + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The year, month and day of a count of days since 1970-01-01, after
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_unix() {
+        let at = |secs| DateTime::from_unix(secs).format(CurrentTime::Timestamp);
+        assert_eq!(at(0), "1970-01-01 00:00:00");
+        assert_eq!(at(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(at(1_700_000_000), "2023-11-14 22:13:20");
+        assert_eq!(at(-1), "1969-12-31 23:59:59");
+        let moment = DateTime::from_unix(1_700_000_000);
+        assert_eq!(moment.format(CurrentTime::Date), "2023-11-14");
+        assert_eq!(moment.format(CurrentTime::Time), "22:13:20");
+        assert_eq!(moment.to_unix(), 1_700_000_000);
+        assert_eq!(DateTime::from_unix(-1).to_unix(), -1);
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        let parse = |s| parse_datetime(s).map(|m| m.format(CurrentTime::Timestamp));
+        assert_eq!(parse("2024-03-15").unwrap(), "2024-03-15 00:00:00");
+        assert_eq!(parse("2024-03-15 10:30:45").unwrap(), "2024-03-15 10:30:45");
+        assert_eq!(parse("2024-03-15T10:30").unwrap(), "2024-03-15 10:30:00");
+        assert_eq!(
+            parse(" 2024-03-15 10:30:45.123 ").unwrap(),
+            "2024-03-15 10:30:45"
+        );
+        assert_eq!(parse("10:30:45").unwrap(), "2000-01-01 10:30:45");
+        assert_eq!(parse("2023-02-30").unwrap(), "2023-03-02 00:00:00");
+        for bad in [
+            "",
+            "2024-3-15",
+            "2024-13-01",
+            "2024-03-32",
+            "2024-03-15 24:00",
+            "2024-03-15 10:60",
+            "2024-03-15X10:30",
+            "10:30.5",
+            "20240315",
+            "today",
+        ] {
+            assert_eq!(parse(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_modify() {
+        let at = |s, modifiers: &[&str]| {
+            modifiers
+                .iter()
+                .try_fold(parse_datetime(s).unwrap(), |m, modifier| m.modify(modifier))
+                .map(|m| m.format(CurrentTime::Timestamp))
+        };
+        let start = "2024-03-15 10:30:45";
+        assert_eq!(at(start, &["+1 day"]).unwrap(), "2024-03-16 10:30:45");
+        assert_eq!(at(start, &["-15 days"]).unwrap(), "2024-02-29 10:30:45");
+        assert_eq!(at(start, &["+1.5 days"]).unwrap(), "2024-03-16 22:30:45");
+        assert_eq!(at(start, &["+14 hours"]).unwrap(), "2024-03-16 00:30:45");
+        assert_eq!(at(start, &["-31 minutes"]).unwrap(), "2024-03-15 09:59:45");
+        assert_eq!(at(start, &["+15 seconds"]).unwrap(), "2024-03-15 10:31:00");
+        assert_eq!(at(start, &["+1 month"]).unwrap(), "2024-04-15 10:30:45");
+        assert_eq!(at(start, &["-3 months"]).unwrap(), "2023-12-15 10:30:45");
+        assert_eq!(at(start, &["+10 years"]).unwrap(), "2034-03-15 10:30:45");
+        assert_eq!(
+            at("2024-01-31", &["+1 month"]).unwrap(),
+            "2024-03-02 00:00:00"
+        );
+        assert_eq!(
+            at("2024-02-29", &["+1 year"]).unwrap(),
+            "2025-03-01 00:00:00"
+        );
+        assert_eq!(at(start, &["start of day"]).unwrap(), "2024-03-15 00:00:00");
+        assert_eq!(
+            at(start, &["START OF MONTH"]).unwrap(),
+            "2024-03-01 00:00:00"
+        );
+        assert_eq!(
+            at(start, &["start of year"]).unwrap(),
+            "2024-01-01 00:00:00"
+        );
+        assert_eq!(
+            at(start, &["start of month", "+1 month", "-1 day"]).unwrap(),
+            "2024-03-31 00:00:00"
+        );
+        for bad in [
+            "+1 fortnight",
+            "1.5 months",
+            "+inf days",
+            "+1e300 seconds",
+            "+10000 years",
+            "start of week",
+            "day",
+        ] {
+            assert_eq!(at(start, &[bad]), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_strftime() {
+        let moment = parse_datetime("2024-03-05 07:08:09").unwrap();
+        assert_eq!(
+            moment.strftime("%Y/%m/%d %H-%M-%S").unwrap(),
+            "2024/03/05 07-08-09"
+        );
+        assert_eq!(moment.strftime("%F %T").unwrap(), "2024-03-05 07:08:09");
+        assert_eq!(
+            moment.strftime("day %j, %w, 100%%").unwrap(),
+            "day 065, 2, 100%"
+        );
+        assert_eq!(moment.strftime("%s").unwrap(), moment.to_unix().to_string());
+        assert_eq!(moment.strftime("%Q"), None);
+        assert_eq!(moment.strftime("%"), None);
+        assert_eq!(
+            DateTime::from_julian_day(2_460_000.5).unwrap().date(),
+            "2023-02-25"
+        );
+    }
+}
@@ -0,0 +1,863 @@
+//! Expression evaluation and operator semantics.
+//!
+//! Arithmetic propagates NULL: if either operand is NULL the result is NULL.
+//! Integer arithmetic that overflows an i64 falls back to REAL, and division
+//! or remainder by zero yields NULL, both as in SQLite. Boolean operators
+//! use three-valued logic, where `None` stands for the unknown (NULL) truth
+//! value.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+use super::datetime::DateTime;
+use super::functions::FunctionRegistry;
+use super::glob::eval_glob;
+use super::like::like_match;
+use super::{Context, EvalError, OuterRow, execute_plan};
+use crate::ast::{BinOp, Expr, Quantifier, SubqueryKind, UnaryOp};
+use crate::planner::{PlanNode, Schema, SubqueryPlan};
+use crate::storage::{Database, Row};
+use crate::types::{
+    DataType, OrdValue, Value, cast_value, collated_compare, parse_number, sql_compare,
+};
+
+/// Kleene AND: FALSE dominates, then NULL.
+pub fn three_valued_and(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+/// Kleene OR: TRUE dominates, then NULL.
+pub fn three_valued_or(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(true), _) | (_, Some(true)) => Some(true),
+        (Some(false), Some(false)) => Some(false),
+        _ => None,
+    }
+}
+
+pub fn three_valued_not(a: Option<bool>) -> Option<bool> {
+    a.map(|b| !b)
+}
+
+/// Interpret a value as a truth value. NULL is unknown; numbers are true
+/// when non-zero; text is true when it is a non-zero number or, if it is
+/// not numeric at all, when it is non-empty.
+pub fn truth_value(value: &Value) -> Option<bool> {
+    match value {
+        Value::Null => None,
+        Value::Boolean(b) => Some(*b),
+        Value::Integer(n) => Some(*n != 0),
+        Value::Real(n) => Some(*n != 0.0),
+        Value::Text(s) => match parse_number(s) {
+            Some(n) => truth_value(&n),
+            None => Some(!s.is_empty()),
+        },
+        Value::Blob(bytes) => Some(!bytes.is_empty()),
+    }
+}
+
+/// Whether a predicate result lets a row through a filter. NULL does not.
+pub fn predicate_holds(value: &Value) -> bool {
+    truth_value(value) == Some(true)
+}
+
+fn bool_value(b: Option<bool>) -> Value {
+    b.map_or(Value::Null, Value::Boolean)
+}
+
+/// The rows an `IN` tests membership of, single values being rows of one
+/// column: the distinct ones without NULLs, and those with.
+#[derive(Debug, Default)]
+pub struct ValueSet {
+    rows: HashSet<Vec<OrdValue>>,
+    with_nulls: Vec<Vec<OrdValue>>,
+}
+
+impl ValueSet {
+    /// Whether the set holds a row equal to `row`: TRUE if it does, FALSE
+    /// if every row differs from it in a non-NULL value, and NULL if that
+    /// depends on a NULL, either in `row` or in a row of the set. Nothing
+    /// is in an empty set, not even NULL.
+    pub fn contains(&self, row: &[Value]) -> Option<bool> {
+        let key = row.iter().cloned().map(OrdValue).collect::<Vec<_>>();
+        let has_null = row.iter().any(Value::is_null);
+        if !has_null && self.rows.contains(&key) {
+            return Some(true);
+        }
+        // Rows that differ from `row` only where one of them is NULL.
+        let may_equal = |other: &&Vec<OrdValue>| {
+            key.iter()
+                .zip(other.iter())
+                .all(|(a, b)| a.0.is_null() || b.0.is_null() || a == b)
+        };
+        let unknown = self.with_nulls.iter().any(|other| may_equal(&other))
+            || has_null && self.rows.iter().any(|other| may_equal(&other));
+        if unknown { None } else { Some(false) }
+    }
+}
+
+impl FromIterator<Vec<Value>> for ValueSet {
+    fn from_iter<I: IntoIterator<Item = Vec<Value>>>(iter: I) -> Self {
+        let mut set = ValueSet::default();
+        for row in iter {
+            let has_null = row.iter().any(Value::is_null);
+            let row = row.into_iter().map(OrdValue).collect();
+            if has_null {
+                set.with_nulls.push(row);
+            } else {
+                set.rows.insert(row);
+            }
+        }
+        set
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Num {
+    Int(i64),
+    Real(f64),
+}
+
+impl Num {
+    fn as_f64(self) -> f64 {
+        match self {
+            Num::Int(n) => n as f64,
+            Num::Real(n) => n,
+        }
+    }
+}
+
+/// The numeric value of an arithmetic operand, or `None` for NULL. Other
+/// values convert as CAST converts them to a number, so one that is not
+/// a number, such as `'abc'` or a blob, is NULL too.
+fn numeric(value: &Value) -> Option<Num> {
+    match value {
+        Value::Null | Value::Blob(_) => None,
+        Value::Integer(n) => Some(Num::Int(*n)),
+        Value::Boolean(b) => Some(Num::Int(*b as i64)),
+        Value::Real(n) => Some(Num::Real(*n)),
+        Value::Text(s) => match parse_number(s)? {
+            Value::Integer(n) => Some(Num::Int(n)),
+            Value::Real(n) => Some(Num::Real(n)),
+            _ => None,
+        },
+    }
+}
+
+fn real(n: f64) -> Value {
+    // NaN (e.g. from inf - inf) is not a SQL value.
+    if n.is_nan() {
+        Value::Null
+    } else {
+        Value::Real(n)
+    }
+}
+
+fn arithmetic(op: BinOp, a: Num, b: Num) -> Value {
+    if let (Num::Int(x), Num::Int(y)) = (a, b) {
+        let checked = match op {
+            BinOp::Add => x.checked_add(y),
+            BinOp::Sub => x.checked_sub(y),
+            BinOp::Mul => x.checked_mul(y),
+            BinOp::Div if y == 0 => return Value::Null,
+            BinOp::Div => x.checked_div(y),
+            BinOp::Mod if y == 0 => return Value::Null,
+            // i64::MIN % -1 overflows in Rust but is mathematically 0.
+            BinOp::Mod => Some(x.checked_rem(y).unwrap_or(0)),
+            _ => unreachable!("not an arithmetic operator: {op:?}"),
+        };
+        if let Some(n) = checked {
+            return Value::Integer(n);
+        }
+        // Overflow: redo the operation in floating point.
+    }
+    let (x, y) = (a.as_f64(), b.as_f64());
+    match op {
+        BinOp::Add => real(x + y),
+        BinOp::Sub => real(x - y),
+        BinOp::Mul => real(x * y),
+        BinOp::Div if y == 0.0 => Value::Null,
+        BinOp::Div => real(x / y),
+        BinOp::Mod if y == 0.0 => Value::Null,
+        BinOp::Mod => real(x % y),
+        _ => unreachable!("not an arithmetic operator: {op:?}"),
+    }
+}
+
+fn comparison(op: BinOp, ordering: Option<Ordering>) -> Value {
+    let Some(ordering) = ordering else {
+        return Value::Null;
+    };
+    let result = match op {
+        BinOp::Eq => ordering == Ordering::Equal,
+        BinOp::Neq => ordering != Ordering::Equal,
+        BinOp::Lt => ordering == Ordering::Less,
+        BinOp::Le => ordering != Ordering::Greater,
+        BinOp::Gt => ordering == Ordering::Greater,
+        BinOp::Ge => ordering != Ordering::Less,
+        _ => unreachable!("not a comparison operator: {op:?}"),
+    };
+    Value::Boolean(result)
+}
+
+/// Apply a binary operator to two already-evaluated operands.
+pub fn eval_binop(op: BinOp, left: Value, right: Value) -> Result<Value, EvalError> {
+    match op {
+        BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
+            match (numeric(&left), numeric(&right)) {
+                (Some(a), Some(b)) => Ok(arithmetic(op, a, b)),
+                _ => Ok(Value::Null),
+            }
+        }
+        BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+            Ok(comparison(op, sql_compare(&left, &right)))
+        }
+        BinOp::And => Ok(bool_value(three_valued_and(
+            truth_value(&left),
+            truth_value(&right),
+        ))),
+        BinOp::Or => Ok(bool_value(three_valued_or(
+            truth_value(&left),
+            truth_value(&right),
+        ))),
+        // Each side converts to text as CAST converts it.
+        BinOp::Concat => match (
+            cast_value(left, DataType::Text),
+            cast_value(right, DataType::Text),
+        ) {
+            (Value::Text(left), Value::Text(right)) => Ok(Value::Text(left + &right)),
+            _ => Ok(Value::Null),
+        },
+        // `left GLOB right` matches the text `left` against the pattern.
+        BinOp::Glob => {
+            if left.is_null() || right.is_null() {
+                Ok(Value::Null)
+            } else {
+                let matched = eval_glob(&right.to_string(), &left.to_string());
+                Ok(Value::Boolean(matched))
+            }
+        }
+    }
+}
+
+/// Apply a unary operator to an already-evaluated operand.
+pub fn eval_unary(op: UnaryOp, val: Value) -> Result<Value, EvalError> {
+    match op {
+        UnaryOp::Not => Ok(bool_value(three_valued_not(truth_value(&val)))),
+        UnaryOp::Plus => Ok(val),
+        UnaryOp::Neg => Ok(match numeric(&val) {
+            None => Value::Null,
+            Some(Num::Int(n)) => n
+                .checked_neg()
+                .map_or(Value::Real(-(n as f64)), Value::Integer),
+            Some(Num::Real(n)) => Value::Real(-n),
+        }),
+    }
+}
+
+/// Evaluates expressions against one row, resolving column references
+/// through the schema that names the row's columns.
+#[derive(Clone, Copy)]
+pub struct Evaluator<'a> {
+    schema: &'a Schema,
+    row: &'a [Value],
+    /// Where subqueries run, if they may be evaluated.
+    ctx: Option<Context<'a>>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(ctx: Context<'a>, schema: &'a Schema, row: &'a [Value]) -> Self {
+        Evaluator {
+            schema,
+            row,
+            ctx: Some(ctx),
+        }
+    }
+
+    /// An evaluator without columns, for expressions such as `VALUES`
+    /// tuples and column defaults, evaluated in the statement `ctx` runs.
+    pub fn without_row(ctx: Context<'a>) -> Self {
+        Evaluator {
+            ctx: Some(ctx),
+            ..Self::constant()
+        }
+    }
+
+    /// An evaluator without columns or subqueries, for constant expressions
+    /// needed outside any statement's execution, such as LIMIT counts.
+    pub fn constant() -> Self {
+        static EMPTY: Schema = Schema {
+            columns: Vec::new(),
+        };
+        Evaluator {
+            schema: &EMPTY,
+            row: &[],
+            ctx: None,
+        }
+    }
+
+    /// The database the expression reads, unless it is evaluated without
+    /// one.
+    pub fn db(&self) -> Option<&'a Database> {
+        self.ctx.map(|ctx| ctx.db)
+    }
+
+    /// The moment the statement began, which `CURRENT_TIMESTAMP` and
+    /// `'now'` read: the same however often they are evaluated.
+    pub fn now(&self) -> SystemTime {
+        self.ctx.map_or_else(SystemTime::now, |ctx| ctx.now)
+    }
+
+    pub fn eval(&self, expr: &Expr) -> Result<Value, EvalError> {
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Column(table, name) => self.column(table.as_deref(), name),
+            Expr::OuterRef { depth, index, .. } => self.outer_column(*depth, *index),
+            Expr::BinOp {
+                op: BinOp::And,
+                left,
+                right,
+            } => self.and(left, right),
+            Expr::BinOp {
+                op: BinOp::Or,
+                left,
+                right,
+            } => self.or(left, right),
+            Expr::BinOp {
+                op: op @ (BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge),
+                left,
+                right,
+            } => {
+                let collation = self.schema.comparison_collation(left, right);
+                let (left, right) = (self.eval(left)?, self.eval(right)?);
+                Ok(comparison(*op, collated_compare(&left, &right, collation)))
+            }
+            Expr::BinOp { op, left, right } => eval_binop(*op, self.eval(left)?, self.eval(right)?),
+            Expr::UnaryOp { op, expr } => eval_unary(*op, self.eval(expr)?),
+            Expr::IsNull { expr, negated } => self.is_null(expr, *negated),
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => self.between(expr, low, high, *negated),
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => self.in_list(expr, list, *negated),
+            // The planner allows row values only where IN reads them.
+            Expr::Tuple(_) => Err(EvalError::RowValueMisused),
+            Expr::Case {
+                when_clauses,
+                else_expr,
+            } => self.case(when_clauses, else_expr.as_deref()),
+            Expr::FunctionCall { name, args } => FunctionRegistry::global()
+                .lookup(name, args.len())?
+                .call(self, args),
+            // The planner replaces every aggregate and window function
+            // call it allows with a column of the node computing it.
+            Expr::Aggregate { func, .. } => Err(EvalError::MisusedAggregate(*func)),
+            Expr::Window { name, .. } => Err(EvalError::MisusedWindow(name.clone())),
+            // A type name that implies no type leaves the value as it is.
+            Expr::Cast { expr, ty } => {
+                let value = self.eval(expr)?;
+                Ok(match DataType::from_name(ty) {
+                    Some(ty) => cast_value(value, ty),
+                    None => value,
+                })
+            }
+            Expr::Like {
+                expr,
+                pattern,
+                escape,
+            } => self.like(expr, pattern, escape.as_deref()),
+            Expr::Collate { expr, .. } => self.eval(expr),
+            Expr::CurrentTime(kind) => Ok(Value::Text(DateTime::at(self.now()).format(*kind))),
+            Expr::Subquery { kind, plan } => self.subquery(kind, plan),
+            Expr::InSubquery { .. }
+            | Expr::QuantifiedComparison { .. }
+            | Expr::Exists(_)
+            | Expr::ScalarSubquery(_) => Err(EvalError::Unsupported("subquery".to_string())),
+        }
+    }
+
+    /// Evaluate a predicate, treating NULL as not satisfied.
+    pub fn holds(&self, expr: &Expr) -> Result<bool, EvalError> {
+        Ok(predicate_holds(&self.eval(expr)?))
+    }
+
+    /// `expr LIKE pattern [ESCAPE escape]`, NULL if any operand is, and
+    /// case-insensitive if the comparison's collation is NOCASE.
+    fn like(&self, expr: &Expr, pattern: &Expr, escape: Option<&Expr>) -> Result<Value, EvalError> {
+        let value = self.eval(expr)?;
+        let pattern_value = self.eval(pattern)?;
+        let escape = match escape.map(|e| self.eval(e)).transpose()? {
+            None => None,
+            Some(Value::Null) => return Ok(Value::Null),
+            Some(e) => {
+                let e = e.to_string();
+                let mut chars = e.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(c),
+                    _ => return Err(EvalError::InvalidEscape),
+                }
+            }
+        };
+        if value.is_null() || pattern_value.is_null() {
+            return Ok(Value::Null);
+        }
+        let collation = self.schema.comparison_collation(expr, pattern);
+        Ok(Value::Boolean(like_match(
+            &pattern_value.to_string(),
+            &value.to_string(),
+            escape,
+            collation,
+        )))
+    }
+
+    fn column(&self, table: Option<&str>, name: &str) -> Result<Value, EvalError> {
+        let i = self.schema.resolve(table, name)?;
+        Ok(self.row[i].clone())
+    }
+
+    fn outer_column(&self, depth: usize, index: usize) -> Result<Value, EvalError> {
+        let mut outer = self.ctx.and_then(|ctx| ctx.outer);
+        for _ in 0..depth {
+            outer = outer.and_then(|o| o.parent);
+        }
+        match outer {
+            Some(outer) => Ok(outer.row[index].clone()),
+            None => Err(EvalError::Unsupported("outer reference".to_string())),
+        }
+    }
+
+    /// Run a subquery's plan with this row visible to its outer references.
+    fn subquery_rows(&self, plan: &PlanNode) -> Result<Vec<Row>, EvalError> {
+        let Some(ctx) = self.ctx else {
+            return Err(EvalError::Unsupported("subquery".to_string()));
+        };
+        let outer = OuterRow {
+            row: self.row,
+            parent: ctx.outer,
+        };
+        let inner = Context {
+            outer: Some(&outer),
+            ..ctx
+        };
+        execute_plan(inner, plan).collect()
+    }
+
+    /// The rows of a subquery: computed for this row if it is correlated,
+    /// and otherwise once in all.
+    fn subquery_result<'p>(&self, plan: &'p SubqueryPlan) -> Result<Cow<'p, [Row]>, EvalError> {
+        if plan.correlated {
+            Ok(Cow::Owned(self.subquery_rows(&plan.plan)?))
+        } else {
+            Ok(Cow::Borrowed(
+                plan.cached_rows(|plan| self.subquery_rows(plan))?,
+            ))
+        }
+    }
+
+    fn subquery(&self, kind: &SubqueryKind, plan: &SubqueryPlan) -> Result<Value, EvalError> {
+        match kind {
+            // An EXISTS subquery is planned to produce its answer as its
+            // only row.
+            SubqueryKind::Scalar | SubqueryKind::Exists => match &self.subquery_result(plan)?[..] {
+                [] => Ok(Value::Null),
+                [row] => Ok(row[0].clone()),
+                _ => Err(EvalError::SubqueryMultipleRows),
+            },
+            SubqueryKind::In { expr, negated } => self.in_subquery(expr, plan, *negated),
+            SubqueryKind::Quantified {
+                expr,
+                op,
+                quantifier,
+            } => self.quantified(expr, *op, *quantifier, plan),
+        }
+    }
+
+    /// `x <op> ANY (SELECT ...)` or `x <op> ALL (SELECT ...)`: the OR, or
+    /// the AND, of comparing `x` with each value, FALSE for ANY and TRUE
+    /// for ALL without values. The comparisons use the collation of `x`,
+    /// or else that of the subquery's column.
+    fn quantified(
+        &self,
+        expr: &Expr,
+        op: BinOp,
+        quantifier: Quantifier,
+        plan: &SubqueryPlan,
+    ) -> Result<Value, EvalError> {
+        let value = self.eval(expr)?;
+        let collation = self
+            .schema
+            .collation_of(expr)
+            .unwrap_or(plan.plan.schema().columns[0].collation);
+        let mut result = Some(quantifier == Quantifier::All);
+        for row in self.subquery_result(plan)?.iter() {
+            let holds = truth_value(&comparison(
+                op,
+                collated_compare(&value, &row[0], collation),
+            ));
+            result = match quantifier {
+                Quantifier::Any => three_valued_or(result, holds),
+                Quantifier::All => three_valued_and(result, holds),
+            };
+            if result == Some(quantifier == Quantifier::Any) {
+                break;
+            }
+        }
+        Ok(bool_value(result))
+    }
+
+    /// `x IN (SELECT ...)`, with the subquery's values collected into a
+    /// set that, like its rows, is kept unless the subquery is correlated.
+    fn in_subquery(
+        &self,
+        expr: &Expr,
+        plan: &SubqueryPlan,
+        negated: bool,
+    ) -> Result<Value, EvalError> {
+        let row = self.row(expr)?;
+        let values = |plan: &PlanNode| -> Result<ValueSet, EvalError> {
+            Ok(self.subquery_rows(plan)?.into_iter().collect())
+        };
+        let fresh;
+        let set = if plan.correlated {
+            fresh = values(&plan.plan)?;
+            &fresh
+        } else {
+            plan.cached_values(values)?
+        };
+        Ok(bool_value(negate_if(set.contains(&row), negated)))
+    }
+
+    /// The values of one side of an IN: a row value's, or else the single
+    /// value of `expr`.
+    fn row(&self, expr: &Expr) -> Result<Vec<Value>, EvalError> {
+        match expr {
+            Expr::Tuple(items) => items.iter().map(|item| self.eval(item)).collect(),
+            expr => Ok(vec![self.eval(expr)?]),
+        }
+    }
+
+    /// `AND`, skipping the right operand once the left one is FALSE.
+    fn and(&self, left: &Expr, right: &Expr) -> Result<Value, EvalError> {
+        let left = truth_value(&self.eval(left)?);
+        if left == Some(false) {
+            return Ok(Value::Boolean(false));
+        }
+        let right = truth_value(&self.eval(right)?);
+        Ok(bool_value(three_valued_and(left, right)))
+    }
+
+    /// `OR`, skipping the right operand once the left one is TRUE.
+    fn or(&self, left: &Expr, right: &Expr) -> Result<Value, EvalError> {
+        let left = truth_value(&self.eval(left)?);
+        if left == Some(true) {
+            return Ok(Value::Boolean(true));
+        }
+        let right = truth_value(&self.eval(right)?);
+        Ok(bool_value(three_valued_or(left, right)))
+    }
+
+    fn is_null(&self, expr: &Expr, negated: bool) -> Result<Value, EvalError> {
+        Ok(Value::Boolean(self.eval(expr)?.is_null() != negated))
+    }
+
+    /// `x BETWEEN low AND high`, as `x >= low AND x <= high` with `x`
+    /// evaluated once; NOT BETWEEN is its negation, `x < low OR x > high`.
+    fn between(
+        &self,
+        expr: &Expr,
+        low: &Expr,
+        high: &Expr,
+        negated: bool,
+    ) -> Result<Value, EvalError> {
+        let value = self.eval(expr)?;
+        let compare = |op, bound: &Expr| -> Result<Value, EvalError> {
+            let collation = self.schema.comparison_collation(expr, bound);
+            let ordering = collated_compare(&value, &self.eval(bound)?, collation);
+            Ok(comparison(op, ordering))
+        };
+        let above = compare(BinOp::Ge, low)?;
+        let below = compare(BinOp::Le, high)?;
+        let within = three_valued_and(truth_value(&above), truth_value(&below));
+        Ok(bool_value(negate_if(within, negated)))
+    }
+
+    /// `x IN (a, b, ...)`, evaluated like `x IN (SELECT ...)` over the
+    /// values of the list.
+    fn in_list(&self, expr: &Expr, list: &[Expr], negated: bool) -> Result<Value, EvalError> {
+        let row = self.row(expr)?;
+        let set = list
+            .iter()
+            .map(|item| self.row(item))
+            .collect::<Result<ValueSet, _>>()?;
+        Ok(bool_value(negate_if(set.contains(&row), negated)))
+    }
+
+    /// The result of the first WHEN clause whose condition holds; later
+    /// clauses are not evaluated.
+    fn case(
+        &self,
+        when_clauses: &[(Expr, Expr)],
+        else_expr: Option<&Expr>,
+    ) -> Result<Value, EvalError> {
+        for (when, then) in when_clauses {
+            if self.holds(when)? {
+                return self.eval(then);
+            }
+        }
+        else_expr.map_or(Ok(Value::Null), |e| self.eval(e))
+    }
+}
+
+fn negate_if(value: Option<bool>, negated: bool) -> Option<bool> {
+    if negated {
+        three_valued_not(value)
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T: Option<bool> = Some(true);
+    const F: Option<bool> = Some(false);
+    const N: Option<bool> = None;
+
+    fn int(n: i64) -> Value {
+        Value::Integer(n)
+    }
+
+    fn binop(op: BinOp, a: Value, b: Value) -> String {
+        format!("{:?}", eval_binop(op, a, b).unwrap())
+    }
+
+    #[test]
+    fn test_three_valued_and_truth_table() {
+        assert_eq!(three_valued_and(T, T), T);
+        assert_eq!(three_valued_and(T, F), F);
+        assert_eq!(three_valued_and(F, T), F);
+        assert_eq!(three_valued_and(F, F), F);
+        assert_eq!(three_valued_and(N, F), F);
+        assert_eq!(three_valued_and(F, N), F);
+        assert_eq!(three_valued_and(N, T), N);
+        assert_eq!(three_valued_and(T, N), N);
+        assert_eq!(three_valued_and(N, N), N);
+    }
+
+    #[test]
+    fn test_three_valued_or_truth_table() {
+        assert_eq!(three_valued_or(T, T), T);
+        assert_eq!(three_valued_or(T, F), T);
+        assert_eq!(three_valued_or(F, T), T);
+        assert_eq!(three_valued_or(F, F), F);
+        assert_eq!(three_valued_or(N, T), T);
+        assert_eq!(three_valued_or(T, N), T);
+        assert_eq!(three_valued_or(N, F), N);
+        assert_eq!(three_valued_or(F, N), N);
+        assert_eq!(three_valued_or(N, N), N);
+    }
+
+    #[test]
+    fn test_three_valued_not() {
+        assert_eq!(three_valued_not(T), F);
+        assert_eq!(three_valued_not(F), T);
+        assert_eq!(three_valued_not(N), N);
+    }
+
+    #[test]
+    fn test_arithmetic_with_null_is_null() {
+        for op in [BinOp::Add, BinOp::Sub, BinOp::Mul, BinOp::Div, BinOp::Mod] {
+            assert_eq!(binop(op, int(1), Value::Null), "Null");
+            assert_eq!(binop(op, Value::Null, int(1)), "Null");
+            assert_eq!(binop(op, Value::Null, Value::Null), "Null");
+        }
+        assert_eq!(binop(BinOp::Concat, Value::Null, int(1)), "Null");
+    }
+
+    #[test]
+    fn test_boolean_operators_on_values() {
+        let null = || Value::Null;
+        let t = || Value::Boolean(true);
+        let f = || Value::Boolean(false);
+        assert_eq!(binop(BinOp::And, null(), f()), "Boolean(false)");
+        assert_eq!(binop(BinOp::And, null(), t()), "Null");
+        assert_eq!(binop(BinOp::Or, null(), t()), "Boolean(true)");
+        assert_eq!(binop(BinOp::Or, null(), f()), "Null");
+        assert_eq!(binop(BinOp::And, int(2), int(3)), "Boolean(true)");
+        assert_eq!(binop(BinOp::Or, int(0), int(0)), "Boolean(false)");
+        assert_eq!(
+            format!("{:?}", eval_unary(UnaryOp::Not, null()).unwrap()),
+            "Null"
+        );
+        assert_eq!(
+            format!("{:?}", eval_unary(UnaryOp::Not, int(0)).unwrap()),
+            "Boolean(true)"
+        );
+    }
+
+    #[test]
+    fn test_comparisons_with_null_are_null() {
+        for op in [
+            BinOp::Eq,
+            BinOp::Neq,
+            BinOp::Lt,
+            BinOp::Le,
+            BinOp::Gt,
+            BinOp::Ge,
+        ] {
+            assert_eq!(binop(op, Value::Null, int(1)), "Null");
+            assert_eq!(binop(op, Value::Null, Value::Null), "Null");
+        }
+        assert_eq!(binop(BinOp::Le, int(1), int(1)), "Boolean(true)");
+        assert_eq!(
+            binop(BinOp::Neq, int(1), Value::Real(1.0)),
+            "Boolean(false)"
+        );
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        assert_eq!(binop(BinOp::Add, int(2), int(3)), "Integer(5)");
+        assert_eq!(binop(BinOp::Sub, int(2), int(3)), "Integer(-1)");
+        assert_eq!(binop(BinOp::Mul, int(4), int(-3)), "Integer(-12)");
+        assert_eq!(binop(BinOp::Div, int(7), int(2)), "Integer(3)");
+        assert_eq!(binop(BinOp::Div, int(-7), int(2)), "Integer(-3)");
+        assert_eq!(binop(BinOp::Mod, int(7), int(3)), "Integer(1)");
+        assert_eq!(binop(BinOp::Mod, int(-7), int(3)), "Integer(-1)");
+    }
+
+    #[test]
+    fn test_mixed_integer_real_arithmetic() {
+        assert_eq!(binop(BinOp::Add, int(1), Value::Real(0.5)), "Real(1.5)");
+        assert_eq!(binop(BinOp::Div, Value::Real(1.0), int(4)), "Real(0.25)");
+        assert_eq!(
+            binop(BinOp::Mul, Value::Boolean(true), int(7)),
+            "Integer(7)"
+        );
+        assert_eq!(
+            binop(BinOp::Add, int(1), Value::Text("2".into())),
+            "Integer(3)"
+        );
+        assert_eq!(binop(BinOp::Add, int(1), Value::Text("abc".into())), "Null");
+    }
+
+    #[test]
+    fn test_division_by_zero_is_null() {
+        assert_eq!(binop(BinOp::Div, int(1), int(0)), "Null");
+        assert_eq!(binop(BinOp::Mod, int(1), int(0)), "Null");
+        assert_eq!(
+            binop(BinOp::Div, Value::Real(1.0), Value::Real(0.0)),
+            "Null"
+        );
+        assert_eq!(binop(BinOp::Mod, Value::Real(1.5), int(0)), "Null");
+    }
+
+    #[test]
+    fn test_integer_overflow_falls_back_to_real() {
+        assert_eq!(
+            binop(BinOp::Add, int(i64::MAX), int(1)),
+            format!("Real({:?})", i64::MAX as f64 + 1.0)
+        );
+        assert_eq!(
+            binop(BinOp::Sub, int(i64::MIN), int(1)),
+            format!("Real({:?})", i64::MIN as f64 - 1.0)
+        );
+        assert_eq!(
+            binop(BinOp::Mul, int(i64::MAX), int(2)),
+            format!("Real({:?})", i64::MAX as f64 * 2.0)
+        );
+        assert_eq!(
+            binop(BinOp::Div, int(i64::MIN), int(-1)),
+            format!("Real({:?})", -(i64::MIN as f64))
+        );
+        assert_eq!(binop(BinOp::Mod, int(i64::MIN), int(-1)), "Integer(0)");
+        assert_eq!(
+            format!("{:?}", eval_unary(UnaryOp::Neg, int(i64::MIN)).unwrap()),
+            format!("Real({:?})", -(i64::MIN as f64))
+        );
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        assert_eq!(
+            format!("{:?}", eval_unary(UnaryOp::Neg, int(5)).unwrap()),
+            "Integer(-5)"
+        );
+        assert_eq!(
+            format!("{:?}", eval_unary(UnaryOp::Neg, Value::Null).unwrap()),
+            "Null"
+        );
+        assert_eq!(
+            format!("{:?}", eval_unary(UnaryOp::Plus, Value::Real(2.5)).unwrap()),
+            "Real(2.5)"
+        );
+    }
+
+    #[test]
+    fn test_concat_stringifies_operands() {
+        assert_eq!(
+            binop(BinOp::Concat, Value::Text("a".into()), int(1)),
+            "Text(\"a1\")"
+        );
+    }
+
+    #[test]
+    fn test_null_predicate_filters_row_out() {
+        assert!(!predicate_holds(&Value::Null));
+        assert!(!predicate_holds(&Value::Boolean(false)));
+        assert!(!predicate_holds(&int(0)));
+        assert!(predicate_holds(&int(-1)));
+        assert!(predicate_holds(&Value::Text("x".into())));
+        assert!(!predicate_holds(&Value::Text("".into())));
+        assert!(!predicate_holds(&Value::Text("0.0".into())));
+    }
+
+    fn eval(sql: &str) -> String {
+        let expr = crate::parser::parse_expr(sql).unwrap();
+        format!("{:?}", Evaluator::constant().eval(&expr).unwrap())
+    }
+
+    #[test]
+    fn test_eval_constant_expressions() {
+        assert_eq!(eval("1 + 2 * 3"), "Integer(7)");
+        assert_eq!(eval("-(2 - 5)"), "Integer(3)");
+        assert_eq!(eval("NULL IS NULL"), "Boolean(true)");
+        assert_eq!(eval("1 IS NOT NULL"), "Boolean(true)");
+        assert_eq!(eval("2 BETWEEN 1 AND 3"), "Boolean(true)");
+        assert_eq!(eval("NULL BETWEEN 1 AND 3"), "Null");
+        assert_eq!(eval("5 NOT BETWEEN 1 AND 3"), "Boolean(true)");
+        assert_eq!(eval("2 IN (1, 2)"), "Boolean(true)");
+        assert_eq!(eval("3 IN (1, NULL)"), "Null");
+        assert_eq!(eval("3 NOT IN (1, 2)"), "Boolean(true)");
+        assert_eq!(eval("2 IN (1.0, 2.0)"), "Boolean(true)");
+        assert_eq!(eval("NULL IN (1, 2)"), "Null");
+        assert_eq!(
+            eval("CASE 2 WHEN 1 THEN 'a' WHEN 2 THEN 'b' END"),
+            r#"Text("b")"#
+        );
+        assert_eq!(eval("CASE WHEN NULL THEN 1 ELSE 0 END"), "Integer(0)");
+        assert_eq!(eval("CASE WHEN 1 = 2 THEN 1 END"), "Null");
+    }
+
+    #[test]
+    fn test_eval_column_without_row_fails() {
+        let expr = crate::parser::parse_expr("t.x + 1").unwrap();
+        assert_eq!(
+            Evaluator::constant().eval(&expr).unwrap_err(),
+            EvalError::NoSuchColumn("t.x".into())
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! Scalar functions, looked up by name in the [`FunctionRegistry`].
+//!
+//! The planner checks each call's argument count against the registry,
+//! so an implementation is only ever given as many arguments as its
+//! [`FunctionImpl`] allows.
+
+mod blob;
+mod date;
+mod math;
+mod printf;
+mod random;
+mod soundex;
+mod string;
+
+pub(super) use string::StringAggAccumulator;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use super::{EvalError, Evaluator};
+use crate::ast::Expr;
+use crate::storage::Database;
+use crate::types::{Value, sql_compare};
+
+/// The most bytes a string or blob that a function makes may have, as in
+/// SQLite.
+pub const MAX_LENGTH: usize = 1_000_000_000;
+
+/// `bytes`, the length of a string or blob to be made, unless it is over
+/// [`MAX_LENGTH`].
+fn fits(bytes: usize) -> Result<usize, EvalError> {
+    match bytes {
+        bytes if bytes > MAX_LENGTH => Err(EvalError::TooBig),
+        bytes => Ok(bytes),
+    }
+}
+
+/// How a function computes its result.
+#[derive(Clone, Copy)]
+pub enum Eval {
+    /// From the values of all of the arguments.
+    Values(fn(&[Value]) -> Value),
+    /// From the values of all of the arguments, or an error.
+    TryValues(fn(&[Value]) -> Result<Value, EvalError>),
+    /// From the arguments unevaluated, evaluating only those it needs.
+    Lazy(fn(&Evaluator<'_>, &[Expr]) -> Result<Value, EvalError>),
+}
+
+/// A scalar function that SQL can call.
+#[derive(Clone, Copy)]
+pub struct FunctionImpl {
+    pub min_args: usize,
+    /// `None` if any number of arguments from `min_args` up will do.
+    pub max_args: Option<usize>,
+    pub eval: Eval,
+}
+
+impl FunctionImpl {
+    fn takes(&self, args: usize) -> bool {
+        args >= self.min_args && self.max_args.is_none_or(|max| args <= max)
+    }
+
+    /// Call the function with the arguments of a call of it, which
+    /// `evaluator` evaluates.
+    pub(super) fn call(
+        &self,
+        evaluator: &Evaluator<'_>,
+        args: &[Expr],
+    ) -> Result<Value, EvalError> {
+        match self.eval {
+            Eval::Values(f) => {
+                let args = args
+                    .iter()
+                    .map(|arg| evaluator.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(f(&args))
+            }
+            Eval::TryValues(f) => {
+                let args = args
+                    .iter()
+                    .map(|arg| evaluator.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                f(&args)
+            }
+            Eval::Lazy(f) => f(evaluator, args),
+        }
+    }
+}
+
+/// The scalar functions, by lowercased name.
+pub struct FunctionRegistry {
+    functions: HashMap<&'static str, FunctionImpl>,
+}
+
+impl FunctionRegistry {
+    /// The registry of the built-in functions.
+    pub fn global() -> &'static FunctionRegistry {
+        static REGISTRY: OnceLock<FunctionRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(|| {
+            let mut registry = FunctionRegistry {
+                functions: HashMap::new(),
+            };
+            registry.register(&["coalesce"], 1, None, Eval::Lazy(coalesce));
+            registry.register(&["nullif"], 2, Some(2), Eval::Values(nullif));
+            registry.register(&["iif", "if"], 3, Some(3), Eval::Lazy(iif));
+            registry.register(&["typeof"], 1, Some(1), Eval::Values(type_of));
+            registry.register(&["quote"], 1, Some(1), Eval::Values(quote));
+            registry.register(&["min"], 2, None, Eval::Values(min));
+            registry.register(&["max"], 2, None, Eval::Values(max));
+            registry.register(&["changes", "row_count"], 0, Some(0), Eval::Lazy(changes));
+            registry.register(&["total_changes"], 0, Some(0), Eval::Lazy(total_changes));
+            registry.register(
+                &["last_insert_rowid", "last_insert_id"],
+                0,
+                Some(0),
+                Eval::Lazy(last_insert_rowid),
+            );
+            blob::register(&mut registry);
+            date::register(&mut registry);
+            math::register(&mut registry);
+            printf::register(&mut registry);
+            random::register(&mut registry);
+            soundex::register(&mut registry);
+            string::register(&mut registry);
+            registry
+        })
+    }
+
+    /// Register a function under each of `names`.
+    fn register(
+        &mut self,
+        names: &[&'static str],
+        min_args: usize,
+        max_args: Option<usize>,
+        eval: Eval,
+    ) {
+        let function = FunctionImpl {
+            min_args,
+            max_args,
+            eval,
+        };
+        for name in names {
+            self.functions.insert(name, function);
+        }
+    }
+
+    /// The function a call of `name` with `args` arguments refers to,
+    /// matched case-insensitively.
+    pub fn lookup(&self, name: &str, args: usize) -> Result<&FunctionImpl, EvalError> {
+        let function = self
+            .functions
+            .get(name.to_ascii_lowercase().as_str())
+            .ok_or_else(|| EvalError::NoSuchFunction(name.to_string()))?;
+        if !function.takes(args) {
+            return Err(EvalError::WrongArgumentCount(name.to_string()));
+        }
+        Ok(function)
+    }
+}
+
+/// `COALESCE(x, ...)`: the first argument that is not NULL, evaluating
+/// none after it.
+fn coalesce(evaluator: &Evaluator<'_>, args: &[Expr]) -> Result<Value, EvalError> {
+    for arg in args {
+        let value = evaluator.eval(arg)?;
+        if !value.is_null() {
+            return Ok(value);
+        }
+    }
+    Ok(Value::Null)
+}
+
+/// `CHANGES()` or `ROW_COUNT()`: how many rows the latest INSERT, UPDATE,
+/// DELETE or TRUNCATE wrote. A SELECT leaves it as it was.
+fn changes(evaluator: &Evaluator<'_>, _: &[Expr]) -> Result<Value, EvalError> {
+    let changes = evaluator.db().map_or(0, Database::changes);
+    Ok(Value::Integer(changes as i64))
+}
+
+/// `TOTAL_CHANGES()`: how many rows every INSERT, UPDATE, DELETE and
+/// TRUNCATE so far has written.
+fn total_changes(evaluator: &Evaluator<'_>, _: &[Expr]) -> Result<Value, EvalError> {
+    let changes = evaluator.db().map_or(0, Database::total_changes);
+    Ok(Value::Integer(changes as i64))
+}
+
+/// `LAST_INSERT_ROWID()` or `LAST_INSERT_ID()`: the INTEGER PRIMARY KEY of
+/// the last row inserted, 0 if its table has none.
+fn last_insert_rowid(evaluator: &Evaluator<'_>, _: &[Expr]) -> Result<Value, EvalError> {
+    Ok(Value::Integer(
+        evaluator.db().map_or(0, Database::last_insert_id),
+    ))
+}
+
+/// `NULLIF(x, y)`: NULL if `x = y`, and `x` otherwise, including when
+/// either is NULL.
+fn nullif(args: &[Value]) -> Value {
+    if sql_compare(&args[0], &args[1]) == Some(Ordering::Equal) {
+        Value::Null
+    } else {
+        args[0].clone()
+    }
+}
+
+/// `MIN(x, y, ...)`: the least of the arguments that are not NULL, by
+/// [`sql_compare`], or NULL if all are. `MIN` with one argument is the
+/// aggregate.
+fn min(args: &[Value]) -> Value {
+    extreme(args, Ordering::Less)
+}
+
+/// `MAX(x, y, ...)`: the greatest of the arguments that are not NULL, or
+/// NULL if all are. `MAX` with one argument is the aggregate.
+fn max(args: &[Value]) -> Value {
+    extreme(args, Ordering::Greater)
+}
+
+/// The first argument that is not NULL and compares `wanted` to each
+/// other one.
+fn extreme(args: &[Value], wanted: Ordering) -> Value {
+    let mut values = args.iter().filter(|value| !value.is_null());
+    let Some(first) = values.next() else {
+        return Value::Null;
+    };
+    values
+        .fold(first, |best, value| {
+            if sql_compare(value, best) == Some(wanted) {
+                value
+            } else {
+                best
+            }
+        })
+        .clone()
+}
+
+/// `IIF(condition, x, y)`: `x` if the condition holds, and `y` if it is
+/// false or NULL, evaluating only the one returned.
+fn iif(evaluator: &Evaluator<'_>, args: &[Expr]) -> Result<Value, EvalError> {
+    if evaluator.holds(&args[0])? {
+        evaluator.eval(&args[1])
+    } else {
+        evaluator.eval(&args[2])
+    }
+}
+
+/// `TYPEOF(x)`: the name of the storage class of `x`. Booleans are stored
+/// as integers.
+fn type_of(args: &[Value]) -> Value {
+    let name = match &args[0] {
+        Value::Null => "null",
+        Value::Integer(_) | Value::Boolean(_) => "integer",
+        Value::Real(_) => "real",
+        Value::Text(_) => "text",
+        Value::Blob(_) => "blob",
+    };
+    Value::Text(name.to_string())
+}
+
+/// `QUOTE(x)`: `x` written as a SQL literal: text in single quotes with
+/// any inside doubled, a blob as `X'...'` and NULL as `NULL`. Booleans are
+/// quoted as the integers they are stored as.
+fn quote(args: &[Value]) -> Value {
+    let value = match &args[0] {
+        Value::Boolean(b) => Value::Integer(i64::from(*b)),
+        value => value.clone(),
+    };
+    Value::Text(Expr::Literal(value).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::tests::query;
+    use crate::parser::parse_statement;
+    use crate::storage::Database;
+
+    pub(super) fn select(expr: &str) -> Result<String, String> {
+        let mut db = Database::new();
+        query(&mut db, &[&format!("SELECT {expr}")])
+            .map(|rows| rows.concat())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_coalesce() {
+        assert_eq!(select("COALESCE(NULL, NULL, 1)").unwrap(), "1");
+        assert_eq!(select("coalesce(NULL, 'a', 1)").unwrap(), "a");
+        assert_eq!(select("COALESCE(NULL, NULL)").unwrap(), "NULL");
+        // The arguments after the first non-NULL one are not evaluated.
+        assert_eq!(
+            select("COALESCE(2, (SELECT 1 UNION SELECT 2))").unwrap(),
+            "2"
+        );
+        assert_eq!(
+            parse_statement("SELECT COALESCE()")
+                .unwrap_err()
+                .to_string(),
+            "wrong number of arguments to function COALESCE()"
+        );
+    }
+
+    #[test]
+    fn test_nullif() {
+        assert_eq!(select("NULLIF(5, 5)").unwrap(), "NULL");
+        assert_eq!(select("NULLIF(5, 6)").unwrap(), "5");
+        assert_eq!(select("NULLIF(5, NULL)").unwrap(), "5");
+        assert_eq!(select("NULLIF(NULL, 5)").unwrap(), "NULL");
+        assert_eq!(
+            select("NULLIF(1, 2, 3)").unwrap_err(),
+            "wrong number of arguments to function NULLIF()"
+        );
+    }
+
+    #[test]
+    fn test_scalar_min_max() {
+        assert_eq!(select("MIN(3, 1, 2)").unwrap(), "1");
+        assert_eq!(select("MAX(3, 1, 2)").unwrap(), "3");
+        assert_eq!(select("MAX('b', 'a', 'c')").unwrap(), "c");
+        assert_eq!(select("MIN(2, 1.5)").unwrap(), "1.5");
+        assert_eq!(select("TYPEOF(MAX(2, 2.0))").unwrap(), "integer");
+        // Numbers sort before text.
+        assert_eq!(select("MIN('a', 10)").unwrap(), "10");
+        assert_eq!(select("MAX('a', 10)").unwrap(), "a");
+        assert_eq!(select("MIN(NULL, 5)").unwrap(), "5");
+        assert_eq!(select("MAX(7, NULL, 9)").unwrap(), "9");
+        assert_eq!(select("MIN(NULL, NULL)").unwrap(), "NULL");
+        assert!(parse_statement("SELECT MIN()").is_err());
+        assert!(parse_statement("SELECT MAX(DISTINCT 1, 2)").is_err());
+
+        let mut db = Database::new();
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "CREATE TABLE t (a INTEGER, b INTEGER)",
+                    "INSERT INTO t VALUES (1, 5), (4, 2), (3, NULL)",
+                    "SELECT MIN(a), MAX(b), MIN(MAX(a), 2) FROM t",
+                ]
+            )
+            .unwrap(),
+            ["1|5|2"]
+        );
+        assert_eq!(
+            query(&mut db, &["SELECT MAX(a, b) FROM t ORDER BY a"]).unwrap(),
+            ["5", "3", "4"]
+        );
+    }
+
+    #[test]
+    fn test_iif() {
+        assert_eq!(select("IIF(1 < 2, 'yes', 'no')").unwrap(), "yes");
+        assert_eq!(select("IIF(NULL, 1, 2)").unwrap(), "2");
+        assert_eq!(select("IF(0, 1, 2)").unwrap(), "2");
+        assert_eq!(
+            select("IIF(1, 'x', (SELECT 1 UNION SELECT 2))").unwrap(),
+            "x"
+        );
+        assert_eq!(
+            select("IIF(1, 2)").unwrap_err(),
+            "wrong number of arguments to function IIF()"
+        );
+        assert_eq!(select("NOPE(1)").unwrap_err(), "no such function: NOPE");
+    }
+
+    #[test]
+    fn test_cast() {
+        assert_eq!(select("CAST('42' AS INTEGER) + 1").unwrap(), "43");
+        assert_eq!(select("CAST(' 2.5 ' AS REAL) * 2").unwrap(), "5.0");
+        assert_eq!(select("CAST(3.9 AS INT)").unwrap(), "3");
+        assert_eq!(select("CAST(12 AS TEXT) || 'x'").unwrap(), "12x");
+        assert_eq!(select("TYPEOF(CAST(12 AS VARCHAR(5)))").unwrap(), "text");
+        assert_eq!(select("TYPEOF(CAST('ab' AS BLOB))").unwrap(), "blob");
+        assert_eq!(select("CAST(2 AS BOOLEAN)").unwrap(), "1");
+        assert_eq!(select("CAST('abc' AS INTEGER)").unwrap(), "NULL");
+        assert_eq!(select("CAST(NULL AS TEXT)").unwrap(), "NULL");
+        assert_eq!(select("CAST(7 AS ANYTHING)").unwrap(), "7");
+    }
+
+    #[test]
+    fn test_implicit_coercion() {
+        assert_eq!(select("1 + '2'").unwrap(), "3");
+        assert_eq!(select("'1.5' * 2").unwrap(), "3.0");
+        assert_eq!(select("TYPEOF(' 10 ' - 1)").unwrap(), "integer");
+        // An operand converts as CAST converts it, so one that is not a
+        // number is NULL either way.
+        assert_eq!(select("1 + 'abc'").unwrap(), "NULL");
+        assert_eq!(select("-CAST('ab' AS BLOB)").unwrap(), "NULL");
+        // Nor are the spellings Rust alone reads as floats.
+        assert_eq!(select("CAST('nan' AS REAL)").unwrap(), "NULL");
+        assert_eq!(select("CAST(' Infinity' AS REAL)").unwrap(), "NULL");
+        for operand in [
+            "'abc'",
+            "'2'",
+            "' 10 '",
+            "'1e3'",
+            "'2x'",
+            "'nan'",
+            "'inf'",
+            "CAST('7' AS BLOB)",
+            "NULL",
+        ] {
+            assert_eq!(
+                select(&format!(
+                    "COALESCE(1 + {operand}, 'null') = COALESCE(1 + CAST({operand} AS REAL), 'null')"
+                ))
+                .unwrap(),
+                "1",
+                "{operand}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_typeof() {
+        for (expr, name) in [
+            ("NULL", "null"),
+            ("1", "integer"),
+            ("TRUE", "integer"),
+            ("1.5", "real"),
+            ("'a'", "text"),
+            ("CAST('a' AS BLOB)", "blob"),
+        ] {
+            assert_eq!(select(&format!("TYPEOF({expr})")).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_quote() {
+        for (expr, literal) in [
+            ("NULL", "NULL"),
+            ("-7", "-7"),
+            ("TRUE", "1"),
+            ("1.5", "1.5"),
+            ("2.0", "2.0"),
+            ("'abc'", "'abc'"),
+            ("'it''s'", "'it''s'"),
+            ("''", "''"),
+            ("UNHEX('00fF')", "X'00FF'"),
+        ] {
+            assert_eq!(select(&format!("QUOTE({expr})")).unwrap(), literal);
+            assert_eq!(select(&format!("TYPEOF(QUOTE({expr}))")).unwrap(), "text");
+        }
+        assert_eq!(select("QUOTE(QUOTE('it''s'))").unwrap(), "'''it''''s'''");
+    }
+}
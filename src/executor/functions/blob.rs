@@ -0,0 +1,89 @@
+//! Blob functions. Each returns NULL if an argument is NULL; other values
+//! are read as blobs the way `CAST` converts them, text as its UTF-8
+//! bytes.
+
+use super::{Eval, FunctionRegistry, fits};
+use crate::executor::EvalError;
+use crate::types::{DataType, Value, cast_value};
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(&["hex"], 1, Some(1), Eval::Values(hex));
+    registry.register(&["unhex"], 1, Some(1), Eval::Values(unhex));
+    registry.register(&["zeroblob"], 1, Some(1), Eval::TryValues(zeroblob));
+}
+
+/// `HEX(x)`: the bytes of `x` as upper-case hex digits, two to a byte. A
+/// number is written out first, so `HEX(12)` is `3132`.
+fn hex(args: &[Value]) -> Value {
+    match cast_value(args[0].clone(), DataType::Blob) {
+        Value::Blob(bytes) => Value::Text(Value::Blob(bytes).to_string()),
+        _ => Value::Null,
+    }
+}
+
+/// `UNHEX(s)`: the blob whose bytes the hex digits of `s` spell, in either
+/// case, or NULL if `s` has an odd number of them or anything else.
+fn unhex(args: &[Value]) -> Value {
+    let Value::Text(s) = cast_value(args[0].clone(), DataType::Text) else {
+        return Value::Null;
+    };
+    if s.len() % 2 != 0 {
+        return Value::Null;
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            if !pair.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect::<Option<Vec<u8>>>()
+        .map_or(Value::Null, Value::Blob)
+}
+
+/// `ZEROBLOB(n)`: a blob of `n` zero bytes, empty if `n` is not positive.
+fn zeroblob(args: &[Value]) -> Result<Value, EvalError> {
+    match cast_value(args[0].clone(), DataType::Int) {
+        Value::Integer(n) => Ok(Value::Blob(vec![0; fits(n.max(0) as usize)?])),
+        _ => Ok(Value::Null),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::select;
+
+    #[test]
+    fn test_hex_and_unhex() {
+        assert_eq!(select("HEX(CAST('ab' AS BLOB))").unwrap(), "6162");
+        assert_eq!(select("HEX('hi')").unwrap(), "6869");
+        assert_eq!(select("HEX(12)").unwrap(), "3132");
+        assert_eq!(select("HEX('')").unwrap(), "");
+        assert_eq!(select("HEX(NULL)").unwrap(), "NULL");
+        assert_eq!(select("TYPEOF(UNHEX('DEADBEEF'))").unwrap(), "blob");
+        assert_eq!(select("HEX(UNHEX('00fF10'))").unwrap(), "00FF10");
+        assert_eq!(
+            select("HEX(UNHEX('c0ffee')) = UPPER('c0ffee')").unwrap(),
+            "1"
+        );
+        assert_eq!(select("LENGTH(HEX(UNHEX('')))").unwrap(), "0");
+        for bad in ["'abc'", "'zz'", "'0x12'", "'+1'", "'日本'", "NULL"] {
+            assert_eq!(select(&format!("UNHEX({bad})")).unwrap(), "NULL", "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_zeroblob() {
+        assert_eq!(select("HEX(ZEROBLOB(3))").unwrap(), "000000");
+        assert_eq!(select("TYPEOF(ZEROBLOB(0))").unwrap(), "blob");
+        assert_eq!(select("ZEROBLOB(0) = CAST('' AS BLOB)").unwrap(), "1");
+        assert_eq!(select("HEX(ZEROBLOB(-2))").unwrap(), "");
+        assert_eq!(select("ZEROBLOB(NULL)").unwrap(), "NULL");
+        assert_eq!(
+            select("ZEROBLOB(9223372036854775807)").unwrap_err(),
+            "string or blob too big"
+        );
+    }
+}
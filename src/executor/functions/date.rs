@@ -0,0 +1,209 @@
+//! Date and time functions, as SQLite's: each reads a time value, applies
+//! the modifiers after it in turn (see [`DateTime::modify`]) and writes the
+//! moment out. A time value is `'now'`, the moment the statement began;
+//! text [`parse_datetime`] reads; or a number, read as a Julian day, or as
+//! seconds since 1970 if the first modifier is `'unixepoch'`. With no time
+//! value at all the moment is now. The result is NULL if any argument is
+//! NULL or malformed.
+
+use super::{Eval, FunctionRegistry};
+use crate::ast::Expr;
+use crate::executor::datetime::{DateTime, parse_datetime};
+use crate::executor::{EvalError, Evaluator};
+use crate::types::Value;
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(&["date"], 0, None, Eval::Lazy(date));
+    registry.register(&["time"], 0, None, Eval::Lazy(time));
+    registry.register(&["datetime"], 0, None, Eval::Lazy(datetime));
+    registry.register(&["strftime"], 1, None, Eval::Lazy(strftime));
+    registry.register(&["julianday"], 0, None, Eval::Lazy(julianday));
+    registry.register(&["unixepoch"], 0, None, Eval::Lazy(unixepoch));
+}
+
+/// The moment a time value and its modifiers name, or `None` for NULL.
+fn moment(evaluator: &Evaluator<'_>, args: &[Expr]) -> Result<Option<DateTime>, EvalError> {
+    let Some((value, modifiers)) = args.split_first() else {
+        return Ok(Some(DateTime::at(evaluator.now())));
+    };
+    let value = evaluator.eval(value)?;
+    let mut modifiers = modifiers
+        .iter()
+        .map(|modifier| evaluator.eval(modifier))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .peekable();
+    let unix = modifiers
+        .next_if(|m| matches!(m, Value::Text(m) if m.trim().eq_ignore_ascii_case("unixepoch")))
+        .is_some();
+    let mut moment = match value {
+        Value::Text(s) if !unix && s.trim().eq_ignore_ascii_case("now") => {
+            Some(DateTime::at(evaluator.now()))
+        }
+        Value::Text(s) if !unix => parse_datetime(&s),
+        Value::Integer(n) if unix => DateTime::from_unix_checked(n as f64),
+        Value::Real(n) if unix => DateTime::from_unix_checked(n),
+        Value::Integer(n) => DateTime::from_julian_day(n as f64),
+        Value::Real(n) => DateTime::from_julian_day(n),
+        _ => None,
+    };
+    for modifier in modifiers {
+        moment = match (moment, modifier) {
+            (Some(moment), Value::Text(modifier)) => moment.modify(&modifier),
+            _ => None,
+        };
+    }
+    Ok(moment)
+}
+
+fn text(moment: Option<DateTime>, format: impl Fn(DateTime) -> Option<String>) -> Value {
+    moment.and_then(format).map_or(Value::Null, Value::Text)
+}
+
+/// `DATE(value, modifiers...)`: `YYYY-MM-DD`.
+fn date(evaluator: &Evaluator<'_>, args: &[Expr]) -> Result<Value, EvalError> {
+    Ok(text(moment(evaluator, args)?, |m| Some(m.date())))
+}
+
+/// `TIME(value, modifiers...)`: `HH:MM:SS`.
+fn time(evaluator: &Evaluator<'_>, args: &[Expr]) -> Result<Value, EvalError> {
+    Ok(text(moment(evaluator, args)?, |m| Some(m.time())))
+}
+
+/// `DATETIME(value, modifiers...)`: `YYYY-MM-DD HH:MM:SS`.
+fn datetime(evaluator: &Evaluator<'_>, args: &[Expr]) -> Result<Value, EvalError> {
+    Ok(text(moment(evaluator, args)?, |m| {
+        Some(format!("{} {}", m.date(), m.time()))
+    }))
+}
+
+/// `JULIANDAY(value, modifiers...)`: the Julian day number, a REAL.
+fn julianday(evaluator: &Evaluator<'_>, args: &[Expr]) -> Result<Value, EvalError> {
+    Ok(moment(evaluator, args)?.map_or(Value::Null, |m| Value::Real(m.to_julian_day())))
+}
+
+/// `UNIXEPOCH(value, modifiers...)`: seconds since 1970, an INTEGER.
+fn unixepoch(evaluator: &Evaluator<'_>, args: &[Expr]) -> Result<Value, EvalError> {
+    Ok(moment(evaluator, args)?.map_or(Value::Null, |m| Value::Integer(m.to_unix())))
+}
+
+/// `STRFTIME(format, value, modifiers...)`, by [`DateTime::strftime`].
+fn strftime(evaluator: &Evaluator<'_>, args: &[Expr]) -> Result<Value, EvalError> {
+    let Value::Text(format) = evaluator.eval(&args[0])? else {
+        return Ok(Value::Null);
+    };
+    Ok(text(moment(evaluator, &args[1..])?, |m| {
+        m.strftime(&format)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::select;
+
+    #[test]
+    fn test_date_functions() {
+        assert_eq!(select("DATE('2024-03-15 10:30:45')").unwrap(), "2024-03-15");
+        assert_eq!(select("TIME('2024-03-15 10:30:45')").unwrap(), "10:30:45");
+        assert_eq!(
+            select("DATETIME('2024-03-15T10:30')").unwrap(),
+            "2024-03-15 10:30:00"
+        );
+        assert_eq!(
+            select("DATE('2024-03-15', '+1 month')").unwrap(),
+            "2024-04-15"
+        );
+        assert_eq!(
+            select("DATETIME('2024-03-15', '+1 day', '-2 hours')").unwrap(),
+            "2024-03-15 22:00:00"
+        );
+        assert_eq!(
+            select("date('2024-03-15', 'start of month', '+1 month', '-1 day')").unwrap(),
+            "2024-03-31"
+        );
+        assert_eq!(select("DATE(2460000.5)").unwrap(), "2023-02-25");
+        assert_eq!(
+            select("STRFTIME('%Y-%m-%d %H:%M:%S', '2024-03-05 07:08:09', '+1 year')").unwrap(),
+            "2025-03-05 07:08:09"
+        );
+        for null in [
+            "DATE(NULL)",
+            "DATE('yesterday')",
+            "DATE('2024-03-15', NULL)",
+            "DATE('2024-03-15', '+1 fortnight')",
+            "STRFTIME(NULL, '2024-03-15')",
+            "STRFTIME('%Q', '2024-03-15')",
+        ] {
+            assert_eq!(select(null).unwrap(), "NULL", "{null}");
+        }
+    }
+
+    #[test]
+    fn test_now() {
+        let date = select("DATE('now')").unwrap();
+        assert_eq!(date.len(), 10);
+        assert_eq!(select("DATE()").unwrap(), date);
+        assert_eq!(select("DATE('now') = CURRENT_DATE").unwrap(), "1");
+        assert_eq!(select("DATETIME('NOW') = CURRENT_TIMESTAMP").unwrap(), "1");
+        assert_eq!(
+            select("DATETIME('now', '+1 day') > DATETIME('now')").unwrap(),
+            "1"
+        );
+        assert_eq!(select("STRFTIME('%Y')").unwrap(), &date[..4]);
+        assert_eq!(
+            select("DATE(JULIANDAY('now') + 7) = DATE('now', '+7 days')").unwrap(),
+            "1"
+        );
+        assert_eq!(
+            select("UNIXEPOCH() = UNIXEPOCH('now') AND UNIXEPOCH() > 1700000000").unwrap(),
+            "1"
+        );
+    }
+
+    #[test]
+    fn test_julianday_and_unixepoch() {
+        assert_eq!(select("JULIANDAY('2024-01-01')").unwrap(), "2460310.5");
+        assert_eq!(select("TYPEOF(JULIANDAY('2024-01-01'))").unwrap(), "real");
+        assert_eq!(
+            select("JULIANDAY('2024-01-01 12:00', '+1 day')").unwrap(),
+            "2460312.0"
+        );
+        assert_eq!(
+            select("DATETIME(JULIANDAY('2024-03-15 10:30:45'))").unwrap(),
+            "2024-03-15 10:30:45"
+        );
+        assert_eq!(
+            select("DATETIME(JULIANDAY('2024-03-15 10:30:45') + 7)").unwrap(),
+            "2024-03-22 10:30:45"
+        );
+        assert_eq!(select("UNIXEPOCH('1970-01-01')").unwrap(), "0");
+        assert_eq!(
+            select("TYPEOF(UNIXEPOCH('1970-01-01'))").unwrap(),
+            "integer"
+        );
+        assert_eq!(
+            select("UNIXEPOCH('2023-11-14 22:13:20')").unwrap(),
+            "1700000000"
+        );
+        assert_eq!(
+            select("UNIXEPOCH('1970-01-01', '+1 hour')").unwrap(),
+            "3600"
+        );
+        assert_eq!(
+            select("DATETIME(1700000000, 'unixepoch')").unwrap(),
+            "2023-11-14 22:13:20"
+        );
+        assert_eq!(
+            select("DATE(UNIXEPOCH('2024-03-15'), 'UnixEpoch', '+1 day')").unwrap(),
+            "2024-03-16"
+        );
+        assert_eq!(
+            select("DATETIME(-1, 'unixepoch')").unwrap(),
+            "1969-12-31 23:59:59"
+        );
+        // 'unixepoch' only reads a number, and only as the first modifier.
+        assert_eq!(select("DATE('2024-03-15', 'unixepoch')").unwrap(), "NULL");
+        assert_eq!(select("DATE(0, '+1 day', 'unixepoch')").unwrap(), "NULL");
+        assert_eq!(select("UNIXEPOCH(NULL)").unwrap(), "NULL");
+    }
+}
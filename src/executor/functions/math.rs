@@ -0,0 +1,209 @@
+//! Math functions. Each returns NULL if an argument is NULL or is not a
+//! number; text arguments are read as numbers the way arithmetic reads
+//! them.
+
+use super::{Eval, FunctionRegistry};
+use crate::types::{Value, parse_number};
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(&["abs"], 1, Some(1), Eval::Values(abs));
+    registry.register(&["round"], 1, Some(2), Eval::Values(round));
+    registry.register(&["ceil", "ceiling"], 1, Some(1), Eval::Values(ceil));
+    registry.register(&["floor"], 1, Some(1), Eval::Values(floor));
+    registry.register(&["sqrt"], 1, Some(1), Eval::Values(sqrt));
+    registry.register(&["power", "pow"], 2, Some(2), Eval::Values(power));
+}
+
+/// A numeric argument: an INTEGER or a REAL, or `None` for anything else.
+fn number(value: &Value) -> Option<Value> {
+    match value {
+        Value::Integer(_) | Value::Real(_) => Some(value.clone()),
+        Value::Boolean(b) => Some(Value::Integer(*b as i64)),
+        Value::Text(s) => parse_number(s),
+        Value::Null | Value::Blob(_) => None,
+    }
+}
+
+/// A numeric argument as a float.
+fn float(value: &Value) -> Option<f64> {
+    match number(value)? {
+        Value::Integer(n) => Some(n as f64),
+        Value::Real(n) => Some(n),
+        _ => None,
+    }
+}
+
+/// A REAL result, NULL if it is not a number.
+fn real(n: f64) -> Value {
+    if n.is_nan() {
+        Value::Null
+    } else {
+        Value::Real(n)
+    }
+}
+
+/// `ABS(x)`, of the same type as `x`.
+fn abs(args: &[Value]) -> Value {
+    match number(&args[0]) {
+        Some(Value::Integer(n)) => n
+            .checked_abs()
+            .map_or(Value::Real((n as f64).abs()), Value::Integer),
+        Some(Value::Real(n)) => Value::Real(n.abs()),
+        _ => Value::Null,
+    }
+}
+
+/// `ROUND(x [, digits])`: `x` rounded to `digits` decimal places, zero by
+/// default, with halves rounded away from zero. The digits rounded are
+/// those of `x` written out to 15 significant digits, as SQLite does, so
+/// `1.005` is a half although the float is a little less. The result is a
+/// REAL, except that an INTEGER rounded to no decimal places stays one.
+fn round(args: &[Value]) -> Value {
+    let digits = match args.get(1).map(number) {
+        None => 0,
+        Some(Some(Value::Integer(n))) => n.max(0),
+        Some(Some(Value::Real(n))) => (n as i64).max(0),
+        Some(_) => return Value::Null,
+    };
+    let x = match number(&args[0]) {
+        Some(Value::Integer(n)) if digits == 0 => return Value::Integer(n),
+        Some(Value::Integer(n)) => n as f64,
+        Some(Value::Real(n)) => n,
+        _ => return Value::Null,
+    };
+    if !x.is_finite() {
+        return Value::Real(x);
+    }
+    let written = format!("{:.*e}", SIGNIFICANT_DIGITS - 1, x.abs());
+    let (mantissa, exponent) = written.split_once('e').expect("written with an exponent");
+    let exponent: i64 = exponent.parse().expect("the exponent is a number");
+    let significant: Vec<u64> = mantissa
+        .bytes()
+        .filter(u8::is_ascii_digit)
+        .map(|b| u64::from(b - b'0'))
+        .collect();
+    // The significant digits before the point, then `digits` after it.
+    let kept = (exponent + 1).saturating_add(digits);
+    if kept >= SIGNIFICANT_DIGITS as i64 {
+        return Value::Real(x);
+    }
+    if kept < 0 {
+        return Value::Real(0f64.copysign(x));
+    }
+    let kept = kept as usize;
+    let mut rounded = significant[..kept].iter().fold(0, |n, d| n * 10 + d);
+    if significant[kept] >= 5 {
+        rounded += 1;
+    }
+    let rounded: f64 = format!("{rounded}e{}", -digits)
+        .parse()
+        .expect("written as a float");
+    Value::Real(rounded.copysign(x))
+}
+
+/// The significant digits of a REAL that [`round`] rounds.
+const SIGNIFICANT_DIGITS: usize = 15;
+
+/// `CEIL(x)`, always a REAL.
+fn ceil(args: &[Value]) -> Value {
+    float(&args[0]).map_or(Value::Null, |x| Value::Real(x.ceil()))
+}
+
+/// `FLOOR(x)`, always a REAL.
+fn floor(args: &[Value]) -> Value {
+    float(&args[0]).map_or(Value::Null, |x| Value::Real(x.floor()))
+}
+
+/// `SQRT(x)`, NULL for a negative `x`.
+fn sqrt(args: &[Value]) -> Value {
+    float(&args[0]).map_or(Value::Null, |x| real(x.sqrt()))
+}
+
+/// `POWER(base, exponent)`, always a REAL.
+fn power(args: &[Value]) -> Value {
+    match (float(&args[0]), number(&args[1])) {
+        (Some(base), Some(Value::Integer(exp))) if i32::try_from(exp).is_ok() => {
+            real(base.powi(exp as i32))
+        }
+        (Some(base), Some(exp)) => float(&exp).map_or(Value::Null, |exp| real(base.powf(exp))),
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::select;
+
+    #[test]
+    fn test_abs() {
+        assert_eq!(select("ABS(-3)").unwrap(), "3");
+        assert_eq!(select("TYPEOF(ABS(-3))").unwrap(), "integer");
+        assert_eq!(select("ABS(-2.5)").unwrap(), "2.5");
+        assert_eq!(select("ABS('-4')").unwrap(), "4");
+        assert_eq!(
+            select("ABS(-9223372036854775807 - 1)").unwrap(),
+            "9.223372036854776e18"
+        );
+    }
+
+    #[test]
+    fn test_round() {
+        assert_eq!(select("ROUND(7)").unwrap(), "7");
+        assert_eq!(select("TYPEOF(ROUND(7))").unwrap(), "integer");
+        assert_eq!(select("ROUND(7, 1)").unwrap(), "7.0");
+        // Halves round away from zero.
+        assert_eq!(select("ROUND(2.5)").unwrap(), "3.0");
+        assert_eq!(select("ROUND(-2.5)").unwrap(), "-3.0");
+        assert_eq!(select("ROUND(0.125, 2)").unwrap(), "0.13");
+        assert_eq!(select("ROUND(3.14159, 3)").unwrap(), "3.142");
+        assert_eq!(select("ROUND(1234.5, -2)").unwrap(), "1235.0");
+        assert_eq!(select("ROUND(1.5, 400)").unwrap(), "1.5");
+        // Halves written in decimal, though their floats fall just short.
+        assert_eq!(select("ROUND(1.005, 2)").unwrap(), "1.01");
+        assert_eq!(select("ROUND(2.675, 2)").unwrap(), "2.68");
+        assert_eq!(select("ROUND(-1.005, 2)").unwrap(), "-1.01");
+        assert_eq!(select("ROUND(0.5)").unwrap(), "1.0");
+        assert_eq!(select("ROUND(0.0004, 3)").unwrap(), "0.0");
+        assert_eq!(select("ROUND(99.96, 1)").unwrap(), "100.0");
+        assert_eq!(select("ROUND(1e300, 2) = 1e300").unwrap(), "1");
+    }
+
+    #[test]
+    fn test_ceil_floor_sqrt_power() {
+        assert_eq!(select("CEIL(1.2)").unwrap(), "2.0");
+        assert_eq!(select("CEILING(-1.2)").unwrap(), "-1.0");
+        assert_eq!(select("FLOOR(-1.2)").unwrap(), "-2.0");
+        assert_eq!(select("FLOOR(3)").unwrap(), "3.0");
+        assert_eq!(select("SQRT(16)").unwrap(), "4.0");
+        assert_eq!(select("SQRT(-1)").unwrap(), "NULL");
+        assert_eq!(select("POWER(2, 10)").unwrap(), "1024.0");
+        assert_eq!(select("POW(2, -1)").unwrap(), "0.5");
+        assert_eq!(select("POWER(4, 0.5)").unwrap(), "2.0");
+        assert_eq!(select("POWER(-8, 0.5)").unwrap(), "NULL");
+    }
+
+    #[test]
+    fn test_null_and_arity() {
+        for call in [
+            "ABS(NULL)",
+            "ROUND(NULL)",
+            "ROUND(1.5, NULL)",
+            "CEIL(NULL)",
+            "FLOOR(NULL)",
+            "SQRT(NULL)",
+            "POWER(NULL, 2)",
+            "POWER(2, NULL)",
+            "ABS('abc')",
+        ] {
+            assert_eq!(select(call).unwrap(), "NULL", "{call}");
+        }
+        assert_eq!(
+            select("ROUND(1, 2, 3)").unwrap_err(),
+            "wrong number of arguments to function ROUND()"
+        );
+        assert_eq!(
+            select("SQRT()").unwrap_err(),
+            "wrong number of arguments to function SQRT()"
+        );
+    }
+}
@@ -0,0 +1,369 @@
+//! `PRINTF(format, args...)`, also called `FORMAT`: C's `printf`, for the
+//! conversions SQL values have use for.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use super::{Eval, FunctionRegistry, fits};
+use crate::ast::Expr;
+use crate::executor::{EvalError, Evaluator};
+use crate::types::{DataType, Value, cast_value};
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(&["printf", "format"], 1, None, Eval::Lazy(printf));
+}
+
+/// NULL if the format is NULL, else [`printf_format`].
+fn printf(evaluator: &Evaluator<'_>, args: &[Expr]) -> Result<Value, EvalError> {
+    let values = args
+        .iter()
+        .map(|arg| evaluator.eval(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    match cast_value(values[0].clone(), DataType::Text) {
+        Value::Text(format) => Ok(Value::Text(printf_format(&format, &values[1..])?)),
+        _ => Ok(Value::Null),
+    }
+}
+
+/// `format` with each conversion in it replaced by the next of `args`,
+/// written out as the conversion says:
+///
+/// - `%d` or `%i`: an integer, with at least `precision` digits;
+/// - `%f`: a real with `precision` digits after the point, 6 by default;
+/// - `%e`: a real in scientific notation, as `1.500000e+03`;
+/// - `%g`: a real as `%e` if its exponent is below -4 or not below the
+///   precision, else as `%f`, either way with `precision` significant
+///   digits and no trailing zeros;
+/// - `%s`: text, cut to `precision` characters;
+/// - `%q`: text with each single quote doubled, to go inside a literal;
+/// - `%%`: a percent sign, taking no argument.
+///
+/// Between the `%` and the conversion may come the flags `-` to pad on the
+/// right, `0` to pad a number with zeros and `+` or a space to put that
+/// before a positive number; then a width to pad to; then a `.` and the
+/// precision, as in `%-10.2f`. NULL, or no argument left, is an empty
+/// string to `%s` and `%q` and zero to the others. A width or precision,
+/// or the result, over [`MAX_LENGTH`](super::MAX_LENGTH) is
+/// [`EvalError::TooBig`].
+pub fn printf_format(format: &str, args: &[Value]) -> Result<String, EvalError> {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut spec = Spec::default();
+        while let Some(flag) = chars.next_if(|c| matches!(c, '-' | '0' | '+' | ' ')) {
+            match flag {
+                '-' => spec.left = true,
+                '0' => spec.zero = true,
+                '+' => spec.sign = Some('+'),
+                _ => {
+                    spec.sign.get_or_insert(' ');
+                }
+            }
+        }
+        spec.width = fits(number(&mut chars))?;
+        let precision = match chars.next_if_eq(&'.') {
+            Some(_) => Some(fits(number(&mut chars))?),
+            None => None,
+        };
+        let field = match chars.next() {
+            Some('%') => {
+                out.push('%');
+                continue;
+            }
+            Some('d' | 'i') => {
+                let n = match args
+                    .next()
+                    .map(|arg| cast_value(arg.clone(), DataType::Int))
+                {
+                    Some(Value::Integer(n)) => n,
+                    _ => 0,
+                };
+                let digits = n.unsigned_abs().to_string();
+                let zeros = precision.unwrap_or(1).saturating_sub(digits.len());
+                spec.number(n < 0, "0".repeat(zeros) + &digits)
+            }
+            Some(conversion @ ('f' | 'e' | 'g')) => {
+                let n = match args
+                    .next()
+                    .map(|arg| cast_value(arg.clone(), DataType::Real))
+                {
+                    Some(Value::Real(n)) => n,
+                    _ => 0.0,
+                };
+                let precision = precision.unwrap_or(6);
+                let digits = if n.is_nan() {
+                    "NaN".to_string()
+                } else if n.is_infinite() {
+                    "Inf".to_string()
+                } else if conversion == 'f' {
+                    fixed(n.abs(), precision)
+                } else if conversion == 'e' {
+                    exponential(n.abs(), precision)
+                } else {
+                    general(n.abs(), precision)
+                };
+                spec.number(n.is_sign_negative() && !n.is_nan(), digits)
+            }
+            Some(conversion @ ('s' | 'q')) => {
+                let s = match args
+                    .next()
+                    .map(|arg| cast_value(arg.clone(), DataType::Text))
+                {
+                    Some(Value::Text(s)) => s,
+                    _ => String::new(),
+                };
+                let s = match precision {
+                    Some(precision) => s.chars().take(precision).collect(),
+                    None => s,
+                };
+                if conversion == 'q' {
+                    s.replace('\'', "''")
+                } else {
+                    s
+                }
+            }
+            Some(c) => return Err(EvalError::InvalidFormat(format!("%{c}"))),
+            None => return Err(EvalError::InvalidFormat("%".to_string())),
+        };
+        out.push_str(&spec.pad(field));
+        fits(out.len())?;
+    }
+    Ok(out)
+}
+
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    sign: Option<char>,
+    width: usize,
+}
+
+impl Spec {
+    /// The digits of a number with its sign before them, and with zeros
+    /// between the two to fill the width if the `0` flag asks for them.
+    fn number(&self, negative: bool, digits: String) -> String {
+        let sign = if negative { Some('-') } else { self.sign };
+        let len = digits.chars().count() + usize::from(sign.is_some());
+        let zeros = if self.zero && !self.left && digits.starts_with(|c: char| c.is_ascii_digit()) {
+            self.width.saturating_sub(len)
+        } else {
+            0
+        };
+        sign.into_iter()
+            .chain(std::iter::repeat_n('0', zeros))
+            .chain(digits.chars())
+            .collect()
+    }
+
+    /// `field` padded with spaces to the width.
+    fn pad(&self, field: String) -> String {
+        let padding = " ".repeat(self.width.saturating_sub(field.chars().count()));
+        if self.left {
+            field + &padding
+        } else {
+            padding + &field
+        }
+    }
+}
+
+/// A run of digits, as a number; 0 if there are none.
+fn number(chars: &mut Peekable<Chars<'_>>) -> usize {
+    let mut n = 0usize;
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        n = n
+            .saturating_mul(10)
+            .saturating_add(digit as usize - '0' as usize);
+    }
+    n
+}
+
+/// The most digits after the point that an `f64` written out exactly has,
+/// in either notation; any further digits are zeros. The standard library
+/// writes no more than `u16::MAX`.
+const EXACT_DIGITS: usize = 1100;
+
+/// `n` as `%f` writes it: `precision` digits after the point.
+fn fixed(n: f64, precision: usize) -> String {
+    let s = format!("{:.*}", precision.min(EXACT_DIGITS), n);
+    s + &"0".repeat(precision.saturating_sub(EXACT_DIGITS))
+}
+
+/// `n` as `%e` writes it: one digit before the point, `precision` after it,
+/// and a signed exponent of at least two digits.
+fn exponential(n: f64, precision: usize) -> String {
+    let s = format!("{:.*e}", precision.min(EXACT_DIGITS), n);
+    let (mantissa, exponent) = s.split_once('e').expect("`{:e}` writes an exponent");
+    let exponent: i32 = exponent.parse().expect("the exponent is an integer");
+    let sign = if exponent < 0 { '-' } else { '+' };
+    let zeros = "0".repeat(precision.saturating_sub(EXACT_DIGITS));
+    format!("{mantissa}{zeros}e{sign}{:02}", exponent.abs())
+}
+
+/// `n` as `%g` writes it. No more significant digits than an `f64` has
+/// can show, as trailing zeros are dropped.
+fn general(n: f64, precision: usize) -> String {
+    let digits = precision.clamp(1, EXACT_DIGITS);
+    if n == 0.0 {
+        return "0".to_string();
+    }
+    // The exponent `n` has once rounded to `digits` significant digits.
+    let rounded = format!("{:.*e}", digits - 1, n);
+    let exponent: i64 = rounded
+        .split_once('e')
+        .map_or(0, |(_, e)| e.parse().unwrap_or(0));
+    let s = if exponent < -4 || exponent >= digits as i64 {
+        exponential(n, digits - 1)
+    } else {
+        format!("{:.*}", (digits as i64 - 1 - exponent) as usize, n)
+    };
+    let (mantissa, exponent) = match s.split_once('e') {
+        Some((mantissa, exponent)) => (mantissa, format!("e{exponent}")),
+        None => (s.as_str(), String::new()),
+    };
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{mantissa}{exponent}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::select;
+    use super::*;
+    use crate::executor::tests::query;
+    use crate::storage::Database;
+
+    fn printf(format: &str, args: &[Value]) -> String {
+        printf_format(format, args).unwrap()
+    }
+
+    #[test]
+    fn test_printf_integers() {
+        let n = |n| Value::Integer(n);
+        assert_eq!(printf("%d|%i", &[n(42), n(-7)]), "42|-7");
+        assert_eq!(
+            printf("[%5d][%-5d][%05d]", &[n(42), n(42), n(-42)]),
+            "[   42][42   ][-0042]"
+        );
+        assert_eq!(printf("%+d % d %.3d", &[n(5), n(5), n(5)]), "+5  5 005");
+        assert_eq!(printf("%d", &[Value::Real(2.9)]), "2");
+        assert_eq!(printf("%d", &[Value::Text("12".into())]), "12");
+        assert_eq!(
+            printf("%d", &[Value::Integer(i64::MIN)]),
+            i64::MIN.to_string()
+        );
+    }
+
+    #[test]
+    fn test_printf_reals() {
+        let r = |n| Value::Real(n);
+        assert_eq!(printf("%f", &[r(1.23456)]), "1.234560");
+        assert_eq!(
+            printf("%.2f|%10.2f|%-8.1f|", &[r(2.5), r(-1.23456), r(1.0)]),
+            "2.50|     -1.23|1.0     |"
+        );
+        assert_eq!(printf("%08.3f %+.0f", &[r(-1.5), r(2.0)]), "-001.500 +2");
+        assert_eq!(printf("%f", &[Value::Integer(3)]), "3.000000");
+        assert_eq!(printf("%e", &[r(1500.0)]), "1.500000e+03");
+        assert_eq!(
+            printf("%.2e|%e", &[r(-0.000123), r(0.0)]),
+            "-1.23e-04|0.000000e+00"
+        );
+        assert_eq!(
+            printf("%g|%g|%g", &[r(100000.0), r(1000000.0), r(0.0001)]),
+            "100000|1e+06|0.0001"
+        );
+        assert_eq!(
+            printf("%g|%g|%.3g", &[r(0.00001), r(2.5), r(1.23456)]),
+            "1e-05|2.5|1.23"
+        );
+        assert_eq!(printf("%g|%f", &[r(0.0), r(f64::INFINITY)]), "0|Inf");
+    }
+
+    #[test]
+    fn test_printf_text() {
+        let t = |s: &str| Value::Text(s.to_string());
+        assert_eq!(printf("Hello %s!", &[t("world")]), "Hello world!");
+        assert_eq!(
+            printf("[%6s][%-6s][%.2s]", &[t("abc"), t("abc"), t("日本語")]),
+            "[   abc][abc   ][日本]"
+        );
+        assert_eq!(printf("'%q'", &[t("it's")]), "'it''s'");
+        assert_eq!(printf("%s", &[Value::Integer(7)]), "7");
+        assert_eq!(printf("100%% %s", &[t("sure")]), "100% sure");
+        assert_eq!(printf("no conversions", &[t("unused")]), "no conversions");
+    }
+
+    #[test]
+    fn test_printf_missing_and_null_arguments() {
+        assert_eq!(printf("[%s][%q][%d][%.1f]", &[Value::Null]), "[][][0][0.0]");
+        assert_eq!(printf("[%s][%d][%5.2f]", &[]), "[][0][ 0.00]");
+        assert_eq!(
+            printf_format("%y", &[]).unwrap_err().to_string(),
+            "unknown format conversion: %y"
+        );
+        assert!(printf_format("50%", &[]).is_err());
+    }
+
+    #[test]
+    fn test_printf_long_fields() {
+        let one = [Value::Real(1.0)];
+        // Past the digits a real has, the rest are zeros.
+        let fixed = printf("%.70000f", &one);
+        assert_eq!(fixed.len(), 70_002);
+        assert!(fixed.starts_with("1.000") && fixed.ends_with("000"));
+        let tiny = printf("%.1200f", &[Value::Real(f64::from_bits(1))]);
+        assert_eq!(tiny.len(), 1202);
+        assert!(tiny[2..].starts_with(&"0".repeat(323)) && tiny.ends_with('0'));
+        let exponential = printf("%.2000e", &one);
+        assert_eq!(exponential.len(), 2006);
+        assert!(exponential.ends_with("0e+00"));
+        assert_eq!(printf("%.2000g", &one), "1");
+        assert_eq!(printf("%.5000d", &[Value::Integer(-7)]).len(), 5001);
+        assert_eq!(printf("%70000d", &[Value::Integer(7)]).len(), 70_000);
+        for format in ["%.9999999999f", "%9999999999d", "%.1000000001s"] {
+            assert_eq!(
+                printf_format(format, &one).unwrap_err().to_string(),
+                "string or blob too big",
+                "{format}"
+            );
+        }
+        assert_eq!(
+            select("PRINTF('%.9999999999f', 1.0)").unwrap_err(),
+            "string or blob too big"
+        );
+        assert_eq!(
+            select("PRINTF('%9999999999d', 1)").unwrap_err(),
+            "string or blob too big"
+        );
+    }
+
+    #[test]
+    fn test_printf_function() {
+        assert_eq!(select("PRINTF('%s is %d', 'x', 3)").unwrap(), "x is 3");
+        assert_eq!(select("FORMAT('%.1f%%', 12.345)").unwrap(), "12.3%");
+        assert_eq!(select("PRINTF(NULL, 1)").unwrap(), "NULL");
+        assert_eq!(select("TYPEOF(PRINTF('%d', NULL))").unwrap(), "text");
+        let mut db = Database::new();
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "CREATE TABLE t (name TEXT, salary INTEGER)",
+                    "INSERT INTO t VALUES ('Cy', 80), ('Di', NULL)",
+                    "SELECT PRINTF('%-4s|%5.1f', name, salary) FROM t",
+                ]
+            )
+            .unwrap(),
+            ["Cy  | 80.0", "Di  |  0.0"]
+        );
+    }
+}
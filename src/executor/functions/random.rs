@@ -0,0 +1,99 @@
+//! Random numbers, for `RANDOM()` and `RANDOMBLOB(n)`. They are not
+//! cryptographically secure: each is drawn from a splitmix64 sequence
+//! started at a seed the operating system's randomness picks for the
+//! process, as the standard library's hash maps pick their keys.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{Eval, FunctionRegistry, fits};
+use crate::executor::EvalError;
+use crate::types::{DataType, Value, cast_value};
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(&["random"], 0, Some(0), Eval::Values(random));
+    registry.register(&["randomblob"], 1, Some(1), Eval::TryValues(randomblob));
+}
+
+/// The next number of the process's sequence. Its state only counts up,
+/// and the mixing is one-to-one, so no two calls see the same number
+/// until 2^64 have been drawn.
+fn next() -> u64 {
+    static STATE: OnceLock<AtomicU64> = OnceLock::new();
+    let state = STATE.get_or_init(|| AtomicU64::new(RandomState::new().build_hasher().finish()));
+    let mut z = state
+        .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// `RANDOM()`: an INTEGER anywhere in the range of one, different on each
+/// call.
+fn random(_: &[Value]) -> Value {
+    Value::Integer(next() as i64)
+}
+
+/// `RANDOMBLOB(n)`: a blob of `n` random bytes. Unlike SQLite's, which
+/// gives one byte for any `n` below 1, it is empty for 0 and NULL for a
+/// negative `n`.
+fn randomblob(args: &[Value]) -> Result<Value, EvalError> {
+    let n = match cast_value(args[0].clone(), DataType::Int) {
+        Value::Integer(n) if n >= 0 => fits(n as usize)?,
+        _ => return Ok(Value::Null),
+    };
+    let mut bytes = Vec::with_capacity(n);
+    while bytes.len() < n {
+        let word = next().to_le_bytes();
+        bytes.extend_from_slice(&word[..(n - bytes.len()).min(word.len())]);
+    }
+    Ok(Value::Blob(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::select;
+    use crate::executor::tests::query;
+    use crate::storage::Database;
+
+    #[test]
+    fn test_random() {
+        let draws: Vec<String> = (0..10).map(|_| select("RANDOM()").unwrap()).collect();
+        assert!(draws.iter().any(|n| *n != draws[0]), "{draws:?}");
+        assert_eq!(select("TYPEOF(RANDOM())").unwrap(), "integer");
+        assert_eq!(select("RANDOM() = RANDOM()").unwrap(), "0");
+        for _ in 0..10 {
+            let n: i64 = select("ABS(RANDOM()) % 100").unwrap().parse().unwrap();
+            assert!((0..100).contains(&n), "{n}");
+        }
+        // Each row draws its own number.
+        let mut db = Database::new();
+        let rows = query(
+            &mut db,
+            &[
+                "CREATE TABLE t (x INTEGER)",
+                "INSERT INTO t VALUES (1), (2), (3), (4)",
+                "SELECT COUNT(DISTINCT r) FROM (SELECT RANDOM() AS r FROM t) AS d",
+            ],
+        )
+        .unwrap();
+        assert_eq!(rows, ["4"]);
+    }
+
+    #[test]
+    fn test_randomblob() {
+        assert_eq!(select("TYPEOF(RANDOMBLOB(16))").unwrap(), "blob");
+        assert_eq!(select("LENGTH(HEX(RANDOMBLOB(13)))").unwrap(), "26");
+        assert_eq!(select("HEX(RANDOMBLOB(0))").unwrap(), "");
+        assert_eq!(select("RANDOMBLOB(-1)").unwrap(), "NULL");
+        assert_eq!(select("RANDOMBLOB(NULL)").unwrap(), "NULL");
+        assert_eq!(
+            select("RANDOMBLOB(100000000000)").unwrap_err(),
+            "string or blob too big"
+        );
+        assert_eq!(select("RANDOMBLOB(8) = RANDOMBLOB(8)").unwrap(), "0");
+    }
+}
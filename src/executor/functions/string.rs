@@ -0,0 +1,501 @@
+//! String functions. Lengths and positions count characters, not bytes.
+//! Arguments are converted to text, or to integers for counts, as `CAST`
+//! converts them, and each function returns NULL if its string is NULL.
+
+use super::{Eval, FunctionRegistry, fits};
+use crate::executor::{EvalError, compare_keys};
+use crate::planner::SortKey;
+use crate::types::{DataType, Value, cast_value};
+
+pub(super) fn register(registry: &mut FunctionRegistry) {
+    registry.register(&["upper"], 1, Some(1), Eval::Values(upper));
+    registry.register(&["lower"], 1, Some(1), Eval::Values(lower));
+    registry.register(&["length"], 1, Some(1), Eval::Values(length));
+    registry.register(&["substr", "substring"], 2, Some(3), Eval::Values(substr));
+    registry.register(&["trim"], 1, Some(2), Eval::Values(trim));
+    registry.register(&["ltrim"], 1, Some(2), Eval::Values(ltrim));
+    registry.register(&["rtrim"], 1, Some(2), Eval::Values(rtrim));
+    registry.register(&["replace"], 3, Some(3), Eval::Values(replace));
+    registry.register(&["instr"], 2, Some(3), Eval::Values(instr));
+    registry.register(&["charindex"], 2, Some(3), Eval::Values(charindex));
+    registry.register(&["lpad"], 2, Some(3), Eval::TryValues(lpad));
+    registry.register(&["rpad"], 2, Some(3), Eval::TryValues(rpad));
+}
+
+/// An argument as text, or `None` if it is NULL or cannot be one.
+fn text(value: &Value) -> Option<String> {
+    match cast_value(value.clone(), DataType::Text) {
+        Value::Text(s) => Some(s),
+        _ => None,
+    }
+}
+
+/// An argument as an integer, or `None` if it is NULL or cannot be one.
+fn integer(value: &Value) -> Option<i64> {
+    match cast_value(value.clone(), DataType::Int) {
+        Value::Integer(n) => Some(n),
+        _ => None,
+    }
+}
+
+/// The running state of a `GROUP_CONCAT` call over one group: its values
+/// as text, each with the values of the call's ORDER BY keys for its row.
+#[derive(Clone)]
+pub(in crate::executor) struct StringAggAccumulator {
+    delimiter: String,
+    rows: Vec<(Vec<Value>, String)>,
+}
+
+impl StringAggAccumulator {
+    pub(in crate::executor) fn new(delimiter: &str) -> Self {
+        StringAggAccumulator {
+            delimiter: delimiter.to_string(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add a value that is not NULL.
+    pub(in crate::executor) fn add(&mut self, sort_key: Vec<Value>, value: &Value) {
+        if let Some(value) = text(value) {
+            self.rows.push((sort_key, value));
+        }
+    }
+
+    /// The values joined in the order of `order_by`, ties and all values
+    /// without keys staying in the order they were added; NULL if there
+    /// are none.
+    pub(in crate::executor) fn finish(mut self, order_by: &[SortKey]) -> Value {
+        if self.rows.is_empty() {
+            return Value::Null;
+        }
+        self.rows
+            .sort_by(|(a, _), (b, _)| compare_keys(order_by, a, b));
+        let values: Vec<_> = self.rows.into_iter().map(|(_, value)| value).collect();
+        Value::Text(values.join(&self.delimiter))
+    }
+}
+
+fn upper(args: &[Value]) -> Value {
+    text(&args[0]).map_or(Value::Null, |s| Value::Text(s.to_uppercase()))
+}
+
+fn lower(args: &[Value]) -> Value {
+    text(&args[0]).map_or(Value::Null, |s| Value::Text(s.to_lowercase()))
+}
+
+/// `LENGTH(x)`: the characters in a string, or the bytes in a blob.
+fn length(args: &[Value]) -> Value {
+    match &args[0] {
+        Value::Blob(bytes) => Value::Integer(bytes.len() as i64),
+        value => text(value).map_or(Value::Null, |s| Value::Integer(s.chars().count() as i64)),
+    }
+}
+
+/// `SUBSTR(s, start [, count])`: `count` characters of `s` from the
+/// 1-based position `start`, or all of them to its end. A `start` of 0 is
+/// taken for 1, a negative one counts back from the end of `s`, and a
+/// negative `count` takes the characters before `start` instead. Positions outside `s` select
+/// nothing, so a range past its end gives the empty string.
+fn substr(args: &[Value]) -> Value {
+    let Some(s) = text(&args[0]) else {
+        return Value::Null;
+    };
+    let Some(start) = integer(&args[1]) else {
+        return Value::Null;
+    };
+    let count = match args.get(2) {
+        Some(count) => match integer(count) {
+            Some(count) => Some(count),
+            None => return Value::Null,
+        },
+        None => None,
+    };
+    let chars: Vec<char> = s.chars().collect();
+    let len = chars.len() as i64;
+    let backwards = count.is_some_and(|count| count < 0);
+    let mut count = count.map_or(len, i64::saturating_abs);
+    // Make `start` a 0-based position, the part of the range before the
+    // start of the string shortening it.
+    let mut start = match start {
+        start if start >= 0 => start.saturating_sub(1).max(0),
+        start => start.saturating_add(len),
+    };
+    if backwards {
+        start = start.saturating_sub(count);
+    }
+    if start < 0 {
+        count = count.saturating_add(start).max(0);
+        start = 0;
+    }
+    let start = start.min(len);
+    let end = start.saturating_add(count).min(len);
+    Value::Text(chars[start as usize..end as usize].iter().collect())
+}
+
+/// The characters to strip for `TRIM` and friends: those of the second
+/// argument, or whitespace if there is none or it is NULL.
+fn strip_set(args: &[Value]) -> impl Fn(char) -> bool {
+    let set: Option<Vec<char>> = args.get(1).and_then(text).map(|s| s.chars().collect());
+    move |c| match &set {
+        Some(set) => set.contains(&c),
+        None => c.is_whitespace(),
+    }
+}
+
+/// `TRIM(s [, chars])`: `s` without the given characters at either end.
+fn trim(args: &[Value]) -> Value {
+    let strip = strip_set(args);
+    text(&args[0]).map_or(Value::Null, |s| {
+        Value::Text(s.trim_matches(&strip).to_string())
+    })
+}
+
+fn ltrim(args: &[Value]) -> Value {
+    let strip = strip_set(args);
+    text(&args[0]).map_or(Value::Null, |s| {
+        Value::Text(s.trim_start_matches(&strip).to_string())
+    })
+}
+
+fn rtrim(args: &[Value]) -> Value {
+    let strip = strip_set(args);
+    text(&args[0]).map_or(Value::Null, |s| {
+        Value::Text(s.trim_end_matches(&strip).to_string())
+    })
+}
+
+/// `REPLACE(s, from, to)`: `s` with every occurrence of `from`, taken from
+/// left to right without overlapping, replaced by `to`.
+fn replace(args: &[Value]) -> Value {
+    match (text(&args[0]), text(&args[1]), text(&args[2])) {
+        (Some(s), Some(from), _) if from.is_empty() => Value::Text(s),
+        (Some(s), Some(from), Some(to)) => Value::Text(s.replace(&from, &to)),
+        _ => Value::Null,
+    }
+}
+
+/// `INSTR(s, sub [, start])`: the 1-based position of the first occurrence
+/// of `sub` in `s` that begins at or after position `start`, 1 unless it
+/// is given, or 0 if there is none. An empty `sub` is found at `start`.
+fn instr(args: &[Value]) -> Value {
+    position(&args[0], &args[1], args.get(2))
+}
+
+/// `CHARINDEX(sub, s [, start])`: `INSTR` with the strings the other way
+/// round, as SQL Server has it.
+fn charindex(args: &[Value]) -> Value {
+    position(&args[1], &args[0], args.get(2))
+}
+
+fn position(s: &Value, sub: &Value, start: Option<&Value>) -> Value {
+    let (Some(s), Some(sub)) = (text(s), text(sub)) else {
+        return Value::Null;
+    };
+    let start = match start.map(integer) {
+        Some(Some(start)) => start.max(1),
+        Some(None) => return Value::Null,
+        None => 1,
+    };
+    // The byte offset of the character at `start`, or of the end of the
+    // string just after the last.
+    let Some(from) = s
+        .char_indices()
+        .map(|(byte, _)| byte)
+        .chain([s.len()])
+        .nth((start - 1) as usize)
+    else {
+        return Value::Integer(0);
+    };
+    Value::Integer(
+        s[from..]
+            .find(&sub)
+            .map_or(0, |byte| s[..from + byte].chars().count() as i64 + 1),
+    )
+}
+
+/// The arguments of `LPAD` and `RPAD`: the string, the length to pad it
+/// to, and the padding, a space unless it is given.
+fn pad_args(args: &[Value]) -> Option<(Vec<char>, usize, Vec<char>)> {
+    let s = text(&args[0])?.chars().collect();
+    let len = integer(&args[1])?.max(0) as usize;
+    let pad = match args.get(2) {
+        Some(pad) => text(pad)?.chars().collect(),
+        None => vec![' '],
+    };
+    Some((s, len, pad))
+}
+
+/// `count` characters of `pad` repeated, unless they and `s` together
+/// are too long.
+fn padding<'a>(
+    s: &[char],
+    pad: &'a [char],
+    count: usize,
+) -> Result<impl Iterator<Item = &'a char>, EvalError> {
+    let bytes = |chars: &[char]| chars.iter().map(|c| c.len_utf8()).sum::<usize>();
+    let (cycles, rest) = match pad.len() {
+        0 => (0, 0),
+        n => (count / n, count % n),
+    };
+    fits(
+        cycles
+            .saturating_mul(bytes(pad))
+            .saturating_add(bytes(&pad[..rest]))
+            .saturating_add(bytes(s)),
+    )?;
+    Ok(pad
+        .iter()
+        .cycle()
+        .take(if pad.is_empty() { 0 } else { count }))
+}
+
+/// `LPAD(s, len [, pad])`: `s` padded on the left to `len` characters, or
+/// cut to its first `len` characters if it is longer.
+fn lpad(args: &[Value]) -> Result<Value, EvalError> {
+    let Some((s, len, pad)) = pad_args(args) else {
+        return Ok(Value::Null);
+    };
+    if s.len() >= len {
+        return Ok(Value::Text(s[..len].iter().collect()));
+    }
+    let padding = padding(&s, &pad, len - s.len())?;
+    Ok(Value::Text(padding.chain(&s).collect()))
+}
+
+/// `RPAD(s, len [, pad])`: `s` padded on the right to `len` characters, or
+/// cut to its last `len` characters if it is longer.
+fn rpad(args: &[Value]) -> Result<Value, EvalError> {
+    let Some((s, len, pad)) = pad_args(args) else {
+        return Ok(Value::Null);
+    };
+    if s.len() >= len {
+        return Ok(Value::Text(s[s.len() - len..].iter().collect()));
+    }
+    let padding = padding(&s, &pad, len - s.len())?;
+    Ok(Value::Text(s.iter().chain(padding).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::select;
+    use crate::executor::tests::query;
+    use crate::storage::Database;
+
+    #[test]
+    fn test_case_and_length() {
+        assert_eq!(select("UPPER('straße')").unwrap(), "STRASSE");
+        assert_eq!(select("LOWER('ÀÉÎ')").unwrap(), "àéî");
+        assert_eq!(select("LENGTH('héllo')").unwrap(), "5");
+        assert_eq!(select("LENGTH('日本語')").unwrap(), "3");
+        assert_eq!(select("LENGTH(12.5)").unwrap(), "4");
+        assert_eq!(select("LENGTH(CAST('ab' AS BLOB))").unwrap(), "2");
+        assert_eq!(select("LENGTH('')").unwrap(), "0");
+    }
+
+    #[test]
+    fn test_substr() {
+        assert_eq!(select("SUBSTR('héllo', 2, 3)").unwrap(), "éll");
+        assert_eq!(select("SUBSTR('hello', 3)").unwrap(), "llo");
+        assert_eq!(select("SUBSTR('hello', -3)").unwrap(), "llo");
+        assert_eq!(select("SUBSTR('hello', -3, 2)").unwrap(), "ll");
+        assert_eq!(select("SUBSTR('hello', 4, 10)").unwrap(), "lo");
+        assert_eq!(select("SUBSTR('hello', 9, 2)").unwrap(), "");
+        assert_eq!(select("SUBSTR('hello', -9, 6)").unwrap(), "he");
+        assert_eq!(select("SUBSTR('hello', 4, -2)").unwrap(), "el");
+        assert_eq!(select("SUBSTR('日本語', 2)").unwrap(), "本語");
+        assert_eq!(select("SUBSTR(12345, 2, 2)").unwrap(), "23");
+        // A negative count takes the characters before `start`.
+        assert_eq!(select("SUBSTR('hello', 6, -3)").unwrap(), "llo");
+        assert_eq!(select("SUBSTR('hello', 2, -5)").unwrap(), "h");
+        assert_eq!(select("SUBSTR('hello', -1, -2)").unwrap(), "ll");
+        // Position 0 is position 1.
+        for args in ["", ", 0", ", 2", ", 3", ", 9", ", -2"] {
+            assert_eq!(
+                select(&format!(
+                    "SUBSTR('hello', 0{args}) = SUBSTR('hello', 1{args})"
+                ))
+                .unwrap(),
+                "1",
+                "{args}"
+            );
+        }
+        assert_eq!(select("SUBSTR('hello', 0, 3)").unwrap(), "hel");
+        assert_eq!(select("SUBSTR('hello', 6)").unwrap(), "");
+        assert_eq!(select("SUBSTR('a😀b🎉c', 2, 3)").unwrap(), "😀b🎉");
+        assert_eq!(select("SUBSTR('a😀b🎉c', -2)").unwrap(), "🎉c");
+        assert_eq!(select("SUBSTR('a😀b🎉c', 4, -2)").unwrap(), "😀b");
+    }
+
+    #[test]
+    fn test_trim() {
+        assert_eq!(select("'[' || TRIM('  a b \t') || ']'").unwrap(), "[a b]");
+        assert_eq!(select("'[' || LTRIM('  a  ') || ']'").unwrap(), "[a  ]");
+        assert_eq!(select("'[' || RTRIM('  a  ') || ']'").unwrap(), "[  a]");
+        assert_eq!(select("TRIM('xxaxyx', 'xy')").unwrap(), "a");
+        assert_eq!(select("LTRIM('xxaxyx', 'xy')").unwrap(), "axyx");
+        assert_eq!(select("RTRIM('xxaxyx', 'xy')").unwrap(), "xxa");
+        assert_eq!(select("TRIM('→a←', '←→')").unwrap(), "a");
+        assert_eq!(select("'[' || TRIM(' a ', NULL) || ']'").unwrap(), "[a]");
+        // The second argument is a set of characters, not a string to strip.
+        assert_eq!(select("LTRIM('###foo###', '#')").unwrap(), "foo###");
+        assert_eq!(select("TRIM('-=-a-=b=-', '=-')").unwrap(), "a-=b");
+        assert_eq!(select("TRIM('abcxcba', 'cab')").unwrap(), "x");
+        assert_eq!(select("TRIM('aaa', 'a')").unwrap(), "");
+        assert_eq!(select("TRIM('😀🎉ok😀', '🎉😀')").unwrap(), "ok");
+        assert_eq!(select("RTRIM('ok😀é', 'é😀')").unwrap(), "ok");
+        assert_eq!(select("TRIM('xox', '')").unwrap(), "xox");
+    }
+
+    #[test]
+    fn test_trim_from() {
+        let bracketed = |expr: &str| select(&format!("'[' || {expr} || ']'")).unwrap();
+        assert_eq!(bracketed("TRIM(LEADING ' ' FROM '  hello  ')"), "[hello  ]");
+        assert_eq!(
+            bracketed("TRIM(TRAILING ' ' FROM '  hello  ')"),
+            "[  hello]"
+        );
+        assert_eq!(bracketed("TRIM(BOTH ' ' FROM '  hello  ')"), "[hello]");
+        assert_eq!(bracketed("TRIM(LEADING FROM '  hello  ')"), "[hello  ]");
+        assert_eq!(bracketed("TRIM(TRAILING FROM '  hello  ')"), "[  hello]");
+        assert_eq!(bracketed("TRIM(BOTH FROM '  hello  ')"), "[hello]");
+        assert_eq!(bracketed("TRIM(FROM '  hello  ')"), "[hello]");
+        assert_eq!(select("TRIM(LEADING 'xy' FROM 'xyxhixy')").unwrap(), "hixy");
+        assert_eq!(
+            select("TRIM(TRAILING 'xy' FROM 'xyxhixy')").unwrap(),
+            "xyxhi"
+        );
+        assert_eq!(select("TRIM('xy' FROM 'xyxhixy')").unwrap(), "hi");
+        assert_eq!(select("TRIM(LEADING NULL FROM 'x')").unwrap(), "x");
+        assert_eq!(select("TRIM(BOTH 'x' FROM NULL)").unwrap(), "NULL");
+        for (standard, sqlite) in [
+            ("TRIM(LEADING '#' FROM '##a##')", "LTRIM('##a##', '#')"),
+            ("TRIM(TRAILING '#' FROM '##a##')", "RTRIM('##a##', '#')"),
+            ("TRIM(BOTH '#' FROM '##a##')", "TRIM('##a##', '#')"),
+            ("TRIM(BOTH FROM ' \ta ')", "TRIM(' \ta ')"),
+        ] {
+            assert_eq!(select(standard).unwrap(), select(sqlite).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_replace_and_instr() {
+        assert_eq!(
+            select("REPLACE('foo food', 'foo', 'bar')").unwrap(),
+            "bar bard"
+        );
+        // Occurrences are matched left to right and do not overlap.
+        assert_eq!(select("REPLACE('aaaa', 'aa', 'b')").unwrap(), "bb");
+        assert_eq!(select("REPLACE('aaa', 'aa', 'b')").unwrap(), "ba");
+        assert_eq!(select("REPLACE('abc', '', 'x')").unwrap(), "abc");
+        assert_eq!(select("REPLACE(1231, 1, 9)").unwrap(), "9239");
+        assert_eq!(select("INSTR('héllo', 'llo')").unwrap(), "3");
+        assert_eq!(select("INSTR('hello', 'z')").unwrap(), "0");
+        assert_eq!(select("INSTR('hello', '')").unwrap(), "1");
+    }
+
+    #[test]
+    fn test_instr_from_start_and_charindex() {
+        assert_eq!(select("INSTR('hello world', 'world')").unwrap(), "7");
+        // Positions count characters: '😀' is four bytes.
+        assert_eq!(select("INSTR('😀a😀b', 'b')").unwrap(), "4");
+        assert_eq!(select("INSTR('😀a😀b', '😀', 2)").unwrap(), "3");
+        assert_eq!(select("INSTR('abcabc', 'bc', 2)").unwrap(), "2");
+        assert_eq!(select("INSTR('abcabc', 'bc', 3)").unwrap(), "5");
+        assert_eq!(select("INSTR('abcabc', 'bc', 6)").unwrap(), "0");
+        assert_eq!(select("INSTR('abc', 'b', -5)").unwrap(), "2");
+        assert_eq!(select("INSTR('abc', 'abcd')").unwrap(), "0");
+        assert_eq!(select("INSTR('abc', '', 1)").unwrap(), "1");
+        assert_eq!(select("INSTR('abc', '', 3)").unwrap(), "3");
+        assert_eq!(select("INSTR('abc', '', 5)").unwrap(), "0");
+        assert_eq!(select("INSTR('', '')").unwrap(), "1");
+        assert_eq!(select("CHARINDEX('world', 'hello world')").unwrap(), "7");
+        assert_eq!(select("CHARINDEX('😀', 'a😀b😀', 3)").unwrap(), "4");
+        assert_eq!(select("CHARINDEX('z', 'abc')").unwrap(), "0");
+        assert_eq!(select("CHARINDEX('', 'abc')").unwrap(), "1");
+        for null in [
+            "INSTR(NULL, 'a')",
+            "INSTR('a', NULL)",
+            "INSTR('a', 'a', NULL)",
+            "CHARINDEX(NULL, 'a')",
+            "CHARINDEX('a', NULL)",
+        ] {
+            assert_eq!(select(null).unwrap(), "NULL", "{null}");
+        }
+    }
+
+    #[test]
+    fn test_pad() {
+        assert_eq!(select("LPAD('7', 3, '0')").unwrap(), "007");
+        assert_eq!(select("LPAD('ab', 7, 'xyz')").unwrap(), "xyzxyab");
+        assert_eq!(select("RPAD('ab', 7, 'xyz')").unwrap(), "abxyzxy");
+        assert_eq!(select("'[' || LPAD('ab', 4) || ']'").unwrap(), "[  ab]");
+        assert_eq!(select("LPAD('héllo', 2, '*')").unwrap(), "hé");
+        assert_eq!(select("RPAD('héllo', 2, '*')").unwrap(), "lo");
+        assert_eq!(select("LPAD('ab', 5, '')").unwrap(), "ab");
+        assert_eq!(select("LPAD('ab', -1, 'x')").unwrap(), "");
+        // The result may be no longer than a string may be.
+        for call in [
+            "LPAD('a', 9223372036854775807, 'x')",
+            "RPAD('a', 9223372036854775807)",
+            "LPAD('', 500000001, 'é')",
+        ] {
+            assert_eq!(
+                select(call).unwrap_err(),
+                "string or blob too big",
+                "{call}"
+            );
+        }
+        assert_eq!(select("LPAD('ab', 9223372036854775807, '')").unwrap(), "ab");
+        assert_eq!(select("LENGTH(RPAD('', 100000, 'é'))").unwrap(), "100000");
+    }
+
+    #[test]
+    fn test_concat_operator() {
+        assert_eq!(select("'a' || 1 || 2.5").unwrap(), "a12.5");
+        assert_eq!(select("1 || 2").unwrap(), "12");
+        assert_eq!(select("TYPEOF(1 || 2)").unwrap(), "text");
+        assert_eq!(select("CAST('hi' AS BLOB) || '!'").unwrap(), "hi!");
+        assert_eq!(select("NULL || 'x'").unwrap(), "NULL");
+        assert_eq!(select("'x' || NULL").unwrap(), "NULL");
+    }
+
+    #[test]
+    fn test_null_strings() {
+        for call in [
+            "UPPER(NULL)",
+            "LOWER(NULL)",
+            "LENGTH(NULL)",
+            "SUBSTR(NULL, 1)",
+            "SUBSTR('a', NULL)",
+            "TRIM(NULL)",
+            "LTRIM(NULL, 'x')",
+            "RTRIM(NULL)",
+            "REPLACE(NULL, 'a', 'b')",
+            "REPLACE('a', 'a', NULL)",
+            "INSTR(NULL, 'a')",
+            "INSTR('a', NULL)",
+            "LPAD(NULL, 2)",
+            "RPAD('a', NULL)",
+            "RPAD('a', 3, NULL)",
+        ] {
+            assert_eq!(select(call).unwrap(), "NULL", "{call}");
+        }
+    }
+
+    #[test]
+    fn test_string_functions_over_rows() {
+        let mut db = Database::new();
+        assert_eq!(
+            query(
+                &mut db,
+                &[
+                    "CREATE TABLE users (name TEXT)",
+                    "INSERT INTO users VALUES ('alice'), ('Zoë')",
+                    "SELECT UPPER(name), LENGTH(name), SUBSTR(name, 2, 3) FROM users",
+                ]
+            )
+            .unwrap(),
+            ["ALICE|5|lic", "ZOË|3|oë"]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! `GLOB` pattern matching.
+//!
+//! `*` matches any sequence of characters (including none), `?` matches
+//! exactly one character, and `[...]` matches one character of a set.
+//! Every other character matches itself. Matching is always
+//! case-sensitive.
+//!
+//! A set lists characters and `a-z` ranges; it is negated if it starts
+//! with `^` or `!`. A `]` right after the opening bracket (or the negation)
+//! and a `-` at either end of the set stand for themselves. A pattern with
+//! an unterminated set matches nothing.
+
+/// One element of a parsed pattern.
+enum Piece {
+    /// `*`
+    Any,
+    /// `?`
+    One,
+    Char(char),
+    Set {
+        negated: bool,
+        /// Inclusive ranges; a single character is a range of one.
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Piece {
+    fn matches_char(&self, c: char) -> bool {
+        match self {
+            Piece::Any | Piece::One => true,
+            Piece::Char(p) => *p == c,
+            Piece::Set { negated, ranges } => {
+                ranges.iter().any(|&(low, high)| (low..=high).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+/// Parse a pattern, or `None` if a set is not terminated.
+fn compile(pattern: &str) -> Option<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        pieces.push(match c {
+            '*' => Piece::Any,
+            '?' => Piece::One,
+            '[' => {
+                let negated = chars.next_if(|&c| c == '^' || c == '!').is_some();
+                let mut ranges = Vec::new();
+                let mut first = true;
+                loop {
+                    let low = chars.next()?;
+                    if low == ']' && !first {
+                        break;
+                    }
+                    first = false;
+                    let high = match chars.peek() {
+                        Some('-') => {
+                            chars.next();
+                            match chars.next()? {
+                                // A trailing `-` is a member, as is `low`.
+                                ']' => {
+                                    ranges.push((low, low));
+                                    ranges.push(('-', '-'));
+                                    break;
+                                }
+                                high => high,
+                            }
+                        }
+                        _ => low,
+                    };
+                    ranges.push((low, high));
+                }
+                Piece::Set { negated, ranges }
+            }
+            c => Piece::Char(c),
+        });
+    }
+    Some(pieces)
+}
+
+pub fn eval_glob(pattern: &str, text: &str) -> bool {
+    let Some(pattern) = compile(pattern) else {
+        return false;
+    };
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the most recent `*` and the text position it was tried
+    // at, so a failed match can retry with the `*` absorbing one more char.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(Piece::Any) => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(piece) if piece.matches_char(text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, start)) => {
+                    p = star + 1;
+                    t = start + 1;
+                    backtrack = Some((star, start + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|piece| matches!(piece, Piece::Any))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_star_and_question_mark() {
+        assert!(eval_glob("abc", "abc"));
+        assert!(!eval_glob("abc", "abcd"));
+        assert!(eval_glob("a*", "a"));
+        assert!(eval_glob("*le", "apple"));
+        assert!(eval_glob("a*b*c", "aXbYbZc"));
+        assert!(!eval_glob("a*b*c", "aXbYbZ"));
+        assert!(eval_glob("*", ""));
+        assert!(eval_glob("a?c", "abc"));
+        assert!(!eval_glob("a?c", "ac"));
+        assert!(eval_glob("???", "héé"));
+        // LIKE's wildcards are ordinary characters.
+        assert!(!eval_glob("a%", "abc"));
+        assert!(eval_glob("a_c", "a_c"));
+    }
+
+    #[test]
+    fn test_sets_and_ranges() {
+        assert!(eval_glob("[abc]x", "bx"));
+        assert!(!eval_glob("[abc]x", "dx"));
+        assert!(eval_glob("[a-z]*", "hello"));
+        assert!(!eval_glob("[a-z]*", "Hello"));
+        assert!(eval_glob("[0-9][0-9]", "42"));
+        assert!(eval_glob("[]]", "]"));
+        assert!(eval_glob("[a-]", "-"));
+        assert!(eval_glob("[*?]", "*"));
+        assert!(!eval_glob("[abc", "a"));
+    }
+
+    #[test]
+    fn test_negated_sets() {
+        assert!(eval_glob("[^abc]", "d"));
+        assert!(!eval_glob("[^abc]", "a"));
+        assert!(eval_glob("[!0-9]*", "x1"));
+        assert!(!eval_glob("[!0-9]*", "1x"));
+        assert!(eval_glob("[^]]", "a"));
+        assert!(!eval_glob("[^]]", "]"));
+    }
+
+    #[test]
+    fn test_matching_is_case_sensitive() {
+        assert!(!eval_glob("ABC", "abc"));
+        assert!(eval_glob("A*", "Apple"));
+        assert!(!eval_glob("a*", "Apple"));
+    }
+}
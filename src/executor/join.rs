@@ -0,0 +1,430 @@
+//! Join execution.
+
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::{Context, EvalError, Evaluator, Rows, execute_plan};
+use crate::ast::{Expr, JoinKind};
+use crate::planner::{HashJoinNode, IndexNestedLoopJoinNode, PlanNode, Schema, SortMergeJoinNode};
+use crate::storage::{DbError, Row};
+use crate::types::{Collation, OrdValue, Value};
+
+/// Join by comparing every left row with every right row. The right input
+/// is read once into memory; the left input is streamed, and joined rows
+/// are produced one pair at a time, so even a large product is never held
+/// in memory.
+///
+/// Output order is the left scan order, with each left row's matches in
+/// right order. An unmatched left row (if preserved) appears where its
+/// matches would have been; unmatched right rows (if preserved) follow
+/// once the left input is exhausted.
+pub(super) fn nested_loop<'a>(
+    ctx: Context<'a>,
+    left: &'a PlanNode,
+    right: &'a PlanNode,
+    kind: JoinKind,
+    predicate: Option<&'a Expr>,
+    schema: &'a Schema,
+) -> Rows<'a> {
+    let right_rows = match execute_plan(ctx, right).collect::<Result<Vec<Row>, _>>() {
+        Ok(rows) => Rc::new(rows),
+        Err(e) => return Box::new(std::iter::once(Err(e))),
+    };
+    let left_width = left.schema().columns.len();
+    let right_width = right.schema().columns.len();
+    let matched = Rc::new(RefCell::new(vec![false; right_rows.len()]));
+
+    let pairs = {
+        let right_rows = Rc::clone(&right_rows);
+        let matched = Rc::clone(&matched);
+        execute_plan(ctx, left).flat_map(move |left_row| -> Rows<'a> {
+            let left_row = match left_row {
+                Ok(row) => Rc::new(row),
+                Err(e) => return Box::new(std::iter::once(Err(e))),
+            };
+            let found = Rc::new(Cell::new(false));
+            let pairs = {
+                let (left_row, right_rows) = (Rc::clone(&left_row), Rc::clone(&right_rows));
+                let (found, matched) = (Rc::clone(&found), Rc::clone(&matched));
+                (0..right_rows.len()).filter_map(move |i| {
+                    let mut row = Row::clone(&left_row);
+                    row.extend(right_rows[i].iter().cloned());
+                    match matches(ctx, predicate, schema, &row) {
+                        Ok(true) => {
+                            found.set(true);
+                            matched.borrow_mut()[i] = true;
+                            Some(Ok(row))
+                        }
+                        Ok(false) => None,
+                        Err(e) => Some(Err(e)),
+                    }
+                })
+            };
+            if !kind.preserves_left() {
+                return Box::new(pairs);
+            }
+            // Checked only after this left row's pairs are exhausted.
+            let unmatched = std::iter::once(()).filter_map(move |()| {
+                if found.get() {
+                    return None;
+                }
+                let mut row = Row::clone(&left_row);
+                row.extend(std::iter::repeat_n(Value::Null, right_width));
+                Some(Ok(row))
+            });
+            Box::new(pairs.chain(unmatched))
+        })
+    };
+    if !kind.preserves_right() {
+        return Box::new(pairs);
+    }
+    // Runs only after `pairs` is exhausted, when `matched` is complete.
+    let unmatched_right = std::iter::once(()).flat_map(move |()| {
+        let matched = matched.borrow();
+        right_rows
+            .iter()
+            .zip(matched.iter())
+            .filter(|(_, matched)| !**matched)
+            .map(|(right_row, _)| {
+                let mut row = vec![Value::Null; left_width];
+                row.extend(right_row.iter().cloned());
+                Ok(row)
+            })
+            .collect::<Vec<_>>()
+    });
+    Box::new(pairs.chain(unmatched_right))
+}
+
+/// Join by looking up each probe row's matches in a hash table of the
+/// build rows. Like a nested loop join, the build rows are read into
+/// memory and the probe rows streamed, but a probe row is only compared
+/// with the build rows whose key equals its own.
+pub(super) fn hash_join<'a>(ctx: Context<'a>, join: &'a HashJoinNode) -> Rows<'a> {
+    let build_rows = match execute_plan(ctx, &join.build).collect::<Result<Vec<Row>, _>>() {
+        Ok(rows) => Rc::new(rows),
+        Err(e) => return Box::new(std::iter::once(Err(e))),
+    };
+    let build_schema = join.build.schema();
+    let mut table: HashMap<Vec<OrdValue>, Vec<usize>> = HashMap::new();
+    for (i, row) in build_rows.iter().enumerate() {
+        match key(ctx, &join.build_key, &join.collations, build_schema, row) {
+            Ok(Some(key)) => table.entry(key).or_default().push(i),
+            Ok(None) => {}
+            Err(e) => return Box::new(std::iter::once(Err(e))),
+        }
+    }
+    let (left, right) = join.sides();
+    let (left_width, right_width) = (left.schema().columns.len(), right.schema().columns.len());
+    let (preserves_build, preserves_probe) = if join.build_left {
+        (join.kind.preserves_left(), join.kind.preserves_right())
+    } else {
+        (join.kind.preserves_right(), join.kind.preserves_left())
+    };
+    // Whole output rows from a build row and a probe row, either of which
+    // may be missing.
+    let pair = move |build: Option<&Row>, probe: Option<&Row>| {
+        let (left, right, left_width) = if join.build_left {
+            (build, probe, left_width)
+        } else {
+            (probe, build, left_width)
+        };
+        let mut row: Row = match left {
+            Some(row) => row.clone(),
+            None => vec![Value::Null; left_width],
+        };
+        match right {
+            Some(r) => row.extend(r.iter().cloned()),
+            None => row.extend(std::iter::repeat_n(Value::Null, right_width)),
+        }
+        row
+    };
+    let matched = Rc::new(RefCell::new(vec![false; build_rows.len()]));
+
+    let probe_schema = join.probe.schema();
+    let pairs = {
+        let build_rows = Rc::clone(&build_rows);
+        let matched = Rc::clone(&matched);
+        execute_plan(ctx, &join.probe).flat_map(move |probe_row| -> Rows<'a> {
+            let probe_row = match probe_row {
+                Ok(row) => row,
+                Err(e) => return Box::new(std::iter::once(Err(e))),
+            };
+            let candidates = match key(
+                ctx,
+                &join.probe_key,
+                &join.collations,
+                probe_schema,
+                &probe_row,
+            ) {
+                Ok(Some(key)) => table.get(&key).map_or(&[][..], Vec::as_slice),
+                Ok(None) => &[],
+                Err(e) => return Box::new(std::iter::once(Err(e))),
+            };
+            let mut rows = Vec::new();
+            for &i in candidates {
+                let row = pair(Some(&build_rows[i]), Some(&probe_row));
+                match matches(ctx, join.residual.as_ref(), &join.schema, &row) {
+                    Ok(true) => {
+                        matched.borrow_mut()[i] = true;
+                        rows.push(Ok(row));
+                    }
+                    Ok(false) => {}
+                    Err(e) => rows.push(Err(e)),
+                }
+            }
+            if rows.is_empty() && preserves_probe {
+                rows.push(Ok(pair(None, Some(&probe_row))));
+            }
+            Box::new(rows.into_iter())
+        })
+    };
+    if !preserves_build {
+        return Box::new(pairs);
+    }
+    // Runs only after `pairs` is exhausted, when `matched` is complete.
+    let unmatched_build = std::iter::once(()).flat_map(move |()| {
+        let matched = matched.borrow();
+        build_rows
+            .iter()
+            .zip(matched.iter())
+            .filter(|(_, matched)| !**matched)
+            .map(|(build_row, _)| Ok(pair(Some(build_row), None)))
+            .collect::<Vec<_>>()
+    });
+    Box::new(pairs.chain(unmatched_build))
+}
+
+/// Join by looking each outer row's key up in an index of the inner
+/// table. The outer input is streamed, and only the table rows the index
+/// finds are read, so the table is never scanned.
+pub(super) fn index_nested_loop<'a>(
+    ctx: Context<'a>,
+    join: &'a IndexNestedLoopJoinNode,
+) -> Rows<'a> {
+    let Some(table) = ctx.db.get_table(&join.inner_table) else {
+        let e = DbError::NoSuchTable(join.inner_table.clone());
+        return Box::new(std::iter::once(Err(e.into())));
+    };
+    let Some(index) = table.index(&join.inner_index) else {
+        let e = DbError::NoSuchIndex(join.inner_index.clone());
+        return Box::new(std::iter::once(Err(e.into())));
+    };
+    let outer_schema = join.outer.schema();
+    let inner_width = join.inner_schema.columns.len();
+    let outer_width = outer_schema.columns.len();
+    let preserves_outer = if join.inner_left {
+        join.kind.preserves_right()
+    } else {
+        join.kind.preserves_left()
+    };
+    let pair = move |inner: Option<&Row>, outer: &Row| {
+        let inner = match inner {
+            Some(row) => row.clone(),
+            None => vec![Value::Null; inner_width],
+        };
+        let mut row = Row::with_capacity(inner_width + outer_width);
+        if join.inner_left {
+            row.extend(inner);
+            row.extend(outer.iter().cloned());
+        } else {
+            row.extend(outer.iter().cloned());
+            row.extend(inner);
+        }
+        row
+    };
+    Box::new(
+        execute_plan(ctx, &join.outer).flat_map(move |outer_row| -> Rows<'a> {
+            let outer_row = match outer_row {
+                Ok(row) => row,
+                Err(e) => return Box::new(std::iter::once(Err(e))),
+            };
+            let evaluator = Evaluator::new(ctx, outer_schema, &outer_row);
+            let key = match join
+                .outer_key
+                .iter()
+                .map(|e| evaluator.eval(e))
+                .collect::<Result<Vec<_>, _>>()
+            {
+                Ok(key) => key,
+                Err(e) => return Box::new(std::iter::once(Err(e))),
+            };
+            let mut rows = Vec::new();
+            for &i in index.lookup(&key) {
+                let inner_row = &table.rows[i];
+                match matches(
+                    ctx,
+                    join.inner_filter.as_ref(),
+                    &join.inner_schema,
+                    inner_row,
+                ) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(e) => {
+                        rows.push(Err(e));
+                        continue;
+                    }
+                }
+                let row = pair(Some(inner_row), &outer_row);
+                match matches(ctx, join.residual.as_ref(), &join.schema, &row) {
+                    Ok(true) => rows.push(Ok(row)),
+                    Ok(false) => {}
+                    Err(e) => rows.push(Err(e)),
+                }
+            }
+            if rows.is_empty() && preserves_outer {
+                rows.push(Ok(pair(None, &outer_row)));
+            }
+            Box::new(rows.into_iter())
+        }),
+    )
+}
+
+/// Join inputs sorted on their keys by walking both together: rows whose
+/// keys are equal are paired, and a row whose key is below the other
+/// side's is unmatched. Both inputs are read into memory, and the output
+/// is produced at once.
+pub(super) fn sort_merge_join<'a>(ctx: Context<'a>, join: &'a SortMergeJoinNode) -> Rows<'a> {
+    match merge(ctx, join) {
+        Ok(rows) => Box::new(rows.into_iter().map(Ok)),
+        Err(e) => Box::new(std::iter::once(Err(e))),
+    }
+}
+
+fn merge(ctx: Context<'_>, join: &SortMergeJoinNode) -> Result<Vec<Row>, EvalError> {
+    let left = keyed_rows(ctx, &join.left, &join.left_key, &join.collations)?;
+    let right = keyed_rows(ctx, &join.right, &join.right_key, &join.collations)?;
+    let (left_width, right_width) = (
+        join.left.schema().columns.len(),
+        join.right.schema().columns.len(),
+    );
+    let pair = |left: Option<&Row>, right: Option<&Row>| {
+        let mut row: Row = match left {
+            Some(row) => row.clone(),
+            None => vec![Value::Null; left_width],
+        };
+        match right {
+            Some(r) => row.extend(r.iter().cloned()),
+            None => row.extend(std::iter::repeat_n(Value::Null, right_width)),
+        }
+        row
+    };
+    let (preserves_left, preserves_right) =
+        (join.kind.preserves_left(), join.kind.preserves_right());
+    // Rows with a NULL in their key match nothing; those preserved come
+    // first, as NULLs sort first.
+    let mut out = Vec::new();
+    let (left, left_nulls): (Vec<_>, Vec<_>) = left.into_iter().partition(|(k, _)| k.is_some());
+    let (right, right_nulls): (Vec<_>, Vec<_>) = right.into_iter().partition(|(k, _)| k.is_some());
+    if preserves_left {
+        out.extend(left_nulls.iter().map(|(_, row)| pair(Some(row), None)));
+    }
+    if preserves_right {
+        out.extend(right_nulls.iter().map(|(_, row)| pair(None, Some(row))));
+    }
+    let (mut i, mut j) = (0, 0);
+    loop {
+        // An exhausted side is behind the other.
+        let order = match (left.get(i), right.get(j)) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some((l, _)), Some((r, _))) => l.cmp(r),
+        };
+        match order {
+            Ordering::Less => {
+                if preserves_left {
+                    out.push(pair(Some(&left[i].1), None));
+                }
+                i += 1;
+            }
+            Ordering::Greater => {
+                if preserves_right {
+                    out.push(pair(None, Some(&right[j].1)));
+                }
+                j += 1;
+            }
+            Ordering::Equal => {
+                let key = &left[i].0;
+                let left_end = i + left[i..].iter().take_while(|(k, _)| k == key).count();
+                let right_end = j + right[j..].iter().take_while(|(k, _)| k == key).count();
+                let mut matched = vec![false; right_end - j];
+                for (_, left_row) in &left[i..left_end] {
+                    let mut found = false;
+                    for (m, (_, right_row)) in right[j..right_end].iter().enumerate() {
+                        let row = pair(Some(left_row), Some(right_row));
+                        if matches(ctx, join.residual.as_ref(), &join.schema, &row)? {
+                            found = true;
+                            matched[m] = true;
+                            out.push(row);
+                        }
+                    }
+                    if !found && preserves_left {
+                        out.push(pair(Some(left_row), None));
+                    }
+                }
+                if preserves_right {
+                    for ((_, right_row), matched) in right[j..right_end].iter().zip(matched) {
+                        if !matched {
+                            out.push(pair(None, Some(right_row)));
+                        }
+                    }
+                }
+                (i, j) = (left_end, right_end);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// A row with its join key.
+type KeyedRow = (Option<Vec<OrdValue>>, Row);
+
+/// The rows of `node`, each with its key.
+fn keyed_rows(
+    ctx: Context<'_>,
+    node: &PlanNode,
+    exprs: &[Expr],
+    collations: &[Collation],
+) -> Result<Vec<KeyedRow>, EvalError> {
+    let schema = node.schema();
+    execute_plan(ctx, node)
+        .map(|row| {
+            let row = row?;
+            Ok((key(ctx, exprs, collations, schema, &row)?, row))
+        })
+        .collect()
+}
+
+/// The join key of a row, to hash or compare: the values of `exprs`, with texts normalized
+/// under their collations; `None` if one is NULL, as NULL equals nothing.
+fn key(
+    ctx: Context<'_>,
+    exprs: &[Expr],
+    collations: &[Collation],
+    schema: &Schema,
+    row: &Row,
+) -> Result<Option<Vec<OrdValue>>, EvalError> {
+    let evaluator = Evaluator::new(ctx, schema, row);
+    let mut key = Vec::with_capacity(exprs.len());
+    for (expr, collation) in exprs.iter().zip(collations) {
+        match evaluator.eval(expr)? {
+            Value::Null => return Ok(None),
+            value => key.push(OrdValue(collation.key(value))),
+        }
+    }
+    Ok(Some(key))
+}
+
+fn matches(
+    ctx: Context<'_>,
+    predicate: Option<&Expr>,
+    schema: &Schema,
+    row: &Row,
+) -> Result<bool, EvalError> {
+    match predicate {
+        Some(predicate) => Evaluator::new(ctx, schema, row).holds(predicate),
+        None => Ok(true),
+    }
+}
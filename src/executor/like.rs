@@ -0,0 +1,147 @@
+//! `LIKE` pattern matching.
+//!
+//! `%` matches any sequence of characters (including none) and `_` matches
+//! exactly one character. A character given as the ESCAPE character makes
+//! the character after it match only itself, so `\%` with ESCAPE `\`
+//! matches a literal `%`. Every other character matches itself, compared
+//! under the collation of the match: exactly for BINARY, ignoring case for
+//! NOCASE.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use crate::types::Collation;
+
+/// One element of a compiled pattern.
+#[derive(Clone, Copy)]
+enum Piece {
+    /// `%`
+    Any,
+    /// `_`
+    One,
+    Char(char),
+}
+
+/// Split a pattern into pieces, resolving escapes. An ESCAPE character at
+/// the end of the pattern, with nothing to escape, matches itself.
+fn compile(pattern: &str, escape: Option<char>) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        pieces.push(match c {
+            c if Some(c) == escape => Piece::Char(chars.next().unwrap_or(c)),
+            '%' => Piece::Any,
+            '_' => Piece::One,
+            c => Piece::Char(c),
+        });
+    }
+    pieces
+}
+
+pub fn like_match(pattern: &str, text: &str, escape: Option<char>, collation: Collation) -> bool {
+    let matcher = Matcher {
+        pattern: compile(pattern, escape),
+        text: text.chars().collect(),
+        collation,
+        failed: RefCell::new(HashSet::new()),
+    };
+    matcher.matches(0, 0)
+}
+
+struct Matcher {
+    pattern: Vec<Piece>,
+    text: Vec<char>,
+    collation: Collation,
+    /// The `(pattern, text)` positions already known not to match, so a
+    /// pattern with many `%` cannot take exponential time.
+    failed: RefCell<HashSet<(usize, usize)>>,
+}
+
+impl Matcher {
+    /// Whether the pattern from piece `p` matches the text from char `t`.
+    fn matches(&self, p: usize, t: usize) -> bool {
+        let Some(&piece) = self.pattern.get(p) else {
+            return t == self.text.len();
+        };
+        if self.failed.borrow().contains(&(p, t)) {
+            return false;
+        }
+        let matched = match piece {
+            // `%` absorbs nothing, or one more char and stays in play.
+            Piece::Any => self.matches(p + 1, t) || (t < self.text.len() && self.matches(p, t + 1)),
+            Piece::One => t < self.text.len() && self.matches(p + 1, t + 1),
+            Piece::Char(c) => self
+                .text
+                .get(t)
+                .is_some_and(|&tc| self.collation.chars_equal(c, tc) && self.matches(p + 1, t + 1)),
+        };
+        if !matched {
+            self.failed.borrow_mut().insert((p, t));
+        }
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn like(pattern: &str, text: &str) -> bool {
+        like_match(pattern, text, None, Collation::Binary)
+    }
+
+    #[test]
+    fn test_literal_patterns() {
+        assert!(like("abc", "abc"));
+        assert!(!like("abc", "abcd"));
+        assert!(!like("abc", "ab"));
+        assert!(like("", ""));
+        assert!(!like("", "a"));
+    }
+
+    #[test]
+    fn test_percent_matches_any_sequence() {
+        assert!(like("a%", "a"));
+        assert!(like("a%", "apple"));
+        assert!(like("%le", "apple"));
+        assert!(like("%p%", "apple"));
+        assert!(like("%", ""));
+        assert!(like("a%b%c", "aXbYbZc"));
+        assert!(!like("a%b%c", "aXbYbZ"));
+        assert!(like("%aab", "aaab"));
+        assert!(!like(&"%a".repeat(30), &"a".repeat(29)));
+    }
+
+    #[test]
+    fn test_underscore_matches_one_char() {
+        assert!(like("a_c", "abc"));
+        assert!(!like("a_c", "ac"));
+        assert!(like("___", "héé"));
+        assert!(like("_%", "x"));
+        assert!(!like("_%", ""));
+    }
+
+    #[test]
+    fn test_escape() {
+        let escaped = |pattern, text| like_match(pattern, text, Some('\\'), Collation::Binary);
+        assert!(escaped("100\\%", "100%"));
+        assert!(!escaped("100\\%", "1000"));
+        assert!(escaped("a\\_c", "a_c"));
+        assert!(!escaped("a\\_c", "abc"));
+        assert!(escaped("a\\\\b", "a\\b"));
+        assert!(escaped("%\\%%", "50% off"));
+        // A trailing escape character stands for itself.
+        assert!(escaped("a\\", "a\\"));
+        assert!(like_match("a!%", "a%", Some('!'), Collation::Binary));
+    }
+
+    #[test]
+    fn test_case_follows_collation() {
+        assert!(!like("ABC", "abc"));
+        assert!(like("A%", "Apple"));
+        assert!(!like("a%", "Apple"));
+        assert!(like_match("ABC", "abc", None, Collation::NoCase));
+        assert!(like_match("a_P%", "APple", None, Collation::NoCase));
+        assert!(like_match("É%", "été", None, Collation::NoCase));
+    }
+}
@@ -0,0 +1,258 @@
+//! `PRAGMA` statements: the schema of the database, as rows.
+
+use super::EvalError;
+use crate::ast::PragmaStmt;
+use crate::planner::plan_select;
+use crate::storage::{Database, Row, Table};
+use crate::types::Value;
+
+/// The rows a PRAGMA answers with, as SQLite's do:
+///
+/// - `table_info(<table>)`: `cid, name, type, notnull, dflt_value, pk` for
+///   each column, `pk` counting the column's place in the primary key from
+///   1, and 0 for others;
+/// - `table_list`: `schema, name, type, ncol, wr, strict` for each table and
+///   view, by name;
+/// - `index_list(<table>)`: `seq, name, unique, origin, partial` for each
+///   index of the table, `origin` being `c` for CREATE INDEX and `u` or
+///   `pk` for a UNIQUE or PRIMARY KEY constraint;
+/// - `foreign_key_list(<table>)`: `id, seq, table, from, to, on_update,
+///   on_delete, match` for each column of each foreign key, `to` being
+///   NULL where the key refers to the parent's primary key;
+/// - `journal_mode`: `memory`, the only mode a database of its own has.
+///   A [`Connection`](crate::connection::Connection) may be put in `wal`
+///   mode instead.
+///
+/// A table that does not exist, like a PRAGMA that does not, has no rows.
+pub(super) fn pragma(db: &Database, pragma: &PragmaStmt) -> Result<Vec<Row>, EvalError> {
+    let table = pragma.arg.as_deref().and_then(|name| db.get_table(name));
+    let rows = match (pragma.name.to_ascii_lowercase().as_str(), table) {
+        ("table_info", Some(table)) => table_info(table),
+        ("table_list", _) => table_list(db)?,
+        ("index_list", Some(table)) => index_list(table),
+        ("foreign_key_list", Some(table)) => foreign_key_list(table),
+        ("journal_mode", _) => vec![vec![text("memory")]],
+        _ => Vec::new(),
+    };
+    Ok(rows)
+}
+
+fn text(s: impl Into<String>) -> Value {
+    Value::Text(s.into())
+}
+
+fn int(n: usize) -> Value {
+    Value::Integer(n as i64)
+}
+
+fn table_info(table: &Table) -> Vec<Row> {
+    let key = table.primary_key.as_ref().map_or(&[][..], |pk| &pk.columns);
+    table
+        .schema
+        .iter()
+        .enumerate()
+        .map(|(cid, column)| {
+            let ty = column.data_type.map_or(String::new(), |ty| ty.to_string());
+            let default = column
+                .default
+                .as_ref()
+                .map_or(Value::Null, |e| text(e.to_string()));
+            let pk = key.iter().position(|&i| i == cid).map_or(0, |i| i + 1);
+            vec![
+                int(cid),
+                text(&column.name),
+                text(ty),
+                Value::Integer(i64::from(!column.nullable)),
+                default,
+                int(pk),
+            ]
+        })
+        .collect()
+}
+
+fn table_list(db: &Database) -> Result<Vec<Row>, EvalError> {
+    let mut rows = Vec::new();
+    for table in db.tables() {
+        rows.push((table.name.clone(), "table", table.schema.len()));
+    }
+    for (name, query) in db.views() {
+        let plan = plan_select(db, query)?;
+        let columns = plan.schema().columns.iter().filter(|c| !c.hidden).count();
+        rows.push((name.to_string(), "view", columns));
+    }
+    rows.sort_by_key(|(name, ..)| name.to_lowercase());
+    Ok(rows
+        .into_iter()
+        .map(|(name, ty, columns)| {
+            vec![
+                text("main"),
+                text(name),
+                text(ty),
+                int(columns),
+                int(0),
+                int(0),
+            ]
+        })
+        .collect())
+}
+
+fn index_list(table: &Table) -> Vec<Row> {
+    table
+        .indexes
+        .iter()
+        .enumerate()
+        .map(|(seq, index)| {
+            let origin = match &table.primary_key {
+                _ if !index.enforces_constraint() => "c",
+                Some(pk) if pk.columns == index.columns => "pk",
+                _ => "u",
+            };
+            vec![
+                int(seq),
+                text(&index.name),
+                Value::Integer(i64::from(index.unique)),
+                text(origin),
+                int(0),
+            ]
+        })
+        .collect()
+}
+
+fn foreign_key_list(table: &Table) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for (id, key) in table.foreign_keys.iter().enumerate() {
+        for (seq, &column) in key.columns.iter().enumerate() {
+            let to = key.references.columns.get(seq).map_or(Value::Null, text);
+            rows.push(vec![
+                int(id),
+                int(seq),
+                text(&key.references.table),
+                text(&table.schema[column].name),
+                to,
+                text("NO ACTION"),
+                text(key.references.on_delete.to_string()),
+                text("NONE"),
+            ]);
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::execute;
+    use crate::parser::parse_statement;
+
+    fn db() -> Database {
+        let mut db = Database::new();
+        for sql in [
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, email TEXT UNIQUE, \
+             age DEFAULT 18)",
+            "CREATE TABLE orders (id INTEGER, user_id INTEGER REFERENCES users ON DELETE CASCADE, \
+             item TEXT, FOREIGN KEY (id, item) REFERENCES items (order_id, name))",
+            "CREATE TABLE items (order_id INTEGER, name TEXT, PRIMARY KEY (order_id, name))",
+            "CREATE INDEX orders_by_item ON orders (item)",
+            "CREATE VIEW adults AS SELECT name, age FROM users WHERE age >= 18",
+        ] {
+            execute(&mut db, &parse_statement(sql).unwrap()).unwrap();
+        }
+        db
+    }
+
+    fn rows(db: &mut Database, sql: &str) -> Vec<String> {
+        execute(db, &parse_statement(sql).unwrap())
+            .unwrap()
+            .iter()
+            .map(|row| {
+                let values: Vec<String> = row.iter().map(Value::to_string).collect();
+                values.join("|")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_table_info() {
+        let mut db = db();
+        assert_eq!(
+            rows(&mut db, "PRAGMA table_info('users')"),
+            [
+                "0|id|INTEGER|1|NULL|1",
+                "1|name|TEXT|1|NULL|0",
+                "2|email|TEXT|0|NULL|0",
+                "3|age||0|18|0",
+            ]
+        );
+        let info = rows(&mut db, "pragma TABLE_INFO(items)");
+        assert_eq!(info.len(), db.get_table("items").unwrap().schema.len());
+        assert_eq!(
+            info,
+            ["0|order_id|INTEGER|1|NULL|1", "1|name|TEXT|1|NULL|2"]
+        );
+        assert!(rows(&mut db, "PRAGMA table_info(missing)").is_empty());
+    }
+
+    #[test]
+    fn test_table_list() {
+        let mut db = db();
+        assert_eq!(
+            rows(&mut db, "PRAGMA table_list"),
+            [
+                "main|adults|view|2|0|0",
+                "main|items|table|2|0|0",
+                "main|orders|table|3|0|0",
+                "main|users|table|4|0|0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_index_list() {
+        let mut db = db();
+        assert_eq!(
+            rows(&mut db, "PRAGMA index_list(users)"),
+            [
+                "0|sqlite_autoindex_users_1|1|pk|0",
+                "1|sqlite_autoindex_users_2|1|u|0",
+            ]
+        );
+        assert_eq!(
+            rows(&mut db, "PRAGMA index_list(orders)"),
+            ["0|orders_by_item|0|c|0"]
+        );
+        rows(&mut db, "CREATE UNIQUE INDEX orders_by_id ON orders (id)");
+        rows(&mut db, "DROP INDEX orders_by_item");
+        assert_eq!(
+            rows(&mut db, "PRAGMA index_list(orders)"),
+            ["0|orders_by_id|1|c|0"]
+        );
+    }
+
+    #[test]
+    fn test_foreign_key_list() {
+        let mut db = db();
+        assert_eq!(
+            rows(&mut db, "PRAGMA foreign_key_list(orders)"),
+            [
+                "0|0|users|user_id|NULL|NO ACTION|CASCADE|NONE",
+                "1|0|items|id|order_id|NO ACTION|NO ACTION|NONE",
+                "1|1|items|item|name|NO ACTION|NO ACTION|NONE",
+            ]
+        );
+        assert!(rows(&mut db, "PRAGMA foreign_key_list(users)").is_empty());
+    }
+
+    #[test]
+    fn test_unknown_pragma_has_no_rows() {
+        let mut db = db();
+        assert!(rows(&mut db, "PRAGMA no_such_pragma").is_empty());
+        assert!(rows(&mut db, "PRAGMA cache_size = 100").is_empty());
+    }
+
+    #[test]
+    fn test_journal_mode() {
+        let mut db = db();
+        assert_eq!(rows(&mut db, "PRAGMA journal_mode"), ["memory"]);
+        assert_eq!(rows(&mut db, "PRAGMA journal_mode = WAL"), ["memory"]);
+    }
+}
@@ -0,0 +1,42 @@
+//! UNION, INTERSECT and EXCEPT.
+
+use std::collections::HashSet;
+
+use super::{Context, Rows, distinct, execute_plan, row_key};
+use crate::ast::SetOperator;
+use crate::planner::PlanNode;
+
+/// Combine the rows of `left` and `right`. UNION ALL streams both inputs
+/// one after the other and UNION also drops duplicates as it goes. For
+/// INTERSECT and EXCEPT the right input is read first into a set of rows,
+/// then the left one is streamed through it. Rows are compared value by
+/// value, with NULL equal to NULL, as for DISTINCT.
+pub(super) fn set_operation<'a>(
+    ctx: Context<'a>,
+    op: SetOperator,
+    left: &'a PlanNode,
+    right: &'a PlanNode,
+) -> Rows<'a> {
+    let left = execute_plan(ctx, left);
+    let keep = match op {
+        SetOperator::UnionAll => return Box::new(left.chain(execute_plan(ctx, right))),
+        SetOperator::Union => return distinct(Box::new(left.chain(execute_plan(ctx, right)))),
+        SetOperator::Intersect => true,
+        SetOperator::Except => false,
+    };
+    let right = match execute_plan(ctx, right)
+        .map(|row| row.map(|row| row_key(&row)))
+        .collect::<Result<HashSet<_>, _>>()
+    {
+        Ok(right) => right,
+        Err(e) => return Box::new(std::iter::once(Err(e))),
+    };
+    let mut seen = HashSet::new();
+    Box::new(left.filter(move |row| match row {
+        Ok(row) => {
+            let key = row_key(row);
+            right.contains(&key) == keep && seen.insert(key)
+        }
+        Err(_) => true,
+    }))
+}
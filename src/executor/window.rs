@@ -0,0 +1,453 @@
+//! Window functions.
+//!
+//! A window function call computes one value per input row from the rows
+//! of the row's partition: the input rows whose PARTITION BY values equal
+//! its own, in the window's ORDER BY order. Unlike aggregation, no rows are
+//! merged; each row keeps its columns and gains one per call.
+
+use super::aggregate::Accumulator;
+use std::ops::Range;
+
+use super::{Context, EvalError, Evaluator, compare_keys, eval_binop, execute_plan};
+use crate::ast::{BinOp, Expr, FrameBound, FrameMode, WindowFrame};
+use crate::planner::{AggregateCall, PlanNode, SortKey, WindowCall};
+use crate::storage::Row;
+use crate::types::{OrdValue, Value};
+
+/// What a window function computes for each row of a partition.
+#[derive(Debug, Clone)]
+pub enum WindowFunction {
+    /// An aggregate over the rows of the row's frame.
+    Aggregate(AggregateCall),
+    Ranking(RankingFunction),
+    OffsetRow(OffsetRowFunction),
+    Value(ValueFunction),
+    Ntile(NtileFunction),
+}
+
+impl WindowFunction {
+    /// The expressions evaluated for every row of a partition.
+    fn args(&self) -> Vec<&Expr> {
+        match self {
+            WindowFunction::Aggregate(call) => call.arg.iter().collect(),
+            WindowFunction::Ranking(_) | WindowFunction::Ntile(_) => Vec::new(),
+            WindowFunction::OffsetRow(function) => std::iter::once(&function.expr)
+                .chain(&function.default)
+                .collect(),
+            WindowFunction::Value(function) => vec![&function.expr],
+        }
+    }
+
+    /// The condition an aggregate's FILTER puts on the rows it reads.
+    fn filter(&self) -> Option<&Expr> {
+        match self {
+            WindowFunction::Aggregate(call) => call.filter.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The frame of a window that does not give one: for an aggregate,
+    /// the partition up to the current row's last peer, which is all of it
+    /// without a window ORDER BY; for FIRST_VALUE and NTH_VALUE, the rows up
+    /// to the current one; and for LAST_VALUE, the whole partition. The
+    /// other functions do not read a frame.
+    pub fn default_frame(&self) -> WindowFrame {
+        let (mode, end) = match self {
+            WindowFunction::Value(ValueFunction {
+                position: ValuePosition::Last,
+                ..
+            }) => (FrameMode::Rows, FrameBound::UnboundedFollowing),
+            WindowFunction::Value(_) => (FrameMode::Rows, FrameBound::CurrentRow),
+            _ => (FrameMode::Range, FrameBound::CurrentRow),
+        };
+        WindowFrame {
+            mode,
+            start: FrameBound::UnboundedPreceding,
+            end,
+        }
+    }
+}
+
+/// `LAG(<expr>[, <offset>[, <default>]])` or `LEAD(...)`: `expr` for the
+/// row `offset` rows before or after the current one in its partition, or
+/// `default` for the current row (NULL if there is none) when the
+/// partition has no such row.
+#[derive(Debug, Clone)]
+pub struct OffsetRowFunction {
+    pub direction: OffsetDirection,
+    pub expr: Expr,
+    /// Evaluated once, when the call is planned; 1 if not given.
+    pub offset: usize,
+    pub default: Option<Expr>,
+}
+
+/// Which way an [`OffsetRowFunction`] looks from the current row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetDirection {
+    /// `LAG`: towards the start of the partition.
+    Lag,
+    /// `LEAD`: towards its end.
+    Lead,
+}
+
+impl OffsetDirection {
+    /// The offset row function called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<OffsetDirection> {
+        [OffsetDirection::Lag, OffsetDirection::Lead]
+            .into_iter()
+            .find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OffsetDirection::Lag => "LAG",
+            OffsetDirection::Lead => "LEAD",
+        }
+    }
+}
+
+/// `FIRST_VALUE(<expr>)`, `LAST_VALUE(<expr>)` or `NTH_VALUE(<expr>, <n>)`:
+/// `expr` for a row of the current row's frame, or NULL if the frame has
+/// no such row.
+#[derive(Debug, Clone)]
+pub struct ValueFunction {
+    pub position: ValuePosition,
+    pub expr: Expr,
+}
+
+/// Which row of the frame a [`ValueFunction`] reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValuePosition {
+    First,
+    Last,
+    /// The `n`th row, counting from 1.
+    Nth(usize),
+}
+
+impl ValuePosition {
+    /// The value function called `name`, ignoring case. NTH_VALUE comes
+    /// back as `Nth(1)`, for its caller to fill in.
+    pub fn from_name(name: &str) -> Option<ValuePosition> {
+        [
+            ValuePosition::First,
+            ValuePosition::Last,
+            ValuePosition::Nth(1),
+        ]
+        .into_iter()
+        .find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ValuePosition::First => "FIRST_VALUE",
+            ValuePosition::Last => "LAST_VALUE",
+            ValuePosition::Nth(_) => "NTH_VALUE",
+        }
+    }
+}
+
+/// `NTILE(<n>)`: the number, from 1, of the row's bucket when the
+/// partition is split in window order into `bucket_count` buckets of
+/// nearly equal size, the first ones a row larger than the rest. With more
+/// buckets than rows, every row has its own. NULLs, which sort last by
+/// default, fill the last buckets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NtileFunction {
+    /// At least 1.
+    pub bucket_count: usize,
+}
+
+impl NtileFunction {
+    /// The bucket of row `i` of a partition of `rows` rows.
+    fn bucket(self, i: usize, rows: usize) -> usize {
+        let size = rows / self.bucket_count;
+        // The first `larger` buckets hold `size + 1` rows.
+        let larger = rows % self.bucket_count;
+        if i < larger * (size + 1) {
+            i / (size + 1) + 1
+        } else {
+            larger + (i - larger * (size + 1)) / size + 1
+        }
+    }
+}
+
+/// A function of a row's position in its partition, which must be ordered.
+/// Rows that sort equal are peers: they share a rank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RankingFunction {
+    /// The row's position, counting from 1; peers are numbered in no
+    /// particular order.
+    RowNumber,
+    /// The position of the row's first peer, so ties leave gaps.
+    Rank,
+    /// The number of peer groups up to the row's, without gaps.
+    DenseRank,
+    /// `(rank - 1) / (rows - 1)`, from 0.0 to 1.0; 0.0 in a partition of
+    /// one row.
+    PercentRank,
+}
+
+impl RankingFunction {
+    /// The ranking function called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<RankingFunction> {
+        [
+            RankingFunction::RowNumber,
+            RankingFunction::Rank,
+            RankingFunction::DenseRank,
+            RankingFunction::PercentRank,
+        ]
+        .into_iter()
+        .find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RankingFunction::RowNumber => "ROW_NUMBER",
+            RankingFunction::Rank => "RANK",
+            RankingFunction::DenseRank => "DENSE_RANK",
+            RankingFunction::PercentRank => "PERCENT_RANK",
+        }
+    }
+}
+
+/// Compute every call for each input row. An output row holds the input
+/// row followed by one value per call, and rows come out in input order.
+pub(super) fn window(
+    ctx: Context<'_>,
+    input: &PlanNode,
+    calls: &[WindowCall],
+) -> Result<Vec<Row>, EvalError> {
+    let mut rows = execute_plan(ctx, input).collect::<Result<Vec<_>, _>>()?;
+    // Each call has its own partitioning and order, so each is computed
+    // over its own arrangement of the rows.
+    let columns = calls
+        .iter()
+        .map(|call| compute(ctx, input, &rows, call))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (i, row) in rows.iter_mut().enumerate() {
+        row.extend(columns.iter().map(|column| column[i].clone()));
+    }
+    Ok(rows)
+}
+
+/// The values a call needs from one input row.
+struct Entry {
+    /// The row's position in the input.
+    index: usize,
+    partition: Vec<OrdValue>,
+    order: Vec<Value>,
+    args: Vec<Value>,
+    /// Whether the call's FILTER, if any, holds for the row.
+    filtered_in: bool,
+}
+
+/// The value of `call` for each of `rows`, in input order.
+fn compute(
+    ctx: Context<'_>,
+    input: &PlanNode,
+    rows: &[Row],
+    call: &WindowCall,
+) -> Result<Vec<Value>, EvalError> {
+    let schema = input.schema();
+    let mut entries = rows
+        .iter()
+        .enumerate()
+        .map(|(index, row)| {
+            let evaluator = Evaluator::new(ctx, schema, row);
+            Ok(Entry {
+                index,
+                partition: eval_all(&evaluator, &call.partition_by)?
+                    .into_iter()
+                    .map(OrdValue)
+                    .collect(),
+                order: eval_all(&evaluator, call.order_by.iter().map(|k| &k.expr))?,
+                args: eval_all(&evaluator, call.function.args())?,
+                filtered_in: match call.function.filter() {
+                    Some(filter) => evaluator.holds(filter)?,
+                    None => true,
+                },
+            })
+        })
+        .collect::<Result<Vec<_>, EvalError>>()?;
+    // `sort_by` is stable, so rows that sort equal keep their input order.
+    entries.sort_by(|a, b| {
+        a.partition
+            .cmp(&b.partition)
+            .then_with(|| compare_keys(&call.order_by, &a.order, &b.order))
+    });
+    let mut values = vec![Value::Null; rows.len()];
+    for rows in entries.chunk_by(|a, b| a.partition == b.partition) {
+        let partition = Partition {
+            rows,
+            order_by: &call.order_by,
+            frame: call.frame,
+        };
+        for (entry, value) in rows.iter().zip(partition.evaluate(&call.function)?) {
+            values[entry.index] = value;
+        }
+    }
+    Ok(values)
+}
+
+fn eval_all<'e>(
+    evaluator: &Evaluator<'_>,
+    exprs: impl IntoIterator<Item = &'e Expr>,
+) -> Result<Vec<Value>, EvalError> {
+    exprs.into_iter().map(|e| evaluator.eval(e)).collect()
+}
+
+/// The rows of one partition, in window order.
+struct Partition<'a> {
+    rows: &'a [Entry],
+    order_by: &'a [SortKey],
+    frame: WindowFrame,
+}
+
+impl Partition<'_> {
+    /// The value of `function` for each row, in window order.
+    fn evaluate(&self, function: &WindowFunction) -> Result<Vec<Value>, EvalError> {
+        match function {
+            WindowFunction::Aggregate(call) => {
+                let mut accumulator = Accumulator::new(call);
+                // The rows folded into `accumulator` so far.
+                let mut folded = 0..0;
+                let mut values = Vec::with_capacity(self.rows.len());
+                for i in 0..self.rows.len() {
+                    let frame = self.frame(i)?;
+                    // A frame starting where the previous one did and ending
+                    // no earlier extends it; any other is folded afresh.
+                    if frame.start != folded.start || frame.end < folded.end {
+                        accumulator = Accumulator::new(call);
+                        folded = frame.start..frame.start;
+                    }
+                    let rows = &self.rows[folded.end..frame.end];
+                    for entry in rows.iter().filter(|entry| entry.filtered_in) {
+                        // `COUNT(*)` counts rows, so any non-NULL value will
+                        // do.
+                        let arg = entry.args.first().cloned();
+                        accumulator.add(arg.unwrap_or(Value::Integer(1)), Vec::new())?;
+                    }
+                    folded.end = frame.end;
+                    values.push(accumulator.clone().finish(&call.order_by));
+                }
+                Ok(values)
+            }
+            WindowFunction::Ranking(function) => {
+                let rows = self.rows.len();
+                let mut values = Vec::with_capacity(rows);
+                let (mut end, mut rank, mut groups) = (0, 0, 0);
+                for i in 0..rows {
+                    if i == end {
+                        end = self.peers_end(i);
+                        rank = i + 1;
+                        groups += 1;
+                    }
+                    values.push(match function {
+                        RankingFunction::RowNumber => Value::Integer(i as i64 + 1),
+                        RankingFunction::Rank => Value::Integer(rank as i64),
+                        RankingFunction::DenseRank => Value::Integer(groups),
+                        RankingFunction::PercentRank if rows == 1 => Value::Real(0.0),
+                        RankingFunction::PercentRank => {
+                            Value::Real((rank - 1) as f64 / (rows - 1) as f64)
+                        }
+                    });
+                }
+                Ok(values)
+            }
+            WindowFunction::OffsetRow(function) => Ok((0..self.rows.len())
+                .map(|i| {
+                    let target = match function.direction {
+                        OffsetDirection::Lag => i.checked_sub(function.offset),
+                        OffsetDirection::Lead => i.checked_add(function.offset),
+                    };
+                    match target.and_then(|j| self.rows.get(j)) {
+                        Some(entry) => entry.args[0].clone(),
+                        None => self.rows[i].args.get(1).cloned().unwrap_or(Value::Null),
+                    }
+                })
+                .collect()),
+            WindowFunction::Value(function) => (0..self.rows.len())
+                .map(|i| {
+                    let frame = self.frame(i)?;
+                    let row = match function.position {
+                        ValuePosition::First => Some(frame.start),
+                        ValuePosition::Last => frame.end.checked_sub(1),
+                        ValuePosition::Nth(n) => frame.start.checked_add(n - 1),
+                    };
+                    Ok(match row.filter(|row| frame.contains(row)) {
+                        Some(row) => self.rows[row].args[0].clone(),
+                        None => Value::Null,
+                    })
+                })
+                .collect(),
+            WindowFunction::Ntile(function) => Ok((0..self.rows.len())
+                .map(|i| Value::Integer(function.bucket(i, self.rows.len()) as i64))
+                .collect()),
+        }
+    }
+
+    /// The end of the peer group that starts at row `i`: the rows after it
+    /// that sort equal to it. Without a window ORDER BY every row is a peer.
+    fn peers_end(&self, i: usize) -> usize {
+        let current = &self.rows[i].order;
+        i + self.rows[i..]
+            .iter()
+            .take_while(|entry| compare_keys(self.order_by, current, &entry.order).is_eq())
+            .count()
+    }
+
+    /// The rows of row `i`'s frame; empty if its end comes before its
+    /// start.
+    fn frame(&self, i: usize) -> Result<Range<usize>, EvalError> {
+        let start = self.bound(i, self.frame.start, true)?;
+        let end = self.bound(i, self.frame.end, false)?;
+        Ok(start..end.max(start))
+    }
+
+    /// Where `bound` makes the frame of row `i` start or, unless
+    /// `is_start`, end (exclusively).
+    fn bound(&self, i: usize, bound: FrameBound, is_start: bool) -> Result<usize, EvalError> {
+        let (n, following) = match bound {
+            FrameBound::UnboundedPreceding => return Ok(0),
+            FrameBound::UnboundedFollowing => return Ok(self.rows.len()),
+            FrameBound::Preceding(n) => (n, false),
+            FrameBound::CurrentRow => (0, false),
+            FrameBound::Following(n) => (n, true),
+        };
+        match self.frame.mode {
+            FrameMode::Rows => {
+                let n = usize::try_from(n).unwrap_or(usize::MAX);
+                let row = if is_start { i } else { i + 1 };
+                Ok(if following {
+                    row.saturating_add(n).min(self.rows.len())
+                } else {
+                    row.saturating_sub(n)
+                })
+            }
+            FrameMode::Range => {
+                // The ORDER BY value `n` away from the current row's,
+                // towards the partition's start or end. The planner ensures
+                // a single ORDER BY expression when `n` is not 0.
+                let current = &self.rows[i].order;
+                let target = match (n, current.first()) {
+                    (0, _) | (_, None | Some(Value::Null)) => current.clone(),
+                    (n, Some(value)) => {
+                        let op = if following != self.order_by[0].desc {
+                            BinOp::Add
+                        } else {
+                            BinOp::Sub
+                        };
+                        let n = Value::Integer(i64::try_from(n).unwrap_or(i64::MAX));
+                        vec![eval_binop(op, value.clone(), n)?]
+                    }
+                };
+                let ordering = |entry: &Entry| compare_keys(self.order_by, &entry.order, &target);
+                Ok(if is_start {
+                    self.rows.partition_point(|entry| ordering(entry).is_lt())
+                } else {
+                    self.rows.partition_point(|entry| ordering(entry).is_le())
+                })
+            }
+        }
+    }
+}
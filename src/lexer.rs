@@ -0,0 +1,816 @@
+//! SQL lexer.
+//!
+//! Converts a raw SQL string into a flat list of [`Token`]s. Keywords are
+//! recognised case-insensitively; everything that is not a keyword, literal,
+//! operator or punctuation becomes an [`Token::Ident`]. Whitespace and both
+//! comment styles (`-- line` and `/* block */`) are discarded.
+
+use std::fmt;
+
+/// A single lexical token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    // Keywords
+    Select,
+    From,
+    Where,
+    Create,
+    Table,
+    Insert,
+    Into,
+    Values,
+    Update,
+    Delete,
+    Drop,
+    As,
+    On,
+    Join,
+    Left,
+    Right,
+    Inner,
+    Outer,
+    Full,
+    Cross,
+    Natural,
+    Using,
+    Union,
+    Intersect,
+    Except,
+    Group,
+    Order,
+    Having,
+    Limit,
+    Offset,
+    Distinct,
+    All,
+    By,
+    And,
+    Or,
+    Not,
+    Null,
+    Is,
+    In,
+    Like,
+    Between,
+    Case,
+    When,
+    Then,
+    Else,
+    End,
+    True,
+    False,
+    With,
+    Recursive,
+
+    // Punctuation
+    Comma,
+    Dot,
+    Star,
+    LParen,
+    RParen,
+    Semicolon,
+
+    // Operators
+    Eq,
+    Neq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Plus,
+    Minus,
+    Slash,
+    Percent,
+    Concat,
+
+    // Identifiers and literals
+    Ident(String),
+    IntLiteral(i64),
+    RealLiteral(f64),
+    StringLiteral(String),
+}
+
+impl Token {
+    /// Map a bare word to its keyword token, if it is one.
+    fn keyword(word: &str) -> Option<Token> {
+        let token = match word.to_ascii_uppercase().as_str() {
+            "SELECT" => Token::Select,
+            "FROM" => Token::From,
+            "WHERE" => Token::Where,
+            "CREATE" => Token::Create,
+            "TABLE" => Token::Table,
+            "INSERT" => Token::Insert,
+            "INTO" => Token::Into,
+            "VALUES" => Token::Values,
+            "UPDATE" => Token::Update,
+            "DELETE" => Token::Delete,
+            "DROP" => Token::Drop,
+            "AS" => Token::As,
+            "ON" => Token::On,
+            "JOIN" => Token::Join,
+            "LEFT" => Token::Left,
+            "RIGHT" => Token::Right,
+            "INNER" => Token::Inner,
+            "OUTER" => Token::Outer,
+            "FULL" => Token::Full,
+            "CROSS" => Token::Cross,
+            "NATURAL" => Token::Natural,
+            "USING" => Token::Using,
+            "UNION" => Token::Union,
+            "INTERSECT" => Token::Intersect,
+            "EXCEPT" => Token::Except,
+            "GROUP" => Token::Group,
+            "ORDER" => Token::Order,
+            "HAVING" => Token::Having,
+            "LIMIT" => Token::Limit,
+            "OFFSET" => Token::Offset,
+            "DISTINCT" => Token::Distinct,
+            "ALL" => Token::All,
+            "BY" => Token::By,
+            "AND" => Token::And,
+            "OR" => Token::Or,
+            "NOT" => Token::Not,
+            "NULL" => Token::Null,
+            "IS" => Token::Is,
+            "IN" => Token::In,
+            "LIKE" => Token::Like,
+            "BETWEEN" => Token::Between,
+            "CASE" => Token::Case,
+            "WHEN" => Token::When,
+            "THEN" => Token::Then,
+            "ELSE" => Token::Else,
+            "END" => Token::End,
+            "TRUE" => Token::True,
+            "FALSE" => Token::False,
+            "WITH" => Token::With,
+            "RECURSIVE" => Token::Recursive,
+            _ => return None,
+        };
+        Some(token)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Comma => write!(f, ","),
+            Token::Dot => write!(f, "."),
+            Token::Star => write!(f, "*"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Semicolon => write!(f, ";"),
+            Token::Eq => write!(f, "="),
+            Token::Neq => write!(f, "<>"),
+            Token::Lt => write!(f, "<"),
+            Token::Le => write!(f, "<="),
+            Token::Gt => write!(f, ">"),
+            Token::Ge => write!(f, ">="),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Slash => write!(f, "/"),
+            Token::Percent => write!(f, "%"),
+            Token::Concat => write!(f, "||"),
+            Token::Ident(name) => write!(f, "{name}"),
+            Token::IntLiteral(n) => write!(f, "{n}"),
+            Token::RealLiteral(n) => write!(f, "{n}"),
+            Token::StringLiteral(s) => write!(f, "'{}'", s.replace('\'', "''")),
+            // Keywords print as their upper-case spelling.
+            keyword => write!(f, "{}", format!("{keyword:?}").to_ascii_uppercase()),
+        }
+    }
+}
+
+/// A failure to tokenize the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub message: String,
+    /// Byte offset into the input where the problem was detected.
+    pub offset: usize,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for LexError {}
+
+/// Tokenize a SQL string.
+pub fn tokenize(sql: &str) -> Result<Vec<Token>, LexError> {
+    Lexer::new(sql).run()
+}
+
+/// Split SQL text into statements at the semicolons between them, leaving
+/// those in string literals, quoted names and comments alone. Each runs
+/// from its first token to its last, so one of only comments, like the
+/// one after a trailing semicolon, is empty. Text that does not lex stays
+/// in one piece from the statement it starts in, for the parser to report.
+pub fn split_statements(sql: &str) -> Vec<&str> {
+    let mut lexer = Lexer::new(sql);
+    let mut statements = Vec::new();
+    // Where the current statement's first token starts and its last ends.
+    let mut span: Option<(usize, usize)> = None;
+    loop {
+        let token = lexer.skip_trivia().and_then(|()| {
+            let start = lexer.pos;
+            Ok((start, lexer.next_token()?))
+        });
+        match token {
+            Ok((_, None)) => break,
+            Ok((_, Some(Token::Semicolon))) => {
+                statements.push(span.take().map_or("", |(start, end)| &sql[start..end]));
+            }
+            Ok((start, Some(_))) => {
+                let first = span.map_or(start, |(first, _)| first);
+                span = Some((first, lexer.pos));
+            }
+            Err(_) => {
+                let first = span.map_or(lexer.pos, |(first, _)| first);
+                statements.push(sql[first..].trim_end());
+                return statements;
+            }
+        }
+    }
+    statements.push(span.map_or("", |(start, end)| &sql[start..end]));
+    statements
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(src: &'a str) -> Self {
+        Lexer {
+            src,
+            bytes: src.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, ahead: usize) -> Option<u8> {
+        self.bytes.get(self.pos + ahead).copied()
+    }
+
+    fn error(&self, message: impl Into<String>, offset: usize) -> LexError {
+        LexError {
+            message: message.into(),
+            offset,
+        }
+    }
+
+    fn run(mut self) -> Result<Vec<Token>, LexError> {
+        let mut tokens = Vec::new();
+        while let Some(token) = self.next_token()? {
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    /// Skip whitespace and comments, then lex one token.
+    fn next_token(&mut self) -> Result<Option<Token>, LexError> {
+        self.skip_trivia()?;
+        let start = self.pos;
+        let Some(c) = self.peek() else {
+            return Ok(None);
+        };
+
+        let token = match c {
+            b'\'' => Token::StringLiteral(self.quoted(b'\'')?),
+            b'"' => Token::Ident(self.quoted(b'"')?),
+            b'`' => Token::Ident(self.quoted(b'`')?),
+            b'[' => Token::Ident(self.bracketed()?),
+            b'0'..=b'9' => self.number()?,
+            b'.' if self.peek_at(1).is_some_and(|d| d.is_ascii_digit()) => self.number()?,
+            c if c == b'_' || c.is_ascii_alphabetic() || c >= 0x80 => self.word(),
+            _ => {
+                self.pos += 1;
+                match c {
+                    b',' => Token::Comma,
+                    b'.' => Token::Dot,
+                    b'*' => Token::Star,
+                    b'(' => Token::LParen,
+                    b')' => Token::RParen,
+                    b';' => Token::Semicolon,
+                    b'+' => Token::Plus,
+                    b'-' => Token::Minus,
+                    b'/' => Token::Slash,
+                    b'%' => Token::Percent,
+                    b'=' => {
+                        if self.peek() == Some(b'=') {
+                            self.pos += 1;
+                        }
+                        Token::Eq
+                    }
+                    b'!' if self.peek() == Some(b'=') => {
+                        self.pos += 1;
+                        Token::Neq
+                    }
+                    b'<' => match self.peek() {
+                        Some(b'=') => {
+                            self.pos += 1;
+                            Token::Le
+                        }
+                        Some(b'>') => {
+                            self.pos += 1;
+                            Token::Neq
+                        }
+                        _ => Token::Lt,
+                    },
+                    b'>' => {
+                        if self.peek() == Some(b'=') {
+                            self.pos += 1;
+                            Token::Ge
+                        } else {
+                            Token::Gt
+                        }
+                    }
+                    b'|' if self.peek() == Some(b'|') => {
+                        self.pos += 1;
+                        Token::Concat
+                    }
+                    _ => {
+                        let ch = self.src[start..].chars().next().unwrap_or('?');
+                        return Err(self.error(format!("unrecognized token \"{ch}\""), start));
+                    }
+                }
+            }
+        };
+        Ok(Some(token))
+    }
+
+    fn skip_trivia(&mut self) -> Result<(), LexError> {
+        loop {
+            match (self.peek(), self.peek_at(1)) {
+                (Some(c), _) if c.is_ascii_whitespace() => self.pos += 1,
+                (Some(b'-'), Some(b'-')) => {
+                    while let Some(c) = self.peek() {
+                        self.pos += 1;
+                        if c == b'\n' {
+                            break;
+                        }
+                    }
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    let start = self.pos;
+                    self.pos += 2;
+                    loop {
+                        match (self.peek(), self.peek_at(1)) {
+                            (Some(b'*'), Some(b'/')) => {
+                                self.pos += 2;
+                                break;
+                            }
+                            (Some(_), _) => self.pos += 1,
+                            (None, _) => {
+                                return Err(self.error("unterminated block comment", start));
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Lex a string delimited by `quote`, where a doubled quote is an escape.
+    fn quoted(&mut self, quote: u8) -> Result<String, LexError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut out = String::new();
+        let mut chunk_start = self.pos;
+        loop {
+            match self.peek() {
+                Some(c) if c == quote => {
+                    out.push_str(&self.src[chunk_start..self.pos]);
+                    self.pos += 1;
+                    if self.peek() == Some(quote) {
+                        out.push(quote as char);
+                        self.pos += 1;
+                        chunk_start = self.pos;
+                    } else {
+                        return Ok(out);
+                    }
+                }
+                Some(_) => self.pos += 1,
+                None => {
+                    let what = if quote == b'\'' {
+                        "string"
+                    } else {
+                        "identifier"
+                    };
+                    return Err(self.error(format!("unterminated {what}"), start));
+                }
+            }
+        }
+    }
+
+    /// Lex a `[bracketed]` identifier (SQL Server / SQLite extension).
+    fn bracketed(&mut self) -> Result<String, LexError> {
+        let start = self.pos;
+        match self.src[start..].find(']') {
+            Some(len) => {
+                self.pos = start + len + 1;
+                Ok(self.src[start + 1..start + len].to_string())
+            }
+            None => Err(self.error("unterminated identifier", start)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Token, LexError> {
+        let start = self.pos;
+        let mut is_real = false;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'.') {
+            is_real = true;
+            self.pos += 1;
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.pos += 1;
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            let mut ahead = 1;
+            if matches!(self.peek_at(1), Some(b'+' | b'-')) {
+                ahead = 2;
+            }
+            if self.peek_at(ahead).is_some_and(|c| c.is_ascii_digit()) {
+                is_real = true;
+                self.pos += ahead;
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+            } else {
+                return Err(self.error("malformed numeric literal", start));
+            }
+        }
+        if self
+            .peek()
+            .is_some_and(|c| c == b'_' || c.is_ascii_alphabetic())
+        {
+            return Err(self.error("malformed numeric literal", start));
+        }
+
+        let text = &self.src[start..self.pos];
+        if !is_real && let Ok(n) = text.parse::<i64>() {
+            return Ok(Token::IntLiteral(n));
+        }
+        // Integers too large for i64 fall back to REAL, as in SQLite.
+        text.parse::<f64>()
+            .map(Token::RealLiteral)
+            .map_err(|_| self.error("malformed numeric literal", start))
+    }
+
+    fn word(&mut self) -> Token {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c == b'_' || c == b'$' || c.is_ascii_alphanumeric() || c >= 0x80)
+        {
+            self.pos += 1;
+        }
+        let word = &self.src[start..self.pos];
+        Token::keyword(word).unwrap_or_else(|| Token::Ident(word.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(sql: &str) -> Vec<Token> {
+        tokenize(sql).unwrap()
+    }
+
+    fn ident(s: &str) -> Token {
+        Token::Ident(s.to_string())
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(lex(""), vec![]);
+        assert_eq!(lex("   \n\t "), vec![]);
+    }
+
+    #[test]
+    fn test_keywords_are_case_insensitive() {
+        assert_eq!(lex("SELECT select SeLeCt"), vec![Token::Select; 3]);
+        assert_eq!(
+            lex("from where create table insert into values update delete drop"),
+            vec![
+                Token::From,
+                Token::Where,
+                Token::Create,
+                Token::Table,
+                Token::Insert,
+                Token::Into,
+                Token::Values,
+                Token::Update,
+                Token::Delete,
+                Token::Drop,
+            ]
+        );
+        assert_eq!(
+            lex("as on join left right inner outer full cross natural using"),
+            vec![
+                Token::As,
+                Token::On,
+                Token::Join,
+                Token::Left,
+                Token::Right,
+                Token::Inner,
+                Token::Outer,
+                Token::Full,
+                Token::Cross,
+                Token::Natural,
+                Token::Using,
+            ]
+        );
+        assert_eq!(
+            lex("union intersect except group order having limit offset distinct all by"),
+            vec![
+                Token::Union,
+                Token::Intersect,
+                Token::Except,
+                Token::Group,
+                Token::Order,
+                Token::Having,
+                Token::Limit,
+                Token::Offset,
+                Token::Distinct,
+                Token::All,
+                Token::By,
+            ]
+        );
+        assert_eq!(
+            lex("and or not null is in like between case when then else end"),
+            vec![
+                Token::And,
+                Token::Or,
+                Token::Not,
+                Token::Null,
+                Token::Is,
+                Token::In,
+                Token::Like,
+                Token::Between,
+                Token::Case,
+                Token::When,
+                Token::Then,
+                Token::Else,
+                Token::End,
+            ]
+        );
+        assert_eq!(
+            lex("true false with recursive"),
+            vec![Token::True, Token::False, Token::With, Token::Recursive]
+        );
+    }
+
+    #[test]
+    fn test_punctuation() {
+        assert_eq!(
+            lex(", . * ( ) ;"),
+            vec![
+                Token::Comma,
+                Token::Dot,
+                Token::Star,
+                Token::LParen,
+                Token::RParen,
+                Token::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(
+            lex("= == <> != < <= > >= + - / % ||"),
+            vec![
+                Token::Eq,
+                Token::Eq,
+                Token::Neq,
+                Token::Neq,
+                Token::Lt,
+                Token::Le,
+                Token::Gt,
+                Token::Ge,
+                Token::Plus,
+                Token::Minus,
+                Token::Slash,
+                Token::Percent,
+                Token::Concat,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operators_without_spaces() {
+        assert_eq!(lex("a<=b"), vec![ident("a"), Token::Le, ident("b")]);
+        assert_eq!(
+            lex("1-2"),
+            vec![Token::IntLiteral(1), Token::Minus, Token::IntLiteral(2)]
+        );
+        assert_eq!(
+            lex("'a'||'b'"),
+            vec![
+                Token::StringLiteral("a".into()),
+                Token::Concat,
+                Token::StringLiteral("b".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(lex("foo"), vec![ident("foo")]);
+        assert_eq!(lex("_bar9"), vec![ident("_bar9")]);
+        assert_eq!(lex("MixedCase"), vec![ident("MixedCase")]);
+        assert_eq!(lex("t1.col"), vec![ident("t1"), Token::Dot, ident("col")]);
+    }
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            split_statements("CREATE TABLE t(x INT); INSERT INTO t VALUES(1);\nSELECT * FROM t"),
+            [
+                "CREATE TABLE t(x INT)",
+                "INSERT INTO t VALUES(1)",
+                "SELECT * FROM t"
+            ]
+        );
+        // The text after a trailing semicolon is an empty statement.
+        assert_eq!(split_statements("SELECT 1;"), ["SELECT 1", ""]);
+        assert_eq!(split_statements(""), [""]);
+        assert_eq!(split_statements(" ; ;"), ["", "", ""]);
+        assert_eq!(
+            split_statements("SELECT 'a;b', \"c;d\" /* ; */ FROM t -- ;\n; SELECT 2"),
+            ["SELECT 'a;b', \"c;d\" /* ; */ FROM t", "SELECT 2"]
+        );
+        assert_eq!(
+            split_statements("/* only; a comment */; -- and another"),
+            ["", ""]
+        );
+        assert_eq!(
+            split_statements("SELECT 1; SELECT 'open; SELECT 3"),
+            ["SELECT 1", "SELECT 'open; SELECT 3"]
+        );
+    }
+
+    #[test]
+    fn test_quoted_identifiers() {
+        assert_eq!(lex("\"select\""), vec![ident("select")]);
+        assert_eq!(lex("\"a\"\"b\""), vec![ident("a\"b")]);
+        assert_eq!(lex("`order`"), vec![ident("order")]);
+        assert_eq!(lex("[my col]"), vec![ident("my col")]);
+    }
+
+    #[test]
+    fn test_integer_literals() {
+        assert_eq!(lex("0"), vec![Token::IntLiteral(0)]);
+        assert_eq!(lex("42"), vec![Token::IntLiteral(42)]);
+        assert_eq!(lex("007"), vec![Token::IntLiteral(7)]);
+        assert_eq!(
+            lex("9223372036854775807"),
+            vec![Token::IntLiteral(i64::MAX)]
+        );
+    }
+
+    #[test]
+    fn test_integer_overflow_becomes_real() {
+        assert_eq!(
+            lex("9223372036854775808"),
+            vec![Token::RealLiteral(9223372036854775808.0)]
+        );
+    }
+
+    #[test]
+    fn test_real_literals() {
+        assert_eq!(lex("1.5"), vec![Token::RealLiteral(1.5)]);
+        assert_eq!(lex(".25"), vec![Token::RealLiteral(0.25)]);
+        assert_eq!(lex("3."), vec![Token::RealLiteral(3.0)]);
+        assert_eq!(lex("00.50"), vec![Token::RealLiteral(0.5)]);
+        assert_eq!(lex("1e3"), vec![Token::RealLiteral(1000.0)]);
+        assert_eq!(lex("2.5E-1"), vec![Token::RealLiteral(0.25)]);
+        assert_eq!(lex("1e+2"), vec![Token::RealLiteral(100.0)]);
+    }
+
+    #[test]
+    fn test_malformed_numbers() {
+        assert_eq!(tokenize("1e").unwrap_err().offset, 0);
+        assert_eq!(tokenize("x 12abc").unwrap_err().offset, 2);
+    }
+
+    #[test]
+    fn test_string_literals() {
+        assert_eq!(lex("'hello'"), vec![Token::StringLiteral("hello".into())]);
+        assert_eq!(lex("''"), vec![Token::StringLiteral(String::new())]);
+        assert_eq!(lex("'it''s'"), vec![Token::StringLiteral("it's".into())]);
+        assert_eq!(lex("''''"), vec![Token::StringLiteral("'".into())]);
+        assert_eq!(
+            lex("'héllo wörld'"),
+            vec![Token::StringLiteral("héllo wörld".into())]
+        );
+        assert_eq!(
+            lex("'-- not a comment'"),
+            vec![Token::StringLiteral("-- not a comment".into())]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let err = tokenize("SELECT 'abc").unwrap_err();
+        assert_eq!(err.offset, 7);
+        assert!(err.message.contains("unterminated"));
+
+        let err = tokenize("'it''s").unwrap_err();
+        assert_eq!(err.offset, 0);
+    }
+
+    #[test]
+    fn test_unterminated_quoted_identifier() {
+        assert_eq!(tokenize("\"abc").unwrap_err().offset, 0);
+        assert_eq!(tokenize("a [b").unwrap_err().offset, 2);
+    }
+
+    #[test]
+    fn test_line_comments() {
+        assert_eq!(
+            lex("SELECT 1 -- trailing\n, 2"),
+            vec![
+                Token::Select,
+                Token::IntLiteral(1),
+                Token::Comma,
+                Token::IntLiteral(2)
+            ]
+        );
+        assert_eq!(lex("-- only a comment"), vec![]);
+    }
+
+    #[test]
+    fn test_block_comments() {
+        assert_eq!(
+            lex("SELECT /* a\nmulti-line comment */ 1"),
+            vec![Token::Select, Token::IntLiteral(1)]
+        );
+        assert_eq!(
+            lex("1/**/2"),
+            vec![Token::IntLiteral(1), Token::IntLiteral(2)]
+        );
+        let err = tokenize("SELECT /* never closed").unwrap_err();
+        assert_eq!(err.offset, 7);
+    }
+
+    #[test]
+    fn test_unrecognized_character() {
+        let err = tokenize("SELECT 1 # 2").unwrap_err();
+        assert_eq!(err.offset, 9);
+        assert!(tokenize("a | b").is_err());
+        assert!(tokenize("!").is_err());
+    }
+
+    #[test]
+    fn test_full_statement() {
+        assert_eq!(
+            lex("SELECT a, b FROM t1 WHERE a >= 10 AND b <> 'x';"),
+            vec![
+                Token::Select,
+                ident("a"),
+                Token::Comma,
+                ident("b"),
+                Token::From,
+                ident("t1"),
+                Token::Where,
+                ident("a"),
+                Token::Ge,
+                Token::IntLiteral(10),
+                Token::And,
+                ident("b"),
+                Token::Neq,
+                Token::StringLiteral("x".into()),
+                Token::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_star_and_count() {
+        assert_eq!(
+            lex("count(*)"),
+            vec![ident("count"), Token::LParen, Token::Star, Token::RParen]
+        );
+    }
+
+    #[test]
+    fn test_display_round_trips_keywords() {
+        assert_eq!(Token::Select.to_string(), "SELECT");
+        assert_eq!(Token::Neq.to_string(), "<>");
+        assert_eq!(Token::StringLiteral("a'b".into()).to_string(), "'a''b'");
+    }
+}
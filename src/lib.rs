@@ -0,0 +1,13 @@
+//! SQL database engine for the Vibe Coding Challenge.
+//!
+//! The REPL in `main.rs` is a thin protocol wrapper; everything that parses
+//! and executes SQL lives in this library so it can be unit tested directly.
+
+pub mod ast;
+pub mod connection;
+pub mod executor;
+pub mod lexer;
+pub mod parser;
+pub mod planner;
+pub mod storage;
+pub mod types;
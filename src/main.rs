@@ -1,27 +1,48 @@
-//! SQL Database Challenge - Rust Starter
-//!
-//! This is a minimal REPL skeleton that implements the protocol expected by
-//! the SQLLogicTest runner. Your job is to replace the "not implemented"
-//! responses with actual SQL execution.
+//! The REPL: reads SQL from stdin, runs it against an in-memory database
+//! through a [`Connection`], so `PRAGMA journal_mode = WAL` applies, and
+//! writes the results to stdout, in the protocol the SQLLogicTest runner
+//! expects.
 //!
 //! Protocol:
-//! - Read SQL from stdin (one statement at a time, ending with semicolon)
-//! - After receiving a blank line, execute the accumulated SQL
+//! - Read SQL from stdin (statements ending with semicolons)
+//! - After receiving a blank line, execute the accumulated SQL, one
+//!   statement after another until one fails
 //! - Output results as tab-separated values, one row per line
 //! - Output a blank line to signal end of results
 //! - For errors, output "Error: <message>" then a blank line
-//!
-//! See CLAUDE.md for implementation tips and suggested approach.
+//! - A line starting with `.` between statements is a dot-command, such as
+//!   `.tables`, answered at once in the same way
 
 use std::io::{self, BufRead, Write};
+use std::ops::ControlFlow;
+
+use sql_challenge::connection::Connection;
+use sql_challenge::lexer;
+use sql_challenge::parser;
+use sql_challenge::storage::Database;
+
+/// The stack the REPL runs on: room for the parser to reach
+/// [`parser::MAX_EXPR_DEPTH`], and for the planner and executor to work
+/// through the deepest expression it accepts, in a debug build too. Only
+/// the part of it that is used is ever touched.
+const STACK_SIZE: usize = 256 << 20;
 
 fn main() {
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| repl(io::stdin().lock(), &mut io::stdout()))
+        .expect("the REPL thread starts")
+        .join()
+        .expect("the REPL does not panic");
+}
 
+/// Read statements and dot-commands from `input` until it ends or `.quit`,
+/// writing the responses to `out`.
+fn repl<R: BufRead, W: Write>(input: R, out: &mut W) {
+    let mut conn = Connection::open(Database::new().into_shared());
     let mut sql_buffer = String::new();
 
-    for line in stdin.lock().lines() {
+    for line in input.lines() {
         let line = match line {
             Ok(l) => l,
             Err(_) => break,
@@ -31,15 +52,24 @@ fn main() {
         if line.is_empty() {
             if !sql_buffer.is_empty() {
                 let sql = sql_buffer.trim();
-                execute_sql(sql, &mut stdout);
+                execute_sql(&mut conn, sql, out);
                 sql_buffer.clear();
             }
             continue;
         }
 
-        // Accumulate SQL
+        // A dot-command is only recognized between statements.
+        if sql_buffer.is_empty() {
+            match handle_dot_command(&line, &conn.lock(), out) {
+                Some(ControlFlow::Break(())) => return,
+                Some(ControlFlow::Continue(())) => continue,
+                None => {}
+            }
+        }
+
+        // Accumulate SQL, keeping the line ends that end `--` comments
         if !sql_buffer.is_empty() {
-            sql_buffer.push(' ');
+            sql_buffer.push('\n');
         }
         sql_buffer.push_str(&line);
     }
@@ -47,91 +77,246 @@ fn main() {
     // Handle any remaining SQL
     if !sql_buffer.is_empty() {
         let sql = sql_buffer.trim();
-        execute_sql(sql, &mut stdout);
+        execute_sql(&mut conn, sql, out);
     }
 }
 
-/// Execute a SQL statement and write results to stdout.
-///
-/// This is where you implement your database!
+/// The dot-commands and what they do, as `.help` lists them.
+const DOT_COMMANDS: &[(&str, &str)] = &[
+    (".help", "List the dot-commands"),
+    (".quit", "Exit"),
+    (
+        ".schema [TABLE]",
+        "Show the CREATE TABLE statements of every table, or of TABLE",
+    ),
+    (".tables", "List the names of the tables"),
+];
+
+/// Answer `line` if it is a dot-command, with its output and a blank line
+/// as for a statement. `None` if it is not one; otherwise whether to go
+/// on reading, which only `.quit` stops.
+fn handle_dot_command<W: Write>(line: &str, db: &Database, out: &mut W) -> Option<ControlFlow<()>> {
+    let command = line.trim().strip_prefix('.')?;
+    let mut words = command.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let arg = words.next();
+    let mut tables: Vec<_> = db.tables().collect();
+    tables.sort_by_key(|t| t.name.to_lowercase());
+    match (name, arg, words.next()) {
+        ("quit" | "exit", None, None) => return Some(ControlFlow::Break(())),
+        ("help", None, None) => {
+            for (command, description) in DOT_COMMANDS {
+                writeln!(out, "{command:<18}{description}").unwrap();
+            }
+        }
+        ("tables", None, None) => {
+            for table in tables {
+                writeln!(out, "{}", table.name).unwrap();
+            }
+        }
+        ("schema", arg, None) => {
+            let wanted = |name: &str| arg.is_none_or(|arg| arg.eq_ignore_ascii_case(name));
+            for table in tables.into_iter().filter(|t| wanted(&t.name)) {
+                writeln!(out, "{};", table.create_statement()).unwrap();
+            }
+        }
+        _ => writeln!(
+            out,
+            "Error: unknown command or invalid arguments: \"{}\". Enter \".help\" for help",
+            command.trim()
+        )
+        .unwrap(),
+    }
+    writeln!(out).unwrap();
+    out.flush().unwrap();
+    Some(ControlFlow::Continue(()))
+}
+
+/// Execute a batch of SQL statements in order and write results to stdout.
 ///
 /// For successful queries, output:
 /// - Result rows as tab-separated values
 /// - One row per line
-/// - Blank line to signal end
-///
-/// For successful statements (CREATE, INSERT, etc.):
-/// - Just output a blank line
 ///
 /// For errors:
 /// - Output "Error: <message>"
-/// - Then a blank line
-fn execute_sql<W: Write>(sql: &str, out: &mut W) {
-    // Remove trailing semicolon for parsing
-    let sql = sql.trim().trim_end_matches(';').trim();
-
-    if sql.is_empty() {
-        // Empty statement - just acknowledge
-        writeln!(out).unwrap();
-        out.flush().unwrap();
-        return;
+/// - Execute none of the statements after the failed one
+///
+/// The batch's output ends with a blank line; one of statements without
+/// results, or of none at all, outputs just that.
+fn execute_sql<W: Write>(conn: &mut Connection, sql: &str, out: &mut W) {
+    for sql in lexer::split_statements(sql) {
+        if sql.is_empty() {
+            continue;
+        }
+        if let Err(message) = execute_statement(conn, sql, out) {
+            writeln!(out, "Error: {message}").unwrap();
+            break;
+        }
     }
-
-    // TODO: Implement your SQL database here!
-    //
-    // Suggested modules to create:
-    //
-    // mod parser;     // Parse SQL into AST
-    // mod types;      // SQL types: Integer, Real, Text, Null
-    // mod storage;    // In-memory tables
-    // mod executor;   // Execute queries
-    // mod functions;  // Built-in functions (ABS, COALESCE, etc.)
-    //
-    // Start with these milestones:
-    //
-    // 1. SELECT <literal>
-    //    - "SELECT 1" -> outputs "1"
-    //    - "SELECT 'hello'" -> outputs "hello"
-    //
-    // 2. CREATE TABLE, INSERT, basic SELECT
-    //    - Store tables in a HashMap<String, Table>
-    //    - Table = Vec<Row>, Row = Vec<Value>
-    //
-    // 3. WHERE clauses
-    //    - Filter rows based on predicates
-    //
-    // 4. JOINs
-    //    - Start with CROSS JOIN, then INNER JOIN
-    //
-    // 5. Aggregates
-    //    - COUNT, SUM, AVG, MIN, MAX
-    //    - GROUP BY, HAVING
-    //
-    // 6. Subqueries
-    //    - Scalar subqueries in SELECT
-    //    - IN (subquery)
-    //    - EXISTS
-    //
-    // For now, we just return "not implemented" for everything:
-
-    writeln!(out, "Error: not implemented - {}", first_word(sql)).unwrap();
     writeln!(out).unwrap();
     out.flush().unwrap();
 }
 
-/// Get the first word of a SQL statement (for error messages)
-fn first_word(sql: &str) -> &str {
-    sql.split_whitespace().next().unwrap_or("unknown")
+/// Execute one statement, writing its result rows, and flushing them, as
+/// the query produces them rather than once it is done.
+fn execute_statement<W: Write>(
+    conn: &mut Connection,
+    sql: &str,
+    out: &mut W,
+) -> Result<(), String> {
+    let stmt = parser::parse_statement(sql).map_err(|e| e.to_string())?;
+    conn.execute_each(&stmt, |row| {
+        let fields: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+        writeln!(out, "{}", fields.join("\t")).unwrap();
+        out.flush().unwrap();
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sql_challenge::executor;
+
+    fn respond(conn: &mut Connection, sql: &str) -> String {
+        let mut out = Vec::new();
+        execute_sql(conn, sql, &mut out);
+        String::from_utf8(out).unwrap()
+    }
 
     #[test]
-    fn test_first_word() {
-        assert_eq!(first_word("SELECT 1"), "SELECT");
-        assert_eq!(first_word("CREATE TABLE t1(x INT)"), "CREATE");
-        assert_eq!(first_word("  INSERT INTO t1 VALUES(1)"), "INSERT");
+    fn test_statement_responses() {
+        let mut conn = Connection::open(Database::new().into_shared());
+        assert_eq!(respond(&mut conn, ";"), "\n");
+        assert_eq!(respond(&mut conn, "CREATE TABLE t1(x INT);"), "\n");
+        assert_eq!(
+            respond(&mut conn, "CREATE TABLE t1(x INT)"),
+            "Error: table t1 already exists\n\n"
+        );
+        assert_eq!(
+            respond(&mut conn, "CREATE t1"),
+            "Error: near \"t1\": syntax error\n\n"
+        );
+        respond(&mut conn, "INSERT INTO t1 VALUES (1), (2)");
+        assert_eq!(
+            respond(&mut conn, "UPDATE t1 SET x = x * 10 WHERE x > 1"),
+            "\n"
+        );
+        assert_eq!(
+            respond(&mut conn, "SELECT x, x * 2 FROM t1"),
+            "1\t2\n20\t40\n\n"
+        );
+    }
+
+    #[test]
+    fn test_statement_batches() {
+        let mut conn = Connection::open(Database::new().into_shared());
+        assert_eq!(
+            respond(
+                &mut conn,
+                "CREATE TABLE t(x INT); INSERT INTO t VALUES(1), (2);\nSELECT * FROM t;"
+            ),
+            "1\n2\n\n"
+        );
+        // A failed statement ends the batch; those before it stay done.
+        assert_eq!(
+            respond(
+                &mut conn,
+                "SELECT x FROM t; INSERT INTO t VALUES (3); SELECT nope FROM t; INSERT INTO t VALUES (4)"
+            ),
+            "1\n2\nError: no such column: nope\n\n"
+        );
+        assert_eq!(
+            respond(&mut conn, "SELECT COUNT(*), MAX(x) FROM t"),
+            "3\t3\n\n"
+        );
+        assert_eq!(
+            respond(
+                &mut conn,
+                "SELECT 'a;b' /* ; */, x FROM t WHERE x = 1; -- done;"
+            ),
+            "a;b\t1\n\n"
+        );
+        assert_eq!(respond(&mut conn, ""), "\n");
+        assert_eq!(respond(&mut conn, "; ;"), "\n");
+        // Lines of a statement are kept apart, so a comment ends with its
+        // line.
+        assert_eq!(session("SELECT 1, -- one\n2;\n\n"), "1\t2\n\n");
+    }
+
+    fn session(input: &str) -> String {
+        let mut out = Vec::new();
+        repl(input.as_bytes(), &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    const TABLES: &str = "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, \
+                          email TEXT COLLATE NOCASE UNIQUE, CHECK (length(name) > 0))\n\n\
+                          CREATE TABLE \"Order Items\" (order_id INTEGER, line INTEGER, \
+                          user_id REFERENCES users ON DELETE CASCADE, qty INTEGER DEFAULT 1, \
+                          PRIMARY KEY (order_id, line), UNIQUE (user_id, qty))\n\n";
+
+    #[test]
+    fn test_dot_tables() {
+        assert_eq!(session(".tables\n"), "\n");
+        assert_eq!(
+            session(&format!("{TABLES}.tables\n")),
+            "\n\nOrder Items\nusers\n\n"
+        );
+        // Mid-statement, a line starting with `.` is part of the statement.
+        assert_eq!(
+            session("SELECT 1\n.5\n\n"),
+            "Error: near \"0.5\": syntax error\n\n"
+        );
+    }
+
+    #[test]
+    fn test_dot_schema_recreates_tables() {
+        let output = session(&format!("{TABLES}.schema\n"));
+        let statements: Vec<&str> = output.trim().lines().collect();
+        assert_eq!(statements.len(), 2);
+        let mut copy = Database::new();
+        for statement in &statements {
+            let sql = statement.strip_suffix(';').unwrap();
+            let stmt = parser::parse_statement(sql).unwrap();
+            executor::execute(&mut copy, &stmt).unwrap();
+        }
+        // The copy has the same schema, so describes itself the same way.
+        let mut again = Vec::new();
+        handle_dot_command(".schema", &copy, &mut again);
+        assert_eq!(String::from_utf8(again).unwrap(), output[2..]);
+        assert_eq!(
+            session(&format!("{TABLES}.schema USERS\n")),
+            format!("\n\n{}\n\n", statements[1])
+        );
+        assert_eq!(session(&format!("{TABLES}.schema missing\n")), "\n\n\n");
+    }
+
+    #[test]
+    fn test_journal_mode() {
+        assert_eq!(session("PRAGMA journal_mode\n\n"), "memory\n\n");
+        assert_eq!(
+            session(
+                "PRAGMA journal_mode = WAL\n\nCREATE TABLE t(x);\n\n\
+                 BEGIN; INSERT INTO t VALUES (1); SELECT COUNT(*) FROM t;\n\n\
+                 CREATE TABLE u(x)\n\nCOMMIT; SELECT x FROM t\n\n"
+            ),
+            "wal\n\n\n1\n\nError: cannot change the schema within a wal transaction\n\n1\n\n"
+        );
+    }
+
+    #[test]
+    fn test_dot_quit_and_help() {
+        assert_eq!(session("SELECT 1\n\n.quit\nSELECT 2\n\n"), "1\n\n");
+        let help = session(".help\n");
+        for (command, _) in DOT_COMMANDS {
+            assert!(help.contains(command), "{help}");
+        }
+        assert_eq!(
+            session(".frobnicate\n"),
+            "Error: unknown command or invalid arguments: \"frobnicate\". \
+             Enter \".help\" for help\n\n"
+        );
     }
 }
@@ -0,0 +1,3230 @@
+//! Recursive-descent SQL parser.
+//!
+//! Expression precedence, loosest to tightest:
+//!
+//! | level       | operators                                            |
+//! |-------------|------------------------------------------------------|
+//! | or          | `OR`                                                 |
+//! | and         | `AND`                                                |
+//! | not         | `NOT`                                                |
+//! | comparison  | `= <> < <= > >= IS [NOT] NULL BETWEEN IN LIKE GLOB`  |
+//! | additive    | `+ -`                                                |
+//! | multiply    | `* / %`                                              |
+//! | concat      | `\|\|`                                               |
+//! | unary       | `- +`, postfix `COLLATE`                             |
+//! | primary     | literals, columns, calls, `(...)`, subqueries, CASE  |
+
+use std::fmt;
+
+use crate::ast::{
+    AggFunc, AlterTableStmt, BinOp, CreateIndexStmt, CreateTableStmt, CreateViewStmt, Cte,
+    CurrentTime, DeleteStmt, DropIndexStmt, DropTableStmt, DropViewStmt, Expr, FrameBound,
+    FrameMode, InsertConflictAction, InsertSource, InsertStmt, JoinConstraint, JoinKind,
+    LimitClause, OrderByItem, PragmaStmt, Quantifier, QueryBody, SelectCore, SelectItem,
+    SelectStmt, SetOperator, Statement, TableConstraint, TableRef, TruncateStmt, UnaryOp,
+    UpdateStmt, WindowFrame, WindowSpec, WithClause,
+};
+use crate::lexer::{self, LexError, Token};
+use crate::storage::{CheckConstraint, ColumnDef, FkAction, Generated, References};
+use crate::types::{Collation, DataType, Value};
+
+/// Failure to parse a statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The input could not be tokenized.
+    Lex(LexError),
+    /// An unexpected token (or end of input) at `token_index`.
+    Syntax { message: String, token_index: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Lex(e) => write!(f, "{e}"),
+            ParseError::Syntax { message, .. } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<LexError> for ParseError {
+    fn from(e: LexError) -> Self {
+        ParseError::Lex(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+/// How deep expressions and queries may nest, as in SQLite. Each operand
+/// of a run of operators such as `1 + 2 + 3` counts one level deeper than
+/// the one before it, since the tree of the run is as deep as it is long.
+pub const MAX_EXPR_DEPTH: usize = 1000;
+
+/// Parse a single statement, with an optional trailing semicolon.
+pub fn parse_statement(sql: &str) -> Result<Statement> {
+    let mut parser = Parser::new(lexer::tokenize(sql)?);
+    let stmt = parser.statement()?;
+    parser.eat(&Token::Semicolon);
+    parser.expect_end()?;
+    Ok(stmt)
+}
+
+/// Parse a standalone expression.
+pub fn parse_expr(sql: &str) -> Result<Expr> {
+    let mut parser = Parser::new(lexer::tokenize(sql)?);
+    let expr = parser.expr()?;
+    parser.expect_end()?;
+    Ok(expr)
+}
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// The levels of nesting around the next token, up to
+    /// [`MAX_EXPR_DEPTH`].
+    depth: usize,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    // ----- token helpers -----
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_at(&self, ahead: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + ahead)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn check(&self, token: &Token) -> bool {
+        self.peek() == Some(token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.check(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Whether the next token is the contextual keyword `word`, which the
+    /// lexer hands over as a plain identifier.
+    fn check_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(w)) if w.eq_ignore_ascii_case(word))
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if self.check_word(word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<()> {
+        if self.eat_word(word) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn expect_end(&self) -> Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError::Syntax {
+            message: message.into(),
+            token_index: self.pos,
+        }
+    }
+
+    /// Go one level deeper, failing past [`MAX_EXPR_DEPTH`]. The caller
+    /// comes back up by lowering `depth` again once it has parsed what is
+    /// nested; after an error it need not, as the parse is abandoned.
+    fn deeper(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_EXPR_DEPTH {
+            return Err(self.error(format!(
+                "Expression tree is too large (maximum depth {MAX_EXPR_DEPTH})"
+            )));
+        }
+        Ok(())
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(token) => self.error(format!("near \"{token}\": syntax error")),
+            None => self.error("incomplete input"),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Parse a comma-separated list of at least one item.
+    fn comma_list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let mut items = vec![item(self)?];
+        while self.eat(&Token::Comma) {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    // ----- statements -----
+
+    pub fn statement(&mut self) -> Result<Statement> {
+        match self.peek() {
+            Some(Token::Select | Token::With) => Ok(Statement::Select(Box::new(self.select()?))),
+            Some(Token::Create) => self.create(),
+            Some(Token::Drop) => self.drop(),
+            _ if self.check_word("TRUNCATE") => Ok(Statement::Truncate(self.truncate()?)),
+            _ if self.check_word("ALTER") => Ok(Statement::AlterTable(self.alter_table()?)),
+            Some(Token::Insert) => Ok(Statement::Insert(self.insert()?)),
+            _ if self.check_word("REPLACE") => Ok(Statement::Insert(self.insert()?)),
+            Some(Token::Update) => Ok(Statement::Update(self.update()?)),
+            Some(Token::Delete) => Ok(Statement::Delete(self.delete()?)),
+            _ if self.check_word("BEGIN") => self.transaction(Statement::Begin),
+            _ if self.check_word("COMMIT") => self.transaction(Statement::Commit),
+            Some(Token::End) => self.transaction(Statement::Commit),
+            _ if self.check_word("ROLLBACK") => self.rollback(),
+            _ if self.check_word("SAVEPOINT") || self.check_word("RELEASE") => self.savepoint(),
+            _ if self.check_word("EXPLAIN") => {
+                self.advance();
+                Ok(Statement::Explain(Box::new(self.select()?)))
+            }
+            _ if self.check_word("ANALYZE") => {
+                self.advance();
+                let table = match self.peek() {
+                    Some(Token::Ident(_)) => Some(self.ident()?),
+                    _ => None,
+                };
+                Ok(Statement::Analyze(table))
+            }
+            _ if self.check_word("PRAGMA") => Ok(Statement::Pragma(self.pragma()?)),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// `<keyword> [TRANSACTION]`, for the statement `stmt` the keyword
+    /// starts.
+    fn transaction(&mut self, stmt: Statement) -> Result<Statement> {
+        self.advance();
+        self.eat_word("TRANSACTION");
+        Ok(stmt)
+    }
+
+    fn rollback(&mut self) -> Result<Statement> {
+        self.transaction(Statement::Rollback)?;
+        if !self.eat_word("TO") {
+            return Ok(Statement::Rollback);
+        }
+        self.eat_word("SAVEPOINT");
+        Ok(Statement::RollbackTo(self.ident()?))
+    }
+
+    /// `SAVEPOINT <name>` or `RELEASE [SAVEPOINT] <name>`
+    fn savepoint(&mut self) -> Result<Statement> {
+        if self.eat_word("SAVEPOINT") {
+            return Ok(Statement::Savepoint(self.ident()?));
+        }
+        self.expect_word("RELEASE")?;
+        self.eat_word("SAVEPOINT");
+        Ok(Statement::Release(self.ident()?))
+    }
+
+    fn insert(&mut self) -> Result<InsertStmt> {
+        let conflict = if !self.eat(&Token::Insert) {
+            self.expect_word("REPLACE")?;
+            Some(InsertConflictAction::Replace)
+        } else if self.eat(&Token::Or) {
+            if self.eat_word("REPLACE") {
+                Some(InsertConflictAction::Replace)
+            } else {
+                self.expect_word("IGNORE")?;
+                Some(InsertConflictAction::Ignore)
+            }
+        } else {
+            None
+        };
+        self.expect(&Token::Into)?;
+        let table = self.ident()?;
+        let columns = if self.eat(&Token::LParen) {
+            let columns = self.comma_list(Self::ident)?;
+            self.expect(&Token::RParen)?;
+            Some(columns)
+        } else {
+            None
+        };
+        let source = if self.at_query(0) {
+            InsertSource::Query(Box::new(self.select()?))
+        } else {
+            InsertSource::Values(self.values()?)
+        };
+        Ok(InsertStmt {
+            conflict,
+            table,
+            columns,
+            source,
+            returning: self.returning()?,
+        })
+    }
+
+    /// `RETURNING <item>, ...` after an INSERT, UPDATE or DELETE, if given.
+    fn returning(&mut self) -> Result<Vec<SelectItem>> {
+        if self.eat_word("RETURNING") {
+            self.comma_list(Self::select_item)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// `VALUES (<expr>, ...), ...`, with as many values in each row.
+    fn values(&mut self) -> Result<Vec<Vec<Expr>>> {
+        self.expect(&Token::Values)?;
+        let rows = self.comma_list(|p| {
+            p.expect(&Token::LParen)?;
+            let row = p.comma_list(Self::expr)?;
+            p.expect(&Token::RParen)?;
+            Ok(row)
+        })?;
+        if rows.iter().any(|row| row.len() != rows[0].len()) {
+            return Err(self.error("all VALUES must have the same number of terms"));
+        }
+        Ok(rows)
+    }
+
+    fn update(&mut self) -> Result<UpdateStmt> {
+        self.expect(&Token::Update)?;
+        let table = self.ident()?;
+        self.expect_word("SET")?;
+        let assignments = self.comma_list(|p| {
+            let column = p.ident()?;
+            p.expect(&Token::Eq)?;
+            Ok((column, p.expr()?))
+        })?;
+        let where_clause = if self.eat(&Token::Where) {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        Ok(UpdateStmt {
+            table,
+            assignments,
+            where_clause,
+            returning: self.returning()?,
+        })
+    }
+
+    fn delete(&mut self) -> Result<DeleteStmt> {
+        self.expect(&Token::Delete)?;
+        self.expect(&Token::From)?;
+        let table = self.ident()?;
+        let where_clause = if self.eat(&Token::Where) {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        Ok(DeleteStmt {
+            table,
+            where_clause,
+            returning: self.returning()?,
+        })
+    }
+
+    /// `CREATE TABLE`, `CREATE [UNIQUE] INDEX` or `CREATE [OR REPLACE] VIEW`.
+    fn create(&mut self) -> Result<Statement> {
+        self.expect(&Token::Create)?;
+        if self.check(&Token::Table) {
+            return Ok(Statement::CreateTable(self.create_table()?));
+        }
+        if self.check(&Token::Or) || self.check_word("VIEW") {
+            return Ok(Statement::CreateView(self.create_view()?));
+        }
+        let unique = self.eat_word("UNIQUE");
+        self.expect_word("INDEX")?;
+        let if_not_exists = self.eat_word("IF");
+        if if_not_exists {
+            self.expect(&Token::Not)?;
+            self.expect_word("EXISTS")?;
+        }
+        let name = self.ident()?;
+        self.expect(&Token::On)?;
+        let table = self.ident()?;
+        self.expect(&Token::LParen)?;
+        let columns = self.comma_list(Self::ident)?;
+        self.expect(&Token::RParen)?;
+        Ok(Statement::CreateIndex(CreateIndexStmt {
+            name,
+            table,
+            columns,
+            unique,
+            if_not_exists,
+        }))
+    }
+
+    /// `DROP TABLE`, `DROP INDEX` or `DROP VIEW`.
+    fn drop(&mut self) -> Result<Statement> {
+        self.expect(&Token::Drop)?;
+        if self.check(&Token::Table) {
+            return Ok(Statement::DropTable(self.drop_table()?));
+        }
+        if self.eat_word("VIEW") {
+            let if_exists = self.eat_word("IF");
+            if if_exists {
+                self.expect_word("EXISTS")?;
+            }
+            let name = self.ident()?;
+            return Ok(Statement::DropView(DropViewStmt { name, if_exists }));
+        }
+        self.expect_word("INDEX")?;
+        let if_exists = self.eat_word("IF");
+        if if_exists {
+            self.expect_word("EXISTS")?;
+        }
+        let name = self.ident()?;
+        Ok(Statement::DropIndex(DropIndexStmt { name, if_exists }))
+    }
+
+    fn create_view(&mut self) -> Result<CreateViewStmt> {
+        let or_replace = self.eat(&Token::Or);
+        if or_replace {
+            self.expect_word("REPLACE")?;
+        }
+        self.expect_word("VIEW")?;
+        let if_not_exists = self.eat_word("IF");
+        if if_not_exists {
+            self.expect(&Token::Not)?;
+            self.expect_word("EXISTS")?;
+        }
+        let name = self.ident()?;
+        self.expect(&Token::As)?;
+        let query = Box::new(self.select()?);
+        Ok(CreateViewStmt {
+            name,
+            or_replace,
+            if_not_exists,
+            query,
+        })
+    }
+
+    fn create_table(&mut self) -> Result<CreateTableStmt> {
+        self.expect(&Token::Table)?;
+        let if_not_exists = self.eat_word("IF");
+        if if_not_exists {
+            self.expect(&Token::Not)?;
+            self.expect_word("EXISTS")?;
+        }
+        let name = self.ident()?;
+        self.expect(&Token::LParen)?;
+        let mut columns = Vec::new();
+        let mut constraints = Vec::new();
+        loop {
+            // Table constraints follow all of the columns.
+            if !constraints.is_empty() || self.at_table_constraint() {
+                constraints.push(self.table_constraint()?);
+            } else {
+                columns.push(self.column_def()?);
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RParen)?;
+        let primary_keys = columns.iter().filter(|c| c.primary_key).count()
+            + constraints
+                .iter()
+                .filter(|c| matches!(c, TableConstraint::PrimaryKey(_)))
+                .count();
+        if primary_keys > 1 {
+            return Err(self.error(format!("table \"{name}\" has more than one primary key")));
+        }
+        Ok(CreateTableStmt {
+            name,
+            if_not_exists,
+            columns,
+            constraints,
+        })
+    }
+
+    fn at_table_constraint(&self) -> bool {
+        const WORDS: [&str; 5] = ["CONSTRAINT", "PRIMARY", "UNIQUE", "FOREIGN", "CHECK"];
+        WORDS.iter().any(|word| self.check_word(word))
+    }
+
+    /// `[CONSTRAINT <name>]` followed by `{PRIMARY KEY | UNIQUE} (<column>,
+    /// ...)`, `FOREIGN KEY (<column>, ...) REFERENCES ...` or `CHECK
+    /// (<expr>)`; only a CHECK constraint keeps the name.
+    fn table_constraint(&mut self) -> Result<TableConstraint> {
+        let name = if self.eat_word("CONSTRAINT") {
+            Some(self.ident()?)
+        } else {
+            None
+        };
+        if self.eat_word("CHECK") {
+            return Ok(TableConstraint::Check(self.check_constraint(name)?));
+        }
+        if self.eat_word("FOREIGN") {
+            self.expect_word("KEY")?;
+            self.expect(&Token::LParen)?;
+            let columns = self.comma_list(Self::ident)?;
+            self.expect(&Token::RParen)?;
+            self.expect_word("REFERENCES")?;
+            let references = self.references()?;
+            return Ok(TableConstraint::ForeignKey {
+                columns,
+                references,
+            });
+        }
+        let primary_key = self.eat_word("PRIMARY");
+        if primary_key {
+            self.expect_word("KEY")?;
+        } else {
+            self.expect_word("UNIQUE")?;
+        }
+        self.expect(&Token::LParen)?;
+        let columns = self.comma_list(Self::ident)?;
+        self.expect(&Token::RParen)?;
+        Ok(if primary_key {
+            TableConstraint::PrimaryKey(columns)
+        } else {
+            TableConstraint::Unique(columns)
+        })
+    }
+
+    fn drop_table(&mut self) -> Result<DropTableStmt> {
+        self.expect(&Token::Table)?;
+        let if_exists = self.eat_word("IF");
+        if if_exists {
+            self.expect_word("EXISTS")?;
+        }
+        let name = self.ident()?;
+        Ok(DropTableStmt { name, if_exists })
+    }
+
+    fn alter_table(&mut self) -> Result<AlterTableStmt> {
+        self.expect_word("ALTER")?;
+        self.expect(&Token::Table)?;
+        let table = self.ident()?;
+        if self.eat(&Token::Drop) {
+            self.eat_word("COLUMN");
+            let if_exists = self.eat_word("IF");
+            if if_exists {
+                self.expect_word("EXISTS")?;
+            }
+            let column_name = self.ident()?;
+            return Ok(AlterTableStmt::DropColumn {
+                table,
+                column_name,
+                if_exists,
+            });
+        }
+        self.expect_word("ADD")?;
+        self.eat_word("COLUMN");
+        let column_def = self.column_def()?;
+        Ok(AlterTableStmt::AddColumn { table, column_def })
+    }
+
+    fn pragma(&mut self) -> Result<PragmaStmt> {
+        self.advance();
+        let name = self.ident()?;
+        let arg = if self.eat(&Token::LParen) {
+            let arg = self.pragma_arg()?;
+            self.expect(&Token::RParen)?;
+            Some(arg)
+        } else if self.eat(&Token::Eq) {
+            Some(self.pragma_arg()?)
+        } else {
+            None
+        };
+        Ok(PragmaStmt { name, arg })
+    }
+
+    /// A PRAGMA's argument: a name, bare or as a string, or a number.
+    fn pragma_arg(&mut self) -> Result<String> {
+        match self.peek().cloned() {
+            Some(Token::StringLiteral(s)) => {
+                self.advance();
+                Ok(s)
+            }
+            Some(Token::IntLiteral(n)) => {
+                self.advance();
+                Ok(n.to_string())
+            }
+            _ => self.ident(),
+        }
+    }
+
+    fn truncate(&mut self) -> Result<TruncateStmt> {
+        self.expect_word("TRUNCATE")?;
+        self.eat(&Token::Table);
+        let name = self.ident()?;
+        Ok(TruncateStmt { name })
+    }
+
+    /// `name [type] [constraint ...]`
+    fn column_def(&mut self) -> Result<ColumnDef> {
+        let name = self.ident()?;
+        let mut column = ColumnDef::new(name, None);
+        if matches!(self.peek(), Some(Token::Ident(_))) && !self.at_constraint() {
+            let type_name = self.type_name()?;
+            // PostgreSQL's SERIAL types are integers that number themselves.
+            if type_name.to_ascii_uppercase().ends_with("SERIAL") {
+                column.data_type = Some(DataType::Int);
+                column.nullable = false;
+                column.autoincrement = true;
+            } else {
+                column.data_type = DataType::from_name(&type_name);
+            }
+        }
+        // Only a CHECK constraint keeps the name given to it.
+        let mut name = None;
+        loop {
+            if self.eat_word("CONSTRAINT") {
+                name = Some(self.ident()?);
+                continue;
+            }
+            if self.eat_word("CHECK") {
+                column.checks.push(self.check_constraint(name.take())?);
+            } else if self.eat(&Token::Not) {
+                self.expect(&Token::Null)?;
+                column.nullable = false;
+            } else if self.eat(&Token::Null) {
+                column.nullable = true;
+            } else if self.eat_word("DEFAULT") {
+                // A COLLATE after the default belongs to the column.
+                column.default = Some(self.prefixed()?);
+            } else if self.eat_word("COLLATE") {
+                column.collation = self.collation()?;
+            } else if self.eat_word("PRIMARY") {
+                self.expect_word("KEY")?;
+                column.primary_key = true;
+                column.nullable = false;
+                column.autoincrement |= self.eat_word("AUTOINCREMENT");
+            } else if self.eat_word("UNIQUE") {
+                column.unique = true;
+            } else if self.eat_word("REFERENCES") {
+                column.references = Some(self.references()?);
+            } else if self.check_word("GENERATED") || self.check(&Token::As) {
+                column.generated = Some(Box::new(self.generated()?));
+            } else if name.is_some() {
+                return Err(self.unexpected());
+            } else {
+                return Ok(column);
+            }
+            name = None;
+        }
+    }
+
+    /// `[GENERATED ALWAYS] AS (<expr>) [STORED | VIRTUAL]`, virtual unless
+    /// stored.
+    fn generated(&mut self) -> Result<Generated> {
+        if self.eat_word("GENERATED") {
+            self.expect_word("ALWAYS")?;
+        }
+        self.expect(&Token::As)?;
+        self.expect(&Token::LParen)?;
+        let expr = self.expr()?;
+        self.expect(&Token::RParen)?;
+        let stored = self.eat_word("STORED");
+        if !stored {
+            self.eat_word("VIRTUAL");
+        }
+        Ok(Generated { expr, stored })
+    }
+
+    /// `(<expr>)`, after `CHECK`.
+    fn check_constraint(&mut self, name: Option<String>) -> Result<CheckConstraint> {
+        self.expect(&Token::LParen)?;
+        let expr = self.expr()?;
+        self.expect(&Token::RParen)?;
+        Ok(CheckConstraint { name, expr })
+    }
+
+    /// Whether the next token starts a column constraint rather than
+    /// continuing a multi-word type name.
+    fn at_constraint(&self) -> bool {
+        const WORDS: [&str; 9] = [
+            "AUTOINCREMENT",
+            "DEFAULT",
+            "GENERATED",
+            "PRIMARY",
+            "UNIQUE",
+            "CONSTRAINT",
+            "REFERENCES",
+            "COLLATE",
+            "CHECK",
+        ];
+        WORDS.iter().any(|word| self.check_word(word))
+    }
+
+    /// `<table> [(<column>, ...)] [ON DELETE <action>]`, after `REFERENCES`.
+    fn references(&mut self) -> Result<References> {
+        let table = self.ident()?;
+        let columns = if self.eat(&Token::LParen) {
+            let columns = self.comma_list(Self::ident)?;
+            self.expect(&Token::RParen)?;
+            columns
+        } else {
+            Vec::new()
+        };
+        let mut on_delete = FkAction::default();
+        if self.eat(&Token::On) {
+            self.expect(&Token::Delete)?;
+            on_delete = if self.eat_word("CASCADE") {
+                FkAction::Cascade
+            } else if self.eat_word("RESTRICT") {
+                FkAction::Restrict
+            } else if self.eat_word("SET") {
+                if self.eat(&Token::Null) {
+                    FkAction::SetNull
+                } else {
+                    self.expect_word("DEFAULT")?;
+                    FkAction::SetDefault
+                }
+            } else {
+                self.expect_word("NO")?;
+                self.expect_word("ACTION")?;
+                FkAction::NoAction
+            };
+        }
+        Ok(References {
+            table,
+            columns,
+            on_delete,
+        })
+    }
+
+    /// Whether the token `ahead` of the current one starts a query.
+    fn at_query(&self, ahead: usize) -> bool {
+        matches!(self.peek_at(ahead), Some(Token::Select | Token::With))
+    }
+
+    fn select(&mut self) -> Result<SelectStmt> {
+        self.deeper()?;
+        let select = self.nested_select()?;
+        self.depth -= 1;
+        Ok(select)
+    }
+
+    fn nested_select(&mut self) -> Result<SelectStmt> {
+        let with = if self.eat(&Token::With) {
+            let recursive = self.eat(&Token::Recursive);
+            let ctes = self.comma_list(|p| {
+                let name = p.ident()?;
+                let columns = if p.eat(&Token::LParen) {
+                    let columns = p.comma_list(Self::ident)?;
+                    p.expect(&Token::RParen)?;
+                    Some(columns)
+                } else {
+                    None
+                };
+                p.expect(&Token::As)?;
+                p.expect(&Token::LParen)?;
+                let query = p.select()?;
+                p.expect(&Token::RParen)?;
+                Ok(Cte {
+                    name,
+                    columns,
+                    query,
+                })
+            })?;
+            for (i, cte) in ctes.iter().enumerate() {
+                if ctes[..i]
+                    .iter()
+                    .any(|earlier| earlier.name.eq_ignore_ascii_case(&cte.name))
+                {
+                    return Err(self.error(format!("duplicate WITH table name: {}", cte.name)));
+                }
+            }
+            Some(WithClause { recursive, ctes })
+        } else {
+            None
+        };
+        let body = self.compound_select()?;
+        let order_by = if self.eat(&Token::Order) {
+            self.expect(&Token::By)?;
+            self.comma_list(Self::order_by_item)?
+        } else {
+            Vec::new()
+        };
+        let limit = self.limit_clause()?;
+        Ok(SelectStmt {
+            with,
+            body,
+            order_by,
+            limit,
+        })
+    }
+
+    /// SELECTs joined by UNION [ALL] and EXCEPT, left-associatively.
+    fn compound_select(&mut self) -> Result<QueryBody> {
+        let depth = self.depth;
+        let mut left = self.intersect_select()?;
+        loop {
+            let op = if self.eat(&Token::Union) {
+                if self.eat(&Token::All) {
+                    SetOperator::UnionAll
+                } else {
+                    SetOperator::Union
+                }
+            } else if self.eat(&Token::Except) {
+                SetOperator::Except
+            } else {
+                self.depth = depth;
+                return Ok(left);
+            };
+            self.deeper()?;
+            let right = self.intersect_select()?;
+            left = QueryBody::SetOperation {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+    }
+
+    /// SELECTs joined by INTERSECT, which binds tighter than the other set
+    /// operators.
+    fn intersect_select(&mut self) -> Result<QueryBody> {
+        let depth = self.depth;
+        let mut left = QueryBody::Select(Box::new(self.select_core()?));
+        while self.eat(&Token::Intersect) {
+            self.deeper()?;
+            let right = QueryBody::Select(Box::new(self.select_core()?));
+            left = QueryBody::SetOperation {
+                op: SetOperator::Intersect,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn select_core(&mut self) -> Result<SelectCore> {
+        self.expect(&Token::Select)?;
+        let distinct = self.eat(&Token::Distinct);
+        if !distinct {
+            self.eat(&Token::All);
+        }
+        let projection = self.comma_list(Self::select_item)?;
+        let from = if self.eat(&Token::From) {
+            Some(self.table_ref()?)
+        } else {
+            None
+        };
+        let where_clause = if self.eat(&Token::Where) {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let group_by = if self.eat(&Token::Group) {
+            self.expect(&Token::By)?;
+            self.comma_list(Self::expr)?
+        } else {
+            Vec::new()
+        };
+        let having = if self.eat(&Token::Having) {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        Ok(SelectCore {
+            distinct,
+            projection,
+            from,
+            where_clause,
+            group_by,
+            having,
+        })
+    }
+
+    fn limit_clause(&mut self) -> Result<Option<LimitClause>> {
+        if self.eat(&Token::Offset) {
+            let offset = Some(self.expr()?);
+            return Ok(Some(LimitClause {
+                limit: None,
+                offset,
+            }));
+        }
+        if !self.eat(&Token::Limit) {
+            return Ok(None);
+        }
+        let first = self.expr()?;
+        let (limit, offset) = if self.eat(&Token::Comma) {
+            // MySQL style: `LIMIT <offset>, <count>`.
+            (self.expr()?, Some(first))
+        } else if self.eat(&Token::Offset) {
+            (first, Some(self.expr()?))
+        } else {
+            (first, None)
+        };
+        Ok(Some(LimitClause {
+            limit: Some(limit),
+            offset,
+        }))
+    }
+
+    fn order_by_item(&mut self) -> Result<OrderByItem> {
+        let expr = self.expr()?;
+        let desc = if self.eat_word("DESC") {
+            true
+        } else {
+            self.eat_word("ASC");
+            false
+        };
+        let nulls_first = if self.eat_word("NULLS") {
+            if self.eat_word("FIRST") {
+                Some(true)
+            } else {
+                self.expect_word("LAST")?;
+                Some(false)
+            }
+        } else {
+            None
+        };
+        Ok(OrderByItem {
+            expr,
+            desc,
+            nulls_first,
+        })
+    }
+
+    fn select_item(&mut self) -> Result<SelectItem> {
+        if self.eat(&Token::Star) {
+            return Ok(SelectItem::Wildcard);
+        }
+        if let (Some(Token::Ident(table)), Some(Token::Dot), Some(Token::Star)) =
+            (self.peek(), self.peek_at(1), self.peek_at(2))
+        {
+            let table = table.clone();
+            self.pos += 3;
+            return Ok(SelectItem::QualifiedWildcard(table));
+        }
+        let expr = self.expr()?;
+        let alias = self.alias()?;
+        Ok(SelectItem::Expr { expr, alias })
+    }
+
+    /// `[AS] alias`. Without AS, `RETURNING` is not taken for one, as it
+    /// may end the query of an INSERT.
+    fn alias(&mut self) -> Result<Option<String>> {
+        if self.eat(&Token::As) {
+            return self.ident().map(Some);
+        }
+        match self.peek() {
+            Some(Token::Ident(_)) if self.check_word("RETURNING") => Ok(None),
+            Some(Token::Ident(_)) => self.ident().map(Some),
+            Some(Token::StringLiteral(s)) => {
+                let alias = s.clone();
+                self.pos += 1;
+                Ok(Some(alias))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// A table followed by any number of joins, associating to the left.
+    fn table_ref(&mut self) -> Result<TableRef> {
+        let mut left = self.table_primary()?;
+        loop {
+            let natural = self.eat(&Token::Natural);
+            if natural && self.check(&Token::Comma) {
+                return Err(self.unexpected());
+            }
+            let comma = self.check(&Token::Comma);
+            let Some(kind) = self.join_kind()? else {
+                if natural {
+                    return Err(self.unexpected());
+                }
+                break;
+            };
+            if natural && kind == JoinKind::Cross {
+                return Err(self.error("a NATURAL join cannot be a CROSS JOIN"));
+            }
+            let right = self.table_primary()?;
+            let has_constraint = self.check(&Token::On) || self.check(&Token::Using);
+            if kind == JoinKind::Cross && has_constraint {
+                return Err(self.error("a CROSS JOIN cannot have an ON or USING clause"));
+            }
+            if comma && has_constraint {
+                return Err(self.error("a comma join cannot have an ON or USING clause"));
+            }
+            if natural && has_constraint {
+                return Err(self.error("a NATURAL join cannot have an ON or USING clause"));
+            }
+            let constraint = if natural {
+                Some(JoinConstraint::Natural)
+            } else if self.eat(&Token::On) {
+                Some(JoinConstraint::On(self.expr()?))
+            } else if self.eat(&Token::Using) {
+                self.expect(&Token::LParen)?;
+                let columns = self.comma_list(Self::ident)?;
+                self.expect(&Token::RParen)?;
+                Some(JoinConstraint::Using(columns))
+            } else {
+                None
+            };
+            left = TableRef::Join {
+                left: Box::new(left),
+                right: Box::new(right),
+                kind,
+                constraint,
+            };
+        }
+        Ok(left)
+    }
+
+    /// Consume a join operator, if one is next. A comma is an inner join
+    /// without a condition, which unlike a CROSS JOIN may be reordered.
+    fn join_kind(&mut self) -> Result<Option<JoinKind>> {
+        if self.eat(&Token::Comma) {
+            return Ok(Some(JoinKind::Inner));
+        }
+        let kind = match self.peek() {
+            Some(Token::Join) => JoinKind::Inner,
+            Some(Token::Inner) => {
+                self.pos += 1;
+                JoinKind::Inner
+            }
+            Some(Token::Left) => {
+                self.pos += 1;
+                self.eat(&Token::Outer);
+                JoinKind::Left
+            }
+            Some(Token::Right) => {
+                self.pos += 1;
+                self.eat(&Token::Outer);
+                JoinKind::Right
+            }
+            Some(Token::Full) => {
+                self.pos += 1;
+                self.eat(&Token::Outer);
+                JoinKind::Full
+            }
+            Some(Token::Cross) => {
+                self.pos += 1;
+                JoinKind::Cross
+            }
+            _ => return Ok(None),
+        };
+        self.expect(&Token::Join)?;
+        Ok(Some(kind))
+    }
+
+    fn table_primary(&mut self) -> Result<TableRef> {
+        if self.check(&Token::LParen) && self.at_query(1) {
+            self.pos += 1;
+            let query = self.select()?;
+            self.expect(&Token::RParen)?;
+            let alias = self.alias()?;
+            let columns = self.column_aliases()?;
+            return Ok(TableRef::Subquery {
+                query: Box::new(query),
+                alias,
+                columns,
+            });
+        }
+        if self.check(&Token::LParen) && self.peek_at(1) == Some(&Token::Values) {
+            self.pos += 1;
+            let rows = self.values()?;
+            self.expect(&Token::RParen)?;
+            let alias = self.alias()?;
+            let columns = match alias {
+                Some(_) => self.column_aliases()?,
+                None => Vec::new(),
+            };
+            return Ok(TableRef::Values {
+                rows,
+                alias,
+                columns,
+            });
+        }
+        let name = self.ident()?;
+        let alias = self.alias()?;
+        Ok(TableRef::Table { name, alias })
+    }
+
+    /// The optional `(<column>, ...)` after a derived table's alias.
+    fn column_aliases(&mut self) -> Result<Vec<String>> {
+        if !self.eat(&Token::LParen) {
+            return Ok(Vec::new());
+        }
+        let columns = self.comma_list(Self::ident)?;
+        self.expect(&Token::RParen)?;
+        Ok(columns)
+    }
+
+    // ----- expressions -----
+
+    pub fn expr(&mut self) -> Result<Expr> {
+        self.deeper()?;
+        let expr = self.or_expr()?;
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn binary(op: BinOp, left: Expr, right: Expr) -> Expr {
+        Expr::BinOp {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    fn or_expr(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.and_expr()?;
+        while self.eat(&Token::Or) {
+            self.deeper()?;
+            let right = self.and_expr()?;
+            left = Self::binary(BinOp::Or, left, right);
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.not_expr()?;
+        while self.eat(&Token::And) {
+            self.deeper()?;
+            let right = self.not_expr()?;
+            left = Self::binary(BinOp::And, left, right);
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr> {
+        if self.eat(&Token::Not) {
+            self.deeper()?;
+            let expr = self.not_expr()?;
+            self.depth -= 1;
+            return Ok(Expr::UnaryOp {
+                op: UnaryOp::Not,
+                expr: Box::new(expr),
+            });
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.additive()?;
+        loop {
+            let operator = matches!(
+                self.peek(),
+                Some(
+                    Token::Eq
+                        | Token::Neq
+                        | Token::Lt
+                        | Token::Le
+                        | Token::Gt
+                        | Token::Ge
+                        | Token::Is
+                        | Token::Not
+                        | Token::Between
+                        | Token::In
+                        | Token::Like
+                )
+            );
+            if operator || self.check_word("GLOB") {
+                self.deeper()?;
+            }
+            let op = match self.peek() {
+                Some(Token::Eq) => Some(BinOp::Eq),
+                Some(Token::Neq) => Some(BinOp::Neq),
+                Some(Token::Lt) => Some(BinOp::Lt),
+                Some(Token::Le) => Some(BinOp::Le),
+                Some(Token::Gt) => Some(BinOp::Gt),
+                Some(Token::Ge) => Some(BinOp::Ge),
+                _ => None,
+            };
+            if let Some(op) = op {
+                self.pos += 1;
+                left = match self.quantifier() {
+                    Some(quantifier) => {
+                        self.pos += 2; // quantifier and '('
+                        let subquery = self.select()?;
+                        self.expect(&Token::RParen)?;
+                        Expr::QuantifiedComparison {
+                            expr: Box::new(left),
+                            op,
+                            quantifier,
+                            subquery: Box::new(subquery),
+                        }
+                    }
+                    None => Self::binary(op, left, self.additive()?),
+                };
+                continue;
+            }
+
+            if self.eat(&Token::Is) {
+                let negated = self.eat(&Token::Not);
+                self.expect(&Token::Null)?;
+                left = Expr::IsNull {
+                    expr: Box::new(left),
+                    negated,
+                };
+                continue;
+            }
+
+            // The remaining forms may be prefixed with NOT.
+            let negated = self.check(&Token::Not)
+                && match self.peek_at(1) {
+                    Some(Token::Between | Token::In | Token::Like) => true,
+                    Some(Token::Ident(word)) => word.eq_ignore_ascii_case("GLOB"),
+                    _ => false,
+                };
+            if negated {
+                self.pos += 1;
+            }
+            match self.peek() {
+                Some(Token::Between) => {
+                    self.pos += 1;
+                    let low = self.additive()?;
+                    self.expect(&Token::And)?;
+                    let high = self.additive()?;
+                    left = Expr::Between {
+                        expr: Box::new(left),
+                        low: Box::new(low),
+                        high: Box::new(high),
+                        negated,
+                    };
+                }
+                Some(Token::In) => {
+                    self.pos += 1;
+                    left = self.in_rhs(left, negated)?;
+                }
+                Some(Token::Like) => {
+                    self.pos += 1;
+                    let pattern = self.additive()?;
+                    let escape = if self.eat_word("ESCAPE") {
+                        Some(Box::new(self.additive()?))
+                    } else {
+                        None
+                    };
+                    let like = Expr::Like {
+                        expr: Box::new(left),
+                        pattern: Box::new(pattern),
+                        escape,
+                    };
+                    left = if negated {
+                        Expr::UnaryOp {
+                            op: UnaryOp::Not,
+                            expr: Box::new(like),
+                        }
+                    } else {
+                        like
+                    };
+                }
+                Some(Token::Ident(word)) if word.eq_ignore_ascii_case("GLOB") => {
+                    self.pos += 1;
+                    let glob = Self::binary(BinOp::Glob, left, self.additive()?);
+                    left = if negated {
+                        Expr::UnaryOp {
+                            op: UnaryOp::Not,
+                            expr: Box::new(glob),
+                        }
+                    } else {
+                        glob
+                    };
+                }
+                _ => {
+                    self.depth = depth;
+                    return Ok(left);
+                }
+            }
+        }
+    }
+
+    /// The quantifier of a comparison with a subquery, `ANY`, `SOME` or
+    /// `ALL` before `(SELECT ...`, if one is next.
+    fn quantifier(&self) -> Option<Quantifier> {
+        let quantifier = match self.peek()? {
+            Token::All => Quantifier::All,
+            Token::Ident(word)
+                if word.eq_ignore_ascii_case("ANY") || word.eq_ignore_ascii_case("SOME") =>
+            {
+                Quantifier::Any
+            }
+            _ => return None,
+        };
+        (self.peek_at(1) == Some(&Token::LParen) && self.at_query(2)).then_some(quantifier)
+    }
+
+    /// The part of an IN predicate after the `IN` keyword.
+    fn in_rhs(&mut self, expr: Expr, negated: bool) -> Result<Expr> {
+        self.expect(&Token::LParen)?;
+        let expr = Box::new(expr);
+        if self.at_query(0) {
+            let subquery = Box::new(self.select()?);
+            self.expect(&Token::RParen)?;
+            return Ok(Expr::InSubquery {
+                expr,
+                subquery,
+                negated,
+            });
+        }
+        let list = if self.check(&Token::RParen) {
+            Vec::new()
+        } else {
+            self.comma_list(Self::expr)?
+        };
+        self.expect(&Token::RParen)?;
+        Ok(Expr::InList {
+            expr,
+            list,
+            negated,
+        })
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinOp::Add,
+                Some(Token::Minus) => BinOp::Sub,
+                _ => {
+                    self.depth = depth;
+                    return Ok(left);
+                }
+            };
+            self.pos += 1;
+            self.deeper()?;
+            let right = self.multiplicative()?;
+            left = Self::binary(op, left, right);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.concat()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => BinOp::Mul,
+                Some(Token::Slash) => BinOp::Div,
+                Some(Token::Percent) => BinOp::Mod,
+                _ => {
+                    self.depth = depth;
+                    return Ok(left);
+                }
+            };
+            self.pos += 1;
+            self.deeper()?;
+            let right = self.concat()?;
+            left = Self::binary(op, left, right);
+        }
+    }
+
+    fn concat(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.unary()?;
+        while self.eat(&Token::Concat) {
+            self.deeper()?;
+            let right = self.unary()?;
+            left = Self::binary(BinOp::Concat, left, right);
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    /// A prefixed expression followed by any number of `COLLATE <name>`.
+    fn unary(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut expr = self.prefixed()?;
+        while self.eat_word("COLLATE") {
+            self.deeper()?;
+            expr = Expr::Collate {
+                expr: Box::new(expr),
+                collation: self.collation()?,
+            };
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn prefixed(&mut self) -> Result<Expr> {
+        let op = match self.peek() {
+            Some(Token::Minus) => UnaryOp::Neg,
+            Some(Token::Plus) => UnaryOp::Plus,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        self.deeper()?;
+        let expr = self.prefixed()?;
+        self.depth -= 1;
+        Ok(Expr::UnaryOp {
+            op,
+            expr: Box::new(expr),
+        })
+    }
+
+    /// The name of a collation, after `COLLATE`.
+    fn collation(&mut self) -> Result<Collation> {
+        let name = self.ident()?;
+        Collation::from_name(&name)
+            .ok_or_else(|| self.error(format!("no such collation sequence: {name}")))
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.unexpected());
+        };
+        match token {
+            Token::IntLiteral(n) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Integer(n)))
+            }
+            Token::RealLiteral(n) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Real(n)))
+            }
+            Token::StringLiteral(s) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Text(s)))
+            }
+            Token::Null => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Null))
+            }
+            Token::True => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Boolean(true)))
+            }
+            Token::False => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Boolean(false)))
+            }
+            Token::LParen => {
+                self.pos += 1;
+                let expr = if self.at_query(0) {
+                    Expr::ScalarSubquery(Box::new(self.select()?))
+                } else {
+                    let first = self.expr()?;
+                    if self.eat(&Token::Comma) {
+                        let mut items = vec![first];
+                        items.extend(self.comma_list(Self::expr)?);
+                        Expr::Tuple(items)
+                    } else {
+                        first
+                    }
+                };
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            }
+            Token::Case => self.case_expr(),
+            Token::Ident(name) => {
+                if self.peek_at(1) == Some(&Token::LParen) {
+                    if name.eq_ignore_ascii_case("exists") {
+                        self.pos += 2;
+                        let subquery = self.select()?;
+                        self.expect(&Token::RParen)?;
+                        return Ok(Expr::Exists(Box::new(subquery)));
+                    }
+                    if name.eq_ignore_ascii_case("cast") {
+                        return self.cast_expr();
+                    }
+                    if name.eq_ignore_ascii_case("trim") {
+                        let (pos, depth) = (self.pos, self.depth);
+                        if let Ok(Some(call)) = self.trim_from() {
+                            return Ok(call);
+                        }
+                        (self.pos, self.depth) = (pos, depth);
+                    }
+                    return self.function_call(name);
+                }
+                self.pos += 1;
+                if self.eat(&Token::Dot) {
+                    let column = self.ident()?;
+                    return Ok(Expr::Column(Some(name), column));
+                }
+                match CurrentTime::from_name(&name) {
+                    Some(kind) => Ok(Expr::CurrentTime(kind)),
+                    None => Ok(Expr::Column(None, name)),
+                }
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// A function call. Calls of the [`AggFunc`]s with a single argument (or
+    /// `COUNT(*)`) are aggregates, as are those of `GROUP_CONCAT` with two,
+    /// which may take `DISTINCT` and an `ORDER BY` after the arguments;
+    /// `MIN` and `MAX` with more arguments are ordinary functions. A
+    /// constant in that `ORDER BY` sorts nothing, and is taken for an
+    /// argument written after it, as in `GROUP_CONCAT(x ORDER BY x, ';')`.
+    fn function_call(&mut self, name: String) -> Result<Expr> {
+        self.pos += 2; // name and '('
+        let func = AggFunc::from_name(&name);
+        if func == Some(AggFunc::Count) && self.eat(&Token::Star) {
+            self.expect(&Token::RParen)?;
+            let call = self.filter(Expr::Aggregate {
+                func: AggFunc::Count,
+                distinct: false,
+                args: Vec::new(),
+                order_by: Vec::new(),
+                filter: None,
+            })?;
+            return self.over(call);
+        }
+        let distinct = func.is_some() && self.eat(&Token::Distinct);
+        let mut args = if self.check(&Token::RParen) {
+            Vec::new()
+        } else {
+            self.comma_list(Self::expr)?
+        };
+        let mut order_by = if func.is_some() && self.eat(&Token::Order) {
+            self.expect(&Token::By)?;
+            self.comma_list(Self::order_by_item)?
+        } else {
+            Vec::new()
+        };
+        let constants = order_by
+            .iter()
+            .skip(1)
+            .rev()
+            .take_while(|item| {
+                matches!(item.expr, Expr::Literal(_)) && !item.desc && item.nulls_first.is_none()
+            })
+            .count();
+        args.extend(
+            order_by
+                .drain(order_by.len() - constants..)
+                .map(|item| item.expr),
+        );
+        self.expect(&Token::RParen)?;
+        let max_args = if func == Some(AggFunc::GroupConcat) {
+            2
+        } else {
+            1
+        };
+        let call = match func {
+            Some(func) if (1..=max_args).contains(&args.len()) => Expr::Aggregate {
+                func,
+                distinct,
+                args,
+                order_by,
+                filter: None,
+            },
+            Some(AggFunc::Min | AggFunc::Max)
+                if args.len() > 1 && !distinct && order_by.is_empty() =>
+            {
+                Expr::FunctionCall { name, args }
+            }
+            Some(_) => {
+                return Err(self.error(format!("wrong number of arguments to function {name}()")));
+            }
+            None if args.is_empty() && name.eq_ignore_ascii_case("coalesce") => {
+                return Err(self.error(format!("wrong number of arguments to function {name}()")));
+            }
+            None => Expr::FunctionCall { name, args },
+        };
+        let call = self.filter(call)?;
+        self.over(call)
+    }
+
+    /// A function call, with a following `FILTER (WHERE <expr>)` attached
+    /// if it is an aggregate.
+    fn filter(&mut self, call: Expr) -> Result<Expr> {
+        if !(self.check_word("FILTER") && self.peek_at(1) == Some(&Token::LParen)) {
+            return Ok(call);
+        }
+        let Expr::Aggregate {
+            func,
+            distinct,
+            args,
+            order_by,
+            ..
+        } = call
+        else {
+            return Err(self.error("FILTER may not be used with non-aggregate functions"));
+        };
+        self.pos += 2; // FILTER and '('
+        self.expect(&Token::Where)?;
+        let filter = self.expr()?;
+        self.expect(&Token::RParen)?;
+        Ok(Expr::Aggregate {
+            func,
+            distinct,
+            args,
+            order_by,
+            filter: Some(Box::new(filter)),
+        })
+    }
+
+    /// A function call, made a window function call by a following
+    /// `OVER (<window>)`.
+    fn over(&mut self, call: Expr) -> Result<Expr> {
+        if !(self.check_word("OVER") && self.peek_at(1) == Some(&Token::LParen)) {
+            return Ok(call);
+        }
+        self.pos += 2; // OVER and '('
+        let (name, args, filter) = match call {
+            Expr::Aggregate { distinct: true, .. } => {
+                return Err(self.error("DISTINCT is not supported for window functions"));
+            }
+            Expr::Aggregate { order_by, .. } if !order_by.is_empty() => {
+                return Err(self.error("ORDER BY is not supported for window functions"));
+            }
+            Expr::Aggregate {
+                func, args, filter, ..
+            } => (func.name().to_string(), args, filter),
+            Expr::FunctionCall { name, args } => (name, args, None),
+            _ => unreachable!("not a function call: {call}"),
+        };
+        let mut window = WindowSpec::default();
+        if self.eat_word("PARTITION") {
+            self.expect(&Token::By)?;
+            window.partition_by = self.comma_list(Self::expr)?;
+        }
+        if self.eat(&Token::Order) {
+            self.expect(&Token::By)?;
+            window.order_by = self.comma_list(Self::order_by_item)?;
+        }
+        let mode = if self.eat_word("ROWS") {
+            Some(FrameMode::Rows)
+        } else if self.eat_word("RANGE") {
+            Some(FrameMode::Range)
+        } else {
+            None
+        };
+        if let Some(mode) = mode {
+            window.frame = Some(self.window_frame(mode)?);
+        }
+        self.expect(&Token::RParen)?;
+        Ok(Expr::Window {
+            name,
+            args,
+            filter,
+            window: Box::new(window),
+        })
+    }
+
+    /// The bounds of a frame, after `ROWS` or `RANGE`. A frame may not
+    /// start after its end's kind of bound: not at UNBOUNDED FOLLOWING, and
+    /// not at a following row if it ends at the current row or before it.
+    fn window_frame(&mut self, mode: FrameMode) -> Result<WindowFrame> {
+        let (start, end) = if self.eat(&Token::Between) {
+            let start = self.frame_bound()?;
+            self.expect(&Token::And)?;
+            (start, self.frame_bound()?)
+        } else {
+            (self.frame_bound()?, FrameBound::CurrentRow)
+        };
+        let invalid = matches!(
+            (start, end),
+            (FrameBound::UnboundedFollowing, _)
+                | (_, FrameBound::UnboundedPreceding)
+                | (FrameBound::CurrentRow, FrameBound::Preceding(_))
+                | (
+                    FrameBound::Following(_),
+                    FrameBound::Preceding(_) | FrameBound::CurrentRow
+                )
+        );
+        if invalid {
+            return Err(self.error("unsupported frame specification"));
+        }
+        Ok(WindowFrame { mode, start, end })
+    }
+
+    /// `UNBOUNDED {PRECEDING | FOLLOWING}`, `<n> {PRECEDING | FOLLOWING}` or
+    /// `CURRENT ROW`, where `n` is a non-negative integer.
+    fn frame_bound(&mut self) -> Result<FrameBound> {
+        if self.eat_word("CURRENT") {
+            self.expect_word("ROW")?;
+            return Ok(FrameBound::CurrentRow);
+        }
+        let offset = match self.peek() {
+            Some(Token::IntLiteral(n)) => {
+                let n = u64::try_from(*n).map_err(|_| self.unexpected())?;
+                self.pos += 1;
+                Some(n)
+            }
+            _ => {
+                self.expect_word("UNBOUNDED")?;
+                None
+            }
+        };
+        if self.eat_word("PRECEDING") {
+            return Ok(offset.map_or(FrameBound::UnboundedPreceding, FrameBound::Preceding));
+        }
+        self.expect_word("FOLLOWING")?;
+        Ok(offset.map_or(FrameBound::UnboundedFollowing, FrameBound::Following))
+    }
+
+    /// `CASE [<operand>] WHEN ... END`. With an operand, each WHEN value
+    /// becomes the condition `<operand> = <value>`, which a NULL operand or
+    /// value does not satisfy.
+    fn case_expr(&mut self) -> Result<Expr> {
+        self.expect(&Token::Case)?;
+        let operand = if self.check(&Token::When) {
+            None
+        } else {
+            Some(self.expr()?)
+        };
+        let mut when_clauses = Vec::new();
+        while self.eat(&Token::When) {
+            let mut when = self.expr()?;
+            if let Some(operand) = &operand {
+                when = Expr::BinOp {
+                    op: BinOp::Eq,
+                    left: Box::new(operand.clone()),
+                    right: Box::new(when),
+                };
+            }
+            self.expect(&Token::Then)?;
+            let then = self.expr()?;
+            when_clauses.push((when, then));
+        }
+        if when_clauses.is_empty() {
+            return Err(self.unexpected());
+        }
+        let else_expr = if self.eat(&Token::Else) {
+            Some(Box::new(self.expr()?))
+        } else {
+            None
+        };
+        self.expect(&Token::End)?;
+        Ok(Expr::Case {
+            when_clauses,
+            else_expr,
+        })
+    }
+
+    fn cast_expr(&mut self) -> Result<Expr> {
+        self.pos += 2; // CAST and '('
+        let expr = self.expr()?;
+        self.expect(&Token::As)?;
+        let ty = self.type_name()?;
+        self.expect(&Token::RParen)?;
+        Ok(Expr::Cast {
+            expr: Box::new(expr),
+            ty,
+        })
+    }
+
+    /// The standard form of TRIM, `TRIM([LEADING | TRAILING | BOTH] [chars]
+    /// FROM s)`, as the call of `LTRIM`, `RTRIM` or `TRIM` it means. Without
+    /// `chars` it strips what one-argument `TRIM` does, whitespace. `None`
+    /// if there turns out to be no FROM, as in `TRIM(s, chars)`.
+    fn trim_from(&mut self) -> Result<Option<Expr>> {
+        self.pos += 2; // TRIM and '('
+        let name = if self.eat_word("LEADING") {
+            "LTRIM"
+        } else if self.eat_word("TRAILING") {
+            "RTRIM"
+        } else {
+            self.eat_word("BOTH");
+            "TRIM"
+        };
+        let chars = if self.eat(&Token::From) {
+            None
+        } else {
+            let chars = self.expr()?;
+            if !self.eat(&Token::From) {
+                return Ok(None);
+            }
+            Some(chars)
+        };
+        let mut args = vec![self.expr()?];
+        args.extend(chars);
+        self.expect(&Token::RParen)?;
+        Ok(Some(Expr::FunctionCall {
+            name: name.to_string(),
+            args,
+        }))
+    }
+
+    /// A type name such as `INTEGER`, `DOUBLE PRECISION` or `VARCHAR(30)`.
+    fn type_name(&mut self) -> Result<String> {
+        let mut name = self.ident()?;
+        while let Some(Token::Ident(word)) = self.peek()
+            && !self.at_constraint()
+        {
+            name.push(' ');
+            name.push_str(word);
+            self.pos += 1;
+        }
+        if self.eat(&Token::LParen) {
+            let mut params = Vec::new();
+            loop {
+                let negative = self.eat(&Token::Minus);
+                match self.advance() {
+                    Some(Token::IntLiteral(n)) => {
+                        params.push(if negative { -n } else { n }.to_string())
+                    }
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.unexpected());
+                    }
+                }
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(&Token::RParen)?;
+            name = format!("{name}({})", params.join(","));
+        }
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn col(name: &str) -> Expr {
+        Expr::Column(None, name.to_string())
+    }
+
+    fn int(n: i64) -> Expr {
+        Expr::Literal(Value::Integer(n))
+    }
+
+    fn bin(op: BinOp, left: Expr, right: Expr) -> Expr {
+        Parser::binary(op, left, right)
+    }
+
+    #[test]
+    fn test_arithmetic_precedence() {
+        assert_eq!(
+            parse_expr("1 + 2 * 3").unwrap(),
+            bin(BinOp::Add, int(1), bin(BinOp::Mul, int(2), int(3)))
+        );
+        assert_eq!(
+            parse_expr("1 - 2 - 3").unwrap(),
+            bin(BinOp::Sub, bin(BinOp::Sub, int(1), int(2)), int(3))
+        );
+        assert_eq!(
+            parse_expr("(1 + 2) * 3").unwrap(),
+            bin(BinOp::Mul, bin(BinOp::Add, int(1), int(2)), int(3))
+        );
+    }
+
+    #[test]
+    fn test_boolean_precedence() {
+        // OR < AND < NOT < comparison
+        assert_eq!(
+            parse_expr("a OR b AND NOT c = 1").unwrap(),
+            bin(
+                BinOp::Or,
+                col("a"),
+                bin(
+                    BinOp::And,
+                    col("b"),
+                    Expr::UnaryOp {
+                        op: UnaryOp::Not,
+                        expr: Box::new(bin(BinOp::Eq, col("c"), int(1))),
+                    }
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn test_comparison_binds_looser_than_arithmetic() {
+        assert_eq!(
+            parse_expr("a + 1 < b * 2").unwrap(),
+            bin(
+                BinOp::Lt,
+                bin(BinOp::Add, col("a"), int(1)),
+                bin(BinOp::Mul, col("b"), int(2))
+            )
+        );
+    }
+
+    #[test]
+    fn test_unary_minus_binds_tightest() {
+        assert_eq!(
+            parse_expr("-a * b").unwrap(),
+            bin(
+                BinOp::Mul,
+                Expr::UnaryOp {
+                    op: UnaryOp::Neg,
+                    expr: Box::new(col("a")),
+                },
+                col("b")
+            )
+        );
+    }
+
+    #[test]
+    fn test_between_does_not_swallow_and() {
+        assert_eq!(
+            parse_expr("x BETWEEN 1 AND 2 AND y").unwrap(),
+            bin(
+                BinOp::And,
+                Expr::Between {
+                    expr: Box::new(col("x")),
+                    low: Box::new(int(1)),
+                    high: Box::new(int(2)),
+                    negated: false,
+                },
+                col("y")
+            )
+        );
+    }
+
+    #[test]
+    fn test_qualified_column() {
+        assert_eq!(
+            parse_expr("t.id").unwrap(),
+            Expr::Column(Some("t".into()), "id".into())
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors_report_token_index() {
+        match parse_expr("1 + * 2") {
+            Err(ParseError::Syntax { token_index, .. }) => assert_eq!(token_index, 2),
+            other => panic!("expected syntax error, got {other:?}"),
+        }
+        match parse_expr("(1 + 2") {
+            Err(ParseError::Syntax { token_index, .. }) => assert_eq!(token_index, 4),
+            other => panic!("expected syntax error, got {other:?}"),
+        }
+        match parse_expr("1 2") {
+            Err(ParseError::Syntax {
+                token_index,
+                message,
+            }) => {
+                assert_eq!(token_index, 1);
+                assert_eq!(message, "near \"2\": syntax error");
+            }
+            other => panic!("expected syntax error, got {other:?}"),
+        }
+        assert!(matches!(parse_expr("'open"), Err(ParseError::Lex(_))));
+        assert!(parse_expr("CASE END").is_err());
+    }
+
+    /// Parse, print, and check both the printed form and that it re-parses
+    /// to the same tree.
+    fn round_trip(sql: &str) {
+        let expr = parse_expr(sql).unwrap_or_else(|e| panic!("{sql}: {e}"));
+        let printed = expr.to_string();
+        assert_eq!(printed, sql);
+        // Compare structurally: `Value`'s `PartialEq` follows SQL semantics,
+        // under which NULL literals never compare equal.
+        let reparsed = parse_expr(&printed).unwrap();
+        assert_eq!(format!("{reparsed:?}"), format!("{expr:?}"), "{sql}");
+    }
+
+    #[test]
+    fn test_round_trip_every_variant() {
+        // Literal
+        round_trip("42");
+        round_trip("1.5");
+        round_trip("'it''s'");
+        round_trip("NULL");
+        round_trip("TRUE");
+        // Column
+        round_trip("a");
+        round_trip("t.a");
+        round_trip("\"select\"");
+        // CurrentTime
+        round_trip("CURRENT_TIMESTAMP");
+        round_trip("CURRENT_DATE || ' ' || CURRENT_TIME");
+        // BinOp
+        round_trip("1 + 2 * 3");
+        round_trip("(1 + 2) * 3");
+        round_trip("1 - (2 - 3)");
+        round_trip("a = 1 AND (b = 2 OR c = 3)");
+        round_trip("'a' || 'b'");
+        round_trip("a LIKE 'x%'");
+        // UnaryOp
+        round_trip("-a");
+        round_trip("-(-1)");
+        round_trip("NOT a = b");
+        round_trip("NOT (a AND b)");
+        // FunctionCall
+        round_trip("abs(-3)");
+        round_trip("coalesce(a, b, 0)");
+        round_trip("random()");
+        // Aggregate
+        round_trip("COUNT(*)");
+        round_trip("SUM(DISTINCT a + 1)");
+        round_trip("max(a, b)");
+        for sql in ["count()", "sum(a, b)", "avg(DISTINCT a, b)"] {
+            assert!(parse_expr(sql).is_err(), "{sql}");
+        }
+        assert!(parse_expr("abs(DISTINCT a)").is_err());
+        // IsNull
+        round_trip("a IS NULL");
+        round_trip("a + 1 IS NOT NULL");
+        // Between
+        round_trip("a BETWEEN 1 AND 10");
+        round_trip("a NOT BETWEEN b - 1 AND b + 1");
+        // InList
+        round_trip("a IN (1, 2, 3)");
+        round_trip("a NOT IN ('x')");
+        // InSubquery
+        round_trip("a IN (SELECT b FROM t)");
+        round_trip("a NOT IN (SELECT b FROM t WHERE c = 1)");
+        round_trip("(a, b + 1) IN ((1, 2), (3, 4))");
+        round_trip("(a, b) NOT IN (SELECT c, d FROM t)");
+        // QuantifiedComparison
+        round_trip("a > ANY (SELECT b FROM t)");
+        round_trip("a + 1 <= ALL (SELECT b FROM t) AND c");
+        // Exists
+        round_trip("EXISTS (SELECT * FROM t WHERE t.a = u.a)");
+        round_trip("NOT EXISTS (SELECT 1)");
+        // ScalarSubquery
+        round_trip("(SELECT MAX(a) FROM t) + 1");
+        // Case
+        round_trip("CASE WHEN a > 1 THEN 'big' ELSE 'small' END");
+        // Cast
+        round_trip("CAST(a AS INTEGER)");
+        round_trip("CAST('1.5' AS VARCHAR(10))");
+        // Common table expressions, in subqueries too
+        round_trip("(WITH c AS (SELECT 1 AS x) SELECT x FROM c)");
+        round_trip("a IN (WITH c AS (SELECT b FROM t) SELECT * FROM c)");
+    }
+
+    #[test]
+    fn test_expression_depth_limit() {
+        // A test thread's stack is too small for the recursion a debug
+        // build does at the limit; the REPL runs on a larger one.
+        std::thread::Builder::new()
+            .stack_size(256 << 20)
+            .spawn(expression_depth_limit)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    fn expression_depth_limit() {
+        let too_deep = "Expression tree is too large (maximum depth 1000)";
+        let fails = |sql: String| {
+            assert_eq!(parse_statement(&sql).unwrap_err().to_string(), too_deep);
+        };
+        fails(format!("SELECT {}1{}", "(".repeat(5000), ")".repeat(5000)));
+        fails(format!("SELECT 1{}", " + 1".repeat(50_000)));
+        fails(format!("SELECT {}1", "NOT ".repeat(50_000)));
+        fails(format!("SELECT {}1", "- ".repeat(50_000)));
+        fails(format!("SELECT 1{}", " = 1".repeat(50_000)));
+        fails(format!("SELECT 'a'{}", " COLLATE NOCASE".repeat(50_000)));
+        fails(format!("SELECT 1{}", " UNION SELECT 1".repeat(50_000)));
+        fails(format!(
+            "SELECT * FROM {}t{}",
+            "(SELECT * FROM ".repeat(5000),
+            ") AS s".repeat(5000)
+        ));
+        // Below the limit, depth is no trouble, and runs of operators
+        // side by side do not add up.
+        let nested = format!("{}1{}", "(".repeat(400), ")".repeat(400));
+        assert_eq!(parse_expr(&nested).unwrap().to_string(), "1");
+        parse_expr(&format!("1{}", " + 1".repeat(900))).unwrap();
+        let runs = vec![format!("(1{})", " + 1".repeat(900)); 10];
+        parse_expr(&runs.join(" * ")).unwrap();
+        parse_statement(&format!("SELECT 1{}", " UNION SELECT 1".repeat(900))).unwrap();
+    }
+
+    #[test]
+    fn test_parse_trim_from() {
+        let parses_as = |sql: &str, call: &str| {
+            assert_eq!(parse_expr(sql).unwrap().to_string(), call, "{sql}");
+        };
+        parses_as("TRIM(LEADING 'x' FROM s)", "LTRIM(s, 'x')");
+        parses_as("trim(trailing FROM s)", "RTRIM(s)");
+        parses_as("TRIM(BOTH 'ab' || c FROM s)", "TRIM(s, 'ab' || c)");
+        parses_as("TRIM('x' FROM s)", "TRIM(s, 'x')");
+        parses_as("TRIM(FROM s || t)", "TRIM(s || t)");
+        // The SQLite forms, and columns named like the keywords, are calls
+        // as written.
+        parses_as("TRIM(s, 'x')", "TRIM(s, 'x')");
+        parses_as("TRIM(leading)", "TRIM(leading)");
+        parses_as("TRIM(both || 'x', trailing)", "TRIM(both || 'x', trailing)");
+        assert!(parse_expr("TRIM(LEADING 'x' FROM)").is_err());
+        assert!(parse_expr("TRIM(LEADING 'x' FROM s, 'y')").is_err());
+    }
+
+    #[test]
+    fn test_parse_with() {
+        let sql = "WITH a AS (SELECT 1 AS x), \"b c\" AS (SELECT x FROM a) SELECT * FROM \"b c\"";
+        let stmt = parse_statement(sql).unwrap();
+        let Statement::Select(select) = &stmt else {
+            panic!("expected SELECT, got {stmt:?}");
+        };
+        let with = select.with.as_ref().unwrap();
+        assert_eq!(with.ctes.len(), 2);
+        assert_eq!(with.ctes[1].name, "b c");
+        assert_eq!(stmt.to_string(), sql);
+        assert!(!with.recursive);
+        assert!(parse_statement("WITH a (SELECT 1) SELECT 1").is_err());
+        let sql =
+            "WITH RECURSIVE r (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r) SELECT n FROM r";
+        let stmt = parse_statement(sql).unwrap();
+        assert_eq!(stmt.to_string(), sql);
+        let Statement::Select(select) = stmt else {
+            unreachable!()
+        };
+        let with = select.with.unwrap();
+        assert!(with.recursive);
+        assert_eq!(with.ctes[0].columns, Some(vec!["n".to_string()]));
+        assert!(parse_statement("WITH a AS (SELECT 1)").is_err());
+        // Names are compared as identifiers are, ignoring case.
+        for (sql, name) in [
+            ("WITH a AS (SELECT 1), a AS (SELECT 2) SELECT * FROM a", "a"),
+            (
+                "WITH a AS (SELECT 1), b AS (SELECT 2), A AS (SELECT 3) SELECT * FROM b",
+                "A",
+            ),
+        ] {
+            let err = parse_statement(sql).unwrap_err().to_string();
+            assert!(
+                err.contains(&format!("duplicate WITH table name: {name}")),
+                "{sql}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_aggregate_filter() {
+        let Expr::Aggregate { filter, .. } = parse_expr("count(*) filter (where a > 1)").unwrap()
+        else {
+            panic!("expected an aggregate call");
+        };
+        assert_eq!(filter, Some(Box::new(bin(BinOp::Gt, col("a"), int(1)))));
+        round_trip("COUNT(*) FILTER (WHERE a > 1)");
+        round_trip("SUM(DISTINCT x) FILTER (WHERE y AND z)");
+        round_trip("SUM(x) FILTER (WHERE y) OVER (PARTITION BY z)");
+        assert!(parse_expr("ABS(x) FILTER (WHERE y)").is_err());
+        assert!(parse_expr("SUM(x) FILTER (y)").is_err());
+        assert!(parse_expr("SUM(x) FILTER (WHERE y").is_err());
+    }
+
+    #[test]
+    fn test_parse_group_concat() {
+        let Expr::Aggregate {
+            func,
+            args,
+            order_by,
+            ..
+        } = parse_expr("string_agg(name, '; ' ORDER BY hired DESC)").unwrap()
+        else {
+            panic!("expected an aggregate call");
+        };
+        assert_eq!(func, AggFunc::GroupConcat);
+        assert_eq!(args.len(), 2);
+        assert_eq!(order_by.len(), 1);
+        assert!(order_by[0].desc);
+        round_trip("GROUP_CONCAT(x)");
+        round_trip("GROUP_CONCAT(DISTINCT x, ',' ORDER BY y, z DESC) FILTER (WHERE y)");
+        round_trip("GROUP_CONCAT(x, '-') OVER (PARTITION BY y)");
+        assert!(parse_expr("GROUP_CONCAT()").is_err());
+        assert!(parse_expr("GROUP_CONCAT(x, ',', y)").is_err());
+        assert!(parse_expr("GROUP_CONCAT(x ORDER BY y) OVER ()").is_err());
+        assert!(parse_expr("ABS(x ORDER BY y)").is_err());
+        // A constant after the sort keys is the separator.
+        assert_eq!(
+            parse_expr("GROUP_CONCAT(name ORDER BY hire_date ASC, ', ')").unwrap(),
+            parse_expr("GROUP_CONCAT(name, ', ' ORDER BY hire_date ASC)").unwrap()
+        );
+        assert_eq!(
+            parse_expr("GROUP_CONCAT(x ORDER BY y, z DESC, ';')").unwrap(),
+            parse_expr("GROUP_CONCAT(x, ';' ORDER BY y, z DESC)").unwrap()
+        );
+        assert_eq!(
+            parse_expr("GROUP_CONCAT(x ORDER BY 1)")
+                .unwrap()
+                .to_string(),
+            "GROUP_CONCAT(x ORDER BY 1)"
+        );
+        assert!(parse_expr("GROUP_CONCAT(x, ',' ORDER BY y, ';')").is_err());
+    }
+
+    #[test]
+    fn test_parse_window_functions() {
+        let Expr::Window {
+            name, args, window, ..
+        } = parse_expr("avg(salary) OVER (PARTITION BY dept, team ORDER BY hired DESC)").unwrap()
+        else {
+            panic!("expected a window function call");
+        };
+        assert_eq!(name, "AVG");
+        assert_eq!(args, [col("salary")]);
+        assert_eq!(window.partition_by, [col("dept"), col("team")]);
+        assert_eq!(window.order_by.len(), 1);
+        assert!(window.order_by[0].desc);
+        round_trip("COUNT(*) OVER ()");
+        round_trip("SUM(x) OVER (ORDER BY y, z DESC NULLS FIRST)");
+        round_trip("MAX(a + 1) OVER (PARTITION BY b) - MIN(a) OVER (PARTITION BY b)");
+        round_trip("ROW_NUMBER() OVER (PARTITION BY b ORDER BY a)");
+        round_trip("SUM(x) OVER (ORDER BY y ROWS BETWEEN 2 PRECEDING AND 1 FOLLOWING)");
+        round_trip("COUNT(*) OVER (RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW)");
+        round_trip("AVG(x) OVER (PARTITION BY z ROWS BETWEEN CURRENT ROW AND UNBOUNDED FOLLOWING)");
+        // A single bound starts a frame that ends at the current row.
+        assert_eq!(
+            parse_expr("SUM(x) OVER (ORDER BY y rows 3 preceding)")
+                .unwrap()
+                .to_string(),
+            "SUM(x) OVER (ORDER BY y ROWS BETWEEN 3 PRECEDING AND CURRENT ROW)"
+        );
+        for frame in [
+            "ROWS BETWEEN UNBOUNDED FOLLOWING AND UNBOUNDED FOLLOWING",
+            "ROWS BETWEEN CURRENT ROW AND UNBOUNDED PRECEDING",
+            "ROWS BETWEEN 1 FOLLOWING AND CURRENT ROW",
+            "RANGE BETWEEN CURRENT ROW AND 1 PRECEDING",
+            "ROWS BETWEEN 1 PRECEDING",
+            "ROWS -1 PRECEDING",
+            "ROWS x PRECEDING",
+            "ROWS CURRENT",
+        ] {
+            assert!(
+                parse_expr(&format!("SUM(x) OVER ({frame})")).is_err(),
+                "{frame}"
+            );
+        }
+        assert!(parse_expr("COUNT(DISTINCT x) OVER ()").is_err());
+        assert!(parse_expr("SUM(x) OVER (PARTITION x)").is_err());
+        assert!(parse_expr("SUM(x) OVER (ORDER BY x").is_err());
+        // Without a window, OVER is an ordinary name.
+        assert_eq!(
+            parse_statement("SELECT SUM(x) over FROM t")
+                .unwrap()
+                .to_string(),
+            "SELECT SUM(x) AS over FROM t"
+        );
+    }
+
+    #[test]
+    fn test_simple_case_desugars_to_comparisons() {
+        let expr =
+            parse_expr("CASE a + 1 WHEN 1 THEN 'one' WHEN b THEN 'b' ELSE 'other' END").unwrap();
+        let Expr::Case { when_clauses, .. } = &expr else {
+            panic!("expected CASE, got {expr:?}");
+        };
+        assert_eq!(
+            when_clauses[1].0,
+            bin(BinOp::Eq, bin(BinOp::Add, col("a"), int(1)), col("b"))
+        );
+        assert_eq!(
+            expr.to_string(),
+            "CASE WHEN a + 1 = 1 THEN 'one' WHEN a + 1 = b THEN 'b' ELSE 'other' END"
+        );
+        assert!(parse_expr("CASE a END").is_err());
+    }
+
+    #[test]
+    fn test_not_like_desugars_to_not() {
+        assert_eq!(
+            parse_expr("a NOT LIKE 'x'").unwrap(),
+            Expr::UnaryOp {
+                op: UnaryOp::Not,
+                expr: Box::new(Expr::Like {
+                    expr: Box::new(col("a")),
+                    pattern: Box::new(Expr::Literal(Value::Text("x".into()))),
+                    escape: None,
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_glob() {
+        assert_eq!(
+            parse_expr("a GLOB 'x*' AND b NOT GLOB '[0-9]' || c")
+                .unwrap()
+                .to_string(),
+            "a GLOB 'x*' AND NOT b GLOB '[0-9]' || c"
+        );
+    }
+
+    #[test]
+    fn test_parse_like_escape_and_collate() {
+        let expr = parse_expr("a COLLATE nocase LIKE 'x!%' || b ESCAPE '!'").unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "a COLLATE NOCASE LIKE 'x!%' || b ESCAPE '!'"
+        );
+        assert!(matches!(
+            parse_expr("-a COLLATE binary").unwrap(),
+            Expr::Collate {
+                collation: Collation::Binary,
+                ..
+            }
+        ));
+        assert_eq!(
+            parse_expr("a COLLATE klingon").unwrap_err().to_string(),
+            "no such collation sequence: klingon"
+        );
+        let Statement::CreateTable(create) =
+            parse_statement("CREATE TABLE t (s TEXT DEFAULT 'x' COLLATE NOCASE NOT NULL)").unwrap()
+        else {
+            panic!("expected CREATE TABLE");
+        };
+        assert_eq!(create.columns[0].collation, Collation::NoCase);
+        assert_eq!(
+            create.columns[0].to_string(),
+            "s TEXT COLLATE NOCASE NOT NULL DEFAULT 'x'"
+        );
+    }
+
+    #[test]
+    fn test_parse_select_statement() {
+        let stmt = parse_statement("SELECT a, t.*, b + 1 AS c FROM t WHERE a > 0;").unwrap();
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT a, t.*, b + 1 AS c FROM t WHERE a > 0"
+        );
+        assert_eq!(
+            parse_statement("SELECT DISTINCT a FROM t")
+                .unwrap()
+                .to_string(),
+            "SELECT DISTINCT a FROM t"
+        );
+        assert_eq!(
+            parse_statement("SELECT ALL a FROM t").unwrap().to_string(),
+            "SELECT a FROM t"
+        );
+        assert_eq!(
+            parse_statement(
+                "SELECT a, COUNT(*) FROM t WHERE b group by a, b + 1 having count(*) > 1 ORDER BY 2"
+            )
+            .unwrap()
+            .to_string(),
+            "SELECT a, COUNT(*) FROM t WHERE b GROUP BY a, b + 1 HAVING COUNT(*) > 1 ORDER BY 2"
+        );
+        assert!(parse_statement("SELECT a FROM t GROUP a").is_err());
+        assert!(parse_statement("SELECT").is_err());
+        assert!(parse_statement("SELECT 1 extra tokens").is_err());
+    }
+
+    #[test]
+    fn test_parse_set_operations() {
+        let stmt = parse_statement(
+            "SELECT a FROM t UNION SELECT b FROM u INTERSECT SELECT c FROM v EXCEPT SELECT 1",
+        )
+        .unwrap();
+        let Statement::Select(select) = &stmt else {
+            panic!("expected SELECT");
+        };
+        // INTERSECT binds tightest; UNION and EXCEPT associate left.
+        let QueryBody::SetOperation { op, left, .. } = &select.body else {
+            panic!("expected a set operation");
+        };
+        assert_eq!(*op, SetOperator::Except);
+        let QueryBody::SetOperation { op, right, .. } = left.as_ref() else {
+            panic!("expected a set operation");
+        };
+        assert_eq!(*op, SetOperator::Union);
+        assert!(matches!(
+            right.as_ref(),
+            QueryBody::SetOperation {
+                op: SetOperator::Intersect,
+                ..
+            }
+        ));
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT a FROM t UNION SELECT b FROM u INTERSECT SELECT c FROM v EXCEPT SELECT 1"
+        );
+        assert_eq!(
+            parse_statement("SELECT a FROM t UNION ALL SELECT a FROM u ORDER BY 1 LIMIT 2")
+                .unwrap()
+                .to_string(),
+            "SELECT a FROM t UNION ALL SELECT a FROM u ORDER BY 1 LIMIT 2"
+        );
+        assert!(parse_statement("SELECT a FROM t ORDER BY a UNION SELECT a FROM u").is_err());
+        assert!(parse_statement("SELECT a FROM t UNION").is_err());
+        assert!(parse_statement("SELECT a FROM t INTERSECT ALL SELECT a FROM u").is_err());
+    }
+
+    #[test]
+    fn test_parse_create_table() {
+        let stmt = parse_statement(
+            "CREATE TABLE foo (id INTEGER NOT NULL, name TEXT DEFAULT 'anon', score REAL)",
+        )
+        .unwrap();
+        let Statement::CreateTable(create) = &stmt else {
+            panic!("expected CREATE TABLE, got {stmt:?}");
+        };
+        assert_eq!(create.name, "foo");
+        assert!(!create.if_not_exists);
+        let [id, name, score] = &create.columns[..] else {
+            panic!("expected three columns");
+        };
+        assert_eq!((id.data_type, id.nullable), (Some(DataType::Int), false));
+        assert_eq!(name.data_type, Some(DataType::Text));
+        assert_eq!(
+            name.default,
+            Some(Expr::Literal(Value::Text("anon".into())))
+        );
+        assert_eq!(
+            (score.data_type, score.nullable),
+            (Some(DataType::Real), true)
+        );
+        assert_eq!(
+            stmt.to_string(),
+            "CREATE TABLE foo (id INTEGER NOT NULL, name TEXT DEFAULT 'anon', score REAL)"
+        );
+    }
+
+    #[test]
+    fn test_parse_check_constraints() {
+        let stmt = parse_statement(
+            "CREATE TABLE p (price REAL CHECK (price > 0) NOT NULL, \
+             qty INT CONSTRAINT small CHECK (qty < 10) CHECK (qty >= 0), \
+             CONSTRAINT cheap CHECK (price * qty < 100), CHECK (qty <> 5))",
+        )
+        .unwrap();
+        let Statement::CreateTable(create) = &stmt else {
+            panic!("expected CREATE TABLE, got {stmt:?}");
+        };
+        let [price, qty] = &create.columns[..] else {
+            panic!("expected two columns");
+        };
+        assert!(!price.nullable);
+        assert_eq!(
+            price.checks,
+            [CheckConstraint {
+                name: None,
+                expr: bin(BinOp::Gt, col("price"), int(0)),
+            }]
+        );
+        assert_eq!(qty.checks.len(), 2);
+        assert_eq!(qty.checks[0].name.as_deref(), Some("small"));
+        assert_eq!(qty.checks[1].name, None);
+        let [
+            TableConstraint::Check(CheckConstraint {
+                name: Some(cheap), ..
+            }),
+            TableConstraint::Check(CheckConstraint { name: None, .. }),
+        ] = &create.constraints[..]
+        else {
+            panic!("expected two table checks, got {:?}", create.constraints);
+        };
+        assert_eq!(cheap, "cheap");
+        assert_eq!(
+            stmt.to_string(),
+            "CREATE TABLE p (price REAL NOT NULL CHECK (price > 0), \
+             qty INTEGER CONSTRAINT small CHECK (qty < 10) CHECK (qty >= 0), \
+             CONSTRAINT cheap CHECK (price * qty < 100), CHECK (qty <> 5))"
+        );
+        // Other constraints may be named, but do not keep the name.
+        assert_eq!(
+            parse_statement("CREATE TABLE t (a INT CONSTRAINT pk PRIMARY KEY)")
+                .unwrap()
+                .to_string(),
+            "CREATE TABLE t (a INTEGER NOT NULL PRIMARY KEY)"
+        );
+        for (sql, display) in [
+            (
+                "CREATE TABLE t (id INTEGER PRIMARY KEY AUTOINCREMENT)",
+                "CREATE TABLE t (id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT)",
+            ),
+            (
+                "CREATE TABLE t (n bigserial)",
+                "CREATE TABLE t (n SERIAL NOT NULL)",
+            ),
+            (
+                "CREATE TABLE t (a INT, b INT GENERATED ALWAYS AS (a + 1) STORED, c AS (a))",
+                "CREATE TABLE t (a INTEGER, b INTEGER GENERATED ALWAYS AS (a + 1) STORED, \
+                 c GENERATED ALWAYS AS (a) VIRTUAL)",
+            ),
+        ] {
+            let stmt = parse_statement(sql).unwrap();
+            assert_eq!(stmt.to_string(), display);
+            assert_eq!(parse_statement(display).unwrap(), stmt);
+        }
+        assert!(parse_statement("CREATE TABLE t (a INT AUTOINCREMENT)").is_err());
+        assert!(parse_statement("CREATE TABLE t (a CHECK a > 0)").is_err());
+        assert!(parse_statement("CREATE TABLE t (a CONSTRAINT c)").is_err());
+        assert!(parse_statement("CREATE TABLE t (a, CHECK ())").is_err());
+    }
+
+    #[test]
+    fn test_parse_table_constraints() {
+        let stmt =
+            parse_statement("CREATE TABLE t (a INT, b INT UNIQUE, CONSTRAINT ab unique (a, b))")
+                .unwrap();
+        let Statement::CreateTable(create) = &stmt else {
+            panic!("expected CREATE TABLE, got {stmt:?}");
+        };
+        assert!(create.columns[1].unique);
+        assert_eq!(
+            create.constraints,
+            [TableConstraint::Unique(vec!["a".into(), "b".into()])]
+        );
+        assert_eq!(
+            stmt.to_string(),
+            "CREATE TABLE t (a INTEGER, b INTEGER UNIQUE, UNIQUE (a, b))"
+        );
+        assert!(parse_statement("CREATE TABLE t (UNIQUE (a), a INT)").is_err());
+        assert!(parse_statement("CREATE TABLE t (a INT, UNIQUE (a), b INT)").is_err());
+        assert!(parse_statement("CREATE TABLE t (a INT, UNIQUE a)").is_err());
+        assert_eq!(
+            parse_statement("CREATE TABLE t (a INT, b INT, PRIMARY KEY (b, a))")
+                .unwrap()
+                .to_string(),
+            "CREATE TABLE t (a INTEGER, b INTEGER, PRIMARY KEY (b, a))"
+        );
+        for sql in [
+            "CREATE TABLE t (a INT PRIMARY KEY, b INT PRIMARY KEY)",
+            "CREATE TABLE t (a INT PRIMARY KEY, b INT, PRIMARY KEY (a, b))",
+            "CREATE TABLE t (a INT, PRIMARY KEY (a), PRIMARY KEY (a))",
+        ] {
+            assert_eq!(
+                parse_statement(sql).unwrap_err().to_string(),
+                r#"table "t" has more than one primary key"#
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_foreign_keys() {
+        let stmt = parse_statement(
+            "CREATE TABLE orders (id INT, customer_id INT REFERENCES customers(id) \
+             ON DELETE CASCADE, note TEXT REFERENCES notes, \
+             FOREIGN KEY (id, note) REFERENCES other (a, b) ON DELETE SET NULL)",
+        )
+        .unwrap();
+        let Statement::CreateTable(create) = &stmt else {
+            panic!("expected CREATE TABLE, got {stmt:?}");
+        };
+        assert_eq!(
+            create.columns[1].references,
+            Some(References {
+                table: "customers".into(),
+                columns: vec!["id".into()],
+                on_delete: FkAction::Cascade,
+            })
+        );
+        assert_eq!(
+            stmt.to_string(),
+            "CREATE TABLE orders (id INTEGER, \
+             customer_id INTEGER REFERENCES customers (id) ON DELETE CASCADE, \
+             note TEXT REFERENCES notes, \
+             FOREIGN KEY (id, note) REFERENCES other (a, b) ON DELETE SET NULL)"
+        );
+        for (action, expected) in [
+            ("SET DEFAULT", FkAction::SetDefault),
+            ("RESTRICT", FkAction::Restrict),
+            ("NO ACTION", FkAction::NoAction),
+        ] {
+            let sql = format!("CREATE TABLE t (a INT REFERENCES p ON DELETE {action})");
+            let Statement::CreateTable(create) = parse_statement(&sql).unwrap() else {
+                unreachable!();
+            };
+            assert_eq!(
+                create.columns[0].references.as_ref().unwrap().on_delete,
+                expected
+            );
+        }
+        assert!(parse_statement("CREATE TABLE t (a INT REFERENCES p ON DELETE SET)").is_err());
+        assert!(parse_statement("CREATE TABLE t (a INT, FOREIGN KEY a REFERENCES p)").is_err());
+    }
+
+    #[test]
+    fn test_parse_drop_table() {
+        let stmt = parse_statement("drop table if exists t").unwrap();
+        assert_eq!(
+            stmt,
+            Statement::DropTable(DropTableStmt {
+                name: "t".into(),
+                if_exists: true,
+            })
+        );
+        assert_eq!(stmt.to_string(), "DROP TABLE IF EXISTS t");
+        assert_eq!(
+            parse_statement("DROP TABLE t").unwrap().to_string(),
+            "DROP TABLE t"
+        );
+        assert!(parse_statement("DROP t").is_err());
+        assert!(parse_statement("DROP TABLE IF t").is_err());
+    }
+
+    #[test]
+    fn test_parse_views() {
+        let stmt =
+            parse_statement("create view if not exists v as select a from t where b").unwrap();
+        let Statement::CreateView(create) = &stmt else {
+            panic!("expected CREATE VIEW");
+        };
+        assert_eq!(create.name, "v");
+        assert!(create.if_not_exists && !create.or_replace);
+        assert_eq!(
+            stmt.to_string(),
+            "CREATE VIEW IF NOT EXISTS v AS SELECT a FROM t WHERE b"
+        );
+        assert_eq!(
+            parse_statement("CREATE OR REPLACE VIEW v AS WITH c AS (SELECT 1) SELECT * FROM c")
+                .unwrap()
+                .to_string(),
+            "CREATE OR REPLACE VIEW v AS WITH c AS (SELECT 1) SELECT * FROM c"
+        );
+        assert_eq!(
+            parse_statement("DROP VIEW IF EXISTS v").unwrap(),
+            Statement::DropView(DropViewStmt {
+                name: "v".into(),
+                if_exists: true,
+            })
+        );
+        assert_eq!(
+            parse_statement("DROP VIEW v").unwrap().to_string(),
+            "DROP VIEW v"
+        );
+        assert!(parse_statement("CREATE VIEW v SELECT 1").is_err());
+        assert!(parse_statement("CREATE OR VIEW v AS SELECT 1").is_err());
+        assert!(parse_statement("CREATE VIEW v AS DELETE FROM t").is_err());
+    }
+
+    #[test]
+    fn test_parse_indexes() {
+        let stmt = parse_statement("create unique index if not exists i on t (a, b)").unwrap();
+        assert_eq!(
+            stmt,
+            Statement::CreateIndex(CreateIndexStmt {
+                name: "i".into(),
+                table: "t".into(),
+                columns: vec!["a".into(), "b".into()],
+                unique: true,
+                if_not_exists: true,
+            })
+        );
+        assert_eq!(
+            stmt.to_string(),
+            "CREATE UNIQUE INDEX IF NOT EXISTS i ON t (a, b)"
+        );
+        assert_eq!(
+            parse_statement("CREATE INDEX i ON t (a)")
+                .unwrap()
+                .to_string(),
+            "CREATE INDEX i ON t (a)"
+        );
+        let stmt = parse_statement("DROP INDEX IF EXISTS i").unwrap();
+        assert_eq!(
+            stmt,
+            Statement::DropIndex(DropIndexStmt {
+                name: "i".into(),
+                if_exists: true,
+            })
+        );
+        assert_eq!(
+            parse_statement("DROP INDEX i").unwrap().to_string(),
+            "DROP INDEX i"
+        );
+        assert!(parse_statement("CREATE INDEX i ON t").is_err());
+        assert!(parse_statement("CREATE INDEX i t (a)").is_err());
+        assert!(parse_statement("CREATE UNIQUE TABLE t (a)").is_err());
+    }
+
+    #[test]
+    fn test_parse_alter_table() {
+        let stmt = parse_statement("ALTER TABLE t ADD COLUMN c TEXT NOT NULL DEFAULT 'x'").unwrap();
+        let Statement::AlterTable(AlterTableStmt::AddColumn { table, column_def }) = &stmt else {
+            panic!("expected ALTER TABLE, got {stmt:?}");
+        };
+        assert_eq!(table, "t");
+        assert_eq!(column_def.name, "c");
+        assert!(!column_def.nullable);
+        assert_eq!(
+            stmt.to_string(),
+            "ALTER TABLE t ADD COLUMN c TEXT NOT NULL DEFAULT 'x'"
+        );
+        assert_eq!(
+            parse_statement("alter table t add c").unwrap().to_string(),
+            "ALTER TABLE t ADD COLUMN c"
+        );
+        assert_eq!(
+            parse_statement("ALTER TABLE t DROP COLUMN IF EXISTS c").unwrap(),
+            Statement::AlterTable(AlterTableStmt::DropColumn {
+                table: "t".into(),
+                column_name: "c".into(),
+                if_exists: true,
+            })
+        );
+        assert_eq!(
+            parse_statement("alter table t drop c").unwrap().to_string(),
+            "ALTER TABLE t DROP COLUMN c"
+        );
+        assert!(parse_statement("ALTER TABLE t DROP").is_err());
+        assert!(parse_statement("ALTER TABLE t ADD").is_err());
+        assert!(parse_statement("ALTER t ADD COLUMN c").is_err());
+    }
+
+    #[test]
+    fn test_parse_truncate() {
+        for sql in ["TRUNCATE TABLE t", "truncate t"] {
+            let stmt = parse_statement(sql).unwrap();
+            assert_eq!(stmt, Statement::Truncate(TruncateStmt { name: "t".into() }));
+            assert_eq!(stmt.to_string(), "TRUNCATE TABLE t");
+        }
+        assert!(parse_statement("TRUNCATE TABLE").is_err());
+    }
+
+    #[test]
+    fn test_parse_transactions() {
+        for (sql, stmt) in [
+            ("BEGIN", Statement::Begin),
+            ("begin transaction", Statement::Begin),
+            ("COMMIT", Statement::Commit),
+            ("COMMIT TRANSACTION", Statement::Commit),
+            ("END", Statement::Commit),
+            ("END TRANSACTION;", Statement::Commit),
+            ("ROLLBACK", Statement::Rollback),
+            ("ROLLBACK TRANSACTION", Statement::Rollback),
+        ] {
+            assert_eq!(parse_statement(sql).unwrap(), stmt);
+        }
+        assert_eq!(Statement::Begin.to_string(), "BEGIN TRANSACTION");
+        assert!(parse_statement("BEGIN t").is_err());
+        assert!(parse_statement("COMMIT WORK").is_err());
+    }
+
+    #[test]
+    fn test_parse_savepoints() {
+        for (sql, stmt, canonical) in [
+            (
+                "SAVEPOINT Sp1",
+                Statement::Savepoint("Sp1".into()),
+                "SAVEPOINT Sp1",
+            ),
+            (
+                "RELEASE SAVEPOINT sp",
+                Statement::Release("sp".into()),
+                "RELEASE SAVEPOINT sp",
+            ),
+            (
+                "release sp",
+                Statement::Release("sp".into()),
+                "RELEASE SAVEPOINT sp",
+            ),
+            (
+                "ROLLBACK TO SAVEPOINT sp",
+                Statement::RollbackTo("sp".into()),
+                "ROLLBACK TO SAVEPOINT sp",
+            ),
+            (
+                "ROLLBACK TRANSACTION TO sp",
+                Statement::RollbackTo("sp".into()),
+                "ROLLBACK TO SAVEPOINT sp",
+            ),
+        ] {
+            let parsed = parse_statement(sql).unwrap();
+            assert_eq!(parsed, stmt);
+            assert_eq!(parsed.to_string(), canonical);
+        }
+        assert!(parse_statement("SAVEPOINT").is_err());
+        assert!(parse_statement("ROLLBACK TO").is_err());
+        assert!(parse_statement("RELEASE SAVEPOINT").is_err());
+    }
+
+    #[test]
+    fn test_parse_explain() {
+        let stmt = parse_statement("explain SELECT a FROM t WHERE b = 1").unwrap();
+        let Statement::Explain(select) = &stmt else {
+            panic!("expected EXPLAIN");
+        };
+        assert_eq!(
+            Statement::Select(select.clone()),
+            parse_statement("SELECT a FROM t WHERE b = 1").unwrap()
+        );
+        assert_eq!(stmt.to_string(), "EXPLAIN SELECT a FROM t WHERE b = 1");
+        assert!(parse_statement("EXPLAIN").is_err());
+        assert!(parse_statement("EXPLAIN DELETE FROM t").is_err());
+    }
+
+    #[test]
+    fn test_parse_analyze() {
+        for (sql, stmt, canonical) in [
+            ("ANALYZE", Statement::Analyze(None), "ANALYZE"),
+            (
+                "analyze Employees",
+                Statement::Analyze(Some("Employees".into())),
+                "ANALYZE Employees",
+            ),
+        ] {
+            let parsed = parse_statement(sql).unwrap();
+            assert_eq!(parsed, stmt);
+            assert_eq!(parsed.to_string(), canonical);
+        }
+        assert!(parse_statement("ANALYZE a b").is_err());
+    }
+
+    #[test]
+    fn test_parse_pragma() {
+        let pragma = |name: &str, arg: Option<&str>| {
+            Statement::Pragma(PragmaStmt {
+                name: name.into(),
+                arg: arg.map(Into::into),
+            })
+        };
+        for (sql, stmt, canonical) in [
+            (
+                "PRAGMA table_list",
+                pragma("table_list", None),
+                "PRAGMA table_list",
+            ),
+            (
+                "pragma table_info('My Table')",
+                pragma("table_info", Some("My Table")),
+                "PRAGMA table_info(\"My Table\")",
+            ),
+            (
+                "PRAGMA index_list(users)",
+                pragma("index_list", Some("users")),
+                "PRAGMA index_list(users)",
+            ),
+            (
+                "PRAGMA cache_size = 100",
+                pragma("cache_size", Some("100")),
+                "PRAGMA cache_size(\"100\")",
+            ),
+        ] {
+            let parsed = parse_statement(sql).unwrap();
+            assert_eq!(parsed, stmt);
+            assert_eq!(parsed.to_string(), canonical);
+        }
+        assert!(parse_statement("PRAGMA").is_err());
+        assert!(parse_statement("PRAGMA table_info(").is_err());
+    }
+
+    #[test]
+    fn test_parse_column_types() {
+        let stmt = parse_statement(
+            "CREATE TABLE t (a INT, b BIGINT, c VARCHAR(20), d DOUBLE PRECISION, \
+             e DECIMAL(10, 2), f BLOB, g BOOLEAN, h, i DATETIME)",
+        )
+        .unwrap();
+        let Statement::CreateTable(create) = stmt else {
+            panic!("expected CREATE TABLE");
+        };
+        let types: Vec<_> = create.columns.iter().map(|c| c.data_type).collect();
+        assert_eq!(
+            types,
+            [
+                Some(DataType::Int),
+                Some(DataType::Int),
+                Some(DataType::Text),
+                Some(DataType::Real),
+                Some(DataType::Real),
+                Some(DataType::Blob),
+                Some(DataType::Boolean),
+                None,
+                Some(DataType::Text),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_column_constraints() {
+        let stmt = parse_statement(
+            "CREATE TABLE IF NOT EXISTS t (id INTEGER PRIMARY KEY, \
+             code TEXT UNIQUE NOT NULL, n INT DEFAULT -1, m DEFAULT (1 + 2))",
+        )
+        .unwrap();
+        let Statement::CreateTable(create) = &stmt else {
+            panic!("expected CREATE TABLE");
+        };
+        assert!(create.if_not_exists);
+        let [id, code, n, m] = &create.columns[..] else {
+            panic!("expected four columns");
+        };
+        assert!(id.primary_key && !id.nullable && !id.unique);
+        assert!(code.unique && !code.nullable && !code.primary_key);
+        assert_eq!(n.default.as_ref().unwrap().to_string(), "-1");
+        assert_eq!(m.data_type, None);
+        assert_eq!(m.default.as_ref().unwrap().to_string(), "1 + 2");
+        let printed = stmt.to_string();
+        assert_eq!(
+            printed,
+            "CREATE TABLE IF NOT EXISTS t (id INTEGER NOT NULL PRIMARY KEY, \
+             code TEXT NOT NULL UNIQUE, n INTEGER DEFAULT -1, m DEFAULT (1 + 2))"
+        );
+        assert_eq!(
+            format!("{:?}", parse_statement(&printed).unwrap()),
+            format!("{stmt:?}")
+        );
+        assert!(parse_statement("CREATE TABLE t ()").is_err());
+        assert!(parse_statement("CREATE TABLE t (a PRIMARY)").is_err());
+        assert!(parse_statement("CREATE TABLE IF EXISTS t (a)").is_err());
+    }
+
+    #[test]
+    fn test_parse_values_in_from() {
+        let Statement::Select(select) =
+            parse_statement("SELECT * FROM (values (1, 'a'), (2, 'b')) t (id, name)").unwrap()
+        else {
+            panic!("expected a SELECT");
+        };
+        let QueryBody::Select(core) = &select.body else {
+            panic!("expected a simple SELECT");
+        };
+        let Some(TableRef::Values {
+            rows,
+            alias,
+            columns,
+        }) = &core.from
+        else {
+            panic!("expected VALUES in FROM");
+        };
+        assert_eq!(rows.len(), 2);
+        assert_eq!(alias.as_deref(), Some("t"));
+        assert_eq!(columns, &["id", "name"]);
+        assert_eq!(
+            select.to_string(),
+            "SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t (id, name)"
+        );
+        assert_eq!(
+            parse_statement("SELECT * FROM (VALUES (1)), (VALUES (2)) AS v")
+                .unwrap()
+                .to_string(),
+            "SELECT * FROM (VALUES (1)) JOIN (VALUES (2)) AS v"
+        );
+        assert!(parse_statement("SELECT * FROM (VALUES) AS t").is_err());
+        assert!(parse_statement("SELECT * FROM (VALUES ()) AS t").is_err());
+        assert!(parse_statement("SELECT * FROM (VALUES (1), (2, 3))").is_err());
+    }
+
+    #[test]
+    fn test_parse_derived_table_columns() {
+        let Statement::Select(select) =
+            parse_statement("SELECT * FROM (SELECT 1, 2) AS t (a, b)").unwrap()
+        else {
+            panic!("expected a SELECT");
+        };
+        let QueryBody::Select(core) = &select.body else {
+            panic!("expected a simple SELECT");
+        };
+        let Some(TableRef::Subquery { alias, columns, .. }) = &core.from else {
+            panic!("expected a subquery in FROM");
+        };
+        assert_eq!(alias.as_deref(), Some("t"));
+        assert_eq!(columns, &["a", "b"]);
+        assert_eq!(
+            select.to_string(),
+            "SELECT * FROM (SELECT 1, 2) AS t (a, b)"
+        );
+        assert!(parse_statement("SELECT * FROM (SELECT 1) AS t ()").is_err());
+    }
+
+    #[test]
+    fn test_parse_insert() {
+        let stmt = parse_statement("INSERT INTO t (a, b) VALUES (1, 'x'), (2 + 3, NULL)").unwrap();
+        let Statement::Insert(insert) = &stmt else {
+            panic!("expected INSERT, got {stmt:?}");
+        };
+        assert_eq!(insert.table, "t");
+        assert_eq!(insert.columns, Some(vec!["a".to_string(), "b".to_string()]));
+        let InsertSource::Values(rows) = &insert.source else {
+            panic!("expected VALUES, got {:?}", insert.source);
+        };
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1][0], bin(BinOp::Add, int(2), int(3)));
+        assert_eq!(
+            stmt.to_string(),
+            "INSERT INTO t (a, b) VALUES (1, 'x'), (2 + 3, NULL)"
+        );
+        assert_eq!(
+            parse_statement("insert into t values (1)")
+                .unwrap()
+                .to_string(),
+            "INSERT INTO t VALUES (1)"
+        );
+        assert!(parse_statement("INSERT INTO t VALUES").is_err());
+        assert!(parse_statement("INSERT INTO t VALUES ()").is_err());
+        assert!(parse_statement("INSERT t VALUES (1)").is_err());
+        let stmt = parse_statement("INSERT INTO a (x) SELECT y FROM b WHERE y > 1").unwrap();
+        let Statement::Insert(insert) = &stmt else {
+            panic!("expected INSERT, got {stmt:?}");
+        };
+        assert!(matches!(insert.source, InsertSource::Query(_)));
+        assert_eq!(
+            stmt.to_string(),
+            "INSERT INTO a (x) SELECT y FROM b WHERE y > 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_insert_conflict_action() {
+        for (sql, conflict, canonical) in [
+            (
+                "INSERT OR REPLACE INTO t VALUES (1)",
+                Some(InsertConflictAction::Replace),
+                "INSERT OR REPLACE INTO t VALUES (1)",
+            ),
+            (
+                "replace into t (a) select 1",
+                Some(InsertConflictAction::Replace),
+                "INSERT OR REPLACE INTO t (a) SELECT 1",
+            ),
+            (
+                "insert or ignore into t values (1)",
+                Some(InsertConflictAction::Ignore),
+                "INSERT OR IGNORE INTO t VALUES (1)",
+            ),
+            ("INSERT INTO t VALUES (1)", None, "INSERT INTO t VALUES (1)"),
+        ] {
+            let stmt = parse_statement(sql).unwrap();
+            let Statement::Insert(insert) = &stmt else {
+                panic!("expected INSERT, got {stmt:?}");
+            };
+            assert_eq!(insert.conflict, conflict);
+            assert_eq!(stmt.to_string(), canonical);
+        }
+        assert!(parse_statement("INSERT OR INTO t VALUES (1)").is_err());
+        assert!(parse_statement("INSERT OR FAIL INTO t VALUES (1)").is_err());
+        assert!(parse_statement("REPLACE t VALUES (1)").is_err());
+    }
+
+    #[test]
+    fn test_parse_update() {
+        let stmt =
+            parse_statement("update t set a = a + 1, b = NULL where a > 0 and b <> 'x'").unwrap();
+        let Statement::Update(update) = &stmt else {
+            panic!("expected UPDATE, got {stmt:?}");
+        };
+        assert_eq!(update.table, "t");
+        assert_eq!(update.assignments[0].0, "a");
+        assert_eq!(update.assignments[0].1, bin(BinOp::Add, col("a"), int(1)));
+        assert_eq!(
+            stmt.to_string(),
+            "UPDATE t SET a = a + 1, b = NULL WHERE a > 0 AND b <> 'x'"
+        );
+        assert_eq!(
+            parse_statement("UPDATE t SET a = 1").unwrap().to_string(),
+            "UPDATE t SET a = 1"
+        );
+        assert!(parse_statement("UPDATE t SET").is_err());
+        assert!(parse_statement("UPDATE t a = 1").is_err());
+        assert!(parse_statement("UPDATE t SET a").is_err());
+    }
+
+    #[test]
+    fn test_parse_delete() {
+        let stmt = parse_statement("delete from t where a = 1 or b is null").unwrap();
+        let Statement::Delete(delete) = &stmt else {
+            panic!("expected DELETE, got {stmt:?}");
+        };
+        assert_eq!(delete.table, "t");
+        assert!(delete.where_clause.is_some());
+        assert_eq!(stmt.to_string(), "DELETE FROM t WHERE a = 1 OR b IS NULL");
+        assert_eq!(
+            parse_statement("DELETE FROM t").unwrap().to_string(),
+            "DELETE FROM t"
+        );
+        assert!(parse_statement("DELETE t").is_err());
+        assert!(parse_statement("DELETE FROM t WHERE").is_err());
+    }
+
+    #[test]
+    fn test_parse_returning() {
+        let stmt = parse_statement("delete from t where a returning *, a + 1 as b").unwrap();
+        let Statement::Delete(delete) = &stmt else {
+            panic!("expected DELETE, got {stmt:?}");
+        };
+        assert_eq!(delete.returning.len(), 2);
+        assert_eq!(
+            stmt.to_string(),
+            "DELETE FROM t WHERE a RETURNING *, a + 1 AS b"
+        );
+        for sql in [
+            "INSERT INTO t VALUES (1) RETURNING t.*",
+            "INSERT INTO t SELECT * FROM u RETURNING a",
+            "INSERT INTO t SELECT a FROM u AS returning RETURNING a",
+            "UPDATE t SET a = 1 WHERE b RETURNING a, b",
+        ] {
+            assert_eq!(parse_statement(sql).unwrap().to_string(), sql);
+        }
+        assert!(parse_statement("DELETE FROM t RETURNING").is_err());
+        assert!(parse_statement("SELECT 1 RETURNING 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_order_by() {
+        let stmt = parse_statement(
+            "SELECT a FROM t ORDER BY a, b DESC, c ASC NULLS FIRST, d DESC NULLS LAST",
+        )
+        .unwrap();
+        let Statement::Select(select) = &stmt else {
+            panic!("expected SELECT");
+        };
+        let keys: Vec<_> = select
+            .order_by
+            .iter()
+            .map(|item| (item.expr.to_string(), item.desc, item.nulls_first))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("a".to_string(), false, None),
+                ("b".to_string(), true, None),
+                ("c".to_string(), false, Some(true)),
+                ("d".to_string(), true, Some(false)),
+            ]
+        );
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT a FROM t ORDER BY a, b DESC, c NULLS FIRST, d DESC NULLS LAST"
+        );
+        assert!(parse_statement("SELECT a FROM t ORDER a").is_err());
+        assert!(parse_statement("SELECT a FROM t ORDER BY a NULLS").is_err());
+    }
+
+    #[test]
+    fn test_parse_limit_offset() {
+        let limit = |sql: &str| match parse_statement(sql).unwrap() {
+            Statement::Select(select) => select.limit.map(|l| {
+                (
+                    l.limit.map(|e| e.to_string()),
+                    l.offset.map(|e| e.to_string()),
+                )
+            }),
+            other => panic!("expected SELECT, got {other:?}"),
+        };
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(limit("SELECT 1"), None);
+        assert_eq!(limit("SELECT 1 LIMIT 10"), Some((some("10"), None)));
+        assert_eq!(
+            limit("SELECT 1 LIMIT 10 OFFSET 20"),
+            Some((some("10"), some("20")))
+        );
+        assert_eq!(
+            limit("SELECT 1 LIMIT 20, 10"),
+            Some((some("10"), some("20")))
+        );
+        assert_eq!(limit("SELECT 1 OFFSET 5"), Some((None, some("5"))));
+        assert_eq!(
+            parse_statement("SELECT a FROM t ORDER BY a LIMIT 2, 3")
+                .unwrap()
+                .to_string(),
+            "SELECT a FROM t ORDER BY a LIMIT 3 OFFSET 2"
+        );
+        assert!(parse_statement("SELECT 1 LIMIT").is_err());
+    }
+
+    #[test]
+    fn test_parse_joins_associate_left() {
+        let stmt = parse_statement(
+            "SELECT * FROM a INNER JOIN b ON a.id = b.a_id JOIN c AS x ON x.id = b.id",
+        )
+        .unwrap();
+        let Statement::Select(select) = &stmt else {
+            panic!("expected SELECT");
+        };
+        let QueryBody::Select(select) = &select.body else {
+            panic!("expected a simple SELECT");
+        };
+        let Some(TableRef::Join { left, right, .. }) = &select.from else {
+            panic!("expected a join");
+        };
+        assert!(matches!(left.as_ref(), TableRef::Join { .. }));
+        assert!(matches!(right.as_ref(), TableRef::Table { name, .. } if name == "c"));
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT * FROM a JOIN b ON a.id = b.a_id JOIN c AS x ON x.id = b.id"
+        );
+        assert_eq!(
+            parse_statement(
+                "SELECT * FROM a LEFT OUTER JOIN b ON 1 RIGHT JOIN c ON 2 FULL OUTER JOIN d ON 3"
+            )
+            .unwrap()
+            .to_string(),
+            "SELECT * FROM a LEFT JOIN b ON 1 RIGHT JOIN c ON 2 FULL JOIN d ON 3"
+        );
+        // A comma is an inner join without a condition.
+        assert_eq!(
+            parse_statement("SELECT * FROM a, b AS x, c")
+                .unwrap()
+                .to_string(),
+            "SELECT * FROM a JOIN b AS x JOIN c"
+        );
+        for sql in [
+            "SELECT * FROM a CROSS JOIN b ON 1",
+            "SELECT * FROM a CROSS JOIN b USING (id)",
+        ] {
+            assert_eq!(
+                parse_statement(sql).unwrap_err().to_string(),
+                "a CROSS JOIN cannot have an ON or USING clause"
+            );
+        }
+        assert_eq!(
+            parse_statement("SELECT * FROM a, b ON 1")
+                .unwrap_err()
+                .to_string(),
+            "a comma join cannot have an ON or USING clause"
+        );
+        assert_eq!(
+            parse_statement("SELECT * FROM a NATURAL JOIN b NATURAL LEFT OUTER JOIN c")
+                .unwrap()
+                .to_string(),
+            "SELECT * FROM a NATURAL JOIN b NATURAL LEFT JOIN c"
+        );
+        assert_eq!(
+            parse_statement("SELECT * FROM a JOIN b USING (id, name)")
+                .unwrap()
+                .to_string(),
+            "SELECT * FROM a JOIN b USING (id, name)"
+        );
+        assert_eq!(
+            parse_statement("SELECT * FROM a NATURAL JOIN b USING (id)")
+                .unwrap_err()
+                .to_string(),
+            "a NATURAL join cannot have an ON or USING clause"
+        );
+        assert!(parse_statement("SELECT * FROM a NATURAL CROSS JOIN b").is_err());
+        assert!(parse_statement("SELECT * FROM a NATURAL, b").is_err());
+        assert!(parse_statement("SELECT * FROM a NATURAL b").is_err());
+        assert!(parse_statement("SELECT * FROM a JOIN b USING ()").is_err());
+        assert_eq!(
+            parse_statement("SELECT d.n FROM (SELECT a AS n FROM t LIMIT 2) d JOIN b ON 1")
+                .unwrap()
+                .to_string(),
+            "SELECT d.n FROM (SELECT a AS n FROM t LIMIT 2) AS d JOIN b ON 1"
+        );
+        assert_eq!(
+            parse_statement("SELECT * FROM (SELECT 1) JOIN (SELECT 2 AS x)")
+                .unwrap()
+                .to_string(),
+            "SELECT * FROM (SELECT 1) JOIN (SELECT 2 AS x)"
+        );
+        assert!(parse_statement("SELECT * FROM a LEFT OUTER b").is_err());
+        assert!(parse_statement("SELECT * FROM a INNER b").is_err());
+        assert!(parse_statement("SELECT * FROM a JOIN b ON").is_err());
+    }
+}
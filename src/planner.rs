@@ -0,0 +1,2653 @@
+//! Query planning.
+//!
+//! A SELECT is turned into a tree of [`PlanNode`]s, each of which knows the
+//! [`Schema`] of the rows it produces. Column references stay symbolic in
+//! the plan and are resolved against the input schema when evaluated, but
+//! planning checks that every reference resolves, so unknown and ambiguous
+//! names are reported before any row is read.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::ast::{
+    AggFunc, BinOp, Cte, DeleteStmt, Expr, FrameBound, FrameMode, JoinConstraint, JoinKind,
+    LimitClause, OrderByItem, QueryBody, SelectCore, SelectItem, SelectStmt, SetOperator,
+    SubqueryKind, TableRef, UpdateStmt, WindowFrame,
+};
+use crate::executor::{
+    EvalError, Evaluator, FunctionRegistry, NtileFunction, OffsetDirection, OffsetRowFunction,
+    RankingFunction, ValueFunction, ValuePosition, ValueSet, WindowFunction,
+};
+use crate::storage::{ColumnDef, Database, DbError, Row, Table};
+use crate::types::{Collation, DataType, Value, coerce};
+
+mod cost;
+mod explain;
+mod join_algorithm;
+mod join_order;
+mod pushdown;
+
+pub use cost::{Cost, estimate_cost};
+pub use explain::explain;
+pub use pushdown::pushdown_predicates;
+
+/// One output column of a plan node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaColumn {
+    /// The table (or alias) the column can be qualified with, if any.
+    pub table: Option<String>,
+    pub name: String,
+    /// Reachable only through a qualified name: the second copy of a column
+    /// a join matched with `USING` or `NATURAL`.
+    pub hidden: bool,
+    /// How the column's text compares: declared for a table column, and
+    /// inherited by output columns that read one.
+    pub collation: Collation,
+}
+
+/// The shape of the rows produced by a plan node.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    pub columns: Vec<SchemaColumn>,
+}
+
+impl Schema {
+    /// Find the column `[table.]name` refers to. Names are matched
+    /// case-insensitively; a name matching more than one column is an
+    /// error, as is one matching none. Hidden columns only match qualified
+    /// names.
+    pub fn resolve(&self, table: Option<&str>, name: &str) -> Result<usize, EvalError> {
+        let mut matches = self.columns.iter().enumerate().filter(|(_, c)| {
+            c.name.eq_ignore_ascii_case(name)
+                && match table {
+                    Some(t) => c
+                        .table
+                        .as_deref()
+                        .is_some_and(|ct| ct.eq_ignore_ascii_case(t)),
+                    None => !c.hidden,
+                }
+        });
+        match (matches.next(), matches.next()) {
+            (Some((i, _)), None) => Ok(i),
+            (Some(_), Some(_)) => Err(EvalError::AmbiguousColumn(qualified(table, name))),
+            (None, _) => Err(EvalError::NoSuchColumn(qualified(table, name))),
+        }
+    }
+
+    /// The collation an expression's value carries: one given with
+    /// `COLLATE`, or else that of the column it reads.
+    pub fn collation_of(&self, expr: &Expr) -> Option<Collation> {
+        match expr {
+            Expr::Collate { collation, .. } => Some(*collation),
+            Expr::Column(table, name) => self
+                .resolve(table.as_deref(), name)
+                .ok()
+                .map(|i| self.columns[i].collation),
+            _ => None,
+        }
+    }
+
+    /// The collation comparing `left` with `right` uses: one given with
+    /// `COLLATE`, the left operand's first, and otherwise that of a column
+    /// operand, again the left one's first. BINARY if there is neither.
+    pub fn comparison_collation(&self, left: &Expr, right: &Expr) -> Collation {
+        let explicit = |e: &Expr| match e {
+            Expr::Collate { collation, .. } => Some(*collation),
+            _ => None,
+        };
+        explicit(left)
+            .or_else(|| explicit(right))
+            .or_else(|| self.collation_of(left))
+            .or_else(|| self.collation_of(right))
+            .unwrap_or_default()
+    }
+}
+
+fn qualified(table: Option<&str>, name: &str) -> String {
+    match table {
+        Some(table) => format!("{table}.{name}"),
+        None => name.to_string(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PlanNode {
+    /// A single row with no columns, the input of a SELECT without FROM.
+    SingleRow,
+    /// Rows given inline by a VALUES list, evaluated when scanned.
+    Values {
+        rows: Vec<Vec<Expr>>,
+        schema: Schema,
+    },
+    /// Every row of a stored table.
+    TableScan { table: String, schema: Schema },
+    /// The rows of a stored table whose indexed columns equal `key`, found
+    /// through the named index, in table order.
+    IndexScan {
+        table: String,
+        index: String,
+        key: Vec<Expr>,
+        schema: Schema,
+    },
+    /// The rows of a common table expression, with its columns qualified
+    /// by the name it is read under. Every scan of it shares its rows.
+    CteScan {
+        cte: Arc<CommonTable>,
+        schema: Schema,
+    },
+    /// In the recursive step of a common table expression, the rows the
+    /// previous step produced.
+    WorkingTableScan {
+        table: Arc<WorkingTable>,
+        schema: Schema,
+    },
+    /// The rows of a derived table, with its columns qualified by the
+    /// derived table's alias.
+    SubqueryScan {
+        input: Box<PlanNode>,
+        schema: Schema,
+    },
+    /// Every pairing of a left row with a right row for which `predicate`
+    /// holds (all pairings if there is none), plus the unmatched rows that
+    /// `kind` preserves. The output row is the left row followed by the
+    /// right row.
+    NestedLoopJoin {
+        left: Box<PlanNode>,
+        right: Box<PlanNode>,
+        kind: JoinKind,
+        predicate: Option<Expr>,
+        schema: Schema,
+    },
+    /// A join whose condition equates columns of one input with columns
+    /// of the other, found through a hash table.
+    HashJoin(HashJoinNode),
+    /// A join whose condition equates columns of one input with columns
+    /// of the other, found by merging the inputs sorted on those columns.
+    SortMergeJoin(SortMergeJoinNode),
+    /// A join whose condition equates the columns of an index of a stored
+    /// table with values from the rows of the other input, found by
+    /// looking each row's values up in the index.
+    IndexNestedLoopJoin(IndexNestedLoopJoinNode),
+    /// The input rows for which `predicate` holds.
+    Filter {
+        input: Box<PlanNode>,
+        predicate: Expr,
+    },
+    /// The input rows, sorted stably by `keys`.
+    Sort {
+        input: Box<PlanNode>,
+        keys: Vec<SortKey>,
+    },
+    /// Evaluate one expression per output column against each input row.
+    Projection {
+        input: Box<PlanNode>,
+        exprs: Vec<Expr>,
+        schema: Schema,
+    },
+    /// One row per group of input rows with equal `group_by` values (a
+    /// single row for the whole input if there are none): the group's key
+    /// values followed by the result of each call.
+    Aggregate {
+        input: Box<PlanNode>,
+        group_by: Vec<Expr>,
+        calls: Vec<AggregateCall>,
+        schema: Schema,
+    },
+    /// The input rows, each followed by the value of every call for it.
+    /// A call is computed over the row's partition: the input rows with
+    /// the same `partition_by` values, sorted by `order_by`.
+    Window {
+        input: Box<PlanNode>,
+        calls: Vec<WindowCall>,
+        schema: Schema,
+    },
+    /// The input rows with duplicates removed, keeping first occurrences.
+    Distinct { input: Box<PlanNode> },
+    /// A single row with a single column: whether the input produces any
+    /// row. No more than its first row is read.
+    Exists {
+        input: Box<PlanNode>,
+        schema: Schema,
+    },
+    /// The rows of `left` combined with those of `right`, which has as
+    /// many columns. All but UNION ALL remove duplicates, keeping first
+    /// occurrences.
+    SetOperation {
+        op: SetOperator,
+        left: Box<PlanNode>,
+        right: Box<PlanNode>,
+    },
+    /// Skip `offset` input rows, then pass on at most `limit` rows.
+    Limit {
+        input: Box<PlanNode>,
+        limit: Option<usize>,
+        offset: usize,
+    },
+}
+
+/// One ORDER BY term, with NULL placement and collation made explicit.
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    pub expr: Expr,
+    pub desc: bool,
+    pub nulls_first: bool,
+    /// How texts order; that of the term's value, as
+    /// [`Schema::comparison_collation`] finds it for an operand.
+    pub collation: Collation,
+}
+
+/// One aggregate function call computed by an aggregation.
+#[derive(Debug, Clone)]
+pub struct AggregateCall {
+    pub func: AggFunc,
+    pub distinct: bool,
+    /// `None` for `COUNT(*)`.
+    pub arg: Option<Expr>,
+    /// What `GROUP_CONCAT` puts between values, evaluated when the call is
+    /// planned; `None` for the other functions.
+    pub delimiter: Option<String>,
+    /// The order `GROUP_CONCAT` concatenates in; the input order if empty.
+    pub order_by: Vec<SortKey>,
+    /// Only the rows this holds for are aggregated.
+    pub filter: Option<Expr>,
+}
+
+/// A join that reads one input, the build side, into a hash table on its
+/// key, then streams the other, the probe side, looking up the build rows
+/// whose key equals that of each probe row. A key with a NULL matches
+/// nothing. The rows are those a nested loop join with the same condition
+/// produces: output rows are the left row followed by the right row, and
+/// unmatched rows `kind` preserves are padded with NULLs.
+///
+/// Rows come in probe order, with each probe row's matches in build order;
+/// unmatched build rows follow once the probe side is exhausted.
+#[derive(Debug, Clone)]
+pub struct HashJoinNode {
+    pub build: Box<PlanNode>,
+    pub probe: Box<PlanNode>,
+    /// Evaluated against build rows.
+    pub build_key: Vec<Expr>,
+    /// Evaluated against probe rows; as long as `build_key`.
+    pub probe_key: Vec<Expr>,
+    /// How the texts of each part of the key compare.
+    pub collations: Vec<Collation>,
+    /// Whether the build side is the left input.
+    pub build_left: bool,
+    pub kind: JoinKind,
+    /// The rest of the join condition, checked for each pair of rows with
+    /// equal keys.
+    pub residual: Option<Expr>,
+    pub schema: Schema,
+}
+
+impl HashJoinNode {
+    /// The (left, right) inputs.
+    pub fn sides(&self) -> (&PlanNode, &PlanNode) {
+        if self.build_left {
+            (&self.build, &self.probe)
+        } else {
+            (&self.probe, &self.build)
+        }
+    }
+
+    /// The (left, right) keys.
+    pub fn keys(&self) -> (&[Expr], &[Expr]) {
+        if self.build_left {
+            (&self.build_key, &self.probe_key)
+        } else {
+            (&self.probe_key, &self.build_key)
+        }
+    }
+
+    /// The whole join condition: the key equalities, then the residual.
+    pub fn predicate(&self) -> Expr {
+        let (left, right) = self.keys();
+        join_predicate(left, right, self.residual.as_ref())
+    }
+}
+
+/// A join that reads both inputs, each sorted ascending on its key, and
+/// walks them together, pairing the rows whose keys are equal. A key with
+/// a NULL matches nothing, so those rows may be anywhere in their input;
+/// preserved, they come first. Output rows are the left row followed by
+/// the right row, and unmatched rows `kind` preserves are padded with
+/// NULLs.
+///
+/// Rows come in key order; rows with equal keys come as a nested loop
+/// join over just those rows produces them. An unmatched row appears
+/// where its key falls.
+#[derive(Debug, Clone)]
+pub struct SortMergeJoinNode {
+    pub left: Box<PlanNode>,
+    pub right: Box<PlanNode>,
+    /// Evaluated against left rows.
+    pub left_key: Vec<Expr>,
+    /// Evaluated against right rows; as long as `left_key`.
+    pub right_key: Vec<Expr>,
+    /// How the texts of each part of the key compare, and are sorted.
+    pub collations: Vec<Collation>,
+    pub kind: JoinKind,
+    /// The rest of the join condition, checked for each pair of rows with
+    /// equal keys.
+    pub residual: Option<Expr>,
+    pub schema: Schema,
+}
+
+impl SortMergeJoinNode {
+    /// The whole join condition: the key equalities, then the residual.
+    pub fn predicate(&self) -> Expr {
+        join_predicate(&self.left_key, &self.right_key, self.residual.as_ref())
+    }
+}
+
+/// A join that streams one input, the outer side, and finds the rows of
+/// the other, a stored table, that match each outer row through an
+/// index of the table: the rows whose indexed columns equal the outer
+/// row's `outer_key`. A key with a NULL matches nothing. Unmatched outer rows
+/// are padded with NULLs if `kind` preserves them; the table's never
+/// are. Output rows are the left row followed by the right row.
+///
+/// Rows come in outer order, with each outer row's matches in table
+/// order.
+#[derive(Debug, Clone)]
+pub struct IndexNestedLoopJoinNode {
+    pub outer: Box<PlanNode>,
+    pub inner_table: String,
+    pub inner_index: String,
+    /// The columns of the table, as its scan would name them.
+    pub inner_schema: Schema,
+    /// Which of the table's rows take part in the join, evaluated
+    /// against them.
+    pub inner_filter: Option<Expr>,
+    /// The indexed columns, evaluated against the table's rows.
+    pub inner_key: Vec<Expr>,
+    /// Evaluated against outer rows, one value per indexed column.
+    pub outer_key: Vec<Expr>,
+    /// Whether the table is the left input.
+    pub inner_left: bool,
+    pub kind: JoinKind,
+    /// The rest of the join condition, checked for each pair of rows the
+    /// index finds.
+    pub residual: Option<Expr>,
+    pub schema: Schema,
+}
+
+impl IndexNestedLoopJoinNode {
+    /// The rows of the table that take part: those a scan of it finds,
+    /// filtered by `inner_filter`.
+    pub fn inner(&self) -> PlanNode {
+        let scan = PlanNode::TableScan {
+            table: self.inner_table.clone(),
+            schema: self.inner_schema.clone(),
+        };
+        match &self.inner_filter {
+            Some(predicate) => PlanNode::Filter {
+                input: Box::new(scan),
+                predicate: predicate.clone(),
+            },
+            None => scan,
+        }
+    }
+
+    /// The whole join condition: the key equalities, then the residual.
+    pub fn predicate(&self) -> Expr {
+        let (left, right) = if self.inner_left {
+            (&self.inner_key, &self.outer_key)
+        } else {
+            (&self.outer_key, &self.inner_key)
+        };
+        join_predicate(left, right, self.residual.as_ref())
+    }
+}
+
+/// The equalities of the parts of two keys, then `residual`, ANDed.
+fn join_predicate(left: &[Expr], right: &[Expr], residual: Option<&Expr>) -> Expr {
+    left.iter()
+        .zip(right)
+        .map(|(l, r)| Expr::BinOp {
+            op: BinOp::Eq,
+            left: Box::new(l.clone()),
+            right: Box::new(r.clone()),
+        })
+        .chain(residual.cloned())
+        .reduce(|left, right| Expr::BinOp {
+            op: BinOp::And,
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+        .expect("an equi-join has a key")
+}
+
+/// One window function call computed by a window node.
+#[derive(Debug, Clone)]
+pub struct WindowCall {
+    pub function: WindowFunction,
+    pub partition_by: Vec<Expr>,
+    pub order_by: Vec<SortKey>,
+    /// As given, or the function's default.
+    pub frame: WindowFrame,
+}
+
+/// A common table expression of a WITH clause, planned once for all of
+/// the scans that read it. Its rows are computed when first read, and
+/// kept until the statement's plan is dropped.
+#[derive(Debug)]
+pub struct CommonTable {
+    pub name: String,
+    /// The table's columns, unqualified.
+    pub schema: Schema,
+    /// The rows of the table or, if it is recursive, of its base case.
+    pub plan: PlanNode,
+    pub step: Option<RecursiveStep>,
+    rows: OnceLock<Result<Vec<Row>, EvalError>>,
+}
+
+/// The recursive case of a common table expression: run on the rows of
+/// its base case, then again on each run's rows until one produces none,
+/// or until the table has as many rows as its LIMIT and OFFSET take. The
+/// table's rows are those of all the runs, less those the LIMIT and
+/// OFFSET leave out.
+#[derive(Debug)]
+pub struct RecursiveStep {
+    pub plan: PlanNode,
+    /// Where `plan` reads the rows of the previous run from.
+    pub working: Arc<WorkingTable>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+/// The rows a recursive step reads, replaced before each run of it.
+#[derive(Debug)]
+pub struct WorkingTable {
+    pub name: String,
+    pub rows: Mutex<Vec<Row>>,
+}
+
+impl CommonTable {
+    /// The rows of the table, computed by `run` on first use.
+    pub fn cached_rows(
+        &self,
+        run: impl FnOnce(&CommonTable) -> Result<Vec<Row>, EvalError>,
+    ) -> Result<&[Row], EvalError> {
+        match self.rows.get_or_init(|| run(self)) {
+            Ok(rows) => Ok(rows),
+            Err(e) => Err(e.clone()),
+        }
+    }
+}
+
+/// A subquery planned for evaluation inside an expression. Unless it is
+/// correlated, its result is the same for every row it is evaluated for,
+/// so its rows (or, for `IN`, its set of values) are computed once, when
+/// first needed, and kept.
+#[derive(Clone)]
+pub struct SubqueryPlan {
+    /// The subquery as written, for printing.
+    pub query: Box<SelectStmt>,
+    pub plan: Arc<PlanNode>,
+    /// Whether the subquery refers to the rows of enclosing queries.
+    pub correlated: bool,
+    rows: Arc<OnceLock<Result<Vec<Row>, EvalError>>>,
+    values: Arc<OnceLock<Result<ValueSet, EvalError>>>,
+}
+
+impl SubqueryPlan {
+    fn new(query: SelectStmt, plan: PlanNode, correlated: bool) -> Self {
+        SubqueryPlan {
+            query: Box::new(query),
+            plan: Arc::new(plan),
+            correlated,
+            rows: Arc::default(),
+            values: Arc::default(),
+        }
+    }
+
+    /// The rows of an uncorrelated subquery, computed by `run` on first
+    /// use.
+    pub fn cached_rows(
+        &self,
+        run: impl FnOnce(&PlanNode) -> Result<Vec<Row>, EvalError>,
+    ) -> Result<&[Row], EvalError> {
+        debug_assert!(!self.correlated, "correlated subqueries are rerun");
+        match self.rows.get_or_init(|| run(&self.plan)) {
+            Ok(rows) => Ok(rows),
+            Err(e) => Err(e.clone()),
+        }
+    }
+
+    /// The values of an uncorrelated `IN` subquery, computed by `run` on
+    /// first use.
+    pub fn cached_values(
+        &self,
+        run: impl FnOnce(&PlanNode) -> Result<ValueSet, EvalError>,
+    ) -> Result<&ValueSet, EvalError> {
+        debug_assert!(!self.correlated, "correlated subqueries are rerun");
+        match self.values.get_or_init(|| run(&self.plan)) {
+            Ok(values) => Ok(values),
+            Err(e) => Err(e.clone()),
+        }
+    }
+}
+
+impl fmt::Debug for SubqueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SubqueryPlan").field(&self.query).finish()
+    }
+}
+
+/// Plans are equal only to themselves; equal queries may still be planned
+/// apart.
+impl PartialEq for SubqueryPlan {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.plan, &other.plan)
+    }
+}
+
+impl PlanNode {
+    pub fn schema(&self) -> &Schema {
+        static EMPTY: Schema = Schema {
+            columns: Vec::new(),
+        };
+        match self {
+            PlanNode::SingleRow => &EMPTY,
+            PlanNode::Filter { input, .. }
+            | PlanNode::Sort { input, .. }
+            | PlanNode::Distinct { input }
+            | PlanNode::Limit { input, .. }
+            | PlanNode::SetOperation { left: input, .. } => input.schema(),
+            PlanNode::Values { schema, .. }
+            | PlanNode::TableScan { schema, .. }
+            | PlanNode::IndexScan { schema, .. }
+            | PlanNode::CteScan { schema, .. }
+            | PlanNode::WorkingTableScan { schema, .. }
+            | PlanNode::SubqueryScan { schema, .. }
+            | PlanNode::NestedLoopJoin { schema, .. }
+            | PlanNode::HashJoin(HashJoinNode { schema, .. })
+            | PlanNode::SortMergeJoin(SortMergeJoinNode { schema, .. })
+            | PlanNode::IndexNestedLoopJoin(IndexNestedLoopJoinNode { schema, .. })
+            | PlanNode::Aggregate { schema, .. }
+            | PlanNode::Window { schema, .. }
+            | PlanNode::Exists { schema, .. }
+            | PlanNode::Projection { schema, .. } => schema,
+        }
+    }
+
+    /// The nodes this one reads rows from, but for the plans of common
+    /// tables, which every scan of them shares.
+    fn inputs_mut(&mut self) -> Vec<&mut PlanNode> {
+        match self {
+            PlanNode::SingleRow
+            | PlanNode::Values { .. }
+            | PlanNode::TableScan { .. }
+            | PlanNode::IndexScan { .. }
+            | PlanNode::CteScan { .. }
+            | PlanNode::WorkingTableScan { .. } => Vec::new(),
+            PlanNode::SubqueryScan { input, .. }
+            | PlanNode::Filter { input, .. }
+            | PlanNode::Sort { input, .. }
+            | PlanNode::Projection { input, .. }
+            | PlanNode::Aggregate { input, .. }
+            | PlanNode::Window { input, .. }
+            | PlanNode::Distinct { input }
+            | PlanNode::Exists { input, .. }
+            | PlanNode::Limit { input, .. }
+            | PlanNode::IndexNestedLoopJoin(IndexNestedLoopJoinNode { outer: input, .. }) => {
+                vec![input]
+            }
+            PlanNode::NestedLoopJoin { left, right, .. }
+            | PlanNode::SetOperation { left, right, .. }
+            | PlanNode::SortMergeJoin(SortMergeJoinNode { left, right, .. })
+            | PlanNode::HashJoin(HashJoinNode {
+                build: left,
+                probe: right,
+                ..
+            }) => vec![left, right],
+        }
+    }
+}
+
+pub fn plan_select(db: &Database, stmt: &SelectStmt) -> Result<PlanNode, EvalError> {
+    let correlated = Cell::new(false);
+    plan_query(Scope::new(db, None, &correlated), stmt, |_, expr| {
+        expr.to_string()
+    })
+}
+
+/// An UPDATE with its expressions bound against the rows of its table.
+#[derive(Debug)]
+pub struct UpdatePlan {
+    pub table: String,
+    pub schema: Schema,
+    pub predicate: Option<Expr>,
+    /// The position of each assigned column, with the value it is given.
+    pub assignments: Vec<(usize, Expr)>,
+}
+
+pub fn plan_update(db: &Database, stmt: &UpdateStmt) -> Result<UpdatePlan, EvalError> {
+    let correlated = Cell::new(false);
+    let scope = Scope::new(db, None, &correlated);
+    let PlanNode::TableScan { table, schema } = table_scan(scope, &stmt.table, None)? else {
+        unreachable!("a table is scanned")
+    };
+    let stored = db.get_table(&table).expect("the table was scanned");
+    let scope = scope.with(&schema);
+    let predicate = stmt
+        .where_clause
+        .as_ref()
+        .map(|p| bound_scalar(p, scope))
+        .transpose()?
+        .map(|p| read_virtual(p, stored));
+    let assignments = stmt
+        .assignments
+        .iter()
+        .map(|(column, expr)| {
+            let i = schema
+                .columns
+                .iter()
+                .position(|c| c.name.eq_ignore_ascii_case(column))
+                .ok_or_else(|| EvalError::NoSuchColumn(column.clone()))?;
+            if stored.schema[i].generated.is_some() {
+                return Err(DbError::UpdateGenerated(stored.schema[i].name.clone()).into());
+            }
+            Ok((i, read_virtual(bound_scalar(expr, scope)?, stored)))
+        })
+        .collect::<Result<Vec<_>, EvalError>>()?;
+    Ok(UpdatePlan {
+        table,
+        schema,
+        predicate,
+        assignments,
+    })
+}
+
+/// A DELETE with its predicate bound against the rows of its table.
+#[derive(Debug)]
+pub struct DeletePlan {
+    pub table: String,
+    pub schema: Schema,
+    pub predicate: Option<Expr>,
+}
+
+pub fn plan_delete(db: &Database, stmt: &DeleteStmt) -> Result<DeletePlan, EvalError> {
+    let correlated = Cell::new(false);
+    let scope = Scope::new(db, None, &correlated);
+    let PlanNode::TableScan { table, schema } = table_scan(scope, &stmt.table, None)? else {
+        unreachable!("a table is scanned")
+    };
+    let stored = db.get_table(&table).expect("the table was scanned");
+    let predicate = stmt
+        .where_clause
+        .as_ref()
+        .map(|p| bound_scalar(p, scope.with(&schema)))
+        .transpose()?
+        .map(|p| read_virtual(p, stored));
+    Ok(DeletePlan {
+        table,
+        schema,
+        predicate,
+    })
+}
+
+/// The RETURNING clause of an INSERT, UPDATE or DELETE, with its
+/// expressions bound against the rows of its table.
+#[derive(Debug)]
+pub struct ReturningPlan {
+    pub schema: Schema,
+    /// Empty without a RETURNING clause.
+    pub exprs: Vec<Expr>,
+}
+
+pub fn plan_returning(
+    db: &Database,
+    table: &str,
+    items: &[SelectItem],
+) -> Result<ReturningPlan, EvalError> {
+    let correlated = Cell::new(false);
+    let scope = Scope::new(db, None, &correlated);
+    let schema = table_scan(scope, table, None)?.schema().clone();
+    let mut exprs = Vec::new();
+    let mut columns = Vec::new();
+    for item in items {
+        match item {
+            SelectItem::Wildcard => {
+                expand_wildcard(&schema, None, &mut exprs, &mut columns);
+            }
+            SelectItem::QualifiedWildcard(name) => {
+                if !expand_wildcard(&schema, Some(name), &mut exprs, &mut columns) {
+                    return Err(DbError::NoSuchTable(name.clone()).into());
+                }
+            }
+            SelectItem::Expr { expr, .. } => exprs.push(bound_scalar(expr, scope.with(&schema))?),
+        }
+    }
+    let stored = db.get_table(table).expect("the table was scanned");
+    let exprs = exprs.into_iter().map(|e| read_virtual(e, stored)).collect();
+    Ok(ReturningPlan { schema, exprs })
+}
+
+/// The CHECK constraints of a table, with their expressions bound against
+/// its rows.
+#[derive(Debug)]
+pub struct CheckPlan {
+    pub schema: Schema,
+    /// Each constraint's expression, with the name a violation reports:
+    /// the one it was given, else `table.column` for one declared with a
+    /// column and its expression for one that was not.
+    pub checks: Vec<(String, Expr)>,
+}
+
+pub fn plan_checks(db: &Database, table: &str) -> Result<CheckPlan, EvalError> {
+    let stored = db
+        .get_table(table)
+        .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?;
+    let mut checks = Vec::new();
+    for column in &stored.schema {
+        for check in &column.checks {
+            let name = check
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{}.{}", stored.name, column.name));
+            checks.push((name, &check.expr));
+        }
+    }
+    for check in &stored.checks {
+        let name = check.name.clone().unwrap_or_else(|| check.expr.to_string());
+        checks.push((name, &check.expr));
+    }
+    let checks = checks
+        .into_iter()
+        .map(|(name, expr)| {
+            let expr = bind_check(db, &stored.name, &stored.schema, expr)?;
+            Ok((name, read_virtual(expr, stored)))
+        })
+        .collect::<Result<_, EvalError>>()?;
+    Ok(CheckPlan {
+        schema: table_schema(&stored.name, &stored.schema),
+        checks,
+    })
+}
+
+/// The generated columns of a table, with their expressions bound against
+/// its rows.
+#[derive(Debug)]
+pub struct GeneratedPlan {
+    pub schema: Schema,
+    /// The position of each generated column, with its expression and
+    /// whether it is stored.
+    pub columns: Vec<(usize, Expr, bool)>,
+}
+
+pub fn plan_generated(db: &Database, table: &str) -> Result<GeneratedPlan, EvalError> {
+    let stored = db
+        .get_table(table)
+        .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?;
+    let columns = stored
+        .schema
+        .iter()
+        .enumerate()
+        .filter_map(|(i, column)| Some((i, column.generated.as_ref()?)))
+        .map(|(i, generated)| {
+            let expr = bind_check(db, &stored.name, &stored.schema, &generated.expr)?;
+            Ok((i, expr, generated.stored))
+        })
+        .collect::<Result<_, EvalError>>()?;
+    Ok(GeneratedPlan {
+        schema: table_schema(&stored.name, &stored.schema),
+        columns,
+    })
+}
+
+/// `expr`, evaluated against the stored rows of `table`, with each
+/// reference to one of its virtual columns replaced by the column's
+/// expression, since the rows hold NULL there.
+fn read_virtual(expr: Expr, table: &Table) -> Expr {
+    if !table.schema.iter().any(ColumnDef::is_virtual) {
+        return expr;
+    }
+    expr.transform(&mut |e| match e {
+        Expr::Column(_, name) => {
+            let column = &table.schema[table.column_index(name)?];
+            column
+                .generated
+                .as_ref()
+                .filter(|_| column.is_virtual())
+                .map(|generated| generated.expr.clone())
+        }
+        _ => None,
+    })
+}
+
+/// Bind the expression of a CHECK constraint against the rows of a table
+/// named `table` with the given columns, which need not exist yet.
+pub fn bind_check(
+    db: &Database,
+    table: &str,
+    columns: &[ColumnDef],
+    expr: &Expr,
+) -> Result<Expr, EvalError> {
+    let correlated = Cell::new(false);
+    let schema = table_schema(table, columns);
+    bound_scalar(expr, Scope::new(db, None, &correlated).with(&schema))
+}
+
+/// Bind an expression evaluated once per row, where aggregates cannot be.
+fn bound_scalar(expr: &Expr, scope: Scope<'_>) -> Result<Expr, EvalError> {
+    let expr = bind(expr, scope)?;
+    if let Some(func) = expr.first_aggregate() {
+        return Err(EvalError::MisusedAggregate(func));
+    }
+    match expr.first_window() {
+        Some(name) => Err(EvalError::MisusedWindow(name)),
+        None => Ok(expr),
+    }
+}
+
+/// What the expressions of a query are bound against: the schema of the
+/// rows they are evaluated for, then the scopes of the enclosing queries.
+#[derive(Clone, Copy)]
+struct Scope<'a> {
+    db: &'a Database,
+    schema: &'a Schema,
+    outer: Option<&'a Scope<'a>>,
+    /// The common table expressions FROM clauses may read, latest last.
+    ctes: &'a [Arc<CommonTable>],
+    /// Inside the recursive step of a common table expression, the step's
+    /// reference to itself.
+    recursion: Option<&'a Recursion<'a>>,
+    /// Set once an expression of the query refers to an enclosing query.
+    correlated: &'a Cell<bool>,
+    /// How many views the query is read through.
+    views: usize,
+}
+
+impl<'a> Scope<'a> {
+    /// The scope of a query, before its FROM clause provides any columns.
+    /// It sees the common table expressions of the enclosing query.
+    fn new(db: &'a Database, outer: Option<&'a Scope<'a>>, correlated: &'a Cell<bool>) -> Self {
+        static EMPTY: Schema = Schema {
+            columns: Vec::new(),
+        };
+        Scope {
+            db,
+            schema: &EMPTY,
+            outer,
+            ctes: outer.map_or(&[], |outer| outer.ctes),
+            recursion: outer.and_then(|outer| outer.recursion),
+            correlated,
+            views: outer.map_or(0, |outer| outer.views),
+        }
+    }
+
+    /// The scope of a query planned on its own, such as a derived table,
+    /// which reads no enclosing query's columns but sees its tables.
+    fn detached(self, correlated: &'a Cell<bool>) -> Self {
+        Scope {
+            ctes: self.ctes,
+            recursion: self.recursion,
+            views: self.views,
+            ..Scope::new(self.db, None, correlated)
+        }
+    }
+
+    /// The common table expression `name` refers to, if any.
+    fn cte(&self, name: &str) -> Option<&'a Arc<CommonTable>> {
+        self.ctes
+            .iter()
+            .rev()
+            .find(|cte| cte.name.eq_ignore_ascii_case(name))
+    }
+
+    fn with(self, schema: &'a Schema) -> Self {
+        Scope { schema, ..self }
+    }
+
+    /// Bind `[table.]name` to a column of this scope or, failing that, of
+    /// the nearest enclosing scope that has it, marking every query the
+    /// reference reaches out of as correlated.
+    fn resolve(&self, table: Option<&str>, name: &str) -> Result<Option<Expr>, EvalError> {
+        let err = match self.schema.resolve(table, name) {
+            Ok(_) => return Ok(None),
+            Err(err @ EvalError::NoSuchColumn(_)) => err,
+            Err(err) => return Err(err),
+        };
+        let mut crossed = vec![self.correlated];
+        let mut scope = self.outer;
+        let mut depth = 0;
+        while let Some(outer) = scope {
+            match outer.schema.resolve(table, name) {
+                Ok(index) => {
+                    crossed.iter().for_each(|c| c.set(true));
+                    return Ok(Some(Expr::OuterRef {
+                        table: table.map(str::to_string),
+                        name: name.to_string(),
+                        depth,
+                        index,
+                    }));
+                }
+                Err(EvalError::NoSuchColumn(_)) => {}
+                Err(err) => return Err(err),
+            }
+            crossed.push(outer.correlated);
+            scope = outer.outer;
+            depth += 1;
+        }
+        Err(err)
+    }
+}
+
+/// The working table of a recursive step being planned.
+struct Recursion<'a> {
+    table: &'a Arc<WorkingTable>,
+    schema: &'a Schema,
+    /// The `correlated` flag of the step's own query, identifying its
+    /// scope: the step may only read the table directly in its FROM clause.
+    query: &'a Cell<bool>,
+    /// How many times the step reads the table.
+    uses: Cell<usize>,
+}
+
+/// Plan a SELECT, naming each output column computed by an expression that
+/// is neither aliased nor a column reference with `unnamed(position, expr)`.
+fn plan_query(
+    scope: Scope<'_>,
+    stmt: &SelectStmt,
+    unnamed: fn(usize, &Expr) -> String,
+) -> Result<PlanNode, EvalError> {
+    let Some(with) = &stmt.with else {
+        return plan_query_body(scope, stmt, unnamed);
+    };
+    // Each CTE is planned on its own, seeing only those before it.
+    let mut ctes = scope.ctes.to_vec();
+    for cte in &with.ctes {
+        let correlated = Cell::new(false);
+        let cte_scope = Scope {
+            ctes: &ctes,
+            ..scope.detached(&correlated)
+        };
+        ctes.push(Arc::new(plan_cte(cte_scope, cte, with.recursive)?));
+    }
+    plan_query_body(
+        Scope {
+            ctes: &ctes,
+            ..scope
+        },
+        stmt,
+        unnamed,
+    )
+}
+
+/// Plan a common table expression. In a `WITH RECURSIVE` clause, one whose
+/// query is a UNION ALL of a base case and a step that reads the table
+/// itself is recursive; it may be limited as a whole, but not ordered.
+fn plan_cte(scope: Scope<'_>, cte: &Cte, recursive: bool) -> Result<CommonTable, EvalError> {
+    let unnamed = |_, expr: &Expr| expr.to_string();
+    let table = |schema, plan, step| CommonTable {
+        name: cte.name.clone(),
+        schema,
+        plan,
+        step,
+        rows: OnceLock::new(),
+    };
+    let query = &cte.query;
+    let (op, left, right) = match &query.body {
+        QueryBody::SetOperation {
+            op: op @ (SetOperator::Union | SetOperator::UnionAll),
+            left,
+            right,
+        } if recursive => (op, left, right),
+        _ => {
+            let plan = plan_query(scope, query, unnamed)?;
+            return Ok(table(cte_schema(cte, &plan)?, plan, None));
+        }
+    };
+    let base = plan_body(scope, left, unnamed)?;
+    let schema = cte_schema(cte, &base)?;
+    let working = Arc::new(WorkingTable {
+        name: cte.name.clone(),
+        rows: Mutex::default(),
+    });
+    let step_correlated = Cell::new(false);
+    let recursion = Recursion {
+        table: &working,
+        schema: &schema,
+        query: &step_correlated,
+        uses: Cell::new(0),
+    };
+    let step_scope = Scope {
+        recursion: Some(&recursion),
+        ..scope.detached(&step_correlated)
+    };
+    let step = plan_body(step_scope, right, unnamed)?;
+    if step.schema().columns.len() != schema.columns.len() {
+        return Err(EvalError::SetOperationColumnCount(*op));
+    }
+    if recursion.uses.get() == 0 {
+        if !query.order_by.is_empty() || query.limit.is_some() {
+            let plan = plan_query(scope, query, unnamed)?;
+            return Ok(table(schema, plan, None));
+        }
+        let plan = PlanNode::SetOperation {
+            op: *op,
+            left: Box::new(base),
+            right: Box::new(step),
+        };
+        return Ok(table(schema, plan, None));
+    }
+    if *op == SetOperator::Union {
+        return Err(EvalError::RecursiveUnion(cte.name.clone()));
+    }
+    if !query.order_by.is_empty() {
+        return Err(EvalError::Unsupported(
+            "ORDER BY in a recursive common table expression".to_string(),
+        ));
+    }
+    let (limit, offset) = match &query.limit {
+        Some(clause) => limit_counts(clause)?,
+        None => (None, 0),
+    };
+    let step = RecursiveStep {
+        plan: step,
+        working: Arc::clone(&working),
+        limit,
+        offset,
+    };
+    Ok(table(schema, base, Some(step)))
+}
+
+/// The columns of a common table expression: those of its plan, renamed
+/// by the column list if it has one.
+fn cte_schema(cte: &Cte, plan: &PlanNode) -> Result<Schema, EvalError> {
+    let columns = &plan.schema().columns;
+    let names: Vec<&str> = match &cte.columns {
+        Some(names) if names.len() != columns.len() => {
+            return Err(EvalError::CteColumnCount {
+                table: cte.name.clone(),
+                values: columns.len(),
+                columns: names.len(),
+            });
+        }
+        Some(names) => names.iter().map(String::as_str).collect(),
+        None => columns.iter().map(|c| c.name.as_str()).collect(),
+    };
+    let columns = columns
+        .iter()
+        .zip(names)
+        .map(|(c, name)| SchemaColumn {
+            table: None,
+            name: name.to_string(),
+            hidden: false,
+            collation: c.collation,
+        })
+        .collect();
+    Ok(Schema { columns })
+}
+
+/// Plan a SELECT after its WITH clause.
+fn plan_query_body(
+    scope: Scope<'_>,
+    stmt: &SelectStmt,
+    unnamed: fn(usize, &Expr) -> String,
+) -> Result<PlanNode, EvalError> {
+    let mut plan = match &stmt.body {
+        QueryBody::Select(select) => plan_select_core(scope, select, &stmt.order_by, unnamed)?,
+        body => {
+            // A compound query can only be ordered by its output columns.
+            let plan = plan_body(scope, body, unnamed)?;
+            let columns = plan.schema().columns.clone();
+            let exprs = columns
+                .iter()
+                .map(|c| Expr::Column(None, c.name.clone()))
+                .collect::<Vec<_>>();
+            let keys = stmt
+                .order_by
+                .iter()
+                .map(|item| sort_key(item, scope.with(plan.schema()), &exprs, &columns))
+                .collect::<Result<Vec<_>, _>>()?;
+            sorted(plan, keys)
+        }
+    };
+    if let Some(clause) = &stmt.limit {
+        plan = plan_limit(plan, clause)?;
+    }
+    Ok(plan)
+}
+
+/// Plan the rows of a query before its ORDER BY and LIMIT. The operands of
+/// a set operation must have as many columns as each other, and the
+/// result is named after the left one.
+fn plan_body(
+    scope: Scope<'_>,
+    body: &QueryBody,
+    unnamed: fn(usize, &Expr) -> String,
+) -> Result<PlanNode, EvalError> {
+    match body {
+        QueryBody::Select(select) => plan_select_core(scope, select, &[], unnamed),
+        QueryBody::SetOperation { op, left, right } => {
+            let left = plan_body(scope, left, unnamed)?;
+            let right = plan_body(scope, right, unnamed)?;
+            if left.schema().columns.len() != right.schema().columns.len() {
+                return Err(EvalError::SetOperationColumnCount(*op));
+            }
+            Ok(PlanNode::SetOperation {
+                op: *op,
+                left: Box::new(left),
+                right: Box::new(right),
+            })
+        }
+    }
+}
+
+/// Plan a single SELECT, sorted by `order_by`.
+fn plan_select_core(
+    scope: Scope<'_>,
+    stmt: &SelectCore,
+    order_by: &[OrderByItem],
+    unnamed: fn(usize, &Expr) -> String,
+) -> Result<PlanNode, EvalError> {
+    let mut plan = match &stmt.from {
+        Some(from) => plan_table_ref(scope, from)?,
+        None => PlanNode::SingleRow,
+    };
+    if let Some(predicate) = &stmt.where_clause {
+        let predicate = bound_scalar(predicate, scope.with(plan.schema()))?;
+        plan = PlanNode::Filter {
+            input: Box::new(plan),
+            predicate,
+        };
+    }
+    // Filters are moved to the tables they test first, so the estimates
+    // that joins are ordered and implemented by count only the rows that
+    // pass them.
+    plan = index_scans(scope.db, pushdown_predicates(plan));
+    if stmt.from.is_some() {
+        plan = join_order::order_joins(scope.db, plan);
+        plan = join_algorithm::join_algorithms(scope.db, plan);
+    }
+    plan_select_list(scope, stmt, plan, order_by, unnamed)
+}
+
+/// Plan what a single SELECT computes from the rows of its FROM and WHERE
+/// clauses, `plan`: its select list, grouping and order. It is kept out
+/// of [`plan_select_core`] so that function's stack frame, which each
+/// nested view and derived table adds, stays small.
+fn plan_select_list(
+    scope: Scope<'_>,
+    stmt: &SelectCore,
+    mut plan: PlanNode,
+    order_by: &[OrderByItem],
+    unnamed: fn(usize, &Expr) -> String,
+) -> Result<PlanNode, EvalError> {
+    let input = plan.schema();
+    // The select list's aliases, which its items may use where no input
+    // column has the name; the first of equal aliases wins.
+    let mut aliases = HashMap::new();
+    for item in &stmt.projection {
+        if let SelectItem::Expr {
+            expr,
+            alias: Some(alias),
+        } = item
+        {
+            aliases.entry(alias.to_ascii_lowercase()).or_insert(expr);
+        }
+    }
+    let mut exprs = Vec::new();
+    let mut columns = Vec::new();
+    for item in &stmt.projection {
+        match item {
+            SelectItem::Wildcard => {
+                if stmt.from.is_none() {
+                    return Err(EvalError::NoTablesSpecified);
+                }
+                expand_wildcard(input, None, &mut exprs, &mut columns);
+            }
+            SelectItem::QualifiedWildcard(table) => {
+                if !expand_wildcard(input, Some(table), &mut exprs, &mut columns) {
+                    return Err(DbError::NoSuchTable(table.clone()).into());
+                }
+            }
+            SelectItem::Expr { expr, alias } => {
+                let name = match (alias, expr) {
+                    (Some(alias), _) => alias.clone(),
+                    (None, Expr::Column(_, name)) => name.clone(),
+                    (None, expr) => unnamed(columns.len(), expr),
+                };
+                let expr = resolve_aliases(expr, input, &aliases, &mut Vec::new())?;
+                exprs.push(bind(&expr, scope.with(input))?);
+                columns.push(SchemaColumn {
+                    table: None,
+                    name,
+                    hidden: false,
+                    collation: input.collation_of(&expr).unwrap_or_default(),
+                });
+            }
+        }
+    }
+
+    let mut keys = order_by
+        .iter()
+        .map(|item| sort_key(item, scope.with(plan.schema()), &exprs, &columns))
+        .collect::<Result<Vec<_>, _>>()?;
+    let group_by = stmt
+        .group_by
+        .iter()
+        .map(|expr| group_key(expr, scope.with(plan.schema()), &exprs, &columns))
+        .collect::<Result<Vec<_>, _>>()?;
+    let having = match &stmt.having {
+        Some(having) => {
+            let having = substitute_aliases(having, plan.schema(), &exprs, &columns);
+            let having = bind(&having, scope.with(plan.schema()))?;
+            if let Some(name) = having.first_window() {
+                return Err(EvalError::MisusedWindow(name));
+            }
+            Some(having)
+        }
+        None => None,
+    };
+    let aggregated = !group_by.is_empty()
+        || having.is_some()
+        || exprs
+            .iter()
+            .chain(keys.iter().map(|k| &k.expr))
+            .any(|e| e.first_aggregate().is_some());
+    if aggregated {
+        // Everything evaluated from here on sees the aggregation's output.
+        let mut aggregation = Aggregation::new(plan.schema().clone(), group_by);
+        for expr in &mut exprs {
+            *expr = aggregation.rewrite(expr)?;
+        }
+        for key in &mut keys {
+            key.expr = aggregation.rewrite(&key.expr)?;
+        }
+        let having = having.map(|h| aggregation.rewrite(&h)).transpose()?;
+        plan = aggregation.plan(plan)?;
+        if let Some(predicate) = having {
+            plan = PlanNode::Filter {
+                input: Box::new(plan),
+                predicate,
+            };
+        }
+    }
+    // Window functions see the rows aggregation left, and ORDER BY sees
+    // their values.
+    let mut windowing = Windowing::default();
+    for expr in &mut exprs {
+        *expr = windowing.rewrite(expr)?;
+    }
+    for key in &mut keys {
+        key.expr = windowing.rewrite(&key.expr)?;
+    }
+    plan = windowing.plan(plan)?;
+    if stmt.distinct {
+        // Deduplicate first, then sort the distinct rows; each key must
+        // therefore be one of the output columns.
+        let keys = keys
+            .into_iter()
+            .map(|key| output_sort_key(key, &exprs, &columns))
+            .collect::<Result<Vec<_>, _>>()?;
+        plan = PlanNode::Projection {
+            input: Box::new(plan),
+            exprs,
+            schema: Schema { columns },
+        };
+        plan = PlanNode::Distinct {
+            input: Box::new(plan),
+        };
+        plan = sorted(plan, keys);
+    } else {
+        plan = sorted(plan, keys);
+        plan = PlanNode::Projection {
+            input: Box::new(plan),
+            exprs,
+            schema: Schema { columns },
+        };
+    }
+    Ok(plan)
+}
+
+/// The grouping keys and aggregate calls of an aggregated SELECT, built up
+/// while rewriting the expressions evaluated after aggregation in terms of
+/// the aggregation's output columns.
+struct Aggregation {
+    input: Schema,
+    group_by: Vec<Expr>,
+    calls: Vec<Expr>,
+}
+
+impl Aggregation {
+    fn new(input: Schema, group_by: Vec<Expr>) -> Self {
+        Aggregation {
+            input,
+            group_by,
+            calls: Vec::new(),
+        }
+    }
+
+    /// Replace grouping keys and aggregate calls in `expr` by references
+    /// to the output columns holding them. Any other column reference
+    /// left over is an error, as its value would differ within a group.
+    fn rewrite(&mut self, expr: &Expr) -> Result<Expr, EvalError> {
+        let mut error = None;
+        let rewritten = expr.transform(&mut |e| {
+            if let Some(i) = self.group_by.iter().position(|k| self.same(k, e)) {
+                return Some(self.column(i));
+            }
+            match e {
+                Expr::Aggregate {
+                    args,
+                    order_by,
+                    filter,
+                    ..
+                } => {
+                    let nested = args
+                        .iter()
+                        .chain(order_by.iter().map(|item| &item.expr))
+                        .chain(filter.as_deref());
+                    if let Some(func) = nested.clone().find_map(Expr::first_aggregate) {
+                        error.get_or_insert(EvalError::MisusedAggregate(func));
+                    }
+                    let wanted = format!("{e:?}");
+                    let i = match self.calls.iter().position(|c| format!("{c:?}") == wanted) {
+                        Some(i) => i,
+                        None => {
+                            self.calls.push(e.clone());
+                            self.calls.len() - 1
+                        }
+                    };
+                    Some(self.column(self.group_by.len() + i))
+                }
+                Expr::Column(table, name) => {
+                    error.get_or_insert(EvalError::NotGrouped(qualified(table.as_deref(), name)));
+                    Some(e.clone())
+                }
+                // Its outer references were bound to the input rows,
+                // which are gone after aggregation.
+                Expr::Subquery { plan, .. } if plan.correlated => {
+                    error.get_or_insert(EvalError::Unsupported(
+                        "a correlated subquery over aggregated rows".to_string(),
+                    ));
+                    Some(e.clone())
+                }
+                _ => None,
+            }
+        });
+        match error {
+            Some(e) => Err(e),
+            None => Ok(rewritten),
+        }
+    }
+
+    /// Whether two expressions over the input compute the same value: they
+    /// print the same, or are references to the same column.
+    fn same(&self, a: &Expr, b: &Expr) -> bool {
+        if let (Expr::Column(ta, na), Expr::Column(tb, nb)) = (a, b)
+            && let (Ok(i), Ok(j)) = (
+                self.input.resolve(ta.as_deref(), na),
+                self.input.resolve(tb.as_deref(), nb),
+            )
+        {
+            return i == j;
+        }
+        format!("{a:?}") == format!("{b:?}")
+    }
+
+    /// A reference to output column `i`, named after what it holds.
+    fn column(&self, i: usize) -> Expr {
+        Expr::Column(None, self.name(i))
+    }
+
+    fn name(&self, i: usize) -> String {
+        match self.group_by.get(i) {
+            Some(key) => key.to_string(),
+            None => self.calls[i - self.group_by.len()].to_string(),
+        }
+    }
+
+    fn plan(self, input: PlanNode) -> Result<PlanNode, EvalError> {
+        let columns = (0..self.group_by.len() + self.calls.len())
+            .map(|i| SchemaColumn {
+                table: None,
+                name: self.name(i),
+                hidden: false,
+                collation: Collation::Binary,
+            })
+            .collect();
+        let calls = self
+            .calls
+            .into_iter()
+            .map(|call| match call {
+                Expr::Aggregate {
+                    func,
+                    distinct,
+                    args,
+                    order_by,
+                    filter,
+                } => aggregate_call(func, distinct, args, order_by, filter.map(|f| *f)),
+                _ => unreachable!("not an aggregate call: {call}"),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(PlanNode::Aggregate {
+            input: Box::new(input),
+            group_by: self.group_by,
+            calls,
+            schema: Schema { columns },
+        })
+    }
+}
+
+/// The call of aggregate `func` with `args`, whose count the parser has
+/// checked.
+fn aggregate_call(
+    func: AggFunc,
+    distinct: bool,
+    args: Vec<Expr>,
+    order_by: Vec<OrderByItem>,
+    filter: Option<Expr>,
+) -> Result<AggregateCall, EvalError> {
+    let mut args = args.into_iter();
+    let arg = args.next();
+    let delimiter = match (func, args.next()) {
+        (AggFunc::GroupConcat, Some(delimiter)) => {
+            let delimiter = Evaluator::constant().eval(&delimiter).map_err(|_| {
+                EvalError::Unsupported("a GROUP_CONCAT() delimiter that is not constant".into())
+            })?;
+            match coerce(delimiter, DataType::Text)? {
+                Value::Text(delimiter) => Some(delimiter),
+                _ => Some(String::new()),
+            }
+        }
+        (AggFunc::GroupConcat, None) => Some(",".to_string()),
+        _ => None,
+    };
+    Ok(AggregateCall {
+        func,
+        distinct,
+        arg,
+        delimiter,
+        order_by: order_by.into_iter().map(call_sort_key).collect(),
+        filter,
+    })
+}
+
+/// The window function calls of a SELECT, built up while rewriting the
+/// expressions evaluated after windowing in terms of the window node's
+/// output columns.
+#[derive(Default)]
+struct Windowing {
+    calls: Vec<Expr>,
+}
+
+impl Windowing {
+    /// Replace window function calls in `expr` by references to the
+    /// output columns holding them.
+    fn rewrite(&mut self, expr: &Expr) -> Result<Expr, EvalError> {
+        let mut error = None;
+        let rewritten = expr.transform(&mut |e| match e {
+            Expr::Window {
+                args,
+                filter,
+                window,
+                ..
+            } => {
+                let nested = args
+                    .iter()
+                    .chain(filter.as_deref())
+                    .chain(&window.partition_by)
+                    .chain(window.order_by.iter().map(|item| &item.expr))
+                    .find_map(Expr::first_window);
+                if let Some(name) = nested {
+                    error.get_or_insert(EvalError::MisusedWindow(name));
+                }
+                let wanted = format!("{e:?}");
+                if !self.calls.iter().any(|c| format!("{c:?}") == wanted) {
+                    self.calls.push(e.clone());
+                }
+                Some(Expr::Column(None, e.to_string()))
+            }
+            _ => None,
+        });
+        match error {
+            Some(e) => Err(e),
+            None => Ok(rewritten),
+        }
+    }
+
+    /// The window node computing the calls over `input`, or `input` itself
+    /// if there are none.
+    fn plan(self, input: PlanNode) -> Result<PlanNode, EvalError> {
+        if self.calls.is_empty() {
+            return Ok(input);
+        }
+        let mut columns = input.schema().columns.clone();
+        columns.extend(self.calls.iter().map(|call| SchemaColumn {
+            table: None,
+            name: call.to_string(),
+            hidden: false,
+            collation: Collation::Binary,
+        }));
+        let calls = self
+            .calls
+            .into_iter()
+            .map(window_call)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(PlanNode::Window {
+            input: Box::new(input),
+            calls,
+            schema: Schema { columns },
+        })
+    }
+}
+
+/// Resolve the function a window function call names.
+fn window_call(call: Expr) -> Result<WindowCall, EvalError> {
+    let Expr::Window {
+        name,
+        args,
+        filter,
+        window,
+    } = call
+    else {
+        unreachable!("not a window function call: {call}");
+    };
+    // Only the parser's aggregate calls take a FILTER.
+    let function = match AggFunc::from_name(&name) {
+        // The parser only makes `COUNT(*)` a call without arguments.
+        Some(func) if args.len() <= 1 + usize::from(func == AggFunc::GroupConcat) => {
+            WindowFunction::Aggregate(aggregate_call(
+                func,
+                false,
+                args,
+                Vec::new(),
+                filter.map(|f| *f),
+            )?)
+        }
+        Some(_) => return Err(EvalError::WrongArgumentCount(name)),
+        None if let Some(function) = RankingFunction::from_name(&name) => {
+            if !args.is_empty() {
+                return Err(EvalError::WrongArgumentCount(name));
+            }
+            if window.order_by.is_empty() {
+                return Err(EvalError::WindowOrderRequired(function.name().to_string()));
+            }
+            WindowFunction::Ranking(function)
+        }
+        None if let Some(direction) = OffsetDirection::from_name(&name) => {
+            if !(1..=3).contains(&args.len()) {
+                return Err(EvalError::WrongArgumentCount(name));
+            }
+            let mut args = args.into_iter();
+            let expr = args.next().unwrap();
+            let offset = match args.next() {
+                Some(offset) => window_count(direction.name(), 2, &offset, false)?,
+                None => 1,
+            };
+            WindowFunction::OffsetRow(OffsetRowFunction {
+                direction,
+                expr,
+                offset,
+                default: args.next(),
+            })
+        }
+        None if name.eq_ignore_ascii_case("NTILE") => {
+            let [n] = args.as_slice() else {
+                return Err(EvalError::WrongArgumentCount(name));
+            };
+            WindowFunction::Ntile(NtileFunction {
+                bucket_count: window_count("NTILE", 1, n, true)?,
+            })
+        }
+        None if let Some(position) = ValuePosition::from_name(&name) => {
+            let mut args = args.into_iter();
+            let (Some(expr), n, None) = (args.next(), args.next(), args.next()) else {
+                return Err(EvalError::WrongArgumentCount(name));
+            };
+            let position = match (position, n) {
+                (ValuePosition::Nth(_), Some(n)) => {
+                    ValuePosition::Nth(window_count("NTH_VALUE", 2, &n, true)?)
+                }
+                (ValuePosition::Nth(_), None) | (_, Some(_)) => {
+                    return Err(EvalError::WrongArgumentCount(name));
+                }
+                (position, None) => position,
+            };
+            WindowFunction::Value(ValueFunction { position, expr })
+        }
+        None => {
+            FunctionRegistry::global().lookup(&name, args.len())?;
+            return Err(EvalError::NotWindowFunction(name));
+        }
+    };
+    let frame = window.frame.unwrap_or_else(|| function.default_frame());
+    let offset = |bound| matches!(bound, FrameBound::Preceding(_) | FrameBound::Following(_));
+    if frame.mode == FrameMode::Range
+        && window.order_by.len() != 1
+        && (offset(frame.start) || offset(frame.end))
+    {
+        return Err(EvalError::RangeFrameOrderBy);
+    }
+    let order_by = window.order_by.into_iter().map(call_sort_key).collect();
+    Ok(WindowCall {
+        function,
+        partition_by: window.partition_by,
+        order_by,
+        frame,
+    })
+}
+
+/// The sort key of an ORDER BY term within a window or aggregate call,
+/// which was bound along with the call.
+/// A sort key of an aggregate or window function call, whose texts order
+/// under a collation only if the term gives one with `COLLATE`.
+fn call_sort_key(item: OrderByItem) -> SortKey {
+    let collation = match &item.expr {
+        Expr::Collate { collation, .. } => *collation,
+        _ => Collation::Binary,
+    };
+    SortKey {
+        expr: item.expr,
+        desc: item.desc,
+        nulls_first: item.nulls_first.unwrap_or(item.desc),
+        collation,
+    }
+}
+
+/// Evaluate argument `position` of window function `name`, which must be a
+/// constant integer that is at least 1 if `positive`, else at least 0.
+fn window_count(
+    name: &str,
+    position: usize,
+    arg: &Expr,
+    positive: bool,
+) -> Result<usize, EvalError> {
+    match coerce(Evaluator::constant().eval(arg)?, DataType::Int) {
+        Ok(Value::Integer(n)) if n >= i64::from(positive) => Ok(n as usize),
+        _ => Err(EvalError::InvalidWindowArgument {
+            name: name.to_string(),
+            position,
+            positive,
+        }),
+    }
+}
+
+/// Evaluate LIMIT and OFFSET, which must be constant integers. A negative
+/// limit means no limit and a negative offset is treated as zero.
+fn plan_limit(input: PlanNode, clause: &LimitClause) -> Result<PlanNode, EvalError> {
+    let (limit, offset) = limit_counts(clause)?;
+    Ok(PlanNode::Limit {
+        input: Box::new(input),
+        limit,
+        offset,
+    })
+}
+
+/// The row count a LIMIT clause takes, if any, and the count it skips.
+fn limit_counts(clause: &LimitClause) -> Result<(Option<usize>, usize), EvalError> {
+    let count = |expr: &Expr| -> Result<Option<usize>, EvalError> {
+        match coerce(Evaluator::constant().eval(expr)?, DataType::Int)? {
+            Value::Integer(n) => Ok(usize::try_from(n).ok()),
+            _ => Ok(None),
+        }
+    };
+    let limit = match &clause.limit {
+        Some(expr) => count(expr)?,
+        None => None,
+    };
+    let offset = match &clause.offset {
+        Some(expr) => count(expr)?.unwrap_or(0),
+        None => 0,
+    };
+    Ok((limit, offset))
+}
+
+fn sorted(input: PlanNode, keys: Vec<SortKey>) -> PlanNode {
+    if keys.is_empty() {
+        input
+    } else {
+        PlanNode::Sort {
+            input: Box::new(input),
+            keys,
+        }
+    }
+}
+
+/// Rewrite a sort key over the projection's input into one over its
+/// output, by finding the output column computed by the same expression.
+fn output_sort_key(
+    key: SortKey,
+    exprs: &[Expr],
+    columns: &[SchemaColumn],
+) -> Result<SortKey, EvalError> {
+    // Compare printed forms: `Expr`'s `PartialEq` follows SQL semantics for
+    // literals, under which NULL differs from itself.
+    let wanted = format!("{:?}", key.expr);
+    let i = exprs
+        .iter()
+        .position(|e| format!("{e:?}") == wanted)
+        .ok_or(EvalError::DistinctOrderByNotSelected)?;
+    Ok(SortKey {
+        expr: Expr::Column(None, columns[i].name.clone()),
+        ..key
+    })
+}
+
+/// Resolve an ORDER BY term against the projection's input. An integer
+/// literal selects an output column by position, and a name that is not an
+/// input column refers to the output column with that alias. NULLs sort
+/// last ascending and first descending unless placed explicitly.
+fn sort_key(
+    item: &OrderByItem,
+    input: Scope<'_>,
+    exprs: &[Expr],
+    columns: &[SchemaColumn],
+) -> Result<SortKey, EvalError> {
+    let expr = match &item.expr {
+        Expr::Literal(Value::Integer(n)) => ordinal(*n, exprs)
+            .ok_or(EvalError::OrderByTermOutOfRange(exprs.len()))?
+            .clone(),
+        expr => bind(
+            &substitute_aliases(expr, input.schema, exprs, columns),
+            input,
+        )?,
+    };
+    Ok(SortKey {
+        collation: input.schema.collation_of(&expr).unwrap_or_default(),
+        expr,
+        desc: item.desc,
+        nulls_first: item.nulls_first.unwrap_or(item.desc),
+    })
+}
+
+/// Resolve a GROUP BY term against the projection's input, the way
+/// [`sort_key`] does: by output column position, or with output aliases
+/// substituted. Aggregate and window function calls are not allowed.
+fn group_key(
+    expr: &Expr,
+    input: Scope<'_>,
+    exprs: &[Expr],
+    columns: &[SchemaColumn],
+) -> Result<Expr, EvalError> {
+    let expr = match expr {
+        Expr::Literal(Value::Integer(n)) => ordinal(*n, exprs)
+            .ok_or(EvalError::GroupByTermOutOfRange(exprs.len()))?
+            .clone(),
+        expr => bind(
+            &substitute_aliases(expr, input.schema, exprs, columns),
+            input,
+        )?,
+    };
+    if let Some(func) = expr.first_aggregate() {
+        return Err(EvalError::MisusedAggregate(func));
+    }
+    match expr.first_window() {
+        Some(name) => Err(EvalError::MisusedWindow(name)),
+        None => Ok(expr),
+    }
+}
+
+/// The output column expression a 1-based ordinal refers to.
+fn ordinal(n: i64, exprs: &[Expr]) -> Option<&Expr> {
+    let i = usize::try_from(n).ok()?;
+    exprs.get(i.checked_sub(1)?)
+}
+
+/// Replace unqualified names that are not input columns but are output
+/// aliases with the aliased expression.
+fn substitute_aliases(
+    expr: &Expr,
+    input: &Schema,
+    exprs: &[Expr],
+    columns: &[SchemaColumn],
+) -> Expr {
+    expr.transform(&mut |e| match e {
+        Expr::Column(None, name) if input.resolve(None, name).is_err() => columns
+            .iter()
+            .position(|c| c.name.eq_ignore_ascii_case(name))
+            .map(|i| exprs[i].clone()),
+        _ => None,
+    })
+}
+
+/// Replace unqualified names that are not input columns but are aliases
+/// of the select list with the aliased expression, its own aliases
+/// replaced in turn. `resolving` holds the aliases being replaced, so one
+/// that turns up again refers to itself.
+fn resolve_aliases(
+    expr: &Expr,
+    input: &Schema,
+    aliases: &HashMap<String, &Expr>,
+    resolving: &mut Vec<String>,
+) -> Result<Expr, EvalError> {
+    let mut error = None;
+    let resolved = expr.transform(&mut |e| match e {
+        Expr::Column(None, name) if input.resolve(None, name).is_err() => {
+            let key = name.to_ascii_lowercase();
+            let aliased = aliases.get(&key)?;
+            if resolving.contains(&key) {
+                error.get_or_insert(EvalError::CircularAlias(name.clone()));
+                return Some(e.clone());
+            }
+            resolving.push(key);
+            let resolved = resolve_aliases(aliased, input, aliases, resolving);
+            resolving.pop();
+            match resolved {
+                Ok(resolved) => Some(resolved),
+                Err(e) => {
+                    error.get_or_insert(e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(resolved),
+    }
+}
+
+/// Scan a stored table, with its columns qualified by `alias` or, failing
+/// that, the table's name.
+fn table_scan(scope: Scope<'_>, name: &str, alias: Option<&str>) -> Result<PlanNode, EvalError> {
+    let table = scope
+        .db
+        .get_table(name)
+        .ok_or_else(|| DbError::NoSuchTable(name.to_string()))?;
+    Ok(PlanNode::TableScan {
+        table: table.name.clone(),
+        schema: table_schema(alias.unwrap_or(&table.name), &table.schema),
+    })
+}
+
+/// A table scan, followed by a projection computing the table's virtual
+/// columns if it has any. Filters moved down through the projection read
+/// their expressions instead.
+fn read_virtual_columns(db: &Database, scan: PlanNode) -> PlanNode {
+    let PlanNode::TableScan { table, schema } = &scan else {
+        return scan;
+    };
+    let Some(stored) = db.get_table(table) else {
+        return scan;
+    };
+    if !stored.schema.iter().any(ColumnDef::is_virtual) {
+        return scan;
+    }
+    let exprs = schema
+        .columns
+        .iter()
+        .map(|c| read_virtual(Expr::Column(c.table.clone(), c.name.clone()), stored))
+        .collect();
+    let schema = schema.clone();
+    PlanNode::Projection {
+        input: Box::new(scan),
+        exprs,
+        schema,
+    }
+}
+
+/// The shape of a table's rows, with its columns qualified by `qualifier`.
+fn table_schema(qualifier: &str, columns: &[ColumnDef]) -> Schema {
+    let columns = columns
+        .iter()
+        .map(|c| SchemaColumn {
+            table: Some(qualifier.to_string()),
+            name: c.name.clone(),
+            hidden: false,
+            collation: c.collation,
+        })
+        .collect();
+    Schema { columns }
+}
+
+/// How many views deep a query may read through views.
+pub const VIEW_DEPTH_LIMIT: usize = 50;
+
+/// Scan a view, with its columns qualified by `alias` or, failing that,
+/// `name`. Its query is planned as written, seeing no common table
+/// expressions of the query reading it.
+fn view_scan(
+    scope: Scope<'_>,
+    name: &str,
+    query: &SelectStmt,
+    alias: Option<&str>,
+) -> Result<PlanNode, EvalError> {
+    if scope.views == VIEW_DEPTH_LIMIT {
+        return Err(EvalError::ViewDepthLimit(name.to_string()));
+    }
+    let correlated = Cell::new(false);
+    let inner = Scope {
+        views: scope.views + 1,
+        ..Scope::new(scope.db, None, &correlated)
+    };
+    let input = plan_query(inner, query, |_, expr| expr.to_string())?;
+    let schema = qualify(input.schema(), alias.unwrap_or(name));
+    Ok(PlanNode::SubqueryScan {
+        input: Box::new(input),
+        schema,
+    })
+}
+
+/// Scan a common table expression, with its columns qualified by `alias`
+/// or, failing that, the name it was defined with.
+fn cte_scan(cte: &Arc<CommonTable>, alias: Option<&str>) -> PlanNode {
+    PlanNode::CteScan {
+        cte: Arc::clone(cte),
+        schema: qualify(&cte.schema, alias.unwrap_or(&cte.name)),
+    }
+}
+
+/// Scan the working table of the recursive step being planned, which must
+/// read it once, and not from a subquery.
+fn working_table_scan(
+    scope: Scope<'_>,
+    recursion: &Recursion<'_>,
+    alias: Option<&str>,
+) -> Result<PlanNode, EvalError> {
+    let name = &recursion.table.name;
+    if !std::ptr::eq(scope.correlated, recursion.query) {
+        return Err(EvalError::RecursiveReferenceInSubquery(name.clone()));
+    }
+    recursion.uses.set(recursion.uses.get() + 1);
+    if recursion.uses.get() > 1 {
+        return Err(EvalError::MultipleRecursiveReferences(name.clone()));
+    }
+    Ok(PlanNode::WorkingTableScan {
+        table: Arc::clone(recursion.table),
+        schema: qualify(recursion.schema, alias.unwrap_or(name)),
+    })
+}
+
+/// `schema` with every column qualified by `qualifier`.
+fn qualify(schema: &Schema, qualifier: &str) -> Schema {
+    let columns = schema
+        .columns
+        .iter()
+        .map(|c| SchemaColumn {
+            table: Some(qualifier.to_string()),
+            ..c.clone()
+        })
+        .collect();
+    Schema { columns }
+}
+
+/// `plan` with each filtered table scan within it made an index scan,
+/// where the filter allows.
+fn index_scans(db: &Database, mut plan: PlanNode) -> PlanNode {
+    if let PlanNode::Filter { input, predicate } = &mut plan
+        && let PlanNode::TableScan { .. } = **input
+    {
+        let scan = std::mem::replace(&mut **input, PlanNode::SingleRow);
+        **input = index_scan(db, scan, predicate);
+    }
+    for input in plan.inputs_mut() {
+        let node = std::mem::replace(input, PlanNode::SingleRow);
+        *input = index_scans(db, node);
+    }
+    plan
+}
+
+/// Read a scanned table through an index instead when `predicate`
+/// requires each of the index's columns to equal a value that does not
+/// depend on the row. The predicate is still applied to the rows found.
+fn index_scan(db: &Database, plan: PlanNode, predicate: &Expr) -> PlanNode {
+    let PlanNode::TableScan { table, schema } = &plan else {
+        return plan;
+    };
+    let Some(stored) = db.get_table(table) else {
+        return plan;
+    };
+    let mut conjuncts = vec![predicate];
+    let mut equalities = Vec::new();
+    while let Some(conjunct) = conjuncts.pop() {
+        let Expr::BinOp { op, left, right } = conjunct else {
+            continue;
+        };
+        match op {
+            BinOp::And => conjuncts.extend([&**left, &**right]),
+            BinOp::Eq => {
+                for (column, value) in [(left, right), (right, left)] {
+                    // The index only finds the rows the comparison matches
+                    // if texts compare under the column's collation.
+                    if let Expr::Column(qualifier, name) = &**column
+                        && let Ok(i) = schema.resolve(qualifier.as_deref(), name)
+                        && row_independent(value)
+                        && schema.comparison_collation(left, right) == schema.columns[i].collation
+                    {
+                        equalities.push((i, (**value).clone()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let value = |column: usize| {
+        equalities
+            .iter()
+            .find(|(i, _)| *i == column)
+            .map(|(_, value)| value.clone())
+    };
+    // Prefer a unique index, then the most selective key.
+    let best = stored
+        .indexes
+        .iter()
+        .filter(|index| index.columns.iter().all(|&c| value(c).is_some()))
+        .max_by_key(|index| (index.unique, index.columns.len()));
+    match best {
+        Some(index) => PlanNode::IndexScan {
+            table: table.clone(),
+            index: index.name.clone(),
+            key: index.columns.iter().filter_map(|&c| value(c)).collect(),
+            schema: schema.clone(),
+        },
+        None => plan,
+    }
+}
+
+/// Whether an expression has the same value for every row of the query it
+/// is in: it reads none of the row's columns, directly or through a
+/// correlated subquery.
+fn row_independent(expr: &Expr) -> bool {
+    let mut independent = true;
+    expr.transform(&mut |e| match e {
+        Expr::Column(..) => {
+            independent = false;
+            Some(e.clone())
+        }
+        Expr::Subquery { plan, .. } if plan.correlated => {
+            independent = false;
+            Some(e.clone())
+        }
+        _ => None,
+    });
+    independent
+}
+
+/// Plan a FROM clause item. Join conditions may refer to enclosing
+/// queries; derived tables are planned on their own.
+fn plan_table_ref(scope: Scope<'_>, table_ref: &TableRef) -> Result<PlanNode, EvalError> {
+    match table_ref {
+        TableRef::Table { name, alias } => {
+            if let Some(recursion) = scope.recursion
+                && recursion.table.name.eq_ignore_ascii_case(name)
+            {
+                return working_table_scan(scope, recursion, alias.as_deref());
+            }
+            match scope.cte(name) {
+                Some(cte) => Ok(cte_scan(cte, alias.as_deref())),
+                None if let Some(query) = scope.db.get_view(name) => {
+                    view_scan(scope, name, query, alias.as_deref())
+                }
+                None => Ok(read_virtual_columns(
+                    scope.db,
+                    table_scan(scope, name, alias.as_deref())?,
+                )),
+            }
+        }
+        TableRef::Subquery {
+            query,
+            alias,
+            columns: names,
+        } => {
+            // Unaliased expressions get positional names, as their text is
+            // no name to refer to them by from outside.
+            let correlated = Cell::new(false);
+            let inner = scope.detached(&correlated);
+            let input = plan_query(inner, query, |i, _| format!("col{}", i + 1))?;
+            let alias = alias.as_deref().unwrap_or("(subquery)");
+            let width = input.schema().columns.len();
+            if names.len() > width {
+                return Err(EvalError::CteColumnCount {
+                    table: alias.to_string(),
+                    values: width,
+                    columns: names.len(),
+                });
+            }
+            let columns = input
+                .schema()
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, c)| SchemaColumn {
+                    table: Some(alias.to_string()),
+                    name: names.get(i).unwrap_or(&c.name).clone(),
+                    hidden: false,
+                    collation: c.collation,
+                })
+                .collect();
+            Ok(PlanNode::SubqueryScan {
+                input: Box::new(input),
+                schema: Schema { columns },
+            })
+        }
+        TableRef::Values {
+            rows,
+            alias,
+            columns,
+        } => {
+            let width = rows[0].len();
+            if columns.len() > width {
+                return Err(EvalError::CteColumnCount {
+                    table: alias.clone().unwrap_or_default(),
+                    values: width,
+                    columns: columns.len(),
+                });
+            }
+            // Like a derived table, the rows read no enclosing query.
+            let correlated = Cell::new(false);
+            let inner = scope.detached(&correlated);
+            let rows = rows
+                .iter()
+                .map(|row| row.iter().map(|e| bind(e, inner)).collect())
+                .collect::<Result<_, _>>()?;
+            let columns = (0..width)
+                .map(|i| SchemaColumn {
+                    table: alias.clone(),
+                    name: columns
+                        .get(i)
+                        .cloned()
+                        .unwrap_or_else(|| format!("column{}", i + 1)),
+                    hidden: false,
+                    collation: Collation::Binary,
+                })
+                .collect();
+            Ok(PlanNode::Values {
+                rows,
+                schema: Schema { columns },
+            })
+        }
+        TableRef::Join {
+            left,
+            right,
+            kind,
+            constraint,
+        } => {
+            let left = plan_table_ref(scope, left)?;
+            let right = plan_table_ref(scope, right)?;
+            let mut columns = left.schema().columns.clone();
+            columns.extend(right.schema().columns.iter().cloned());
+            let predicate = match constraint {
+                None => None,
+                Some(JoinConstraint::On(on)) => Some(on.clone()),
+                Some(JoinConstraint::Using(names)) => {
+                    Some(using_predicate(&left, &right, *kind, names, &mut columns)?)
+                }
+                Some(JoinConstraint::Natural) => {
+                    let names = common_columns(left.schema(), right.schema());
+                    if names.is_empty() {
+                        return Err(EvalError::NoCommonColumns);
+                    }
+                    Some(using_predicate(&left, &right, *kind, &names, &mut columns)?)
+                }
+            };
+            let schema = Schema { columns };
+            let predicate = predicate
+                .map(|p| bind(&p, scope.with(&schema)))
+                .transpose()?;
+            Ok(PlanNode::NestedLoopJoin {
+                left: Box::new(left),
+                right: Box::new(right),
+                kind: *kind,
+                predicate,
+                schema,
+            })
+        }
+    }
+}
+
+/// Build the predicate equating each `USING` column of the two sides, and
+/// hide one copy of each in the join's `columns`. The copy kept is the one
+/// from the side whose rows are always present, so the left one unless
+/// only the right side is preserved.
+fn using_predicate(
+    left: &PlanNode,
+    right: &PlanNode,
+    kind: JoinKind,
+    names: &[String],
+    columns: &mut [SchemaColumn],
+) -> Result<Expr, EvalError> {
+    let side = |schema: &Schema, name: &str| {
+        schema.resolve(None, name).map_err(|e| match e {
+            EvalError::NoSuchColumn(_) => EvalError::UsingColumnNotInBothTables(name.to_string()),
+            e => e,
+        })
+    };
+    let left_width = left.schema().columns.len();
+    let mut predicate: Option<Expr> = None;
+    for name in names {
+        let l = side(left.schema(), name)?;
+        let r = side(right.schema(), name)?;
+        let column = |c: &SchemaColumn| Expr::Column(c.table.clone(), c.name.clone());
+        let eq = Expr::BinOp {
+            op: BinOp::Eq,
+            left: Box::new(column(&columns[l])),
+            right: Box::new(column(&columns[left_width + r])),
+        };
+        let hidden = if kind == JoinKind::Right {
+            l
+        } else {
+            left_width + r
+        };
+        columns[hidden].hidden = true;
+        predicate = Some(match predicate {
+            Some(p) => Expr::BinOp {
+                op: BinOp::And,
+                left: Box::new(p),
+                right: Box::new(eq),
+            },
+            None => eq,
+        });
+    }
+    Ok(predicate.expect("USING lists at least one column"))
+}
+
+/// The names of the columns both sides can be referred to by unqualified,
+/// in left-side order.
+fn common_columns(left: &Schema, right: &Schema) -> Vec<String> {
+    left.columns
+        .iter()
+        .filter(|c| !c.hidden && right.resolve(None, &c.name).is_ok())
+        .map(|c| c.name.clone())
+        .collect()
+}
+
+/// Append a column reference for every input column (of `table`, if given).
+/// Returns false if no input column belongs to `table`.
+fn expand_wildcard(
+    input: &Schema,
+    table: Option<&str>,
+    exprs: &mut Vec<Expr>,
+    columns: &mut Vec<SchemaColumn>,
+) -> bool {
+    let mut found = false;
+    for column in &input.columns {
+        let belongs = match table {
+            Some(t) => column
+                .table
+                .as_deref()
+                .is_some_and(|ct| ct.eq_ignore_ascii_case(t)),
+            None => !column.hidden,
+        };
+        if belongs {
+            found = true;
+            exprs.push(Expr::Column(column.table.clone(), column.name.clone()));
+            columns.push(SchemaColumn {
+                table: None,
+                name: column.name.clone(),
+                hidden: false,
+                collation: column.collation,
+            });
+        }
+    }
+    found
+}
+
+/// Check that every column an expression refers to resolves in `scope`,
+/// binding those of enclosing queries as outer references, that every
+/// function it calls exists and takes its arguments, and plan the
+/// subqueries it contains.
+fn bind(expr: &Expr, scope: Scope<'_>) -> Result<Expr, EvalError> {
+    let mut error = None;
+    let bound = expr.transform(&mut |e| {
+        let bound = match e {
+            Expr::Column(table, name) => scope.resolve(table.as_deref(), name),
+            Expr::FunctionCall { name, args } => FunctionRegistry::global()
+                .lookup(name, args.len())
+                .map(|_| None),
+            Expr::ScalarSubquery(query) => plan_subquery(query, scope, Some(1)).map(|plan| {
+                Some(Expr::Subquery {
+                    kind: SubqueryKind::Scalar,
+                    plan,
+                })
+            }),
+            Expr::Exists(query) => plan_subquery(query, scope, None).map(|plan| {
+                Some(Expr::Subquery {
+                    kind: SubqueryKind::Exists,
+                    plan,
+                })
+            }),
+            Expr::InSubquery {
+                expr,
+                subquery,
+                negated,
+            } => bind_row(expr, scope).and_then(|bound| {
+                let plan = plan_subquery(subquery, scope, Some(row_width(expr)))?;
+                Ok(Some(Expr::Subquery {
+                    kind: SubqueryKind::In {
+                        expr: Box::new(bound),
+                        negated: *negated,
+                    },
+                    plan,
+                }))
+            }),
+            Expr::QuantifiedComparison {
+                expr,
+                op,
+                quantifier,
+                subquery,
+            } => bind(expr, scope).and_then(|bound| {
+                let plan = plan_subquery(subquery, scope, Some(1))?;
+                Ok(Some(Expr::Subquery {
+                    kind: SubqueryKind::Quantified {
+                        expr: Box::new(bound),
+                        op: *op,
+                        quantifier: *quantifier,
+                    },
+                    plan,
+                }))
+            }),
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let width = row_width(expr);
+                std::iter::once(&**expr)
+                    .chain(list)
+                    .map(|item| match row_width(item) == width {
+                        true => bind_row(item, scope),
+                        false => Err(EvalError::RowValueMisused),
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|mut items| {
+                        let expr = Box::new(items.remove(0));
+                        Some(Expr::InList {
+                            expr,
+                            list: items,
+                            negated: *negated,
+                        })
+                    })
+            }
+            Expr::Tuple(_) => Err(EvalError::RowValueMisused),
+            _ => Ok(None),
+        };
+        bound.unwrap_or_else(|err| {
+            error.get_or_insert(err);
+            Some(e.clone())
+        })
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(bound),
+    }
+}
+
+/// Bind one side of an IN: a row value's items one by one, or else a
+/// single expression.
+fn bind_row(expr: &Expr, scope: Scope<'_>) -> Result<Expr, EvalError> {
+    match expr {
+        Expr::Tuple(items) => items
+            .iter()
+            .map(|item| bind(item, scope))
+            .collect::<Result<_, _>>()
+            .map(Expr::Tuple),
+        expr => bind(expr, scope),
+    }
+}
+
+/// The number of values an expression is: a row value's item count, else
+/// one.
+fn row_width(expr: &Expr) -> usize {
+    match expr {
+        Expr::Tuple(items) => items.len(),
+        _ => 1,
+    }
+}
+
+/// Plan a subquery nested in `scope`, which must have `width` columns. An
+/// EXISTS subquery only tells whether it has rows, so it may have any
+/// number of columns, and passes `None`.
+fn plan_subquery(
+    query: &SelectStmt,
+    scope: Scope<'_>,
+    width: Option<usize>,
+) -> Result<SubqueryPlan, EvalError> {
+    let correlated = Cell::new(false);
+    let inner = Scope::new(scope.db, Some(&scope), &correlated);
+    let plan = plan_query(inner, query, |_, expr| expr.to_string())?;
+    let plan = match (plan.schema().columns.len(), width) {
+        (_, None) => PlanNode::Exists {
+            input: Box::new(plan),
+            schema: Schema {
+                columns: vec![SchemaColumn {
+                    table: None,
+                    name: "exists".to_string(),
+                    hidden: false,
+                    collation: Collation::Binary,
+                }],
+            },
+        },
+        (columns, Some(expected)) if columns == expected => plan,
+        (columns, Some(expected)) => {
+            return Err(EvalError::SubqueryColumnCount { columns, expected });
+        }
+    };
+    Ok(SubqueryPlan::new(query.clone(), plan, correlated.get()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Statement;
+    use crate::parser::parse_statement;
+    use crate::storage::ColumnDef;
+
+    fn db() -> Database {
+        let mut db = Database::new();
+        let columns = |names: &[&str]| names.iter().map(|n| ColumnDef::new(*n, None)).collect();
+        db.create_table("users", columns(&["id", "name"])).unwrap();
+        db
+    }
+
+    fn plan(db: &Database, sql: &str) -> Result<PlanNode, EvalError> {
+        match parse_statement(sql).unwrap() {
+            Statement::Select(select) => plan_select(db, &select),
+            other => panic!("not a SELECT: {other}"),
+        }
+    }
+
+    #[test]
+    fn test_cte_is_planned_once_for_all_scans() {
+        let db = db();
+        let plan = plan(
+            &db,
+            "WITH u AS (SELECT id FROM users) SELECT * FROM u JOIN u AS v ON u.id = v.id",
+        )
+        .unwrap();
+        let PlanNode::Projection { input, .. } = plan else {
+            panic!("expected a projection");
+        };
+        let PlanNode::NestedLoopJoin { left, right, .. } = *input else {
+            panic!("expected a join");
+        };
+        let (PlanNode::CteScan { cte: a, .. }, PlanNode::CteScan { cte: b, schema }) =
+            (*left, *right)
+        else {
+            panic!("expected CTE scans");
+        };
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(schema.columns[0].table.as_deref(), Some("v"));
+    }
+
+    #[test]
+    fn test_equality_on_indexed_column_uses_index() {
+        let mut db = db();
+        db.create_index("users", "by_id", vec![0], false).unwrap();
+        let scanned = |sql: &str| {
+            let PlanNode::Projection { input, .. } = plan(&db, sql).unwrap() else {
+                panic!("expected a projection");
+            };
+            let PlanNode::Filter { input, .. } = *input else {
+                panic!("expected a filter");
+            };
+            match *input {
+                PlanNode::IndexScan { index, key, .. } => {
+                    let key: Vec<String> = key.iter().map(ToString::to_string).collect();
+                    format!("{index}: {}", key.join(", "))
+                }
+                PlanNode::TableScan { .. } => "full scan".to_string(),
+                other => panic!("unexpected scan {other:?}"),
+            }
+        };
+        assert_eq!(
+            scanned("SELECT * FROM users WHERE name = 'x' AND 1 + 1 = users.id"),
+            "by_id: 1 + 1"
+        );
+        assert_eq!(
+            scanned("SELECT * FROM users WHERE id = (SELECT 2)"),
+            "by_id: (SELECT 2)"
+        );
+        assert_eq!(
+            scanned("SELECT * FROM users WHERE id = id + 0"),
+            "full scan"
+        );
+        assert_eq!(
+            scanned("SELECT * FROM users WHERE id = 1 OR name = 'x'"),
+            "full scan"
+        );
+        assert_eq!(scanned("SELECT * FROM users WHERE name = 'x'"), "full scan");
+    }
+
+    fn sort_keys(plan: &PlanNode) -> Vec<String> {
+        let PlanNode::Projection { input, .. } = plan else {
+            panic!("expected a projection");
+        };
+        let PlanNode::Sort { keys, .. } = input.as_ref() else {
+            panic!("expected a sort");
+        };
+        keys.iter()
+            .map(|k| format!("{} {} {}", k.expr, k.desc, k.nulls_first))
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_keys_resolve_aliases_and_ordinals() {
+        let db = db();
+        let sorted = plan(
+            &db,
+            "SELECT id AS name, name AS label, id * 2 AS twice FROM users \
+             ORDER BY name, label DESC, twice + 1, 3 NULLS FIRST",
+        )
+        .unwrap();
+        // `name` is a real column, so it wins over the alias.
+        assert_eq!(
+            sort_keys(&sorted),
+            [
+                "name false false",
+                "name true true",
+                "id * 2 + 1 false false",
+                "id * 2 false true",
+            ]
+        );
+        let err = |sql| plan(&db, sql).unwrap_err().to_string();
+        assert_eq!(
+            err("SELECT id FROM users ORDER BY 2"),
+            "ORDER BY term out of range - should be between 1 and 1"
+        );
+        assert_eq!(
+            err("SELECT id FROM users ORDER BY nope"),
+            "no such column: nope"
+        );
+    }
+
+    fn output(plan: &PlanNode) -> Vec<String> {
+        plan.schema()
+            .columns
+            .iter()
+            .map(|c| c.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_reports_ambiguity() {
+        let schema = Schema {
+            columns: vec![
+                SchemaColumn {
+                    table: Some("a".into()),
+                    name: "id".into(),
+                    hidden: false,
+                    collation: Collation::Binary,
+                },
+                SchemaColumn {
+                    table: Some("b".into()),
+                    name: "ID".into(),
+                    hidden: false,
+                    collation: Collation::Binary,
+                },
+            ],
+        };
+        assert_eq!(schema.resolve(Some("B"), "id"), Ok(1));
+        assert_eq!(
+            schema.resolve(None, "id"),
+            Err(EvalError::AmbiguousColumn("id".into()))
+        );
+        assert_eq!(
+            schema.resolve(Some("c"), "id"),
+            Err(EvalError::NoSuchColumn("c.id".into()))
+        );
+    }
+
+    #[test]
+    fn test_resolve_skips_hidden_columns_unless_qualified() {
+        let mut schema = Schema {
+            columns: ["a", "b"]
+                .into_iter()
+                .map(|t| SchemaColumn {
+                    table: Some(t.into()),
+                    name: "id".into(),
+                    hidden: false,
+                    collation: Collation::Binary,
+                })
+                .collect(),
+        };
+        schema.columns[1].hidden = true;
+        assert_eq!(schema.resolve(None, "id"), Ok(0));
+        assert_eq!(schema.resolve(Some("b"), "id"), Ok(1));
+    }
+
+    #[test]
+    fn test_projection_names() {
+        let db = db();
+        let plan = plan(&db, "SELECT *, u.*, name AS n, id + 1 FROM users AS u").unwrap();
+        assert_eq!(output(&plan), ["id", "name", "id", "name", "n", "id + 1"]);
+    }
+
+    #[test]
+    fn test_planning_errors() {
+        let db = db();
+        let err = |sql| plan(&db, sql).unwrap_err().to_string();
+        assert_eq!(err("SELECT nope FROM users"), "no such column: nope");
+        assert_eq!(
+            err("SELECT users.id FROM users u"),
+            "no such column: users.id"
+        );
+        assert_eq!(err("SELECT x.* FROM users"), "no such table: x");
+        assert_eq!(err("SELECT * FROM missing"), "no such table: missing");
+        assert_eq!(err("SELECT *"), "no tables specified");
+    }
+
+    #[test]
+    fn test_subqueries_bind_outer_references() {
+        let db = db();
+        let correlated = |sql| {
+            let PlanNode::Projection { input, .. } = plan(&db, sql).unwrap() else {
+                panic!("expected a projection");
+            };
+            let PlanNode::Filter { predicate, .. } = *input else {
+                panic!("expected a filter");
+            };
+            let Expr::Subquery { plan, .. } = predicate else {
+                panic!("expected a subquery");
+            };
+            plan.correlated
+        };
+        assert!(!correlated(
+            "SELECT id FROM users WHERE EXISTS (SELECT id FROM users)"
+        ));
+        assert!(correlated(
+            "SELECT id FROM users u WHERE EXISTS (SELECT id FROM users WHERE id < u.id)"
+        ));
+        assert!(correlated(
+            "SELECT id FROM users u WHERE EXISTS (SELECT 1 WHERE name = 'x')"
+        ));
+    }
+}
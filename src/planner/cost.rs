@@ -0,0 +1,516 @@
+//! Estimates of how many rows a plan produces and how much work it takes.
+//!
+//! Row counts come from the stored tables as they are when the query is
+//! planned. How many rows a predicate holds for is estimated from the
+//! statistics `ANALYZE` collected on the columns it compares; without
+//! them, each conjunct is assumed to hold for a fixed fraction of rows.
+
+use std::cmp::Ordering;
+
+use crate::ast::{BinOp, Expr, JoinKind, SetOperator};
+use crate::storage::{ColumnStats, Database};
+use crate::types::{Value, sql_compare};
+
+use super::{PlanNode, row_independent};
+
+/// The fraction of rows a conjunct of a predicate is assumed to hold for
+/// when there are no statistics to tell.
+pub const SELECTIVITY: f64 = 0.25;
+
+/// The estimated size of a plan's output and the work of producing it, in
+/// rows read or compared. Either is NaN if it cannot be estimated, as for
+/// the rows of a recursive step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cost {
+    pub rows: f64,
+    pub cpu: f64,
+}
+
+impl Cost {
+    /// The cost of producing `rows` rows one at a time.
+    fn scan(rows: f64) -> Cost {
+        Cost { rows, cpu: rows }
+    }
+
+    /// This plan's rows passed through a step doing `work` more.
+    fn then(self, rows: f64, work: f64) -> Cost {
+        Cost {
+            rows,
+            cpu: self.cpu + work,
+        }
+    }
+}
+
+pub fn estimate_cost(node: &PlanNode, db: &Database) -> Cost {
+    let cost = |node: &PlanNode| estimate_cost(node, db);
+    match node {
+        PlanNode::SingleRow => Cost::scan(1.0),
+        PlanNode::Values { rows, .. } => Cost::scan(rows.len() as f64),
+        PlanNode::TableScan { table, .. } => Cost::scan(table_rows(db, table)),
+        PlanNode::IndexScan {
+            table,
+            index,
+            schema,
+            ..
+        } => {
+            let total = table_rows(db, table);
+            let Some(index) = db.get_index(index) else {
+                return Cost::scan(total);
+            };
+            let rows = if index.unique {
+                total.min(1.0)
+            } else {
+                let matched: f64 = index
+                    .columns
+                    .iter()
+                    .map(|&c| match db.column_stats(table, &schema.columns[c].name) {
+                        Some(stats) => equal_fraction(stats),
+                        None => SELECTIVITY,
+                    })
+                    .product();
+                total * matched
+            };
+            Cost {
+                rows,
+                cpu: total.max(1.0).log2() + rows,
+            }
+        }
+        PlanNode::CteScan { cte, .. } => cost(&cte.plan),
+        PlanNode::WorkingTableScan { .. } => Cost::scan(f64::NAN),
+        PlanNode::SubqueryScan { input, .. } => cost(input),
+        PlanNode::NestedLoopJoin {
+            left,
+            right,
+            kind,
+            predicate,
+            ..
+        } => join_cost(
+            cost(left),
+            cost(right),
+            *kind,
+            predicate.as_ref().map_or(1.0, |p| selectivity(p, node, db)),
+        ),
+        PlanNode::HashJoin(join) => {
+            let (left, right) = join.sides();
+            let fraction = selectivity(&join.predicate(), node, db);
+            keyed_join_cost(cost(left), cost(right), join.kind, fraction)
+        }
+        PlanNode::SortMergeJoin(join) => {
+            let fraction = selectivity(&join.predicate(), node, db);
+            keyed_join_cost(cost(&join.left), cost(&join.right), join.kind, fraction)
+        }
+        // Each outer row costs a lookup, rather than a pass over the table.
+        PlanNode::IndexNestedLoopJoin(join) => {
+            let outer = cost(&join.outer);
+            let inner = cost(&join.inner());
+            let lookup = cost(&PlanNode::IndexScan {
+                table: join.inner_table.clone(),
+                index: join.inner_index.clone(),
+                key: join.outer_key.clone(),
+                schema: join.inner_schema.clone(),
+            });
+            let (left, right) = if join.inner_left {
+                (inner, outer)
+            } else {
+                (outer, inner)
+            };
+            let fraction = selectivity(&join.predicate(), node, db);
+            let rows = join_rows(left, right, join.kind, fraction);
+            Cost {
+                rows,
+                cpu: outer.cpu + outer.rows * lookup.cpu + rows,
+            }
+        }
+        PlanNode::Filter {
+            input: rows,
+            predicate,
+        } => {
+            let input = cost(rows);
+            let fraction = selectivity(predicate, rows, db);
+            input.then(input.rows * fraction, input.rows)
+        }
+        PlanNode::Sort { input, .. } | PlanNode::Window { input, .. } => {
+            let input = cost(input);
+            input.then(input.rows, sort_work(input.rows))
+        }
+        PlanNode::Projection { input, .. } | PlanNode::Distinct { input } => {
+            let input = cost(input);
+            input.then(input.rows, input.rows)
+        }
+        PlanNode::Aggregate {
+            input, group_by, ..
+        } => {
+            // Without statistics, every row may be a group of its own.
+            let input = cost(input);
+            let groups = if group_by.is_empty() { 1.0 } else { input.rows };
+            input.then(groups, input.rows)
+        }
+        PlanNode::Exists { input, .. } => cost(input).then(1.0, 0.0),
+        PlanNode::SetOperation { op, left, right } => {
+            let (left, right) = (cost(left), cost(right));
+            let rows = match op {
+                SetOperator::Union | SetOperator::UnionAll => left.rows + right.rows,
+                SetOperator::Intersect => left.rows.min(right.rows),
+                SetOperator::Except => left.rows,
+            };
+            Cost {
+                rows,
+                cpu: left.cpu + right.cpu + left.rows + right.rows,
+            }
+        }
+        PlanNode::Limit {
+            input,
+            limit,
+            offset,
+        } => {
+            let input = cost(input);
+            let mut rows = (input.rows - *offset as f64).max(0.0);
+            if let Some(limit) = limit {
+                rows = rows.min(*limit as f64);
+            }
+            input.then(rows, 0.0)
+        }
+    }
+}
+
+/// The cost of a nested loop join of two inputs, whose condition holds for
+/// the fraction `selectivity` of the pairs. The right rows are read once
+/// and compared with each left row; each left row also takes a step of its
+/// own, so of two inputs the smaller is better on the left.
+pub fn join_cost(left: Cost, right: Cost, kind: JoinKind, selectivity: f64) -> Cost {
+    let pairs = left.rows * right.rows;
+    Cost {
+        rows: join_rows(left, right, kind, selectivity),
+        cpu: left.cpu + right.cpu + left.rows + pairs,
+    }
+}
+
+/// The cost of a hash or sort-merge join: each input row is hashed or
+/// stepped past once, and each output row found once. Sorting the inputs
+/// of a merge is costed by the sorts that do it.
+fn keyed_join_cost(left: Cost, right: Cost, kind: JoinKind, selectivity: f64) -> Cost {
+    let rows = join_rows(left, right, kind, selectivity);
+    Cost {
+        rows,
+        cpu: left.cpu + right.cpu + left.rows + right.rows + rows,
+    }
+}
+
+/// The rows of a join whose condition holds for the fraction
+/// `selectivity` of pairs, with the unmatched rows `kind` preserves.
+fn join_rows(left: Cost, right: Cost, kind: JoinKind, selectivity: f64) -> f64 {
+    let matched = left.rows * right.rows * selectivity;
+    match kind {
+        JoinKind::Inner | JoinKind::Cross => matched,
+        JoinKind::Left => matched.max(left.rows),
+        JoinKind::Right => matched.max(right.rows),
+        JoinKind::Full => matched.max(left.rows + right.rows),
+    }
+}
+
+/// The fraction of the rows of `input` that `predicate` is estimated to
+/// hold for, taking its conjuncts to be independent.
+pub fn selectivity(predicate: &Expr, input: &PlanNode, db: &Database) -> f64 {
+    conjuncts(predicate)
+        .into_iter()
+        .map(|term| term_selectivity(term, input, db))
+        .product()
+}
+
+fn term_selectivity(term: &Expr, input: &PlanNode, db: &Database) -> f64 {
+    estimated_selectivity(term, input, db).unwrap_or(SELECTIVITY)
+}
+
+fn estimated_selectivity(term: &Expr, input: &PlanNode, db: &Database) -> Option<f64> {
+    let stats = |expr: &Expr| {
+        let (table, column) = column_source(expr, input)?;
+        db.column_stats(table, column)
+    };
+    match term {
+        Expr::BinOp { op, left, right } => match (op, stats(left), stats(right)) {
+            // Each value of the column with fewer of them is taken to
+            // match one of the other.
+            (BinOp::Eq, Some(l), Some(r)) => {
+                Some(1.0 / l.distinct_count.max(r.distinct_count).max(1) as f64)
+            }
+            (BinOp::Eq, Some(stats), None) if row_independent(right) => Some(equal_fraction(stats)),
+            (BinOp::Eq, None, Some(stats)) if row_independent(left) => Some(equal_fraction(stats)),
+            (BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge, Some(stats), None) => {
+                literal(right).map(|value| range_fraction(stats, *op, value))
+            }
+            (BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge, None, Some(stats)) => {
+                let flipped = match op {
+                    BinOp::Lt => BinOp::Gt,
+                    BinOp::Le => BinOp::Ge,
+                    BinOp::Gt => BinOp::Lt,
+                    _ => BinOp::Le,
+                };
+                literal(left).map(|value| range_fraction(stats, flipped, value))
+            }
+            _ => None,
+        },
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated,
+        } => match (stats(expr), literal(low), literal(high)) {
+            (Some(stats), Some(low), Some(high)) => {
+                let below = range_fraction(stats, BinOp::Lt, low);
+                let within = (range_fraction(stats, BinOp::Le, high) - below).max(0.0);
+                Some(if *negated { 1.0 - within } else { within })
+            }
+            _ => None,
+        },
+        Expr::IsNull { expr, negated } => {
+            let (table, column) = column_source(expr, input)?;
+            let stats = db.column_stats(table, column)?;
+            let nulls = (stats.null_count as f64 / table_rows(db, table).max(1.0)).min(1.0);
+            Some(if *negated { 1.0 - nulls } else { nulls })
+        }
+        _ => None,
+    }
+}
+
+/// The fraction of a column's rows equal to a given value.
+fn equal_fraction(stats: &ColumnStats) -> f64 {
+    1.0 / stats.distinct_count.max(1) as f64
+}
+
+/// The fraction of a column's values other than NULL that compare with
+/// `value` by `op`, counted in buckets of its histogram.
+fn range_fraction(stats: &ColumnStats, op: BinOp, value: &Value) -> f64 {
+    if stats.histogram.is_empty() {
+        return 0.0;
+    }
+    let below = |inclusive: bool| {
+        let count = stats
+            .histogram
+            .iter()
+            .filter(|bound| match sql_compare(bound, value) {
+                Some(Ordering::Less) => true,
+                Some(Ordering::Equal) => inclusive,
+                _ => false,
+            })
+            .count();
+        count as f64 / stats.histogram.len() as f64
+    };
+    match op {
+        BinOp::Lt => below(false),
+        BinOp::Le => below(true),
+        BinOp::Gt => 1.0 - below(true),
+        _ => 1.0 - below(false),
+    }
+}
+
+fn literal(expr: &Expr) -> Option<&Value> {
+    match expr {
+        Expr::Literal(value) if !value.is_null() => Some(value),
+        _ => None,
+    }
+}
+
+/// The stored table and column `expr` reads, if it is a column of `input`
+/// that comes unchanged from a table.
+fn column_source<'a>(expr: &Expr, input: &'a PlanNode) -> Option<(&'a str, &'a str)> {
+    let Expr::Column(table, name) = expr else {
+        return None;
+    };
+    let i = input.schema().resolve(table.as_deref(), name).ok()?;
+    source_column(input, i)
+}
+
+/// The stored table and column that column `i` of `node` is read from.
+fn source_column(node: &PlanNode, i: usize) -> Option<(&str, &str)> {
+    match node {
+        PlanNode::TableScan { table, schema } | PlanNode::IndexScan { table, schema, .. } => {
+            Some((table, &schema.columns[i].name))
+        }
+        PlanNode::Filter { input, .. }
+        | PlanNode::Sort { input, .. }
+        | PlanNode::Distinct { input }
+        | PlanNode::Limit { input, .. }
+        | PlanNode::SubqueryScan { input, .. } => source_column(input, i),
+        PlanNode::CteScan { cte, .. } if cte.step.is_none() => source_column(&cte.plan, i),
+        PlanNode::NestedLoopJoin { left, right, .. } => join_source_column(left, right, i),
+        PlanNode::HashJoin(join) => {
+            let (left, right) = join.sides();
+            join_source_column(left, right, i)
+        }
+        PlanNode::SortMergeJoin(join) => join_source_column(&join.left, &join.right, i),
+        PlanNode::IndexNestedLoopJoin(join) => {
+            let width = if join.inner_left {
+                join.inner_schema.columns.len()
+            } else {
+                join.outer.schema().columns.len()
+            };
+            match (i < width, join.inner_left) {
+                (true, false) => source_column(&join.outer, i),
+                (false, true) => source_column(&join.outer, i - width),
+                (true, true) => Some((&join.inner_table, &join.inner_schema.columns[i].name)),
+                (false, false) => Some((
+                    &join.inner_table,
+                    &join.inner_schema.columns[i - width].name,
+                )),
+            }
+        }
+        PlanNode::Projection { input, exprs, .. } => match &exprs[i] {
+            Expr::Column(table, name) => {
+                source_column(input, input.schema().resolve(table.as_deref(), name).ok()?)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The terms of a predicate that must all hold, in order.
+pub fn conjuncts(predicate: &Expr) -> Vec<&Expr> {
+    match predicate {
+        Expr::BinOp {
+            op: BinOp::And,
+            left,
+            right,
+        } => {
+            let mut terms = conjuncts(left);
+            terms.extend(conjuncts(right));
+            terms
+        }
+        predicate => vec![predicate],
+    }
+}
+
+fn table_rows(db: &Database, table: &str) -> f64 {
+    db.get_table(table).map_or(0.0, |t| t.rows.len() as f64)
+}
+
+fn join_source_column<'a>(
+    left: &'a PlanNode,
+    right: &'a PlanNode,
+    i: usize,
+) -> Option<(&'a str, &'a str)> {
+    let width = left.schema().columns.len();
+    match i.checked_sub(width) {
+        Some(i) => source_column(right, i),
+        None => source_column(left, i),
+    }
+}
+
+/// The comparisons needed to sort `rows` rows.
+fn sort_work(rows: f64) -> f64 {
+    if rows > 1.0 { rows * rows.log2() } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Statement;
+    use crate::executor::execute;
+    use crate::parser::parse_statement;
+    use crate::planner::plan_select;
+
+    fn cost(db: &mut Database, sql: &str) -> Cost {
+        let Statement::Select(select) = parse_statement(sql).unwrap() else {
+            panic!("expected SELECT");
+        };
+        estimate_cost(&plan_select(db, &select).unwrap(), db)
+    }
+
+    fn run(db: &mut Database, sql: &str) {
+        execute(db, &parse_statement(sql).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_scan_cost_scales_with_table_size() {
+        let mut db = Database::new();
+        run(&mut db, "CREATE TABLE t (x INTEGER)");
+        assert_eq!(cost(&mut db, "SELECT * FROM t").rows, 0.0);
+        run(&mut db, "INSERT INTO t VALUES (1), (2), (3), (4)");
+        let small = cost(&mut db, "SELECT * FROM t");
+        assert_eq!(small.rows, 4.0);
+        for _ in 0..3 {
+            run(&mut db, "INSERT INTO t SELECT * FROM t");
+        }
+        let large = cost(&mut db, "SELECT * FROM t");
+        assert_eq!(large.rows, 32.0);
+        assert!(large.cpu > small.cpu * 4.0);
+        // A filter is assumed to keep a fixed fraction of its input.
+        assert_eq!(cost(&mut db, "SELECT * FROM t WHERE x = 1").rows, 8.0);
+        // Sorting costs more than linear time.
+        let sorted = cost(&mut db, "SELECT * FROM t ORDER BY x");
+        assert_eq!(sorted.cpu - large.cpu, 32.0 * 5.0);
+        assert_eq!(cost(&mut db, "SELECT * FROM t LIMIT 5 OFFSET 30").rows, 2.0);
+    }
+
+    #[test]
+    fn test_index_scan_cost_is_logarithmic() {
+        let mut db = Database::new();
+        run(
+            &mut db,
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, x INTEGER)",
+        );
+        run(
+            &mut db,
+            "INSERT INTO t VALUES (1, 1), (2, 1), (3, 2), (4, 2)",
+        );
+        for i in 0..6 {
+            run(
+                &mut db,
+                &format!("INSERT INTO t SELECT id + {}, x FROM t", 4 << i),
+            );
+        }
+        let lookup = cost(&mut db, "SELECT * FROM t WHERE id = 7");
+        let scan = cost(&mut db, "SELECT * FROM t WHERE x = 7");
+        assert_eq!(lookup.rows, 0.25);
+        assert!(lookup.cpu < 20.0);
+        assert!(scan.cpu > 256.0);
+    }
+
+    #[test]
+    fn test_selectivity_from_statistics() {
+        let mut db = Database::new();
+        run(&mut db, "CREATE TABLE a (x INTEGER)");
+        run(&mut db, "CREATE TABLE b (y INTEGER)");
+        for x in 1..=100 {
+            run(&mut db, &format!("INSERT INTO a VALUES ({x})"));
+            run(&mut db, &format!("INSERT INTO b VALUES ({})", x % 20));
+        }
+        run(&mut db, "ANALYZE");
+        assert_eq!(cost(&mut db, "SELECT * FROM a WHERE x = 5").rows, 1.0);
+        assert_eq!(cost(&mut db, "SELECT * FROM a WHERE 5 = x").rows, 1.0);
+        assert_eq!(cost(&mut db, "SELECT * FROM b WHERE y = 5").rows, 5.0);
+        assert_eq!(cost(&mut db, "SELECT * FROM a WHERE x <= 30").rows, 30.0);
+        assert_eq!(cost(&mut db, "SELECT * FROM a WHERE 30 < x").rows, 70.0);
+        assert_eq!(
+            cost(&mut db, "SELECT * FROM a WHERE x BETWEEN 21 AND 60").rows,
+            40.0
+        );
+        assert_eq!(
+            cost(&mut db, "SELECT * FROM a WHERE x = 5 AND x > 50").rows,
+            0.5
+        );
+        // A join on two columns matches each row of the one with more
+        // values once.
+        assert_eq!(
+            cost(&mut db, "SELECT * FROM a JOIN b ON a.x = b.y").rows,
+            100.0
+        );
+        // Comparisons the statistics say nothing about get the default.
+        assert_eq!(
+            cost(&mut db, "SELECT * FROM a WHERE x % 2 = 0").rows,
+            100.0 * SELECTIVITY
+        );
+    }
+
+    #[test]
+    fn test_join_cost_prefers_the_smaller_input_on_the_left() {
+        let small = Cost::scan(2.0);
+        let large = Cost::scan(100.0);
+        let small_left = join_cost(small, large, JoinKind::Inner, SELECTIVITY);
+        let large_left = join_cost(large, small, JoinKind::Inner, SELECTIVITY);
+        assert_eq!(small_left.rows, large_left.rows);
+        assert!(small_left.cpu < large_left.cpu);
+        let left = join_cost(large, Cost::scan(0.0), JoinKind::Left, 1.0);
+        assert_eq!(left.rows, 100.0);
+    }
+}
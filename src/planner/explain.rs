@@ -0,0 +1,223 @@
+//! `EXPLAIN` output: a plan as an indented tree of one-line descriptions.
+
+use std::fmt::{self, Write};
+
+use super::cost::estimate_cost;
+use super::{AggregateCall, PlanNode, SortKey, WindowCall};
+use crate::executor::WindowFunction;
+use crate::storage::Database;
+
+/// Describe `node` and its inputs, one line per node. A node's inputs
+/// follow it on lines indented two spaces further; the first line is
+/// indented by `indent` levels. Each line ends with the node's estimated
+/// row count and cost in `db`, `?` where they are unknown.
+pub fn explain(node: &PlanNode, db: &Database, indent: usize) -> String {
+    let mut out = String::new();
+    write_node(&mut out, node, db, indent);
+    out
+}
+
+fn write_node(out: &mut String, node: &PlanNode, db: &Database, depth: usize) {
+    let cost = estimate_cost(node, db);
+    let _ = writeln!(
+        out,
+        "{:width$}{} rows={} cost={}",
+        "",
+        Node(node),
+        Estimate(cost.rows),
+        Estimate(cost.cpu),
+        width = depth * 2
+    );
+    for input in inputs(node) {
+        write_node(out, input, db, depth + 1);
+    }
+}
+
+/// An estimate to two decimal places, with no trailing zeros.
+struct Estimate(f64);
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_nan() {
+            return write!(f, "?");
+        }
+        let text = format!("{:.2}", self.0);
+        write!(f, "{}", text.trim_end_matches('0').trim_end_matches('.'))
+    }
+}
+
+/// The nodes `node` reads rows from, in the order it reads them.
+fn inputs(node: &PlanNode) -> Vec<&PlanNode> {
+    match node {
+        PlanNode::SingleRow
+        | PlanNode::Values { .. }
+        | PlanNode::TableScan { .. }
+        | PlanNode::IndexScan { .. }
+        | PlanNode::WorkingTableScan { .. } => Vec::new(),
+        PlanNode::CteScan { cte, .. } => std::iter::once(&cte.plan)
+            .chain(cte.step.as_ref().map(|step| &step.plan))
+            .collect(),
+        PlanNode::SubqueryScan { input, .. }
+        | PlanNode::Filter { input, .. }
+        | PlanNode::Sort { input, .. }
+        | PlanNode::Projection { input, .. }
+        | PlanNode::Aggregate { input, .. }
+        | PlanNode::Window { input, .. }
+        | PlanNode::Distinct { input }
+        | PlanNode::Exists { input, .. }
+        | PlanNode::Limit { input, .. } => vec![input],
+        PlanNode::NestedLoopJoin { left, right, .. }
+        | PlanNode::SetOperation { left, right, .. } => {
+            vec![left, right]
+        }
+        PlanNode::HashJoin(join) => {
+            let (left, right) = join.sides();
+            vec![left, right]
+        }
+        PlanNode::SortMergeJoin(join) => vec![&join.left, &join.right],
+        PlanNode::IndexNestedLoopJoin(join) => vec![&join.outer],
+    }
+}
+
+/// The one-line description of a node, without its inputs.
+struct Node<'a>(&'a PlanNode);
+
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            PlanNode::SingleRow => write!(f, "SingleRow"),
+            PlanNode::Values { rows, .. } => write!(f, "Values({} rows)", rows.len()),
+            PlanNode::TableScan { table, .. } => write!(f, "TableScan({table})"),
+            PlanNode::IndexScan {
+                table, index, key, ..
+            } => {
+                write!(f, "IndexScan({table} USING {index}, key ")?;
+                match key.as_slice() {
+                    [value] => write!(f, "{value})"),
+                    key => write!(f, "({}))", List(key)),
+                }
+            }
+            PlanNode::CteScan { cte, .. } => write!(f, "CteScan({})", cte.name),
+            PlanNode::WorkingTableScan { table, .. } => {
+                write!(f, "WorkingTableScan({})", table.name)
+            }
+            PlanNode::SubqueryScan { schema, .. } => {
+                match schema.columns.first().and_then(|c| c.table.as_deref()) {
+                    Some(alias) => write!(f, "SubqueryScan({alias})"),
+                    None => write!(f, "SubqueryScan"),
+                }
+            }
+            PlanNode::NestedLoopJoin {
+                kind, predicate, ..
+            } => match predicate {
+                Some(predicate) => write!(f, "NestedLoopJoin({kind} ON {predicate})"),
+                None => write!(f, "NestedLoopJoin({kind})"),
+            },
+            PlanNode::HashJoin(join) => {
+                let build = if join.build_left { "left" } else { "right" };
+                let predicate = join.predicate();
+                write!(f, "HashJoin({} ON {predicate}, build {build})", join.kind)
+            }
+            PlanNode::SortMergeJoin(join) => {
+                write!(f, "SortMergeJoin({} ON {})", join.kind, join.predicate())
+            }
+            PlanNode::IndexNestedLoopJoin(join) => {
+                write!(
+                    f,
+                    "IndexNestedLoopJoin({} ON {}, {} USING {}",
+                    join.kind,
+                    join.predicate(),
+                    join.inner_table,
+                    join.inner_index
+                )?;
+                if let Some(filter) = &join.inner_filter {
+                    write!(f, " WHERE {filter}")?;
+                }
+                write!(f, ")")
+            }
+            PlanNode::Filter { predicate, .. } => write!(f, "Filter({predicate})"),
+            PlanNode::Sort { keys, .. } => write!(f, "Sort({})", List(keys)),
+            PlanNode::Projection { exprs, .. } => write!(f, "Projection({})", List(exprs)),
+            PlanNode::Aggregate {
+                group_by, calls, ..
+            } => {
+                write!(f, "Aggregate(")?;
+                if !group_by.is_empty() {
+                    write!(f, "GROUP BY {}", List(group_by))?;
+                    if !calls.is_empty() {
+                        write!(f, "; ")?;
+                    }
+                }
+                write!(f, "{})", List(calls))
+            }
+            PlanNode::Window { calls, .. } => write!(f, "Window({})", List(calls)),
+            PlanNode::Distinct { .. } => write!(f, "Distinct"),
+            PlanNode::Exists { .. } => write!(f, "Exists"),
+            PlanNode::SetOperation { op, .. } => write!(f, "SetOperation({op})"),
+            PlanNode::Limit { limit, offset, .. } => {
+                match limit {
+                    Some(limit) => write!(f, "Limit({limit}")?,
+                    None => write!(f, "Limit(ALL")?,
+                }
+                if *offset > 0 {
+                    write!(f, " OFFSET {offset}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Items separated by commas.
+struct List<'a, T>(&'a [T]);
+
+impl<T: fmt::Display> fmt::Display for List<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, item) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{item}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expr)?;
+        if self.desc {
+            write!(f, " DESC")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AggregateCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.func.name())?;
+        if self.distinct {
+            write!(f, "DISTINCT ")?;
+        }
+        match &self.arg {
+            Some(arg) => write!(f, "{arg})"),
+            None => write!(f, "*)"),
+        }
+    }
+}
+
+impl fmt::Display for WindowCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function {
+            WindowFunction::Aggregate(call) => write!(f, "{call}"),
+            WindowFunction::Ranking(function) => write!(f, "{}()", function.name()),
+            WindowFunction::OffsetRow(function) => {
+                write!(f, "{}({})", function.direction.name(), function.expr)
+            }
+            WindowFunction::Value(function) => {
+                write!(f, "{}({})", function.position.name(), function.expr)
+            }
+            WindowFunction::Ntile(function) => write!(f, "NTILE({})", function.bucket_count),
+        }
+    }
+}
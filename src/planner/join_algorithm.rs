@@ -0,0 +1,383 @@
+//! Choosing how each join of equal columns finds its matches: by a nested
+//! loop, a hash table, a merge of sorted inputs, or an index.
+
+use crate::ast::{BinOp, Expr, JoinKind};
+use crate::storage::Database;
+use crate::types::Collation;
+
+use super::cost::{conjuncts, estimate_cost};
+use super::{HashJoinNode, IndexNestedLoopJoinNode, PlanNode, SortKey, SortMergeJoinNode};
+
+/// `plan` with each join of its top tree of joins that equates columns
+/// of its two inputs made a hash, sort-merge or index join, where that is
+/// estimated to be cheaper.
+///
+/// A merge does no hashing, so it is chosen over a hash join whose cost
+/// it ties, as it does when both inputs are already sorted on their keys.
+pub fn join_algorithms(db: &Database, plan: PlanNode) -> PlanNode {
+    match plan {
+        PlanNode::NestedLoopJoin {
+            left,
+            right,
+            kind,
+            predicate,
+            schema,
+        } => {
+            let nested = PlanNode::NestedLoopJoin {
+                left: Box::new(join_algorithms(db, *left)),
+                right: Box::new(join_algorithms(db, *right)),
+                kind,
+                predicate,
+                schema,
+            };
+            let Some(keys) = equi_join(&nested) else {
+                return nested;
+            };
+            // In order of preference, as a candidate must be strictly
+            // cheaper than the best before it.
+            let candidates = [
+                Some(sort_merge_join(db, &nested, keys.clone())),
+                index_join(db, &nested, &keys, false),
+                index_join(db, &nested, &keys, true),
+                Some(hash_join(db, &nested, keys)),
+            ];
+            let cpu = |plan: &PlanNode| estimate_cost(plan, db).cpu;
+            let mut best = nested;
+            let mut best_cpu = cpu(&best);
+            for candidate in candidates.into_iter().flatten() {
+                let candidate_cpu = cpu(&candidate);
+                if candidate_cpu < best_cpu {
+                    (best, best_cpu) = (candidate, candidate_cpu);
+                }
+            }
+            best
+        }
+        PlanNode::Filter { input, predicate } => PlanNode::Filter {
+            input: Box::new(join_algorithms(db, *input)),
+            predicate,
+        },
+        // The columns of reordered joins are put back in order on top.
+        PlanNode::Projection {
+            input,
+            exprs,
+            schema,
+        } => PlanNode::Projection {
+            input: Box::new(join_algorithms(db, *input)),
+            exprs,
+            schema,
+        },
+        plan => plan,
+    }
+}
+
+/// The keys of a join whose condition has conjuncts equating a column of
+/// the left input with one of the right: the left and right key, the
+/// collation of each part, and the other conjuncts.
+#[derive(Clone)]
+struct Keys {
+    left: Vec<Expr>,
+    right: Vec<Expr>,
+    collations: Vec<Collation>,
+    residual: Option<Expr>,
+}
+
+fn equi_join(nested: &PlanNode) -> Option<Keys> {
+    let PlanNode::NestedLoopJoin {
+        left,
+        predicate: Some(predicate),
+        schema,
+        ..
+    } = nested
+    else {
+        return None;
+    };
+    let width = left.schema().columns.len();
+    let side = |expr: &Expr| match expr {
+        Expr::Column(table, name) => {
+            let i = schema.resolve(table.as_deref(), name).ok()?;
+            Some(i >= width)
+        }
+        _ => None,
+    };
+    let (mut left_key, mut right_key, mut collations) = (Vec::new(), Vec::new(), Vec::new());
+    let mut residual = Vec::new();
+    for term in conjuncts(predicate) {
+        if let Expr::BinOp {
+            op: BinOp::Eq,
+            left: l,
+            right: r,
+        } = term
+        {
+            let pair = match (side(l), side(r)) {
+                (Some(false), Some(true)) => Some((l, r)),
+                (Some(true), Some(false)) => Some((r, l)),
+                _ => None,
+            };
+            if let Some((l, r)) = pair {
+                left_key.push((**l).clone());
+                right_key.push((**r).clone());
+                collations.push(schema.comparison_collation(l, r));
+                continue;
+            }
+        }
+        residual.push(term.clone());
+    }
+    if left_key.is_empty() {
+        return None;
+    }
+    let residual = residual.into_iter().reduce(|left, right| Expr::BinOp {
+        op: BinOp::And,
+        left: Box::new(left),
+        right: Box::new(right),
+    });
+    Some(Keys {
+        left: left_key,
+        right: right_key,
+        collations,
+        residual,
+    })
+}
+
+/// The hash join equivalent to a nested loop join on `keys`.
+fn hash_join(db: &Database, nested: &PlanNode, keys: Keys) -> PlanNode {
+    let PlanNode::NestedLoopJoin {
+        left,
+        right,
+        kind,
+        schema,
+        ..
+    } = nested
+    else {
+        unreachable!("only nested loop joins have keys");
+    };
+    let (left, right) = (left.clone(), right.clone());
+    // Building the right side gives rows in the order a nested loop join
+    // produces them, which outer joins keep: a preserved left row appears
+    // where its matches would have been. So does a CROSS JOIN, which reads
+    // its tables in the order written. Other inner joins build the smaller
+    // side.
+    let build_left =
+        *kind == JoinKind::Inner && estimate_cost(&left, db).rows < estimate_cost(&right, db).rows;
+    let (build, probe, build_key, probe_key) = if build_left {
+        (left, right, keys.left, keys.right)
+    } else {
+        (right, left, keys.right, keys.left)
+    };
+    PlanNode::HashJoin(HashJoinNode {
+        build,
+        probe,
+        build_key,
+        probe_key,
+        collations: keys.collations,
+        build_left,
+        kind: *kind,
+        residual: keys.residual,
+        schema: schema.clone(),
+    })
+}
+
+/// The index nested loop join equivalent to a nested loop join on `keys`
+/// that looks up the rows of its left input if `inner_left` is set, else
+/// of its right: possible if that input scans a table, perhaps through a
+/// filter, and does not have its unmatched rows preserved, and the table
+/// has an index on columns that the keys equate with the other input's.
+/// The left input of a CROSS JOIN is read first, so it is not looked up.
+fn index_join(db: &Database, nested: &PlanNode, keys: &Keys, inner_left: bool) -> Option<PlanNode> {
+    let PlanNode::NestedLoopJoin {
+        left,
+        right,
+        kind,
+        schema,
+        ..
+    } = nested
+    else {
+        unreachable!("only nested loop joins have keys");
+    };
+    let (inner, outer, inner_key, outer_key) = match inner_left {
+        true if !kind.preserves_left() && *kind != JoinKind::Cross => {
+            (left, right, &keys.left, &keys.right)
+        }
+        false if !kind.preserves_right() => (right, left, &keys.right, &keys.left),
+        _ => return None,
+    };
+    let (inner, inner_filter) = match &**inner {
+        PlanNode::Filter { input, predicate } => (&**input, Some(predicate.clone())),
+        inner => (inner, None),
+    };
+    let PlanNode::TableScan {
+        table,
+        schema: inner_schema,
+    } = inner
+    else {
+        return None;
+    };
+    // The part of the keys that equates a column of the table, if one
+    // does, under the collation it is indexed under.
+    let part_of = |column: usize| {
+        inner_key
+            .iter()
+            .zip(&keys.collations)
+            .position(|(expr, &collation)| {
+                column_of(expr, inner) == Some(column)
+                    && collation == inner_schema.columns[column].collation
+            })
+    };
+    // Prefer a unique index, then the most selective key.
+    let index = db
+        .get_table(table)?
+        .indexes
+        .iter()
+        .filter(|index| index.columns.iter().all(|&c| part_of(c).is_some()))
+        .max_by_key(|index| (index.unique, index.columns.len()))?;
+    let parts: Vec<usize> = index.columns.iter().filter_map(|&c| part_of(c)).collect();
+    // The equalities the index does not check join the rest.
+    let unchecked = (0..keys.left.len())
+        .filter(|part| !parts.contains(part))
+        .map(|part| Expr::BinOp {
+            op: BinOp::Eq,
+            left: Box::new(keys.left[part].clone()),
+            right: Box::new(keys.right[part].clone()),
+        });
+    let residual = unchecked
+        .chain(keys.residual.clone())
+        .reduce(|left, right| Expr::BinOp {
+            op: BinOp::And,
+            left: Box::new(left),
+            right: Box::new(right),
+        });
+    Some(PlanNode::IndexNestedLoopJoin(IndexNestedLoopJoinNode {
+        outer: outer.clone(),
+        inner_table: table.clone(),
+        inner_index: index.name.clone(),
+        inner_schema: inner_schema.clone(),
+        inner_filter,
+        inner_key: parts.iter().map(|&part| inner_key[part].clone()).collect(),
+        outer_key: parts.iter().map(|&part| outer_key[part].clone()).collect(),
+        inner_left,
+        kind: *kind,
+        residual,
+        schema: schema.clone(),
+    }))
+}
+
+/// The sort-merge join equivalent to a nested loop join on `keys`, with
+/// each input not already sorted on its key put through a sort.
+fn sort_merge_join(db: &Database, nested: &PlanNode, keys: Keys) -> PlanNode {
+    let PlanNode::NestedLoopJoin {
+        left,
+        right,
+        kind,
+        schema,
+        ..
+    } = nested
+    else {
+        unreachable!("only nested loop joins have keys");
+    };
+    PlanNode::SortMergeJoin(SortMergeJoinNode {
+        left: sorted(db, left, &keys.left, &keys.collations),
+        right: sorted(db, right, &keys.right, &keys.collations),
+        left_key: keys.left,
+        right_key: keys.right,
+        collations: keys.collations,
+        kind: *kind,
+        residual: keys.residual,
+        schema: schema.clone(),
+    })
+}
+
+/// `input`, sorted ascending on `key` if it is not already.
+fn sorted(
+    db: &Database,
+    input: &PlanNode,
+    key: &[Expr],
+    collations: &[Collation],
+) -> Box<PlanNode> {
+    let columns = key
+        .iter()
+        .map(|expr| column_of(expr, input))
+        .collect::<Option<Vec<_>>>();
+    match columns {
+        Some(columns) if sorted_on(db, input, &columns, collations) => Box::new(input.clone()),
+        _ => Box::new(PlanNode::Sort {
+            input: Box::new(input.clone()),
+            keys: key
+                .iter()
+                .zip(collations)
+                .map(|(expr, &collation)| SortKey {
+                    expr: expr.clone(),
+                    desc: false,
+                    nulls_first: true,
+                    collation,
+                })
+                .collect(),
+        }),
+    }
+}
+
+/// The position of the column `expr` names among those of `node`.
+fn column_of(expr: &Expr, node: &PlanNode) -> Option<usize> {
+    match expr {
+        Expr::Column(table, name) => node.schema().resolve(table.as_deref(), name).ok(),
+        _ => None,
+    }
+}
+
+/// Whether the plan `node` gives its rows with no NULL in `columns`
+/// ordered ascending on them, their texts compared under `collations`:
+/// those are the rows a merge pairs. Stored rows are in no known order.
+fn sorted_on(db: &Database, node: &PlanNode, columns: &[usize], collations: &[Collation]) -> bool {
+    match node {
+        PlanNode::Sort { input, keys } => {
+            keys.len() >= columns.len()
+                && keys
+                    .iter()
+                    .zip(columns)
+                    .zip(collations)
+                    .all(|((key, &column), &collation)| {
+                        !key.desc
+                            && key.collation == collation
+                            && column_of(&key.expr, input) == Some(column)
+                    })
+        }
+        PlanNode::Filter { input, .. }
+        | PlanNode::Distinct { input }
+        | PlanNode::Limit { input, .. }
+        | PlanNode::SubqueryScan { input, .. } => sorted_on(db, input, columns, collations),
+        PlanNode::Projection { input, exprs, .. } => {
+            let columns = columns
+                .iter()
+                .map(|&i| column_of(&exprs[i], input))
+                .collect::<Option<Vec<_>>>();
+            columns.is_some_and(|columns| sorted_on(db, input, &columns, collations))
+        }
+        // Rows of equal keys come together, in key order.
+        PlanNode::SortMergeJoin(join) => {
+            let width = join.left.schema().columns.len();
+            columns.len() <= join.left_key.len()
+                && columns.iter().enumerate().all(|(part, &column)| {
+                    join.collations[part] == collations[part]
+                        && if column < width {
+                            column_of(&join.left_key[part], &join.left) == Some(column)
+                        } else {
+                            column_of(&join.right_key[part], &join.right) == Some(column - width)
+                        }
+                })
+        }
+        // Every row an index finds has the same value in each indexed
+        // column, under the column's collation.
+        PlanNode::IndexScan {
+            table,
+            index,
+            schema,
+            ..
+        } => db
+            .get_table(table)
+            .and_then(|table| table.indexes.iter().find(|i| &i.name == index))
+            .is_some_and(|index| {
+                columns.iter().zip(collations).all(|(column, &collation)| {
+                    index.columns.contains(column) && schema.columns[*column].collation == collation
+                })
+            }),
+        _ => false,
+    }
+}
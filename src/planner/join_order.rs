@@ -0,0 +1,406 @@
+//! Choosing the order a query's inner joins read their tables in.
+//!
+//! A tree of inner joins produces the same rows whatever order its tables
+//! are joined in, so the planner may pick the order it estimates to be
+//! cheapest; a CROSS JOIN, as in SQLite, keeps the order written. Each
+//! conjunct of the join conditions is checked at the first join that has
+//! all the tables it reads, and the columns are put back in the order
+//! written on top.
+
+use crate::ast::{BinOp, Expr, JoinKind};
+use crate::storage::Database;
+
+use super::cost::{Cost, conjuncts, estimate_cost, join_cost, selectivity};
+use super::{PlanNode, Schema};
+
+/// The most tables whose every order is tried; larger joins are ordered by
+/// dynamic programming over the sets of tables joined so far.
+const PERMUTATION_LIMIT: usize = 8;
+
+/// The most tables reordered at all; larger joins keep the written order.
+const REORDER_LIMIT: usize = 16;
+
+/// `plan` with its top tree of inner joins reordered, if another
+/// order has a lower estimated cost than the written one.
+///
+/// Joins are only reordered if every column of their output can be named
+/// unambiguously by a qualified name, and their conditions contain no
+/// correlated subquery: those read the joined row by position.
+pub fn order_joins(db: &Database, plan: PlanNode) -> PlanNode {
+    // What a filter above the joins tests could not be pushed into them.
+    if let PlanNode::Filter { input, predicate } = plan {
+        return PlanNode::Filter {
+            input: Box::new(order_joins(db, *input)),
+            predicate,
+        };
+    }
+    match reordered(db, &plan) {
+        Some(reordered) => reordered,
+        None => plan,
+    }
+}
+
+fn reordered(db: &Database, plan: &PlanNode) -> Option<PlanNode> {
+    let schema = plan.schema();
+    let mut tables = Vec::new();
+    let mut terms = Vec::new();
+    flatten(plan, &mut tables, &mut terms);
+    if tables.len() < 2 || tables.len() > REORDER_LIMIT || !nameable(schema) {
+        return None;
+    }
+    // The columns of each table, in the written order.
+    let mut ends = Vec::new();
+    let mut end = 0;
+    for table in &tables {
+        end += table.schema().columns.len();
+        ends.push(end);
+    }
+    let table_of = |column: usize| ends.iter().position(|&end| column < end);
+    let terms = terms
+        .into_iter()
+        .map(|term| Some((term, tables_read(term, schema, &table_of)?)))
+        .collect::<Option<Vec<_>>>()?;
+
+    let search = Search {
+        costs: tables.iter().map(|t| estimate_cost(t, db)).collect(),
+        terms: terms
+            .iter()
+            .map(|&(term, read)| (read, selectivity(term, plan, db)))
+            .collect(),
+    };
+    let written: Vec<usize> = (0..tables.len()).collect();
+    let order = search.best(written.clone())?;
+    if order == written {
+        return None;
+    }
+
+    let mut joined = tables[order[0]].clone();
+    let mut set = 1 << order[0];
+    for &table in &order[1..] {
+        let applied: Vec<Expr> = terms
+            .iter()
+            .filter(|&&(_, read)| search.applies(read, set, set | 1 << table))
+            .map(|(term, _)| (*term).clone())
+            .collect();
+        set |= 1 << table;
+        let right = tables[table].clone();
+        let mut columns = joined.schema().columns.clone();
+        columns.extend(right.schema().columns.iter().cloned());
+        let predicate = applied.into_iter().reduce(|left, right| Expr::BinOp {
+            op: BinOp::And,
+            left: Box::new(left),
+            right: Box::new(right),
+        });
+        joined = PlanNode::NestedLoopJoin {
+            left: Box::new(joined),
+            kind: JoinKind::Inner,
+            right: Box::new(right),
+            predicate,
+            schema: Schema { columns },
+        };
+    }
+    let exprs = schema
+        .columns
+        .iter()
+        .map(|c| Expr::Column(c.table.clone(), c.name.clone()))
+        .collect();
+    Some(PlanNode::Projection {
+        input: Box::new(joined),
+        exprs,
+        schema: schema.clone(),
+    })
+}
+
+/// Collect the tables of a tree of inner joins, and the conjuncts of its
+/// conditions. Anything else, a CROSS JOIN too, is a single table.
+fn flatten<'a>(plan: &'a PlanNode, tables: &mut Vec<&'a PlanNode>, terms: &mut Vec<&'a Expr>) {
+    match plan {
+        PlanNode::NestedLoopJoin {
+            left,
+            right,
+            kind: JoinKind::Inner,
+            predicate,
+            ..
+        } => {
+            flatten(left, tables, terms);
+            flatten(right, tables, terms);
+            terms.extend(predicate.iter().flat_map(conjuncts));
+        }
+        plan => tables.push(plan),
+    }
+}
+
+/// Whether every column of `schema` is visible and found by its table and
+/// name alone.
+fn nameable(schema: &Schema) -> bool {
+    schema.columns.iter().enumerate().all(|(i, c)| {
+        !c.hidden
+            && c.table.is_some()
+            && schema.resolve(c.table.as_deref(), &c.name).ok() == Some(i)
+    })
+}
+
+/// The set of tables a conjunct reads, as a bit per table; `None` if it
+/// has a correlated subquery.
+fn tables_read(
+    term: &Expr,
+    schema: &Schema,
+    table_of: &impl Fn(usize) -> Option<usize>,
+) -> Option<u32> {
+    let mut read = Some(0);
+    term.transform(&mut |e| {
+        match e {
+            Expr::Column(table, name) => {
+                match schema
+                    .resolve(table.as_deref(), name)
+                    .ok()
+                    .and_then(table_of)
+                {
+                    Some(table) => read = read.map(|read| read | 1 << table),
+                    None => read = None,
+                }
+            }
+            Expr::Subquery { plan, .. } if plan.correlated => read = None,
+            _ => return None,
+        }
+        Some(e.clone())
+    });
+    read
+}
+
+/// The estimates an order of the tables is chosen by.
+struct Search {
+    /// The cost of reading each table.
+    costs: Vec<Cost>,
+    /// The tables each conjunct reads, and the fraction of rows it holds
+    /// for.
+    terms: Vec<(u32, f64)>,
+}
+
+impl Search {
+    /// Whether a conjunct reading `read` is checked by the join of the
+    /// tables `before` with another, giving the tables `after`: it reads
+    /// only tables joined by then, and not only tables already joined to
+    /// each other.
+    fn applies(&self, read: u32, before: u32, after: u32) -> bool {
+        read & !after == 0 && (read & !before != 0 || before.count_ones() == 1)
+    }
+
+    /// The cost of joining the tables `before`, at `cost`, with `table`.
+    fn join(&self, cost: Cost, before: u32, table: usize) -> Cost {
+        let after = before | 1 << table;
+        let selectivity = self
+            .terms
+            .iter()
+            .filter(|&&(read, _)| self.applies(read, before, after))
+            .map(|&(_, fraction)| fraction)
+            .product();
+        join_cost(cost, self.costs[table], JoinKind::Inner, selectivity)
+    }
+
+    fn cost(&self, order: &[usize]) -> Cost {
+        let mut cost = self.costs[order[0]];
+        let mut set = 1 << order[0];
+        for &table in &order[1..] {
+            cost = self.join(cost, set, table);
+            set |= 1 << table;
+        }
+        cost
+    }
+
+    /// The cheapest order of the tables, `written` unless another is
+    /// cheaper; `None` if the costs cannot be estimated.
+    fn best(&self, written: Vec<usize>) -> Option<Vec<usize>> {
+        let mut best = (self.cost(&written).cpu, written);
+        if best.0.is_nan() {
+            return None;
+        }
+        if self.costs.len() <= PERMUTATION_LIMIT {
+            let mut order = Vec::new();
+            self.permute(
+                &mut order,
+                Cost {
+                    rows: 0.0,
+                    cpu: 0.0,
+                },
+                &mut best,
+            );
+        } else if let Some((cost, order)) = self.dynamic()
+            && cost < best.0
+        {
+            best = (cost, order);
+        }
+        Some(best.1)
+    }
+
+    /// Try every order starting with `order`, which costs `cost`, keeping
+    /// the cheapest in `best`. Orders already dearer are not extended.
+    fn permute(&self, order: &mut Vec<usize>, cost: Cost, best: &mut (f64, Vec<usize>)) {
+        if order.len() == self.costs.len() {
+            if cost.cpu < best.0 {
+                *best = (cost.cpu, order.clone());
+            }
+            return;
+        }
+        let set = order.iter().fold(0, |set, &t| set | 1 << t);
+        for table in 0..self.costs.len() {
+            if set & 1 << table != 0 {
+                continue;
+            }
+            let cost = match order.is_empty() {
+                true => self.costs[table],
+                false => self.join(cost, set, table),
+            };
+            if cost.cpu >= best.0 {
+                continue;
+            }
+            order.push(table);
+            self.permute(order, cost, best);
+            order.pop();
+        }
+    }
+
+    /// The cheapest order found by extending the cheapest order of each
+    /// set of tables by one more table. As a set's output rows do not
+    /// depend on its order, the cheapest order of a set is always the
+    /// best one to extend.
+    fn dynamic(&self) -> Option<(f64, Vec<usize>)> {
+        let n = self.costs.len();
+        let mut best: Vec<Option<(Cost, Vec<usize>)>> = vec![None; 1 << n];
+        for (table, &cost) in self.costs.iter().enumerate() {
+            best[1 << table] = Some((cost, vec![table]));
+        }
+        for set in 1..1usize << n {
+            let Some((cost, order)) = best[set].clone() else {
+                continue;
+            };
+            for table in (0..n).filter(|&t| set & 1 << t == 0) {
+                let after = set | 1 << table;
+                let joined = self.join(cost, set as u32, table);
+                if best[after]
+                    .as_ref()
+                    .is_none_or(|(cost, _)| joined.cpu < cost.cpu)
+                {
+                    let mut order = order.clone();
+                    order.push(table);
+                    best[after] = Some((joined, order));
+                }
+            }
+        }
+        best[(1 << n) - 1]
+            .take()
+            .map(|(cost, order)| (cost.cpu, order))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Statement;
+    use crate::executor::execute;
+    use crate::parser::parse_statement;
+    use crate::planner::{explain, plan_select};
+
+    /// A database with a table `name (id, name)` of `rows` rows for each
+    /// entry, numbered from 1 and all holding the table's name.
+    fn sized(tables: &[(&str, usize)]) -> Database {
+        let mut db = Database::new();
+        for &(name, rows) in tables {
+            run(
+                &mut db,
+                &format!("CREATE TABLE {name} (id INTEGER, name TEXT)"),
+            );
+            for id in 1..=rows {
+                run(
+                    &mut db,
+                    &format!("INSERT INTO {name} VALUES ({id}, '{name}')"),
+                );
+            }
+        }
+        db
+    }
+
+    /// Run a statement, returning its rows with values separated by `|`.
+    fn run(db: &mut Database, sql: &str) -> Vec<String> {
+        let rows = execute(db, &parse_statement(sql).unwrap()).unwrap();
+        rows.iter()
+            .map(|row| {
+                let values: Vec<String> = row.iter().map(ToString::to_string).collect();
+                values.join("|")
+            })
+            .collect()
+    }
+
+    /// The tables a query scans, in the order its plan joins them.
+    fn scanned(db: &Database, sql: &str) -> Vec<String> {
+        let Statement::Select(select) = parse_statement(sql).unwrap() else {
+            panic!("expected SELECT");
+        };
+        explain(&plan_select(db, &select).unwrap(), db, 0)
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix("TableScan("))
+            .map(|line| line[..line.find(')').unwrap()].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_smaller_table_is_joined_on_the_left() {
+        let mut db = sized(&[("big", 20), ("small", 3)]);
+        let sql = "SELECT * FROM big JOIN small ON big.id = small.id";
+        assert_eq!(scanned(&db, sql), ["small", "big"]);
+        assert_eq!(
+            scanned(&db, "SELECT * FROM small JOIN big ON big.id = small.id"),
+            ["small", "big"]
+        );
+        // The columns keep the order written.
+        let rows = run(&mut db, sql);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], "1|big|1|small");
+    }
+
+    #[test]
+    fn test_three_table_join_order() {
+        let mut db = sized(&[("big", 20), ("mid", 5), ("small", 2)]);
+        let sql = "SELECT big.name, mid.name, small.name, small.id FROM big \
+                   JOIN mid ON big.id = mid.id JOIN small ON mid.id = small.id";
+        // Joining small with big first would pair every row of each.
+        assert_eq!(scanned(&db, sql), ["small", "mid", "big"]);
+        let rows = run(&mut db, sql);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1], "big|mid|small|2");
+    }
+
+    #[test]
+    fn test_outer_joins_keep_their_order() {
+        let db = sized(&[("big", 20), ("small", 3)]);
+        assert_eq!(
+            scanned(
+                &db,
+                "SELECT * FROM big LEFT JOIN small ON big.id = small.id"
+            ),
+            ["big", "small"]
+        );
+        assert_eq!(
+            scanned(&db, "SELECT * FROM big JOIN small USING (id)"),
+            ["big", "small"]
+        );
+        // Equal tables stay in the order written.
+        let db = sized(&[("a", 3), ("b", 3)]);
+        assert_eq!(scanned(&db, "SELECT * FROM b, a"), ["b", "a"]);
+    }
+
+    #[test]
+    fn test_many_tables_are_ordered_by_dynamic_programming() {
+        let names: Vec<String> = (0..10).map(|i| format!("t{i}")).collect();
+        let tables: Vec<(&str, usize)> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), 10 - i))
+            .collect();
+        let db = sized(&tables);
+        let sql = format!("SELECT * FROM {}", names.join(", "));
+        let mut smallest_first = names.clone();
+        smallest_first.reverse();
+        assert_eq!(scanned(&db, &sql), smallest_first);
+    }
+}
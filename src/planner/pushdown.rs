@@ -0,0 +1,411 @@
+//! Moving filters down a plan, toward the scans whose rows they test, so
+//! the nodes above them handle fewer rows.
+//!
+//! Each conjunct of a filter moves on its own. It passes through sorts,
+//! derived tables and projections, with its columns renamed or replaced
+//! by what they are computed from; at a join it goes into the input whose
+//! columns it reads, unless that input's unmatched rows are padded with
+//! NULLs, and one reading both inputs of an inner join becomes part of
+//! the join's condition. A conjunct with a subquery stays where it is.
+
+use crate::ast::{BinOp, Expr, JoinKind};
+
+use super::cost::conjuncts;
+use super::{PlanNode, Schema};
+
+/// `plan` with the conjuncts of its filters moved as far down as they
+/// can go. Running it on its own output changes nothing.
+pub fn pushdown_predicates(plan: PlanNode) -> PlanNode {
+    push(plan, Vec::new())
+}
+
+/// `plan` filtered by `terms`, with those and the filters within it
+/// moved down.
+fn push(plan: PlanNode, mut terms: Vec<Expr>) -> PlanNode {
+    match plan {
+        // The lower filter's conjuncts were checked first.
+        PlanNode::Filter { input, predicate } => {
+            let mut lower: Vec<Expr> = conjuncts(&predicate).into_iter().cloned().collect();
+            lower.append(&mut terms);
+            push(*input, lower)
+        }
+        PlanNode::Sort { input, keys } => PlanNode::Sort {
+            input: Box::new(push(*input, terms)),
+            keys,
+        },
+        PlanNode::SubqueryScan { input, schema } => {
+            let inner = input.schema();
+            let (pushed, kept) = rewrite_all(terms, &schema, |i| name_of(inner, i));
+            filtered(
+                PlanNode::SubqueryScan {
+                    input: Box::new(push(*input, pushed)),
+                    schema,
+                },
+                kept,
+            )
+        }
+        PlanNode::Projection {
+            input,
+            exprs,
+            schema,
+        } => {
+            let (pushed, kept) = rewrite_all(terms, &schema, |i| Some(exprs[i].clone()));
+            filtered(
+                PlanNode::Projection {
+                    input: Box::new(push(*input, pushed)),
+                    exprs,
+                    schema,
+                },
+                kept,
+            )
+        }
+        PlanNode::NestedLoopJoin {
+            left,
+            right,
+            kind,
+            predicate,
+            schema,
+        } => {
+            let split = Split::new(terms, &schema, left.schema().columns.len(), kind);
+            let predicate = and(predicate.into_iter().chain(split.both));
+            filtered(
+                PlanNode::NestedLoopJoin {
+                    left: Box::new(push(*left, split.left)),
+                    right: Box::new(push(*right, split.right)),
+                    kind,
+                    predicate,
+                    schema,
+                },
+                split.kept,
+            )
+        }
+        PlanNode::HashJoin(mut join) => {
+            let width = join.sides().0.schema().columns.len();
+            let split = Split::new(terms, &join.schema, width, join.kind);
+            let (build, probe) = if join.build_left {
+                (split.left, split.right)
+            } else {
+                (split.right, split.left)
+            };
+            join.build = Box::new(push(*join.build, build));
+            join.probe = Box::new(push(*join.probe, probe));
+            join.residual = and(join.residual.take().into_iter().chain(split.both));
+            filtered(PlanNode::HashJoin(join), split.kept)
+        }
+        PlanNode::SortMergeJoin(mut join) => {
+            let width = join.left.schema().columns.len();
+            let split = Split::new(terms, &join.schema, width, join.kind);
+            join.left = Box::new(push(*join.left, split.left));
+            join.right = Box::new(push(*join.right, split.right));
+            join.residual = and(join.residual.take().into_iter().chain(split.both));
+            filtered(PlanNode::SortMergeJoin(join), split.kept)
+        }
+        // Filters within may still move down, but none moves in.
+        mut plan => {
+            for input in plan.inputs_mut() {
+                let node = std::mem::replace(input, PlanNode::SingleRow);
+                *input = push(node, Vec::new());
+            }
+            filtered(plan, terms)
+        }
+    }
+}
+
+/// Where the conjuncts above a join go: into its left or right input,
+/// into its condition, or nowhere.
+struct Split {
+    left: Vec<Expr>,
+    right: Vec<Expr>,
+    both: Vec<Expr>,
+    kept: Vec<Expr>,
+}
+
+impl Split {
+    /// Split `terms` above a join of kind `kind` with output columns
+    /// `schema`, the first `width` of them from its left input.
+    fn new(terms: Vec<Expr>, schema: &Schema, width: usize, kind: JoinKind) -> Split {
+        let inner = matches!(kind, JoinKind::Inner | JoinKind::Cross);
+        let mut split = Split {
+            left: Vec::new(),
+            right: Vec::new(),
+            both: Vec::new(),
+            kept: Vec::new(),
+        };
+        for term in terms {
+            let Some(read) = columns_read(&term, schema) else {
+                split.kept.push(term);
+                continue;
+            };
+            let reads_left = read.iter().any(|&i| i < width);
+            let reads_right = read.iter().any(|&i| i >= width);
+            // A column resolves the same way in the input it comes from.
+            match (reads_left, reads_right) {
+                (true, false) if !kind.preserves_right() => split.left.push(term),
+                (false, true) if !kind.preserves_left() => split.right.push(term),
+                (true, true) if inner => split.both.push(term),
+                _ => split.kept.push(term),
+            }
+        }
+        split
+    }
+}
+
+/// Each of `terms` with the columns it reads from `schema` replaced by
+/// `replacement` of their position, where every one has a replacement;
+/// then those that have none.
+fn rewrite_all(
+    terms: Vec<Expr>,
+    schema: &Schema,
+    replacement: impl Fn(usize) -> Option<Expr>,
+) -> (Vec<Expr>, Vec<Expr>) {
+    let mut pushed = Vec::new();
+    let mut kept = Vec::new();
+    for term in terms {
+        match rewrite(&term, schema, &replacement) {
+            Some(rewritten) => pushed.push(rewritten),
+            None => kept.push(term),
+        }
+    }
+    (pushed, kept)
+}
+
+fn rewrite(
+    term: &Expr,
+    schema: &Schema,
+    replacement: impl Fn(usize) -> Option<Expr>,
+) -> Option<Expr> {
+    columns_read(term, schema)?;
+    let mut complete = true;
+    let rewritten = term.transform(&mut |e| match e {
+        Expr::Column(table, name) => {
+            let replaced = schema
+                .resolve(table.as_deref(), name)
+                .ok()
+                .and_then(&replacement);
+            complete &= replaced.is_some();
+            replaced.or_else(|| Some(e.clone()))
+        }
+        _ => None,
+    });
+    // What a projection computes may itself have a subquery.
+    (complete && has_no_subquery(&rewritten)).then_some(rewritten)
+}
+
+/// A column reference that finds column `i` of `schema`, if one does.
+fn name_of(schema: &Schema, i: usize) -> Option<Expr> {
+    let column = &schema.columns[i];
+    [
+        Expr::Column(column.table.clone(), column.name.clone()),
+        Expr::Column(None, column.name.clone()),
+    ]
+    .into_iter()
+    .find(|e| matches!(e, Expr::Column(table, name) if schema.resolve(table.as_deref(), name) == Ok(i)))
+}
+
+/// The positions in `schema` of the columns `expr` reads, if it has no
+/// subquery and each of them resolves.
+fn columns_read(expr: &Expr, schema: &Schema) -> Option<Vec<usize>> {
+    if !has_no_subquery(expr) {
+        return None;
+    }
+    let mut read = Some(Vec::new());
+    expr.transform(&mut |e| match e {
+        Expr::Column(table, name) => {
+            match (schema.resolve(table.as_deref(), name), &mut read) {
+                (Ok(i), Some(read)) => read.push(i),
+                _ => read = None,
+            }
+            Some(e.clone())
+        }
+        _ => None,
+    });
+    read
+}
+
+fn has_no_subquery(expr: &Expr) -> bool {
+    let mut found = false;
+    expr.transform(&mut |e| match e {
+        Expr::Subquery { .. } | Expr::Exists(_) | Expr::ScalarSubquery(_) => {
+            found = true;
+            Some(e.clone())
+        }
+        _ => None,
+    });
+    !found
+}
+
+/// `plan` filtered by the conjunction of `terms`, if there are any.
+fn filtered(plan: PlanNode, terms: Vec<Expr>) -> PlanNode {
+    match and(terms) {
+        Some(predicate) => PlanNode::Filter {
+            input: Box::new(plan),
+            predicate,
+        },
+        None => plan,
+    }
+}
+
+fn and(terms: impl IntoIterator<Item = Expr>) -> Option<Expr> {
+    terms.into_iter().reduce(|left, right| Expr::BinOp {
+        op: BinOp::And,
+        left: Box::new(left),
+        right: Box::new(right),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Statement;
+    use crate::executor::{Context, execute, execute_plan};
+    use crate::parser::{parse_expr, parse_statement};
+    use crate::planner::{explain, plan_select};
+    use crate::storage::Database;
+
+    fn shop() -> Database {
+        let mut db = Database::new();
+        for sql in [
+            "CREATE TABLE customers (id INTEGER, name TEXT)",
+            "CREATE TABLE orders (id INTEGER, customer INTEGER, status TEXT)",
+            "INSERT INTO customers VALUES (1, 'Al'), (2, 'Bo'), (3, 'Cy')",
+            "INSERT INTO orders VALUES (1, 1, 'active'), (2, 2, 'done'), \
+             (3, 2, 'active'), (4, 3, 'active'), (5, 9, 'active')",
+        ] {
+            execute(&mut db, &parse_statement(sql).unwrap()).unwrap();
+        }
+        db
+    }
+
+    fn plan(db: &Database, sql: &str) -> PlanNode {
+        let Statement::Select(select) = parse_statement(sql).unwrap() else {
+            panic!("expected SELECT");
+        };
+        plan_select(db, &select).unwrap()
+    }
+
+    /// The plan of a query, filtered by `predicate` as a WHERE clause
+    /// would be: below the projection computing its columns.
+    fn filter(plan: PlanNode, predicate: &str) -> PlanNode {
+        let PlanNode::Projection {
+            input,
+            exprs,
+            schema,
+        } = plan
+        else {
+            panic!("expected a projection");
+        };
+        PlanNode::Projection {
+            input: Box::new(PlanNode::Filter {
+                input,
+                predicate: parse_expr(predicate).unwrap(),
+            }),
+            exprs,
+            schema,
+        }
+    }
+
+    /// The lines of `plan`'s EXPLAIN output, without the estimates.
+    fn lines(db: &Database, plan: &PlanNode) -> Vec<String> {
+        explain(plan, db, 0)
+            .lines()
+            .map(|line| line[..line.find(" rows=").unwrap()].to_string())
+            .collect()
+    }
+
+    /// The rows of `plan`, values separated by `|`.
+    fn rows(db: &Database, plan: &PlanNode) -> Vec<String> {
+        execute_plan(Context::new(db), plan)
+            .map(|row| {
+                let values: Vec<String> = row.unwrap().iter().map(ToString::to_string).collect();
+                values.join("|")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_moves_into_derived_table() {
+        let db = shop();
+        let unpushed = filter(
+            plan(&db, "SELECT * FROM (SELECT * FROM orders) AS o"),
+            "o.status = 'active'",
+        );
+        let pushed = pushdown_predicates(unpushed.clone());
+        assert_eq!(
+            lines(&db, &pushed),
+            [
+                "Projection(o.id, o.customer, o.status)",
+                "  SubqueryScan(o)",
+                "    Projection(orders.id, orders.customer, orders.status)",
+                "      Filter(orders.status = 'active')",
+                "        TableScan(orders)",
+            ]
+        );
+        assert_eq!(rows(&db, &pushed), rows(&db, &unpushed));
+        assert_eq!(rows(&db, &pushed).len(), 4);
+        // The planner runs the pass, and running it again changes nothing.
+        let sql = "SELECT * FROM (SELECT * FROM orders) AS o WHERE o.status = 'active'";
+        assert_eq!(
+            lines(&db, &plan(&db, sql)).last().unwrap(),
+            "        TableScan(orders)"
+        );
+        assert_eq!(
+            lines(&db, &pushdown_predicates(pushed.clone())),
+            lines(&db, &pushed)
+        );
+    }
+
+    #[test]
+    fn test_join_filters_move_to_their_side() {
+        let db = shop();
+        let joined = plan(
+            &db,
+            "SELECT * FROM orders JOIN customers ON orders.customer = customers.id",
+        );
+        let unpushed = filter(
+            joined,
+            "orders.status = 'active' AND customers.name <> 'Bo' AND orders.id > customers.id",
+        );
+        let pushed = pushdown_predicates(unpushed.clone());
+        let explained = lines(&db, &pushed);
+        // The condition reading both tables joins them.
+        assert!(
+            explained[2].contains(
+                "Join(JOIN ON customers.id = orders.customer AND orders.id > customers.id"
+            )
+        );
+        let filters: Vec<&str> = explained
+            .iter()
+            .filter(|line| line.trim_start().starts_with("Filter"))
+            .map(|line| line.trim_start())
+            .collect();
+        assert_eq!(
+            filters,
+            [
+                "Filter(customers.name <> 'Bo')",
+                "Filter(orders.status = 'active')"
+            ]
+        );
+        assert_eq!(rows(&db, &pushed), rows(&db, &unpushed));
+        assert_eq!(rows(&db, &pushed), ["4|3|active|3|Cy"]);
+        assert_eq!(lines(&db, &pushdown_predicates(pushed.clone())), explained);
+    }
+
+    #[test]
+    fn test_filters_that_stay_above() {
+        let db = shop();
+        // Customers' columns are NULL for an order with no customer, which
+        // the filter must see.
+        let sql = "SELECT orders.id FROM orders LEFT JOIN customers \
+                   ON orders.customer = customers.id \
+                   WHERE customers.name IS NULL AND orders.status = 'active'";
+        let planned = plan(&db, sql);
+        let explained = lines(&db, &planned);
+        assert_eq!(explained[1], "  Filter(customers.name IS NULL)");
+        assert!(explained.contains(&"      Filter(orders.status = 'active')".to_string()));
+        assert_eq!(rows(&db, &planned), ["5"]);
+        // A subquery is not moved.
+        let sql = "SELECT * FROM (SELECT * FROM orders) AS o \
+                   WHERE o.customer IN (SELECT id FROM customers)";
+        assert!(lines(&db, &plan(&db, sql))[1].starts_with("  Filter(o.customer IN"));
+    }
+}
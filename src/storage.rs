@@ -0,0 +1,1812 @@
+//! In-memory table storage.
+//!
+//! Table names are case-insensitive: the catalog is keyed by the lowercased
+//! name, while each [`Table`] remembers the spelling it was created with.
+//! Views share the tables' namespace.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::ast::{CreateTableStmt, Expr, InsertConflictAction, SelectStmt, TableConstraint};
+use crate::types::{Collation, DataType, OrdValue, TypeError, Value, coerce};
+
+mod foreign_key;
+mod pending_writes;
+
+pub use foreign_key::{FkAction, ForeignKey, References};
+pub use pending_writes::{PendingWrites, TableWrites};
+
+/// One stored row; values are in schema order.
+pub type Row = Vec<Value>;
+
+/// A database shared between connections.
+pub type SharedDatabase = Arc<Mutex<Database>>;
+
+/// A column as declared in `CREATE TABLE`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    /// `None` for a column declared without a type; it accepts any value.
+    pub data_type: Option<DataType>,
+    pub collation: Collation,
+    pub nullable: bool,
+    pub default: Option<Expr>,
+    pub primary_key: bool,
+    pub unique: bool,
+    /// A foreign key made of this column alone.
+    pub references: Option<References>,
+    /// CHECK constraints declared with the column, which see only its
+    /// value.
+    pub checks: Vec<CheckConstraint>,
+    /// `AUTOINCREMENT` or a `SERIAL` type: a row inserted with NULL here
+    /// gets the next value of the table's sequence instead.
+    pub autoincrement: bool,
+    /// `GENERATED ALWAYS AS (<expr>)`: the column is computed from the
+    /// others rather than written.
+    pub generated: Option<Box<Generated>>,
+}
+
+impl ColumnDef {
+    /// A nullable column without a default or key constraints.
+    pub fn new(name: impl Into<String>, data_type: Option<DataType>) -> Self {
+        ColumnDef {
+            name: name.into(),
+            data_type,
+            collation: Collation::Binary,
+            nullable: true,
+            default: None,
+            primary_key: false,
+            unique: false,
+            references: None,
+            checks: Vec::new(),
+            autoincrement: false,
+            generated: None,
+        }
+    }
+
+    /// Whether the column is computed wherever it is read, holding NULL
+    /// in the stored rows.
+    pub fn is_virtual(&self) -> bool {
+        self.generated.as_ref().is_some_and(|g| !g.stored)
+    }
+}
+
+/// How a generated column is computed, from the other columns of its row.
+/// Its expression refers to no generated column and reads nothing but the
+/// row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generated {
+    pub expr: Expr,
+    /// `STORED`: computed when the row is written and stored with it,
+    /// rather than `VIRTUAL`, computed when it is read.
+    pub stored: bool,
+}
+
+/// A CHECK constraint: its expression is not false for any row, though it
+/// may be NULL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckConstraint {
+    /// The name given with `CONSTRAINT <name>`, reported by a violation.
+    pub name: Option<String>,
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub name: String,
+    pub schema: Vec<ColumnDef>,
+    pub rows: Vec<Row>,
+    pub indexes: Vec<Index>,
+    pub unique: Vec<UniqueConstraint>,
+    /// Like a UNIQUE constraint, but its columns cannot be NULL either.
+    pub primary_key: Option<UniqueConstraint>,
+    pub foreign_keys: Vec<ForeignKey>,
+    /// The CHECK constraints declared apart from the columns, which may
+    /// refer to any of them.
+    pub checks: Vec<CheckConstraint>,
+    /// Every row stored in the table and removed from it since
+    /// [`Database::record_writes`], if it was called.
+    writes: Option<TableWrites>,
+}
+
+/// A UNIQUE constraint: no two rows may have equal values in `columns`
+/// unless one of them is NULL. It is enforced through a unique index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UniqueConstraint {
+    pub columns: Vec<usize>,
+}
+
+/// The prefix of the names of indexes created for constraints, which is
+/// reserved for them.
+const AUTO_INDEX_PREFIX: &str = "sqlite_autoindex_";
+
+/// An index on some of a table's columns: the positions of the rows
+/// holding each combination of their values, in row order. Rows with a
+/// NULL in any of the columns are left out, as NULL equals nothing. Texts
+/// are equal under the collations of the columns.
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub name: String,
+    /// Positions of the indexed columns in the table's schema.
+    pub columns: Vec<usize>,
+    /// Whether no two rows may have the same key.
+    pub unique: bool,
+    /// The collation of each indexed column.
+    collations: Vec<Collation>,
+    entries: HashMap<Vec<OrdValue>, Vec<usize>>,
+}
+
+impl Index {
+    /// Positions of the rows whose indexed columns equal `key`, one value
+    /// per column.
+    pub fn lookup(&self, key: &[Value]) -> &[usize] {
+        match self.key_of(key) {
+            Some(key) => self.entries.get(&key).map_or(&[], Vec::as_slice),
+            None => &[],
+        }
+    }
+
+    /// The key a row is indexed under, unless one of its values is NULL.
+    fn key(&self, row: &[Value]) -> Option<Vec<OrdValue>> {
+        self.key_of(self.columns.iter().map(|&i| &row[i]))
+    }
+
+    /// The key for values of the indexed columns, one per column, unless
+    /// one of them is NULL.
+    fn key_of<'v>(&self, values: impl IntoIterator<Item = &'v Value>) -> Option<Vec<OrdValue>> {
+        values
+            .into_iter()
+            .zip(&self.collations)
+            .map(|(value, collation)| {
+                (!value.is_null()).then(|| OrdValue(collation.key(value.clone())))
+            })
+            .collect()
+    }
+
+    fn insert(&mut self, row: &[Value], position: usize) {
+        if let Some(key) = self.key(row) {
+            self.entries.entry(key).or_default().push(position);
+        }
+    }
+
+    /// Whether the index was created for a UNIQUE or PRIMARY KEY
+    /// constraint rather than by `CREATE INDEX`.
+    pub fn enforces_constraint(&self) -> bool {
+        is_auto_index(&self.name)
+    }
+
+    fn rebuild(&mut self, rows: &[Row]) {
+        self.entries.clear();
+        for (position, row) in rows.iter().enumerate() {
+            self.insert(row, position);
+        }
+    }
+}
+
+/// How many buckets a column's histogram divides its values into.
+pub const HISTOGRAM_BUCKETS: usize = 10;
+
+/// What `ANALYZE` found out about the values of a column. The statistics
+/// are not kept up to date as rows change; ANALYZE again replaces them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    /// How many different values other than NULL the column held.
+    pub distinct_count: usize,
+    pub null_count: usize,
+    /// The least and greatest values other than NULL, if there were any.
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    /// The greatest value of each of up to [`HISTOGRAM_BUCKETS`] buckets
+    /// holding as many of the values other than NULL as one another, in
+    /// order. The last is `max`.
+    pub histogram: Vec<Value>,
+}
+
+impl ColumnStats {
+    fn of<'a>(values: impl Iterator<Item = &'a Value>) -> ColumnStats {
+        let mut null_count = 0;
+        let mut sorted = Vec::new();
+        for value in values {
+            match value {
+                Value::Null => null_count += 1,
+                value => sorted.push(OrdValue(value.clone())),
+            }
+        }
+        sorted.sort();
+        let mut distinct = sorted.clone();
+        distinct.dedup();
+        let buckets = HISTOGRAM_BUCKETS.min(sorted.len());
+        let histogram = (1..=buckets)
+            .map(|bucket| {
+                sorted[(bucket * sorted.len()).div_ceil(buckets) - 1]
+                    .0
+                    .clone()
+            })
+            .collect();
+        ColumnStats {
+            distinct_count: distinct.len(),
+            null_count,
+            min: sorted.first().map(|v| v.0.clone()),
+            max: sorted.last().map(|v| v.0.clone()),
+            histogram,
+        }
+    }
+}
+
+impl Table {
+    /// Position of a column in the schema, matched case-insensitively.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.schema
+            .iter()
+            .position(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The table's primary key column if it has one of type INTEGER alone.
+    pub fn integer_key(&self) -> Option<usize> {
+        match self.primary_key.as_ref()?.columns[..] {
+            [i] if self.schema[i].data_type == Some(DataType::Int) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// The column numbered from the table's sequence, if it has one.
+    pub fn autoincrement_column(&self) -> Option<usize> {
+        self.schema.iter().position(|column| column.autoincrement)
+    }
+
+    /// A CREATE TABLE statement for the table as it is now, without its
+    /// rows or the indexes CREATE INDEX added. Constraints over one column
+    /// are declared with the column, others after the columns.
+    pub fn create_statement(&self) -> CreateTableStmt {
+        let mut columns = self.schema.clone();
+        for column in &mut columns {
+            column.primary_key = false;
+            column.unique = false;
+            column.references = None;
+        }
+        let names = |positions: &[usize]| -> Vec<String> {
+            positions
+                .iter()
+                .map(|&i| self.schema[i].name.clone())
+                .collect()
+        };
+        let mut constraints = Vec::new();
+        match self.primary_key.as_ref().map(|pk| pk.columns.as_slice()) {
+            Some(&[i]) => columns[i].primary_key = true,
+            Some(key) => constraints.push(TableConstraint::PrimaryKey(names(key))),
+            None => {}
+        }
+        for unique in &self.unique {
+            match unique.columns[..] {
+                [i] => columns[i].unique = true,
+                _ => constraints.push(TableConstraint::Unique(names(&unique.columns))),
+            }
+        }
+        for key in &self.foreign_keys {
+            match key.columns[..] {
+                [i] if columns[i].references.is_none() => {
+                    columns[i].references = Some(key.references.clone());
+                }
+                _ => constraints.push(TableConstraint::ForeignKey {
+                    columns: names(&key.columns),
+                    references: key.references.clone(),
+                }),
+            }
+        }
+        constraints.extend(self.checks.iter().cloned().map(TableConstraint::Check));
+        CreateTableStmt {
+            name: self.name.clone(),
+            if_not_exists: false,
+            columns,
+            constraints,
+        }
+    }
+
+    /// The index with the given name, matched case-insensitively.
+    pub fn index(&self, name: &str) -> Option<&Index> {
+        self.indexes
+            .iter()
+            .find(|index| index.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Build the index a new index or constraint needs over the table's
+    /// rows, checking they do not already break it if it is unique.
+    fn build_index(&self, name: &str, columns: Vec<usize>, unique: bool) -> Result<Index, DbError> {
+        if let Some(&i) = columns.iter().find(|&&i| self.schema[i].is_virtual()) {
+            return Err(DbError::VirtualIndex(self.schema[i].name.clone()));
+        }
+        let mut index = Index {
+            name: name.to_string(),
+            collations: columns.iter().map(|&i| self.schema[i].collation).collect(),
+            columns,
+            unique,
+            entries: HashMap::new(),
+        };
+        index.rebuild(&self.rows);
+        if unique && index.entries.values().any(|rows| rows.len() > 1) {
+            return Err(self.key_violation(&index.columns));
+        }
+        Ok(index)
+    }
+
+    /// Create the unique index enforcing a constraint over `columns`.
+    fn add_auto_index(&mut self, columns: Vec<usize>) -> Result<(), DbError> {
+        let name = (1..)
+            .map(|n| format!("{AUTO_INDEX_PREFIX}{}_{n}", self.name))
+            .find(|name| self.index(name).is_none())
+            .expect("some index name is free");
+        let index = self.build_index(&name, columns, true)?;
+        self.indexes.push(index);
+        Ok(())
+    }
+
+    fn add_unique(&mut self, columns: Vec<usize>) -> Result<(), DbError> {
+        self.add_auto_index(columns.clone())?;
+        self.unique.push(UniqueConstraint { columns });
+        Ok(())
+    }
+
+    fn add_primary_key(&mut self, columns: Vec<usize>) -> Result<(), DbError> {
+        if self.primary_key.is_some() {
+            return Err(DbError::MultiplePrimaryKeys(self.name.clone()));
+        }
+        if columns.iter().any(|&i| self.schema[i].generated.is_some()) {
+            return Err(DbError::GeneratedPrimaryKey);
+        }
+        if let Some(&i) = columns
+            .iter()
+            .find(|&&i| self.rows.iter().any(|row| row[i].is_null()))
+        {
+            return Err(DbError::NotNull {
+                table: self.name.clone(),
+                column: self.schema[i].name.clone(),
+            });
+        }
+        self.add_auto_index(columns.clone())?;
+        for &i in &columns {
+            self.schema[i].primary_key = true;
+            self.schema[i].nullable = false;
+        }
+        self.primary_key = Some(UniqueConstraint { columns });
+        Ok(())
+    }
+
+    /// Check that a row has a value for every column and coerce each value
+    /// to its column's declared type.
+    pub fn coerce_row(&self, row: Row) -> Result<Row, DbError> {
+        if row.len() != self.schema.len() {
+            return Err(DbError::ColumnCount {
+                table: self.name.clone(),
+                expected: self.schema.len(),
+                actual: row.len(),
+            });
+        }
+        Ok(row
+            .into_iter()
+            .zip(&self.schema)
+            .map(|(value, column)| match column.data_type {
+                Some(ty) => coerce(value, ty),
+                None => Ok(value),
+            })
+            .collect::<Result<Row, TypeError>>()?)
+    }
+
+    /// Append a row already checked against the constraints.
+    fn push_row(&mut self, row: Row) {
+        let position = self.rows.len();
+        for index in &mut self.indexes {
+            index.insert(&row, position);
+        }
+        self.log_inserted(std::slice::from_ref(&row));
+        self.rows.push(row);
+    }
+
+    /// Remove the rows from position `len` on, as undoing their appending.
+    fn truncate_rows(&mut self, len: usize) {
+        let removed = self.rows.split_off(len);
+        self.log_deleted(&removed);
+        self.reindex();
+    }
+
+    /// Replace rows, each given with its position, by new versions already
+    /// checked against the constraints.
+    fn replace_rows(&mut self, updates: Vec<(usize, Row)>) {
+        for (i, row) in updates {
+            self.log_inserted(std::slice::from_ref(&row));
+            let old = std::mem::replace(&mut self.rows[i], row);
+            self.log_deleted(&[old]);
+        }
+        self.reindex();
+    }
+
+    /// Remove the rows at the given positions, returning them.
+    fn remove_rows(&mut self, positions: &[usize]) -> Vec<Row> {
+        let mut doomed = vec![false; self.rows.len()];
+        for &i in positions {
+            doomed[i] = true;
+        }
+        let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.rows)
+            .into_iter()
+            .zip(doomed)
+            .partition(|&(_, doomed)| doomed);
+        self.rows = kept.into_iter().map(|(row, _)| row).collect();
+        self.reindex();
+        let removed: Vec<Row> = removed.into_iter().map(|(row, _)| row).collect();
+        self.log_deleted(&removed);
+        removed
+    }
+
+    /// Positions of the rows with the same key as `row` in some unique
+    /// index, in order.
+    fn conflicts(&self, row: &[Value]) -> Vec<usize> {
+        let mut positions: Vec<usize> = self
+            .indexes
+            .iter()
+            .filter(|index| index.unique)
+            .filter_map(|index| index.entries.get(&index.key(row)?))
+            .flatten()
+            .copied()
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+        positions
+    }
+
+    /// Check a row that is about to be stored against the column
+    /// constraints.
+    fn check_row(&self, row: &[Value]) -> Result<(), DbError> {
+        self.check_not_null(row)?;
+        for index in self.indexes.iter().filter(|index| index.unique) {
+            if let Some(key) = index.key(row)
+                && index.entries.contains_key(&key)
+            {
+                return Err(self.key_violation(&index.columns));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_not_null(&self, row: &[Value]) -> Result<(), DbError> {
+        for (value, column) in row.iter().zip(&self.schema) {
+            // What a virtual column holds is checked as it is computed.
+            if value.is_null() && !column.nullable && !column.is_virtual() {
+                return Err(DbError::NotNull {
+                    table: self.name.clone(),
+                    column: column.name.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that replacing rows, each given with its position, leaves no
+    /// two rows with the same key in a unique index.
+    fn check_unique(&self, updates: &[(usize, Row)]) -> Result<(), DbError> {
+        let unique: Vec<&Index> = self.indexes.iter().filter(|i| i.unique).collect();
+        if unique.is_empty() {
+            return Ok(());
+        }
+        let mut rows: Vec<&Row> = self.rows.iter().collect();
+        for (i, row) in updates {
+            rows[*i] = row;
+        }
+        for index in unique {
+            let mut seen = HashSet::new();
+            for row in &rows {
+                if let Some(key) = index.key(row)
+                    && !seen.insert(key)
+                {
+                    return Err(self.key_violation(&index.columns));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Bring the indexes up to date after the rows have been rewritten.
+    fn reindex(&mut self) {
+        for index in &mut self.indexes {
+            index.rebuild(&self.rows);
+        }
+    }
+
+    fn key_violation(&self, key: &[usize]) -> DbError {
+        DbError::Unique {
+            table: self.name.clone(),
+            columns: key.iter().map(|&i| self.schema[i].name.clone()).collect(),
+        }
+    }
+}
+
+/// A failure reported by the storage layer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DbError {
+    TableExists(String),
+    NoSuchTable(String),
+    ViewExists(String),
+    NoSuchView(String),
+    DuplicateColumn(String),
+    IndexExists(String),
+    NoSuchIndex(String),
+    /// A name starting with the prefix reserved for the indexes of
+    /// constraints.
+    ReservedName(String),
+    /// A DROP INDEX of an index that enforces a constraint.
+    ConstraintIndex(String),
+    MultiplePrimaryKeys(String),
+    /// An ALTER TABLE ADD COLUMN that cannot be applied to existing rows:
+    /// what kind of column it would add.
+    CannotAddColumn(&'static str),
+    /// An ALTER TABLE DROP COLUMN of a table's only column.
+    CannotDropColumn(String),
+    /// An ALTER TABLE DROP COLUMN of a column a table CHECK constraint
+    /// refers to.
+    CheckedColumn(String),
+    /// A DROP TABLE of a table that the foreign keys of `by` refer to.
+    ReferencedTable {
+        table: String,
+        by: Vec<String>,
+    },
+    /// A foreign key that would lead back to its own table.
+    CircularForeignKey(String),
+    /// A foreign key of `child` whose referenced key is not a primary key
+    /// or UNIQUE in `parent`, or for which `parent` does not exist.
+    ForeignKeyMismatch {
+        child: String,
+        parent: String,
+    },
+    ForeignKey,
+    ColumnCount {
+        table: String,
+        expected: usize,
+        actual: usize,
+    },
+    NotNull {
+        table: String,
+        column: String,
+    },
+    /// A key already present in the table; `columns` names the key.
+    Unique {
+        table: String,
+        columns: Vec<String>,
+    },
+    Type(TypeError),
+    /// A row for which a CHECK constraint is false, named as reported.
+    Check(String),
+    /// A BEGIN while a transaction is already open.
+    NestedTransaction,
+    /// A COMMIT or ROLLBACK, as named, outside a transaction.
+    NoTransaction(&'static str),
+    NoSuchSavepoint(String),
+    /// AUTOINCREMENT on a column that is not the table's only primary key
+    /// column, or is not an INTEGER.
+    AutoincrementKey,
+    /// More than one AUTOINCREMENT or SERIAL column in the table named.
+    MultipleAutoincrement(String),
+    /// A sequence that has reached the largest integer.
+    Full,
+    /// An INSERT naming the generated column given.
+    InsertGenerated(String),
+    /// An UPDATE assigning to the generated column given.
+    UpdateGenerated(String),
+    GeneratedPrimaryKey,
+    GeneratedDefault,
+    /// A generated column whose expression refers to a generated column,
+    /// perhaps itself.
+    GeneratedLoop(String),
+    /// An index or UNIQUE constraint on the virtual column given, none of
+    /// whose values are stored.
+    VirtualIndex(String),
+    GeneratedSubquery,
+    /// Dropping a column a generated column is computed from.
+    GeneratedFromColumn(String),
+    /// A change of journal mode, into or out of the mode named, inside a
+    /// transaction.
+    JournalMode {
+        into: bool,
+        mode: &'static str,
+    },
+    /// A schema change inside a snapshot transaction, whose writes are
+    /// kept as rows alone.
+    SnapshotSchemaChange,
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::TableExists(name) => write!(f, "table {name} already exists"),
+            DbError::NoSuchTable(name) => write!(f, "no such table: {name}"),
+            DbError::ViewExists(name) => write!(f, "view {name} already exists"),
+            DbError::NoSuchView(name) => write!(f, "no such view: {name}"),
+            DbError::DuplicateColumn(name) => write!(f, "duplicate column name: {name}"),
+            DbError::IndexExists(name) => write!(f, "index {name} already exists"),
+            DbError::NoSuchIndex(name) => write!(f, "no such index: {name}"),
+            DbError::ReservedName(name) => {
+                write!(f, "object name reserved for internal use: {name}")
+            }
+            DbError::MultiplePrimaryKeys(table) => {
+                write!(f, "table \"{table}\" has more than one primary key")
+            }
+            DbError::ConstraintIndex(_) => write!(
+                f,
+                "index associated with UNIQUE or PRIMARY KEY constraint cannot be dropped"
+            ),
+            DbError::CannotAddColumn(what) => write!(f, "Cannot add a {what}"),
+            DbError::CannotDropColumn(name) => {
+                write!(f, "cannot drop column \"{name}\": no other columns exist")
+            }
+            DbError::CheckedColumn(name) => {
+                write!(
+                    f,
+                    "cannot drop column \"{name}\": used in a CHECK constraint"
+                )
+            }
+            DbError::ReferencedTable { table, by } => {
+                write!(
+                    f,
+                    "cannot drop table {table}: referenced by {}",
+                    by.join(", ")
+                )
+            }
+            DbError::CircularForeignKey(table) => {
+                write!(f, "circular foreign key reference involving table {table}")
+            }
+            DbError::ForeignKeyMismatch { child, parent } => {
+                write!(
+                    f,
+                    "foreign key mismatch - \"{child}\" referencing \"{parent}\""
+                )
+            }
+            DbError::ForeignKey => write!(f, "FOREIGN KEY constraint failed"),
+            DbError::Check(name) => write!(f, "CHECK constraint failed: {name}"),
+            DbError::NestedTransaction => {
+                write!(f, "cannot start a transaction within a transaction")
+            }
+            DbError::NoTransaction(what) => {
+                write!(f, "cannot {what} - no transaction is active")
+            }
+            DbError::NoSuchSavepoint(name) => write!(f, "no such savepoint: {name}"),
+            DbError::AutoincrementKey => {
+                write!(f, "AUTOINCREMENT is only allowed on an INTEGER PRIMARY KEY")
+            }
+            DbError::MultipleAutoincrement(table) => {
+                write!(
+                    f,
+                    "table \"{table}\" has more than one AUTOINCREMENT column"
+                )
+            }
+            DbError::Full => write!(f, "database or disk is full"),
+            DbError::InsertGenerated(name) => {
+                write!(f, "cannot INSERT into generated column \"{name}\"")
+            }
+            DbError::UpdateGenerated(name) => {
+                write!(f, "cannot UPDATE generated column \"{name}\"")
+            }
+            DbError::GeneratedPrimaryKey => {
+                write!(f, "generated columns cannot be part of the PRIMARY KEY")
+            }
+            DbError::GeneratedDefault => write!(f, "cannot use DEFAULT on a generated column"),
+            DbError::GeneratedSubquery => {
+                write!(f, "subqueries prohibited in generated columns")
+            }
+            DbError::GeneratedLoop(name) => write!(f, "generated column loop on \"{name}\""),
+            DbError::VirtualIndex(name) => {
+                write!(f, "cannot index virtual generated column \"{name}\"")
+            }
+            DbError::GeneratedFromColumn(name) => {
+                write!(
+                    f,
+                    "cannot drop column \"{name}\": used in a generated column"
+                )
+            }
+            DbError::JournalMode { into, mode } => {
+                let direction = if *into { "into" } else { "out of" };
+                write!(
+                    f,
+                    "cannot change {direction} {mode} mode from within a transaction"
+                )
+            }
+            DbError::SnapshotSchemaChange => {
+                write!(f, "cannot change the schema within a wal transaction")
+            }
+            DbError::ColumnCount {
+                table,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "table {table} has {expected} columns but {actual} values were supplied"
+            ),
+            DbError::NotNull { table, column } => {
+                write!(f, "NOT NULL constraint failed: {table}.{column}")
+            }
+            DbError::Unique { table, columns } => {
+                let columns: Vec<String> = columns.iter().map(|c| format!("{table}.{c}")).collect();
+                write!(f, "UNIQUE constraint failed: {}", columns.join(", "))
+            }
+            DbError::Type(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<TypeError> for DbError {
+    fn from(e: TypeError) -> Self {
+        DbError::Type(e)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Database {
+    tables: HashMap<String, Table>,
+    /// The query of each view, by lowercased name. It is planned afresh
+    /// wherever the view is read.
+    views: HashMap<String, SelectStmt>,
+    /// A copy of the database as it was when the open transaction began,
+    /// which ROLLBACK restores. `None` outside a transaction, and inside
+    /// one started by a SAVEPOINT.
+    transaction: Option<Box<Database>>,
+    /// The open savepoints by name, outermost first, each with a copy of
+    /// the database as it was when the savepoint was created. A SAVEPOINT
+    /// outside a transaction starts one, which releasing that savepoint
+    /// commits.
+    savepoints: Vec<(String, Database)>,
+    /// The statistics of each analyzed column, by lowercased table and
+    /// column name.
+    stats: HashMap<(String, String), ColumnStats>,
+    /// The largest value each table's AUTOINCREMENT column has held, by
+    /// lowercased table name. Every write to the column advances it, so
+    /// the next value is one more: deleting rows leaves a gap, which is
+    /// not filled again.
+    sequences: HashMap<String, i64>,
+    /// How many rows the latest INSERT, UPDATE, DELETE or TRUNCATE wrote,
+    /// and how many all of them have since the database was opened. Rows
+    /// foreign keys deleted or changed with them are not counted.
+    changes: usize,
+    total_changes: usize,
+    /// The INTEGER PRIMARY KEY of the last row the latest INSERT to insert
+    /// any stored, 0 if its table has none.
+    last_insert_id: Option<i64>,
+}
+
+impl Database {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap the database so it can be shared across threads.
+    pub fn into_shared(self) -> SharedDatabase {
+        Arc::new(Mutex::new(self))
+    }
+
+    pub fn create_table(&mut self, name: &str, schema: Vec<ColumnDef>) -> Result<(), DbError> {
+        let key = name.to_lowercase();
+        if self.tables.contains_key(&key) {
+            return Err(DbError::TableExists(name.to_string()));
+        }
+        if self.views.contains_key(&key) {
+            return Err(DbError::ViewExists(name.to_string()));
+        }
+        for (i, column) in schema.iter().enumerate() {
+            if schema[..i]
+                .iter()
+                .any(|c| c.name.eq_ignore_ascii_case(&column.name))
+            {
+                return Err(DbError::DuplicateColumn(column.name.clone()));
+            }
+        }
+        let keys = schema.iter().filter(|c| c.primary_key).count();
+        for column in schema.iter().filter(|c| c.autoincrement) {
+            if column.data_type != Some(DataType::Int) || column.primary_key && keys > 1 {
+                return Err(DbError::AutoincrementKey);
+            }
+        }
+        if schema.iter().filter(|c| c.autoincrement).count() > 1 {
+            return Err(DbError::MultipleAutoincrement(name.to_string()));
+        }
+        let mut table = Table {
+            name: name.to_string(),
+            schema,
+            rows: Vec::new(),
+            indexes: Vec::new(),
+            unique: Vec::new(),
+            primary_key: None,
+            foreign_keys: Vec::new(),
+            checks: Vec::new(),
+            writes: None,
+        };
+        let primary_key: Vec<usize> = (0..table.schema.len())
+            .filter(|&i| table.schema[i].primary_key)
+            .collect();
+        if !primary_key.is_empty() {
+            table.add_primary_key(primary_key)?;
+        }
+        for i in 0..table.schema.len() {
+            if table.schema[i].unique {
+                table.add_unique(vec![i])?;
+            }
+            if let Some(references) = &table.schema[i].references {
+                table.foreign_keys.push(ForeignKey {
+                    columns: vec![i],
+                    references: references.clone(),
+                });
+            }
+        }
+        self.check_no_cycle(name, &table.foreign_keys)?;
+        self.tables.insert(key, table);
+        Ok(())
+    }
+
+    /// Make a combination of a table's columns, given by position, its
+    /// primary key, which it must not have yet.
+    pub fn add_primary_key(&mut self, table: &str, columns: Vec<usize>) -> Result<(), DbError> {
+        self.get_table_mut(table)
+            .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?
+            .add_primary_key(columns)
+    }
+
+    /// Constrain a combination of a table's columns, given by position, to
+    /// be unique.
+    pub fn add_unique(&mut self, table: &str, columns: Vec<usize>) -> Result<(), DbError> {
+        self.get_table_mut(table)
+            .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?
+            .add_unique(columns)
+    }
+
+    /// Constrain a combination of a table's columns, given by position, to
+    /// refer to a key of another table.
+    pub fn add_foreign_key(&mut self, table: &str, foreign_key: ForeignKey) -> Result<(), DbError> {
+        self.check_no_cycle(table, std::slice::from_ref(&foreign_key))?;
+        self.get_table_mut(table)
+            .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?
+            .foreign_keys
+            .push(foreign_key);
+        Ok(())
+    }
+
+    pub fn add_check(&mut self, table: &str, check: CheckConstraint) -> Result<(), DbError> {
+        self.get_table_mut(table)
+            .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?
+            .checks
+            .push(check);
+        Ok(())
+    }
+
+    /// Append a column to a table, giving every existing row `default`,
+    /// coerced to the column's type, as its value. A key column is refused,
+    /// as every row would have the same value for it, and so is a
+    /// REFERENCES column whose default is not NULL.
+    pub fn add_column(
+        &mut self,
+        table: &str,
+        column: ColumnDef,
+        default: Value,
+    ) -> Result<(), DbError> {
+        if let Some(references) = &column.references {
+            if !default.is_null() {
+                return Err(DbError::CannotAddColumn(
+                    "REFERENCES column with non-NULL default value",
+                ));
+            }
+            let foreign_key = ForeignKey {
+                columns: Vec::new(),
+                references: references.clone(),
+            };
+            self.check_no_cycle(table, &[foreign_key])?;
+        }
+        let table = self
+            .get_table_mut(table)
+            .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?;
+        if table.column_index(&column.name).is_some() {
+            return Err(DbError::DuplicateColumn(column.name));
+        }
+        if column.primary_key {
+            return Err(DbError::CannotAddColumn("PRIMARY KEY column"));
+        }
+        if column.unique {
+            return Err(DbError::CannotAddColumn("UNIQUE column"));
+        }
+        if column.autoincrement {
+            return Err(DbError::CannotAddColumn("AUTOINCREMENT column"));
+        }
+        if column.generated.as_ref().is_some_and(|g| g.stored) {
+            return Err(DbError::CannotAddColumn("STORED column"));
+        }
+        let default = match column.data_type {
+            Some(ty) => coerce(default, ty)?,
+            None => default,
+        };
+        if default.is_null() && !column.nullable {
+            return Err(DbError::CannotAddColumn(
+                "NOT NULL column with default value NULL",
+            ));
+        }
+        for row in &mut table.rows {
+            row.push(default.clone());
+        }
+        if let Some(references) = &column.references {
+            table.foreign_keys.push(ForeignKey {
+                columns: vec![table.schema.len()],
+                references: references.clone(),
+            });
+        }
+        table.schema.push(column);
+        Ok(())
+    }
+
+    /// Index the columns of a table at the given positions. A unique index
+    /// cannot be created over rows that already break it.
+    pub fn create_index(
+        &mut self,
+        table: &str,
+        name: &str,
+        columns: Vec<usize>,
+        unique: bool,
+    ) -> Result<(), DbError> {
+        if is_auto_index(name) {
+            return Err(DbError::ReservedName(name.to_string()));
+        }
+        if self.get_index(name).is_some() {
+            return Err(DbError::IndexExists(name.to_string()));
+        }
+        let table = self
+            .get_table_mut(table)
+            .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?;
+        let index = table.build_index(name, columns, unique)?;
+        table.indexes.push(index);
+        Ok(())
+    }
+
+    /// The index with the given name, on whichever table has it.
+    pub fn get_index(&self, name: &str) -> Option<&Index> {
+        self.tables.values().find_map(|table| table.index(name))
+    }
+
+    pub fn drop_index(&mut self, name: &str) -> Result<(), DbError> {
+        if is_auto_index(name) && self.get_index(name).is_some() {
+            return Err(DbError::ConstraintIndex(name.to_string()));
+        }
+        for table in self.tables.values_mut() {
+            if let Some(i) = table
+                .indexes
+                .iter()
+                .position(|index| index.name.eq_ignore_ascii_case(name))
+            {
+                table.indexes.remove(i);
+                return Ok(());
+            }
+        }
+        Err(DbError::NoSuchIndex(name.to_string()))
+    }
+
+    /// Remove the column at `index` from a table, with its value in every
+    /// row and any key or index it is part of. A table keeps at least one
+    /// column, and the table's CHECK constraints keep theirs.
+    pub fn drop_column(&mut self, table: &str, index: usize) -> Result<(), DbError> {
+        let table = self
+            .get_table_mut(table)
+            .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?;
+        if table.schema.len() == 1 {
+            return Err(DbError::CannotDropColumn(table.schema[0].name.clone()));
+        }
+        let name = &table.schema[index].name;
+        if table
+            .checks
+            .iter()
+            .any(|check| refers_to(&check.expr, name))
+        {
+            return Err(DbError::CheckedColumn(name.clone()));
+        }
+        if table
+            .schema
+            .iter()
+            .filter_map(|column| column.generated.as_ref())
+            .any(|generated| refers_to(&generated.expr, name))
+        {
+            return Err(DbError::GeneratedFromColumn(name.clone()));
+        }
+        if let Some(key) = table
+            .primary_key
+            .take_if(|key| key.columns.contains(&index))
+        {
+            for &i in &key.columns {
+                table.schema[i].primary_key = false;
+            }
+        }
+        let column = table.schema.remove(index);
+        let key = (table.name.to_lowercase(), column.name.to_lowercase());
+        for row in &mut table.rows {
+            row.remove(index);
+        }
+        table.indexes.retain(|i| !i.columns.contains(&index));
+        table.unique.retain(|u| !u.columns.contains(&index));
+        table.foreign_keys.retain(|fk| !fk.columns.contains(&index));
+        let indexes = table.indexes.iter_mut().map(|i| &mut i.columns);
+        let unique = table.unique.iter_mut().chain(&mut table.primary_key);
+        let unique = unique.map(|u| &mut u.columns);
+        let foreign_keys = table.foreign_keys.iter_mut().map(|fk| &mut fk.columns);
+        for column in indexes.chain(unique).chain(foreign_keys).flatten() {
+            if *column > index {
+                *column -= 1;
+            }
+        }
+        self.stats.remove(&key);
+        Ok(())
+    }
+
+    /// Drop a table, unless another table's foreign keys refer to it.
+    pub fn drop_table(&mut self, name: &str) -> Result<(), DbError> {
+        let mut by: Vec<String> = self.referencing(name).map(|t| t.name.clone()).collect();
+        if !by.is_empty() {
+            by.sort();
+            return Err(DbError::ReferencedTable {
+                table: name.to_string(),
+                by,
+            });
+        }
+        let key = name.to_lowercase();
+        self.tables
+            .remove(&key)
+            .ok_or_else(|| DbError::NoSuchTable(name.to_string()))?;
+        self.stats.retain(|(table, _), _| *table != key);
+        self.sequences.remove(&key);
+        Ok(())
+    }
+
+    /// How many rows the latest statement that writes rows wrote.
+    pub fn changes(&self) -> usize {
+        self.changes
+    }
+
+    /// How many rows every statement that writes rows has written.
+    pub fn total_changes(&self) -> usize {
+        self.total_changes
+    }
+
+    /// Count the rows a statement that writes rows wrote, which becomes
+    /// the latest.
+    pub fn record_changes(&mut self, count: usize) {
+        self.changes = count;
+        self.total_changes += count;
+    }
+
+    /// The INTEGER PRIMARY KEY of the last row inserted, 0 if its table has
+    /// none or no row has been.
+    pub fn last_insert_id(&self) -> i64 {
+        self.last_insert_id.unwrap_or(0)
+    }
+
+    /// Remember the key of the last row an INSERT inserted, if it inserted
+    /// any.
+    pub fn record_insert(&mut self, id: Option<i64>) {
+        if id.is_some() {
+            self.last_insert_id = id;
+        }
+    }
+
+    /// The largest value the AUTOINCREMENT column of the table `name` has
+    /// held, if it has held any.
+    pub fn sequence(&self, name: &str) -> Option<i64> {
+        self.sequences.get(&name.to_lowercase()).copied()
+    }
+
+    /// Start the table's sequence over, so its next value is 1.
+    pub fn reset_sequence(&mut self, name: &str) {
+        self.sequences.remove(&name.to_lowercase());
+    }
+
+    fn restore_sequence(&mut self, name: &str, sequence: Option<i64>) {
+        let key = name.to_lowercase();
+        match sequence {
+            Some(last) => self.sequences.insert(key, last),
+            None => self.sequences.remove(&key),
+        };
+    }
+
+    /// Give a row NULL in its table's AUTOINCREMENT column the sequence's
+    /// next value there.
+    fn assign_autoincrement(&self, table: &Table, row: &mut Row) -> Result<(), DbError> {
+        let Some(i) = table.autoincrement_column() else {
+            return Ok(());
+        };
+        if let Some(value @ Value::Null) = row.get_mut(i) {
+            let last = self.sequence(&table.name).unwrap_or(0);
+            *value = Value::Integer(last.checked_add(1).ok_or(DbError::Full)?);
+        }
+        Ok(())
+    }
+
+    /// Advance the table's sequence to the row's AUTOINCREMENT value, if
+    /// that is an integer beyond it.
+    fn advance_sequence(&mut self, name: &str, row: &[Value]) {
+        let Some(i) = self.get_table(name).and_then(Table::autoincrement_column) else {
+            return;
+        };
+        if let Value::Integer(n) = row[i] {
+            let last = self.sequences.entry(name.to_lowercase()).or_insert(n);
+            *last = (*last).max(n);
+        }
+    }
+
+    /// Append a row, coercing each value to its column's declared type and
+    /// enforcing NOT NULL, UNIQUE, primary key and foreign key constraints.
+    pub fn insert_row(&mut self, name: &str, row: Row) -> Result<(), DbError> {
+        let table = self
+            .get_table(name)
+            .ok_or_else(|| DbError::NoSuchTable(name.to_string()))?;
+        let mut row = row;
+        self.assign_autoincrement(table, &mut row)?;
+        let row = table.coerce_row(row)?;
+        table.check_row(&row)?;
+        self.check_foreign_keys_insert(name, &row)?;
+        self.advance_sequence(name, &row);
+        let table = self.get_table_mut(name).expect("table was found above");
+        table.push_row(row);
+        Ok(())
+    }
+
+    /// Append rows as [`Database::insert_row`] does, all or none: if one is
+    /// rejected, those appended before it are removed again.
+    pub fn insert_rows(
+        &mut self,
+        name: &str,
+        rows: impl IntoIterator<Item = Row>,
+    ) -> Result<(), DbError> {
+        let start = self
+            .get_table(name)
+            .ok_or_else(|| DbError::NoSuchTable(name.to_string()))?
+            .rows
+            .len();
+        let sequence = self.sequence(name);
+        for row in rows {
+            if let Err(e) = self.insert_row(name, row) {
+                self.restore_sequence(name, sequence);
+                let table = self.get_table_mut(name).expect("table was found above");
+                table.truncate_rows(start);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Append rows as [`Database::insert_rows`] does, except that a row
+    /// whose key some row already has in a unique index is resolved by
+    /// `conflict`. For REPLACE the rows in the way are deleted as
+    /// [`Database::delete_rows`] deletes them, with `default` evaluating
+    /// column defaults. Returns the rows appended, as stored.
+    pub fn insert_rows_or<E: From<DbError>>(
+        &mut self,
+        name: &str,
+        rows: impl IntoIterator<Item = Row>,
+        conflict: InsertConflictAction,
+        default: impl Fn(&Expr) -> Result<Value, E>,
+    ) -> Result<Vec<Row>, E> {
+        let start = self
+            .get_table(name)
+            .ok_or_else(|| DbError::NoSuchTable(name.to_string()))?
+            .rows
+            .len();
+        // Deleting rows may change other tables too, so a failure restores
+        // a copy of them all taken before the first deletion, from which
+        // the rows appended before it are then removed.
+        let mut saved = None;
+        let sequence = self.sequence(name);
+        let mut inserted = Vec::new();
+        for row in rows {
+            match self.insert_resolving(name, row, conflict, &mut saved, &default) {
+                Ok(Some(row)) => inserted.push(row),
+                Ok(None) => {}
+                Err(e) => {
+                    if let Some(tables) = saved {
+                        self.tables = tables;
+                    }
+                    self.restore_sequence(name, sequence);
+                    let table = self.get_table_mut(name).expect("table was found above");
+                    table.truncate_rows(start);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(inserted)
+    }
+
+    /// Append one row resolving its conflicts, first saving the tables in
+    /// `saved` if it deletes rows. Returns the row unless it was left out.
+    fn insert_resolving<E: From<DbError>>(
+        &mut self,
+        name: &str,
+        row: Row,
+        conflict: InsertConflictAction,
+        saved: &mut Option<HashMap<String, Table>>,
+        default: &impl Fn(&Expr) -> Result<Value, E>,
+    ) -> Result<Option<Row>, E> {
+        let table = self.get_table(name).expect("table was found before");
+        let mut row = row;
+        self.assign_autoincrement(table, &mut row)?;
+        let row = table.coerce_row(row)?;
+        let conflicts = table.conflicts(&row);
+        if !conflicts.is_empty() {
+            match conflict {
+                InsertConflictAction::Ignore => return Ok(None),
+                InsertConflictAction::Replace => {
+                    saved.get_or_insert_with(|| self.tables.clone());
+                    self.delete_rows(name, &conflicts, default)?;
+                }
+            }
+        }
+        self.insert_row(name, row.clone())?;
+        Ok(Some(row))
+    }
+
+    /// Replace rows, each given with its position, coercing and checking the
+    /// new rows as [`Database::insert_row`] does. If any of them is rejected
+    /// the table is left unchanged. Nor may the update change a key that
+    /// rows of another table still refer to.
+    pub fn update_rows(&mut self, name: &str, updates: Vec<(usize, Row)>) -> Result<(), DbError> {
+        let table = self
+            .get_table(name)
+            .ok_or_else(|| DbError::NoSuchTable(name.to_string()))?;
+        let updates = updates
+            .into_iter()
+            .map(|(i, row)| {
+                let row = table.coerce_row(row)?;
+                table.check_not_null(&row)?;
+                self.check_foreign_keys_insert(name, &row)?;
+                Ok((i, row))
+            })
+            .collect::<Result<Vec<_>, DbError>>()?;
+        table.check_unique(&updates)?;
+        self.check_foreign_keys_update(table, &updates)?;
+        for (_, row) in &updates {
+            self.advance_sequence(name, row);
+        }
+        let table = self.get_table_mut(name).expect("table was found above");
+        table.replace_rows(updates);
+        Ok(())
+    }
+
+    /// Store a view's query, replacing any view of the same name if
+    /// `replace`.
+    pub fn create_view(
+        &mut self,
+        name: &str,
+        query: SelectStmt,
+        replace: bool,
+    ) -> Result<(), DbError> {
+        let key = name.to_lowercase();
+        if self.tables.contains_key(&key) {
+            return Err(DbError::TableExists(name.to_string()));
+        }
+        if !replace && self.views.contains_key(&key) {
+            return Err(DbError::ViewExists(name.to_string()));
+        }
+        self.views.insert(key, query);
+        Ok(())
+    }
+
+    pub fn get_view(&self, name: &str) -> Option<&SelectStmt> {
+        self.views.get(&name.to_lowercase())
+    }
+
+    pub fn drop_view(&mut self, name: &str) -> Result<(), DbError> {
+        self.views
+            .remove(&name.to_lowercase())
+            .map(|_| ())
+            .ok_or_else(|| DbError::NoSuchView(name.to_string()))
+    }
+
+    /// Start a transaction. Transactions do not nest.
+    pub fn begin(&mut self) -> Result<(), DbError> {
+        if self.in_transaction() {
+            return Err(DbError::NestedTransaction);
+        }
+        self.transaction = Some(Box::new(self.snapshot()));
+        Ok(())
+    }
+
+    /// End the open transaction, keeping its changes.
+    pub fn commit(&mut self) -> Result<(), DbError> {
+        if !self.in_transaction() {
+            return Err(DbError::NoTransaction("commit"));
+        }
+        self.transaction = None;
+        self.savepoints.clear();
+        Ok(())
+    }
+
+    /// End the open transaction, undoing its changes.
+    pub fn rollback(&mut self) -> Result<(), DbError> {
+        let snapshot = match self.transaction.take() {
+            Some(snapshot) => *snapshot,
+            None if !self.savepoints.is_empty() => self.savepoints.swap_remove(0).1,
+            None => return Err(DbError::NoTransaction("rollback")),
+        };
+        // The counts of changes are not undone with the changes, nor is
+        // the key of the last row inserted forgotten.
+        let (changes, total_changes) = (self.changes, self.total_changes);
+        let last_insert_id = self.last_insert_id;
+        *self = snapshot;
+        self.changes = changes;
+        self.total_changes = total_changes;
+        self.last_insert_id = last_insert_id;
+        Ok(())
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some() || !self.savepoints.is_empty()
+    }
+
+    /// Create a savepoint, starting a transaction if none is open. Names
+    /// are case-sensitive and may repeat; the innermost one is used.
+    pub fn savepoint(&mut self, name: &str) {
+        let snapshot = self.snapshot();
+        self.savepoints.push((name.to_string(), snapshot));
+    }
+
+    /// Undo the changes made since the savepoint `name` was created. It
+    /// stays open, but those created after it are released.
+    pub fn rollback_to(&mut self, name: &str) -> Result<(), DbError> {
+        let i = self.find_savepoint(name)?;
+        self.savepoints.truncate(i + 1);
+        let snapshot = &self.savepoints[i].1;
+        self.tables = snapshot.tables.clone();
+        self.views = snapshot.views.clone();
+        self.sequences = snapshot.sequences.clone();
+        Ok(())
+    }
+
+    /// Forget the savepoint `name` and those created after it, keeping
+    /// their changes. Releasing the savepoint that started the
+    /// transaction commits it.
+    pub fn release(&mut self, name: &str) -> Result<(), DbError> {
+        let i = self.find_savepoint(name)?;
+        self.savepoints.truncate(i);
+        Ok(())
+    }
+
+    fn find_savepoint(&self, name: &str) -> Result<usize, DbError> {
+        self.savepoints
+            .iter()
+            .rposition(|(savepoint, _)| savepoint == name)
+            .ok_or_else(|| DbError::NoSuchSavepoint(name.to_string()))
+    }
+
+    /// A copy of the tables and views, without the transaction state.
+    pub fn snapshot(&self) -> Database {
+        Database {
+            tables: self.tables.clone(),
+            views: self.views.clone(),
+            stats: self.stats.clone(),
+            sequences: self.sequences.clone(),
+            changes: self.changes,
+            total_changes: self.total_changes,
+            last_insert_id: self.last_insert_id,
+            ..Database::default()
+        }
+    }
+
+    /// Every table, in no particular order.
+    pub fn tables(&self) -> impl Iterator<Item = &Table> {
+        self.tables.values()
+    }
+
+    /// Every view's lowercased name and query, in no particular order.
+    pub fn views(&self) -> impl Iterator<Item = (&str, &SelectStmt)> {
+        self.views
+            .iter()
+            .map(|(name, query)| (name.as_str(), query))
+    }
+
+    pub fn get_table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(&name.to_lowercase())
+    }
+
+    pub fn get_table_mut(&mut self, name: &str) -> Option<&mut Table> {
+        self.tables.get_mut(&name.to_lowercase())
+    }
+
+    /// Collect the statistics of every column of a table, or of every
+    /// table, replacing those collected before.
+    pub fn analyze(&mut self, table: Option<&str>) -> Result<(), DbError> {
+        let tables: Vec<&Table> = match table {
+            Some(name) => vec![
+                self.get_table(name)
+                    .ok_or_else(|| DbError::NoSuchTable(name.to_string()))?,
+            ],
+            None => self.tables.values().collect(),
+        };
+        let mut stats = Vec::new();
+        for table in tables {
+            for (i, column) in table.schema.iter().enumerate() {
+                let key = (table.name.to_lowercase(), column.name.to_lowercase());
+                stats.push((key, ColumnStats::of(table.rows.iter().map(|row| &row[i]))));
+            }
+        }
+        self.stats.extend(stats);
+        Ok(())
+    }
+
+    /// The statistics ANALYZE last collected for a column, if any.
+    pub fn column_stats(&self, table: &str, column: &str) -> Option<&ColumnStats> {
+        self.stats
+            .get(&(table.to_lowercase(), column.to_lowercase()))
+    }
+}
+
+/// Whether an expression refers to a column named `name`, of any table.
+fn refers_to(expr: &Expr, name: &str) -> bool {
+    let mut found = false;
+    expr.transform(&mut |e| match e {
+        Expr::Column(_, column) if column.eq_ignore_ascii_case(name) => {
+            found = true;
+            Some(e.clone())
+        }
+        _ => None,
+    });
+    found
+}
+
+fn is_auto_index(name: &str) -> bool {
+    name.len() >= AUTO_INDEX_PREFIX.len()
+        && name[..AUTO_INDEX_PREFIX.len()].eq_ignore_ascii_case(AUTO_INDEX_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn people() -> Database {
+        let mut db = Database::new();
+        db.create_table(
+            "People",
+            vec![
+                ColumnDef::new("id", Some(DataType::Int)),
+                ColumnDef::new("name", Some(DataType::Text)),
+            ],
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_create_and_lookup_is_case_insensitive() {
+        let db = people();
+        let table = db.get_table("PEOPLE").unwrap();
+        assert_eq!(table.name, "People");
+        assert_eq!(table.column_index("NAME"), Some(1));
+        assert!(db.get_table("nobody").is_none());
+    }
+
+    #[test]
+    fn test_duplicate_table_is_rejected() {
+        let mut db = people();
+        let err = db.create_table("people", vec![]).unwrap_err();
+        assert_eq!(err, DbError::TableExists("people".into()));
+    }
+
+    #[test]
+    fn test_duplicate_column_is_rejected() {
+        let mut db = Database::new();
+        let schema = vec![ColumnDef::new("a", None), ColumnDef::new("A", None)];
+        assert_eq!(
+            db.create_table("t", schema).unwrap_err(),
+            DbError::DuplicateColumn("A".into())
+        );
+        assert!(db.get_table("t").is_none());
+    }
+
+    #[test]
+    fn test_add_column() {
+        let mut db = people();
+        db.insert_row("people", vec![Value::Integer(1), Value::Null])
+            .unwrap();
+        db.add_column(
+            "people",
+            ColumnDef::new("age", Some(DataType::Int)),
+            Value::Text("30".into()),
+        )
+        .unwrap();
+        let table = db.get_table("people").unwrap();
+        assert_eq!(table.column_index("age"), Some(2));
+        assert_eq!(
+            format!("{:?}", table.rows),
+            "[[Integer(1), Null, Integer(30)]]"
+        );
+        let err = db
+            .add_column("people", ColumnDef::new("AGE", None), Value::Null)
+            .unwrap_err();
+        assert_eq!(err, DbError::DuplicateColumn("AGE".into()));
+        let mut key = ColumnDef::new("k", None);
+        key.primary_key = true;
+        let err = db.add_column("people", key, Value::Null).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot add a PRIMARY KEY column");
+        assert_eq!(db.get_table("people").unwrap().schema.len(), 3);
+    }
+
+    #[test]
+    fn test_drop_column() {
+        let mut db = people();
+        db.insert_row("people", vec![Value::Integer(1), Value::Text("a".into())])
+            .unwrap();
+        db.drop_column("people", 0).unwrap();
+        let table = db.get_table("people").unwrap();
+        assert_eq!(table.column_index("name"), Some(0));
+        assert_eq!(format!("{:?}", table.rows), r#"[[Text("a")]]"#);
+        assert_eq!(
+            db.drop_column("people", 0).unwrap_err().to_string(),
+            r#"cannot drop column "name": no other columns exist"#
+        );
+    }
+
+    #[test]
+    fn test_drop_table() {
+        let mut db = people();
+        db.drop_table("people").unwrap();
+        assert!(db.get_table("people").is_none());
+        assert_eq!(
+            db.drop_table("people").unwrap_err(),
+            DbError::NoSuchTable("people".into())
+        );
+    }
+
+    #[test]
+    fn test_insert_coerces_to_column_types() {
+        let mut db = people();
+        db.insert_row("people", vec![Value::Text("7".into()), Value::Integer(3)])
+            .unwrap();
+        db.insert_row("people", vec![Value::Null, Value::Null])
+            .unwrap();
+        let rows = &db.get_table("people").unwrap().rows;
+        assert_eq!(format!("{:?}", rows[0]), r#"[Integer(7), Text("3")]"#);
+        assert_eq!(format!("{:?}", rows[1]), "[Null, Null]");
+    }
+
+    #[test]
+    fn test_insert_wrong_column_count() {
+        let mut db = people();
+        let err = db
+            .insert_row("people", vec![Value::Integer(1)])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            DbError::ColumnCount {
+                table: "People".into(),
+                expected: 2,
+                actual: 1,
+            }
+        );
+        assert!(db.get_table("people").unwrap().rows.is_empty());
+    }
+
+    #[test]
+    fn test_insert_type_mismatch() {
+        let mut db = people();
+        let err = db
+            .insert_row(
+                "people",
+                vec![Value::Text("abc".into()), Value::Text("x".into())],
+            )
+            .unwrap_err();
+        assert!(matches!(err, DbError::Type(_)));
+    }
+
+    #[test]
+    fn test_insert_enforces_not_null_and_primary_key() {
+        let mut db = Database::new();
+        let mut id = ColumnDef::new("id", Some(DataType::Int));
+        id.primary_key = true;
+        id.nullable = false;
+        let mut name = ColumnDef::new("name", Some(DataType::Text));
+        name.nullable = false;
+        db.create_table("t", vec![id, name]).unwrap();
+        db.insert_row("t", vec![Value::Integer(1), Value::Text("a".into())])
+            .unwrap();
+        let err = db
+            .insert_row("t", vec![Value::Integer(2), Value::Null])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "NOT NULL constraint failed: t.name");
+        let err = db
+            .insert_row("t", vec![Value::Text("1".into()), Value::Text("b".into())])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "UNIQUE constraint failed: t.id");
+        assert_eq!(db.get_table("t").unwrap().rows.len(), 1);
+    }
+
+    #[test]
+    fn test_update_rows_is_all_or_nothing() {
+        let mut db = Database::new();
+        let mut id = ColumnDef::new("id", Some(DataType::Int));
+        id.primary_key = true;
+        id.nullable = false;
+        db.create_table("t", vec![id, ColumnDef::new("x", None)])
+            .unwrap();
+        for i in 1..=3 {
+            db.insert_row("t", vec![Value::Integer(i), Value::Null])
+                .unwrap();
+        }
+        let row = |id: i64| vec![Value::Integer(id), Value::Integer(id * 10)];
+        // Keys may be swapped, as only the final rows must be distinct.
+        db.update_rows("t", vec![(0, row(2)), (1, row(1))]).unwrap();
+        let ids = |db: &Database| format!("{:?}", db.get_table("t").unwrap().rows);
+        assert_eq!(
+            ids(&db),
+            "[[Integer(2), Integer(20)], [Integer(1), Integer(10)], [Integer(3), Null]]"
+        );
+        let err = db
+            .update_rows("t", vec![(0, row(4)), (1, row(3))])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "UNIQUE constraint failed: t.id");
+        let err = db
+            .update_rows("t", vec![(0, row(4)), (1, vec![Value::Null, Value::Null])])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "NOT NULL constraint failed: t.id");
+        assert_eq!(
+            ids(&db),
+            "[[Integer(2), Integer(20)], [Integer(1), Integer(10)], [Integer(3), Null]]"
+        );
+    }
+
+    #[test]
+    fn test_index_follows_rows() {
+        let mut db = people();
+        db.create_index("people", "by_name", vec![1], false)
+            .unwrap();
+        for (id, name) in [(1, "a"), (2, "b"), (3, "a")] {
+            db.insert_row("people", vec![Value::Integer(id), Value::Text(name.into())])
+                .unwrap();
+        }
+        let lookup = |db: &Database, name: &str| {
+            db.get_index("BY_NAME")
+                .unwrap()
+                .lookup(&[Value::Text(name.into())])
+                .to_vec()
+        };
+        assert_eq!(lookup(&db, "a"), [0, 2]);
+        assert!(
+            db.get_index("by_name")
+                .unwrap()
+                .lookup(&[Value::Null])
+                .is_empty()
+        );
+        db.update_rows(
+            "people",
+            vec![(1, vec![Value::Integer(2), Value::Text("a".into())])],
+        )
+        .unwrap();
+        assert_eq!(lookup(&db, "a"), [0, 1, 2]);
+        db.delete_rows("people", &[0], |_| Ok::<_, DbError>(Value::Null))
+            .unwrap();
+        assert_eq!(lookup(&db, "a"), [0, 1]);
+        // Dropping an earlier column renumbers the index's columns.
+        db.drop_column("people", 0).unwrap();
+        assert_eq!(db.get_index("by_name").unwrap().columns, [0]);
+        db.insert_row("people", vec![Value::Text("a".into())])
+            .unwrap();
+        assert_eq!(lookup(&db, "a"), [0, 1, 2]);
+        db.drop_index("by_name").unwrap();
+        assert_eq!(
+            db.drop_index("by_name"),
+            Err(DbError::NoSuchIndex("by_name".into()))
+        );
+    }
+
+    #[test]
+    fn test_unique_constraint() {
+        let mut db = people();
+        for (id, name) in [(1, "a"), (2, "b"), (1, "b")] {
+            db.insert_row("people", vec![Value::Integer(id), Value::Text(name.into())])
+                .unwrap();
+        }
+        assert_eq!(
+            db.add_unique("people", vec![1]).unwrap_err().to_string(),
+            "UNIQUE constraint failed: People.name"
+        );
+        db.add_unique("people", vec![0, 1]).unwrap();
+        let table = db.get_table("people").unwrap();
+        assert_eq!(
+            table.unique,
+            [UniqueConstraint {
+                columns: vec![0, 1]
+            }]
+        );
+        assert_eq!(table.indexes[0].name, "sqlite_autoindex_People_1");
+        assert_eq!(
+            db.insert_row("people", vec![Value::Integer(2), Value::Text("b".into())]),
+            Err(DbError::Unique {
+                table: "People".into(),
+                columns: vec!["id".into(), "name".into()],
+            })
+        );
+        // A constraint goes with a column it covers.
+        db.drop_column("people", 0).unwrap();
+        let table = db.get_table("people").unwrap();
+        assert!(table.unique.is_empty() && table.indexes.is_empty());
+    }
+
+    #[test]
+    fn test_insert_into_missing_table() {
+        let mut db = Database::new();
+        assert_eq!(
+            db.insert_row("t", vec![]).unwrap_err(),
+            DbError::NoSuchTable("t".into())
+        );
+    }
+
+    #[test]
+    fn test_untyped_column_accepts_anything() {
+        let mut db = Database::new();
+        db.create_table("t", vec![ColumnDef::new("x", None)])
+            .unwrap();
+        db.insert_row("t", vec![Value::Blob(vec![1])]).unwrap();
+        db.insert_row("t", vec![Value::Real(1.5)]).unwrap();
+        assert_eq!(db.get_table("t").unwrap().rows.len(), 2);
+    }
+
+    #[test]
+    fn test_shared_database_across_threads() {
+        let db = people().into_shared();
+        let handle = {
+            let db = Arc::clone(&db);
+            std::thread::spawn(move || {
+                db.lock()
+                    .unwrap()
+                    .insert_row("people", vec![Value::Integer(1), Value::Null])
+            })
+        };
+        handle.join().unwrap().unwrap();
+        assert_eq!(
+            db.lock().unwrap().get_table("people").unwrap().rows.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_analyze() {
+        let mut db = people();
+        let rows = [(1, Some("b")), (2, None), (3, Some("a")), (4, Some("b"))];
+        for (id, name) in rows {
+            let name = name.map_or(Value::Null, |n| Value::Text(n.into()));
+            db.insert_row("people", vec![Value::Integer(id), name])
+                .unwrap();
+        }
+        assert_eq!(db.column_stats("people", "name"), None);
+        db.analyze(Some("PEOPLE")).unwrap();
+        assert_eq!(
+            db.column_stats("People", "NAME"),
+            Some(&ColumnStats {
+                distinct_count: 2,
+                null_count: 1,
+                min: Some(Value::Text("a".into())),
+                max: Some(Value::Text("b".into())),
+                histogram: vec![
+                    Value::Text("a".into()),
+                    Value::Text("b".into()),
+                    Value::Text("b".into()),
+                ],
+            })
+        );
+        // Statistics are replaced only by analyzing again.
+        for id in 5..=40 {
+            db.insert_row("people", vec![Value::Integer(id), Value::Null])
+                .unwrap();
+        }
+        assert_eq!(db.column_stats("people", "id").unwrap().distinct_count, 4);
+        db.analyze(None).unwrap();
+        let id = db.column_stats("people", "id").unwrap();
+        assert_eq!(id.distinct_count, 40);
+        assert_eq!(id.max, Some(Value::Integer(40)));
+        let bounds: Vec<Value> = (1..=10).map(|b| Value::Integer(b * 4)).collect();
+        assert_eq!(id.histogram, bounds);
+        assert_eq!(db.column_stats("people", "name").unwrap().null_count, 37);
+        assert_eq!(
+            db.analyze(Some("nope")).unwrap_err(),
+            DbError::NoSuchTable("nope".into())
+        );
+        db.drop_column("people", 1).unwrap();
+        assert_eq!(db.column_stats("people", "name"), None);
+        db.drop_table("people").unwrap();
+        assert_eq!(db.column_stats("people", "id"), None);
+    }
+}
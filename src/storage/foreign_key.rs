@@ -0,0 +1,347 @@
+//! FOREIGN KEY constraints: the parent rows a child row refers to, and what
+//! deleting a parent row does to the rows referring to it.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::{Database, DbError, Index, Row, Table};
+use crate::ast::Expr;
+use crate::types::{OrdValue, Value};
+
+/// What happens to the rows referring to a parent row that is deleted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FkAction {
+    /// Refuse the deletion, as `RESTRICT` does.
+    #[default]
+    NoAction,
+    Restrict,
+    /// Delete the referring rows as well.
+    Cascade,
+    SetNull,
+    /// Give the referring columns their declared defaults.
+    SetDefault,
+}
+
+impl fmt::Display for FkAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FkAction::NoAction => "NO ACTION",
+            FkAction::Restrict => "RESTRICT",
+            FkAction::Cascade => "CASCADE",
+            FkAction::SetNull => "SET NULL",
+            FkAction::SetDefault => "SET DEFAULT",
+        })
+    }
+}
+
+/// `REFERENCES <table> [(<column>, ...)] [ON DELETE <action>]`: the key of a
+/// parent table that a foreign key refers to, its primary key if no
+/// columns are named. It is resolved whenever the constraint is checked,
+/// as the parent table need not exist yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct References {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub on_delete: FkAction,
+}
+
+/// A FOREIGN KEY constraint: the values of `columns` in every row, unless
+/// one of them is NULL, are those of the referenced key in some row of the
+/// parent table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub columns: Vec<usize>,
+    pub references: References,
+}
+
+impl ForeignKey {
+    fn refers_to(&self, table: &str) -> bool {
+        self.references.table.eq_ignore_ascii_case(table)
+    }
+
+    /// A row's values for the constraint's columns as a key of the
+    /// referenced key's `index`, unless one is NULL.
+    fn key(&self, row: &[Value], index: &Index) -> Option<Vec<OrdValue>> {
+        index.key_of(self.columns.iter().map(|&i| &row[i]))
+    }
+
+    /// The unique index on the parent's referenced key, through which
+    /// referring rows are matched with parent rows. The key must be the
+    /// parent's primary key or have a UNIQUE constraint.
+    fn parent_index<'t>(
+        &self,
+        child: &Table,
+        parent: Option<&'t Table>,
+    ) -> Result<&'t Index, DbError> {
+        let mismatch = || DbError::ForeignKeyMismatch {
+            child: child.name.clone(),
+            parent: self.references.table.clone(),
+        };
+        let parent = parent.ok_or_else(mismatch)?;
+        let columns: Option<Vec<usize>> = if self.references.columns.is_empty() {
+            parent.primary_key.as_ref().map(|key| key.columns.clone())
+        } else {
+            self.references
+                .columns
+                .iter()
+                .map(|c| parent.column_index(c))
+                .collect()
+        };
+        let columns = columns
+            .filter(|columns| columns.len() == self.columns.len())
+            .ok_or_else(mismatch)?;
+        parent
+            .indexes
+            .iter()
+            .find(|index| index.unique && index.columns == columns)
+            .ok_or_else(mismatch)
+    }
+}
+
+/// Check that a row of `child` refers only to parent rows that exist,
+/// finding the parent tables through `lookup`.
+fn check_references<'t>(
+    child: &Table,
+    row: &[Value],
+    lookup: impl Fn(&str) -> Option<&'t Table>,
+) -> Result<(), DbError> {
+    for fk in &child.foreign_keys {
+        // A row with a NULL in the key refers to nothing, even a parent
+        // table that does not exist.
+        if fk.columns.iter().any(|&i| row[i].is_null()) {
+            continue;
+        }
+        let index = fk.parent_index(child, lookup(&fk.references.table))?;
+        let key = fk.key(row, index).expect("the key has no NULL");
+        if !index.entries.contains_key(&key) {
+            return Err(DbError::ForeignKey);
+        }
+    }
+    Ok(())
+}
+
+/// A table as a statement sees it: its copy in `changed` if the statement
+/// has changed it, and otherwise the stored one.
+fn working<'a>(
+    db: &'a Database,
+    changed: &'a HashMap<String, Table>,
+    name: &str,
+) -> Option<&'a Table> {
+    let key = name.to_lowercase();
+    changed.get(&key).or_else(|| db.tables.get(&key))
+}
+
+impl Database {
+    /// Check that a row about to be stored in `table` refers only to
+    /// parent rows that exist.
+    pub fn check_foreign_keys_insert(&self, table: &str, row: &[Value]) -> Result<(), DbError> {
+        let child = self
+            .get_table(table)
+            .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?;
+        check_references(child, row, |name| self.get_table(name))
+    }
+
+    /// Check that new versions of rows of `table` leave no row of another
+    /// table referring to a key that no row has any more.
+    pub(super) fn check_foreign_keys_update(
+        &self,
+        table: &Table,
+        updates: &[(usize, Row)],
+    ) -> Result<(), DbError> {
+        for child in self.referencing(&table.name) {
+            for fk in child
+                .foreign_keys
+                .iter()
+                .filter(|fk| fk.refers_to(&table.name))
+            {
+                let index = fk.parent_index(child, Some(table))?;
+                let mut gone = HashSet::new();
+                for (i, row) in updates {
+                    if let Some(old) = index.key(&table.rows[*i])
+                        && index.key(row).as_ref() != Some(&old)
+                    {
+                        gone.insert(old);
+                    }
+                }
+                for (_, row) in updates {
+                    if let Some(new) = index.key(row) {
+                        gone.remove(&new);
+                    }
+                }
+                if !gone.is_empty()
+                    && child
+                        .rows
+                        .iter()
+                        .any(|row| fk.key(row, index).is_some_and(|key| gone.contains(&key)))
+                {
+                    return Err(DbError::ForeignKey);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that no row of another table refers to the key of one of the
+    /// `removed` rows of `table` unless a row still stored has it too.
+    pub(super) fn check_not_referenced(&self, table: &str, removed: &[Row]) -> Result<(), DbError> {
+        if removed.is_empty() {
+            return Ok(());
+        }
+        let parent = self
+            .get_table(table)
+            .ok_or_else(|| DbError::NoSuchTable(table.to_string()))?;
+        for child in self.referencing(table) {
+            for fk in child.foreign_keys.iter().filter(|fk| fk.refers_to(table)) {
+                let index = fk.parent_index(child, Some(parent))?;
+                let gone: HashSet<Vec<OrdValue>> = removed
+                    .iter()
+                    .filter_map(|row| index.key(row))
+                    .filter(|key| !index.entries.contains_key(key))
+                    .collect();
+                if !gone.is_empty()
+                    && child
+                        .rows
+                        .iter()
+                        .any(|row| fk.key(row, index).is_some_and(|key| gone.contains(&key)))
+                {
+                    return Err(DbError::ForeignKey);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The other tables with a foreign key referring to `table`.
+    pub(super) fn referencing(&self, table: &str) -> impl Iterator<Item = &Table> {
+        self.tables.values().filter(move |t| {
+            !t.name.eq_ignore_ascii_case(table)
+                && t.foreign_keys.iter().any(|fk| fk.refers_to(table))
+        })
+    }
+
+    /// Refuse foreign keys of `table` through which a chain of references
+    /// would lead back to it.
+    pub fn check_no_cycle(&self, table: &str, foreign_keys: &[ForeignKey]) -> Result<(), DbError> {
+        let mut pending: Vec<&str> = foreign_keys
+            .iter()
+            .map(|fk| fk.references.table.as_str())
+            .collect();
+        let mut seen = HashSet::new();
+        while let Some(parent) = pending.pop() {
+            if parent.eq_ignore_ascii_case(table) {
+                return Err(DbError::CircularForeignKey(table.to_string()));
+            }
+            if !seen.insert(parent.to_lowercase()) {
+                continue;
+            }
+            if let Some(parent) = self.get_table(parent) {
+                pending.extend(
+                    parent
+                        .foreign_keys
+                        .iter()
+                        .map(|fk| fk.references.table.as_str()),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the rows at `positions` from a table, along with applying
+    /// the ON DELETE action of every foreign key referring to them, with
+    /// `default` evaluating column defaults for SET DEFAULT. If any
+    /// constraint fails, no table is changed.
+    pub fn delete_rows<E: From<DbError>>(
+        &mut self,
+        table: &str,
+        positions: &[usize],
+        default: impl Fn(&Expr) -> Result<Value, E>,
+    ) -> Result<(), E> {
+        let key = table.to_lowercase();
+        let Some(stored) = self.tables.get(&key) else {
+            return Err(DbError::NoSuchTable(table.to_string()).into());
+        };
+        if self.referencing(&key).next().is_none() {
+            if let Some(table) = self.tables.get_mut(&key) {
+                table.remove_rows(positions);
+            }
+            return Ok(());
+        }
+        let mut parent = stored.clone();
+        let deleted = parent.remove_rows(positions);
+        let mut changed = HashMap::from([(key.clone(), parent)]);
+        self.apply_foreign_key_delete(&mut changed, &key, deleted, &default)?;
+        self.tables.extend(changed);
+        Ok(())
+    }
+
+    /// Apply the ON DELETE actions of the foreign keys referring to the
+    /// `deleted` rows of `table`, and those of the rows they delete in
+    /// turn, to copies of the tables they change kept in `changed`.
+    fn apply_foreign_key_delete<E: From<DbError>>(
+        &self,
+        changed: &mut HashMap<String, Table>,
+        table: &str,
+        deleted: Vec<Row>,
+        default: &impl Fn(&Expr) -> Result<Value, E>,
+    ) -> Result<(), E> {
+        // Foreign keys form no cycle, so this ends.
+        let mut pending = vec![(table.to_lowercase(), deleted)];
+        while let Some((parent, deleted)) = pending.pop() {
+            let children: Vec<String> = self
+                .referencing(&parent)
+                .map(|child| child.name.to_lowercase())
+                .collect();
+            for key in children {
+                let mut child = changed
+                    .remove(&key)
+                    .unwrap_or_else(|| self.tables[&key].clone());
+                let foreign_keys = child.foreign_keys.clone();
+                for fk in foreign_keys.iter().filter(|fk| fk.refers_to(&parent)) {
+                    let index = fk.parent_index(&child, working(self, changed, &parent))?;
+                    let gone: HashSet<Vec<OrdValue>> =
+                        deleted.iter().filter_map(|row| index.key(row)).collect();
+                    let hits: Vec<usize> = (0..child.rows.len())
+                        .filter(|&i| {
+                            fk.key(&child.rows[i], index)
+                                .is_some_and(|key| gone.contains(&key))
+                        })
+                        .collect();
+                    if hits.is_empty() {
+                        continue;
+                    }
+                    match fk.references.on_delete {
+                        FkAction::NoAction | FkAction::Restrict => {
+                            return Err(DbError::ForeignKey.into());
+                        }
+                        FkAction::Cascade => {
+                            let removed = child.remove_rows(&hits);
+                            pending.push((key.clone(), removed));
+                        }
+                        action @ (FkAction::SetNull | FkAction::SetDefault) => {
+                            let mut updates = Vec::with_capacity(hits.len());
+                            for i in hits {
+                                let mut row = child.rows[i].clone();
+                                for &c in &fk.columns {
+                                    row[c] = match (action, &child.schema[c].default) {
+                                        (FkAction::SetDefault, Some(expr)) => default(expr)?,
+                                        _ => Value::Null,
+                                    };
+                                }
+                                let row = child.coerce_row(row)?;
+                                child.check_not_null(&row)?;
+                                check_references(&child, &row, |name| {
+                                    working(self, changed, name)
+                                })?;
+                                updates.push((i, row));
+                            }
+                            child.check_unique(&updates)?;
+                            child.replace_rows(updates);
+                        }
+                    }
+                }
+                changed.insert(key, child);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+//! The rows a snapshot transaction has inserted and deleted, kept apart
+//! from the database until it commits. Each table of the snapshot logs its
+//! writes as they are made; [`Database::pending_writes`] nets them out.
+
+use std::collections::HashMap;
+
+use super::{Database, DbError, Row, Table};
+use crate::types::OrdValue;
+
+/// The changes a transaction has made to each table's rows, by lowercased
+/// table name. An updated row counts as the old row deleted and the new
+/// one inserted; a row inserted and then deleted again is not counted at
+/// all.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PendingWrites {
+    tables: HashMap<String, TableWrites>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableWrites {
+    pub inserted: Vec<Row>,
+    pub deleted: Vec<Row>,
+}
+
+impl PendingWrites {
+    pub fn is_empty(&self) -> bool {
+        self.tables.values().all(TableWrites::is_empty)
+    }
+
+    /// The changes made to the table `name`, if any.
+    pub fn table(&self, name: &str) -> Option<&TableWrites> {
+        self.tables
+            .get(&name.to_lowercase())
+            .filter(|writes| !writes.is_empty())
+    }
+}
+
+impl TableWrites {
+    fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.deleted.is_empty()
+    }
+
+    /// The writes with each row both inserted and deleted left out, as
+    /// what they add up to. Rows are compared by value: which of two equal
+    /// rows went is not kept.
+    fn net(&self) -> TableWrites {
+        let mut counts: HashMap<Vec<OrdValue>, Vec<&Row>> = HashMap::new();
+        for row in &self.deleted {
+            counts.entry(key(row)).or_default().push(row);
+        }
+        let mut inserted = Vec::new();
+        for row in &self.inserted {
+            match counts.get_mut(&key(row)).and_then(Vec::pop) {
+                Some(_) => {}
+                None => inserted.push(row.clone()),
+            }
+        }
+        let deleted = counts.into_values().flatten().cloned().collect();
+        TableWrites { inserted, deleted }
+    }
+}
+
+fn key(row: &Row) -> Vec<OrdValue> {
+    row.iter().cloned().map(OrdValue).collect()
+}
+
+impl Database {
+    /// Have every table log the rows stored in it and removed from it from
+    /// now on, for [`Database::pending_writes`]. Copies of the tables, as
+    /// savepoints keep, carry their logs with them, so undoing writes
+    /// undoes their logging too.
+    pub fn record_writes(&mut self) {
+        for table in self.tables.values_mut() {
+            table.writes = Some(TableWrites::default());
+        }
+    }
+
+    /// The writes made since [`Database::record_writes`].
+    pub fn pending_writes(&self) -> PendingWrites {
+        let tables = self
+            .tables
+            .iter()
+            .filter_map(|(key, table)| Some((key.clone(), table.writes.as_ref()?.net())))
+            .collect();
+        PendingWrites { tables }
+    }
+
+    /// Apply a transaction's writes, all or none: each deleted row is
+    /// removed if the table still has it, and each inserted row is
+    /// appended, checked against the NOT NULL and unique constraints.
+    /// Then the foreign keys are checked again, as others may have
+    /// deleted a parent row an inserted row refers to, or referred to a
+    /// deleted one. Their actions are among the writes already, so they
+    /// are not applied again.
+    pub fn merge(&mut self, writes: &PendingWrites) -> Result<(), DbError> {
+        let writes: Vec<(&String, &TableWrites)> = writes
+            .tables
+            .iter()
+            .filter(|(_, w)| !w.is_empty())
+            .collect();
+        let mut saved = HashMap::new();
+        for &(key, table_writes) in &writes {
+            let Some(table) = self.tables.get_mut(key) else {
+                self.restore(saved);
+                return Err(DbError::NoSuchTable(key.clone()));
+            };
+            saved.insert(key.clone(), table.clone());
+            if let Err(e) = table.apply(table_writes) {
+                self.restore(saved);
+                return Err(e);
+            }
+        }
+        for &(key, table_writes) in &writes {
+            let checked = table_writes
+                .inserted
+                .iter()
+                .try_for_each(|row| self.check_foreign_keys_insert(key, row))
+                .and_then(|()| self.check_not_referenced(key, &table_writes.deleted));
+            if let Err(e) = checked {
+                self.restore(saved);
+                return Err(e);
+            }
+        }
+        for (key, table_writes) in writes {
+            for row in &table_writes.inserted {
+                self.advance_sequence(key, row);
+            }
+        }
+        Ok(())
+    }
+
+    fn restore(&mut self, saved: HashMap<String, Table>) {
+        self.tables.extend(saved);
+    }
+}
+
+impl Table {
+    fn apply(&mut self, writes: &TableWrites) -> Result<(), DbError> {
+        let mut counts: HashMap<Vec<OrdValue>, usize> = HashMap::new();
+        for row in &writes.deleted {
+            *counts.entry(key(row)).or_default() += 1;
+        }
+        let positions: Vec<usize> = self
+            .rows
+            .iter()
+            .enumerate()
+            .filter_map(|(i, row)| {
+                let count = counts.get_mut(&key(row)).filter(|count| **count > 0)?;
+                *count -= 1;
+                Some(i)
+            })
+            .collect();
+        self.remove_rows(&positions);
+        for row in &writes.inserted {
+            self.check_row(row)?;
+            self.push_row(row.clone());
+        }
+        Ok(())
+    }
+
+    /// Add rows stored in the table to its log, if it keeps one.
+    pub(super) fn log_inserted(&mut self, rows: &[Row]) {
+        if let Some(writes) = &mut self.writes {
+            writes.inserted.extend_from_slice(rows);
+        }
+    }
+
+    /// Add rows removed from the table to its log, if it keeps one.
+    pub(super) fn log_deleted(&mut self, rows: &[Row]) {
+        if let Some(writes) = &mut self.writes {
+            writes.deleted.extend_from_slice(rows);
+        }
+    }
+}
@@ -0,0 +1,737 @@
+//! SQL value representation, type coercion and comparison.
+//!
+//! Comparisons follow SQL three-valued logic: anything compared with NULL
+//! is unknown, so [`sql_compare`] returns `None` and the `PartialEq` /
+//! `PartialOrd` impls report "not equal" and "unordered" respectively.
+//! Values of different storage classes order as SQLite does:
+//! numeric < text < blob.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A single SQL value.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+    Boolean(bool),
+}
+
+/// A declared column type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    Int,
+    Real,
+    Text,
+    Blob,
+    Boolean,
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DataType::Int => "INTEGER",
+            DataType::Real => "REAL",
+            DataType::Text => "TEXT",
+            DataType::Blob => "BLOB",
+            DataType::Boolean => "BOOLEAN",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl DataType {
+    /// Map a declared type name such as `VARCHAR(20)` or `BIGINT` to a
+    /// data type, using SQLite's substring rules for type affinity.
+    /// Returns `None` for names that imply no particular type.
+    pub fn from_name(name: &str) -> Option<DataType> {
+        let name = name.to_ascii_uppercase();
+        let has = |word: &str| name.contains(word);
+        if has("BOOL") {
+            Some(DataType::Boolean)
+        } else if has("INT") {
+            Some(DataType::Int)
+        } else if has("CHAR") || has("CLOB") || has("TEXT") || has("DATE") || has("TIME") {
+            Some(DataType::Text)
+        } else if has("BLOB") {
+            Some(DataType::Blob)
+        } else if has("REAL") || has("FLOA") || has("DOUB") || has("NUMERIC") || has("DECIMAL") {
+            Some(DataType::Real)
+        } else {
+            None
+        }
+    }
+}
+
+/// How text is compared: declared for a column with `COLLATE <name>`, or
+/// applied to an expression with the same suffix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Collation {
+    #[default]
+    Binary,
+    /// Letters compare equal to their lowercase forms.
+    NoCase,
+    /// Trailing spaces are ignored.
+    Rtrim,
+}
+
+impl Collation {
+    pub fn from_name(name: &str) -> Option<Collation> {
+        match name.to_ascii_uppercase().as_str() {
+            "BINARY" => Some(Collation::Binary),
+            "NOCASE" => Some(Collation::NoCase),
+            "RTRIM" => Some(Collation::Rtrim),
+            _ => None,
+        }
+    }
+
+    /// Whether two characters are the same under this collation.
+    pub fn chars_equal(self, a: char, b: char) -> bool {
+        match self {
+            Collation::Binary | Collation::Rtrim => a == b,
+            Collation::NoCase => a == b || a.to_lowercase().eq(b.to_lowercase()),
+        }
+    }
+
+    /// The form of a text that compares byte by byte the way the text
+    /// compares under this collation, so texts it makes equal have the
+    /// same form.
+    pub fn normalize(self, text: &str) -> Cow<'_, str> {
+        match self {
+            Collation::Binary => Cow::Borrowed(text),
+            Collation::NoCase => Cow::Owned(text.to_lowercase()),
+            Collation::Rtrim => Cow::Borrowed(text.trim_end_matches(' ')),
+        }
+    }
+
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        self.normalize(a)
+            .as_bytes()
+            .cmp(self.normalize(b).as_bytes())
+    }
+
+    /// A value to key a value under, so values equal under this collation
+    /// have equal keys: a text is normalized, and other values are kept.
+    pub fn key(self, value: Value) -> Value {
+        match value {
+            Value::Text(text) if self != Collation::Binary => {
+                Value::Text(self.normalize(&text).into_owned())
+            }
+            value => value,
+        }
+    }
+}
+
+impl fmt::Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Collation::Binary => "BINARY",
+            Collation::NoCase => "NOCASE",
+            Collation::Rtrim => "RTRIM",
+        })
+    }
+}
+
+/// A value that cannot be converted to the requested type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub value: String,
+    pub target: DataType,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot convert {} to {}", self.value, self.target)
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Numeric view of the value, if it has one without conversion.
+    fn as_number(&self) -> Option<Number> {
+        match self {
+            Value::Integer(n) => Some(Number::Int(*n)),
+            Value::Boolean(b) => Some(Number::Int(*b as i64)),
+            Value::Real(n) => Some(Number::Real(*n)),
+            _ => None,
+        }
+    }
+
+    /// Rank of the storage class in the cross-type ordering.
+    fn class_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Integer(_) | Value::Real(_) | Value::Boolean(_) => 1,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Real(f64),
+}
+
+fn compare_numbers(a: Number, b: Number) -> Option<Ordering> {
+    match (a, b) {
+        (Number::Int(a), Number::Int(b)) => Some(a.cmp(&b)),
+        (Number::Int(a), Number::Real(b)) => (a as f64).partial_cmp(&b),
+        (Number::Real(a), Number::Int(b)) => a.partial_cmp(&(b as f64)),
+        (Number::Real(a), Number::Real(b)) => a.partial_cmp(&b),
+    }
+}
+
+/// Compare two values. Returns `None` if either side is NULL (or NaN).
+pub fn sql_compare(a: &Value, b: &Value) -> Option<Ordering> {
+    if a.is_null() || b.is_null() {
+        return None;
+    }
+    if let (Some(x), Some(y)) = (a.as_number(), b.as_number()) {
+        return compare_numbers(x, y);
+    }
+    match (a, b) {
+        (Value::Text(x), Value::Text(y)) => Some(x.as_bytes().cmp(y.as_bytes())),
+        (Value::Blob(x), Value::Blob(y)) => Some(x.cmp(y)),
+        _ => Some(a.class_rank().cmp(&b.class_rank())),
+    }
+}
+
+/// Compare two values as [`sql_compare`] does, but texts under
+/// `collation`.
+pub fn collated_compare(a: &Value, b: &Value, collation: Collation) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Text(x), Value::Text(y)) => Some(collation.compare(x, y)),
+        _ => sql_compare(a, b),
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        sql_compare(self, other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        sql_compare(self, other)
+    }
+}
+
+/// Format a REAL so it parses back to the same `f64`, always keeping a
+/// decimal point or exponent so it is distinguishable from an integer.
+pub fn format_real(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Inf" } else { "-Inf" }.to_string()
+    } else {
+        format!("{n:?}")
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Integer(n) => write!(f, "{n}"),
+            Value::Real(n) => write!(f, "{}", format_real(*n)),
+            Value::Text(s) => write!(f, "{s}"),
+            Value::Blob(bytes) => {
+                for b in bytes {
+                    write!(f, "{b:02X}")?;
+                }
+                Ok(())
+            }
+            Value::Boolean(b) => write!(f, "{}", *b as i64),
+        }
+    }
+}
+
+/// Truncate a REAL toward zero, if the result fits in an i64.
+fn real_to_int(n: f64) -> Option<i64> {
+    // 2^63: -2^63 is the smallest i64, 2^63 the first value out of range.
+    const BOUND: f64 = 9_223_372_036_854_775_808.0;
+    if (-BOUND..BOUND).contains(&n) {
+        Some(n.trunc() as i64)
+    } else {
+        None
+    }
+}
+
+/// The number a text spells, after trimming whitespace: an INTEGER if it
+/// is an integer literal in range, and otherwise a REAL if it parses as
+/// one.
+pub fn parse_number(s: &str) -> Option<Value> {
+    let s = s.trim();
+    // Rust reads `inf` and `NaN` as floats too; SQL does not.
+    if !s
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E'))
+    {
+        return None;
+    }
+    s.parse::<i64>()
+        .map(Value::Integer)
+        .ok()
+        .or_else(|| s.parse::<f64>().ok().map(Value::Real))
+}
+
+/// Convert `value` to `ty` as `CAST` does: as [`coerce`] would, except that
+/// a value that cannot be converted becomes NULL.
+pub fn cast_value(value: Value, ty: DataType) -> Value {
+    coerce(value, ty).unwrap_or(Value::Null)
+}
+
+/// Convert `value` to `target` using SQL implicit casting rules.
+///
+/// NULL converts to NULL for every target. Text is parsed (after trimming
+/// whitespace) when converting to a numeric type; REAL values are truncated
+/// toward zero when converting to INTEGER.
+pub fn coerce(value: Value, target: DataType) -> Result<Value, TypeError> {
+    let fail = |value: &Value| TypeError {
+        value: match value {
+            Value::Text(s) => format!("'{s}'"),
+            other => other.to_string(),
+        },
+        target,
+    };
+    let result = match (target, &value) {
+        (_, Value::Null) => Some(Value::Null),
+
+        (DataType::Int, Value::Integer(_)) => Some(value.clone()),
+        (DataType::Int, Value::Real(n)) => real_to_int(*n).map(Value::Integer),
+        (DataType::Int, Value::Boolean(b)) => Some(Value::Integer(*b as i64)),
+        (DataType::Int, Value::Text(s)) => match parse_number(s) {
+            Some(Value::Real(n)) => real_to_int(n).map(Value::Integer),
+            number => number,
+        },
+        (DataType::Int, Value::Blob(_)) => None,
+
+        (DataType::Real, Value::Integer(n)) => Some(Value::Real(*n as f64)),
+        (DataType::Real, Value::Real(_)) => Some(value.clone()),
+        (DataType::Real, Value::Boolean(b)) => Some(Value::Real(*b as i64 as f64)),
+        (DataType::Real, Value::Text(s)) => match parse_number(s) {
+            Some(Value::Integer(n)) => Some(Value::Real(n as f64)),
+            number => number,
+        },
+        (DataType::Real, Value::Blob(_)) => None,
+
+        (DataType::Text, Value::Text(_)) => Some(value.clone()),
+        (DataType::Text, Value::Blob(bytes)) => {
+            String::from_utf8(bytes.clone()).ok().map(Value::Text)
+        }
+        (DataType::Text, _) => Some(Value::Text(value.to_string())),
+
+        (DataType::Blob, Value::Blob(_)) => Some(value.clone()),
+        (DataType::Blob, Value::Text(s)) => Some(Value::Blob(s.clone().into_bytes())),
+        (DataType::Blob, _) => Some(Value::Blob(value.to_string().into_bytes())),
+
+        (DataType::Boolean, Value::Boolean(_)) => Some(value.clone()),
+        (DataType::Boolean, Value::Integer(n)) => Some(Value::Boolean(*n != 0)),
+        (DataType::Boolean, Value::Real(n)) => Some(Value::Boolean(*n != 0.0)),
+        (DataType::Boolean, Value::Text(s)) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "t" | "1" => Some(Value::Boolean(true)),
+            "false" | "f" | "0" => Some(Value::Boolean(false)),
+            _ => None,
+        },
+        (DataType::Boolean, Value::Blob(_)) => None,
+    };
+    result.ok_or_else(|| fail(&value))
+}
+
+/// A value with a total order and a hash, for use as a key in sets and
+/// maps (DISTINCT, grouping, set operations, indexes).
+///
+/// Unlike [`Value`]'s own comparisons, NULL equals NULL here and sorts
+/// before everything else. Numbers compare by value across INTEGER, REAL
+/// and BOOLEAN, so `1`, `1.0` and `TRUE` are the same key.
+#[derive(Debug, Clone)]
+pub struct OrdValue(pub Value);
+
+impl PartialEq for OrdValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OrdValue {}
+
+impl PartialOrd for OrdValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrdValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.0.is_null(), other.0.is_null()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            // Values never hold NaN, so non-NULL values always compare.
+            (false, false) => sql_compare(&self.0, &other.0).unwrap_or(Ordering::Equal),
+        }
+    }
+}
+
+impl std::hash::Hash for OrdValue {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.class_rank().hash(state);
+        match &self.0 {
+            Value::Null => {}
+            Value::Integer(n) => n.hash(state),
+            Value::Boolean(b) => (*b as i64).hash(state),
+            // Integral reals must hash like the equal integer.
+            Value::Real(n) => match real_to_int(*n) {
+                Some(i) if i as f64 == *n => i.hash(state),
+                _ => n.to_bits().hash(state),
+            },
+            Value::Text(s) => s.hash(state),
+            Value::Blob(b) => b.hash(state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    /// Assert that two values are identical, including their variant.
+    fn assert_same(actual: Value, expected: Value) {
+        assert_eq!(
+            format!("{actual:?}"),
+            format!("{expected:?}"),
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn test_coerce_null_to_every_type() {
+        for target in [
+            DataType::Int,
+            DataType::Real,
+            DataType::Text,
+            DataType::Blob,
+            DataType::Boolean,
+        ] {
+            assert_same(coerce(Value::Null, target).unwrap(), Value::Null);
+        }
+    }
+
+    #[test]
+    fn test_coerce_integer() {
+        assert_same(
+            coerce(Value::Integer(7), DataType::Int).unwrap(),
+            Value::Integer(7),
+        );
+        assert_same(
+            coerce(Value::Integer(1), DataType::Real).unwrap(),
+            Value::Real(1.0),
+        );
+        assert_same(
+            coerce(Value::Integer(-3), DataType::Text).unwrap(),
+            text("-3"),
+        );
+        assert_same(
+            coerce(Value::Integer(12), DataType::Blob).unwrap(),
+            Value::Blob(b"12".to_vec()),
+        );
+        assert_same(
+            coerce(Value::Integer(2), DataType::Boolean).unwrap(),
+            Value::Boolean(true),
+        );
+        assert_same(
+            coerce(Value::Integer(0), DataType::Boolean).unwrap(),
+            Value::Boolean(false),
+        );
+    }
+
+    #[test]
+    fn test_coerce_real() {
+        assert_same(
+            coerce(Value::Real(3.9), DataType::Int).unwrap(),
+            Value::Integer(3),
+        );
+        assert_same(
+            coerce(Value::Real(-3.9), DataType::Int).unwrap(),
+            Value::Integer(-3),
+        );
+        assert!(coerce(Value::Real(1e300), DataType::Int).is_err());
+        assert!(coerce(Value::Real(f64::NAN), DataType::Int).is_err());
+        assert_same(
+            coerce(Value::Real(2.5), DataType::Real).unwrap(),
+            Value::Real(2.5),
+        );
+        assert_same(
+            coerce(Value::Real(2.5), DataType::Text).unwrap(),
+            text("2.5"),
+        );
+        assert_same(
+            coerce(Value::Real(1.0), DataType::Text).unwrap(),
+            text("1.0"),
+        );
+        assert_same(
+            coerce(Value::Real(0.5), DataType::Blob).unwrap(),
+            Value::Blob(b"0.5".to_vec()),
+        );
+        assert_same(
+            coerce(Value::Real(0.0), DataType::Boolean).unwrap(),
+            Value::Boolean(false),
+        );
+    }
+
+    #[test]
+    fn test_coerce_text() {
+        assert_same(
+            coerce(text("42"), DataType::Int).unwrap(),
+            Value::Integer(42),
+        );
+        assert_same(
+            coerce(text(" 42 "), DataType::Int).unwrap(),
+            Value::Integer(42),
+        );
+        assert_same(
+            coerce(text("4.7"), DataType::Int).unwrap(),
+            Value::Integer(4),
+        );
+        assert!(coerce(text("abc"), DataType::Int).is_err());
+        assert_same(
+            coerce(text("1e2"), DataType::Real).unwrap(),
+            Value::Real(100.0),
+        );
+        assert!(coerce(text("x1"), DataType::Real).is_err());
+        assert_same(coerce(text("hi"), DataType::Text).unwrap(), text("hi"));
+        assert_same(
+            coerce(text("hi"), DataType::Blob).unwrap(),
+            Value::Blob(b"hi".to_vec()),
+        );
+        assert_same(
+            coerce(text("TRUE"), DataType::Boolean).unwrap(),
+            Value::Boolean(true),
+        );
+        assert_same(
+            coerce(text("0"), DataType::Boolean).unwrap(),
+            Value::Boolean(false),
+        );
+        assert!(coerce(text("maybe"), DataType::Boolean).is_err());
+    }
+
+    #[test]
+    fn test_coerce_blob() {
+        let blob = Value::Blob(b"ab".to_vec());
+        assert!(coerce(blob.clone(), DataType::Int).is_err());
+        assert!(coerce(blob.clone(), DataType::Real).is_err());
+        assert_same(coerce(blob.clone(), DataType::Text).unwrap(), text("ab"));
+        assert!(coerce(Value::Blob(vec![0xff]), DataType::Text).is_err());
+        assert_same(coerce(blob.clone(), DataType::Blob).unwrap(), blob.clone());
+        assert!(coerce(blob, DataType::Boolean).is_err());
+    }
+
+    #[test]
+    fn test_coerce_boolean() {
+        let t = Value::Boolean(true);
+        assert_same(coerce(t.clone(), DataType::Int).unwrap(), Value::Integer(1));
+        assert_same(coerce(t.clone(), DataType::Real).unwrap(), Value::Real(1.0));
+        assert_same(coerce(t.clone(), DataType::Text).unwrap(), text("1"));
+        assert_same(
+            coerce(t.clone(), DataType::Blob).unwrap(),
+            Value::Blob(b"1".to_vec()),
+        );
+        assert_same(coerce(t.clone(), DataType::Boolean).unwrap(), t);
+    }
+
+    #[test]
+    fn test_cast_value() {
+        let cast = |value, ty| cast_value(value, ty);
+        assert_same(cast(text(" 42 "), DataType::Int), Value::Integer(42));
+        assert_same(cast(text("4.7"), DataType::Int), Value::Integer(4));
+        assert_same(cast(text("abc"), DataType::Int), Value::Null);
+        assert_same(cast(text("1e2"), DataType::Real), Value::Real(100.0));
+        assert_same(cast(text("x"), DataType::Real), Value::Null);
+        assert_same(cast(Value::Real(-2.9), DataType::Int), Value::Integer(-2));
+        assert_same(cast(Value::Real(1e300), DataType::Int), Value::Null);
+        assert_same(cast(Value::Integer(3), DataType::Real), Value::Real(3.0));
+        assert_same(cast(Value::Real(2.5), DataType::Text), text("2.5"));
+        assert_same(
+            cast(Value::Integer(7), DataType::Blob),
+            Value::Blob(b"7".to_vec()),
+        );
+        assert_same(
+            cast(Value::Blob(b"hi".to_vec()), DataType::Text),
+            text("hi"),
+        );
+        assert_same(cast(Value::Blob(vec![0xff]), DataType::Text), Value::Null);
+        assert_same(cast(Value::Blob(vec![1]), DataType::Int), Value::Null);
+        assert_same(cast(text("yes"), DataType::Boolean), Value::Null);
+        assert_same(
+            cast(Value::Integer(2), DataType::Boolean),
+            Value::Boolean(true),
+        );
+        for ty in [
+            DataType::Int,
+            DataType::Real,
+            DataType::Text,
+            DataType::Blob,
+            DataType::Boolean,
+        ] {
+            assert_same(cast(Value::Null, ty), Value::Null);
+        }
+        assert_eq!(parse_number("12"), Some(Value::Integer(12)));
+        assert_eq!(parse_number("1.5"), Some(Value::Real(1.5)));
+        assert_eq!(parse_number("1x"), None);
+        for spelling in ["nan", "NaN", "inf", "-Infinity", "infinity"] {
+            assert_eq!(parse_number(spelling), None, "{spelling}");
+            assert_same(cast(text(spelling), DataType::Real), Value::Null);
+        }
+    }
+
+    #[test]
+    fn test_type_error_message() {
+        let err = coerce(text("abc"), DataType::Int).unwrap_err();
+        assert_eq!(err.to_string(), "cannot convert 'abc' to INTEGER");
+    }
+
+    #[test]
+    fn test_null_comparisons_are_unknown() {
+        let values = [
+            Value::Null,
+            Value::Integer(1),
+            Value::Real(1.0),
+            text("a"),
+            Value::Blob(vec![1]),
+            Value::Boolean(true),
+        ];
+        for v in &values {
+            assert_eq!(sql_compare(&Value::Null, v), None);
+            assert_eq!(sql_compare(v, &Value::Null), None);
+            assert!(Value::Null != *v);
+            assert!(Value::Null.partial_cmp(v).is_none());
+        }
+    }
+
+    #[test]
+    fn test_numeric_comparisons_cross_types() {
+        assert_eq!(
+            sql_compare(&Value::Integer(1), &Value::Real(1.0)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            sql_compare(&Value::Integer(2), &Value::Real(1.5)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            sql_compare(&Value::Boolean(true), &Value::Integer(1)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(sql_compare(&Value::Real(f64::NAN), &Value::Real(1.0)), None);
+        assert!(Value::Integer(1) < Value::Integer(2));
+    }
+
+    #[test]
+    fn test_storage_class_ordering() {
+        assert_eq!(
+            sql_compare(&Value::Integer(999), &text("1")),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            sql_compare(&text("z"), &Value::Blob(vec![0])),
+            Some(Ordering::Less)
+        );
+        assert_eq!(sql_compare(&text("a"), &text("b")), Some(Ordering::Less));
+        assert_eq!(sql_compare(&text("B"), &text("a")), Some(Ordering::Less));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Value::Null.to_string(), "NULL");
+        assert_eq!(Value::Integer(-5).to_string(), "-5");
+        assert_eq!(Value::Real(1.0).to_string(), "1.0");
+        assert_eq!(Value::Real(0.1).to_string(), "0.1");
+        assert_eq!(Value::Real(f64::INFINITY).to_string(), "Inf");
+        assert_eq!(text("plain").to_string(), "plain");
+        assert_eq!(Value::Blob(vec![0xde, 0xad, 0x01]).to_string(), "DEAD01");
+        assert_eq!(Value::Boolean(true).to_string(), "1");
+        assert_eq!(Value::Boolean(false).to_string(), "0");
+    }
+
+    #[test]
+    fn test_real_display_round_trips() {
+        for n in [0.1, 1.0 / 3.0, 1e-7, 123456789.125, 1e300, -2.5] {
+            let printed = Value::Real(n).to_string();
+            assert_eq!(printed.parse::<f64>().unwrap(), n, "{printed}");
+        }
+    }
+
+    #[test]
+    fn test_data_type_from_name() {
+        assert_eq!(DataType::from_name("INTEGER"), Some(DataType::Int));
+        assert_eq!(DataType::from_name("bigint"), Some(DataType::Int));
+        assert_eq!(DataType::from_name("VARCHAR(20)"), Some(DataType::Text));
+        assert_eq!(DataType::from_name("DATETIME"), Some(DataType::Text));
+        assert_eq!(
+            DataType::from_name("DOUBLE PRECISION"),
+            Some(DataType::Real)
+        );
+        assert_eq!(DataType::from_name("DECIMAL(10,2)"), Some(DataType::Real));
+        assert_eq!(DataType::from_name("BLOB"), Some(DataType::Blob));
+        assert_eq!(DataType::from_name("BOOLEAN"), Some(DataType::Boolean));
+        assert_eq!(DataType::from_name("ANYTHING"), None);
+    }
+
+    #[test]
+    fn test_collation_compare() {
+        assert_eq!(Collation::Binary.compare("a", "B"), Ordering::Greater);
+        assert_eq!(Collation::NoCase.compare("a", "B"), Ordering::Less);
+        assert_eq!(Collation::NoCase.compare("ÉTÉ", "été"), Ordering::Equal);
+        assert_eq!(Collation::Rtrim.compare("a  ", "a"), Ordering::Equal);
+        assert_eq!(Collation::Rtrim.compare(" a", "a"), Ordering::Less);
+        assert_eq!(Collation::Rtrim.compare("A ", "a"), Ordering::Less);
+        assert_eq!(
+            collated_compare(&text("x"), &Value::Integer(1), Collation::NoCase),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            collated_compare(&text("x"), &Value::Null, Collation::NoCase),
+            None
+        );
+        assert_eq!(Collation::NoCase.key(text("AbC")), text("abc"));
+        assert_eq!(Collation::Rtrim.key(Value::Integer(1)), Value::Integer(1));
+        assert_eq!(Collation::from_name("rtrim"), Some(Collation::Rtrim));
+    }
+
+    #[test]
+    fn test_ord_value_keys() {
+        use std::collections::HashSet;
+        let keys: HashSet<OrdValue> = [
+            Value::Null,
+            Value::Null,
+            Value::Integer(1),
+            Value::Real(1.0),
+            Value::Boolean(true),
+            Value::Real(1.5),
+            text("1"),
+        ]
+        .into_iter()
+        .map(OrdValue)
+        .collect();
+        assert_eq!(keys.len(), 4);
+        assert!(keys.contains(&OrdValue(Value::Null)));
+        assert!(OrdValue(Value::Null) < OrdValue(Value::Integer(i64::MIN)));
+        assert!(OrdValue(Value::Integer(2)) < OrdValue(text("1")));
+    }
+}